@@ -2,44 +2,77 @@ use core::fmt;
 use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt::Debug;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use bitcoin::{secp256k1, Address, Network, Transaction};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use fedimint_api::config::{ClientConfig, ModuleGenRegistry};
 use fedimint_api::core::{
-    LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
+    ModuleInstanceId, LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
     LEGACY_HARDCODED_INSTANCE_ID_WALLET,
 };
 use fedimint_api::db::Database;
+use fedimint_api::module::audit::AuditSummary;
 use fedimint_api::module::registry::ModuleDecoderRegistry;
-use fedimint_api::module::DynModuleGen;
-use fedimint_api::task::TaskGroup;
-use fedimint_api::{Amount, OutPoint, TieredMulti, TransactionId};
+use fedimint_api::module::{DynModuleGen, ModuleConsensusVersion};
+use fedimint_api::task::{sleep, TaskGroup};
+use fedimint_api::{Amount, OutPoint, PeerId, TieredMulti, TransactionId};
+use fedimint_core::admin::{ApiAuth, GuardianAdminApi, GuardianStatus, PeerConnectionStatus};
 use fedimint_core::api::{
     FederationApiExt, GlobalFederationApi, IFederationApi, WsClientConnectInfo, WsFederationApi,
 };
 use fedimint_core::config::load_from_file;
-use fedimint_core::query::EventuallyConsistent;
+use fedimint_core::epoch::{ModuleParamsVote, ModuleVersionVote};
+use fedimint_core::meta::FederationMeta;
+use fedimint_core::outcome::TransactionStatus;
+use fedimint_core::query::{EventuallyConsistent, QueryStrategyKind};
+use fedimint_logging::TracingSetup;
 use fedimint_mint::common::MintDecoder;
 use fedimint_mint::MintGen;
+use futures::StreamExt;
+use mint_client::db::EncryptionParamsKey;
+use mint_client::discovery::discover_federations;
+use mint_client::encrypted_db::{DatabaseSecret, EncryptedDatabase};
+use mint_client::events::ClientEvent;
+use mint_client::ln::lnurl::{self, LnurlError, PayTarget};
+use mint_client::ln::outgoing::OutgoingPaymentRecord;
+use mint_client::ln::proof::ContractProof;
+use mint_client::ln::GatewaySelection;
+use mint_client::mint::backup::RecoveryProgress;
+use mint_client::mint::note_selection::NoteSelection;
+use mint_client::mint::transfer::EcashTransfer;
 use mint_client::mint::SpendableNote;
+use mint_client::modules::ln::bolt12::Bolt12Offer;
 use mint_client::modules::ln::common::LightningDecoder;
 use mint_client::modules::ln::contracts::ContractId;
 use mint_client::modules::ln::LightningGen;
 use mint_client::modules::wallet::common::WalletDecoder;
 use mint_client::modules::wallet::txoproof::TxOutProof;
 use mint_client::modules::wallet::WalletGen;
+use mint_client::operations::{OperationId, OperationKind, OperationOutcome, OperationRecord};
 use mint_client::utils::{
-    from_hex, parse_bitcoin_amount, parse_ecash, parse_fedimint_amount, parse_node_pub_key,
-    serialize_ecash,
+    from_hex, parse_bitcoin_amount, parse_fedimint_amount, parse_node_pub_key,
 };
-use mint_client::{module_decode_stubs, Client, UserClientConfig};
+use mint_client::wallet::peg_out::PegOutDescriptor;
+use mint_client::{
+    module_decode_stubs, Client, ClientError, ClientSecret, Mnemonic, NoteReconciliation,
+    UserClientConfig, DEFAULT_FEDERATION_META_MAX_AGE,
+};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use tracing_subscriber::EnvFilter;
+use tracing::warn;
+use url::Url;
 
 #[derive(Serialize)]
 #[serde(rename_all(serialize = "snake_case"))]
@@ -61,6 +94,10 @@ enum CliOutput {
         id: TransactionId,
     },
 
+    AcceleratePegIn {
+        tx_id: bitcoin::Txid,
+    },
+
     Reissue {
         id: OutPoint,
     },
@@ -68,6 +105,22 @@ enum CliOutput {
     Validate {
         all_valid: bool,
         details: BTreeMap<Amount, usize>,
+        total_amount: Amount,
+        /// Why the notes can't be claimed, empty if they are valid
+        problems: Vec<String>,
+    },
+
+    SplitNotes {
+        notes: String,
+        remainder: String,
+    },
+
+    CombineNotes {
+        notes: String,
+    },
+
+    Reconcile {
+        reconciliation: NoteReconciliation,
     },
 
     Spend {
@@ -76,6 +129,15 @@ enum CliOutput {
 
     PegOut {
         tx_id: bitcoin::Txid,
+        vout: u32,
+    },
+
+    PegOutDescriptor {
+        descriptor: Option<String>,
+    },
+
+    PegOutAddress {
+        address: Address,
     },
 
     LnPay {
@@ -86,6 +148,22 @@ enum CliOutput {
         issuance: Vec<OutPoint>,
     },
 
+    RefreshNotes {
+        issuance: Vec<OutPoint>,
+    },
+
+    ConsolidateNotes {
+        issuance: Vec<OutPoint>,
+    },
+
+    FederationMeta {
+        meta: FederationMeta,
+    },
+
+    ModuleVersions {
+        module_versions: BTreeMap<ModuleInstanceId, ModuleConsensusVersion>,
+    },
+
     Info {
         federation_id: String,
         federation_name: String,
@@ -103,18 +181,70 @@ enum CliOutput {
         paid_in_tx: OutPoint,
     },
 
+    ReleaseInvoice {
+        released_in_tx: OutPoint,
+    },
+
+    ClaimPendingInvoices {
+        claimed_in_txs: Vec<OutPoint>,
+    },
+
+    LnPayments {
+        payments: Vec<OutgoingPaymentRecord>,
+    },
+
+    History {
+        operations: Vec<OperationRecord>,
+    },
+
+    IncomingFunds {
+        kind: IncomingFundsKind,
+        amount: Amount,
+        operation_id: OperationId,
+    },
+
+    TxOutcome {
+        status: TransactionStatus,
+    },
+
+    LnOffer {
+        offer: Bolt12Offer,
+    },
+
+    ClaimOfferPayments {
+        claimed_in_tx: Option<OutPoint>,
+    },
+
+    ContractProof {
+        proof: ContractProof,
+    },
+
+    RegisterRefund {
+        watchtower: Url,
+    },
+
+    VerifyContractProof {
+        contract_id: ContractId,
+        settled: bool,
+    },
+
     WaitBlockHeight {
         reached: u64,
     },
 
     ConnectInfo {
         connect_info: WsClientConnectInfo,
+        invite_code: String,
     },
 
     JoinFederation {
         joined: String,
     },
 
+    DiscoverFederations {
+        federations: Vec<DiscoveredFederation>,
+    },
+
     ListGateways {
         num_gateways: usize,
         gateways: Value,
@@ -124,7 +254,51 @@ enum CliOutput {
         new_gateway: Value,
     },
 
-    Backup,
+    Backup {
+        metadata: Option<String>,
+    },
+
+    Restore {
+        total_amount: Amount,
+        metadata: Option<String>,
+    },
+
+    WipeNotes,
+
+    ExportSecret {
+        secret: String,
+        mnemonic: Option<String>,
+    },
+
+    ImportSecret,
+
+    GuardianStatus {
+        status: GuardianStatus,
+    },
+
+    Audit {
+        audit: AuditSummary,
+    },
+
+    BackupDb {
+        backup_path: String,
+    },
+
+    SignalUpgrade {
+        upgrade_signaled: bool,
+    },
+
+    VoteModuleVersion {
+        vote: ModuleVersionVote,
+    },
+
+    VoteModuleParams {
+        vote: ModuleParamsVote,
+    },
+
+    ConnectedPeers {
+        peers: BTreeMap<PeerId, PeerConnectionStatus>,
+    },
 }
 
 impl fmt::Display for CliOutput {
@@ -133,6 +307,16 @@ impl fmt::Display for CliOutput {
     }
 }
 
+/// A federation announced on Nostr by one of its guardians
+#[derive(Serialize)]
+struct DiscoveredFederation {
+    invite_code: String,
+    federation_id: String,
+    meta: FederationMeta,
+    /// Nostr key of the announcing guardian
+    announced_by: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 enum CliErrorKind {
     NetworkError,
@@ -188,12 +372,141 @@ impl fmt::Display for CliError {
 
 impl Error for CliError {}
 
+impl CliErrorKind {
+    /// Exit code of the CLI when failing with this kind of error. Invalid
+    /// arguments rejected by the argument parser exit with `2` as well.
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliErrorKind::GeneralFailure => 1,
+            CliErrorKind::InvalidValue => 2,
+            CliErrorKind::NetworkError => 3,
+            CliErrorKind::GeneralFederationError => 4,
+            CliErrorKind::InsufficientBalance => 5,
+            CliErrorKind::AlreadySpent => 6,
+            CliErrorKind::Timeout => 7,
+            CliErrorKind::IOError => 8,
+            CliErrorKind::OSError => 9,
+            CliErrorKind::SerializationError => 10,
+        }
+    }
+}
+
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0   success
+  1   general failure
+  2   invalid value or argument
+  3   network error
+  4   federation error
+  5   insufficient balance
+  6   notes already spent
+  7   timeout
+  8   I/O error
+  9   OS error
+  10  serialization error";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Pretty-printed JSON of the result on stdout, errors go to stderr
+    #[default]
+    Text,
+    /// One line of JSON with a stable schema on stdout, for results and
+    /// errors alike
+    Json,
+}
+
+/// Set once the arguments are parsed, so errors can be printed in the right
+/// format from anywhere
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Version of the schema of [`JsonOutput`]. Fields may be added to it or the
+/// results without bumping it, removing or changing one requires a new
+/// version.
+const JSON_OUTPUT_VERSION: u32 = 1;
+
+/// Everything printed with `--output json`
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    version: u32,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'a CliOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JsonError<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonError<'a> {
+    kind: &'a CliErrorKind,
+    message: &'a str,
+    raw_error: Option<String>,
+    exit_code: i32,
+}
+
+impl fmt::Display for JsonOutput<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string(self).unwrap())
+    }
+}
+
+/// Prints the result of a successful command in the selected output format
+fn print_output(output: &CliOutput) {
+    if JSON_OUTPUT.load(Ordering::Relaxed) {
+        let output = JsonOutput {
+            version: JSON_OUTPUT_VERSION,
+            success: true,
+            result: Some(output),
+            error: None,
+        };
+        println!("{output}");
+    } else {
+        println!("{output}");
+    }
+}
+
+/// Prints `error` in the selected output format and exits with the code of its
+/// kind
+fn terminate(error: CliError) -> ! {
+    print_error(&error);
+    fedimint_logging::shutdown();
+    exit(error.kind.exit_code());
+}
+
+/// Prints `error` in the selected output format, errors of commands run in
+/// `shell` only get printed
+fn print_error(error: &CliError) {
+    let exit_code = error.kind.exit_code();
+    if JSON_OUTPUT.load(Ordering::Relaxed) {
+        let output = JsonOutput {
+            version: JSON_OUTPUT_VERSION,
+            success: false,
+            result: None,
+            error: Some(JsonError {
+                kind: &error.kind,
+                message: &error.message,
+                raw_error: error.raw_error.as_ref().map(|e| e.to_string()),
+                exit_code,
+            }),
+        };
+        println!("{output}");
+    } else {
+        eprintln!("{error}");
+    }
+}
+
 #[derive(Parser)]
-#[command(version)]
+#[command(version, after_help = EXIT_CODES_HELP)]
 struct Cli {
     /// The working directory of the client containing the config and db
     #[arg(long = "workdir")]
     workdir: PathBuf,
+    /// Password encrypting the client db. The db gets encrypted the first
+    /// time it is given and can't be opened without it afterwards.
+    #[arg(long = "password", env = "FM_CLIENT_PASSWORD")]
+    password: Option<String>,
+    /// Format of the output, `json` is meant for scripts and has a stable
+    /// schema
+    #[arg(long = "output", value_enum, default_value_t, env = "FM_CLI_OUTPUT")]
+    output: OutputFormat,
     #[clap(subcommand)]
     command: Command,
 }
@@ -201,6 +514,8 @@ struct Cli {
 #[derive(Parser)]
 #[command(version)]
 struct CliNoWorkdir {
+    #[arg(long = "output", value_enum, default_value_t, env = "FM_CLI_OUTPUT")]
+    output: OutputFormat,
     #[clap(subcommand)]
     command: CommandNoWorkdir,
 }
@@ -209,6 +524,13 @@ struct CliNoWorkdir {
 enum CommandNoWorkdir {
     /// Print the latest git commit hash this bin. was build with
     VersionHash,
+    /// List the federations announced on Nostr relays, their invite codes can
+    /// be used with `join-federation`
+    DiscoverFederations {
+        /// Relays to query, e.g. `wss://relay.damus.io`
+        #[clap(long, value_delimiter = ',', required = true)]
+        relays: Vec<Url>,
+    },
 }
 #[derive(Subcommand)]
 enum Command {
@@ -218,12 +540,16 @@ enum Command {
     PegInAddress,
 
     /// Send direct method call to the API, waiting for all peers to agree on a
-    /// response
+    /// response unless another query strategy is given
     Api {
         method: String,
         /// JSON args that will be serialized and send with the request
         #[clap(default_value = "null")]
         arg: String,
+        /// How many peers have to agree on the response (fastest,
+        /// current-consensus, threshold-consensus, all-peers)
+        #[clap(long, value_parser = QueryStrategyKind::from_str)]
+        strategy: Option<QueryStrategyKind>,
     },
 
     /// Issue notes in exchange for a peg-in proof
@@ -234,23 +560,56 @@ enum Command {
         transaction: Transaction,
     },
 
+    /// Ask the federation to speed up the confirmation of a deposit sent with a
+    /// too low fee
+    AcceleratePegIn {
+        #[clap(value_parser = from_hex::<Transaction>)]
+        transaction: Transaction,
+    },
+
     /// Reissue notes received from a third party to avoid double spends
     Reissue {
-        #[clap(value_parser = parse_ecash)]
-        notes: TieredMulti<SpendableNote>,
+        #[clap(value_parser = EcashTransfer::from_str)]
+        notes: EcashTransfer,
     },
 
-    /// Validate notes without claiming them (only checks if signatures valid,
-    /// does not check if nonce unspent)
-    Validate {
-        #[clap(value_parser = parse_ecash)]
-        notes: TieredMulti<SpendableNote>,
+    /// Validate notes without claiming them or contacting the federation:
+    /// checks the format, that our federation issued them, that it issues
+    /// their tiers and their signatures, but not if they were spent already
+    Validate { notes: String },
+
+    /// Split notes received from someone else into notes worth exactly
+    /// `amount` and the remainder, e.g. to pass part of them on without
+    /// claiming them first
+    SplitNotes {
+        #[clap(value_parser = EcashTransfer::from_str)]
+        notes: EcashTransfer,
+        #[clap(value_parser = parse_fedimint_amount)]
+        amount: Amount,
+    },
+
+    /// Combine notes of the same federation into one string, notes contained
+    /// more than once are only kept once. Use `reissue` to claim the result.
+    CombineNotes {
+        #[clap(value_parser = EcashTransfer::from_str, required = true)]
+        notes: Vec<EcashTransfer>,
+    },
+
+    /// Check the status of notes accepted while offline and claim the
+    /// unspent ones, reports notes that were double-spent or invalid
+    Reconcile {
+        #[clap(value_parser = EcashTransfer::from_str, required = true)]
+        notes: Vec<EcashTransfer>,
     },
 
     /// Prepare notes to send to a third party as a payment
     Spend {
         #[clap(value_parser = parse_fedimint_amount)]
         amount: Amount,
+        /// Strategy choosing the notes to spend (minimize-notes,
+        /// minimize-change, maximize-anonymity), defaults to the client's
+        #[clap(long, value_parser = NoteSelection::from_str)]
+        selection: Option<NoteSelection>,
     },
 
     /// Withdraw funds from the federation
@@ -260,15 +619,94 @@ enum Command {
         satoshis: bitcoin::Amount,
     },
 
-    /// Pay a lightning invoice via a gateway
-    LnPay { bolt11: lightning_invoice::Invoice },
+    /// Restrict peg-outs to addresses of your own wallet given by an output
+    /// descriptor or xpub, lifts the restriction if none is given
+    PegOutDescriptor {
+        #[clap(value_parser = PegOutDescriptor::from_str)]
+        descriptor: Option<PegOutDescriptor>,
+    },
+
+    /// Hand out the next address of the peg-out descriptor
+    PegOutAddress,
+
+    /// Pay a lightning invoice, lightning address (`name@domain`) or LNURL-pay
+    /// link via a gateway
+    LnPay {
+        #[clap(value_parser = PayTarget::from_str)]
+        payment: PayTarget,
+        /// Amount to pay to a lightning address or LNURL. If omitted it is
+        /// asked for, unless the service only accepts a single amount.
+        #[clap(long, value_parser = parse_fedimint_amount)]
+        amount: Option<Amount>,
+        /// How to pick the gateway: `lowest-fee`, `best-reliability` or a
+        /// gateway's node public key. Uses the active gateway if omitted.
+        #[clap(long, value_parser = GatewaySelection::from_str)]
+        gateway_selection: Option<GatewaySelection>,
+        /// Number of gateways to try before giving up
+        #[clap(long, default_value_t = 3)]
+        max_attempts: usize,
+    },
+
+    /// Pay a lightning address (`name@domain`) or LNURL-pay link
+    LnurlPay {
+        target: String,
+        #[clap(value_parser = parse_fedimint_amount)]
+        amount: Amount,
+        /// How to pick the gateway: `lowest-fee`, `best-reliability` or a
+        /// gateway's node public key. Uses the active gateway if omitted.
+        #[clap(long, value_parser = GatewaySelection::from_str)]
+        gateway_selection: Option<GatewaySelection>,
+        /// Number of gateways to try before giving up
+        #[clap(long, default_value_t = 3)]
+        max_attempts: usize,
+    },
+
+    /// Pay a BOLT12 offer, fetching an invoice for it through a gateway
+    OfferPay {
+        offer: Bolt12Offer,
+        /// Amount to pay if the offer doesn't specify one
+        #[clap(long, value_parser = parse_fedimint_amount)]
+        amount: Option<Amount>,
+        /// How to pick the gateway: `lowest-fee`, `best-reliability` or a
+        /// gateway's node public key
+        #[clap(long, value_parser = GatewaySelection::from_str, default_value = "lowest-fee")]
+        gateway_selection: GatewaySelection,
+        /// Number of gateways to try before giving up
+        #[clap(long, default_value_t = 3)]
+        max_attempts: usize,
+    },
 
     /// Fetch (re-)issued notes and finalize issuance process
     Fetch,
 
+    /// Reissue notes that expire with the federation's next key rotation
+    RefreshNotes,
+
+    /// Reissue the surplus notes of tiers holding many notes into larger
+    /// denominations
+    ConsolidateNotes {
+        /// Number of notes to keep per tier, defaults to the number targeted
+        /// when receiving change
+        #[clap(long, default_value_t = 0)]
+        notes_per_tier: u16,
+    },
+
     /// Display wallet info (holdings, tiers)
     Info,
 
+    /// Display the federation's name, icon, message of the day and fee
+    /// schedule as signed by the guardians
+    Meta {
+        /// Age in seconds up to which the cached meta is displayed instead of
+        /// fetching it again
+        #[clap(long, default_value_t = DEFAULT_FEDERATION_META_MAX_AGE.as_secs())]
+        max_age: u64,
+    },
+
+    /// Show the consensus version each module instance of the federation
+    /// currently runs with
+    ModuleVersions,
+
     /// Create a lightning invoice to receive payment via gateway
     LnInvoice {
         #[clap(value_parser = parse_fedimint_amount)]
@@ -277,17 +715,109 @@ enum Command {
         expiry_time: Option<u64>,
     },
 
+    /// Create a held lightning invoice whose payment only completes once it
+    /// gets released
+    LnHoldInvoice {
+        #[clap(value_parser = parse_fedimint_amount)]
+        amount: Amount,
+        description: String,
+        /// Number of blocks after which the payment fails if it wasn't
+        /// released
+        hold_blocks: u32,
+        expiry_time: Option<u64>,
+    },
+
+    /// Create a reusable BOLT12 offer through the active gateway. The gateway
+    /// is trusted to forward payments to it.
+    LnOffer { description: String },
+
+    /// Claim all payments received through our BOLT12 offers so far
+    ClaimOfferPayments,
+
     /// Wait for incoming invoice to be paid
     WaitInvoice { invoice: lightning_invoice::Invoice },
 
+    /// Release the preimage of a paid held invoice to complete the payment
+    ReleaseInvoice { invoice: lightning_invoice::Invoice },
+
+    /// Claim all invoices that got paid since we last checked, e.g. while
+    /// the client wasn't running
+    ClaimPendingInvoices,
+
+    /// List our outgoing lightning payments, refunding the ones whose
+    /// gateway failed to pay the invoice
+    LnPayments,
+
+    /// Wait for incoming funds and report each of them as soon as it arrives:
+    /// reissued ecash, confirmed deposits and paid lightning invoices, which
+    /// get claimed automatically. Runs until interrupted, other commands can't
+    /// use the workdir meanwhile.
+    Watch {
+        /// Shell command run for every incoming payment, with the payment
+        /// described by the `FM_INCOMING_KIND`, `FM_INCOMING_AMOUNT_MSAT`,
+        /// `FM_INCOMING_OPERATION_ID` and `FM_INCOMING_JSON` env variables
+        #[clap(long)]
+        hook: Option<String>,
+        /// Seconds between checks for paid invoices and processed operations
+        #[clap(long, default_value_t = 5)]
+        interval: u64,
+    },
+
+    /// List our past operations, most recent first
+    History {
+        /// Maximum number of operations to list
+        #[clap(long, default_value = "20")]
+        limit: usize,
+
+        /// Only list operations started before this one, to page through the
+        /// history
+        #[clap(long)]
+        before: Option<OperationId>,
+    },
+
+    /// Fetch the outcome of a transaction
+    TxOutcome {
+        txid: TransactionId,
+        /// How many peers have to agree on the outcome, `verified` additionally
+        /// checks acceptances against the federation's signed epochs
+        #[clap(long, default_value_t, value_parser = QueryStrategyKind::from_str)]
+        strategy: QueryStrategyKind,
+    },
+
+    /// Prove the settlement of a lightning contract we funded or claimed, e.g.
+    /// to resolve a dispute with a gateway
+    ContractProof { contract_id: ContractId },
+
+    /// Hand a signed refund of an outgoing contract to a watchtower, which
+    /// submits it if the contract times out while we are offline
+    RegisterRefund {
+        contract_id: ContractId,
+        /// Defaults to the active gateway
+        #[clap(long)]
+        watchtower: Option<Url>,
+    },
+
+    /// Check a contract proof created by a user or gateway of this federation
+    VerifyContractProof {
+        /// Proof as returned by `contract-proof`
+        proof: String,
+    },
+
     /// Wait for the fed to reach a consensus block height
     WaitBlockHeight { height: u64 },
 
     /// Config enabling client to establish websocket connection to federation
     ConnectInfo,
 
-    /// Join a federation using it's ConnectInfo
-    JoinFederation { connect: String },
+    /// Join a federation using an invite code
+    ///
+    /// The config is downloaded from all guardians in the invite and only
+    /// accepted if they agree on it. The connect info printed as JSON by
+    /// earlier versions is accepted as well.
+    JoinFederation {
+        #[clap(value_parser = WsClientConnectInfo::from_str)]
+        invite: WsClientConnectInfo,
+    },
 
     /// List registered gateways
     ListGateways,
@@ -300,10 +830,15 @@ enum Command {
     },
 
     /// Upload the (encrypted) snapshot of mint notes to federation
-    Backup,
+    Backup {
+        /// Text stored with this and all later backups, e.g. to label the
+        /// wallet, an empty text removes it
+        #[clap(long)]
+        metadata: Option<String>,
+    },
 
     /// Restore the previously created backup of mint notes (with `backup`
-    /// command)
+    /// command), printing the progress of scanning the federation's history
     Restore {
         /// The amount of nonces to look ahead when scanning epoch history (per
         /// amount tier)
@@ -313,11 +848,104 @@ enum Command {
         /// notes in some rare situations.
         #[clap(long = "gap-limit", default_value = "100")]
         gap_limit: usize,
+
+        /// Secret exported from the lost client, imported before restoring
+        /// into a new client (see `import-secret`)
+        #[clap(long, value_parser = ClientSecret::from_str)]
+        secret: Option<ClientSecret>,
+
+        /// Mnemonic of the lost client, imported before restoring into a new
+        /// client (see `import-mnemonic`). The words have to be quoted as one
+        /// argument.
+        #[clap(long, value_parser = Mnemonic::from_str, conflicts_with = "secret")]
+        mnemonic: Option<Mnemonic>,
+    },
+
+    /// Print the secret all keys of this client are derived from, and the
+    /// mnemonic it was derived from if the client has one. Together with the
+    /// federation's connect info it allows restoring the client's notes if
+    /// its database is lost.
+    ExportSecret,
+
+    /// Use a secret exported from another client, has to be done before using
+    /// a new client. Afterwards the notes can be recovered using `restore`.
+    ImportSecret {
+        #[clap(value_parser = ClientSecret::from_str)]
+        secret: ClientSecret,
+    },
+
+    /// Like `import-secret` for the words of a BIP39 mnemonic, e.g. exported
+    /// from another wallet. The words have to be quoted as one argument.
+    ImportMnemonic {
+        #[clap(value_parser = Mnemonic::from_str)]
+        mnemonic: Mnemonic,
     },
 
     /// Wipe the notes data from the DB. Useful for testing backup & restore
     #[clap(hide = true)]
     WipeNotes,
+
+    /// Run commands interactively, keeping the client loaded between them.
+    /// Supports command history and tab completion, `exit` or Ctrl-D quits.
+    Shell,
+
+    /// Manage a guardian of the federation through its admin API
+    Admin {
+        /// Guardian to manage, its API URL is taken from the client config
+        #[clap(long = "peer-id")]
+        peer_id: u16,
+        /// Password of the guardian's admin API, by default the password of
+        /// its config
+        #[clap(long, env = "FM_ADMIN_AUTH")]
+        auth: Option<String>,
+        #[clap(subcommand)]
+        command: AdminCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum AdminCommand {
+    /// Show the guardian's version, epoch count, connections and whether it
+    /// signaled an upgrade
+    Status,
+
+    /// Show the federation's balance sheet as seen by the guardian
+    Audit,
+
+    /// Write a copy of the guardian's database next to it and print its path
+    /// on the guardian's machine
+    BackupDb,
+
+    /// Signal that the guardian is ready for an upgrade. Once a threshold of
+    /// guardians signaled, all of them halt consensus after the same epoch and
+    /// can be restarted with the new version.
+    SignalUpgrade,
+
+    /// Vote to switch a module instance to another consensus version. Once a
+    /// threshold of guardians voted for the same version, the federation
+    /// switches to it after the same epoch. Voting for the active version
+    /// withdraws the vote.
+    VoteModuleVersion {
+        /// Id of the module instance, as in the client config
+        module_instance_id: ModuleInstanceId,
+        version: u32,
+    },
+
+    /// Vote to change the consensus params of a module instance, e.g. the
+    /// fees of the mint. Once a threshold of guardians voted for the same
+    /// change, all of them halt consensus before the activation epoch and
+    /// apply the change to their configs when restarted.
+    VoteModuleParams {
+        /// Id of the module instance, as in the client config
+        module_instance_id: ModuleInstanceId,
+        /// First epoch in which the changed params apply
+        activation_epoch: u64,
+        /// JSON object with the params to change, depends on the module kind
+        change: String,
+    },
+
+    /// Show which peers the guardian is connected to
+    ConnectedPeers,
 }
 
 trait ErrorHandler<T, E> {
@@ -331,11 +959,7 @@ impl<T, E: Error + 'static> ErrorHandler<T, E> for Result<T, E> {
     fn or_terminate(self, err: CliErrorKind, msg: &str) -> T {
         match self {
             Ok(v) => v,
-            Err(e) => {
-                let cli_error = CliError::from(err, msg, Some(Box::new(e)));
-                eprintln!("{cli_error}");
-                exit(1);
-            }
+            Err(e) => terminate(CliError::from(err, msg, Some(Box::new(e)))),
         }
     }
     fn transform<F>(self, success: F, err: CliErrorKind, msg: &str) -> CliResult
@@ -359,26 +983,49 @@ struct PayRequest {
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| EnvFilter::new("error,mint_client=info,fedimint_cli=info")),
-        )
-        .with_writer(std::io::stderr)
-        .init();
+    // Read before parsing the command line, so spans of the whole run are exported
+    let otlp_endpoint = std::env::var("FM_OTLP_ENDPOINT").ok().map(|url| {
+        Url::parse(&url).or_terminate(CliErrorKind::InvalidValue, "invalid FM_OTLP_ENDPOINT")
+    });
+    TracingSetup::new("fedimint-cli")
+        .default_directives("error,mint_client=info,fedimint_cli=info")
+        .with_stderr()
+        .otlp_endpoint(otlp_endpoint)
+        .init()
+        .unwrap_or_else(|e| {
+            terminate(CliError::from(
+                CliErrorKind::GeneralFailure,
+                "couldn't set up logging",
+                Some(e.into()),
+            ))
+        });
+
+    run().await;
+    fedimint_logging::shutdown();
+}
 
+async fn run() {
     if let Ok(cli) = CliNoWorkdir::try_parse() {
+        JSON_OUTPUT.store(cli.output == OutputFormat::Json, Ordering::Relaxed);
         // Only commands that don't need the workdir can be used here
-        //TODO: remove allow when there are more commands
-        #[allow(irrefutable_let_patterns)]
-        if let CommandNoWorkdir::VersionHash = cli.command {
-            println!(
-                "{}",
-                CliOutput::VersionHash {
-                    hash: env!("GIT_HASH").to_string()
-                }
-            );
-        };
+        match cli.command {
+            CommandNoWorkdir::VersionHash => print_output(&CliOutput::VersionHash {
+                hash: env!("GIT_HASH").to_string(),
+            }),
+            CommandNoWorkdir::DiscoverFederations { relays } => {
+                let federations = discover_federations(&relays)
+                    .await
+                    .into_iter()
+                    .map(|announcement| DiscoveredFederation {
+                        invite_code: announcement.connect_info.to_string(),
+                        federation_id: announcement.federation_id.to_string(),
+                        meta: announcement.meta,
+                        announced_by: announcement.author,
+                    })
+                    .collect();
+                print_output(&CliOutput::DiscoverFederations { federations });
+            }
+        }
     } else {
         let module_gens = ModuleGenRegistry::from(vec![
             DynModuleGen::from(WalletGen),
@@ -387,38 +1034,111 @@ async fn main() {
         ]);
 
         let cli = Cli::parse();
-        if let Command::JoinFederation { connect } = cli.command {
-            let connect_obj: WsClientConnectInfo = serde_json::from_str(&connect)
-                .or_terminate(CliErrorKind::InvalidValue, "invalid connect info");
-            let api = Arc::new(WsFederationApi::from_urls(&connect_obj))
+        JSON_OUTPUT.store(cli.output == OutputFormat::Json, Ordering::Relaxed);
+        if let Command::JoinFederation { invite } = cli.command {
+            let cfg_path = cli.workdir.join("client.json");
+            if cfg_path.exists() {
+                terminate(CliError::from(
+                    CliErrorKind::InvalidValue,
+                    "the client directory already contains a config",
+                    None,
+                ));
+            }
+
+            let api = Arc::new(WsFederationApi::from_urls(&invite))
                 as Arc<dyn IFederationApi + Send + Sync + 'static>;
             let cfg: ClientConfig = api
-                .download_client_config(&connect_obj.id, module_gens.clone())
+                .download_client_config_from_all_peers(&invite.id, module_gens.clone())
                 .await
                 .or_terminate(
                     CliErrorKind::NetworkError,
-                    "couldn't download config from peer",
+                    "couldn't download a config all guardians in the invite agree on",
                 );
-            let cfg_path = cli.workdir.join("client.json");
             std::fs::create_dir_all(&cli.workdir)
                 .or_terminate(CliErrorKind::IOError, "failed to create config directory");
             let writer = std::fs::File::create(cfg_path)
                 .or_terminate(CliErrorKind::IOError, "couldn't create config.json");
             serde_json::to_writer_pretty(writer, &cfg)
                 .or_terminate(CliErrorKind::IOError, "couldn't write config");
-            println!(
-                "{}",
-                &CliOutput::JoinFederation { joined: (connect) }.to_string()
-            );
+            print_output(&CliOutput::JoinFederation {
+                joined: invite.to_string(),
+            });
             return;
         };
 
         let cfg_path = cli.workdir.join("client.json");
         let db_path = cli.workdir.join("client.db");
-        let cfg: UserClientConfig = load_from_file(&cfg_path).expect("Failed to parse config");
+        let cfg: UserClientConfig = load_from_file(&cfg_path).unwrap_or_else(|e| {
+            terminate(CliError::from(
+                CliErrorKind::IOError,
+                "couldn't load the client config, join a federation first",
+                Some(e.into()),
+            ))
+        });
         let db = fedimint_rocksdb::RocksDb::open(db_path)
             .or_terminate(CliErrorKind::IOError, "could not open transaction db");
-        let db = Database::new(db, module_decode_stubs());
+        let db = match &cli.password {
+            Some(password) => {
+                let db = EncryptedDatabase::new(db);
+                db.unlock(&DatabaseSecret::Password(password.clone()))
+                    .await
+                    .or_terminate(CliErrorKind::InvalidValue, "couldn't unlock transaction db");
+                Database::new(db, module_decode_stubs())
+            }
+            None => {
+                let db = Database::new(db, module_decode_stubs());
+                let params = db
+                    .begin_read_transaction()
+                    .await
+                    .get_value(&EncryptionParamsKey)
+                    .await
+                    .expect("DB error");
+                if params.is_some() {
+                    terminate(CliError::from(
+                        CliErrorKind::InvalidValue,
+                        "transaction db is encrypted, a password is required",
+                        None,
+                    ));
+                }
+                db
+            }
+        };
+
+        if let Command::ImportSecret { secret } = cli.command {
+            Client::<UserClientConfig>::import_secret(&db, secret)
+                .await
+                .or_terminate(CliErrorKind::InvalidValue, "couldn't import secret");
+            print_output(&CliOutput::ImportSecret);
+            return;
+        }
+
+        if let Command::ImportMnemonic { mnemonic } = &cli.command {
+            Client::<UserClientConfig>::import_mnemonic(&db, mnemonic)
+                .await
+                .or_terminate(CliErrorKind::InvalidValue, "couldn't import mnemonic");
+            print_output(&CliOutput::ImportSecret);
+            return;
+        }
+
+        if let Command::Restore {
+            secret: Some(secret),
+            ..
+        } = &cli.command
+        {
+            Client::<UserClientConfig>::import_secret(&db, secret.clone())
+                .await
+                .or_terminate(CliErrorKind::InvalidValue, "couldn't import secret");
+        }
+
+        if let Command::Restore {
+            mnemonic: Some(mnemonic),
+            ..
+        } = &cli.command
+        {
+            Client::<UserClientConfig>::import_mnemonic(&db, mnemonic)
+                .await
+                .or_terminate(CliErrorKind::InvalidValue, "couldn't import mnemonic");
+        }
 
         let rng = rand::rngs::OsRng;
 
@@ -429,41 +1149,64 @@ async fn main() {
         ]);
 
         let client = Client::new(cfg.clone(), decoders, module_gens, db, Default::default()).await;
+        // Finish operations a previous invocation was interrupted in
+        client.resume_operations().await;
+
+        if let Command::Shell = cli.command {
+            run_shell(&cli.workdir, Arc::new(client), rng).await;
+            return;
+        }
 
-        let cli_result = handle_command(cli, client, rng).await;
+        let cli_result = handle_command(cli.command, Arc::new(client), rng).await;
 
         match cli_result {
-            Ok(output) => {
-                println!("{output}");
-            }
-            Err(err) => {
-                eprintln!("{err}");
-                exit(1);
-            }
+            Ok(output) => print_output(&output),
+            Err(err) => terminate(err),
         }
     }
 }
 
 async fn handle_command(
-    cli: Cli,
-    client: Client<UserClientConfig>,
+    command: Command,
+    client: Arc<Client<UserClientConfig>>,
     mut rng: rand::rngs::OsRng,
 ) -> CliResult {
     let mut task_group = TaskGroup::new();
-    match cli.command {
-        Command::Api { method, arg } => {
-            let arg: Value = serde_json::from_str(&arg).unwrap();
+    match command {
+        Command::Api {
+            method,
+            arg,
+            strategy,
+        } => {
+            let arg: Value = match serde_json::from_str(&arg) {
+                Ok(arg) => arg,
+                Err(e) => {
+                    return Err(CliError::from(
+                        CliErrorKind::InvalidValue,
+                        "the argument isn't valid JSON",
+                        Some(e.into()),
+                    ))
+                }
+            };
             let ws_api: Arc<_> = WsFederationApi::from_config(client.config().as_ref()).into();
-            let response: Value = ws_api
-                .request_with_strategy(
-                    EventuallyConsistent::new(ws_api.peers().len()),
-                    method,
-                    vec![arg],
-                )
-                .await
-                .unwrap();
+            let response: Result<Value, _> = match strategy {
+                Some(strategy) => ws_api.request_with_kind(strategy, method, vec![arg]).await,
+                None => {
+                    ws_api
+                        .request_with_strategy(
+                            EventuallyConsistent::new(ws_api.peers().len()),
+                            method,
+                            vec![arg],
+                        )
+                        .await
+                }
+            };
 
-            Ok(CliOutput::UntypedApiOutput { value: response })
+            response.transform(
+                |value| CliOutput::UntypedApiOutput { value },
+                CliErrorKind::GeneralFederationError,
+                "the API request failed",
+            )
         }
         Command::VersionHash => Ok(CliOutput::VersionHash {
             hash: env!("GIT_HASH").to_string(),
@@ -486,7 +1229,17 @@ async fn handle_command(
                 "peg-in failed (no further information)",
             ),
 
+        Command::AcceleratePegIn { transaction } => {
+            let tx_id = transaction.txid();
+            client.accelerate_peg_in(transaction).await.transform(
+                |_| CliOutput::AcceleratePegIn { tx_id },
+                CliErrorKind::GeneralFederationError,
+                "peg-in acceleration failed",
+            )
+        }
+
         Command::Reissue { notes } => {
+            let notes = federation_notes(&client, notes)?;
             let id = client.reissue(notes, &mut rng).await;
             id.transform(
                 |v| CliOutput::Reissue { id: (v) },
@@ -494,41 +1247,93 @@ async fn handle_command(
                 "could not reissue notes (no further information)",
             )
         }
-        Command::Validate { notes } => {
-            let validate_result = client.validate_note_signatures(&notes).await;
-            let details_vec = notes
-                .iter()
-                .map(|(amount, notes)| (amount.to_owned(), notes.len()))
-                .collect();
-
-            match validate_result {
-                Ok(()) => Ok(CliOutput::Validate {
-                    all_valid: true,
-                    details: (details_vec),
-                }),
-                Err(_) => Ok(CliOutput::Validate {
-                    all_valid: false,
-                    details: (details_vec),
-                }),
-            }
-        }
-        Command::Spend { amount } => client.spend_ecash(amount, rng).await.transform(
-            |v| CliOutput::Spend {
-                note: (serialize_ecash(&v)),
-            },
-            CliErrorKind::GeneralFederationError,
-            "failed to execute spend (no further information)",
-        ),
-        Command::Fetch => match client.fetch_all_notes().await {
-            Ok(result) => Ok(CliOutput::Fetch { issuance: (result) }),
-            Err(error) => Err(CliError::from(
-                CliErrorKind::GeneralFederationError,
-                "failed to fetch notes",
-                Some(Box::new(error)),
+        Command::Validate { notes } => Ok(validate_notes(&client, &notes).await),
+        Command::SplitNotes { notes, amount } => match notes.split(amount) {
+            Some((notes, remainder)) => Ok(CliOutput::SplitNotes {
+                notes: notes.to_string(),
+                remainder: remainder.to_string(),
+            }),
+            None => Err(CliError::from(
+                CliErrorKind::InvalidValue,
+                "the notes can't be split into this amount, reissue them and use spend instead",
+                None,
             )),
         },
-        Command::Info => {
-            let notes = client.notes().await;
+        Command::CombineNotes { notes } => {
+            let mut notes = notes.into_iter();
+            let first = notes.next().expect("at least one is required");
+            notes.try_fold(first, EcashTransfer::combine).transform(
+                |combined| CliOutput::CombineNotes {
+                    notes: combined.to_string(),
+                },
+                CliErrorKind::InvalidValue,
+                "couldn't combine notes",
+            )
+        }
+        Command::Reconcile { notes } => {
+            let mut all_notes = TieredMulti::default();
+            for transfer in notes {
+                all_notes.extend(federation_notes(&client, transfer)?);
+            }
+            client.reconcile_notes(all_notes, &mut rng).await.transform(
+                |reconciliation| CliOutput::Reconcile { reconciliation },
+                CliErrorKind::GeneralFederationError,
+                "could not reconcile notes (no further information)",
+            )
+        }
+        Command::Spend { amount, selection } => {
+            let notes = match selection {
+                Some(selection) => client.spend_ecash_with(amount, selection, rng).await,
+                None => client.spend_ecash(amount, rng).await,
+            };
+            notes.transform(
+                |v| CliOutput::Spend {
+                    note: EcashTransfer::new(client.config().as_ref().federation_id.clone(), v)
+                        .to_string(),
+                },
+                CliErrorKind::GeneralFederationError,
+                "failed to execute spend (no further information)",
+            )
+        }
+        Command::Fetch => match client.fetch_all_notes().await {
+            Ok(result) => Ok(CliOutput::Fetch { issuance: (result) }),
+            Err(error) => Err(CliError::from(
+                CliErrorKind::GeneralFederationError,
+                "failed to fetch notes",
+                Some(Box::new(error)),
+            )),
+        },
+        Command::RefreshNotes => client.refresh_aging_notes(&mut rng).await.transform(
+            |v| CliOutput::RefreshNotes { issuance: (v) },
+            CliErrorKind::GeneralFederationError,
+            "could not refresh notes (no further information)",
+        ),
+        Command::ConsolidateNotes { notes_per_tier } => client
+            .consolidate_notes(notes_per_tier, &mut rng)
+            .await
+            .transform(
+                |v| CliOutput::ConsolidateNotes { issuance: (v) },
+                CliErrorKind::GeneralFederationError,
+                "could not consolidate notes (no further information)",
+            ),
+        Command::Meta { max_age } => client
+            .federation_meta(Duration::from_secs(max_age))
+            .await
+            .transform(
+                |meta| CliOutput::FederationMeta { meta },
+                CliErrorKind::GeneralFederationError,
+                "couldn't fetch federation meta",
+            ),
+        Command::ModuleVersions => WsFederationApi::from_config(client.config().as_ref())
+            .fetch_module_versions()
+            .await
+            .transform(
+                |module_versions| CliOutput::ModuleVersions { module_versions },
+                CliErrorKind::GeneralFederationError,
+                "couldn't fetch the module versions",
+            ),
+        Command::Info => {
+            let notes = client.notes().await;
             let details_vec = notes
                 .iter()
                 .map(|(amount, notes)| (amount.to_owned(), notes.len()))
@@ -550,7 +1355,10 @@ async fn handle_command(
                         .await_peg_out_outcome(out_point)
                         .await
                         .transform(
-                            |txid| CliOutput::PegOut { tx_id: (txid) },
+                            |out_point| CliOutput::PegOut {
+                                tx_id: out_point.txid,
+                                vout: out_point.vout,
+                            },
                             CliErrorKind::GeneralFederationError,
                             "invalid peg-out outcome",
                         ),
@@ -567,34 +1375,78 @@ async fn handle_command(
                 )),
             }
         }
-        Command::LnPay { bolt11 } => {
-            match client.fund_outgoing_ln_contract(bolt11, &mut rng).await {
-                Ok((contract_id, outpoint)) => {
-                    match client.await_outgoing_contract_acceptance(outpoint).await {
-                        Ok(_) => client
-                            .await_outgoing_contract_execution(contract_id, &mut rng)
-                            .await
-                            .transform(
-                                |_| CliOutput::LnPay {
-                                    contract_id: (contract_id),
-                                },
-                                CliErrorKind::GeneralFederationError,
-                                "gateway failed to execute contract",
-                            ),
-                        Err(e) => Err(CliError::from(
-                            CliErrorKind::Timeout,
-                            "contract wasn't accepted in time",
-                            Some(Box::new(e)),
-                        )),
+        Command::PegOutDescriptor { descriptor } => {
+            let output = CliOutput::PegOutDescriptor {
+                descriptor: descriptor.as_ref().map(ToString::to_string),
+            };
+            client
+                .wallet_client()
+                .set_peg_out_descriptor(descriptor)
+                .await;
+            Ok(output)
+        }
+        Command::PegOutAddress => client
+            .wallet_client()
+            .new_peg_out_address(None)
+            .await
+            .transform(
+                |address| CliOutput::PegOutAddress { address },
+                CliErrorKind::InvalidValue,
+                "couldn't derive peg-out address",
+            ),
+        Command::LnPay {
+            payment,
+            amount,
+            gateway_selection,
+            max_attempts,
+        } => {
+            let bolt11 = match payment {
+                PayTarget::Invoice(invoice) => {
+                    if amount.map_or(false, |amount| {
+                        invoice.amount_milli_satoshis() != Some(amount.msats)
+                    }) {
+                        return Err(CliError::from(
+                            CliErrorKind::InvalidValue,
+                            "the invoice is for a different amount than --amount",
+                            None,
+                        ));
                     }
+                    invoice
                 }
-                Err(e) => Err(CliError::from(
-                    CliErrorKind::GeneralFederationError,
-                    "Failure creating outgoing LN contract",
-                    Some(Box::new(e)),
-                )),
-            }
+                PayTarget::Lnurl(url) => fetch_lnurl_invoice(url, amount).await?,
+            };
+            pay_invoice(&client, bolt11, gateway_selection, max_attempts, rng).await
         }
+        Command::LnurlPay {
+            target,
+            amount,
+            gateway_selection,
+            max_attempts,
+        } => match client
+            .ln_client()
+            .fetch_lnurl_invoice(&target, amount)
+            .await
+        {
+            Ok(bolt11) => pay_invoice(&client, bolt11, gateway_selection, max_attempts, rng).await,
+            Err(e) => Err(CliError::from(
+                CliErrorKind::GeneralFederationError,
+                "couldn't fetch invoice",
+                Some(Box::new(e)),
+            )),
+        },
+        Command::OfferPay {
+            offer,
+            amount,
+            gateway_selection,
+            max_attempts,
+        } => client
+            .pay_offer(&offer, amount, gateway_selection, max_attempts, &mut rng)
+            .await
+            .transform(
+                |contract_id| CliOutput::LnPay { contract_id },
+                CliErrorKind::GeneralFederationError,
+                "failed to pay offer",
+            ),
         Command::LnInvoice {
             amount,
             description,
@@ -609,6 +1461,27 @@ async fn handle_command(
                 CliErrorKind::GeneralFederationError,
                 "couldn't create invoice",
             ),
+        Command::LnHoldInvoice {
+            amount,
+            description,
+            hold_blocks,
+            expiry_time,
+        } => client
+            .generate_confirmed_hold_invoice(
+                amount,
+                description,
+                hold_blocks,
+                &mut rng,
+                expiry_time,
+            )
+            .await
+            .transform(
+                |confirmed_invoice| CliOutput::LnInvoice {
+                    invoice: (confirmed_invoice.invoice),
+                },
+                CliErrorKind::GeneralFederationError,
+                "couldn't create invoice",
+            ),
         Command::WaitInvoice { invoice } => {
             let contract_id = (*invoice.payment_hash()).into();
             client
@@ -622,6 +1495,108 @@ async fn handle_command(
                     "invoice did not get paid in time",
                 )
         }
+        Command::ReleaseInvoice { invoice } => {
+            let contract_id = (*invoice.payment_hash()).into();
+            client
+                .release_incoming_contract(contract_id, &mut rng)
+                .await
+                .transform(
+                    |outpoint| CliOutput::ReleaseInvoice {
+                        released_in_tx: (outpoint),
+                    },
+                    CliErrorKind::GeneralFederationError,
+                    "couldn't release invoice",
+                )
+        }
+        Command::ClaimPendingInvoices => client
+            .claim_pending_incoming_contracts(&mut rng)
+            .await
+            .transform(
+                |claimed_in_txs| CliOutput::ClaimPendingInvoices { claimed_in_txs },
+                CliErrorKind::GeneralFederationError,
+                "couldn't claim pending invoices",
+            ),
+        Command::LnPayments => {
+            client.update_outgoing_payments(&mut rng).await;
+            Ok(CliOutput::LnPayments {
+                payments: client.list_outgoing_payments().await,
+            })
+        }
+        Command::History { limit, before } => {
+            client.update_operations().await;
+            Ok(CliOutput::History {
+                operations: client.list_operations(before, limit).await,
+            })
+        }
+        Command::Watch { hook, interval } => {
+            watch_incoming_funds(client, hook, Duration::from_secs(interval), rng).await
+        }
+        Command::TxOutcome { txid, strategy } => {
+            client.fetch_tx_outcome(txid, strategy).await.transform(
+                |status| CliOutput::TxOutcome { status },
+                CliErrorKind::GeneralFederationError,
+                "couldn't fetch transaction outcome",
+            )
+        }
+        Command::ContractProof { contract_id } => {
+            client.contract_proof(contract_id).await.transform(
+                |proof| CliOutput::ContractProof { proof },
+                CliErrorKind::GeneralFederationError,
+                "couldn't create contract proof",
+            )
+        }
+        Command::RegisterRefund {
+            contract_id,
+            watchtower,
+        } => {
+            let watchtower = match watchtower {
+                Some(watchtower) => watchtower,
+                None => {
+                    client
+                        .fetch_active_gateway()
+                        .await
+                        .or_terminate(CliErrorKind::GeneralFailure, "couldn't find a gateway")
+                        .api
+                }
+            };
+            client
+                .register_watchtower_refund(contract_id, &watchtower, &mut rng)
+                .await
+                .transform(
+                    |_| CliOutput::RegisterRefund {
+                        watchtower: watchtower.clone(),
+                    },
+                    CliErrorKind::GeneralFailure,
+                    "couldn't register refund with watchtower",
+                )
+        }
+        Command::VerifyContractProof { proof } => {
+            let proof: ContractProof = serde_json::from_str(&proof)
+                .or_terminate(CliErrorKind::SerializationError, "invalid contract proof");
+            proof
+                .verify(&client.config().as_ref().epoch_pk, client.decoders())
+                .transform(
+                    |_| CliOutput::VerifyContractProof {
+                        contract_id: proof.evidence.contract_id,
+                        settled: proof.evidence.is_settled(),
+                    },
+                    CliErrorKind::InvalidValue,
+                    "contract proof is invalid",
+                )
+        }
+        Command::LnOffer { description } => client
+            .create_receive_offer(description, &mut rng)
+            .await
+            .transform(
+                |offer| CliOutput::LnOffer { offer },
+                CliErrorKind::GeneralFederationError,
+                "couldn't create offer",
+            ),
+        Command::ClaimOfferPayments => client.claim_offer_payments(&mut rng).await.transform(
+            |claimed_in_tx| CliOutput::ClaimOfferPayments { claimed_in_tx },
+            CliErrorKind::GeneralFederationError,
+            "couldn't claim offer payments",
+        ),
         Command::WaitBlockHeight { height } => {
             client.await_consensus_block_height(height).await.transform(
                 |_| CliOutput::WaitBlockHeight { reached: (height) },
@@ -632,6 +1607,7 @@ async fn handle_command(
         Command::ConnectInfo => {
             let info = WsClientConnectInfo::from_honest_peers(client.config().as_ref());
             Ok(CliOutput::ConnectInfo {
+                invite_code: info.to_string(),
                 connect_info: (info),
             })
         }
@@ -697,33 +1673,630 @@ async fn handle_command(
                 )),
             }
         }
-        Command::Backup => match client.mint_client().back_up_ecash_to_federation().await {
-            Ok(_) => Ok(CliOutput::Backup),
+        Command::Backup { metadata } => {
+            let mint_client = client.mint_client();
+            if let Some(metadata) = metadata {
+                mint_client
+                    .set_backup_metadata(Some(metadata).filter(|metadata| !metadata.is_empty()))
+                    .await;
+            }
+            match mint_client.back_up_ecash_to_federation().await {
+                Ok(_) => Ok(CliOutput::Backup {
+                    metadata: mint_client.backup_metadata().await,
+                }),
+                Err(e) => Err(CliError::from(
+                    CliErrorKind::GeneralFederationError,
+                    "failed",
+                    Some(e.into()),
+                )),
+            }
+        }
+        Command::Restore { gap_limit, .. } => {
+            let last_percent = AtomicU64::new(u64::MAX);
+            let progress = |progress: RecoveryProgress| {
+                let percent = progress.percent();
+                if last_percent.swap(percent, Ordering::Relaxed) != percent {
+                    eprintln!(
+                        "Scanned epoch {} of {} ({percent}%)",
+                        progress.epoch + 1,
+                        progress.end_epoch
+                    );
+                }
+            };
+            match client
+                .mint_client()
+                .restore_ecash_from_federation_with_progress(gap_limit, &mut task_group, progress)
+                .await
+            {
+                Ok(_) => Ok(CliOutput::Restore {
+                    total_amount: client.notes().await.total_amount(),
+                    metadata: client.mint_client().backup_metadata().await,
+                }),
+                Err(e) => Err(CliError::from(
+                    CliErrorKind::GeneralFederationError,
+                    "failed",
+                    Some(e.into()),
+                )),
+            }
+        }
+        Command::ExportSecret => Ok(CliOutput::ExportSecret {
+            secret: client.export_secret().await.to_hex(),
+            mnemonic: client
+                .export_mnemonic()
+                .await
+                .map(|mnemonic| mnemonic.to_string()),
+        }),
+        Command::ImportSecret { .. } | Command::ImportMnemonic { .. } | Command::Shell => {
+            unreachable!("handled before creating the client")
+        }
+        Command::WipeNotes => match client.mint_client().wipe_notes().await {
+            Ok(_) => Ok(CliOutput::WipeNotes),
             Err(e) => Err(CliError::from(
                 CliErrorKind::GeneralFederationError,
                 "failed",
                 Some(e.into()),
             )),
         },
-        Command::Restore { gap_limit } => match client
-            .mint_client()
-            .restore_ecash_from_federation(gap_limit, &mut task_group)
+        Command::Admin {
+            peer_id,
+            auth,
+            command,
+        } => {
+            handle_admin_command(
+                client.config().as_ref(),
+                PeerId::from(peer_id),
+                auth.map(ApiAuth),
+                command,
+            )
             .await
-        {
-            Ok(_) => Ok(CliOutput::Backup),
-            Err(e) => Err(CliError::from(
+        }
+    }
+}
+
+/// Sends an admin command to the guardian `peer_id` of the federation
+async fn handle_admin_command(
+    config: &ClientConfig,
+    peer_id: PeerId,
+    auth: Option<ApiAuth>,
+    command: AdminCommand,
+) -> CliResult {
+    let Some(node) = config.nodes.get(peer_id.to_usize()) else {
+        return Err(CliError::from(
+            CliErrorKind::InvalidValue,
+            "the federation has no guardian with this peer id",
+            None,
+        ));
+    };
+    let admin_api = WsFederationApi::new(vec![(peer_id, node.url.clone())]);
+    let auth = || {
+        auth.clone().ok_or_else(|| {
+            CliError::from(
+                CliErrorKind::InvalidValue,
+                "the admin password has to be given with --auth or FM_ADMIN_AUTH",
+                None,
+            )
+        })
+    };
+
+    match command {
+        AdminCommand::Status => admin_api.guardian_status(auth()?).await.transform(
+            |status| CliOutput::GuardianStatus { status },
+            CliErrorKind::GeneralFederationError,
+            "failed to get the guardian status",
+        ),
+        AdminCommand::Audit => admin_api.audit().await.transform(
+            |audit| CliOutput::Audit { audit },
+            CliErrorKind::GeneralFederationError,
+            "failed to get the audit",
+        ),
+        AdminCommand::BackupDb => admin_api.backup_database(auth()?).await.transform(
+            |backup_path| CliOutput::BackupDb { backup_path },
+            CliErrorKind::GeneralFederationError,
+            "failed to back up the guardian's database",
+        ),
+        AdminCommand::SignalUpgrade => admin_api.signal_upgrade(auth()?).await.transform(
+            |()| CliOutput::SignalUpgrade {
+                upgrade_signaled: true,
+            },
+            CliErrorKind::GeneralFederationError,
+            "failed to signal the upgrade",
+        ),
+        AdminCommand::VoteModuleVersion {
+            module_instance_id,
+            version,
+        } => {
+            let vote = ModuleVersionVote {
+                module_instance_id,
+                version: ModuleConsensusVersion(version),
+            };
+            admin_api
+                .vote_module_version(auth()?, vote.clone())
+                .await
+                .transform(
+                    |()| CliOutput::VoteModuleVersion { vote },
+                    CliErrorKind::GeneralFederationError,
+                    "failed to vote for the module version",
+                )
+        }
+        AdminCommand::VoteModuleParams {
+            module_instance_id,
+            activation_epoch,
+            change,
+        } => {
+            let vote = ModuleParamsVote {
+                module_instance_id,
+                activation_epoch,
+                change,
+            };
+            admin_api.vote_module_params(auth()?, vote).await.transform(
+                |vote| CliOutput::VoteModuleParams { vote },
                 CliErrorKind::GeneralFederationError,
-                "failed",
+                "failed to vote for the module params",
+            )
+        }
+        AdminCommand::ConnectedPeers => admin_api.connected_peers(auth()?).await.transform(
+            |peers| CliOutput::ConnectedPeers { peers },
+            CliErrorKind::GeneralFederationError,
+            "failed to get the connected peers",
+        ),
+    }
+}
+
+/// File in the workdir the commands entered in `shell` are kept in
+const SHELL_HISTORY_FILE: &str = "shell_history";
+
+const SHELL_PROMPT: &str = "fedimint> ";
+
+/// A line entered in `shell`, parsed like the arguments of a single invocation
+#[derive(Parser)]
+#[command(no_binary_name = true, disable_version_flag = true, name = "")]
+struct ShellLine {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+/// Reads commands from the terminal and runs them against the already loaded
+/// `client` until `exit` or the end of the input
+async fn run_shell(workdir: &Path, client: Arc<Client<UserClientConfig>>, rng: rand::rngs::OsRng) {
+    let mut editor = Editor::<ShellHelper>::new()
+        .or_terminate(CliErrorKind::IOError, "couldn't open the terminal");
+    editor.set_helper(Some(ShellHelper {
+        command: ShellLine::command(),
+    }));
+    let history_path = workdir.join(SHELL_HISTORY_FILE);
+    // Doesn't exist the first time the shell is used
+    let _ = editor.load_history(&history_path);
+
+    loop {
+        let line = match tokio::task::block_in_place(|| editor.readline(SHELL_PROMPT)) {
+            Ok(line) => line,
+            // Like in other shells Ctrl-C only discards the current line
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => terminate(CliError::from(
+                CliErrorKind::IOError,
+                "couldn't read from the terminal",
                 Some(e.into()),
             )),
-        },
-        Command::WipeNotes => match client.mint_client().wipe_notes().await {
-            Ok(_) => Ok(CliOutput::Backup),
-            Err(e) => Err(CliError::from(
-                CliErrorKind::GeneralFederationError,
-                "failed",
-                Some(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line);
+        if line == "exit" || line == "quit" {
+            break;
+        }
+
+        let Some(args) = shlex::split(line) else {
+            print_error(&CliError::from(
+                CliErrorKind::InvalidValue,
+                "the command has unbalanced quotes",
+                None,
+            ));
+            continue;
+        };
+        let command = match ShellLine::try_parse_from(args) {
+            Ok(line) => line.command,
+            Err(e) => {
+                // Also prints the help if it was asked for
+                let _ = e.print();
+                continue;
+            }
+        };
+        let result = match command {
+            // These have to happen before the client is created
+            Command::Shell
+            | Command::JoinFederation { .. }
+            | Command::ImportSecret { .. }
+            | Command::ImportMnemonic { .. }
+            | Command::Restore {
+                secret: Some(_), ..
+            }
+            | Command::Restore {
+                mnemonic: Some(_), ..
+            } => Err(CliError::from(
+                CliErrorKind::InvalidValue,
+                "this command can't be used in the shell, run it on its own instead",
+                None,
             )),
-        },
+            command => handle_command(command, client.clone(), rng).await,
+        };
+        match result {
+            Ok(output) => print_output(&output),
+            Err(err) => print_error(&err),
+        }
+    }
+
+    if let Err(e) = editor.save_history(&history_path) {
+        warn!("Failed to save the shell history: {}", e);
+    }
+}
+
+/// Completes the names of commands and their long flags in `shell`
+struct ShellHelper {
+    command: clap::Command,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |idx| idx + 1);
+        let word = &line[start..];
+
+        // Descend into the (nested) subcommand the previous words name
+        let mut command = &self.command;
+        for name in line[..start].split_whitespace() {
+            match command.find_subcommand(name) {
+                Some(subcommand) => command = subcommand,
+                None => break,
+            }
+        }
+
+        let candidates = if word.starts_with('-') {
+            command
+                .get_arguments()
+                .filter_map(|arg| arg.get_long())
+                .map(|long| format!("--{long}"))
+                .filter(|flag| flag.starts_with(word))
+                .collect()
+        } else {
+            let mut names: Vec<String> = command
+                .get_subcommands()
+                .filter(|subcommand| !subcommand.is_hide_set())
+                .map(|subcommand| subcommand.get_name().to_owned())
+                .collect();
+            if start == 0 {
+                names.push("exit".to_owned());
+            }
+            names.retain(|name| name.starts_with(word));
+            names
+        };
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Kind of the incoming payments reported by `watch`
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum IncomingFundsKind {
+    /// Ecash notes of someone else that we reissued
+    Ecash,
+    /// A peg-in whose deposit got confirmed
+    Deposit,
+    /// A payment to one of our lightning invoices that we claimed
+    Lightning,
+}
+
+impl IncomingFundsKind {
+    fn from_operation(kind: &OperationKind) -> Option<Self> {
+        match kind {
+            OperationKind::ReceiveEcash { .. } => Some(IncomingFundsKind::Ecash),
+            OperationKind::PegIn { .. } => Some(IncomingFundsKind::Deposit),
+            OperationKind::LnReceive { .. } => Some(IncomingFundsKind::Lightning),
+            OperationKind::PegOut { .. }
+            | OperationKind::SpendEcash
+            | OperationKind::LnPay { .. }
+            | OperationKind::Module { .. } => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            IncomingFundsKind::Ecash => "ecash",
+            IncomingFundsKind::Deposit => "deposit",
+            IncomingFundsKind::Lightning => "lightning",
+        }
+    }
+}
+
+/// Claims paid invoices and updates pending operations every `interval` in
+/// the background, and reports every incoming payment that succeeded until the
+/// process gets interrupted
+async fn watch_incoming_funds(
+    client: Arc<Client<UserClientConfig>>,
+    hook: Option<String>,
+    interval: Duration,
+    rng: rand::rngs::OsRng,
+) -> CliResult {
+    // Subscribe first so nothing claimed by the background task is missed
+    let mut events = client.subscribe_events().await;
+
+    let mut task_group = TaskGroup::new();
+    let bg_client = client.clone();
+    task_group
+        .spawn("claim incoming funds", move |handle| async move {
+            while !handle.is_shutting_down() {
+                if let Err(e) = bg_client.claim_pending_incoming_contracts(rng).await {
+                    warn!("Failed to claim paid invoices: {}", e);
+                }
+                bg_client.update_operations().await;
+                sleep(interval).await;
+            }
+        })
+        .await;
+
+    while let Some(event) = events.next().await {
+        let ClientEvent::OperationFinished(record) = event else {
+            continue;
+        };
+        let Some(kind) = IncomingFundsKind::from_operation(&record.kind) else {
+            continue;
+        };
+        if record.outcome != OperationOutcome::Succeeded {
+            continue;
+        }
+
+        let output = CliOutput::IncomingFunds {
+            kind,
+            amount: record.amount,
+            operation_id: record.id,
+        };
+        print_output(&output);
+        if let Some(hook) = &hook {
+            run_hook(hook, kind, &record, &output).await;
+        }
+    }
+
+    Err(CliError::from(
+        CliErrorKind::GeneralFailure,
+        "the client stopped emitting events",
+        None,
+    ))
+}
+
+/// Runs the `--hook` of `watch` for an incoming payment, failures are only
+/// logged so a broken hook doesn't stop the watching
+async fn run_hook(
+    hook: &str,
+    kind: IncomingFundsKind,
+    record: &OperationRecord,
+    output: &CliOutput,
+) {
+    let status = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .env("FM_INCOMING_KIND", kind.as_str())
+        .env("FM_INCOMING_AMOUNT_MSAT", record.amount.msats.to_string())
+        .env("FM_INCOMING_OPERATION_ID", record.id.to_string())
+        .env(
+            "FM_INCOMING_JSON",
+            serde_json::to_string(output).expect("serialization can't fail"),
+        )
+        .status()
+        .await;
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!(operation_id = %record.id, "Hook failed with {}", status),
+        Err(e) => warn!(operation_id = %record.id, "Failed to run hook: {}", e),
+    }
+}
+
+/// Checks notes received from someone else as far as possible without
+/// contacting the federation
+async fn validate_notes(client: &Client<UserClientConfig>, notes: &str) -> CliOutput {
+    let transfer = match EcashTransfer::from_str(notes.trim()) {
+        Ok(transfer) => transfer,
+        Err(e) => {
+            return CliOutput::Validate {
+                all_valid: false,
+                details: BTreeMap::new(),
+                total_amount: Amount::ZERO,
+                problems: vec![e.to_string()],
+            }
+        }
+    };
+
+    let mut problems = vec![];
+    if transfer.notes.is_empty() {
+        problems.push("contains no notes".to_string());
+    }
+    if transfer.federation_id != client.config().as_ref().federation_id {
+        problems.push("issued by a different federation".to_string());
+    } else {
+        // Checked per tier to report all tiers with problems
+        for (amount, notes) in transfer.notes.iter() {
+            let tier = TieredMulti::new(BTreeMap::from([(*amount, notes.clone())]));
+            match client.validate_note_signatures(&tier).await {
+                Ok(()) => {}
+                Err(ClientError::InvalidAmountTier(_)) => {
+                    problems.push(format!("the federation doesn't issue notes of {amount}"))
+                }
+                Err(_) => problems.push(format!("notes of {amount} have invalid signatures")),
+            }
+        }
+    }
+
+    CliOutput::Validate {
+        all_valid: problems.is_empty(),
+        details: transfer
+            .notes
+            .iter()
+            .map(|(amount, notes)| (*amount, notes.len()))
+            .collect(),
+        total_amount: transfer.notes.total_amount(),
+        problems,
+    }
+}
+
+/// Fetches an invoice from the LNURL-pay service at `url` for `amount`, or
+/// for the only amount the service accepts. The amount is asked for on the
+/// terminal if neither is known.
+async fn fetch_lnurl_invoice(
+    url: Url,
+    amount: Option<Amount>,
+) -> Result<lightning_invoice::Invoice, CliError> {
+    let pay_request = lnurl::fetch_pay_request(url).await.map_err(|e| {
+        CliError::from(
+            CliErrorKind::NetworkError,
+            "couldn't fetch the LNURL-pay parameters",
+            Some(e.into()),
+        )
+    })?;
+    let min = Amount::from_msats(pay_request.min_sendable);
+    let max = Amount::from_msats(pay_request.max_sendable);
+    let amount = match amount {
+        Some(amount) => amount,
+        None if min == max => min,
+        None => prompt_amount(min, max)?,
+    };
+
+    lnurl::fetch_invoice(&pay_request, amount)
+        .await
+        .map_err(|e| match e {
+            LnurlError::AmountOutOfRange { .. } => CliError::from(
+                CliErrorKind::InvalidValue,
+                "the amount is not accepted by the LNURL service",
+                Some(e.into()),
+            ),
+            e => CliError::from(
+                CliErrorKind::NetworkError,
+                "couldn't fetch invoice",
+                Some(e.into()),
+            ),
+        })
+}
+
+/// Asks the user for an amount between `min` and `max`, fails if there is no
+/// one to ask
+fn prompt_amount(min: Amount, max: Amount) -> Result<Amount, CliError> {
+    if JSON_OUTPUT.load(Ordering::Relaxed) || !atty::is(atty::Stream::Stdin) {
+        return Err(CliError::from(
+            CliErrorKind::InvalidValue,
+            &format!(
+                "the amount has to be given with --amount, the service accepts {min} to {max}"
+            ),
+            None,
+        ));
+    }
+
+    eprint!("Amount to pay ({min} to {max}): ");
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line).map_err(|e| {
+        CliError::from(
+            CliErrorKind::IOError,
+            "couldn't read the amount",
+            Some(e.into()),
+        )
+    })?;
+    parse_fedimint_amount(line.trim())
+        .map_err(|e| CliError::from(CliErrorKind::InvalidValue, "invalid amount", Some(e.into())))
+}
+
+/// Pays `bolt11` through the gateway chosen by `gateway_selection`, or the
+/// active gateway if none is given. Invoices of our own federation's users are
+/// paid without a gateway.
+async fn pay_invoice(
+    client: &Client<UserClientConfig>,
+    bolt11: lightning_invoice::Invoice,
+    gateway_selection: Option<GatewaySelection>,
+    max_attempts: usize,
+    mut rng: rand::rngs::OsRng,
+) -> CliResult {
+    match client
+        .pay_invoice_internally(&bolt11.clone().into(), &mut rng)
+        .await
+    {
+        Ok(Some(contract_id)) => return Ok(CliOutput::LnPay { contract_id }),
+        Ok(None) => {}
+        Err(e) => {
+            return Err(CliError::from(
+                CliErrorKind::GeneralFederationError,
+                "failed to pay invoice internally",
+                Some(Box::new(e)),
+            ))
+        }
+    }
+
+    if let Some(selection) = gateway_selection {
+        return client
+            .pay_invoice(bolt11, selection, max_attempts, &mut rng)
+            .await
+            .transform(
+                |contract_id| CliOutput::LnPay { contract_id },
+                CliErrorKind::GeneralFederationError,
+                "failed to pay invoice",
+            );
+    }
+
+    let (contract_id, outpoint) = match client.fund_outgoing_ln_contract(bolt11, &mut rng).await {
+        Ok(funded) => funded,
+        Err(e) => {
+            return Err(CliError::from(
+                CliErrorKind::GeneralFederationError,
+                "Failure creating outgoing LN contract",
+                Some(Box::new(e)),
+            ))
+        }
+    };
+
+    match client.await_outgoing_contract_acceptance(outpoint).await {
+        Ok(_) => client
+            .await_outgoing_contract_execution(contract_id, &mut rng)
+            .await
+            .transform(
+                |_| CliOutput::LnPay {
+                    contract_id: (contract_id),
+                },
+                CliErrorKind::GeneralFederationError,
+                "gateway failed to execute contract",
+            ),
+        Err(e) => Err(CliError::from(
+            CliErrorKind::Timeout,
+            "contract wasn't accepted in time",
+            Some(Box::new(e)),
+        )),
+    }
+}
+
+/// Returns the notes of `transfer` if they were issued by our federation
+fn federation_notes(
+    client: &Client<UserClientConfig>,
+    transfer: EcashTransfer,
+) -> Result<TieredMulti<SpendableNote>, CliError> {
+    if transfer.federation_id != client.config().as_ref().federation_id {
+        return Err(CliError::from(
+            CliErrorKind::InvalidValue,
+            "notes were issued by a different federation",
+            None,
+        ));
     }
+    Ok(transfer.notes)
 }
@@ -7,18 +7,20 @@ use fedimint_api::core::{
     LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
     LEGACY_HARDCODED_INSTANCE_ID_WALLET,
 };
-use fedimint_api::NumPeers;
+use fedimint_api::{NumPeers, OutPoint};
 use fedimint_core::api::FederationApiExt;
 use fedimint_core::api::{
     erased_multi_param, erased_no_param, erased_single_param, FederationResult, IFederationApi,
 };
 use fedimint_core::query::{EventuallyConsistent, Retry404, UnionResponsesSingle};
 use fedimint_mint::db::ECashUserBackupSnapshot;
+use fedimint_mint::{MintInput, MintOutputBlindSignatures, NoteStatus};
 
+use crate::mint::signature_shares::SignatureShareAggregation;
 use crate::modules::ln::contracts::incoming::IncomingContractOffer;
 use crate::modules::ln::contracts::ContractId;
 use crate::modules::ln::{ContractAccount, LightningGateway};
-use crate::modules::wallet::PegOutFees;
+use crate::modules::wallet::{PegInAcceleration, PegOutFees};
 
 #[cfg_attr(target_family = "wasm", async_trait(? Send))]
 #[cfg_attr(not(target_family = "wasm"), async_trait)]
@@ -95,6 +97,13 @@ pub trait MintFederationApi {
         &self,
         id: &secp256k1::XOnlyPublicKey,
     ) -> FederationResult<Vec<ECashUserBackupSnapshot>>;
+    async fn fetch_blind_signatures(
+        &self,
+        out_point: OutPoint,
+        strategy: SignatureShareAggregation,
+    ) -> FederationResult<MintOutputBlindSignatures>;
+    async fn fetch_output_key_epoch(&self, out_point: OutPoint) -> FederationResult<u32>;
+    async fn fetch_note_status(&self, notes: &MintInput) -> FederationResult<Vec<NoteStatus>>;
 }
 
 #[cfg_attr(target_family = "wasm", async_trait(? Send))]
@@ -130,6 +139,35 @@ where
             .flatten()
             .collect())
     }
+
+    async fn fetch_blind_signatures(
+        &self,
+        out_point: OutPoint,
+        strategy: SignatureShareAggregation,
+    ) -> FederationResult<MintOutputBlindSignatures> {
+        self.request_with_strategy(
+            strategy,
+            format!("/module/{LEGACY_HARDCODED_INSTANCE_ID_MINT}/signature_share"),
+            erased_single_param(&out_point),
+        )
+        .await
+    }
+
+    async fn fetch_output_key_epoch(&self, out_point: OutPoint) -> FederationResult<u32> {
+        self.request_current_consensus(
+            format!("/module/{LEGACY_HARDCODED_INSTANCE_ID_MINT}/output_key_epoch"),
+            erased_single_param(&out_point),
+        )
+        .await
+    }
+
+    async fn fetch_note_status(&self, notes: &MintInput) -> FederationResult<Vec<NoteStatus>> {
+        self.request_current_consensus(
+            format!("/module/{LEGACY_HARDCODED_INSTANCE_ID_MINT}/note_status"),
+            erased_single_param(notes),
+        )
+        .await
+    }
 }
 
 #[cfg_attr(target_family = "wasm", async_trait(? Send))]
@@ -141,6 +179,10 @@ pub trait WalletFederationApi {
         address: &Address,
         amount: bitcoin::Amount,
     ) -> FederationResult<Option<PegOutFees>>;
+    async fn request_peg_in_acceleration(
+        &self,
+        acceleration: &PegInAcceleration,
+    ) -> FederationResult<()>;
 }
 
 #[cfg_attr(target_family = "wasm", async_trait(? Send))]
@@ -169,4 +211,15 @@ where
         )
         .await
     }
+
+    async fn request_peg_in_acceleration(
+        &self,
+        acceleration: &PegInAcceleration,
+    ) -> FederationResult<()> {
+        self.request_current_consensus(
+            format!("/module/{LEGACY_HARDCODED_INSTANCE_ID_WALLET}/accelerate_peg_in"),
+            erased_single_param(acceleration),
+        )
+        .await
+    }
 }
@@ -1,14 +1,25 @@
+use fedimint_api::config::FederationId;
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::impl_db_prefix_const;
+use fedimint_api::TransactionId;
 use serde::Serialize;
 use strum_macros::EnumIter;
 
-use crate::ClientSecret;
+use crate::encrypted_db::EncryptionParams;
+use crate::operations::{OperationId, OperationRecord, OperationStateMachine};
+use crate::{CachedFederationMeta, ClientSecret, UserClientConfig};
 
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
 pub enum DbKeyPrefix {
     ClientSecret = 0x29,
+    FederationConfig = 0x39,
+    ActiveFederation = 0x3a,
+    Operation = 0x3b,
+    ClientMnemonic = 0x3c,
+    EncryptionParams = 0x3d,
+    OperationState = 0x3e,
+    FederationMeta = 0x42,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -25,3 +36,86 @@ impl_db_prefix_const!(
     value = ClientSecret,
     prefix = DbKeyPrefix::ClientSecret
 );
+
+/// Entropy of the BIP39 mnemonic the [`ClientSecret`] was derived from, if any
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ClientMnemonicKey;
+
+impl_db_prefix_const!(
+    key = ClientMnemonicKey,
+    value = Vec<u8>,
+    prefix = DbKeyPrefix::ClientMnemonic
+);
+
+/// Config of a federation joined by a [`crate::federations::FederationClients`]
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct FederationConfigKey(pub FederationId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct FederationConfigKeyPrefix;
+
+impl_db_prefix_const!(
+    key = FederationConfigKey,
+    value = UserClientConfig,
+    prefix = DbKeyPrefix::FederationConfig,
+    key_prefix = FederationConfigKeyPrefix
+);
+
+/// Federation operations of a [`crate::federations::FederationClients`]
+/// target by default
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ActiveFederationKey;
+
+impl_db_prefix_const!(
+    key = ActiveFederationKey,
+    value = FederationId,
+    prefix = DbKeyPrefix::ActiveFederation
+);
+
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct OperationKey(pub OperationId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OperationKeyPrefix;
+
+impl_db_prefix_const!(
+    key = OperationKey,
+    value = OperationRecord,
+    prefix = DbKeyPrefix::Operation,
+    key_prefix = OperationKeyPrefix
+);
+
+/// State machine of an in-flight transaction of an operation
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct OperationStateKey(pub TransactionId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OperationStateKeyPrefix;
+
+impl_db_prefix_const!(
+    key = OperationStateKey,
+    value = OperationStateMachine,
+    prefix = DbKeyPrefix::OperationState,
+    key_prefix = OperationStateKeyPrefix
+);
+
+/// Written by an [`crate::encrypted_db::EncryptedDatabase`] below the client,
+/// it is never visible to the client itself
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct EncryptionParamsKey;
+
+impl_db_prefix_const!(
+    key = EncryptionParamsKey,
+    value = EncryptionParams,
+    prefix = DbKeyPrefix::EncryptionParams
+);
+
+/// Last federation meta fetched from the guardians
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct FederationMetaKey;
+
+impl_db_prefix_const!(
+    key = FederationMetaKey,
+    value = CachedFederationMeta,
+    prefix = DbKeyPrefix::FederationMeta
+);
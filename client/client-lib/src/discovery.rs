@@ -0,0 +1,63 @@
+//! Discovery of federations their guardians announced on Nostr relays, see
+//! [`fedimint_core::nostr`]. Announcements only prove that the federation
+//! signed the meta they carry, the guardian urls in them are checked when
+//! joining, which only accepts a config matching the federation id.
+
+use std::collections::HashMap;
+
+use fedimint_core::nostr::{fetch_events, VerifiedAnnouncement, FEDERATION_ANNOUNCEMENT_KIND};
+use futures::future::join_all;
+use serde_json::json;
+use tracing::{debug, warn};
+use url::Url;
+
+/// Fetches the federation announcements stored on `relays` and returns the
+/// latest verified one of every guardian and federation
+pub async fn discover_federations(relays: &[Url]) -> Vec<VerifiedAnnouncement> {
+    let filter = json!({ "kinds": [FEDERATION_ANNOUNCEMENT_KIND] });
+    let fetched = join_all(
+        relays
+            .iter()
+            .map(|relay| fetch_events(relay, filter.clone())),
+    )
+    .await;
+
+    let mut latest = HashMap::new();
+    for (relay, events) in relays.iter().zip(fetched) {
+        let events = match events {
+            Ok(events) => events,
+            Err(e) => {
+                warn!(%relay, "Could not fetch federation announcements: {e:#}");
+                continue;
+            }
+        };
+
+        for event in events {
+            let announcement = match VerifiedAnnouncement::verify(&event) {
+                Ok(announcement) => announcement,
+                Err(e) => {
+                    debug!(%relay, id = %event.id, "Ignoring invalid announcement: {e:#}");
+                    continue;
+                }
+            };
+            let key = (
+                announcement.author.clone(),
+                announcement.federation_id.clone(),
+            );
+            let is_newer = latest
+                .get(&key)
+                .map_or(true, |known: &VerifiedAnnouncement| {
+                    known.created_at < announcement.created_at
+                });
+            if is_newer {
+                latest.insert(key, announcement);
+            }
+        }
+    }
+
+    let mut announcements = latest.into_values().collect::<Vec<_>>();
+    announcements.sort_by(|a, b| {
+        (a.federation_id.to_string(), &a.author).cmp(&(b.federation_id.to_string(), &b.author))
+    });
+    announcements
+}
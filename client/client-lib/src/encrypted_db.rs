@@ -0,0 +1,399 @@
+//! Encryption of the client database, so someone getting hold of the device
+//! can't spend the notes stored on it without knowing the user's secret.
+//!
+//! [`EncryptedDatabase`] wraps the database of a client and encrypts every
+//! value with ChaCha20-Poly1305 under a random data key, authenticating the
+//! entry's key along with it so values can't be swapped between entries. Keys
+//! stay in plaintext to keep prefix scans working, they only reveal metadata
+//! like the amounts of notes. The data key is stored encrypted by a key
+//! derived from the [`DatabaseSecret`] under [`EncryptionParamsKey`], so the
+//! secret can be changed without re-encrypting the whole database.
+//!
+//! The database starts out locked, all reads and writes fail until it is
+//! [unlocked](EncryptedDatabase::unlock). Wallets can
+//! [lock](EncryptedDatabase::lock) it again e.g. when the app goes to the
+//! background, clients using it have to be stopped before as they treat
+//! database errors as fatal.
+
+use std::fmt::{Debug, Formatter};
+use std::io::Cursor;
+use std::sync::{Arc, RwLock};
+
+use aead::{LessSafeKey, UnboundKey};
+use anyhow::format_err;
+use async_trait::async_trait;
+use fedimint_api::db::{
+    DatabaseKeyPrefix, IDatabase, IDatabaseTransaction, PrefixStream, SerializableDatabaseValue,
+};
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use futures::{future, StreamExt};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use ring::aead::CHACHA20_POLY1305;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::db::{DbKeyPrefix, EncryptionParamsKey};
+
+/// Length of the salt passwords are stretched with
+const SALT_LEN: usize = 16;
+/// Length of ChaCha20-Poly1305 keys
+const KEY_LEN: usize = 32;
+
+/// Secret the database is unlocked with
+pub enum DatabaseSecret {
+    /// Password entered by the user, it is stretched with PBKDF2 like the
+    /// passwords encrypting the configs of guardians
+    Password(String),
+    /// Random key kept by the platform's keystore, e.g. behind a biometric
+    /// prompt, it is used as is
+    Key([u8; KEY_LEN]),
+}
+
+impl DatabaseSecret {
+    fn derive_key(&self, salt: &[u8]) -> anyhow::Result<LessSafeKey> {
+        match self {
+            DatabaseSecret::Password(password) => aead::derive_key(password, salt),
+            DatabaseSecret::Key(key) => chacha_key(key),
+        }
+    }
+}
+
+/// Stored unencrypted so the data key can be recovered from the secret
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct EncryptionParams {
+    /// Salt of the password stretching, unused for keys
+    pub salt: Vec<u8>,
+    /// The data key encrypted by the key derived from the secret
+    pub wrapped_key: Vec<u8>,
+}
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("Wrong secret")]
+    WrongSecret,
+    #[error("The database has not been encrypted yet")]
+    NotEncrypted,
+    #[error("Database error: {0}")]
+    Database(#[from] anyhow::Error),
+}
+
+type EncryptionResult<T> = std::result::Result<T, EncryptionError>;
+
+/// Encrypting wrapper around another database, see the module docs. Clones
+/// share the lock state, so wallets can keep one to lock and unlock the
+/// database handed to a client.
+#[derive(Clone)]
+pub struct EncryptedDatabase {
+    inner: Arc<dyn IDatabase>,
+    key: Arc<RwLock<Option<Arc<LessSafeKey>>>>,
+}
+
+impl EncryptedDatabase {
+    /// Wraps `inner`, which may already hold unencrypted entries, they get
+    /// encrypted on the first unlock
+    pub fn new(inner: impl IDatabase + 'static) -> Self {
+        EncryptedDatabase {
+            inner: Arc::new(inner),
+            key: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Makes the database accessible. The first unlock sets up the encryption
+    /// with `secret`, encrypting all entries written before.
+    pub async fn unlock(&self, secret: &DatabaseSecret) -> EncryptionResult<()> {
+        let data_key = match self.params().await? {
+            Some(params) => unwrap_data_key(&params, secret)?,
+            None => self.set_up(secret).await?,
+        };
+        *self.key.write().expect("lock poisoned") = Some(Arc::new(data_key));
+        Ok(())
+    }
+
+    /// Forgets the data key, transactions begun afterwards fail until the
+    /// database is unlocked again
+    pub fn lock(&self) {
+        *self.key.write().expect("lock poisoned") = None;
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.read().expect("lock poisoned").is_some()
+    }
+
+    /// Whether the encryption has been set up by unlocking the database once
+    pub async fn is_encrypted(&self) -> EncryptionResult<bool> {
+        Ok(self.params().await?.is_some())
+    }
+
+    /// Replaces the secret unlocking the database, e.g. when switching from a
+    /// password to a keystore. Doesn't change whether it is locked.
+    pub async fn change_secret(
+        &self,
+        old: &DatabaseSecret,
+        new: &DatabaseSecret,
+    ) -> EncryptionResult<()> {
+        let params = self.params().await?.ok_or(EncryptionError::NotEncrypted)?;
+        let raw_key = open_raw_data_key(&params, old)?;
+        let params = wrap_data_key(&raw_key, new)?;
+
+        let mut dbtx = self.inner.begin_transaction().await;
+        dbtx.raw_insert_bytes(&EncryptionParamsKey.to_bytes(), params.to_bytes())
+            .await?;
+        dbtx.commit_tx().await?;
+        Ok(())
+    }
+
+    async fn params(&self) -> EncryptionResult<Option<EncryptionParams>> {
+        let mut dbtx = self.inner.begin_read_transaction().await;
+        let bytes = match dbtx.raw_get_bytes(&EncryptionParamsKey.to_bytes()).await? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        let params = EncryptionParams::consensus_decode(
+            &mut Cursor::new(bytes),
+            &ModuleDecoderRegistry::default(),
+        )
+        .map_err(|e| format_err!("Invalid encryption params: {e:?}"))?;
+        Ok(Some(params))
+    }
+
+    /// Generates the data key and encrypts all existing entries with it
+    async fn set_up(&self, secret: &DatabaseSecret) -> EncryptionResult<LessSafeKey> {
+        let mut raw_key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut raw_key);
+        let params = wrap_data_key(&raw_key, secret)?;
+        let data_key = chacha_key(&raw_key)?;
+
+        let mut dbtx = self.inner.begin_transaction().await;
+        let entries = dbtx.raw_find_by_prefix(&[]).await.collect::<Vec<_>>().await;
+        for (key, value) in entries {
+            let value = aead::encrypt_with_aad(value?, &data_key, &key)?;
+            dbtx.raw_insert_bytes(&key, value).await?;
+        }
+        dbtx.raw_insert_bytes(&EncryptionParamsKey.to_bytes(), params.to_bytes())
+            .await?;
+        dbtx.commit_tx().await?;
+        Ok(data_key)
+    }
+
+    fn data_key(&self) -> Option<Arc<LessSafeKey>> {
+        self.key.read().expect("lock poisoned").clone()
+    }
+}
+
+impl Debug for EncryptedDatabase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedDatabase")
+            .field("inner", &self.inner)
+            .field("unlocked", &self.is_unlocked())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl IDatabase for EncryptedDatabase {
+    async fn begin_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        Box::new(EncryptedTransaction {
+            inner: self.inner.begin_transaction().await,
+            key: self.data_key(),
+        })
+    }
+
+    async fn begin_read_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        Box::new(EncryptedTransaction {
+            inner: self.inner.begin_read_transaction().await,
+            key: self.data_key(),
+        })
+    }
+}
+
+/// Transaction of an [`EncryptedDatabase`], holding the data key it was begun
+/// with or `None` if the database was locked at the time
+struct EncryptedTransaction<'a> {
+    inner: Box<dyn IDatabaseTransaction<'a>>,
+    key: Option<Arc<LessSafeKey>>,
+}
+
+impl<'a> EncryptedTransaction<'a> {
+    /// Returns the data key, rejecting access to the encryption params which
+    /// aren't part of the client's data
+    fn key_for(&self, key: &[u8]) -> anyhow::Result<&LessSafeKey> {
+        if is_params_key(key) {
+            return Err(format_err!("Key reserved for the database encryption"));
+        }
+        self.key
+            .as_deref()
+            .ok_or_else(|| format_err!("Database is locked"))
+    }
+
+    fn decrypt(&self, key: &[u8], value: Option<Vec<u8>>) -> anyhow::Result<Option<Vec<u8>>> {
+        let data_key = self.key_for(key)?;
+        value
+            .map(|mut value| Ok(aead::decrypt_with_aad(&mut value, data_key, key)?.to_vec()))
+            .transpose()
+    }
+}
+
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for EncryptedTransaction<'a> {
+    async fn raw_insert_bytes(
+        &mut self,
+        key: &[u8],
+        value: Vec<u8>,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let value = aead::encrypt_with_aad(value, self.key_for(key)?, key)?;
+        let old_value = self.inner.raw_insert_bytes(key, value).await?;
+        self.decrypt(key, old_value)
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        self.key_for(key)?;
+        let value = self.inner.raw_get_bytes(key).await?;
+        self.decrypt(key, value)
+    }
+
+    async fn raw_remove_entry(&mut self, key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        self.key_for(key)?;
+        let old_value = self.inner.raw_remove_entry(key).await?;
+        self.decrypt(key, old_value)
+    }
+
+    /// Values are returned as errors if the database is locked or they don't
+    /// decrypt
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixStream<'_> {
+        let data_key = self.key.clone();
+        Box::pin(
+            self.inner
+                .raw_find_by_prefix(key_prefix)
+                .await
+                .filter(|(key, _)| future::ready(!is_params_key(key)))
+                .map(move |(key, value)| {
+                    let value = value.and_then(|mut value| {
+                        let data_key = data_key
+                            .as_deref()
+                            .ok_or_else(|| format_err!("Database is locked"))?;
+                        Ok(aead::decrypt_with_aad(&mut value, data_key, &key)?.to_vec())
+                    });
+                    (key, value)
+                }),
+        )
+    }
+
+    async fn raw_remove_by_prefix(&mut self, key_prefix: &[u8]) -> anyhow::Result<()> {
+        if self.key.is_none() {
+            return Err(format_err!("Database is locked"));
+        }
+        let keys = self
+            .inner
+            .raw_find_by_prefix(key_prefix)
+            .await
+            .map(|(key, _)| key)
+            .filter(|key| future::ready(!is_params_key(key)))
+            .collect::<Vec<_>>()
+            .await;
+        for key in keys {
+            self.inner.raw_remove_entry(&key).await?;
+        }
+        Ok(())
+    }
+
+    async fn commit_tx(self: Box<Self>) -> anyhow::Result<()> {
+        self.inner.commit_tx().await
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        self.inner.rollback_tx_to_savepoint().await
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        self.inner.set_tx_savepoint().await
+    }
+}
+
+fn is_params_key(key: &[u8]) -> bool {
+    key.first() == Some(&(DbKeyPrefix::EncryptionParams as u8))
+}
+
+fn chacha_key(raw_key: &[u8]) -> anyhow::Result<LessSafeKey> {
+    let key = UnboundKey::new(&CHACHA20_POLY1305, raw_key)
+        .map_err(|_| format_err!("Unable to create key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypts the data key under a key derived from `secret` with a new salt
+fn wrap_data_key(raw_key: &[u8], secret: &DatabaseSecret) -> EncryptionResult<EncryptionParams> {
+    let mut salt = vec![0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let wrapped_key = aead::encrypt(raw_key.to_vec(), &secret.derive_key(&salt)?)?;
+    Ok(EncryptionParams { salt, wrapped_key })
+}
+
+fn open_raw_data_key(
+    params: &EncryptionParams,
+    secret: &DatabaseSecret,
+) -> EncryptionResult<Vec<u8>> {
+    let mut wrapped_key = params.wrapped_key.clone();
+    let raw_key = aead::decrypt(&mut wrapped_key, &secret.derive_key(&params.salt)?)
+        .map_err(|_| EncryptionError::WrongSecret)?;
+    Ok(raw_key.to_vec())
+}
+
+fn unwrap_data_key(
+    params: &EncryptionParams,
+    secret: &DatabaseSecret,
+) -> EncryptionResult<LessSafeKey> {
+    Ok(chacha_key(&open_raw_data_key(params, secret)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::Database;
+
+    use super::{DatabaseSecret, EncryptedDatabase, EncryptionError};
+    use crate::db::ClientMnemonicKey;
+    use crate::module_decode_stubs;
+
+    #[tokio::test]
+    async fn test_lock_and_unlock() {
+        let encrypted = EncryptedDatabase::new(MemDatabase::new());
+        let db = Database::new(encrypted.clone(), module_decode_stubs());
+        let key = DatabaseSecret::Key([7; 32]);
+
+        encrypted.unlock(&key).await.unwrap();
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&ClientMnemonicKey, &vec![42u8; 16])
+            .await
+            .unwrap();
+        dbtx.commit_tx().await.unwrap();
+
+        encrypted.lock();
+        assert!(db
+            .begin_transaction()
+            .await
+            .get_value(&ClientMnemonicKey)
+            .await
+            .is_err());
+        assert!(matches!(
+            encrypted.unlock(&DatabaseSecret::Key([8; 32])).await,
+            Err(EncryptionError::WrongSecret)
+        ));
+
+        let password = DatabaseSecret::Password("correct horse".to_string());
+        encrypted.change_secret(&key, &password).await.unwrap();
+        assert!(matches!(
+            encrypted.unlock(&key).await,
+            Err(EncryptionError::WrongSecret)
+        ));
+        encrypted.unlock(&password).await.unwrap();
+        assert_eq!(
+            db.begin_transaction()
+                .await
+                .get_value(&ClientMnemonicKey)
+                .await
+                .unwrap(),
+            Some(vec![42u8; 16])
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Events letting wallets react to changes of a client's state instead of
+//! polling it. They are derived from writes to the client's database, so they
+//! cover changes made by any task using the client.
+
+use std::collections::{HashMap, VecDeque};
+
+use fedimint_api::db::Database;
+use fedimint_api::Amount;
+use fedimint_core::meta::FederationMeta;
+use futures::{future, FutureExt, StreamExt};
+use serde::Serialize;
+
+use crate::db::{FederationMetaKey, OperationKeyPrefix};
+use crate::mint::db::NoteKeyPrefix;
+use crate::operations::{OperationId, OperationRecord};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ClientEvent {
+    /// Notes were added or spent, `balance` is the new total of our notes
+    BalanceChanged { balance: Amount },
+    /// An operation was added to the history
+    OperationStarted(OperationRecord),
+    /// The federation processed an operation, e.g. a deposit got confirmed or
+    /// a lightning payment succeeded or failed. Outcomes are only learned when
+    /// [`crate::Client::update_operations`] runs.
+    OperationFinished(OperationRecord),
+    /// The guardians changed the federation meta, e.g. posted a new message of
+    /// the day. Changes are only learned when
+    /// [`crate::Client::refresh_federation_meta`] runs.
+    FederationMetaChanged(FederationMeta),
+}
+
+/// Compares the client's state after each write to its database with the
+/// state seen before to find out which events happened
+pub(crate) struct EventWatcher {
+    db: Database,
+    balance: Amount,
+    operations: HashMap<OperationId, OperationRecord>,
+    meta: Option<FederationMeta>,
+    queue: VecDeque<ClientEvent>,
+}
+
+impl EventWatcher {
+    pub async fn new(db: Database) -> Self {
+        let mut watcher = EventWatcher {
+            db,
+            balance: Amount::ZERO,
+            operations: HashMap::new(),
+            meta: None,
+            queue: VecDeque::new(),
+        };
+        // Only changes after subscribing are of interest
+        watcher.check().await;
+        watcher.queue.clear();
+        watcher
+    }
+
+    pub async fn next(&mut self) -> ClientEvent {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return event;
+            }
+
+            // Register interest before reading so writes in between aren't missed
+            let writes = [
+                self.db.wait_key_prefix_write(&NoteKeyPrefix).boxed(),
+                self.db.wait_key_prefix_write(&OperationKeyPrefix).boxed(),
+                self.db.wait_key_prefix_write(&FederationMetaKey).boxed(),
+            ];
+            self.check().await;
+            if self.queue.is_empty() {
+                future::select_all(writes).await;
+            }
+        }
+    }
+
+    /// Queues events for the differences to the state seen last
+    async fn check(&mut self) {
+        let mut dbtx = self.db.begin_read_transaction().await;
+        let balance: Amount = dbtx
+            .find_by_prefix(&NoteKeyPrefix)
+            .await
+            .map(|res| res.expect("DB error").0.amount)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .sum();
+        let operations = dbtx
+            .find_by_prefix(&OperationKeyPrefix)
+            .await
+            .map(|res| res.expect("DB error").1)
+            .collect::<Vec<OperationRecord>>()
+            .await;
+        let meta = dbtx
+            .get_value(&FederationMetaKey)
+            .await
+            .expect("DB error")
+            .map(|cached| cached.meta);
+
+        if balance != self.balance {
+            self.balance = balance;
+            self.queue
+                .push_back(ClientEvent::BalanceChanged { balance });
+        }
+
+        for record in operations {
+            let event = match self.operations.get(&record.id) {
+                None if record.outcome.is_finished() => {
+                    // Started and finished before we could notice
+                    self.queue
+                        .push_back(ClientEvent::OperationStarted(record.clone()));
+                    ClientEvent::OperationFinished(record.clone())
+                }
+                None => ClientEvent::OperationStarted(record.clone()),
+                Some(known) if known.outcome != record.outcome && record.outcome.is_finished() => {
+                    ClientEvent::OperationFinished(record.clone())
+                }
+                Some(_) => continue,
+            };
+            self.queue.push_back(event);
+            self.operations.insert(record.id, record);
+        }
+
+        if meta != self.meta {
+            self.meta = meta.clone();
+            if let Some(meta) = meta {
+                self.queue
+                    .push_back(ClientEvent::FederationMetaChanged(meta));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::{sha256, Hash};
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::Database;
+    use fedimint_api::Amount;
+
+    use super::{ClientEvent, EventWatcher};
+    use crate::db::OperationKey;
+    use crate::module_decode_stubs;
+    use crate::operations::{OperationId, OperationKind, OperationOutcome, OperationRecord};
+
+    async fn save(db: &Database, record: &OperationRecord) {
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&OperationKey(record.id), record)
+            .await
+            .unwrap();
+        dbtx.commit_tx().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_operation_events() {
+        let db = Database::new(MemDatabase::new(), module_decode_stubs());
+        let mut record = OperationRecord {
+            id: OperationId(sha256::Hash::hash(b"operation")),
+            kind: OperationKind::PegIn {
+                txid: sha256::Hash::hash(b"tx").into(),
+            },
+            amount: Amount::from_sats(1000),
+            outcome: OperationOutcome::Pending,
+            created_at: 1,
+            updated_at: 1,
+        };
+        save(&db, &record).await;
+
+        // Operations from before subscribing aren't reported
+        let mut watcher = EventWatcher::new(db.clone()).await;
+        record.outcome = OperationOutcome::Succeeded;
+        save(&db, &record).await;
+        assert_eq!(
+            watcher.next().await,
+            ClientEvent::OperationFinished(record.clone())
+        );
+
+        let started = OperationRecord {
+            id: OperationId(sha256::Hash::hash(b"other operation")),
+            outcome: OperationOutcome::Pending,
+            ..record
+        };
+        save(&db, &started).await;
+        assert_eq!(watcher.next().await, ClientEvent::OperationStarted(started));
+    }
+}
@@ -0,0 +1,271 @@
+//! Client side of modules that aren't built into the client, e.g. of
+//! third-party modules. Like the server's [`ModuleGenRegistry`], a
+//! [`ClientExtensionRegistry`] maps module kinds to their code. A client using
+//! it runs the registered extension for every module instance of that kind in
+//! the federation's config, so the funds the user holds in the instance count
+//! towards [`crate::Client::balance`], its operations show up in the history
+//! and its state is part of the e-cash backups.
+//!
+//! Every instance keeps its state below its own prefix of the client's
+//! database, see [`DatabaseTransaction::with_module_prefix`].
+//!
+//! [`ModuleGenRegistry`]: fedimint_api::config::ModuleGenRegistry
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use fedimint_api::config::{ClientConfig, ClientModuleConfig};
+use fedimint_api::core::{ModuleInstanceId, ModuleKind};
+use fedimint_api::db::DatabaseTransaction;
+use fedimint_api::Amount;
+use fedimint_core::api::DynFederationApi;
+
+use crate::operations::{OperationId, OperationOutcome};
+
+/// What an extension knows about the module instance it runs for
+#[derive(Debug, Clone)]
+pub struct ExtensionContext {
+    pub module_instance_id: ModuleInstanceId,
+    pub config: ClientModuleConfig,
+    pub api: DynFederationApi,
+}
+
+/// Operation of an extension, recorded in the client's history as
+/// [`crate::operations::OperationKind::Module`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionOperation {
+    /// Should be derived with a tag unique to the module kind, see
+    /// [`OperationId::derive`]
+    pub id: OperationId,
+    /// Shown to the user, e.g. "Locked funds in escrow"
+    pub description: String,
+    pub amount: Amount,
+    pub outcome: OperationOutcome,
+}
+
+/// Client side of a module kind. All methods get a database transaction that
+/// is isolated to the module instance.
+#[async_trait]
+pub trait ClientExtension: Debug + Send + Sync {
+    fn module_kind(&self) -> ModuleKind;
+
+    /// Funds the user holds in the module instance
+    async fn balance(&self, ctx: &ExtensionContext, dbtx: &mut DatabaseTransaction<'_>) -> Amount;
+
+    /// Advances the extension's state machines, e.g. by asking the federation
+    /// about the outcome of transactions, and returns the operations that
+    /// started or changed their outcome since the last call
+    async fn update_operations(
+        &self,
+        ctx: &ExtensionContext,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> anyhow::Result<Vec<ExtensionOperation>>;
+
+    /// State needed to recover the funds held in the module instance, stored
+    /// with the client's e-cash backups. It has to be small, backups are
+    /// limited in size.
+    async fn backup(
+        &self,
+        _ctx: &ExtensionContext,
+        _dbtx: &mut DatabaseTransaction<'_>,
+    ) -> anyhow::Result<Vec<u8>> {
+        Ok(vec![])
+    }
+
+    /// Restores the state returned by [`Self::backup`] into an empty database
+    async fn restore(
+        &self,
+        _ctx: &ExtensionContext,
+        _dbtx: &mut DatabaseTransaction<'_>,
+        _backup: &[u8],
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ClientExtensionRegistry(BTreeMap<ModuleKind, Arc<dyn ClientExtension>>);
+
+impl ClientExtensionRegistry {
+    pub fn get(&self, kind: &ModuleKind) -> Option<&Arc<dyn ClientExtension>> {
+        self.0.get(kind)
+    }
+
+    /// Add the extension of another module kind
+    ///
+    /// # Panics
+    /// If an extension for the same module kind was added before
+    pub fn attach<T>(mut self, extension: T) -> Self
+    where
+        T: ClientExtension + 'static,
+    {
+        let kind = extension.module_kind();
+        assert!(
+            !self.0.contains_key(&kind),
+            "Module kind {kind} registered twice"
+        );
+        self.0.insert(kind, Arc::new(extension));
+        self
+    }
+
+    /// Binds the registered extensions to the module instances of their kind
+    /// in `config`
+    pub fn instances(
+        &self,
+        config: &ClientConfig,
+        api: &DynFederationApi,
+    ) -> BTreeMap<ModuleInstanceId, ClientExtensionInstance> {
+        config
+            .modules
+            .iter()
+            .filter_map(|(module_instance_id, module_cfg)| {
+                let extension = self.0.get(module_cfg.kind())?.clone();
+                let ctx = ExtensionContext {
+                    module_instance_id: *module_instance_id,
+                    config: module_cfg.clone(),
+                    api: api.clone(),
+                };
+                Some((
+                    *module_instance_id,
+                    ClientExtensionInstance { ctx, extension },
+                ))
+            })
+            .collect()
+    }
+}
+
+/// An extension running for a module instance of the federation
+#[derive(Debug, Clone)]
+pub struct ClientExtensionInstance {
+    ctx: ExtensionContext,
+    extension: Arc<dyn ClientExtension>,
+}
+
+impl ClientExtensionInstance {
+    pub async fn balance(&self, dbtx: &mut DatabaseTransaction<'_>) -> Amount {
+        let mut dbtx = dbtx.with_module_prefix(self.ctx.module_instance_id);
+        self.extension.balance(&self.ctx, &mut dbtx).await
+    }
+
+    pub async fn update_operations(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> anyhow::Result<Vec<ExtensionOperation>> {
+        let mut dbtx = dbtx.with_module_prefix(self.ctx.module_instance_id);
+        self.extension.update_operations(&self.ctx, &mut dbtx).await
+    }
+
+    pub async fn backup(&self, dbtx: &mut DatabaseTransaction<'_>) -> anyhow::Result<Vec<u8>> {
+        let mut dbtx = dbtx.with_module_prefix(self.ctx.module_instance_id);
+        self.extension.backup(&self.ctx, &mut dbtx).await
+    }
+
+    pub async fn restore(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        backup: &[u8],
+    ) -> anyhow::Result<()> {
+        let mut dbtx = dbtx.with_module_prefix(self.ctx.module_instance_id);
+        self.extension.restore(&self.ctx, &mut dbtx, backup).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use async_trait::async_trait;
+    use fedimint_api::config::{ClientConfig, ClientModuleConfig, FederationId};
+    use fedimint_api::core::ModuleKind;
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::{Database, DatabaseTransaction};
+    use fedimint_api::encoding::{Decodable, Encodable};
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use fedimint_api::{impl_db_prefix_const, Amount};
+    use fedimint_core::api::WsFederationApi;
+
+    use super::{ClientExtension, ClientExtensionRegistry, ExtensionContext, ExtensionOperation};
+    use crate::operations::{OperationId, OperationOutcome};
+
+    #[derive(Debug, Encodable, Decodable)]
+    struct DepositKey;
+
+    impl_db_prefix_const!(key = DepositKey, value = Amount, prefix = 0x01u8);
+
+    /// Deposits 1 sat into every instance when operations are updated
+    #[derive(Debug)]
+    struct DepositExtension;
+
+    #[async_trait]
+    impl ClientExtension for DepositExtension {
+        fn module_kind(&self) -> ModuleKind {
+            ModuleKind::from_static_str("deposit")
+        }
+
+        async fn balance(
+            &self,
+            _ctx: &ExtensionContext,
+            dbtx: &mut DatabaseTransaction<'_>,
+        ) -> Amount {
+            dbtx.get_value(&DepositKey)
+                .await
+                .expect("DB error")
+                .unwrap_or(Amount::ZERO)
+        }
+
+        async fn update_operations(
+            &self,
+            ctx: &ExtensionContext,
+            dbtx: &mut DatabaseTransaction<'_>,
+        ) -> anyhow::Result<Vec<ExtensionOperation>> {
+            let amount = Amount::from_sats(1);
+            let balance = self.balance(ctx, dbtx).await;
+            dbtx.insert_entry(&DepositKey, &(balance + amount)).await?;
+            Ok(vec![ExtensionOperation {
+                id: OperationId::derive("deposit", &ctx.module_instance_id),
+                description: "Deposit".to_string(),
+                amount,
+                outcome: OperationOutcome::Succeeded,
+            }])
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn extensions_run_per_instance_in_own_namespace() {
+        let module_cfg =
+            |kind| ClientModuleConfig::new(ModuleKind::from_static_str(kind), Default::default());
+        let config = ClientConfig {
+            federation_name: "test".to_string(),
+            federation_id: FederationId::dummy(),
+            nodes: vec![],
+            epoch_pk: FederationId::dummy().0,
+            max_transaction_size: None,
+            modules: BTreeMap::from([
+                (0, module_cfg("mint")),
+                (4, module_cfg("deposit")),
+                (7, module_cfg("deposit")),
+            ]),
+        };
+        let registry = ClientExtensionRegistry::default().attach(DepositExtension);
+        let instances = registry.instances(&config, &WsFederationApi::new(vec![]).into());
+        assert_eq!(instances.keys().copied().collect::<Vec<_>>(), vec![4, 7]);
+
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let mut dbtx = db.begin_transaction().await;
+        instances[&4].update_operations(&mut dbtx).await.unwrap();
+        instances[&4].update_operations(&mut dbtx).await.unwrap();
+        instances[&7].update_operations(&mut dbtx).await.unwrap();
+        dbtx.commit_tx().await.unwrap();
+
+        let mut dbtx = db.begin_read_transaction().await;
+        assert_eq!(instances[&4].balance(&mut dbtx).await, Amount::from_sats(2));
+        assert_eq!(instances[&7].balance(&mut dbtx).await, Amount::from_sats(1));
+        assert_eq!(
+            dbtx.get_value(&DepositKey).await.unwrap(),
+            None,
+            "Extensions must not write outside of their module prefix"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Wallets holding e-cash in several federations at once. Every federation
+//! gets its own [`UserClient`] with a database of its own, while the secrets
+//! of all of them are derived from the single secret of the wallet. Exporting
+//! that secret thus backs up the notes of every joined federation.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use fedimint_api::config::{FederationId, ModuleGenRegistry};
+use fedimint_api::db::Database;
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::Amount;
+use fedimint_derive_secret::DerivableSecret;
+use futures::StreamExt;
+use secp256k1_zkp::{All, Secp256k1};
+
+use crate::db::{
+    ActiveFederationKey, ClientSecretKey, FederationConfigKey, FederationConfigKeyPrefix,
+};
+use crate::secret::federation_secret;
+use crate::{Client, ClientError, ClientSecret, Mnemonic, Result, UserClient, UserClientConfig};
+
+/// Opens the database of a joined federation's client. It must not share its
+/// keyspace with the database of any other federation or the
+/// [`FederationClients`] itself.
+pub type FederationDbFactory = Arc<dyn Fn(&FederationId) -> Database + Send + Sync>;
+
+pub struct FederationClients {
+    /// Holds the wallet's secret and the configs of the joined federations
+    db: Database,
+    open_db: FederationDbFactory,
+    decoders: ModuleDecoderRegistry,
+    module_gens: ModuleGenRegistry,
+    secp: Secp256k1<All>,
+    root_secret: DerivableSecret,
+    clients: RwLock<HashMap<FederationId, Arc<UserClient>>>,
+}
+
+impl FederationClients {
+    /// Opens the clients of all federations joined so far. Like for a single
+    /// [`Client`] a secret has to be imported into `db` before, otherwise a
+    /// new one is generated.
+    pub async fn new(
+        db: Database,
+        open_db: FederationDbFactory,
+        decoders: ModuleDecoderRegistry,
+        module_gens: ModuleGenRegistry,
+        secp: Secp256k1<All>,
+    ) -> Result<Self> {
+        let root_secret = UserClient::get_secret(&db).await;
+        let federations = Self {
+            db,
+            open_db,
+            decoders,
+            module_gens,
+            secp,
+            root_secret,
+            clients: RwLock::new(HashMap::new()),
+        };
+
+        let configs = federations
+            .db
+            .begin_transaction()
+            .await
+            .find_by_prefix(&FederationConfigKeyPrefix)
+            .await
+            .map(|res| res.expect("DB error").1)
+            .collect::<Vec<UserClientConfig>>()
+            .await;
+        for config in configs {
+            let client = federations.open_client(config).await?;
+            federations.insert_client(client);
+        }
+
+        Ok(federations)
+    }
+
+    /// Joins the federation of `config`, returning its client. The first
+    /// federation joined becomes the active one.
+    pub async fn join(&self, config: UserClientConfig) -> Result<Arc<UserClient>> {
+        let federation_id = config.0.federation_id.clone();
+        if let Ok(client) = self.client(&federation_id) {
+            return Ok(client);
+        }
+
+        let client = self.open_client(config.clone()).await?;
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(&FederationConfigKey(federation_id.clone()), &config)
+            .await
+            .expect("DB error");
+        if dbtx
+            .get_value(&ActiveFederationKey)
+            .await
+            .expect("DB error")
+            .is_none()
+        {
+            dbtx.insert_new_entry(&ActiveFederationKey, &federation_id)
+                .await
+                .expect("DB error");
+        }
+        dbtx.commit_tx().await.expect("DB Error");
+
+        Ok(self.insert_client(client))
+    }
+
+    /// Ids of the joined federations
+    pub fn federations(&self) -> Vec<FederationId> {
+        self.clients
+            .read()
+            .expect("lock poisoned")
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Client of a joined federation
+    pub fn client(&self, federation_id: &FederationId) -> Result<Arc<UserClient>> {
+        self.clients
+            .read()
+            .expect("lock poisoned")
+            .get(federation_id)
+            .cloned()
+            .ok_or(ClientError::UnknownFederation)
+    }
+
+    /// Makes a joined federation the one operations target by default
+    pub async fn select(&self, federation_id: &FederationId) -> Result<()> {
+        self.client(federation_id)?;
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(&ActiveFederationKey, federation_id)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+        Ok(())
+    }
+
+    /// Client of the federation selected last, or of the first one joined
+    pub async fn active(&self) -> Result<Arc<UserClient>> {
+        let federation_id = self
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&ActiveFederationKey)
+            .await
+            .expect("DB error")
+            .ok_or(ClientError::NoActiveFederation)?;
+        self.client(&federation_id)
+    }
+
+    /// Total value of our notes in each joined federation
+    pub async fn balances(&self) -> Vec<(FederationId, Amount)> {
+        let clients = self
+            .clients
+            .read()
+            .expect("lock poisoned")
+            .iter()
+            .map(|(id, client)| (id.clone(), client.clone()))
+            .collect::<Vec<_>>();
+
+        let mut balances = vec![];
+        for (federation_id, client) in clients {
+            balances.push((federation_id, client.notes().await.total_amount()));
+        }
+        balances
+    }
+
+    /// Returns the secret the keys of all federations' clients are derived
+    /// from, see [`Client::import_secret`]
+    pub async fn export_secret(&self) -> ClientSecret {
+        self.db
+            .begin_transaction()
+            .await
+            .get_value(&ClientSecretKey)
+            .await
+            .expect("DB error")
+            .expect("Secret is created with the clients")
+    }
+
+    /// Returns the mnemonic the wallet's secret was derived from, if any, see
+    /// [`Client::import_mnemonic`]
+    pub async fn export_mnemonic(&self) -> Option<Mnemonic> {
+        UserClient::get_mnemonic(&self.db).await
+    }
+
+    async fn open_client(&self, config: UserClientConfig) -> Result<UserClient> {
+        let db = (self.open_db)(&config.0.federation_id);
+        let secret = federation_secret(&self.root_secret, &config.0.federation_id);
+        Client::<UserClientConfig>::import_secret(&db, secret).await?;
+        Ok(Client::new(
+            config,
+            self.decoders.clone(),
+            self.module_gens.clone(),
+            db,
+            self.secp.clone(),
+        )
+        .await)
+    }
+
+    fn insert_client(&self, client: UserClient) -> Arc<UserClient> {
+        let client = Arc::new(client);
+        self.clients
+            .write()
+            .expect("lock poisoned")
+            .insert(client.config().0.federation_id, client.clone());
+        client
+    }
+}
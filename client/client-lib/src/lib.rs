@@ -1,9 +1,17 @@
 pub mod api;
 pub mod db;
+#[cfg(not(target_family = "wasm"))]
+pub mod discovery;
+pub mod encrypted_db;
+pub mod events;
+pub mod extensions;
+pub mod federations;
 pub mod ln;
 pub mod logging;
 pub mod mint;
+pub mod operations;
 pub mod outcome;
+pub mod secret;
 pub mod transaction;
 pub mod utils;
 pub mod wallet;
@@ -14,24 +22,25 @@ pub mod modules {
     pub use fedimint_wallet as wallet;
 }
 
-use std::fmt::{Debug, Formatter};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::iter::once;
 use std::sync::Arc;
 use std::time::Duration;
 
-use api::{LnFederationApi, WalletFederationApi};
+use api::{LnFederationApi, MintFederationApi, WalletFederationApi};
 use bitcoin::util::key::KeyPair;
 use bitcoin::{secp256k1, Address, Transaction as BitcoinTransaction};
 use bitcoin_hashes::{sha256, Hash};
+use fedimint_api::cancellable::{Cancellable, Cancelled};
 use fedimint_api::config::{ClientConfig, FederationId, ModuleGenRegistry};
 use fedimint_api::core::{
     DynDecoder, LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
     LEGACY_HARDCODED_INSTANCE_ID_WALLET,
 };
-use fedimint_api::db::Database;
-use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::db::{Database, DatabaseTransaction};
+use fedimint_api::encoding::{Decodable, DecodeError, Encodable};
 use fedimint_api::module::registry::ModuleDecoderRegistry;
-use fedimint_api::task::{self, sleep};
+use fedimint_api::task::{self, sleep, TaskGroup, TaskHandle};
 use fedimint_api::tiered::InvalidAmountTierError;
 use fedimint_api::time::SystemTime;
 use fedimint_api::TieredMulti;
@@ -40,63 +49,93 @@ use fedimint_core::api::MemberError;
 use fedimint_core::api::{
     DynFederationApi, FederationError, GlobalFederationApi, OutputOutcomeError, WsFederationApi,
 };
-use fedimint_core::epoch::SignedEpochOutcome;
+use fedimint_core::epoch::{SerdeEpochHistory, SignedEpochOutcome};
+use fedimint_core::meta::FederationMeta;
 use fedimint_core::outcome::TransactionStatus;
+use fedimint_core::query::QueryStrategyKind;
+use fedimint_core::transaction::{Transaction, TransactionError};
 use fedimint_derive_secret::{ChildId, DerivableSecret};
-use futures::stream::{self, FuturesUnordered};
+use futures::stream::{self, BoxStream, FuturesUnordered};
 use futures::StreamExt;
 use itertools::{Either, Itertools};
 use lightning::ln::PaymentSecret;
 use lightning::routing::gossip::RoutingFees;
 use lightning::routing::router::{RouteHint, RouteHintHop};
 use lightning_invoice::{CreationError, Invoice, InvoiceBuilder, DEFAULT_EXPIRY_TIME};
-use ln::{db::LightningGatewayKey, PayInvoicePayload};
+use ln::{db::LightningGatewayKey, GatewaySelection, PayInvoicePayload};
 use mint::NoteIssuanceRequests;
-use rand::distributions::Standard;
-use rand::prelude::*;
-use rand::{thread_rng, CryptoRng, Rng, RngCore};
+use rand::{thread_rng, CryptoRng, RngCore};
 use secp256k1_zkp::{All, Secp256k1};
+pub use secret::{ClientSecret, Mnemonic, FEDERATION_SECRET_CHILD_ID, MINT_SECRET_CHILD_ID};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use threshold_crypto::PublicKey;
 use tracing::trace;
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
 use url::Url;
 
-use crate::db::ClientSecretKey;
+use crate::db::{
+    ClientMnemonicKey, ClientSecretKey, FederationMetaKey, OperationKey, OperationKeyPrefix,
+    OperationStateKey, OperationStateKeyPrefix,
+};
+use crate::events::{ClientEvent, EventWatcher};
+use crate::extensions::ClientExtensionRegistry;
 use crate::ln::db::{
-    OutgoingContractAccountKey, OutgoingContractAccountKeyPrefix, OutgoingPaymentClaimKey,
-    OutgoingPaymentClaimKeyPrefix, OutgoingPaymentKey,
+    GatewayPaymentKey, GatewayPaymentKeyPrefix, InternalPaymentKey, OfferPayIndexKey,
+    OfferRegistrationKey, OutgoingContractAccountKey, OutgoingContractAccountKeyPrefix,
+    OutgoingPaymentClaimKey, OutgoingPaymentClaimKeyPrefix, OutgoingPaymentKey,
+    OutgoingPaymentPartsKey, OutgoingPaymentRecordKey, WatchedRefundKey, WatchedRefundKeyPrefix,
+    WatchtowerRefundKey,
+};
+use crate::ln::outgoing::{
+    OutgoingContractAccount, OutgoingPaymentParts, OutgoingPaymentRecord, OutgoingPaymentStatus,
 };
-use crate::ln::outgoing::OutgoingContractAccount;
-use crate::ln::LnClientError;
+use crate::ln::proof::{ContractDirection, ContractEvidence, ContractProof};
+use crate::ln::watchtower::{WatchtowerRefund, WatchtowerRefundIssuance};
+use crate::ln::{GatewayPayment, LnClientError};
 use crate::logging::LOG_WALLET;
-use crate::mint::db::{NoteKey, PendingNotesKeyPrefix};
+use crate::mint::db::{
+    NoteKey, OutputFinalizationKey, OutputFinalizationKeyPrefix, PendingNotesKey,
+    PendingNotesKeyPrefix,
+};
+use crate::mint::note_selection::NoteSelection;
 use crate::mint::MintClientError;
+use crate::modules::ln::bolt12::Bolt12Offer;
 use crate::modules::ln::common::LightningDecoder;
 use crate::modules::ln::config::LightningClientConfig;
+use crate::modules::ln::contracts::outgoing::OutgoingInvoice;
 use crate::modules::mint::common::MintDecoder;
 use crate::modules::mint::config::MintClientConfig;
-use crate::modules::mint::{MintOutput, MintOutputOutcome};
+use crate::modules::mint::{
+    MintInput, MintOutput, MintOutputOutcome, Nonce, NoteStatus, MAX_NOTE_STATUS_BATCH,
+};
 use crate::modules::wallet::common::WalletDecoder;
 use crate::modules::wallet::config::WalletClientConfig;
 use crate::modules::wallet::{PegOut, WalletInput, WalletOutput};
 use crate::modules::{
     ln::{
         contracts::{
-            incoming::{IncomingContract, IncomingContractOffer},
+            account::AccountContract,
+            incoming::{HoldTerms, IncomingContract, IncomingContractOffer},
             Contract, ContractId, DecryptedPreimage, IdentifyableContract, Preimage,
         },
-        ContractOutput, LightningGateway, LightningOutput,
+        ContractOutput, GatewayFees, GatewayLimits, GatewayLiquidity, LightningGateway,
+        LightningOutput,
     },
     mint::BlindNonce,
     wallet::txoproof::TxOutProof,
 };
+use crate::operations::{
+    OperationId, OperationKind, OperationOutcome, OperationRecord, OperationState,
+    OperationStateMachine,
+};
 use crate::outcome::legacy::OutputOutcome;
+use crate::secret::generate_mnemonic;
 use crate::transaction::legacy::Transaction as LegacyTransaction;
 use crate::transaction::legacy::{Input, Output};
 use crate::transaction::TransactionBuilder;
 use crate::utils::{network_to_currency, ClientContext};
+use crate::wallet::db::RebalancePegInKey;
 use crate::wallet::WalletClientError;
 use crate::{
     ln::{incoming::ConfirmedInvoice, LnClient},
@@ -107,8 +146,12 @@ use crate::{
 /// Number of blocks until outgoing lightning contracts times out and user
 /// client can get refund
 const OUTGOING_LN_CONTRACT_TIMELOCK: u64 = 500;
-/// Mint module's secret key derivation child id
-pub const MINT_SECRET_CHILD_ID: ChildId = ChildId(0);
+/// Number of blocks the payer's last hop has to leave us to claim an incoming
+/// payment, extended by the hold period for held invoices
+const MIN_FINAL_CLTV_EXPIRY: u64 = 18;
+/// How often we check whether our unfinished outgoing payments were paid or
+/// can be refunded
+const OUTGOING_PAYMENT_CHECK_INTERVAL: Duration = Duration::from_secs(30);
 
 type Result<T> = std::result::Result<T, ClientError>;
 pub type GatewayClient = Client<GatewayClientConfig>;
@@ -126,6 +169,27 @@ pub struct PaymentParameters {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct UserClientConfig(pub ClientConfig);
 
+/// Encoded as JSON since the module configs are only available as such
+impl Encodable for UserClientConfig {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<usize> {
+        serde_json::to_vec(&self.0)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+            .consensus_encode(writer)
+    }
+}
+
+impl Decodable for UserClientConfig {
+    fn consensus_decode<D: std::io::Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> std::result::Result<Self, DecodeError> {
+        let bytes = Vec::<u8>::consensus_decode(d, modules)?;
+        Ok(UserClientConfig(
+            serde_json::from_slice(&bytes).map_err(DecodeError::from_err)?,
+        ))
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub struct GatewayClientConfig {
     pub client_config: ClientConfig,
@@ -139,12 +203,42 @@ pub struct GatewayClientConfig {
     /// `short_channel_id` when creating invoices to be settled by this
     /// gateway.
     pub mint_channel_id: u64,
+    /// Fees the gateway charges for routing payments of this federation
+    #[serde(default)]
+    pub fees: GatewayFees,
+    /// Payment sizes the gateway is willing to route for this federation
+    #[serde(default)]
+    pub limits: GatewayLimits,
+    /// E-cash balance the gateway keeps for this federation, not rebalanced
+    /// automatically if unset
+    #[serde(default)]
+    pub rebalance: Option<GatewayRebalanceBand>,
+    /// Whether the gateway acts as a watchtower for users of this federation
+    #[serde(default)]
+    pub watchtower: bool,
+}
+
+/// Range of the e-cash balance a gateway keeps for a federation. Outside of
+/// it the gateway pegs in from or out to its lightning node's on-chain wallet
+/// until its balance is back at the middle of the range.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct GatewayRebalanceBand {
+    pub min_ecash: Amount,
+    pub max_ecash: Amount,
+}
+
+impl GatewayRebalanceBand {
+    /// Balance rebalancing aims for
+    pub fn target(&self) -> Amount {
+        Amount::from_msats((self.min_ecash.msats + self.max_ecash.msats) / 2)
+    }
 }
 
 impl GatewayClientConfig {
     pub fn to_gateway_registration_info(
         &self,
         route_hints: Vec<modules::ln::route_hints::RouteHint>,
+        liquidity: GatewayLiquidity,
         time_to_live: Duration,
     ) -> LightningGateway {
         LightningGateway {
@@ -153,11 +247,42 @@ impl GatewayClientConfig {
             node_pub_key: self.node_pub_key,
             api: self.api.clone(),
             route_hints,
+            fees: self.fees,
+            limits: self.limits,
+            liquidity,
             valid_until: SystemTime::now() + time_to_live,
         }
     }
 }
 
+/// Current unix timestamp in seconds
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+/// How long [`Client::federation_meta`] callers usually accept a cached meta
+pub const DEFAULT_FEDERATION_META_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Federation meta together with the time it was fetched at
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct CachedFederationMeta {
+    pub meta: FederationMeta,
+    /// Unix timestamp in seconds
+    pub fetched_at: u64,
+}
+
+/// Result of [`Client::reconcile_notes`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteReconciliation {
+    /// Status the federation reported for each of the reconciled notes
+    pub notes: Vec<(Amount, Nonce, NoteStatus)>,
+    /// Out points of the reissuance transactions claiming the unspent notes
+    pub claims: Vec<OutPoint>,
+}
+
 pub struct Client<C> {
     config: C,
     context: Arc<ClientContext>,
@@ -175,18 +300,6 @@ impl<C> Client<C> {
     }
 }
 
-#[derive(Encodable, Decodable)]
-pub struct ClientSecret([u8; 64]);
-
-impl Serialize for ClientSecret {
-    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_bytes(&self.0)
-    }
-}
-
 impl AsRef<ClientConfig> for GatewayClientConfig {
     fn as_ref(&self) -> &ClientConfig {
         &self.client_config
@@ -211,6 +324,55 @@ impl<T> Client<T> {
     pub fn mint_secret_static(root_secret: &DerivableSecret) -> DerivableSecret {
         root_secret.child_key(MINT_SECRET_CHILD_ID)
     }
+
+    /// Stores the secret exported from another client in `db`, which has to be
+    /// done before creating a client using it. All note nonces and spend keys
+    /// are derived from it, so the notes can then be recovered from the
+    /// federation without the original database (see
+    /// [`MintClient::restore_ecash_from_federation`]).
+    pub async fn import_secret(db: &Database, secret: ClientSecret) -> Result<()> {
+        let mut tx = db.begin_transaction().await;
+        match tx.get_value(&ClientSecretKey).await.expect("DB error") {
+            Some(existing) if existing.0 != secret.0 => {
+                tx.abort_tx();
+                Err(ClientError::SecretMismatch)
+            }
+            Some(_) => {
+                tx.abort_tx();
+                Ok(())
+            }
+            None => {
+                tx.insert_new_entry(&ClientSecretKey, &secret)
+                    .await
+                    .expect("DB error");
+                tx.commit_tx().await.expect("DB error");
+                Ok(())
+            }
+        }
+    }
+
+    /// Like [`Self::import_secret`] for the secret of a BIP39 `mnemonic`,
+    /// which is kept so it can be exported again
+    pub async fn import_mnemonic(db: &Database, mnemonic: &Mnemonic) -> Result<()> {
+        Self::import_secret(db, ClientSecret::from_mnemonic(mnemonic)).await?;
+        let mut tx = db.begin_transaction().await;
+        tx.insert_entry(&ClientMnemonicKey, &mnemonic.to_entropy())
+            .await
+            .expect("DB error");
+        tx.commit_tx().await.expect("DB error");
+        Ok(())
+    }
+
+    /// The mnemonic the secret in `db` was derived from, `None` for secrets
+    /// of clients created before mnemonics were introduced
+    pub(crate) async fn get_mnemonic(db: &Database) -> Option<Mnemonic> {
+        db.begin_transaction()
+            .await
+            .get_value(&ClientMnemonicKey)
+            .await
+            .expect("DB error")
+            .map(|entropy| Mnemonic::from_entropy(&entropy).expect("Stored entropy is valid"))
+    }
 }
 
 // TODO: `get_module` is parsing `serde_json::Value` every time, which is not
@@ -263,6 +425,26 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
         self.config.clone()
     }
 
+    /// Returns the secret all of the client's keys are derived from, see
+    /// [`Client::import_secret`]
+    pub async fn export_secret(&self) -> ClientSecret {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&ClientSecretKey)
+            .await
+            .expect("DB error")
+            .expect("Secret is created with the client")
+    }
+
+    /// Returns the mnemonic whose words allow restoring the client, see
+    /// [`Client::import_mnemonic`]. Clients created before mnemonics were
+    /// introduced only have a secret.
+    pub async fn export_mnemonic(&self) -> Option<Mnemonic> {
+        Self::get_mnemonic(&self.context.db).await
+    }
+
     /// Verifies the config using the federation id
     pub async fn verify_config(&self, id: &FederationId) -> Result<()> {
         let config = self
@@ -319,20 +501,40 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
                 db,
                 api,
                 secp,
+                extensions: BTreeMap::new(),
             }),
             root_secret,
         }
     }
 
-    /// Fetches the client secret from the database or generates a new one if
-    /// none is present
+    /// Runs the extensions of `registry` for the module instances of their
+    /// kinds, see [`extensions`]
+    pub fn with_extensions(mut self, registry: &ClientExtensionRegistry) -> Self {
+        let context = &self.context;
+        self.context = Arc::new(ClientContext {
+            decoders: context.decoders.clone(),
+            module_gens: context.module_gens.clone(),
+            db: context.db.clone(),
+            api: context.api.clone(),
+            secp: context.secp.clone(),
+            extensions: registry.instances(self.config.as_ref(), &context.api),
+        });
+        self
+    }
+
+    /// Fetches the client secret from the database or generates a new one
+    /// from a fresh mnemonic if none is present
     async fn get_secret(db: &Database) -> DerivableSecret {
         let mut tx = db.begin_transaction().await;
         let client_secret = tx.get_value(&ClientSecretKey).await.expect("DB error");
         let secret = if let Some(client_secret) = client_secret {
             client_secret
         } else {
-            let secret: ClientSecret = thread_rng().gen();
+            let mnemonic = generate_mnemonic(thread_rng());
+            tx.insert_entry(&ClientMnemonicKey, &mnemonic.to_entropy())
+                .await
+                .expect("DB error");
+            let secret = ClientSecret::from_mnemonic(&mnemonic);
             let no_replacement = tx
                 .insert_entry(&ClientSecretKey, &secret)
                 .await
@@ -348,6 +550,299 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
         secret.into_root_secret()
     }
 
+    /// Creates a client from nothing but the `secret` exported from a lost
+    /// one. Its notes are restored from the newest e-cash backup the
+    /// federation holds for the secret, replaying the epochs since the backup
+    /// was taken to account for notes issued or spent afterwards. `db` has to
+    /// be empty or hold the same secret.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn restore(
+        config: T,
+        decoders: ModuleDecoderRegistry,
+        module_gens: ModuleGenRegistry,
+        db: Database,
+        secp: Secp256k1<All>,
+        secret: ClientSecret,
+        gap_limit: usize,
+        task_group: &mut TaskGroup,
+    ) -> Result<Cancellable<Self>> {
+        Self::import_secret(&db, secret).await?;
+        let client = Self::new(config, decoders, module_gens, db, secp).await;
+        match client
+            .mint_client()
+            .restore_ecash_from_federation(gap_limit, task_group)
+            .await
+            .map_err(ClientError::RestoreFailed)?
+        {
+            Ok(()) => Ok(Ok(client)),
+            Err(Cancelled) => Ok(Err(Cancelled)),
+        }
+    }
+
+    /// Uploads an encrypted backup of our e-cash to the federation every
+    /// `interval` until `task_handle` shuts down. Backups are uploaded even if
+    /// nothing changed, so their timing doesn't reveal when we transact.
+    pub async fn back_up_periodically(&self, task_handle: TaskHandle, interval: Duration) {
+        while !task_handle.is_shutting_down() {
+            match self.mint_client().back_up_ecash_to_federation().await {
+                Ok(()) => debug!("Uploaded e-cash backup"),
+                Err(e) => warn!("Failed to upload e-cash backup: {}", e),
+            }
+            sleep(interval).await;
+        }
+    }
+
+    /// Adds an operation we started to the history or changes the outcome of
+    /// one recorded before
+    async fn record_operation(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        id: OperationId,
+        kind: OperationKind,
+        amount: Amount,
+        outcome: OperationOutcome,
+    ) -> OperationRecord {
+        let now = unix_now();
+        let created_at = dbtx
+            .get_value(&OperationKey(id))
+            .await
+            .expect("DB error")
+            .map_or(now, |record| record.created_at);
+        let record = OperationRecord {
+            id,
+            kind,
+            amount,
+            outcome,
+            created_at,
+            updated_at: now,
+        };
+        dbtx.insert_entry(&OperationKey(id), &record)
+            .await
+            .expect("DB error");
+        record
+    }
+
+    pub async fn operation(&self, id: OperationId) -> Option<OperationRecord> {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&OperationKey(id))
+            .await
+            .expect("DB error")
+    }
+
+    /// Lists up to `limit` operations, most recently started first. Passing
+    /// the id of the last operation of a page as `before` returns the next
+    /// page.
+    pub async fn list_operations(
+        &self,
+        before: Option<OperationId>,
+        limit: usize,
+    ) -> Vec<OperationRecord> {
+        let mut records = self
+            .context
+            .db
+            .begin_transaction()
+            .await
+            .find_by_prefix(&OperationKeyPrefix)
+            .await
+            .map(|res| res.expect("DB error").1)
+            .collect::<Vec<OperationRecord>>()
+            .await;
+        records.sort_by(|a, b| (b.created_at, b.id).cmp(&(a.created_at, a.id)));
+
+        let start = match before {
+            Some(before) => records
+                .iter()
+                .position(|record| record.id == before)
+                .map_or(records.len(), |pos| pos + 1),
+            None => 0,
+        };
+        records.into_iter().skip(start).take(limit).collect()
+    }
+
+    /// Asks the federation about the outcome of our pending operations,
+    /// returns the operations that finished since the last update
+    pub async fn update_operations(&self) -> Vec<OperationRecord> {
+        let pending = self
+            .list_operations(None, usize::MAX)
+            .await
+            .into_iter()
+            .filter(|record| !record.outcome.is_finished());
+
+        let mut updated = vec![];
+        for record in pending {
+            let id = record.id;
+            let outcome = match self.fetch_operation_outcome(&record.kind).await {
+                Ok(OperationOutcome::Pending) => continue,
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    warn!(%id, "Failed to update operation: {}", e);
+                    continue;
+                }
+            };
+
+            let mut dbtx = self.context.db.begin_transaction().await;
+            let record = self
+                .record_operation(&mut dbtx, id, record.kind, record.amount, outcome)
+                .await;
+            dbtx.commit_tx().await.expect("DB Error");
+            info!(%id, outcome = ?record.outcome, "Operation finished");
+            updated.push(record);
+        }
+
+        for (module_instance_id, extension) in &self.context.extensions {
+            let mut dbtx = self.context.db.begin_transaction().await;
+            let operations = match extension.update_operations(&mut dbtx).await {
+                Ok(operations) => operations,
+                Err(e) => {
+                    warn!(module_instance_id, "Failed to update operations: {}", e);
+                    continue;
+                }
+            };
+            let mut records = vec![];
+            for operation in operations {
+                let kind = OperationKind::Module {
+                    module_instance_id: *module_instance_id,
+                    description: operation.description,
+                };
+                records.push(
+                    self.record_operation(
+                        &mut dbtx,
+                        operation.id,
+                        kind,
+                        operation.amount,
+                        operation.outcome,
+                    )
+                    .await,
+                );
+            }
+            dbtx.commit_tx().await.expect("DB Error");
+            updated.extend(records.into_iter().filter(|r| r.outcome.is_finished()));
+        }
+        updated
+    }
+
+    async fn fetch_operation_outcome(&self, kind: &OperationKind) -> Result<OperationOutcome> {
+        let txids = match kind {
+            OperationKind::PegIn { txid }
+            | OperationKind::PegOut { txid }
+            | OperationKind::LnReceive { txid, .. } => vec![*txid],
+            OperationKind::ReceiveEcash { txids } => txids.clone(),
+            OperationKind::SpendEcash => return Ok(OperationOutcome::Succeeded),
+            // Updated by the extension of the module instance
+            OperationKind::Module { .. } => return Ok(OperationOutcome::Pending),
+            OperationKind::LnPay { contract_id } => {
+                let status = self
+                    .ln_client()
+                    .get_outgoing_payment_record(*contract_id)
+                    .await
+                    .map(|record| record.status);
+                return Ok(match status {
+                    Some(OutgoingPaymentStatus::Paid) => OperationOutcome::Succeeded,
+                    Some(OutgoingPaymentStatus::Refunded { .. }) => OperationOutcome::Failed {
+                        reason: "The payment failed and was refunded".to_string(),
+                    },
+                    _ => OperationOutcome::Pending,
+                });
+            }
+        };
+
+        for txid in txids {
+            match self.context.api.fetch_tx_outcome(&txid).await {
+                Ok(TransactionStatus::Accepted { .. }) => {}
+                Ok(TransactionStatus::Rejected(reason)) => {
+                    return Ok(OperationOutcome::Failed { reason })
+                }
+                Err(e) if e.is_retryable() => return Ok(OperationOutcome::Pending),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(OperationOutcome::Succeeded)
+    }
+
+    /// Runs [`Self::update_operations`] every `interval` until `task_handle`
+    /// shuts down, so subscribers of [`Self::subscribe_events`] learn about
+    /// finished operations
+    pub async fn update_operations_periodically(
+        &self,
+        task_handle: TaskHandle,
+        interval: Duration,
+    ) {
+        while !task_handle.is_shutting_down() {
+            self.update_operations().await;
+            sleep(interval).await;
+        }
+    }
+
+    /// The federation's name, icon, message of the day and fee schedule. A
+    /// cached meta is returned if it was fetched less than `max_age` ago,
+    /// otherwise it is fetched from the guardians.
+    pub async fn federation_meta(&self, max_age: Duration) -> Result<FederationMeta> {
+        let cached = self
+            .context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&FederationMetaKey)
+            .await
+            .expect("DB error");
+        match cached {
+            Some(cached) if unix_now().saturating_sub(cached.fetched_at) < max_age.as_secs() => {
+                Ok(cached.meta)
+            }
+            _ => self.refresh_federation_meta().await,
+        }
+    }
+
+    /// Fetches the federation meta signed by the guardians and caches it.
+    /// Subscribers of [`Self::subscribe_events`] are notified if it changed.
+    pub async fn refresh_federation_meta(&self) -> Result<FederationMeta> {
+        let meta = self
+            .context
+            .api
+            .fetch_federation_meta(&self.config.as_ref().federation_id)
+            .await?;
+
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(
+            &FederationMetaKey,
+            &CachedFederationMeta {
+                meta: meta.clone(),
+                fetched_at: unix_now(),
+            },
+        )
+        .await
+        .expect("DB error");
+        dbtx.commit_tx().await.expect("DB error");
+        Ok(meta)
+    }
+
+    /// Runs [`Self::refresh_federation_meta`] every `interval` until
+    /// `task_handle` shuts down, so subscribers learn about changes of the meta
+    pub async fn refresh_federation_meta_periodically(
+        &self,
+        task_handle: TaskHandle,
+        interval: Duration,
+    ) {
+        while !task_handle.is_shutting_down() {
+            if let Err(e) = self.refresh_federation_meta().await {
+                warn!("Failed to fetch federation meta: {}", e);
+            }
+            sleep(interval).await;
+        }
+    }
+
+    /// Stream of the events of this client from now on, see [`ClientEvent`]
+    pub async fn subscribe_events(&self) -> BoxStream<'static, ClientEvent> {
+        let watcher = EventWatcher::new(self.context.db.clone()).await;
+        stream::unfold(watcher, |mut watcher| async move {
+            Some((watcher.next().await, watcher))
+        })
+        .boxed()
+    }
+
     pub async fn peg_in<R: RngCore + CryptoRng>(
         &self,
         txout_proof: TxOutProof,
@@ -360,13 +855,42 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
             .wallet_client()
             .create_pegin_input(txout_proof, btc_transaction)
             .await?;
+        let amount = Amount::from_sats(peg_in_proof.tx_output().value);
 
         tx.input(
             &mut vec![peg_in_key],
             Input::Wallet(WalletInput(Box::new(peg_in_proof))),
         );
 
-        self.submit_tx_with_change(tx, &mut rng).await
+        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+
+        let mut dbtx = self.context.db.begin_transaction().await;
+        self.record_operation(
+            &mut dbtx,
+            OperationId::derive("peg-in", &txid),
+            OperationKind::PegIn { txid },
+            amount,
+            OperationOutcome::Pending,
+        )
+        .await;
+        dbtx.commit_tx().await.expect("DB Error");
+
+        Ok(txid)
+    }
+
+    /// Asks the federation to speed up the confirmation of our deposit
+    /// `btc_transaction` that was sent with a too low fee. Guardians only do
+    /// so if they enabled it and the fee is within their limit.
+    pub async fn accelerate_peg_in(&self, btc_transaction: BitcoinTransaction) -> Result<()> {
+        let acceleration = self
+            .wallet_client()
+            .create_peg_in_acceleration(btc_transaction)
+            .await?;
+        self.context
+            .api
+            .request_peg_in_acceleration(&acceleration)
+            .await?;
+        Ok(())
     }
 
     /// Submits a transaction to the fed, making change using our change module
@@ -380,87 +904,532 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
         rng: R,
     ) -> Result<TransactionId> {
         let mut dbtx = self.context.db.begin_transaction().await;
-        let final_tx = tx.build(self, &mut dbtx, rng).await;
+        let final_tx = tx.build(self, &mut dbtx, rng).await.into_type_erased();
+        // Don't spend our notes on a transaction the federation will reject anyway
+        if let Err(e) = final_tx.validate_size(self.config.as_ref().max_transaction_size) {
+            dbtx.abort_tx();
+            return Err(e.into());
+        }
         dbtx.commit_tx().await.expect("DB Error");
-        let result = self
-            .context
-            .api
-            .submit_transaction(final_tx.into_type_erased())
-            .await?;
+        let result = self.context.api.submit_transaction(final_tx).await?;
 
         Ok(result)
     }
 
-    /// Spent some [`SpendableNote`]s to receive a freshly minted ones
-    ///
-    /// This is useful in scenarios where certain notes were handed over
-    /// directly to us by another user as a payment. By spending them we can
-    /// make sure they can no longer be potentially double-spent.
-    ///
-    /// On success the out point of the newly issued e-cash notes is returned.
-    /// It can be used to easily poll the transaction status using
-    /// [`MintClient::fetch_notes`] until it returns `Ok(())`, indicating we
-    /// received our newly issued e-cash notes.
-    pub async fn reissue<R: RngCore + CryptoRng>(
+    /// Builds `tx` and submits it, `operation` returns the id and kind of the
+    /// operation given the transaction's id. The transaction is persisted
+    /// along with the spent inputs, the operation's record and its
+    /// [`OperationStateMachine`] before submitting, so if the client stops
+    /// in between [`Self::resume_operations`] finishes the operation.
+    async fn submit_operation_tx<R: RngCore + CryptoRng>(
         &self,
-        notes: TieredMulti<SpendableNote>,
-        mut rng: R,
-    ) -> Result<OutPoint> {
-        // Ensure we have the notes in the DB (in case we received them from another
-        // user)
+        tx: TransactionBuilder,
+        operation: impl FnOnce(TransactionId) -> (OperationId, OperationKind) + Send,
+        amount: Amount,
+        rng: R,
+    ) -> Result<TransactionId> {
         let mut dbtx = self.context.db.begin_transaction().await;
-        for (amount, note) in notes.clone() {
-            let key = NoteKey {
-                amount,
-                nonce: note.note.0,
-            };
-            dbtx.insert_entry(&key, &note).await.expect("DB error");
+        let transaction = tx.build(self, &mut dbtx, rng).await.into_type_erased();
+        if let Err(e) = transaction.validate_size(self.config.as_ref().max_transaction_size) {
+            dbtx.abort_tx();
+            return Err(e.into());
         }
+        let txid = transaction.tx_hash();
+        let (id, kind) = operation(txid);
+        self.create_operation_state(&mut dbtx, id, kind, amount, transaction.clone())
+            .await;
         dbtx.commit_tx().await.expect("DB Error");
 
-        let mut tx = TransactionBuilder::default();
-        let (mut keys, input) = MintClient::ecash_input(notes)?;
-        tx.input(&mut keys, input);
-        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+        self.submit_operation_transaction(txid, transaction).await
+    }
 
-        Ok(OutPoint { txid, out_idx: 0 })
+    /// Records operation `id` as pending and starts the state machine of its
+    /// `transaction`, which must be built within `dbtx`
+    async fn create_operation_state(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        id: OperationId,
+        kind: OperationKind,
+        amount: Amount,
+        transaction: Transaction,
+    ) {
+        self.record_operation(dbtx, id, kind, amount, OperationOutcome::Pending)
+            .await;
+        dbtx.insert_new_entry(
+            &OperationStateKey(transaction.tx_hash()),
+            &OperationStateMachine {
+                operation: id,
+                state: OperationState::Created { transaction },
+            },
+        )
+        .await
+        .expect("DB error");
     }
 
-    /// Validate signatures on notes.
-    ///
-    /// This function checks if signatures are valid
-    /// based on the federation public key. It does not check if the nonce is
-    /// unspent.
-    pub async fn validate_note_signatures(&self, notes: &TieredMulti<SpendableNote>) -> Result<()> {
-        let tbs_pks = &self.mint_client().config.tbs_pks;
-        notes.iter_items().try_for_each(|(amt, note)| {
-            if note.note.verify(*tbs_pks.tier(&amt)?) {
-                Ok(())
-            } else {
-                Err(ClientError::InvalidSignature)
+    /// Submits the transaction of a [`OperationState::Created`] state
+    /// machine. If the federation refuses it the operation fails and the
+    /// inputs are returned to our wallet, on errors that may be temporary the
+    /// submission is retried by [`Self::resume_operations`].
+    async fn submit_operation_transaction(
+        &self,
+        txid: TransactionId,
+        transaction: Transaction,
+    ) -> Result<TransactionId> {
+        match self.context.api.submit_transaction(transaction).await {
+            Ok(_) => {
+                self.set_operation_state(txid, OperationState::Submitted)
+                    .await;
+                Ok(txid)
             }
-        })
+            Err(e) if e.is_retryable() => Err(e.into()),
+            Err(e) => {
+                self.fail_operation_transaction(txid, e.to_string()).await;
+                Err(e.into())
+            }
+        }
     }
 
-    /// Pay by creating notes provided (and most probably controlled) by the
-    /// recipient.
-    ///
-    /// A standard way to facilitate a payment between users of a mint.
-    /// Generate a transaction spending notes we own as inputs and
-    /// creating new notes from [`BlindNonce`]s provided by the recipient as
-    /// outputs.
-    ///
-    /// Returns a `OutPoint` of a fedimint transaction created and submitted as
-    /// a payment.
-    ///
-    /// The name is derived from Bitcoin's terminology of "pay to
-    /// `<address-type>`".
-    pub async fn pay_to_blind_nonces<R: RngCore + CryptoRng>(
-        &self,
-        blind_nonces: TieredMulti<BlindNonce>,
-        mut rng: R,
-    ) -> Result<OutPoint> {
-        let mut tx = TransactionBuilder::default();
+    async fn set_operation_state(&self, txid: TransactionId, state: OperationState) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        if let Some(mut state_machine) = dbtx
+            .get_value(&OperationStateKey(txid))
+            .await
+            .expect("DB error")
+        {
+            state_machine.state = state;
+            dbtx.insert_entry(&OperationStateKey(txid), &state_machine)
+                .await
+                .expect("DB error");
+        }
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    /// Ends the state machine of a transaction the federation refused or
+    /// rejected and marks its operation failed. The notes it spent are put
+    /// back into our wallet and returned, the notes it would have issued and
+    /// the contract of a lightning payment are forgotten.
+    async fn fail_operation_transaction(
+        &self,
+        txid: TransactionId,
+        reason: String,
+    ) -> TieredMulti<SpendableNote> {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        let state_machine = match dbtx
+            .remove_entry(&OperationStateKey(txid))
+            .await
+            .expect("DB error")
+        {
+            Some(state_machine) => state_machine,
+            None => return TieredMulti::default(),
+        };
+
+        for outpoint in self.operation_outputs(&mut dbtx, txid).await {
+            dbtx.remove_entry(&OutputFinalizationKey(outpoint))
+                .await
+                .expect("DB error");
+        }
+        let inputs = dbtx
+            .remove_entry(&PendingNotesKey(txid))
+            .await
+            .expect("DB error")
+            .unwrap_or_default();
+        for (amount, note) in inputs.iter_items() {
+            let key = NoteKey {
+                amount,
+                nonce: note.note.0,
+            };
+            dbtx.insert_entry(&key, note).await.expect("DB error");
+        }
+
+        let record = dbtx
+            .get_value(&OperationKey(state_machine.operation))
+            .await
+            .expect("DB error");
+        if let Some(record) = record {
+            if let OperationKind::LnPay { contract_id } = record.kind {
+                dbtx.remove_entry(&OutgoingPaymentRecordKey(contract_id))
+                    .await
+                    .expect("DB error");
+                dbtx.remove_entry(&OutgoingPaymentKey(contract_id))
+                    .await
+                    .expect("DB error");
+            }
+            self.record_operation(
+                &mut dbtx,
+                record.id,
+                record.kind,
+                record.amount,
+                OperationOutcome::Failed { reason },
+            )
+            .await;
+        }
+        dbtx.commit_tx().await.expect("DB Error");
+        inputs
+    }
+
+    /// Out points of the notes transaction `txid` issues to us that weren't
+    /// fetched yet
+    async fn operation_outputs(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        txid: TransactionId,
+    ) -> Vec<OutPoint> {
+        dbtx.find_by_prefix(&OutputFinalizationKeyPrefix)
+            .await
+            .map(|res| res.expect("DB error").0 .0)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .filter(|outpoint| outpoint.txid == txid)
+            .collect()
+    }
+
+    /// Finishes the operations that were in flight when the client stopped,
+    /// e.g. submits transactions that never reached the federation and fetches
+    /// the notes of accepted ones. Should be called on startup, and
+    /// periodically to finish operations whose steps failed temporarily.
+    /// Returns the operations whose transactions completed.
+    pub async fn resume_operations(&self) -> Vec<OperationId> {
+        let state_machines = self
+            .context
+            .db
+            .begin_transaction()
+            .await
+            .find_by_prefix(&OperationStateKeyPrefix)
+            .await
+            .map(|res| {
+                let (key, state_machine) = res.expect("DB error");
+                (key.0, state_machine)
+            })
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut finished = vec![];
+        for (txid, state_machine) in state_machines {
+            let operation = state_machine.operation;
+            let mut state = state_machine.state;
+            loop {
+                match self.advance_operation_state(txid, state).await {
+                    Ok(Some(next)) => state = next,
+                    Ok(None) => {
+                        debug!(target: LOG_WALLET, %operation, %txid, "Operation transaction completed");
+                        finished.push(operation);
+                        break;
+                    }
+                    Err(e) => {
+                        warn!(target: LOG_WALLET, %operation, %txid, "Failed to resume operation: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+        finished
+    }
+
+    /// Performs the next step of the state machine of transaction `txid`,
+    /// returns its new state or `None` if it completed
+    async fn advance_operation_state(
+        &self,
+        txid: TransactionId,
+        state: OperationState,
+    ) -> Result<Option<OperationState>> {
+        match state {
+            OperationState::Created { transaction } => {
+                // We may have stopped after submitting, resubmitting would make the federation
+                // refuse the transaction as its inputs are spent already
+                if self.context.api.fetch_tx_outcome(&txid).await.is_ok() {
+                    self.set_operation_state(txid, OperationState::Submitted)
+                        .await;
+                } else {
+                    self.submit_operation_transaction(txid, transaction).await?;
+                }
+                Ok(Some(OperationState::Submitted))
+            }
+            OperationState::Submitted => match self.context.api.fetch_tx_outcome(&txid).await? {
+                TransactionStatus::Accepted { .. } => {
+                    self.set_operation_state(txid, OperationState::Accepted)
+                        .await;
+                    Ok(Some(OperationState::Accepted))
+                }
+                TransactionStatus::Rejected(reason) => {
+                    warn!(target: LOG_WALLET, %txid, %reason, "Operation transaction rejected");
+                    let inputs = self.fail_operation_transaction(txid, reason).await;
+                    // Some of the inputs may be spent already, so we can't keep them as they are
+                    if !inputs.is_empty() {
+                        self.reissue_batched(inputs, rand::rngs::OsRng).await?;
+                    }
+                    Ok(None)
+                }
+            },
+            OperationState::Accepted => {
+                let mut dbtx = self.context.db.begin_transaction().await;
+                let state_machine = match dbtx
+                    .get_value(&OperationStateKey(txid))
+                    .await
+                    .expect("DB error")
+                {
+                    Some(state_machine) => state_machine,
+                    None => return Ok(None),
+                };
+                for outpoint in self.operation_outputs(&mut dbtx, txid).await {
+                    self.mint_client().fetch_notes(&mut dbtx, outpoint).await?;
+                }
+                dbtx.remove_entry(&OperationStateKey(txid))
+                    .await
+                    .expect("DB error");
+                dbtx.commit_tx().await.expect("DB Error");
+
+                let kind = self
+                    .operation(state_machine.operation)
+                    .await
+                    .map(|record| record.kind);
+                if let Some(OperationKind::LnPay { contract_id }) = kind {
+                    self.track_outgoing_payment(contract_id).await?;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Makes sure the outgoing payment of an accepted contract is tracked, we
+    /// may have stopped before saving its record
+    async fn track_outgoing_payment(&self, contract_id: ContractId) -> Result<()> {
+        let ln_client = self.ln_client();
+        if ln_client
+            .get_outgoing_payment_record(contract_id)
+            .await
+            .is_none()
+        {
+            let account = ln_client.get_outgoing_contract(contract_id).await?;
+            ln_client
+                .save_outgoing_payment_status(
+                    contract_id,
+                    account.amount,
+                    OutgoingPaymentStatus::Pending,
+                )
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Spent some [`SpendableNote`]s to receive a freshly minted ones
+    ///
+    /// This is useful in scenarios where certain notes were handed over
+    /// directly to us by another user as a payment. By spending them we can
+    /// make sure they can no longer be potentially double-spent.
+    ///
+    /// On success the out point of the newly issued e-cash notes is returned.
+    /// It can be used to easily poll the transaction status using
+    /// [`MintClient::fetch_notes`] until it returns `Ok(())`, indicating we
+    /// received our newly issued e-cash notes.
+    pub async fn reissue<R: RngCore + CryptoRng>(
+        &self,
+        notes: TieredMulti<SpendableNote>,
+        mut rng: R,
+    ) -> Result<OutPoint> {
+        self.save_received_notes(&notes).await;
+        let id = OperationId::derive("receive-ecash", &notes);
+        let amount = notes.total_amount();
+
+        let mut tx = TransactionBuilder::default();
+        let (mut keys, input) = MintClient::ecash_input(notes)?;
+        tx.input(&mut keys, input);
+        let txid = self
+            .submit_operation_tx(
+                tx,
+                |txid| (id, OperationKind::ReceiveEcash { txids: vec![txid] }),
+                amount,
+                &mut rng,
+            )
+            .await?;
+
+        Ok(OutPoint { txid, out_idx: 0 })
+    }
+
+    /// Like [`Self::reissue`], but splits the notes into as few transactions
+    /// as the federation's maximum transaction size allows instead of failing
+    /// if they don't fit into a single one. All notes issued by a transaction
+    /// are requested in a single aggregated output.
+    ///
+    /// Returns the out points of the newly issued notes, one per transaction.
+    /// If a batch fails after others were submitted already, the error is a
+    /// [`ClientError::PartialReissuance`] containing their out points.
+    pub async fn reissue_batched<R: RngCore + CryptoRng>(
+        &self,
+        notes: TieredMulti<SpendableNote>,
+        mut rng: R,
+    ) -> Result<Vec<OutPoint>> {
+        self.save_received_notes(&notes).await;
+        let id = OperationId::derive("receive-ecash", &notes);
+        let amount = notes.total_amount();
+
+        let max_transaction_size = self.config.as_ref().max_transaction_size;
+        let mut remaining = notes.into_iter().collect::<Vec<_>>();
+        let mut batch_size = remaining.len();
+        let mut out_points = vec![];
+        while !remaining.is_empty() {
+            let batch = remaining[..batch_size]
+                .iter()
+                .cloned()
+                .collect::<TieredMulti<_>>();
+            let mut tx = TransactionBuilder::default();
+            let (mut keys, input) = MintClient::ecash_input(batch)?;
+            tx.input(&mut keys, input);
+
+            let mut dbtx = self.context.db.begin_transaction().await;
+            let final_tx = tx.build(self, &mut dbtx, &mut rng).await.into_type_erased();
+            if let Err(e) = final_tx.validate_size(max_transaction_size) {
+                // Nothing was submitted yet, so we can retry with a smaller batch
+                dbtx.abort_tx();
+                if batch_size == 1 {
+                    return Err(ClientError::partial_reissuance(out_points, e.into()));
+                }
+                batch_size /= 2;
+                continue;
+            }
+            let txid = final_tx.tx_hash();
+            let txids = out_points
+                .iter()
+                .map(|out_point| out_point.txid)
+                .chain(once(txid))
+                .collect();
+            self.create_operation_state(
+                &mut dbtx,
+                id,
+                OperationKind::ReceiveEcash { txids },
+                amount,
+                final_tx.clone(),
+            )
+            .await;
+            dbtx.commit_tx().await.expect("DB Error");
+
+            if let Err(e) = self.submit_operation_transaction(txid, final_tx).await {
+                return Err(ClientError::partial_reissuance(out_points, e));
+            }
+            debug!(target: LOG_WALLET, %txid, notes = batch_size, "Submitted reissuance batch");
+            out_points.push(OutPoint { txid, out_idx: 0 });
+
+            remaining.drain(..batch_size);
+            batch_size = batch_size.min(remaining.len());
+        }
+
+        Ok(out_points)
+    }
+
+    /// Reconciles notes that were accepted while offline once we are online
+    /// again: queries the federation for the status of each note and claims
+    /// the ones that are still unspent by reissuing them.
+    ///
+    /// Spent notes were double-spent by the sender (or already claimed by us),
+    /// expired notes can't be redeemed anymore and notes with an invalid tier
+    /// or signature were never valid. None of them get reissued, the caller
+    /// can use the returned statuses to act on them.
+    pub async fn reconcile_notes<R: RngCore + CryptoRng>(
+        &self,
+        notes: TieredMulti<SpendableNote>,
+        rng: R,
+    ) -> Result<NoteReconciliation> {
+        let notes = notes.into_iter().collect::<Vec<_>>();
+        let mut statuses = Vec::with_capacity(notes.len());
+        for chunk in notes.chunks(MAX_NOTE_STATUS_BATCH) {
+            let input = MintInput(
+                chunk
+                    .iter()
+                    .map(|(amount, note)| (*amount, note.note))
+                    .collect(),
+            );
+            let chunk_statuses = self.context.api.fetch_note_status(&input).await?;
+            // Statuses are returned in the input's iteration order
+            statuses.extend(
+                input
+                    .iter_items()
+                    .map(|(amount, note)| (amount, note.0))
+                    .zip(chunk_statuses),
+            );
+        }
+
+        let unspent_nonces = statuses
+            .iter()
+            .filter(|(_, status)| *status == NoteStatus::Unspent)
+            .map(|((_, nonce), _)| *nonce)
+            .collect::<HashSet<_>>();
+        let unspent = notes
+            .into_iter()
+            .filter(|(_, note)| unspent_nonces.contains(&note.note.0))
+            .collect::<TieredMulti<_>>();
+        let claims = if unspent.is_empty() {
+            vec![]
+        } else {
+            self.reissue_batched(unspent, rng).await?
+        };
+
+        Ok(NoteReconciliation {
+            notes: statuses
+                .into_iter()
+                .map(|((amount, nonce), status)| (amount, nonce, status))
+                .collect(),
+            claims,
+        })
+    }
+
+    /// Ensures we have the notes in the DB (in case we received them from
+    /// another user)
+    async fn save_received_notes(&self, notes: &TieredMulti<SpendableNote>) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        for (amount, note) in notes.clone() {
+            let key = NoteKey {
+                amount,
+                nonce: note.note.0,
+            };
+            dbtx.insert_entry(&key, &note).await.expect("DB error");
+        }
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    /// Validate signatures on notes.
+    ///
+    /// This function checks if signatures are valid
+    /// based on the federation public keys of any key epoch. It does not check
+    /// if the nonce is unspent or if the note expired.
+    pub async fn validate_note_signatures(&self, notes: &TieredMulti<SpendableNote>) -> Result<()> {
+        let mint_client = self.mint_client();
+        let retired_tbs_pks = mint_client.config.retired_tbs_pks();
+        notes.iter_items().try_for_each(|(amt, note)| {
+            if let Some(retired_tbs_pk) = retired_tbs_pks.get(&amt) {
+                return if note.note.verify(*retired_tbs_pk) {
+                    Ok(())
+                } else {
+                    Err(ClientError::InvalidSignature)
+                };
+            }
+            mint_client.config.tbs_pks.tier(&amt)?;
+            if mint_client.note_key_epoch(amt, &note.note).is_some() {
+                Ok(())
+            } else {
+                Err(ClientError::InvalidSignature)
+            }
+        })
+    }
+
+    /// Pay by creating notes provided (and most probably controlled) by the
+    /// recipient.
+    ///
+    /// A standard way to facilitate a payment between users of a mint.
+    /// Generate a transaction spending notes we own as inputs and
+    /// creating new notes from [`BlindNonce`]s provided by the recipient as
+    /// outputs.
+    ///
+    /// Returns a `OutPoint` of a fedimint transaction created and submitted as
+    /// a payment.
+    ///
+    /// The name is derived from Bitcoin's terminology of "pay to
+    /// `<address-type>`".
+    pub async fn pay_to_blind_nonces<R: RngCore + CryptoRng>(
+        &self,
+        blind_nonces: TieredMulti<BlindNonce>,
+        mut rng: R,
+    ) -> Result<OutPoint> {
+        let mut tx = TransactionBuilder::default();
 
         let (mut keys, input) = self
             .mint_client()
@@ -516,6 +1485,9 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
         peg_out: PegOut,
         mut rng: R,
     ) -> Result<OutPoint> {
+        self.wallet_client()
+            .check_peg_out_address(&peg_out.recipient)
+            .await?;
         let mut tx = TransactionBuilder::default();
 
         let funding_amount = self
@@ -525,13 +1497,26 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
             .expect("missing wallet module config")
             .1
             .fee_consensus
-            .peg_out_abs
+            .peg_out_fee(peg_out.amount)
             + (peg_out.amount + peg_out.fees.amount()).into();
+        let amount = peg_out.amount.into();
         let (mut keys, input) = self.mint_client().select_input(funding_amount).await?;
         tx.input(&mut keys, input);
         let peg_out_idx = tx.output(Output::Wallet(WalletOutput(peg_out)));
 
-        let fedimint_tx_id = self.submit_tx_with_change(tx, &mut rng).await?;
+        let fedimint_tx_id = self
+            .submit_operation_tx(
+                tx,
+                |txid| {
+                    (
+                        OperationId::derive("peg-out", &txid),
+                        OperationKind::PegOut { txid },
+                    )
+                },
+                amount,
+                &mut rng,
+            )
+            .await?;
 
         Ok(OutPoint {
             txid: fedimint_tx_id,
@@ -568,7 +1553,22 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
         amount: Amount,
         rng: R,
     ) -> Result<TieredMulti<SpendableNote>> {
-        let notes = self.mint_client().select_notes(amount).await?;
+        let selection = self.mint_client().note_selection().await;
+        self.spend_ecash_with(amount, selection, rng).await
+    }
+
+    /// Like [`Self::spend_ecash`], but chooses the notes to spend using
+    /// `selection` instead of the configured [`NoteSelection`]
+    pub async fn spend_ecash_with<R: RngCore + CryptoRng>(
+        &self,
+        amount: Amount,
+        selection: NoteSelection,
+        rng: R,
+    ) -> Result<TieredMulti<SpendableNote>> {
+        let notes = self
+            .mint_client()
+            .select_notes_with(amount, selection)
+            .await?;
         let mut dbtx = self.context.db.begin_transaction().await;
 
         let final_notes = if notes.total_amount() == amount {
@@ -584,7 +1584,9 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
             self.mint_client()
                 .await_fetch_notes(&mut dbtx, &outpoint)
                 .await?;
-            self.mint_client().select_notes(amount).await?
+            self.mint_client()
+                .select_notes_with(amount, selection)
+                .await?
         };
         if final_notes.total_amount() != amount {
             return Err(ClientError::SpendReusedNote);
@@ -598,6 +1600,16 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
             .await
             .expect("DB Error");
         }
+        // The notes are out of our hands, whether the recipient reissues them
+        // is up to them
+        self.record_operation(
+            &mut dbtx,
+            OperationId::derive("spend-ecash", &final_notes),
+            OperationKind::SpendEcash,
+            amount,
+            OperationOutcome::Succeeded,
+        )
+        .await;
         dbtx.commit_tx().await.expect("DB Error");
 
         Ok(final_notes)
@@ -655,10 +1667,23 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
         Ok(())
     }
 
+    /// Waits until the federation accepted the transaction creating `outpoint`,
+    /// returns an error if it was rejected or `timeout` elapsed
+    pub async fn await_output_accepted(&self, outpoint: OutPoint, timeout: Duration) -> Result<()> {
+        self.context
+            .api
+            .await_output_outcome::<OutputOutcome>(outpoint, timeout, &self.context.decoders)
+            .await?;
+        Ok(())
+    }
+
     /// Should be called after any transaction that might have failed in order
     /// to get any note inputs back.
     #[instrument(skip_all, level = "debug")]
-    pub async fn reissue_pending_notes<R: RngCore + CryptoRng>(&self, rng: R) -> Result<OutPoint> {
+    pub async fn reissue_pending_notes<R: RngCore + CryptoRng>(
+        &self,
+        rng: R,
+    ) -> Result<Vec<OutPoint>> {
         let mut dbtx = self.context.db.begin_transaction().await;
         let pending: Vec<_> = dbtx
             .find_by_prefix(&PendingNotesKeyPrefix)
@@ -694,7 +1719,112 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
         debug!(target: LOG_WALLET, notes_to_reissue = ?notes_to_reissue.summary(), total = %notes_to_reissue.total_amount());
         trace!(target: LOG_WALLET, ?notes_to_reissue, "foo");
 
-        self.reissue(notes_to_reissue, rng).await
+        self.reissue_batched(notes_to_reissue, rng).await
+    }
+
+    /// Reissues the notes that won't be redeemable anymore after the next key
+    /// rotation of a federation whose notes expire, as well as notes of amount
+    /// tiers the federation retired, so they don't lose their value
+    ///
+    /// Returns the out points of the newly issued notes.
+    pub async fn refresh_aging_notes<R: RngCore + CryptoRng>(
+        &self,
+        rng: R,
+    ) -> Result<Vec<OutPoint>> {
+        let mint_client = self.mint_client();
+        let retired_tier_notes = mint_client.retired_tier_notes().await;
+        if !retired_tier_notes.is_empty() {
+            debug!(target: LOG_WALLET, retired_tier_notes = ?retired_tier_notes.summary(), "Migrating notes of retired tiers");
+        }
+
+        let aging_notes = if mint_client.config.note_expiry.is_some() {
+            let block_height = self.context.api.fetch_consensus_block_height().await?;
+            let current_key_epoch = mint_client.config.key_epoch(block_height as u32);
+            let aging_notes = mint_client.aging_notes(current_key_epoch).await;
+            debug!(target: LOG_WALLET, current_key_epoch, aging_notes = ?aging_notes.summary(), "Refreshing aging notes");
+            aging_notes
+        } else {
+            TieredMulti::default()
+        };
+
+        let notes = aging_notes
+            .into_iter()
+            .chain(retired_tier_notes.into_iter())
+            .collect::<TieredMulti<_>>();
+        if notes.is_empty() {
+            return Ok(vec![]);
+        }
+        self.reissue_batched(notes, rng).await
+    }
+
+    /// Whether none of our operations, issuances or note reissuances is in
+    /// flight, so notes can be reissued without racing a spend
+    pub async fn is_idle(&self) -> bool {
+        let pending_operation = self
+            .list_operations(None, usize::MAX)
+            .await
+            .iter()
+            .any(|record| !record.outcome.is_finished());
+        let pending_notes = self
+            .context
+            .db
+            .begin_transaction()
+            .await
+            .find_by_prefix(&PendingNotesKeyPrefix)
+            .await
+            .next()
+            .await
+            .is_some();
+        !pending_operation && !pending_notes && self.list_active_issuances().await.is_empty()
+    }
+
+    /// Reissues the surplus notes of tiers holding more than `notes_per_tier`
+    /// notes into larger denominations, keeping spends fast and backups small
+    ///
+    /// Returns the out points of the newly issued notes.
+    pub async fn consolidate_notes<R: RngCore + CryptoRng>(
+        &self,
+        notes_per_tier: u16,
+        rng: R,
+    ) -> Result<Vec<OutPoint>> {
+        let surplus = self.mint_client().surplus_notes(notes_per_tier).await;
+        if surplus.is_empty() {
+            return Ok(vec![]);
+        }
+        debug!(target: LOG_WALLET, surplus = ?surplus.summary(), "Consolidating notes");
+        self.reissue_batched(surplus, rng).await
+    }
+
+    /// Opt-in background task running [`Self::consolidate_notes`] every
+    /// `interval` until `task_handle` shuts down. Notes are only consolidated
+    /// while the client [is idle](Self::is_idle), the new notes are fetched
+    /// right away.
+    pub async fn consolidate_notes_periodically(
+        &self,
+        task_handle: TaskHandle,
+        interval: Duration,
+        notes_per_tier: u16,
+    ) {
+        while !task_handle.is_shutting_down() {
+            self.update_operations().await;
+            if self.is_idle().await {
+                match self
+                    .consolidate_notes(notes_per_tier, rand::rngs::OsRng)
+                    .await
+                {
+                    Ok(out_points) if out_points.is_empty() => {}
+                    Ok(out_points) => {
+                        debug!(target: LOG_WALLET, ?out_points, "Consolidated notes");
+                        if let Err(e) = self.fetch_all_notes().await {
+                            warn!(target: LOG_WALLET, "Failed to fetch consolidated notes: {}", e);
+                        }
+                        self.update_operations().await;
+                    }
+                    Err(e) => warn!(target: LOG_WALLET, "Failed to consolidate notes: {}", e),
+                }
+            }
+            sleep(interval).await;
+        }
     }
 
     pub async fn await_consensus_block_height(
@@ -716,6 +1846,11 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
         }
     }
 
+    /// Fetches the notes of all pending issuances
+    ///
+    /// Afterwards notes signed under keys the federation retires with its next
+    /// key rotation are reissued transparently, their new notes get fetched by
+    /// the next call.
     pub async fn fetch_all_notes<'a>(&self) -> Result<Vec<OutPoint>> {
         let (errors, outpoints): (Vec<_>, Vec<_>) = self
             .mint_client()
@@ -727,21 +1862,42 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
                 Err(error) => Either::Left(error.into()),
             });
 
-        if errors.is_empty() {
-            Ok(outpoints)
-        } else {
-            Err(ClientError::UnableToFetchAllNotes(errors, outpoints))
+        if !errors.is_empty() {
+            return Err(ClientError::UnableToFetchAllNotes(errors, outpoints));
+        }
+
+        if let Err(error) = self.refresh_aging_notes(rand::rngs::OsRng).await {
+            warn!(target: LOG_WALLET, %error, "Could not reissue notes signed under retiring keys");
         }
+
+        Ok(outpoints)
     }
 
     pub async fn notes(&self) -> TieredMulti<SpendableNote> {
         self.mint_client().notes().await
     }
 
+    /// Total of our notes and of the funds held in the module instances run
+    /// by extensions
+    pub async fn balance(&self) -> Amount {
+        let mut balance = self.notes().await.total_amount();
+        let mut dbtx = self.context.db.begin_read_transaction().await;
+        for extension in self.context.extensions.values() {
+            balance += extension.balance(&mut dbtx).await;
+        }
+        balance
+    }
+
     pub async fn list_active_issuances(&self) -> Vec<(OutPoint, NoteIssuanceRequests)> {
         self.mint_client().list_active_issuances().await
     }
 
+    /// Fetches the number of epochs the federation processed, which fails if
+    /// not enough guardians respond
+    pub async fn fetch_epoch_count(&self) -> Result<u64> {
+        Ok(self.context.api.fetch_epoch_count().await?)
+    }
+
     pub async fn fetch_epoch_history(
         &self,
         epoch: u64,
@@ -753,6 +1909,299 @@ impl<T: AsRef<ClientConfig> + Clone + Send> Client<T> {
             .fetch_epoch_history(epoch, epoch_pk, &self.context.decoders)
             .await?)
     }
+
+    /// Fetches the outcome of a transaction, trusting it once as many peers
+    /// agree as `strategy` requires. With [`QueryStrategyKind::Verified`] an
+    /// acceptance is only trusted once a peer proved that the transaction is
+    /// part of the threshold signed header of the epoch accepting it. Epochs
+    /// signed before headers existed have no such proof, so their acceptance is
+    /// checked against the whole signed epoch instead.
+    pub async fn fetch_tx_outcome(
+        &self,
+        txid: TransactionId,
+        strategy: QueryStrategyKind,
+    ) -> Result<TransactionStatus> {
+        let status = self
+            .context
+            .api
+            .fetch_tx_outcome_with(&txid, strategy)
+            .await?;
+
+        if let (QueryStrategyKind::Verified, TransactionStatus::Accepted { epoch, .. }) =
+            (strategy, &status)
+        {
+            let epoch_pk = self.config.as_ref().epoch_pk;
+            match self
+                .context
+                .api
+                .fetch_transaction_proof(&txid, epoch_pk)
+                .await
+            {
+                Ok(proof) if proof.header.epoch != *epoch => {
+                    return Err(ClientError::UnverifiedTransactionOutcome(
+                        txid,
+                        format!(
+                            "it was proven to be accepted in epoch {} instead of {epoch}",
+                            proof.header.epoch
+                        ),
+                    ));
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    let signed = self.fetch_epoch_history(*epoch, epoch_pk).await?;
+                    if !signed.outcome.accepted_txids().contains(&txid) {
+                        return Err(ClientError::UnverifiedTransactionOutcome(
+                            txid,
+                            format!("signed epoch {epoch} doesn't accept it"),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(status)
+    }
+
+    /// What we know about the settlement of a lightning contract we took part
+    /// in
+    pub async fn contract_evidence(&self, contract_id: ContractId) -> Result<ContractEvidence> {
+        Ok(self.ln_client().get_contract_evidence(contract_id).await?)
+    }
+
+    /// Bundles the evidence about a contract with the signed epochs accepting
+    /// its transactions, so third parties can verify it using
+    /// [`ContractProof::verify`]. Fails if the epochs haven't been signed yet.
+    pub async fn contract_proof(&self, contract_id: ContractId) -> Result<ContractProof> {
+        let evidence = self.contract_evidence(contract_id).await?;
+
+        let mut epochs = BTreeSet::new();
+        for txid in &evidence.transactions {
+            match self.context.api.fetch_tx_outcome(txid).await? {
+                TransactionStatus::Accepted { epoch, .. } => {
+                    epochs.insert(epoch);
+                }
+                TransactionStatus::Rejected(reason) => {
+                    return Err(ClientError::TransactionRejected(*txid, reason))
+                }
+            }
+        }
+
+        let epoch_pk = self.config.as_ref().epoch_pk;
+        let mut signed_epochs = vec![];
+        for epoch in epochs {
+            let signed = self.fetch_epoch_history(epoch, epoch_pk).await?;
+            signed_epochs.push(SerdeEpochHistory::from(&signed));
+        }
+
+        Ok(ContractProof {
+            evidence,
+            epochs: signed_epochs,
+        })
+    }
+
+    /// Starts watching the contract of a user's `refund` as a watchtower, see
+    /// [`Self::submit_due_refunds`]
+    pub async fn watch_refund(&self, refund: WatchtowerRefund) -> Result<()> {
+        Transaction::consensus_decode(&mut refund.transaction.as_slice(), &self.context.decoders)
+            .map_err(|e| ClientError::InvalidWatchtowerRefund(e.to_string()))?;
+        let account = self
+            .ln_client()
+            .get_outgoing_contract(refund.contract_id)
+            .await?;
+        if account.amount == Amount::ZERO {
+            return Err(ClientError::InvalidWatchtowerRefund(
+                "Contract was spent already".into(),
+            ));
+        }
+
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(&WatchedRefundKey(refund.contract_id), &refund)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+        Ok(())
+    }
+
+    /// Submits the watched refunds whose contract timed out or got cancelled.
+    /// Refunds of contracts that were spent in the meantime, by their gateway
+    /// or their user, are dropped. Returns the ids of the contracts we
+    /// submitted a refund for.
+    pub async fn submit_due_refunds(&self) -> Vec<ContractId> {
+        let refunds = self
+            .context
+            .db
+            .begin_transaction()
+            .await
+            .find_by_prefix(&WatchedRefundKeyPrefix)
+            .await
+            .map(|res| res.expect("DB error").1)
+            .collect::<Vec<WatchtowerRefund>>()
+            .await;
+
+        let mut submitted = vec![];
+        for refund in refunds {
+            let contract_id = refund.contract_id;
+            match self.submit_refund_if_due(refund).await {
+                Ok(true) => submitted.push(contract_id),
+                Ok(false) => {}
+                Err(e) => warn!(%contract_id, "Failed to submit watched refund: {}", e),
+            }
+        }
+        submitted
+    }
+
+    async fn submit_refund_if_due(&self, refund: WatchtowerRefund) -> Result<bool> {
+        let contract_id = refund.contract_id;
+        let ln_client = self.ln_client();
+        let account = ln_client.get_outgoing_contract(contract_id).await?;
+        let spent = account.amount == Amount::ZERO;
+        let refundable = ln_client.is_outgoing_contract_refundable(contract_id);
+        if !spent && !refundable.await? {
+            return Ok(false);
+        }
+
+        if !spent {
+            let transaction = Transaction::consensus_decode(
+                &mut refund.transaction.as_slice(),
+                &self.context.decoders,
+            )
+            .map_err(|e| ClientError::InvalidWatchtowerRefund(e.to_string()))?;
+            let txid = self.context.api.submit_transaction(transaction).await?;
+            info!(%contract_id, %txid, "Submitted watched refund");
+        }
+
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.remove_entry(&WatchedRefundKey(contract_id))
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+        Ok(!spent)
+    }
+
+    /// Funds an incoming contract buying the preimage `offer` sells. If the
+    /// federation fails to decrypt it `refund_key` can claim the contract back.
+    async fn fund_incoming_contract(
+        &self,
+        offer: &IncomingContractOffer,
+        refund_key: secp256k1_zkp::XOnlyPublicKey,
+        rng: impl RngCore + CryptoRng,
+    ) -> Result<(OutPoint, ContractId)> {
+        // Inputs
+        let mut builder = TransactionBuilder::default();
+        let (mut keys, input) = self.mint_client().select_input(offer.amount).await?;
+        builder.input(&mut keys, input);
+
+        // Outputs
+        let contract = Contract::Incoming(IncomingContract {
+            hash: offer.hash,
+            encrypted_preimage: offer.encrypted_preimage.clone(),
+            decrypted_preimage: DecryptedPreimage::Pending,
+            gateway_key: refund_key,
+            hold: offer.hold.clone(),
+        });
+        let incoming_output = Output::LN(LightningOutput::Contract(ContractOutput {
+            amount: offer.amount,
+            contract: contract.clone(),
+        }));
+
+        // Submit transaction
+        builder.output(incoming_output);
+        let txid = self.submit_tx_with_change(builder, rng).await?;
+        let outpoint = OutPoint { txid, out_idx: 0 };
+
+        let evidence = ContractEvidence::new(
+            contract.contract_id(),
+            ContractDirection::Incoming,
+            offer.hash,
+        )
+        .with_transaction(txid);
+        self.ln_client().save_contract_evidence(evidence).await;
+
+        Ok((outpoint, contract.contract_id()))
+    }
+
+    /// Claws back the funds of an incoming contract we funded with
+    /// `refund_key` after its preimage turned out invalid
+    async fn refund_incoming_contract_with(
+        &self,
+        contract_id: ContractId,
+        refund_key: KeyPair,
+        rng: impl RngCore + CryptoRng,
+    ) -> Result<TransactionId> {
+        let contract_account = self.ln_client().get_incoming_contract(contract_id).await?;
+
+        let mut builder = TransactionBuilder::default();
+
+        // Input claims this contract
+        builder.input(&mut vec![refund_key], Input::LN(contract_account.claim()));
+        let mint_tx_id = self.submit_tx_with_change(builder, rng).await?;
+        Ok(mint_tx_id)
+    }
+
+    /// Wait for the preimage of an incoming contract we funded to become
+    /// available. Held contracts are only decrypted once their recipient
+    /// released the preimage, so for these we wait until the hold timelock
+    /// expired at most, after which the contract can be refunded.
+    pub async fn await_incoming_contract_preimage(
+        &self,
+        outpoint: OutPoint,
+        contract_id: ContractId,
+    ) -> Result<Preimage> {
+        let account = self.ln_client().get_incoming_contract(contract_id).await?;
+        let preimage = match account.contract.hold {
+            Some(hold) => self.await_held_preimage(contract_id, hold).await?,
+            None => self.await_preimage_decryption(outpoint).await?,
+        };
+
+        let evidence = ContractEvidence::new(
+            contract_id,
+            ContractDirection::Incoming,
+            account.contract.hash,
+        )
+        .with_preimage(preimage.clone());
+        self.ln_client().save_contract_evidence(evidence).await;
+
+        Ok(preimage)
+    }
+
+    /// Waits until the recipient released the preimage of a held contract or
+    /// its hold timelock expired
+    async fn await_held_preimage(
+        &self,
+        contract_id: ContractId,
+        hold: HoldTerms,
+    ) -> Result<Preimage> {
+        loop {
+            let account = self.ln_client().get_incoming_contract(contract_id).await?;
+            match account.contract.decrypted_preimage {
+                DecryptedPreimage::Some(preimage) => return Ok(preimage),
+                DecryptedPreimage::Invalid => return Err(ClientError::InvalidPreimage),
+                DecryptedPreimage::Pending => {}
+            }
+
+            let block_height = self.context.api.fetch_consensus_block_height().await?;
+            if block_height >= u64::from(hold.timelock) {
+                return Err(ClientError::HoldExpired);
+            }
+
+            fedimint_api::task::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Wait for a lightning preimage gateway has purchased to be decrypted by
+    /// the federation
+    pub async fn await_preimage_decryption(&self, outpoint: OutPoint) -> Result<Preimage> {
+        Ok(self
+            .context
+            .api
+            .await_output_outcome::<OutputOutcome>(
+                outpoint,
+                Duration::from_secs(10),
+                &self.context.decoders,
+            )
+            .await?
+            .try_into_variant()?)
+    }
 }
 
 impl Client<UserClientConfig> {
@@ -763,16 +2212,21 @@ impl Client<UserClientConfig> {
     pub async fn fetch_active_gateway(&self) -> Result<LightningGateway> {
         // FIXME: forgetting about old gws might not always be ideal. We assume that the
         // gateway stays the same except for route hints for now.
-        if let Some(gateway) = self
+        //
+        // The active gateway is only a cache of the federation's registrations, so one
+        // that was stored before the announcement format changed is fetched again.
+        let cached = self
             .context
             .db
             .begin_transaction()
             .await
             .get_value(&LightningGatewayKey)
             .await
-            .expect("DB error")
-            .filter(|gw| gw.valid_until > SystemTime::now())
-        {
+            .unwrap_or_else(|e| {
+                debug!("Could not decode cached gateway, fetching it again: {e}");
+                None
+            });
+        if let Some(gateway) = cached.filter(|gw| gw.valid_until > SystemTime::now()) {
             return Ok(gateway);
         }
 
@@ -817,12 +2271,24 @@ impl Client<UserClientConfig> {
 
     pub async fn fund_outgoing_ln_contract<R: RngCore + CryptoRng>(
         &self,
-        invoice: Invoice,
-        mut rng: R,
+        invoice: impl Into<OutgoingInvoice>,
+        rng: R,
     ) -> Result<(ContractId, OutPoint)> {
         let gateway = self.fetch_active_gateway().await?;
+        self.fund_outgoing_ln_contract_via(invoice, &gateway, rng)
+            .await
+    }
+
+    /// Funds an outgoing contract that `gateway` can claim by paying `invoice`
+    pub async fn fund_outgoing_ln_contract_via<R: RngCore + CryptoRng>(
+        &self,
+        invoice: impl Into<OutgoingInvoice>,
+        gateway: &LightningGateway,
+        mut rng: R,
+    ) -> Result<(ContractId, OutPoint)> {
         let mut dbtx = self.context.db.begin_transaction().await;
         let mut tx = TransactionBuilder::default();
+        let invoice = invoice.into();
 
         let consensus_height = self.context.api.fetch_consensus_block_height().await?;
         let absolute_timelock = consensus_height + OUTGOING_LN_CONTRACT_TIMELOCK;
@@ -831,8 +2297,8 @@ impl Client<UserClientConfig> {
             .ln_client()
             .create_outgoing_output(
                 &mut dbtx,
-                invoice,
-                &gateway,
+                invoice.clone(),
+                gateway,
                 absolute_timelock as u32,
                 &mut rng,
             )
@@ -846,7 +2312,9 @@ impl Client<UserClientConfig> {
                 let amount = c.amount;
                 (contract_id, amount)
             }
-            LightningOutput::Offer(_) | LightningOutput::CancelOutgoing { .. } => {
+            LightningOutput::Offer(_)
+            | LightningOutput::CancelOutgoing { .. }
+            | LightningOutput::ReleaseIncoming { .. } => {
                 panic!()
             } // FIXME: impl TryFrom
         };
@@ -854,9 +2322,37 @@ impl Client<UserClientConfig> {
         let (mut keys, input) = self.mint_client().select_input(amount).await?;
         tx.input(&mut keys, input);
         tx.output(Output::LN(contract));
-        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+        let invoice_amount = invoice
+            .amount_milli_satoshis()
+            .map_or(amount, Amount::from_msats);
+        let txid = self
+            .submit_operation_tx(
+                tx,
+                |_| {
+                    (
+                        OperationId::derive("ln-pay", &contract_id),
+                        OperationKind::LnPay { contract_id },
+                    )
+                },
+                invoice_amount,
+                &mut rng,
+            )
+            .await?;
         let outpoint = OutPoint { txid, out_idx: 0 };
 
+        let evidence = ContractEvidence::new(
+            contract_id,
+            ContractDirection::Outgoing,
+            invoice.payment_hash(),
+        )
+        .with_invoice(invoice)
+        .with_transaction(txid);
+        self.ln_client().save_contract_evidence(evidence).await;
+
+        self.ln_client()
+            .save_outgoing_payment_status(contract_id, amount, OutgoingPaymentStatus::Pending)
+            .await;
+
         debug!("Funded outgoing contract {} in {}", contract_id, outpoint);
         Ok((contract_id, outpoint))
     }
@@ -865,7 +2361,8 @@ impl Client<UserClientConfig> {
     ///
     /// This can be necessary when the Lightning gateway cannot route the
     /// payment, is malicious or offline. The function returns the out point
-    /// of the e-cash output generated as change.
+    /// of the e-cash output generated as change. The contract's data is kept
+    /// until [`Self::update_outgoing_payments`] sees the refund accepted.
     pub async fn try_refund_outgoing_contract(
         &self,
         contract_id: ContractId,
@@ -888,14 +2385,238 @@ impl Client<UserClientConfig> {
         tx.input(&mut vec![*refund_key], Input::LN(refund_input));
         let txid = self.submit_tx_with_change(tx, rng).await?;
 
+        self.ln_client()
+            .save_outgoing_payment_status(
+                contract_id,
+                contract_data.contract_account.amount,
+                OutgoingPaymentStatus::Refunding { txid },
+            )
+            .await;
+
+        Ok(OutPoint { txid, out_idx: 0 })
+    }
+
+    /// Hands a signed refund of our outgoing contract to the `watchtower`,
+    /// which submits it once the contract timed out or got cancelled. This
+    /// way we get our funds back even if we are offline at that time, their
+    /// e-cash is fetched by [`Self::update_outgoing_payments`] once we are
+    /// back.
+    pub async fn register_watchtower_refund(
+        &self,
+        contract_id: ContractId,
+        watchtower: &Url,
+        rng: impl RngCore + CryptoRng,
+    ) -> Result<()> {
+        let contract_data = self
+            .context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&OutgoingPaymentKey(contract_id))
+            .await
+            .expect("DB error")
+            .ok_or(ClientError::RefundUnknownOutgoingContract)?;
+
+        let mut builder = TransactionBuilder::default();
+        let (refund_key, refund_input) = self
+            .ln_client()
+            .create_refund_outgoing_contract_input(&contract_data);
+        builder.input(&mut vec![*refund_key], Input::LN(refund_input));
+
         let mut dbtx = self.context.db.begin_transaction().await;
-        dbtx.remove_entry(&OutgoingPaymentKey(contract_id))
+        let transaction = builder.build(self, &mut dbtx, rng).await;
+        let out_point = OutPoint {
+            txid: transaction.tx_hash(),
+            out_idx: 0,
+        };
+        // The notes only get issued once the watchtower submits the refund, till then
+        // fetching our notes mustn't wait for them
+        let issuance = match dbtx
+            .remove_entry(&OutputFinalizationKey(out_point))
             .await
             .expect("DB error")
-            .ok_or(ClientError::DeleteUnknownOutgoingContract)?;
+        {
+            Some(issuance) => issuance,
+            None => {
+                dbtx.abort_tx();
+                return Err(ClientError::RefundBelowFees);
+            }
+        };
+        // Persisted before handing out the refund, we couldn't finalize its notes
+        // otherwise
+        dbtx.insert_entry(
+            &WatchtowerRefundKey(contract_id),
+            &WatchtowerRefundIssuance {
+                out_point,
+                issuance,
+            },
+        )
+        .await
+        .expect("DB error");
         dbtx.commit_tx().await.expect("DB Error");
 
-        Ok(OutPoint { txid, out_idx: 0 })
+        let refund = WatchtowerRefund {
+            contract_id,
+            transaction: transaction
+                .into_type_erased()
+                .consensus_encode_to_vec()
+                .expect("Encoding to a vec can't fail"),
+        };
+        self.ln_client()
+            .register_watchtower_refund(watchtower, self.config.0.federation_id.clone(), refund)
+            .await?;
+        Ok(())
+    }
+
+    /// Our outgoing lightning payments and their status, most recently
+    /// updated first
+    pub async fn list_outgoing_payments(&self) -> Vec<OutgoingPaymentRecord> {
+        self.ln_client().list_outgoing_payment_records().await
+    }
+
+    /// Advances our unfinished outgoing payments: payments whose contract the
+    /// gateway claimed are marked paid, contracts the gateway cancelled or let
+    /// time out are refunded and refunds get marked as such once the
+    /// federation accepted them. Returns the payments whose status changed.
+    pub async fn update_outgoing_payments(
+        &self,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> Vec<OutgoingPaymentRecord> {
+        let mut updated = vec![];
+        for record in self.ln_client().list_outgoing_payment_records().await {
+            if record.status.is_finished() {
+                continue;
+            }
+
+            let contract_id = record.contract_id;
+            match self.update_outgoing_payment(record, &mut rng).await {
+                Ok(Some(record)) => {
+                    info!(%contract_id, status = ?record.status, "Outgoing payment changed status");
+                    updated.push(record);
+                }
+                Ok(None) => {}
+                Err(e) => warn!(%contract_id, "Failed to update outgoing payment: {}", e),
+            }
+        }
+        updated
+    }
+
+    /// Keeps advancing our unfinished outgoing payments like
+    /// [`Self::update_outgoing_payments`] until all of them are finished, so
+    /// funds of failed payments are refunded as soon as their contract allows
+    /// it. Long-running clients should spawn this after paying an invoice.
+    pub async fn monitor_outgoing_payments(&self, mut rng: impl RngCore + CryptoRng) {
+        loop {
+            self.update_outgoing_payments(&mut rng).await;
+            let unfinished = self
+                .list_outgoing_payments()
+                .await
+                .into_iter()
+                .filter(|record| !record.status.is_finished())
+                .count();
+            if unfinished == 0 {
+                return;
+            }
+            debug!(unfinished, "Waiting for outgoing payments to finish");
+            fedimint_api::task::sleep(OUTGOING_PAYMENT_CHECK_INTERVAL).await;
+        }
+    }
+
+    /// Checks whether the federation accepted the refund we handed to a
+    /// watchtower for `contract_id`. If so its notes get fetched with our
+    /// other pending notes and the id of the refund transaction is returned.
+    async fn finalize_watchtower_refund(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<Option<TransactionId>> {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        let refund = match dbtx
+            .get_value(&WatchtowerRefundKey(contract_id))
+            .await
+            .expect("DB error")
+        {
+            Some(refund) => refund,
+            None => return Ok(None),
+        };
+
+        let txid = refund.out_point.txid;
+        let refunded = matches!(
+            self.context.api.fetch_tx_outcome(&txid).await,
+            Ok(TransactionStatus::Accepted { .. })
+        );
+        if refunded {
+            dbtx.insert_new_entry(&OutputFinalizationKey(refund.out_point), &refund.issuance)
+                .await
+                .expect("DB error");
+            dbtx.remove_entry(&OutgoingPaymentKey(contract_id))
+                .await
+                .expect("DB error");
+        }
+        dbtx.remove_entry(&WatchtowerRefundKey(contract_id))
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+
+        Ok(refunded.then_some(txid))
+    }
+
+    /// Moves a single unfinished outgoing payment on, returns its new record
+    /// if its status changed
+    async fn update_outgoing_payment(
+        &self,
+        record: OutgoingPaymentRecord,
+        rng: impl RngCore + CryptoRng,
+    ) -> Result<Option<OutgoingPaymentRecord>> {
+        let contract_id = record.contract_id;
+        let ln_client = self.ln_client();
+        let status = match record.status {
+            OutgoingPaymentStatus::Pending => {
+                let account = ln_client.get_outgoing_contract(contract_id).await?;
+                if account.amount == Amount::ZERO {
+                    // Either a watchtower refunded the contract for us, or the gateway claimed
+                    // it by revealing the preimage
+                    match self.finalize_watchtower_refund(contract_id).await? {
+                        Some(txid) => OutgoingPaymentStatus::Refunded { txid },
+                        None => OutgoingPaymentStatus::Paid,
+                    }
+                } else if ln_client
+                    .is_outgoing_contract_refundable(contract_id)
+                    .await?
+                {
+                    self.try_refund_outgoing_contract(contract_id, rng).await?;
+                    return Ok(ln_client.get_outgoing_payment_record(contract_id).await);
+                } else {
+                    return Ok(None);
+                }
+            }
+            OutgoingPaymentStatus::Refunding { txid } => {
+                match self.context.api.fetch_tx_outcome(&txid).await? {
+                    TransactionStatus::Accepted { .. } => {
+                        let mut dbtx = self.context.db.begin_transaction().await;
+                        dbtx.remove_entry(&OutgoingPaymentKey(contract_id))
+                            .await
+                            .expect("DB error");
+                        dbtx.commit_tx().await.expect("DB Error");
+                        OutgoingPaymentStatus::Refunded { txid }
+                    }
+                    TransactionStatus::Rejected(reason) => {
+                        // The gateway may have claimed the contract before our refund got
+                        // processed, otherwise we try again
+                        warn!(%contract_id, %reason, "Refund of outgoing contract rejected");
+                        OutgoingPaymentStatus::Pending
+                    }
+                }
+            }
+            OutgoingPaymentStatus::Paid | OutgoingPaymentStatus::Refunded { .. } => {
+                return Ok(None)
+            }
+        };
+
+        Ok(Some(
+            ln_client
+                .save_outgoing_payment_status(contract_id, record.amount, status)
+                .await,
+        ))
     }
 
     pub async fn await_outgoing_contract_acceptance(&self, outpoint: OutPoint) -> Result<()> {
@@ -968,7 +2689,14 @@ impl Client<UserClientConfig> {
     ) -> Result<(TransactionId, Invoice, KeyPair)> {
         let payment_keypair = KeyPair::new(&self.context.secp, &mut rng);
         let (invoice, ln_output) = self
-            .generate_unsigned_invoice(amount, description, payment_keypair, &mut rng, expiry_time)
+            .generate_unsigned_invoice(
+                amount,
+                description,
+                payment_keypair,
+                None,
+                &mut rng,
+                expiry_time,
+            )
             .await?;
 
         // There is no input here because this is just an announcement
@@ -979,11 +2707,51 @@ impl Client<UserClientConfig> {
         Ok((txid, invoice, payment_keypair))
     }
 
+    /// Creates a held invoice (comparable to a HODL invoice): the gateway that
+    /// pays it only learns the preimage once we call
+    /// [`Client::release_incoming_contract`]. If we don't do so within
+    /// `hold_blocks` blocks the gateway gets its money back and the payment
+    /// fails.
+    pub async fn generate_confirmed_hold_invoice<R: RngCore + CryptoRng>(
+        &self,
+        amount: Amount,
+        description: String,
+        hold_blocks: u32,
+        mut rng: R,
+        expiry_time: Option<u64>,
+    ) -> Result<ConfirmedInvoice> {
+        let payment_keypair = KeyPair::new(&self.context.secp, &mut rng);
+        let release_keypair = KeyPair::new(&self.context.secp, &mut rng);
+        let block_height = self.context.api.fetch_consensus_block_height().await?;
+        let hold = HoldTerms {
+            release_key: release_keypair.x_only_public_key().0,
+            timelock: block_height as u32 + hold_blocks,
+        };
+        let (invoice, ln_output) = self
+            .generate_unsigned_invoice(
+                amount,
+                description,
+                payment_keypair,
+                Some(hold),
+                &mut rng,
+                expiry_time,
+            )
+            .await?;
+
+        let mut tx = TransactionBuilder::default();
+        tx.output(ln_output);
+        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+
+        self.confirm_invoice(txid, invoice, payment_keypair, Some(release_keypair))
+            .await
+    }
+
     pub async fn generate_unsigned_invoice<R: RngCore + CryptoRng>(
         &self,
         amount: Amount,
         description: String,
         payment_keypair: KeyPair,
+        hold: Option<HoldTerms>,
         mut rng: R,
         expiry_time: Option<u64>,
     ) -> Result<(Invoice, Output)> {
@@ -1000,12 +2768,12 @@ impl Client<UserClientConfig> {
             src_node_id: gateway.node_pub_key,
             short_channel_id: gateway.mint_channel_id,
             fees: RoutingFees {
-                base_msat: 0,
-                proportional_millionths: 0,
+                base_msat: gateway.fees.base_msat,
+                proportional_millionths: gateway.fees.proportional_millionths,
             },
             cltv_expiry_delta: 30,
-            htlc_minimum_msat: None,
-            htlc_maximum_msat: None,
+            htlc_minimum_msat: Some(gateway.limits.min_payment.msats).filter(|msat| *msat != 0),
+            htlc_maximum_msat: gateway.limits.max_payment.map(|max| max.msats),
         };
         let route_hints = if gateway.route_hints.is_empty() {
             vec![RouteHint(vec![route_hint_last_hop])]
@@ -1030,6 +2798,15 @@ impl Client<UserClientConfig> {
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap();
 
+        // The gateway has to be able to hold the HTLC until the hold timelock expired
+        let min_final_cltv_expiry = match &hold {
+            Some(hold) => {
+                let block_height = self.context.api.fetch_consensus_block_height().await?;
+                MIN_FINAL_CLTV_EXPIRY + u64::from(hold.timelock).saturating_sub(block_height)
+            }
+            None => MIN_FINAL_CLTV_EXPIRY,
+        };
+
         let mut invoice_builder = InvoiceBuilder::new(network_to_currency(
             self.config
                 .0
@@ -1043,7 +2820,7 @@ impl Client<UserClientConfig> {
         .payment_hash(payment_hash)
         .payment_secret(payment_secret)
         .duration_since_epoch(duration_since_epoch)
-        .min_final_cltv_expiry(18)
+        .min_final_cltv_expiry(min_final_cltv_expiry)
         .payee_pub_key(node_public_key)
         .expiry_time(Duration::from_secs(
             expiry_time.unwrap_or(DEFAULT_EXPIRY_TIME),
@@ -1064,6 +2841,7 @@ impl Client<UserClientConfig> {
             payment_hash,
             Preimage(raw_payment_secret),
             expiry_time,
+            hold,
         );
         let ln_output = Output::LN(offer_output);
 
@@ -1075,6 +2853,17 @@ impl Client<UserClientConfig> {
         txid: TransactionId,
         invoice: Invoice,
         payment_keypair: KeyPair,
+    ) -> Result<ConfirmedInvoice> {
+        self.confirm_invoice(txid, invoice, payment_keypair, None)
+            .await
+    }
+
+    async fn confirm_invoice(
+        &self,
+        txid: TransactionId,
+        invoice: Invoice,
+        payment_keypair: KeyPair,
+        release_key: Option<KeyPair>,
     ) -> Result<ConfirmedInvoice> {
         // Await acceptance by the federation
         let timeout = std::time::Duration::from_secs(15);
@@ -1086,6 +2875,7 @@ impl Client<UserClientConfig> {
         let confirmed = ConfirmedInvoice {
             invoice,
             keypair: payment_keypair,
+            release_key,
         };
         self.ln_client().save_confirmed_invoice(&confirmed).await;
         Ok(confirmed)
@@ -1105,55 +2895,427 @@ impl Client<UserClientConfig> {
         tx.input(&mut vec![ci.keypair], Input::LN(contract.claim()));
         let txid = self.submit_tx_with_change(tx, &mut rng).await?;
 
-        // TODO: Update database if invoice is paid or expired
+        let mut evidence = ContractEvidence::new(
+            contract_id,
+            ContractDirection::Incoming,
+            contract.contract.hash,
+        )
+        .with_invoice(ci.invoice.into())
+        .with_transaction(txid);
+        if let DecryptedPreimage::Some(preimage) = contract.contract.decrypted_preimage {
+            evidence = evidence.with_preimage(preimage);
+        }
+        self.ln_client().save_contract_evidence(evidence).await;
+
+        let out_point = OutPoint { txid, out_idx: 0 };
+        self.ln_client()
+            .save_incoming_claim(contract_id, out_point)
+            .await;
+
+        let mut dbtx = self.context.db.begin_transaction().await;
+        self.record_operation(
+            &mut dbtx,
+            OperationId::derive("ln-receive", &contract_id),
+            OperationKind::LnReceive { contract_id, txid },
+            contract.amount,
+            OperationOutcome::Pending,
+        )
+        .await;
+        dbtx.commit_tx().await.expect("DB Error");
+
+        Ok(out_point)
+    }
+
+    /// Claims the incoming contracts of all our invoices that were paid since
+    /// we last checked, e.g. while the app was closed. The gateway funding a
+    /// contract doesn't need us to be online: the federation keeps the
+    /// encrypted preimage of our offer and decrypts it on its own, so all we
+    /// need is the invoice's keypair persisted when it was confirmed.
+    ///
+    /// Invoices that weren't paid yet, held invoices we didn't release and
+    /// contracts whose preimage turned out invalid are skipped.
+    pub async fn claim_pending_incoming_contracts(
+        &self,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> Result<Vec<OutPoint>> {
+        let mut claimed = vec![];
+        for ci in self.ln_client().list_unclaimed_invoices().await {
+            let contract_id = ci.contract_id();
+            let contract = match self
+                .ln_client()
+                .try_get_incoming_contract(contract_id)
+                .await?
+            {
+                Some(contract) => contract,
+                None => continue,
+            };
+            if !matches!(
+                contract.contract.decrypted_preimage,
+                DecryptedPreimage::Some(_)
+            ) {
+                debug!(%contract_id, "Incoming contract can't be claimed yet");
+                continue;
+            }
+
+            claimed.push(self.claim_incoming_contract(contract_id, &mut rng).await?);
+        }
+        Ok(claimed)
+    }
+
+    /// Releases the preimage of a paid held invoice to the gateway, which
+    /// allows it to settle the payment and us to claim the incoming contract
+    /// once the federation decrypted the preimage.
+    pub async fn release_incoming_contract(
+        &self,
+        contract_id: ContractId,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> Result<OutPoint> {
+        let ci = self.ln_client().get_confirmed_invoice(contract_id).await?;
+        let release_key = ci.release_key.ok_or(ClientError::NotHeldInvoice)?;
+        let contract = self.ln_client().get_incoming_contract(contract_id).await?;
+
+        let release_signature = self
+            .context
+            .secp
+            .sign_schnorr(&contract.contract.release_message().into(), &release_key);
+        let release_output = self
+            .ln_client()
+            .create_release_incoming_output(contract_id, release_signature);
+
+        let mut tx = TransactionBuilder::default();
+        tx.output(Output::LN(release_output));
+        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+
+        let evidence = ContractEvidence::new(
+            contract_id,
+            ContractDirection::Incoming,
+            contract.contract.hash,
+        )
+        .with_invoice(ci.invoice.into())
+        .with_transaction(txid);
+        self.ln_client().save_contract_evidence(evidence).await;
+
+        Ok(OutPoint { txid, out_idx: 0 })
+    }
+
+    /// Notify gateway that we've escrowed notes they can claim by routing our
+    /// payment and wait for them to do so
+    pub async fn await_outgoing_contract_execution(
+        &self,
+        contract_id: ContractId,
+        rng: impl RngCore + CryptoRng,
+    ) -> Result<()> {
+        let gateway = self.fetch_active_gateway().await?;
+        self.await_outgoing_contract_execution_via(contract_id, &gateway, rng)
+            .await
+    }
+
+    /// Asks `gateway` to pay the invoice of the outgoing contract and claims a
+    /// refund if it fails to do so
+    pub async fn await_outgoing_contract_execution_via(
+        &self,
+        contract_id: ContractId,
+        gateway: &LightningGateway,
+        rng: impl RngCore + CryptoRng,
+    ) -> Result<()> {
+        let payload = PayInvoicePayload::new(self.config.0.federation_id.clone(), contract_id);
+
+        let future = reqwest::Client::new()
+            .post(
+                gateway
+                    .api
+                    .join("pay_invoice")
+                    .expect("'pay_invoice' contains no invalid characters for a URL")
+                    .as_str(),
+            )
+            .json(&payload)
+            .send();
+        let result = fedimint_api::task::timeout(Duration::from_secs(120), future)
+            .await
+            .map_err(|_| ClientError::OutgoingPaymentTimeout)?
+            .map_err(ClientError::HttpError);
+
+        match result {
+            Ok(response) => {
+                if response.status().is_success() {
+                    // Gateways reveal the preimage proving they paid the invoice, older ones
+                    // only return an empty response
+                    let ln_client = self.ln_client();
+                    if let (Ok(preimage), Ok(evidence)) = (
+                        response.json::<Preimage>().await,
+                        ln_client.get_contract_evidence(contract_id).await,
+                    ) {
+                        // Preimages not matching the payment hash are ignored
+                        ln_client
+                            .save_contract_evidence(evidence.with_preimage(preimage))
+                            .await;
+                    }
+                    if let Some(record) = ln_client.get_outgoing_payment_record(contract_id).await {
+                        ln_client
+                            .save_outgoing_payment_status(
+                                contract_id,
+                                record.amount,
+                                OutgoingPaymentStatus::Paid,
+                            )
+                            .await;
+                    }
+                    return Ok(());
+                }
+
+                fedimint_api::task::timeout(
+                    Duration::from_secs(10),
+                    self.ln_client().await_outgoing_refundable(contract_id),
+                )
+                .await
+                .map_err(|_| ClientError::FailedPaymentNoRefund)??;
+
+                self.try_refund_outgoing_contract(contract_id, rng).await?;
+                Err(ClientError::RefundedFailedPayment)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Pays `invoice` through a gateway picked according to `selection`.
+    ///
+    /// If the gateway fails to route the payment and we got our funds back the
+    /// payment is retried through an alternate gateway, trying at most
+    /// `max_attempts` gateways. A pinned gateway is never substituted.
+    pub async fn pay_invoice<R: RngCore + CryptoRng>(
+        &self,
+        invoice: impl Into<OutgoingInvoice>,
+        selection: GatewaySelection,
+        max_attempts: usize,
+        mut rng: R,
+    ) -> Result<ContractId> {
+        let invoice = invoice.into();
+        let invoice_amount = Amount::from_msats(
+            invoice
+                .amount_milli_satoshis()
+                .ok_or(ClientError::InvoiceMissingAmount)?,
+        );
+        if let Some(contract_id) = self.pay_invoice_internally(&invoice, &mut rng).await? {
+            return Ok(contract_id);
+        }
+        let mut failed_gateways = vec![];
+
+        loop {
+            let gateway = self
+                .ln_client()
+                .select_gateway(
+                    self.fetch_registered_gateways().await?,
+                    invoice_amount,
+                    &selection,
+                    &failed_gateways,
+                )
+                .await?;
+
+            let (contract_id, outpoint) = self
+                .fund_outgoing_ln_contract_via(invoice.clone(), &gateway, &mut rng)
+                .await?;
+            self.await_outgoing_contract_acceptance(outpoint).await?;
+
+            let result = self
+                .await_outgoing_contract_execution_via(contract_id, &gateway, &mut rng)
+                .await;
+            self.ln_client()
+                .record_gateway_outcome(gateway.node_pub_key, result.is_ok())
+                .await;
+
+            match result {
+                Ok(()) => return Ok(contract_id),
+                Err(ClientError::RefundedFailedPayment)
+                    if !matches!(selection, GatewaySelection::Pinned(_))
+                        && failed_gateways.len() + 1 < max_attempts =>
+                {
+                    warn!(
+                        gateway = %gateway.node_pub_key,
+                        "Gateway failed to pay invoice, retrying through another gateway"
+                    );
+                    failed_gateways.push(gateway.node_pub_key);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Pays `invoice` without a gateway if its recipient is a user of our own
+    /// federation, returns `None` otherwise.
+    ///
+    /// Like a gateway would, we buy the preimage from the federation by
+    /// funding an incoming contract, so no lightning or gateway fees apply and
+    /// we still learn the preimage proving the payment. If the federation
+    /// fails to decrypt it we claim our funds back.
+    pub async fn pay_invoice_internally<R: RngCore + CryptoRng>(
+        &self,
+        invoice: &OutgoingInvoice,
+        mut rng: R,
+    ) -> Result<Option<ContractId>> {
+        // Users of the federation only create BOLT11 invoices
+        let bolt11 = match invoice {
+            OutgoingInvoice::Bolt11(bolt11) => bolt11,
+            OutgoingInvoice::Bolt12(_) => return Ok(None),
+        };
+        let payment_hash = *bolt11.payment_hash();
+        if !self
+            .ln_client()
+            .offer_exists(payment_hash)
+            .await
+            .unwrap_or(false)
+        {
+            return Ok(None);
+        }
+
+        let invoice_amount = Amount::from_msats(
+            bolt11
+                .amount_milli_satoshis()
+                .ok_or(ClientError::InvoiceMissingAmount)?,
+        );
+        let offer = self.ln_client().get_offer(payment_hash).await?;
+        if offer.amount > invoice_amount || offer.hash != payment_hash {
+            return Err(ClientError::InvalidOffer);
+        }
+
+        // Remember the refund key before funding the contract so a crash can't
+        // lock our funds in it
+        let refund_key = KeyPair::new(&self.context.secp, &mut rng);
+        let contract_id = ContractId::from_hash(payment_hash);
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(&InternalPaymentKey(contract_id), &refund_key)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let (outpoint, contract_id) = self
+            .fund_incoming_contract(&offer, refund_key.x_only_public_key().0, &mut rng)
+            .await?;
+        debug!(%contract_id, "Funded incoming contract for internal payment");
+
+        let result = match self
+            .await_incoming_contract_preimage(outpoint, contract_id)
+            .await
+        {
+            Ok(preimage) => {
+                let ln_client = self.ln_client();
+                let evidence = ln_client.get_contract_evidence(contract_id).await?;
+                ln_client
+                    .save_contract_evidence(evidence.with_invoice(invoice.clone()))
+                    .await;
+                debug!("Paid invoice internally, preimage {:?}", preimage);
+                Ok(Some(contract_id))
+            }
+            Err(e) => {
+                warn!("Internal payment failed: {}. Now requesting a refund", e);
+                self.refund_incoming_contract_with(contract_id, refund_key, &mut rng)
+                    .await?;
+                Err(e)
+            }
+        };
+        let outcome = match &result {
+            Ok(_) => OperationOutcome::Succeeded,
+            Err(e) => OperationOutcome::Failed {
+                reason: e.to_string(),
+            },
+        };
+
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.remove_entry(&InternalPaymentKey(contract_id))
+            .await
+            .expect("DB error");
+        self.record_operation(
+            &mut dbtx,
+            OperationId::derive("ln-pay", &contract_id),
+            OperationKind::LnPay { contract_id },
+            invoice_amount,
+            outcome,
+        )
+        .await;
+        dbtx.commit_tx().await.expect("DB Error");
+
+        result
+    }
+
+    /// Fetches an invoice for a BOLT12 `offer` through a gateway and pays it
+    /// like [`Self::pay_invoice`]. `amount` is only used if the offer doesn't
+    /// specify one.
+    pub async fn pay_offer<R: RngCore + CryptoRng>(
+        &self,
+        offer: &Bolt12Offer,
+        amount: Option<Amount>,
+        selection: GatewaySelection,
+        max_attempts: usize,
+        rng: R,
+    ) -> Result<ContractId> {
+        let amount = offer
+            .amount_msats()
+            .map(Amount::from_msats)
+            .or(amount)
+            .ok_or(ClientError::InvoiceMissingAmount)?;
+        let gateway = self
+            .ln_client()
+            .select_gateway(
+                self.fetch_registered_gateways().await?,
+                amount,
+                &selection,
+                &[],
+            )
+            .await?;
+        let invoice = self
+            .ln_client()
+            .fetch_bolt12_invoice(
+                self.config.0.federation_id.clone(),
+                &gateway,
+                offer,
+                Some(amount),
+            )
+            .await?;
 
-        Ok(OutPoint { txid, out_idx: 0 })
+        self.pay_invoice(invoice, selection, max_attempts, rng)
+            .await
     }
 
-    /// Notify gateway that we've escrowed notes they can claim by routing our
-    /// payment and wait for them to do so
-    pub async fn await_outgoing_contract_execution(
+    /// Requests a reusable BOLT12 offer from the active gateway. Payments to it
+    /// are deposited into an account contract and can be claimed with
+    /// [`Self::claim_offer_payments`].
+    ///
+    /// Unlike invoices the offer's payments are not atomically exchanged for a
+    /// preimage: the gateway receives them and is trusted to forward them.
+    pub async fn create_receive_offer<R: RngCore + CryptoRng>(
         &self,
-        contract_id: ContractId,
-        rng: impl RngCore + CryptoRng,
-    ) -> Result<()> {
+        description: String,
+        rng: R,
+    ) -> Result<Bolt12Offer> {
         let gateway = self.fetch_active_gateway().await?;
-
-        let payload = PayInvoicePayload::new(self.config.0.federation_id.clone(), contract_id);
-
-        let future = reqwest::Client::new()
-            .post(
-                gateway
-                    .api
-                    .join("pay_invoice")
-                    .expect("'pay_invoice' contains no invalid characters for a URL")
-                    .as_str(),
+        let receive_offer = self
+            .ln_client()
+            .create_receive_offer(
+                self.config.0.federation_id.clone(),
+                &gateway,
+                description,
+                rng,
             )
-            .json(&payload)
-            .send();
-        let result = fedimint_api::task::timeout(Duration::from_secs(120), future)
-            .await
-            .map_err(|_| ClientError::OutgoingPaymentTimeout)?
-            .map_err(ClientError::HttpError);
-
-        match result {
-            Ok(response) => {
-                if response.status().is_success() {
-                    return Ok(());
-                }
+            .await?;
+        Ok(receive_offer.offer)
+    }
 
-                fedimint_api::task::timeout(
-                    Duration::from_secs(10),
-                    self.ln_client().await_outgoing_refundable(contract_id),
-                )
-                .await
-                .map_err(|_| ClientError::FailedPaymentNoRefund)??;
+    /// Claims everything paid to our receive offers so far, returns `None` if
+    /// there was nothing to claim
+    pub async fn claim_offer_payments<R: RngCore + CryptoRng>(
+        &self,
+        mut rng: R,
+    ) -> Result<Option<OutPoint>> {
+        let inputs = self.ln_client().create_receive_offer_inputs().await;
+        if inputs.is_empty() {
+            return Ok(None);
+        }
 
-                self.try_refund_outgoing_contract(contract_id, rng).await?;
-                Err(ClientError::RefundedFailedPayment)
-            }
-            Err(e) => Err(e),
+        let mut tx = TransactionBuilder::default();
+        for (keypair, input) in inputs {
+            tx.input(&mut vec![keypair], Input::LN(input));
         }
+        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+
+        Ok(Some(OutPoint { txid, out_idx: 0 }))
     }
 }
 
@@ -1186,15 +3348,35 @@ impl Client<GatewayClientConfig> {
             return Err(ClientError::NotOurKey);
         }
 
-        let invoice: Invoice = account.contract.invoice.clone();
+        let invoice = &account.contract.invoice;
         let invoice_amount = Amount::from_msats(
             invoice
                 .amount_milli_satoshis()
                 .ok_or(ClientError::InvoiceMissingAmount)?,
         );
 
-        if account.amount < invoice_amount {
-            return Err(ClientError::Underfunded(invoice_amount, account.amount));
+        if !self.config.limits.allows_payment(invoice_amount) {
+            return Err(ClientError::PaymentOutsideLimits(invoice_amount));
+        }
+
+        let gateway_fee = self.config.fees.fee(invoice_amount);
+        if account.amount < invoice_amount + gateway_fee {
+            return Err(ClientError::Underfunded(
+                invoice_amount + gateway_fee,
+                account.amount,
+            ));
+        }
+
+        if let Some(max_in_flight) = self.config.limits.max_in_flight {
+            let in_flight = self
+                .list_pending_outgoing()
+                .await
+                .into_iter()
+                .map(|pending| pending.amount)
+                .sum::<Amount>();
+            if in_flight + account.amount > max_in_flight {
+                return Err(ClientError::InFlightLimitReached(max_in_flight));
+            }
         }
 
         let consensus_block_height = self.context.api.fetch_consensus_block_height().await?;
@@ -1208,14 +3390,19 @@ impl Client<GatewayClientConfig> {
         Ok(PaymentParameters {
             max_delay,
             invoice_amount,
-            max_send_amount: account.amount,
-            payment_hash: *invoice.payment_hash(),
-            maybe_internal: self.is_maybe_internal_payment(&invoice),
+            max_send_amount: account.amount - gateway_fee,
+            payment_hash: invoice.payment_hash(),
+            maybe_internal: self.is_maybe_internal_payment(invoice),
         })
     }
 
     /// Returns true if the invoice contains us as a routing hint
-    fn is_maybe_internal_payment(&self, invoice: &Invoice) -> bool {
+    fn is_maybe_internal_payment(&self, invoice: &OutgoingInvoice) -> bool {
+        let invoice = match invoice {
+            OutgoingInvoice::Bolt11(invoice) => invoice,
+            // Routes to federation users are only advertised in BOLT11 route hints
+            OutgoingInvoice::Bolt12(_) => return false,
+        };
         let maybe_route_hint_first_id = invoice
             .route_hints()
             .first()
@@ -1244,6 +3431,122 @@ impl Client<GatewayClientConfig> {
         dbtx.commit_tx().await.expect("DB Error");
     }
 
+    /// Remembers to forward payments to the BOLT12 offer `offer_id` to
+    /// `account`
+    pub async fn register_offer(&self, offer_id: sha256::Hash, account: AccountContract) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(&OfferRegistrationKey(offer_id), &account)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    pub async fn offer_account(&self, offer_id: sha256::Hash) -> Option<AccountContract> {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&OfferRegistrationKey(offer_id))
+            .await
+            .expect("DB error")
+    }
+
+    /// Index of the last offer payment of our node we processed, 0 if none
+    pub async fn last_offer_pay_index(&self) -> u64 {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&OfferPayIndexKey)
+            .await
+            .expect("DB error")
+            .unwrap_or(0)
+    }
+
+    pub async fn save_last_offer_pay_index(&self, pay_index: u64) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(&OfferPayIndexKey, &pay_index)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    /// Transaction of our lightning node paying a peg-in to rebalance our
+    /// e-cash that we didn't claim yet
+    pub async fn pending_rebalance_peg_in(&self) -> Option<BitcoinTransaction> {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&RebalancePegInKey)
+            .await
+            .expect("DB error")
+    }
+
+    pub async fn save_rebalance_peg_in(&self, transaction: &BitcoinTransaction) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(&RebalancePegInKey, transaction)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    pub async fn remove_rebalance_peg_in(&self) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.remove_entry(&RebalancePegInKey)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    /// Deposits `amount` of our e-cash into `account`
+    pub async fn fund_account_contract(
+        &self,
+        account: AccountContract,
+        amount: Amount,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> Result<OutPoint> {
+        let mut tx = TransactionBuilder::default();
+
+        let (mut keys, input) = self.mint_client().select_input(amount).await?;
+        tx.input(&mut keys, input);
+        tx.output(Output::LN(LightningOutput::Contract(ContractOutput {
+            amount,
+            contract: Contract::Account(account),
+        })));
+        let txid = self.submit_tx_with_change(tx, &mut rng).await?;
+
+        Ok(OutPoint { txid, out_idx: 0 })
+    }
+
+    /// Adds a payment we routed or failed to route to our payment history
+    pub async fn save_gateway_payment(&self, payment: &GatewayPayment) {
+        let key = GatewayPaymentKey {
+            finished_at: payment.finished_at,
+            contract_id: payment.contract_id,
+        };
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(&key, payment).await.expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    /// Lists the `limit` payments that finished last, newest first
+    pub async fn list_gateway_payments(&self, limit: usize) -> Vec<GatewayPayment> {
+        let mut payments: Vec<GatewayPayment> = self
+            .context
+            .db
+            .begin_transaction()
+            .await
+            .find_by_prefix(&GatewayPaymentKeyPrefix)
+            .await
+            .map(|res| res.expect("DB error").1)
+            .collect()
+            .await;
+        payments.sort_by(|a, b| b.finished_at.cmp(&a.finished_at));
+        payments.truncate(limit);
+        payments
+    }
+
     /// Lists all previously saved transactions that have not been driven to
     /// completion so far
     pub async fn list_pending_outgoing(&self) -> Vec<OutgoingContractAccount> {
@@ -1258,10 +3561,49 @@ impl Client<GatewayClientConfig> {
             .await
     }
 
+    /// Records the state of the parts our node sent to pay the invoice of a
+    /// pending outgoing contract
+    pub async fn save_outgoing_payment_parts(
+        &self,
+        contract_id: ContractId,
+        parts: &OutgoingPaymentParts,
+    ) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(&OutgoingPaymentPartsKey(contract_id), parts)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    pub async fn outgoing_payment_parts(
+        &self,
+        contract_id: ContractId,
+    ) -> Option<OutgoingPaymentParts> {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&OutgoingPaymentPartsKey(contract_id))
+            .await
+            .expect("DB error")
+    }
+
     /// Abort payment if our node can't route it and give money back to user
+    ///
+    /// Fails while parts of the payment are still in flight since they could
+    /// still settle, in which case we would have paid without getting paid.
     pub async fn abort_outgoing_payment(&self, contract_id: ContractId) -> Result<()> {
         // FIXME: needs outbox pattern
         let mut dbtx = self.context.db.begin_transaction().await;
+        if let Some(parts) = dbtx
+            .remove_entry(&OutgoingPaymentPartsKey(contract_id))
+            .await
+            .expect("DB error")
+        {
+            if !parts.is_resolved() {
+                return Err(ClientError::PaymentPartsInFlight(parts.in_flight()));
+            }
+        }
         let contract_account = dbtx
             .remove_entry(&OutgoingContractAccountKey(contract_id))
             .await
@@ -1320,11 +3662,14 @@ impl Client<GatewayClientConfig> {
         let mut tx = TransactionBuilder::default();
 
         let contract = self.ln_client().get_outgoing_contract(contract_id).await?;
-        let input = Input::LN(contract.claim(preimage));
+        let input = Input::LN(contract.claim(preimage.clone()));
 
         dbtx.remove_entry(&OutgoingContractAccountKey(contract_id))
             .await
             .expect("DB Error");
+        dbtx.remove_entry(&OutgoingPaymentPartsKey(contract_id))
+            .await
+            .expect("DB Error");
         dbtx.insert_entry(&OutgoingPaymentClaimKey(contract_id), &())
             .await
             .expect("DB Error");
@@ -1333,6 +3678,16 @@ impl Client<GatewayClientConfig> {
         tx.input(&mut vec![self.config.redeem_key], input);
         let txid = self.submit_tx_with_change(tx, rng).await?;
 
+        let evidence = ContractEvidence::new(
+            contract_id,
+            ContractDirection::Outgoing,
+            contract.contract.hash,
+        )
+        .with_invoice(contract.contract.invoice)
+        .with_preimage(preimage)
+        .with_transaction(txid);
+        self.ln_client().save_contract_evidence(evidence).await;
+
         Ok(OutPoint { txid, out_idx: 0 })
     }
 
@@ -1365,31 +3720,9 @@ impl Client<GatewayClientConfig> {
             return Err(ClientError::InvalidOffer);
         }
 
-        // Inputs
-        let mut builder = TransactionBuilder::default();
-        let (mut keys, input) = self.mint_client().select_input(offer.amount).await?;
-        builder.input(&mut keys, input);
-
-        // Outputs
         let our_pub_key = secp256k1_zkp::XOnlyPublicKey::from_keypair(&self.config.redeem_key).0;
-        let contract = Contract::Incoming(IncomingContract {
-            hash: offer.hash,
-            encrypted_preimage: offer.encrypted_preimage.clone(),
-            decrypted_preimage: DecryptedPreimage::Pending,
-            gateway_key: our_pub_key,
-        });
-        let incoming_output = Output::LN(LightningOutput::Contract(ContractOutput {
-            amount: offer.amount,
-            contract: contract.clone(),
-        }));
-
-        // Submit transaction
-        builder.output(incoming_output);
-        let txid = self.submit_tx_with_change(builder, rng).await?;
-        let outpoint = OutPoint { txid, out_idx: 0 };
-
         // FIXME: Save this contract in DB
-        Ok((outpoint, contract.contract_id()))
+        self.fund_incoming_contract(&offer, our_pub_key, rng).await
     }
 
     /// Claw back funds after incoming contract that had invalid preimage
@@ -1399,17 +3732,8 @@ impl Client<GatewayClientConfig> {
         contract_id: ContractId,
         rng: impl RngCore + CryptoRng,
     ) -> Result<TransactionId> {
-        let contract_account = self.ln_client().get_incoming_contract(contract_id).await?;
-
-        let mut builder = TransactionBuilder::default();
-
-        // Input claims this contract
-        builder.input(
-            &mut vec![self.config.redeem_key],
-            Input::LN(contract_account.claim()),
-        );
-        let mint_tx_id = self.submit_tx_with_change(builder, rng).await?;
-        Ok(mint_tx_id)
+        self.refund_incoming_contract_with(contract_id, self.config.redeem_key, rng)
+            .await
     }
 
     /// Lists all claim transactions for outgoing contracts that we have
@@ -1426,21 +3750,6 @@ impl Client<GatewayClientConfig> {
             .await
     }
 
-    /// Wait for a lightning preimage gateway has purchased to be decrypted by
-    /// the federation
-    pub async fn await_preimage_decryption(&self, outpoint: OutPoint) -> Result<Preimage> {
-        Ok(self
-            .context
-            .api
-            .await_output_outcome::<OutputOutcome>(
-                outpoint,
-                Duration::from_secs(10),
-                &self.context.decoders,
-            )
-            .await?
-            .try_into_variant()?)
-    }
-
     // TODO: improve error propagation on tx transmission
     /// Waits for a outgoing contract claim transaction to be confirmed and
     /// retransmits it periodically if this does not happen.
@@ -1489,27 +3798,6 @@ impl Client<GatewayClientConfig> {
     }
 }
 
-impl Distribution<ClientSecret> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> ClientSecret {
-        let mut secret = [0u8; 64];
-        rng.fill(&mut secret);
-        ClientSecret(secret)
-    }
-}
-
-impl ClientSecret {
-    fn into_root_secret(self) -> DerivableSecret {
-        const FEDIMINT_CLIENT_NONCE: &[u8] = b"Fedimint Client Salt";
-        DerivableSecret::new_root(&self.0, FEDIMINT_CLIENT_NONCE)
-    }
-}
-
-impl Debug for ClientSecret {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "ClientSecret([redacted])")
-    }
-}
-
 /// Builds a fake module registry which is only usable for decoding messages
 /// since the client isn't modularized yet but we need the decoding
 /// functionality.
@@ -1588,6 +3876,10 @@ pub enum ClientError {
     InvoiceMissingAmount,
     #[error("Outgoing contract is underfunded, wants us to pay {0}, but only contains {1}")]
     Underfunded(Amount, Amount),
+    #[error("Payment of {0} is outside of the gateway's limits")]
+    PaymentOutsideLimits(Amount),
+    #[error("Gateway already has the maximum of {0} in outgoing payments in flight")]
+    InFlightLimitReached(Amount),
     #[error("The contract's timeout is in the past or does not allow for a safety margin")]
     TimeoutTooClose,
     #[error("No offer")]
@@ -1600,6 +3892,10 @@ pub enum ClientError {
     WrongTransactionType,
     #[error("Invalid transaction {0}")]
     InvalidTransaction(String),
+    #[error("Transaction {0} was rejected: {1}")]
+    TransactionRejected(TransactionId, String),
+    #[error("Transaction error: {0}")]
+    TransactionError(#[from] TransactionError),
     #[error("Invalid preimage")]
     InvalidPreimage,
     #[error("Federation has no lightning gateways")]
@@ -1616,16 +3912,28 @@ pub enum ClientError {
     InvalidSignature,
     #[error("Violated fee policy")]
     ViolatedFeePolicy,
+    #[error("Invoice isn't held, its preimage is released on payment")]
+    NotHeldInvoice,
+    #[error("Held incoming contract wasn't released before its timelock expired")]
+    HoldExpired,
     #[error("Tried to cancel outgoing contract that we don't know about")]
     CancelUnknownOutgoingContract,
+    #[error("Parts of the outgoing payment worth {0} are still in flight")]
+    PaymentPartsInFlight(Amount),
     #[error("Tried to refund outgoing contract that we don't know about")]
     RefundUnknownOutgoingContract,
+    #[error("Federation wasn't joined")]
+    UnknownFederation,
+    #[error("No federation was joined yet")]
+    NoActiveFederation,
+    #[error("Refund of the outgoing contract doesn't cover its fees")]
+    RefundBelowFees,
+    #[error("Invalid watchtower refund: {0}")]
+    InvalidWatchtowerRefund(String),
     #[error("Routing outgoing payment failed but we got a refund")]
     RefundedFailedPayment,
     #[error("Routing outgoing payment failed, we didn't get a refund (yet)")]
     FailedPaymentNoRefund,
-    #[error("Failed to delete unknown outgoing contract")]
-    DeleteUnknownOutgoingContract,
     #[error("Timeout")]
     Timeout,
     #[error("Failed to spend ecash, we tried to double-spend an ecash note")]
@@ -1634,8 +3942,16 @@ pub enum ClientError {
     CancelledContract,
     #[error("The client config cannot be verified because {0:?}")]
     ConfigVerify(ConfigVerifyError),
+    #[error("The outcome of transaction {0} cannot be verified, {1}")]
+    UnverifiedTransactionOutcome(TransactionId, String),
     #[error("Failed to fetch notes we expected to be issued {0:?}")]
     UnableToFetchAllNotes(Vec<ClientError>, Vec<OutPoint>),
+    #[error("The database already contains a different client secret")]
+    SecretMismatch,
+    #[error("Failed to restore from backup: {0}")]
+    RestoreFailed(anyhow::Error),
+    #[error("Reissuance failed after {} transactions were submitted: {1}", .0.len())]
+    PartialReissuance(Vec<OutPoint>, Box<ClientError>),
 }
 
 #[derive(Debug, Error)]
@@ -1653,3 +3969,79 @@ impl From<InvalidAmountTierError> for ClientError {
         ClientError::InvalidAmountTier(e.0)
     }
 }
+
+impl ClientError {
+    /// Keeps the out points of the reissuance batches submitted before `error`
+    fn partial_reissuance(out_points: Vec<OutPoint>, error: ClientError) -> Self {
+        if out_points.is_empty() {
+            error
+        } else {
+            ClientError::PartialReissuance(out_points, Box::new(error))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::Database;
+    use rand::{thread_rng, Rng};
+
+    use crate::db::ClientSecretKey;
+    use crate::secret::generate_mnemonic;
+    use crate::{module_decode_stubs, Client, ClientError, ClientSecret, UserClientConfig};
+
+    #[tokio::test]
+    async fn test_import_secret() {
+        let secret: ClientSecret = thread_rng().gen();
+        let parsed = secret.to_hex().parse::<ClientSecret>().unwrap();
+        assert_eq!(parsed.0, secret.0);
+        assert!("00".parse::<ClientSecret>().is_err());
+
+        let db = Database::new(MemDatabase::new(), module_decode_stubs());
+        Client::<UserClientConfig>::import_secret(&db, parsed)
+            .await
+            .unwrap();
+        let stored = db
+            .begin_transaction()
+            .await
+            .get_value(&ClientSecretKey)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.0, secret.0);
+
+        // Importing the same secret again is fine, but a different one would lose
+        // access to the notes derived from the first one
+        let same = secret.to_hex().parse().unwrap();
+        assert!(Client::<UserClientConfig>::import_secret(&db, same)
+            .await
+            .is_ok());
+        assert!(matches!(
+            Client::<UserClientConfig>::import_secret(&db, thread_rng().gen()).await,
+            Err(ClientError::SecretMismatch)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_import_mnemonic() {
+        let mnemonic = generate_mnemonic(thread_rng());
+        let db = Database::new(MemDatabase::new(), module_decode_stubs());
+        Client::<UserClientConfig>::import_mnemonic(&db, &mnemonic)
+            .await
+            .unwrap();
+
+        let stored = db
+            .begin_transaction()
+            .await
+            .get_value(&ClientSecretKey)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.0, ClientSecret::from_mnemonic(&mnemonic).0);
+        assert_eq!(
+            Client::<UserClientConfig>::get_mnemonic(&db).await,
+            Some(mnemonic)
+        );
+    }
+}
@@ -1,11 +1,18 @@
+use bitcoin::KeyPair;
+use bitcoin_hashes::sha256;
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::impl_db_prefix_const;
+use fedimint_api::OutPoint;
 use serde::Serialize;
 use strum_macros::EnumIter;
 
-use super::incoming::ConfirmedInvoice;
-use super::outgoing::OutgoingContractAccount;
+use super::incoming::{ConfirmedInvoice, ReceiveOffer};
+use super::outgoing::{OutgoingContractAccount, OutgoingPaymentParts, OutgoingPaymentRecord};
+use super::proof::ContractEvidence;
+use super::watchtower::{WatchtowerRefund, WatchtowerRefundIssuance};
+use super::{GatewayPayment, GatewayReliability};
 use crate::ln::outgoing::OutgoingContractData;
+use crate::modules::ln::contracts::account::AccountContract;
 use crate::modules::ln::contracts::ContractId;
 use crate::modules::ln::LightningGateway;
 
@@ -17,6 +24,18 @@ pub enum DbKeyPrefix {
     OutgoingContractAccount = 0x25,
     ConfirmedInvoice = 0x26,
     LightningGateway = 0x28,
+    GatewayReliability = 0x2c,
+    ReceiveOffer = 0x2d,
+    OfferRegistration = 0x2e,
+    OfferPayIndex = 0x2f,
+    ContractEvidence = 0x31,
+    IncomingClaim = 0x32,
+    OutgoingPaymentParts = 0x33,
+    GatewayPayment = 0x34,
+    InternalPayment = 0x35,
+    OutgoingPaymentRecord = 0x36,
+    WatchtowerRefund = 0x37,
+    WatchedRefund = 0x38,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -89,3 +108,178 @@ impl_db_prefix_const!(
     prefix = DbKeyPrefix::LightningGateway,
     key_prefix = LightningGatewayKeyPrefix
 );
+
+/// Payment history with a gateway, keyed by the gateway's node public key
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct GatewayReliabilityKey(pub secp256k1::PublicKey);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct GatewayReliabilityKeyPrefix;
+
+impl_db_prefix_const!(
+    key = GatewayReliabilityKey,
+    value = GatewayReliability,
+    prefix = DbKeyPrefix::GatewayReliability,
+    key_prefix = GatewayReliabilityKeyPrefix
+);
+
+/// BOLT12 offers we receive payments with, keyed by the id of the account
+/// contract the gateway deposits them into
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ReceiveOfferKey(pub ContractId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ReceiveOfferKeyPrefix;
+
+impl_db_prefix_const!(
+    key = ReceiveOfferKey,
+    value = ReceiveOffer,
+    prefix = DbKeyPrefix::ReceiveOffer,
+    key_prefix = ReceiveOfferKeyPrefix
+);
+
+/// Account contract a gateway's BOLT12 offer forwards payments to, keyed by
+/// the offer id
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct OfferRegistrationKey(pub sha256::Hash);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OfferRegistrationKeyPrefix;
+
+impl_db_prefix_const!(
+    key = OfferRegistrationKey,
+    value = AccountContract,
+    prefix = DbKeyPrefix::OfferRegistration,
+    key_prefix = OfferRegistrationKeyPrefix
+);
+
+/// Index of the last offer payment of the gateway's node that was processed
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct OfferPayIndexKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OfferPayIndexKeyPrefix;
+
+impl_db_prefix_const!(
+    key = OfferPayIndexKey,
+    value = u64,
+    prefix = DbKeyPrefix::OfferPayIndex,
+    key_prefix = OfferPayIndexKeyPrefix
+);
+
+/// What we know about the settlement of lightning contracts we took part in
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ContractEvidenceKey(pub ContractId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ContractEvidenceKeyPrefix;
+
+impl_db_prefix_const!(
+    key = ContractEvidenceKey,
+    value = ContractEvidence,
+    prefix = DbKeyPrefix::ContractEvidence,
+    key_prefix = ContractEvidenceKeyPrefix
+);
+
+/// Transaction in which we claimed the incoming contract of a confirmed
+/// invoice, invoices without an entry may still get paid
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct IncomingClaimKey(pub ContractId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct IncomingClaimKeyPrefix;
+
+impl_db_prefix_const!(
+    key = IncomingClaimKey,
+    value = OutPoint,
+    prefix = DbKeyPrefix::IncomingClaim,
+    key_prefix = IncomingClaimKeyPrefix
+);
+
+/// Parts the gateway's node sent to pay the invoice of an outgoing contract,
+/// only recorded for payments that didn't complete right away
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct OutgoingPaymentPartsKey(pub ContractId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OutgoingPaymentPartsKeyPrefix;
+
+impl_db_prefix_const!(
+    key = OutgoingPaymentPartsKey,
+    value = OutgoingPaymentParts,
+    prefix = DbKeyPrefix::OutgoingPaymentParts,
+    key_prefix = OutgoingPaymentPartsKeyPrefix
+);
+
+/// Payments a gateway routed or failed to route, keyed by when they finished
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct GatewayPaymentKey {
+    pub finished_at: u64,
+    pub contract_id: ContractId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct GatewayPaymentKeyPrefix;
+
+impl_db_prefix_const!(
+    key = GatewayPaymentKey,
+    value = GatewayPayment,
+    prefix = DbKeyPrefix::GatewayPayment,
+    key_prefix = GatewayPaymentKeyPrefix
+);
+
+/// Refund key of an incoming contract we funded to pay a user of our own
+/// federation, kept until the federation decrypted its preimage
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct InternalPaymentKey(pub ContractId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct InternalPaymentKeyPrefix;
+
+impl_db_prefix_const!(
+    key = InternalPaymentKey,
+    value = KeyPair,
+    prefix = DbKeyPrefix::InternalPayment,
+    key_prefix = InternalPaymentKeyPrefix
+);
+
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct OutgoingPaymentRecordKey(pub ContractId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OutgoingPaymentRecordKeyPrefix;
+
+impl_db_prefix_const!(
+    key = OutgoingPaymentRecordKey,
+    value = OutgoingPaymentRecord,
+    prefix = DbKeyPrefix::OutgoingPaymentRecord,
+    key_prefix = OutgoingPaymentRecordKeyPrefix
+);
+
+/// E-cash output of a refund we handed to a watchtower
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct WatchtowerRefundKey(pub ContractId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct WatchtowerRefundKeyPrefix;
+
+impl_db_prefix_const!(
+    key = WatchtowerRefundKey,
+    value = WatchtowerRefundIssuance,
+    prefix = DbKeyPrefix::WatchtowerRefund,
+    key_prefix = WatchtowerRefundKeyPrefix
+);
+
+/// Refund a user asked us to submit as a watchtower
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct WatchedRefundKey(pub ContractId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct WatchedRefundKeyPrefix;
+
+impl_db_prefix_const!(
+    key = WatchedRefundKey,
+    value = WatchtowerRefund,
+    prefix = DbKeyPrefix::WatchedRefund,
+    key_prefix = WatchedRefundKeyPrefix
+);
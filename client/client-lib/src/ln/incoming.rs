@@ -4,6 +4,8 @@ use fedimint_api::Amount;
 use lightning_invoice::Invoice;
 use serde::Serialize;
 
+use crate::modules::ln::bolt12::Bolt12Offer;
+use crate::modules::ln::contracts::account::AccountContract;
 use crate::modules::ln::contracts::incoming::IncomingContract;
 use crate::modules::ln::contracts::{ContractId, IdentifyableContract};
 use crate::modules::ln::LightningInput;
@@ -33,6 +35,8 @@ pub struct ConfirmedInvoice {
     pub invoice: Invoice,
     /// Keypair that will be able to sweep contract once it has received payment
     pub keypair: KeyPair,
+    /// Keypair releasing the preimage of a held invoice to the paying gateway
+    pub release_key: Option<KeyPair>,
 }
 
 impl Serialize for ConfirmedInvoice {
@@ -50,3 +54,29 @@ impl ConfirmedInvoice {
         (*self.invoice.payment_hash()).into()
     }
 }
+
+/// Reusable BOLT12 offer of a gateway that deposits payments into an account
+/// contract spendable by `keypair`
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct ReceiveOffer {
+    pub offer: Bolt12Offer,
+    pub keypair: KeyPair,
+}
+
+impl Serialize for ReceiveOffer {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.offer.to_string().as_str())
+    }
+}
+
+impl ReceiveOffer {
+    pub fn contract_id(&self) -> ContractId {
+        AccountContract {
+            key: self.keypair.x_only_public_key().0,
+        }
+        .contract_id()
+    }
+}
@@ -0,0 +1,279 @@
+//! Minimal LNURL-pay client resolving lightning addresses and LNURL-pay links
+//! to invoices that can be paid like any other invoice.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use bech32::FromBase32;
+use bitcoin_hashes::sha256::Hash as Sha256Hash;
+use bitcoin_hashes::Hash;
+use fedimint_api::Amount;
+use lightning_invoice::{Invoice, InvoiceDescription, ParseOrSemanticError};
+use serde::Deserialize;
+use thiserror::Error;
+use url::Url;
+
+const LNURL_HRP: &str = "lnurl";
+const PAY_REQUEST_TAG: &str = "payRequest";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Parameters of a LNURL-pay service (LUD-06)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayRequest {
+    /// Endpoint to request invoices from
+    pub callback: Url,
+    /// Largest amount in msat the service accepts
+    pub max_sendable: u64,
+    /// Smallest amount in msat the service accepts
+    pub min_sendable: u64,
+    /// JSON encoded metadata the invoice's description hash commits to
+    pub metadata: String,
+    pub tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct InvoiceResponse {
+    pr: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    status: String,
+    reason: String,
+}
+
+/// Something a user can pay over lightning, as scanned from a QR code or
+/// pasted by them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayTarget {
+    Invoice(Invoice),
+    /// URL of the LNURL-pay endpoint behind a lightning address or LNURL, see
+    /// [`parse_pay_target`]
+    Lnurl(Url),
+}
+
+impl FromStr for PayTarget {
+    type Err = LnurlError;
+
+    /// Parses a BOLT11 invoice, falling back to [`parse_pay_target`]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let target = strip_lightning_prefix(s.trim());
+        // Invoices in QR codes are usually upper case
+        if let Ok(invoice) = target.to_lowercase().parse::<Invoice>() {
+            return Ok(PayTarget::Invoice(invoice));
+        }
+        parse_pay_target(target).map(PayTarget::Lnurl)
+    }
+}
+
+fn strip_lightning_prefix(target: &str) -> &str {
+    target
+        .strip_prefix("lightning:")
+        .or_else(|| target.strip_prefix("LIGHTNING:"))
+        .unwrap_or(target)
+}
+
+/// Turns a lightning address (`name@domain`, LUD-16), a bech32 encoded
+/// `lnurl1…` string (LUD-01) or a `lnurlp://` link (LUD-17) into the URL of
+/// the LNURL-pay endpoint. A `lightning:` prefix is ignored.
+pub fn parse_pay_target(target: &str) -> Result<Url, LnurlError> {
+    let target = strip_lightning_prefix(target.trim());
+
+    if let Some((user, domain)) = target.split_once('@') {
+        if user.is_empty() || domain.is_empty() || domain.contains('@') {
+            return Err(LnurlError::InvalidTarget(target.to_owned()));
+        }
+        // Onion services aren't served via TLS
+        let scheme = if domain.ends_with(".onion") {
+            "http"
+        } else {
+            "https"
+        };
+        return Ok(Url::parse(&format!(
+            "{scheme}://{domain}/.well-known/lnurlp/{user}"
+        ))?);
+    }
+
+    if let Some(rest) = target.strip_prefix("lnurlp://") {
+        let scheme = if rest
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .ends_with(".onion")
+        {
+            "http"
+        } else {
+            "https"
+        };
+        return Ok(Url::parse(&format!("{scheme}://{rest}"))?);
+    }
+
+    let (hrp, data, _variant) = bech32::decode(target)?;
+    if hrp != LNURL_HRP {
+        return Err(LnurlError::InvalidTarget(target.to_owned()));
+    }
+    let bytes = Vec::<u8>::from_base32(&data)?;
+    let url = String::from_utf8(bytes).map_err(|_| LnurlError::InvalidTarget(target.to_owned()))?;
+    Ok(Url::parse(&url)?)
+}
+
+/// Fetches the parameters of the LNURL-pay service behind `url`
+pub async fn fetch_pay_request(url: Url) -> Result<PayRequest, LnurlError> {
+    let pay_request: PayRequest = get_json(url).await?;
+    if pay_request.tag != PAY_REQUEST_TAG {
+        return Err(LnurlError::NotPayRequest(pay_request.tag));
+    }
+    Ok(pay_request)
+}
+
+/// Requests an invoice for `amount` from a LNURL-pay service and checks it
+/// actually is for that amount and commits to the service's metadata
+pub async fn fetch_invoice(
+    pay_request: &PayRequest,
+    amount: Amount,
+) -> Result<Invoice, LnurlError> {
+    if amount.msats < pay_request.min_sendable || amount.msats > pay_request.max_sendable {
+        return Err(LnurlError::AmountOutOfRange {
+            amount,
+            min: Amount::from_msats(pay_request.min_sendable),
+            max: Amount::from_msats(pay_request.max_sendable),
+        });
+    }
+
+    let mut callback = pay_request.callback.clone();
+    callback
+        .query_pairs_mut()
+        .append_pair("amount", &amount.msats.to_string());
+    let response: InvoiceResponse = get_json(callback).await?;
+    let invoice: Invoice = response.pr.parse()?;
+
+    validate_invoice(&invoice, pay_request, amount)?;
+    Ok(invoice)
+}
+
+fn validate_invoice(
+    invoice: &Invoice,
+    pay_request: &PayRequest,
+    amount: Amount,
+) -> Result<(), LnurlError> {
+    if invoice.amount_milli_satoshis() != Some(amount.msats) {
+        return Err(LnurlError::InvoiceAmountMismatch(amount));
+    }
+
+    let metadata_hash = Sha256Hash::hash(pay_request.metadata.as_bytes());
+    match invoice.description() {
+        InvoiceDescription::Hash(hash) if hash.0 == metadata_hash => Ok(()),
+        _ => Err(LnurlError::DescriptionHashMismatch),
+    }
+}
+
+/// Fetches a JSON object from a LNURL service, turning `{"status": "ERROR"}`
+/// responses into errors
+async fn get_json<T: serde::de::DeserializeOwned>(url: Url) -> Result<T, LnurlError> {
+    let response = fedimint_api::task::timeout(
+        REQUEST_TIMEOUT,
+        reqwest::Client::new().get(url.as_str()).send(),
+    )
+    .await
+    .map_err(|_| LnurlError::Timeout)??;
+    let body: serde_json::Value = response.json().await?;
+
+    if let Ok(error) = serde_json::from_value::<ErrorResponse>(body.clone()) {
+        if error.status.eq_ignore_ascii_case("ERROR") {
+            return Err(LnurlError::ServiceError(error.reason));
+        }
+    }
+    Ok(serde_json::from_value(body)?)
+}
+
+#[derive(Debug, Error)]
+pub enum LnurlError {
+    #[error("Not a lightning address or LNURL: {0}")]
+    InvalidTarget(String),
+    #[error("Invalid bech32 encoding: {0}")]
+    Bech32(#[from] bech32::Error),
+    #[error("Invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("HTTP Error {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Malformed response: {0}")]
+    MalformedResponse(#[from] serde_json::Error),
+    #[error("Timeout")]
+    Timeout,
+    #[error("LNURL service returned an error: {0}")]
+    ServiceError(String),
+    #[error("LNURL is no pay request but {0}")]
+    NotPayRequest(String),
+    #[error("Amount {amount} is outside of the service's range of {min} to {max}")]
+    AmountOutOfRange {
+        amount: Amount,
+        min: Amount,
+        max: Amount,
+    },
+    #[error("Invalid invoice: {0:?}")]
+    InvalidInvoice(#[from] ParseOrSemanticError),
+    #[error("Invoice is not for the requested amount of {0}")]
+    InvoiceAmountMismatch(Amount),
+    #[error("Invoice description hash doesn't commit to the service's metadata")]
+    DescriptionHashMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use bech32::{ToBase32, Variant};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_pay_target() {
+        assert_eq!(
+            parse_pay_target("satoshi@example.com").unwrap().as_str(),
+            "https://example.com/.well-known/lnurlp/satoshi"
+        );
+        assert_eq!(
+            parse_pay_target("lightning:satoshi@example.onion")
+                .unwrap()
+                .as_str(),
+            "http://example.onion/.well-known/lnurlp/satoshi"
+        );
+        assert_eq!(
+            parse_pay_target("lnurlp://example.com/pay?id=1")
+                .unwrap()
+                .as_str(),
+            "https://example.com/pay?id=1"
+        );
+
+        let url = "https://example.com/api?q=3fc3645b439ce8e7f2553a69e5267081";
+        let lnurl = bech32::encode(LNURL_HRP, url.as_bytes().to_base32(), Variant::Bech32)
+            .unwrap()
+            .to_uppercase();
+        assert_eq!(parse_pay_target(&lnurl).unwrap().as_str(), url);
+
+        let not_lnurl = bech32::encode("bc", url.as_bytes().to_base32(), Variant::Bech32).unwrap();
+        assert!(parse_pay_target(&not_lnurl).is_err());
+        assert!(parse_pay_target("@example.com").is_err());
+    }
+
+    #[test]
+    fn test_parse_invoice_or_pay_target() {
+        let invoice = "lnbcrt1u1pslya9jpp58005t06rezrqx2g6e84j44gs0aalcxfc47nzu97040fjzfrl\
+        cmasdq8w3jhxaqxqyjw5qcqp2sp5huz0lzk5v47kfdd58d0k96gm06kr2rkedgr5j8488jaqk44puz6s9qyyssqexyz\
+        s9rzrhu73625ag4ndtw4fqmstrnuaukh3z427la6mn2m2u25zy7j2jfk36pcsz5hl4m07ehcmhvh729424tjagv4lx2\
+        vgdsgy3sqphsc92";
+        let expected = PayTarget::Invoice(invoice.parse().unwrap());
+        assert_eq!(invoice.parse::<PayTarget>().unwrap(), expected);
+        assert_eq!(
+            format!("LIGHTNING:{}", invoice.to_uppercase())
+                .parse::<PayTarget>()
+                .unwrap(),
+            expected
+        );
+
+        assert_eq!(
+            "satoshi@example.com".parse::<PayTarget>().unwrap(),
+            PayTarget::Lnurl(Url::parse("https://example.com/.well-known/lnurlp/satoshi").unwrap())
+        );
+        assert!("lnbcrt1invalid".parse::<PayTarget>().is_err());
+    }
+}
@@ -1,8 +1,12 @@
 // TODO: once user and mint client are merged, make this private again
 pub mod db;
 pub mod incoming;
+pub mod lnurl;
 pub mod outgoing;
+pub mod proof;
+pub mod watchtower;
 
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -10,26 +14,40 @@ use bitcoin_hashes::sha256::Hash as Sha256Hash;
 use fedimint_api::config::FederationId;
 use fedimint_api::core::client::ClientModule;
 use fedimint_api::db::DatabaseTransaction;
+use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::module::TransactionItemAmount;
 use fedimint_api::task::timeout;
-use fedimint_api::Amount;
+use fedimint_api::time::SystemTime;
+use fedimint_api::{Amount, OutPoint};
 use fedimint_core::api::FederationError;
 use futures::StreamExt;
 use lightning_invoice::Invoice;
 use rand::{CryptoRng, RngCore};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use url::Url;
 
-use self::db::ConfirmedInvoiceKey;
-use self::incoming::ConfirmedInvoice;
+use self::db::{
+    ConfirmedInvoiceKey, ConfirmedInvoiceKeyPrefix, ContractEvidenceKey, GatewayReliabilityKey,
+    IncomingClaimKey, OutgoingPaymentRecordKey, OutgoingPaymentRecordKeyPrefix, ReceiveOfferKey,
+    ReceiveOfferKeyPrefix,
+};
+use self::incoming::{ConfirmedInvoice, ReceiveOffer};
+use self::lnurl::LnurlError;
+use self::proof::{ContractDirection, ContractEvidence};
+use self::watchtower::{RegisterRefundPayload, WatchtowerRefund};
 use crate::api::{LnFederationApi, WalletFederationApi};
 use crate::ln::db::{OutgoingPaymentKey, OutgoingPaymentKeyPrefix};
 use crate::ln::incoming::IncomingContractAccount;
-use crate::ln::outgoing::{OutgoingContractAccount, OutgoingContractData};
+use crate::ln::outgoing::{
+    OutgoingContractAccount, OutgoingContractData, OutgoingPaymentRecord, OutgoingPaymentStatus,
+};
+use crate::modules::ln::bolt12::{Bolt12Error, Bolt12Invoice, Bolt12Offer};
 use crate::modules::ln::common::LightningDecoder;
 use crate::modules::ln::config::LightningClientConfig;
-use crate::modules::ln::contracts::incoming::IncomingContractOffer;
-use crate::modules::ln::contracts::outgoing::OutgoingContract;
+use crate::modules::ln::contracts::incoming::{HoldTerms, IncomingContractOffer};
+use crate::modules::ln::contracts::outgoing::{OutgoingContract, OutgoingInvoice};
 use crate::modules::ln::contracts::{
     Contract, ContractId, EncryptedPreimage, FundedContract, IdentifyableContract, Preimage,
 };
@@ -66,12 +84,12 @@ impl ClientModule for LnClient {
                 amount: account_output.amount,
                 fee: self.config.fee_consensus.contract_output,
             },
-            LightningOutput::Offer(_) | LightningOutput::CancelOutgoing { .. } => {
-                TransactionItemAmount {
-                    amount: Amount::ZERO,
-                    fee: Amount::ZERO,
-                }
-            }
+            LightningOutput::Offer(_)
+            | LightningOutput::CancelOutgoing { .. }
+            | LightningOutput::ReleaseIncoming { .. } => TransactionItemAmount {
+                amount: Amount::ZERO,
+                fee: Amount::ZERO,
+            },
         }
     }
 }
@@ -84,25 +102,30 @@ impl LnClient {
     pub async fn create_outgoing_output<'a, 'b>(
         &'a self,
         dbtx: &mut DatabaseTransaction<'b>,
-        invoice: Invoice,
+        invoice: OutgoingInvoice,
         gateway: &LightningGateway,
         timelock: u32,
         mut rng: impl RngCore + CryptoRng + 'a,
     ) -> Result<LightningOutput> {
         let contract_amount = {
-            let invoice_amount_msat = invoice
-                .amount_milli_satoshis()
-                .ok_or(LnClientError::MissingInvoiceAmount)?;
+            let invoice_amount = Amount::from_msats(
+                invoice
+                    .amount_milli_satoshis()
+                    .ok_or(LnClientError::MissingInvoiceAmount)?,
+            );
+            if !gateway.limits.allows_payment(invoice_amount) {
+                return Err(LnClientError::GatewayLimitsViolated(invoice_amount));
+            }
             // TODO: better define fee handling
-            // Add 1% fee margin
-            let contract_amount_msat = invoice_amount_msat + (invoice_amount_msat / 100);
-            Amount::from_msats(contract_amount_msat)
+            // Add the gateway's fee and a 1% margin for LN routing fees
+            let routing_margin = Amount::from_msats(invoice_amount.msats / 100);
+            invoice_amount + gateway.fees.fee(invoice_amount) + routing_margin
         };
 
         let user_sk = bitcoin::KeyPair::new(&self.context.secp, &mut rng);
 
         let contract = OutgoingContract {
-            hash: *invoice.payment_hash(),
+            hash: invoice.payment_hash(),
             gateway_key: gateway.mint_pub_key,
             timelock,
             user_key: user_sk.x_only_public_key().0,
@@ -191,6 +214,28 @@ impl LnClient {
             _ => Err(LnClientError::WrongAccountType),
         }
     }
+
+    /// Fetches the incoming contract with the given id if it was funded
+    /// already. Unlike [`LnClient::get_incoming_contract`] this doesn't wait
+    /// for the contract to appear.
+    pub async fn try_get_incoming_contract(
+        &self,
+        id: ContractId,
+    ) -> Result<Option<IncomingContractAccount>> {
+        match timeout(Duration::from_secs(5), self.context.api.fetch_contract(id)).await {
+            Ok(Ok(account)) => match account.contract {
+                FundedContract::Incoming(c) => Ok(Some(IncomingContractAccount {
+                    amount: account.amount,
+                    contract: c.contract,
+                })),
+                _ => Err(LnClientError::WrongAccountType),
+            },
+            Ok(Err(e)) => Err(LnClientError::ApiError(e)),
+            // The federation keeps answering 404 till the contract gets funded
+            Err(_) => Ok(None),
+        }
+    }
+
     pub async fn refundable_outgoing_contracts(
         &self,
         block_height: u64,
@@ -233,6 +278,7 @@ impl LnClient {
         payment_hash: Sha256Hash,
         payment_secret: Preimage,
         expiry_time: Option<u64>,
+        hold: Option<HoldTerms>,
     ) -> LightningOutput {
         LightningOutput::Offer(IncomingContractOffer {
             amount,
@@ -242,6 +288,7 @@ impl LnClient {
                 &self.config.threshold_pub_key,
             ),
             expiry_time,
+            hold,
         })
     }
 
@@ -255,6 +302,166 @@ impl LnClient {
         .map_err(LnClientError::ApiError)
     }
 
+    /// Resolves a lightning address or LNURL-pay link and fetches an invoice
+    /// for `amount` from the service behind it
+    pub async fn fetch_lnurl_invoice(&self, target: &str, amount: Amount) -> Result<Invoice> {
+        let url = lnurl::parse_pay_target(target)?;
+        let pay_request = lnurl::fetch_pay_request(url).await?;
+        Ok(lnurl::fetch_invoice(&pay_request, amount).await?)
+    }
+
+    /// Asks `gateway` to request an invoice for `offer` from the offer's node
+    /// and checks it is signed by that node and asks for the expected amount.
+    ///
+    /// `amount` is only used if the offer doesn't specify one itself.
+    pub async fn fetch_bolt12_invoice(
+        &self,
+        federation_id: FederationId,
+        gateway: &LightningGateway,
+        offer: &Bolt12Offer,
+        amount: Option<Amount>,
+    ) -> Result<Bolt12Invoice> {
+        let amount = offer
+            .amount_msats()
+            .map(Amount::from_msats)
+            .or(amount)
+            .ok_or(LnClientError::MissingInvoiceAmount)?;
+        let payload = FetchOfferInvoicePayload {
+            federation_id,
+            offer: offer.clone(),
+            amount: Some(amount),
+        };
+        let response: FetchOfferInvoiceResponse = self
+            .post_gateway(gateway, "fetch_invoice", &payload)
+            .await?;
+        let invoice = response.invoice;
+
+        if !invoice.is_for_offer(offer) {
+            return Err(LnClientError::Bolt12InvoiceMismatch);
+        }
+        if invoice.amount_msats() != amount.msats {
+            return Err(LnClientError::Bolt12AmountMismatch(amount));
+        }
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        if invoice.expires_at() <= now {
+            return Err(LnClientError::Bolt12InvoiceExpired);
+        }
+        Ok(invoice)
+    }
+
+    /// Asks `gateway` for a reusable offer whose payments it deposits into an
+    /// account contract only we can spend
+    pub async fn create_receive_offer(
+        &self,
+        federation_id: FederationId,
+        gateway: &LightningGateway,
+        description: String,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> Result<ReceiveOffer> {
+        let keypair = bitcoin::KeyPair::new(&self.context.secp, &mut rng);
+        let payload = CreateOfferPayload {
+            federation_id,
+            description,
+            account_key: keypair.x_only_public_key().0,
+        };
+        let response: CreateOfferResponse =
+            self.post_gateway(gateway, "create_offer", &payload).await?;
+        let receive_offer = ReceiveOffer {
+            offer: response.offer,
+            keypair,
+        };
+
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(
+            &ReceiveOfferKey(receive_offer.contract_id()),
+            &receive_offer,
+        )
+        .await
+        .expect("Db error");
+        dbtx.commit_tx().await.expect("DB Error");
+        Ok(receive_offer)
+    }
+
+    pub async fn list_receive_offers(&self) -> Vec<ReceiveOffer> {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .find_by_prefix(&ReceiveOfferKeyPrefix)
+            .await
+            .map(|res| res.expect("Db error").1)
+            .collect()
+            .await
+    }
+
+    /// Creates inputs spending everything paid to our receive offers so far
+    pub async fn create_receive_offer_inputs(&self) -> Vec<(bitcoin::KeyPair, LightningInput)> {
+        let mut inputs = vec![];
+        for receive_offer in self.list_receive_offers().await {
+            let contract_id = receive_offer.contract_id();
+            // Accounts only exist once the gateway forwarded the first payment
+            let account = match self.get_contract_account(contract_id).await {
+                Ok(account) if account.amount != Amount::ZERO => account,
+                _ => continue,
+            };
+            inputs.push((
+                receive_offer.keypair,
+                LightningInput {
+                    contract_id,
+                    amount: account.amount,
+                    witness: None,
+                },
+            ));
+        }
+        inputs
+    }
+
+    async fn post_gateway<T: DeserializeOwned>(
+        &self,
+        gateway: &LightningGateway,
+        endpoint: &str,
+        payload: &impl Serialize,
+    ) -> Result<T> {
+        let url = gateway
+            .api
+            .join(endpoint)
+            .expect("Endpoints contain no invalid characters for a URL");
+        let response = post_json(url, payload).await?;
+
+        if !response.status().is_success() {
+            return Err(LnClientError::GatewayError(response.text().await?));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Hands `refund` to the watchtower at `watchtower`
+    pub async fn register_watchtower_refund(
+        &self,
+        watchtower: &Url,
+        federation_id: FederationId,
+        refund: WatchtowerRefund,
+    ) -> Result<()> {
+        let url = watchtower
+            .join("register_refund")
+            .expect("'register_refund' contains no invalid characters for a URL");
+        let response = post_json(
+            url,
+            &RegisterRefundPayload {
+                federation_id,
+                refund,
+            },
+        )
+        .await?;
+
+        if !response.status().is_success() {
+            return Err(LnClientError::WatchtowerError(response.text().await?));
+        }
+        Ok(())
+    }
+
     pub async fn offer_exists(&self, payment_hash: Sha256Hash) -> Result<bool> {
         self.context
             .api
@@ -284,6 +491,123 @@ impl LnClient {
         Ok(confirmed_invoice)
     }
 
+    /// Confirmed invoices whose incoming contract we haven't claimed yet,
+    /// e.g. because they got paid while we were offline
+    pub async fn list_unclaimed_invoices(&self) -> Vec<ConfirmedInvoice> {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        let invoices = dbtx
+            .find_by_prefix(&ConfirmedInvoiceKeyPrefix)
+            .await
+            .map(|res| res.expect("Db error").1)
+            .collect::<Vec<ConfirmedInvoice>>()
+            .await;
+
+        let mut unclaimed = vec![];
+        for invoice in invoices {
+            let claim = dbtx
+                .get_value(&IncomingClaimKey(invoice.contract_id()))
+                .await
+                .expect("Db error");
+            if claim.is_none() {
+                unclaimed.push(invoice);
+            }
+        }
+        unclaimed
+    }
+
+    /// Remembers that the incoming contract `contract_id` was claimed in
+    /// `out_point` so we don't try to claim it again
+    pub async fn save_incoming_claim(&self, contract_id: ContractId, out_point: OutPoint) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(&IncomingClaimKey(contract_id), &out_point)
+            .await
+            .expect("Db error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    /// Moves the outgoing payment of `contract_id` to `status`, creating its
+    /// history entry if it doesn't exist yet
+    pub async fn save_outgoing_payment_status(
+        &self,
+        contract_id: ContractId,
+        amount: Amount,
+        status: OutgoingPaymentStatus,
+    ) -> OutgoingPaymentRecord {
+        let updated_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        let record = OutgoingPaymentRecord {
+            contract_id,
+            amount,
+            status,
+            updated_at,
+        };
+
+        let mut dbtx = self.context.db.begin_transaction().await;
+        dbtx.insert_entry(&OutgoingPaymentRecordKey(contract_id), &record)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+        record
+    }
+
+    pub async fn get_outgoing_payment_record(
+        &self,
+        contract_id: ContractId,
+    ) -> Option<OutgoingPaymentRecord> {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&OutgoingPaymentRecordKey(contract_id))
+            .await
+            .expect("DB error")
+    }
+
+    /// Our outgoing payments, most recently updated first
+    pub async fn list_outgoing_payment_records(&self) -> Vec<OutgoingPaymentRecord> {
+        let mut records = self
+            .context
+            .db
+            .begin_transaction()
+            .await
+            .find_by_prefix(&OutgoingPaymentRecordKeyPrefix)
+            .await
+            .map(|res| res.expect("DB error").1)
+            .collect::<Vec<_>>()
+            .await;
+        records.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        records
+    }
+
+    /// Adds `evidence` to what we already know about the settlement of its
+    /// contract
+    pub async fn save_contract_evidence(&self, evidence: ContractEvidence) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        let key = ContractEvidenceKey(evidence.contract_id);
+        let evidence = match dbtx.get_value(&key).await.expect("DB error") {
+            Some(mut known) => {
+                known.merge(evidence);
+                known
+            }
+            None => evidence,
+        };
+        dbtx.insert_entry(&key, &evidence).await.expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    pub async fn get_contract_evidence(&self, contract_id: ContractId) -> Result<ContractEvidence> {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&ContractEvidenceKey(contract_id))
+            .await
+            .expect("DB error")
+            .ok_or(LnClientError::NoContractEvidence(contract_id))
+    }
+
     /// Used by gateway to prematurely return funds to the user if the payment
     /// failed
     pub fn create_cancel_outgoing_output(
@@ -296,6 +620,161 @@ impl LnClient {
             gateway_signature: signature,
         }
     }
+
+    /// Used by the recipient of a held payment to let the federation decrypt
+    /// the preimage for the gateway
+    pub fn create_release_incoming_output(
+        &self,
+        contract_id: ContractId,
+        signature: secp256k1_zkp::schnorr::Signature,
+    ) -> LightningOutput {
+        LightningOutput::ReleaseIncoming {
+            contract: contract_id,
+            release_signature: signature,
+        }
+    }
+
+    /// Picks the gateway to route a payment of `invoice_amount` through
+    /// according to `selection`. Expired announcements, gateways whose limits
+    /// or advertised liquidity don't allow the payment and the gateways in
+    /// `excluded` are skipped.
+    pub async fn select_gateway(
+        &self,
+        gateways: Vec<LightningGateway>,
+        invoice_amount: Amount,
+        selection: &GatewaySelection,
+        excluded: &[secp256k1::PublicKey],
+    ) -> Result<LightningGateway> {
+        let now = SystemTime::now();
+        let mut candidates = gateways.into_iter().filter(|gw| {
+            gw.valid_until > now
+                && gw.limits.allows_payment(invoice_amount)
+                && gw.liquidity.can_send(invoice_amount)
+                && !excluded.contains(&gw.node_pub_key)
+        });
+
+        let gateway = match selection {
+            GatewaySelection::Pinned(node_pub_key) => {
+                candidates.find(|gw| gw.node_pub_key == *node_pub_key)
+            }
+            GatewaySelection::LowestFee => candidates.min_by_key(|gw| gw.fees.fee(invoice_amount)),
+            GatewaySelection::BestReliability => {
+                let mut scored = vec![];
+                for gw in candidates {
+                    let score = self.gateway_reliability(gw.node_pub_key).await.score();
+                    scored.push((score, gw));
+                }
+                // Prefer the cheaper gateway among equally reliable ones
+                scored
+                    .into_iter()
+                    .max_by(|(score_a, gw_a), (score_b, gw_b)| {
+                        score_a.total_cmp(score_b).then_with(|| {
+                            gw_b.fees
+                                .fee(invoice_amount)
+                                .cmp(&gw_a.fees.fee(invoice_amount))
+                        })
+                    })
+                    .map(|(_, gw)| gw)
+            }
+        };
+
+        gateway.ok_or(LnClientError::NoSuitableGateway)
+    }
+
+    /// Returns the track record of payments we routed through the gateway
+    /// with the given node public key
+    pub async fn gateway_reliability(
+        &self,
+        node_pub_key: secp256k1::PublicKey,
+    ) -> GatewayReliability {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&GatewayReliabilityKey(node_pub_key))
+            .await
+            .expect("DB error")
+            .unwrap_or_default()
+    }
+
+    /// Records whether a payment routed through the gateway with the given
+    /// node public key succeeded
+    pub async fn record_gateway_outcome(&self, node_pub_key: secp256k1::PublicKey, success: bool) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        let mut reliability = dbtx
+            .get_value(&GatewayReliabilityKey(node_pub_key))
+            .await
+            .expect("DB error")
+            .unwrap_or_default();
+        if success {
+            reliability.succeeded += 1;
+        } else {
+            reliability.failed += 1;
+        }
+        dbtx.insert_entry(&GatewayReliabilityKey(node_pub_key), &reliability)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+}
+
+/// Policy by which a client picks the gateway for an outgoing payment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GatewaySelection {
+    /// The gateway charging the lowest fee for the payment
+    LowestFee,
+    /// The gateway that most reliably routed our previous payments
+    BestReliability,
+    /// Only the gateway with the given node public key
+    Pinned(secp256k1::PublicKey),
+}
+
+impl FromStr for GatewaySelection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "lowest-fee" => Ok(GatewaySelection::LowestFee),
+            "best-reliability" => Ok(GatewaySelection::BestReliability),
+            node_pub_key => Ok(GatewaySelection::Pinned(node_pub_key.parse().map_err(
+                |e| anyhow::anyhow!("Expected lowest-fee, best-reliability or a node pubkey: {e}"),
+            )?)),
+        }
+    }
+}
+
+/// Outcomes of the payments we routed through a gateway
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable,
+)]
+pub struct GatewayReliability {
+    pub succeeded: u64,
+    pub failed: u64,
+}
+
+impl GatewayReliability {
+    /// Estimated probability of the next payment succeeding, gateways we
+    /// haven't used yet score 0.5
+    pub fn score(&self) -> f64 {
+        (self.succeeded as f64 + 1.0) / ((self.succeeded + self.failed) as f64 + 2.0)
+    }
+}
+
+/// Payment a gateway routed for a federation user, or failed to route
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct GatewayPayment {
+    /// Outgoing contract of the user, or incoming or account contract the
+    /// gateway funded
+    pub contract_id: ContractId,
+    pub direction: ContractDirection,
+    /// Amount paid over or received from the lightning network
+    pub amount: Amount,
+    /// Fee the gateway charged, zero if the payment failed
+    pub fee: Amount,
+    /// Why the payment failed, `None` if it succeeded
+    pub error: Option<String>,
+    /// Unix timestamp in seconds at which the payment finished
+    pub finished_at: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -313,6 +792,34 @@ impl PayInvoicePayload {
     }
 }
 
+/// Asks the gateway to fetch an invoice for a BOLT12 offer from its node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchOfferInvoicePayload {
+    pub federation_id: FederationId,
+    pub offer: Bolt12Offer,
+    /// Amount to request if the offer doesn't specify one
+    pub amount: Option<Amount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchOfferInvoiceResponse {
+    pub invoice: Bolt12Invoice,
+}
+
+/// Asks the gateway for a reusable BOLT12 offer whose payments are deposited
+/// into an account contract locked to `account_key`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateOfferPayload {
+    pub federation_id: FederationId,
+    pub description: String,
+    pub account_key: secp256k1::XOnlyPublicKey,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateOfferResponse {
+    pub offer: Bolt12Offer,
+}
+
 pub type Result<T> = std::result::Result<T, LnClientError>;
 
 #[derive(Debug, Error)]
@@ -327,12 +834,46 @@ pub enum LnClientError {
     WrongAccountType,
     #[error("No ConfirmedOffer found for contract ID {0}")]
     NoConfirmedInvoice(ContractId),
+    #[error("No settlement evidence known for contract ID {0}")]
+    NoContractEvidence(ContractId),
+    #[error("Gateway does not route payments of {0}")]
+    GatewayLimitsViolated(Amount),
+    #[error("No registered gateway matches the selection policy")]
+    NoSuitableGateway,
+    #[error("LNURL error: {0}")]
+    Lnurl(#[from] LnurlError),
+    #[error("HTTP error: {0}")]
+    HttpError(#[from] reqwest::Error),
+    #[error("Gateway returned an error: {0}")]
+    GatewayError(String),
+    #[error("Watchtower returned an error: {0}")]
+    WatchtowerError(String),
+    #[error("Invalid BOLT12 data: {0}")]
+    Bolt12(#[from] Bolt12Error),
+    #[error("Invoice wasn't issued for the offer by the offer's node")]
+    Bolt12InvoiceMismatch,
+    #[error("Invoice is not for the expected amount of {0}")]
+    Bolt12AmountMismatch(Amount),
+    #[error("Invoice has already expired")]
+    Bolt12InvoiceExpired,
+}
+
+async fn post_json(url: Url, payload: &impl Serialize) -> Result<reqwest::Response> {
+    Ok(timeout(
+        Duration::from_secs(60),
+        reqwest::Client::new()
+            .post(url.as_str())
+            .json(payload)
+            .send(),
+    )
+    .await
+    .map_err(|_e| LnClientError::Timeout)??)
 }
 
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
-    use std::time::SystemTime;
+    use std::time::{Duration, SystemTime};
 
     use bitcoin::hashes::{sha256, Hash};
     use fedimint_api::config::ConfigGenParams;
@@ -348,11 +889,16 @@ mod tests {
     use url::Url;
 
     use crate::api::fake::FederationApiFaker;
-    use crate::ln::LnClient;
+    use crate::ln::incoming::ConfirmedInvoice;
+    use crate::ln::outgoing::OutgoingPaymentStatus;
+    use crate::ln::{GatewaySelection, LnClient};
     use crate::modules::ln::common::LightningDecoder;
     use crate::modules::ln::config::LightningClientConfig;
     use crate::modules::ln::contracts::{ContractId, IdentifyableContract};
-    use crate::modules::ln::{Lightning, LightningGateway, LightningGen, LightningOutput};
+    use crate::modules::ln::{
+        GatewayFees, GatewayLimits, GatewayLiquidity, Lightning, LightningGateway, LightningGen,
+        LightningOutput,
+    };
     use crate::{module_decode_stubs, ClientContext};
 
     type Fed = FakeFed<Lightning>;
@@ -439,6 +985,7 @@ mod tests {
             db: Database::new(MemDatabase::new(), module_decode_stubs()),
             api: api.into(),
             secp: secp256k1_zkp::Secp256k1::new(),
+            extensions: Default::default(),
         };
 
         (fed, client_config.cast().unwrap(), client_context)
@@ -479,6 +1026,12 @@ mod tests {
                 api: Url::parse("http://example.com")
                     .expect("Could not parse URL to generate GatewayClientConfig API endpoint"),
                 route_hints: vec![],
+                fees: GatewayFees {
+                    base_msat: 1000,
+                    proportional_millionths: 10_000,
+                },
+                limits: GatewayLimits::default(),
+                liquidity: GatewayLiquidity::default(),
                 valid_until: SystemTime::now(),
             }
         };
@@ -486,7 +1039,13 @@ mod tests {
 
         let mut dbtx = client.context.db.begin_transaction().await;
         let output = client
-            .create_outgoing_output(&mut dbtx, invoice.clone(), &gateway, timelock, &mut rng)
+            .create_outgoing_output(
+                &mut dbtx,
+                invoice.clone().into(),
+                &gateway,
+                timelock,
+                &mut rng,
+            )
             .await
             .unwrap();
 
@@ -508,13 +1067,15 @@ mod tests {
             .unwrap();
 
         assert_eq!(contract_acc.contract.contract_id(), contract.contract_id());
-        assert_eq!(contract_acc.contract.invoice, invoice);
+        assert_eq!(contract_acc.contract.invoice, invoice.clone().into());
         assert_eq!(contract_acc.contract.timelock, timelock);
         assert_eq!(contract_acc.contract.hash, *invoice.payment_hash());
         assert_eq!(contract_acc.contract.gateway_key, gateway.mint_pub_key);
         // TODO: test that the client has its key
 
-        let expected_amount_msat = invoice_amt_msat + (invoice_amt_msat / 100);
+        // Invoice amount plus the gateway fee plus the 1% LN routing fee margin
+        let expected_amount_msat =
+            invoice_amt_msat + (1000 + invoice_amt_msat / 100) + (invoice_amt_msat / 100);
         let expected_amount = Amount::from_msats(expected_amount_msat);
         assert_eq!(contract_acc.amount, expected_amount);
 
@@ -550,4 +1111,196 @@ mod tests {
             .unwrap();
         assert_eq!(account.amount, Amount::ZERO);
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_unclaimed_invoices() {
+        let mut rng = rand::thread_rng();
+        let (_fed, client_config, client_context) = new_mint_and_client().await;
+
+        let client = LnClient {
+            config: client_config,
+            context: Arc::new(client_context),
+        };
+
+        let invoice: Invoice =
+            "lnbcrt1u1pslya9jpp58005t06rezrqx2g6e84j44gs0aalcxfc47nzu97040fjzfrl\
+        cmasdq8w3jhxaqxqyjw5qcqp2sp5huz0lzk5v47kfdd58d0k96gm06kr2rkedgr5j8488jaqk44puz6s9qyyssqexyz\
+        s9rzrhu73625ag4ndtw4fqmstrnuaukh3z427la6mn2m2u25zy7j2jfk36pcsz5hl4m07ehcmhvh729424tjagv4lx2\
+        vgdsgy3sqphsc92"
+                .parse()
+                .unwrap();
+        let confirmed = ConfirmedInvoice {
+            invoice,
+            keypair: bitcoin::KeyPair::new(&client.context.secp, &mut rng),
+            release_key: None,
+        };
+        let contract_id = confirmed.contract_id();
+        client.save_confirmed_invoice(&confirmed).await;
+
+        // Invoices stay pending till we claimed their contract …
+        let unclaimed = client.list_unclaimed_invoices().await;
+        assert_eq!(unclaimed.len(), 1);
+        assert_eq!(unclaimed[0].contract_id(), contract_id);
+
+        // … after that they are never claimed again
+        let out_point = OutPoint {
+            txid: sha256::Hash::hash(b"claim").into(),
+            out_idx: 0,
+        };
+        client.save_incoming_claim(contract_id, out_point).await;
+        assert!(client.list_unclaimed_invoices().await.is_empty());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_outgoing_payment_records() {
+        let (_fed, client_config, client_context) = new_mint_and_client().await;
+
+        let client = LnClient {
+            config: client_config,
+            context: Arc::new(client_context),
+        };
+
+        let contract_id: ContractId = sha256::Hash::hash(b"contract").into();
+        let amount = Amount::from_sats(1000);
+        client
+            .save_outgoing_payment_status(contract_id, amount, OutgoingPaymentStatus::Pending)
+            .await;
+
+        // Status changes replace the payment's record instead of adding one
+        let txid = sha256::Hash::hash(b"refund").into();
+        client
+            .save_outgoing_payment_status(
+                contract_id,
+                amount,
+                OutgoingPaymentStatus::Refunding { txid },
+            )
+            .await;
+        let records = client.list_outgoing_payment_records().await;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].amount, amount);
+        assert_eq!(records[0].status, OutgoingPaymentStatus::Refunding { txid });
+        assert!(!records[0].status.is_finished());
+
+        client
+            .save_outgoing_payment_status(
+                contract_id,
+                amount,
+                OutgoingPaymentStatus::Refunded { txid },
+            )
+            .await;
+        let record = client
+            .get_outgoing_payment_record(contract_id)
+            .await
+            .unwrap();
+        assert!(record.status.is_finished());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_gateway_selection() {
+        let mut rng = rand::thread_rng();
+        let (_fed, client_config, client_context) = new_mint_and_client().await;
+
+        let client = LnClient {
+            config: client_config,
+            context: Arc::new(client_context),
+        };
+
+        let secp = secp256k1_zkp::Secp256k1::new();
+        let mut make_gateway = |base_msat, max_payment| LightningGateway {
+            mint_channel_id: 0,
+            mint_pub_key: secp.generate_keypair(&mut rng).1.x_only_public_key().0,
+            node_pub_key: secp.generate_keypair(&mut rng).1,
+            api: Url::parse("http://example.com").unwrap(),
+            route_hints: vec![],
+            fees: GatewayFees {
+                base_msat,
+                proportional_millionths: 0,
+            },
+            limits: GatewayLimits {
+                max_payment,
+                ..GatewayLimits::default()
+            },
+            liquidity: GatewayLiquidity::default(),
+            valid_until: SystemTime::now() + Duration::from_secs(600),
+        };
+        let cheap = make_gateway(1_000, None);
+        let expensive = make_gateway(5_000, None);
+        let too_small = make_gateway(0, Some(Amount::from_msats(1_000)));
+        let gateways = vec![expensive.clone(), cheap.clone(), too_small.clone()];
+        let amount = Amount::from_msats(100_000);
+
+        // The cheapest gateway whose limits allow the payment wins
+        let selected = client
+            .select_gateway(gateways.clone(), amount, &GatewaySelection::LowestFee, &[])
+            .await
+            .unwrap();
+        assert_eq!(selected, cheap);
+
+        // Gateways without enough outbound liquidity are skipped
+        let drained = LightningGateway {
+            liquidity: GatewayLiquidity::from_balances(Amount::ZERO, Amount::from_msats(1_000)),
+            ..cheap.clone()
+        };
+        let selected = client
+            .select_gateway(
+                vec![drained, expensive.clone()],
+                amount,
+                &GatewaySelection::LowestFee,
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(selected, expensive);
+
+        // Failed gateways are skipped when retrying
+        let selected = client
+            .select_gateway(
+                gateways.clone(),
+                amount,
+                &GatewaySelection::LowestFee,
+                &[cheap.node_pub_key],
+            )
+            .await
+            .unwrap();
+        assert_eq!(selected, expensive);
+
+        // The gateway with the better payment history wins regardless of fees
+        client
+            .record_gateway_outcome(cheap.node_pub_key, false)
+            .await;
+        client
+            .record_gateway_outcome(expensive.node_pub_key, true)
+            .await;
+        let selected = client
+            .select_gateway(
+                gateways.clone(),
+                amount,
+                &GatewaySelection::BestReliability,
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(selected, expensive);
+
+        // A pinned gateway is only selected if it can route the payment
+        let selected = client
+            .select_gateway(
+                gateways.clone(),
+                amount,
+                &GatewaySelection::Pinned(cheap.node_pub_key),
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(selected, cheap);
+        assert!(client
+            .select_gateway(
+                gateways,
+                amount,
+                &GatewaySelection::Pinned(too_small.node_pub_key),
+                &[],
+            )
+            .await
+            .is_err());
+    }
 }
@@ -1,8 +1,10 @@
 use fedimint_api::encoding::{Decodable, Encodable};
-use fedimint_api::Amount;
+use fedimint_api::{Amount, TransactionId};
 use serde::Serialize;
 
-use crate::modules::ln::contracts::{outgoing::OutgoingContract, IdentifyableContract, Preimage};
+use crate::modules::ln::contracts::{
+    outgoing::OutgoingContract, ContractId, IdentifyableContract, Preimage,
+};
 use crate::modules::ln::LightningInput;
 
 #[derive(Debug, Encodable, Decodable, Serialize)]
@@ -34,3 +36,62 @@ impl OutgoingContractAccount {
         }
     }
 }
+
+/// Parts our lightning node sent to pay the invoice of an outgoing contract.
+/// A multi-part payment can still settle after some of its parts failed, so
+/// the contract may only be refunded once no part is in flight anymore.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encodable, Decodable, Serialize)]
+pub struct OutgoingPaymentParts {
+    /// Amounts of the parts still in flight
+    pub pending: Vec<Amount>,
+    /// Amounts of the parts that settled
+    pub settled: Vec<Amount>,
+    /// Amounts of the parts that failed
+    pub failed: Vec<Amount>,
+}
+
+impl OutgoingPaymentParts {
+    /// Amount still locked up in HTLCs of our node
+    pub fn in_flight(&self) -> Amount {
+        self.pending.iter().copied().sum()
+    }
+
+    /// Whether all parts either settled or failed
+    pub fn is_resolved(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Where an outgoing payment of ours stands. Payments start out `Pending` and
+/// either become `Paid` once the gateway claimed the contract, or go through
+/// `Refunding` to `Refunded` if the gateway cancelled the contract or let it
+/// time out.
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize)]
+pub enum OutgoingPaymentStatus {
+    /// The contract is funded and waits for the gateway to pay the invoice
+    Pending,
+    /// The gateway paid the invoice and claimed the contract
+    Paid,
+    /// We submitted a transaction claiming the contract back
+    Refunding { txid: TransactionId },
+    /// The federation accepted our refund
+    Refunded { txid: TransactionId },
+}
+
+impl OutgoingPaymentStatus {
+    /// Whether the payment reached a final state
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Paid | Self::Refunded { .. })
+    }
+}
+
+/// Entry of our outgoing payment history
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize)]
+pub struct OutgoingPaymentRecord {
+    pub contract_id: ContractId,
+    /// Amount locked in the contract, including the gateway's fee
+    pub amount: Amount,
+    pub status: OutgoingPaymentStatus,
+    /// Unix timestamp in seconds of the last status change
+    pub updated_at: u64,
+}
@@ -0,0 +1,322 @@
+//! Evidence about the settlement of lightning contracts that can be handed to
+//! a third party to resolve disputes between users and gateways.
+//!
+//! A [`ContractProof`] bundles the invoice and preimage of a contract with the
+//! threshold signed epochs in which the federation accepted the transactions
+//! funding and claiming it. Anyone knowing the federation's epoch public key
+//! can check it without having to trust either party.
+
+use std::collections::BTreeMap;
+
+use bitcoin_hashes::sha256;
+use bitcoin_hashes::Hash;
+use fedimint_api::core::LEGACY_HARDCODED_INSTANCE_ID_LN;
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::TransactionId;
+use fedimint_core::epoch::{ConsensusItem, SerdeEpochHistory};
+use fedimint_core::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use threshold_crypto::PublicKey;
+
+use crate::modules::ln::contracts::outgoing::OutgoingInvoice;
+use crate::modules::ln::contracts::{Contract, ContractId, IdentifyableContract, Preimage};
+use crate::modules::ln::{ContractOutput, LightningInput, LightningOutput};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractDirection {
+    /// Contract funded by a user to have a gateway pay an invoice
+    Outgoing,
+    /// Contract funded by a gateway to buy the preimage of a user's invoice
+    Incoming,
+}
+
+/// Everything we learned about a contract that is needed to prove it was
+/// settled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct ContractEvidence {
+    pub contract_id: ContractId,
+    pub direction: ContractDirection,
+    pub payment_hash: sha256::Hash,
+    /// Invoice that was paid, unknown to gateways for incoming contracts
+    pub invoice: Option<OutgoingInvoice>,
+    /// Preimage of `payment_hash`, only known once the payment succeeded
+    pub preimage: Option<Preimage>,
+    /// Accepted transactions funding, releasing or claiming the contract
+    pub transactions: Vec<TransactionId>,
+}
+
+impl ContractEvidence {
+    pub fn new(
+        contract_id: ContractId,
+        direction: ContractDirection,
+        payment_hash: sha256::Hash,
+    ) -> Self {
+        ContractEvidence {
+            contract_id,
+            direction,
+            payment_hash,
+            invoice: None,
+            preimage: None,
+            transactions: vec![],
+        }
+    }
+
+    pub fn with_invoice(mut self, invoice: OutgoingInvoice) -> Self {
+        self.invoice = Some(invoice);
+        self
+    }
+
+    pub fn with_preimage(mut self, preimage: Preimage) -> Self {
+        self.preimage = Some(preimage);
+        self
+    }
+
+    pub fn with_transaction(mut self, txid: TransactionId) -> Self {
+        self.transactions.push(txid);
+        self
+    }
+
+    /// Whether the preimage proving the payment succeeded is known
+    pub fn is_settled(&self) -> bool {
+        self.preimage.is_some()
+    }
+
+    /// Adds what `other` learned about the same contract, ignoring preimages
+    /// that don't match the payment hash
+    pub fn merge(&mut self, other: ContractEvidence) {
+        debug_assert_eq!(self.contract_id, other.contract_id);
+
+        if self.invoice.is_none() {
+            self.invoice = other.invoice;
+        }
+        if let Some(preimage) = other.preimage {
+            if preimage_hash(&preimage) == self.payment_hash {
+                self.preimage = Some(preimage);
+            }
+        }
+        for txid in other.transactions {
+            if !self.transactions.contains(&txid) {
+                self.transactions.push(txid);
+            }
+        }
+    }
+}
+
+/// [`ContractEvidence`] together with the signed epochs it refers to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractProof {
+    pub evidence: ContractEvidence,
+    pub epochs: Vec<SerdeEpochHistory>,
+}
+
+impl ContractProof {
+    /// Checks that the invoice and preimage match the contract and that the
+    /// federation signed off on all the contract's transactions
+    pub fn verify(
+        &self,
+        epoch_pk: &PublicKey,
+        decoders: &ModuleDecoderRegistry,
+    ) -> Result<(), ContractProofError> {
+        let evidence = &self.evidence;
+
+        if let Some(preimage) = &evidence.preimage {
+            if preimage_hash(preimage) != evidence.payment_hash {
+                return Err(ContractProofError::PreimageMismatch);
+            }
+        }
+        if let Some(invoice) = &evidence.invoice {
+            if invoice.payment_hash() != evidence.payment_hash {
+                return Err(ContractProofError::InvoiceMismatch);
+            }
+        }
+
+        let mut accepted = BTreeMap::new();
+        for epoch in &self.epochs {
+            let epoch = epoch
+                .try_into_inner(decoders)
+                .map_err(|e| ContractProofError::InvalidEpoch(e.to_string()))?;
+            let epoch_number = epoch.outcome.epoch;
+            if !epoch.has_valid_hash() {
+                return Err(ContractProofError::InvalidEpochHash(epoch_number));
+            }
+            epoch
+                .verify_sig(epoch_pk)
+                .map_err(|_| ContractProofError::InvalidEpochSignature(epoch_number))?;
+
+            for (_, items) in epoch.outcome.items {
+                for item in items {
+                    if let ConsensusItem::Transaction(tx) = item {
+                        let txid = tx.tx_hash();
+                        if !epoch.outcome.rejected_txs.contains(&txid) {
+                            accepted.insert(txid, tx);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut bound_to_payment_hash = evidence.direction == ContractDirection::Incoming
+            && evidence.contract_id == ContractId::from_hash(evidence.payment_hash);
+        for txid in &evidence.transactions {
+            let tx = accepted
+                .get(txid)
+                .ok_or(ContractProofError::TransactionNotAccepted(*txid))?;
+            match touches_contract(tx, evidence) {
+                ContractUsage::Unrelated => {
+                    return Err(ContractProofError::UnrelatedTransaction(*txid))
+                }
+                ContractUsage::Bound => bound_to_payment_hash = true,
+                ContractUsage::Referenced => {}
+            }
+        }
+
+        if !bound_to_payment_hash {
+            return Err(ContractProofError::UnboundPaymentHash);
+        }
+
+        Ok(())
+    }
+}
+
+/// How a transaction relates to the contract of some evidence
+enum ContractUsage {
+    Unrelated,
+    /// Spends or updates the contract
+    Referenced,
+    /// Proves that the contract is locked to the evidence's payment hash
+    Bound,
+}
+
+fn touches_contract(tx: &Transaction, evidence: &ContractEvidence) -> ContractUsage {
+    let mut usage = ContractUsage::Unrelated;
+
+    for input in &tx.inputs {
+        if input.module_instance_id() != LEGACY_HARDCODED_INSTANCE_ID_LN {
+            continue;
+        }
+        let input = match input.as_any().downcast_ref::<LightningInput>() {
+            Some(input) if input.contract_id == evidence.contract_id => input,
+            _ => continue,
+        };
+        // The federation only accepts claims revealing the contract's preimage
+        match &input.witness {
+            Some(preimage) if preimage_hash(preimage) == evidence.payment_hash => {
+                return ContractUsage::Bound
+            }
+            _ => usage = ContractUsage::Referenced,
+        }
+    }
+
+    for output in &tx.outputs {
+        if output.module_instance_id() != LEGACY_HARDCODED_INSTANCE_ID_LN {
+            continue;
+        }
+        match output.as_any().downcast_ref::<LightningOutput>() {
+            Some(LightningOutput::Contract(ContractOutput { contract, .. }))
+                if contract.contract_id() == evidence.contract_id =>
+            {
+                let hash = match contract {
+                    Contract::Incoming(contract) => Some(contract.hash),
+                    Contract::Outgoing(contract) => Some(contract.hash),
+                    Contract::Account(_) => None,
+                };
+                if hash == Some(evidence.payment_hash) {
+                    return ContractUsage::Bound;
+                }
+                usage = ContractUsage::Referenced;
+            }
+            Some(LightningOutput::Offer(offer)) if offer.hash == evidence.payment_hash => {
+                usage = ContractUsage::Referenced;
+            }
+            Some(LightningOutput::CancelOutgoing { contract, .. })
+            | Some(LightningOutput::ReleaseIncoming { contract, .. })
+                if *contract == evidence.contract_id =>
+            {
+                usage = ContractUsage::Referenced;
+            }
+            _ => {}
+        }
+    }
+
+    usage
+}
+
+fn preimage_hash(preimage: &Preimage) -> sha256::Hash {
+    sha256::Hash::hash(&preimage.0)
+}
+
+#[derive(Debug, Error)]
+pub enum ContractProofError {
+    #[error("Preimage doesn't match the payment hash")]
+    PreimageMismatch,
+    #[error("Invoice isn't for the payment hash")]
+    InvoiceMismatch,
+    #[error("Epoch can't be decoded: {0}")]
+    InvalidEpoch(String),
+    #[error("Hash of epoch {0} doesn't match its contents")]
+    InvalidEpochHash(u64),
+    #[error("Epoch {0} isn't signed by the federation")]
+    InvalidEpochSignature(u64),
+    #[error("Transaction {0} wasn't accepted in any of the epochs")]
+    TransactionNotAccepted(TransactionId),
+    #[error("Transaction {0} doesn't touch the contract")]
+    UnrelatedTransaction(TransactionId),
+    #[error("No transaction ties the contract to the payment hash")]
+    UnboundPaymentHash,
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::{sha256, Hash};
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+
+    use super::{ContractDirection, ContractEvidence, ContractProof, ContractProofError};
+    use crate::modules::ln::contracts::{ContractId, Preimage};
+
+    fn incoming_evidence() -> (ContractEvidence, Preimage) {
+        let preimage = Preimage([42; 32]);
+        let payment_hash = sha256::Hash::hash(&preimage.0);
+        let evidence = ContractEvidence::new(
+            ContractId::from_hash(payment_hash),
+            ContractDirection::Incoming,
+            payment_hash,
+        );
+        (evidence, preimage)
+    }
+
+    #[test]
+    fn merge_ignores_wrong_preimage() {
+        let (mut evidence, preimage) = incoming_evidence();
+
+        evidence.merge(evidence.clone().with_preimage(Preimage([0; 32])));
+        assert!(!evidence.is_settled());
+
+        evidence.merge(evidence.clone().with_preimage(preimage.clone()));
+        assert_eq!(evidence.preimage, Some(preimage));
+    }
+
+    #[test]
+    fn verify_rejects_wrong_preimage() {
+        let (evidence, _) = incoming_evidence();
+        let epoch_pk = threshold_crypto::SecretKey::random().public_key();
+        let decoders = ModuleDecoderRegistry::default();
+
+        let proof = ContractProof {
+            evidence: evidence.clone(),
+            epochs: vec![],
+        };
+        assert!(proof.verify(&epoch_pk, &decoders).is_ok());
+
+        let proof = ContractProof {
+            evidence: evidence.with_preimage(Preimage([0; 32])),
+            epochs: vec![],
+        };
+        assert!(matches!(
+            proof.verify(&epoch_pk, &decoders),
+            Err(ContractProofError::PreimageMismatch)
+        ));
+    }
+}
@@ -0,0 +1,36 @@
+//! Watchtowers claim refunds of outgoing contracts on behalf of users that are
+//! offline when the contract times out. The user signs the refund transaction
+//! in advance and keeps the secrets needed to finalize its e-cash output, so
+//! the watchtower can only submit it, not redirect the funds.
+
+use fedimint_api::config::FederationId;
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::OutPoint;
+use serde::{Deserialize, Serialize};
+
+use crate::mint::NoteIssuanceRequests;
+use crate::modules::ln::contracts::ContractId;
+
+/// Refund of an outgoing contract, signed by its user, that a watchtower
+/// submits once the contract timed out or was cancelled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct WatchtowerRefund {
+    pub contract_id: ContractId,
+    /// Consensus encoding of the signed refund transaction
+    #[serde(with = "fedimint_api::hex::serde")]
+    pub transaction: Vec<u8>,
+}
+
+/// Asks a watchtower to watch the contract of a refund for us
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterRefundPayload {
+    pub federation_id: FederationId,
+    pub refund: WatchtowerRefund,
+}
+
+/// What we need to pick up the e-cash of a refund we handed to a watchtower
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct WatchtowerRefundIssuance {
+    pub out_point: OutPoint,
+    pub issuance: NoteIssuanceRequests,
+}
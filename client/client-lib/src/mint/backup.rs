@@ -13,10 +13,10 @@ use std::{
     ops::Range,
 };
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use fedimint_api::{
     cancellable::{Cancellable, Cancelled},
-    core::LEGACY_HARDCODED_INSTANCE_ID_MINT,
+    core::{ModuleInstanceId, LEGACY_HARDCODED_INSTANCE_ID_MINT},
     task::TaskGroup,
     NumPeers, PeerId,
 };
@@ -26,7 +26,10 @@ use fedimint_mint::{BackupRequest, SignedBackupRequest};
 use tbs::{combine_valid_shares, verify_blind_share, BlindedMessage, PublicKeyShare};
 use tracing::{error, info};
 
-use super::{db::NextECashNoteIndexKeyPrefix, *};
+use super::{
+    db::{BackupMetadataKey, NextECashNoteIndexKeyPrefix},
+    *,
+};
 use crate::api::MintFederationApi;
 use crate::modules::mint::{MintConsensusItem, MintInput, MintOutput};
 
@@ -40,10 +43,49 @@ impl MintClient {
         Ok(())
     }
 
+    /// Sets the metadata included in our backups from now on, see
+    /// [`Self::backup_metadata`]
+    pub async fn set_backup_metadata(&self, metadata: Option<String>) {
+        let mut dbtx = self.start_dbtx().await;
+        match metadata {
+            Some(metadata) => dbtx
+                .insert_entry(&BackupMetadataKey, &metadata)
+                .await
+                .expect("DB error"),
+            None => dbtx
+                .remove_entry(&BackupMetadataKey)
+                .await
+                .expect("DB error"),
+        };
+        dbtx.commit_tx().await.expect("DB error");
+    }
+
+    /// Free-form text stored with our backups, e.g. to label the wallet. It is
+    /// restored along with the notes.
+    pub async fn backup_metadata(&self) -> Option<String> {
+        self.start_dbtx()
+            .await
+            .get_value(&BackupMetadataKey)
+            .await
+            .expect("DB error")
+    }
+
     pub async fn restore_ecash_from_federation(
         &self,
         gap_limit: usize,
         task_group: &mut TaskGroup,
+    ) -> Result<Cancellable<()>> {
+        self.restore_ecash_from_federation_with_progress(gap_limit, task_group, |_| {})
+            .await
+    }
+
+    /// Like [`Self::restore_ecash_from_federation`], calling `progress` after
+    /// every epoch of the federation's history scanned for our notes
+    pub async fn restore_ecash_from_federation_with_progress(
+        &self,
+        gap_limit: usize,
+        task_group: &mut TaskGroup,
+        progress: impl Fn(RecoveryProgress) + Send + Sync,
     ) -> Result<Cancellable<()>> {
         let backup = if let Some(backup) = self.download_ecash_backup_from_federation().await? {
             backup
@@ -51,6 +93,8 @@ impl MintClient {
             warn!("Could not find any valid existing backup. Will attempt to restore from scratch. This might take a long time.");
             PlaintextEcashBackup::new_empty()
         };
+        let metadata = backup.metadata.clone();
+        let extension_backups = backup.extensions.clone();
 
         let mut task_group = task_group.make_subgroup().await;
 
@@ -59,7 +103,7 @@ impl MintClient {
         // lead to incorrect state. We should probably lock everything in some
         // way during recovery for corectness.
         let snapshot = match self
-            .restore_current_state_from_backup(&mut task_group, backup, gap_limit)
+            .restore_current_state_from_backup(&mut task_group, backup, gap_limit, &progress)
             .await?
         {
             Ok(o) => o,
@@ -93,6 +137,22 @@ impl MintClient {
                 .await
                 .expect("DB Error");
         }
+
+        if let Some(metadata) = metadata {
+            dbtx.insert_entry(&BackupMetadataKey, &metadata)
+                .await
+                .expect("DB Error");
+        }
+
+        for (module_instance_id, extension_backup) in extension_backups {
+            match self.context.extensions.get(&module_instance_id) {
+                Some(extension) => extension.restore(&mut dbtx, &extension_backup).await?,
+                None => warn!(
+                    module_instance_id,
+                    "Backup contains state of a module instance without extension"
+                ),
+            }
+        }
         dbtx.commit_tx().await?;
 
         Ok(Ok(()))
@@ -192,11 +252,20 @@ impl MintClient {
         }
         let next_note_idx = Tiered::from_iter(idxes);
 
+        let metadata = dbtx.get_value(&BackupMetadataKey).await.expect("DB error");
+
+        let mut extensions = BTreeMap::new();
+        for (module_instance_id, extension) in &self.context.extensions {
+            extensions.insert(*module_instance_id, extension.backup(&mut dbtx).await?);
+        }
+
         Ok(PlaintextEcashBackup {
             notes,
             pending_notes,
             next_note_idx,
             epoch_count,
+            metadata,
+            extensions,
         })
     }
 
@@ -206,6 +275,12 @@ impl MintClient {
     }
 
     async fn upload_ecash_backup(&self, backup: EcashBackup) -> Result<()> {
+        ensure!(
+            backup.0.len() as u64 <= self.config.max_backup_size,
+            "Backup of {} bytes exceeds the federation's limit of {} bytes",
+            backup.0.len(),
+            self.config.max_backup_size
+        );
         let backup_request = backup.into_backup_request(&self.get_derived_backup_signing_key())?;
         self.context
             .api
@@ -250,6 +325,7 @@ impl MintClient {
         task_group: &mut TaskGroup,
         backup: PlaintextEcashBackup,
         gap_limit: usize,
+        progress: &(dyn Fn(RecoveryProgress) + Send + Sync),
     ) -> Result<Cancellable<EcashRecoveryFinalState>> {
         let current_epoch_count = match self.context.api.fetch_epoch_count().await {
             Ok(v) => v,
@@ -270,8 +346,14 @@ impl MintClient {
             backup,
             self.secret.clone(),
             gap_limit,
-            self.config.tbs_pks.clone(),
-            self.config.peer_tbs_pks.clone(),
+            self.config
+                .keysets()
+                .map(|(tbs_pks, _)| tbs_pks.clone())
+                .collect(),
+            self.config
+                .keysets()
+                .map(|(_, peer_tbs_pks)| peer_tbs_pks.clone())
+                .collect(),
         );
         let task_handle = task_group.make_handle();
 
@@ -300,6 +382,11 @@ impl MintClient {
                     );
                 }
             }
+            progress(RecoveryProgress {
+                epoch,
+                start_epoch,
+                end_epoch: current_epoch_count,
+            });
         }
 
         Ok(Ok(tracker.finalize()))
@@ -316,6 +403,13 @@ pub struct PlaintextEcashBackup {
     pending_notes: Vec<(OutputFinalizationKey, NoteIssuanceRequests)>,
     epoch_count: u64,
     next_note_idx: Tiered<NoteIndex>,
+    /// See [`MintClient::backup_metadata`]. As one of the last fields, the
+    /// zero padding of backups made before it existed decodes as `None`.
+    metadata: Option<String>,
+    /// State of the module instances run by client extensions, see
+    /// [`crate::extensions::ClientExtension::backup`]. Decodes as empty from
+    /// the padding of older backups.
+    extensions: BTreeMap<ModuleInstanceId, Vec<u8>>,
 }
 
 impl PlaintextEcashBackup {
@@ -326,6 +420,8 @@ impl PlaintextEcashBackup {
             pending_notes: vec![],
             epoch_count: 0,
             next_note_idx: Tiered::default(),
+            metadata: None,
+            extensions: BTreeMap::new(),
         }
     }
 
@@ -364,6 +460,27 @@ impl PlaintextEcashBackup {
     }
 }
 
+/// Progress of a recovery scanning the epochs `start_epoch..end_epoch` for our
+/// notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RecoveryProgress {
+    /// Last epoch scanned
+    pub epoch: u64,
+    pub start_epoch: u64,
+    pub end_epoch: u64,
+}
+
+impl RecoveryProgress {
+    /// Share of the epochs scanned so far, in percent
+    pub fn percent(&self) -> u64 {
+        let total = self.end_epoch.saturating_sub(self.start_epoch);
+        if total == 0 {
+            return 100;
+        }
+        (self.epoch + 1).saturating_sub(self.start_epoch) * 100 / total
+    }
+}
+
 /// Encrypted version of [`PlaintextEcashBackup`].
 pub struct EcashBackup(Vec<u8>);
 
@@ -376,7 +493,7 @@ impl EcashBackup {
     pub fn into_backup_request(self, keypair: &KeyPair) -> Result<SignedBackupRequest> {
         let request = BackupRequest {
             id: keypair.x_only_public_key().0,
-            timestamp: std::time::SystemTime::now(),
+            timestamp: fedimint_api::time::now(),
             payload: self.0,
         };
 
@@ -452,13 +569,17 @@ struct EcashRecoveryTracker {
     /// The **mint** (not root) derived secret used to derive notes
     secret: DerivableSecret,
 
-    /// Public key shares for each peer
+    /// Public key shares for each peer, one set per key epoch
     ///
     /// Used to validate contributed consensus items
-    pub_key_shares: BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+    pub_key_shares: Vec<BTreeMap<PeerId, Tiered<PublicKeyShare>>>,
+
+    /// Aggregate public key for each amount tier, one set per key epoch
+    tbs_pks: Vec<Tiered<AggregatePublicKey>>,
 
-    /// Aggregate public key for each amount tier
-    tbs_pks: Tiered<AggregatePublicKey>,
+    /// Key epoch of the keys pending outputs were signed with, known once the
+    /// first valid share arrived
+    output_key_epochs: HashMap<OutPoint, usize>,
 
     /// The number of nonces we look-ahead when looking for mints (per each
     /// amount).
@@ -470,11 +591,12 @@ impl EcashRecoveryTracker {
         backup: PlaintextEcashBackup,
         mint_secret: DerivableSecret,
         gap_limit: usize,
-        tbs_pks: Tiered<AggregatePublicKey>,
-        pub_key_shares: BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+        tbs_pks: Vec<Tiered<AggregatePublicKey>>,
+        pub_key_shares: Vec<BTreeMap<PeerId, Tiered<PublicKeyShare>>>,
     ) -> Self {
         assert_eq!(mint_secret.level(), 1);
-        let amount_tiers: Vec<_> = tbs_pks.tiers().copied().collect();
+        assert_eq!(tbs_pks.len(), pub_key_shares.len());
+        let amount_tiers: Vec<_> = tbs_pks[0].tiers().copied().collect();
         let mut s = Self {
             spendable_note_by_nonce: backup
                 .notes
@@ -504,10 +626,11 @@ impl EcashRecoveryTracker {
             next_pending_note_idx: backup.next_note_idx.clone(),
             last_mined_nonce_idx: backup.next_note_idx,
             secret: mint_secret,
-            threshold: pub_key_shares.threshold(),
+            threshold: pub_key_shares[0].threshold(),
             gap_limit,
             tbs_pks,
             pub_key_shares,
+            output_key_epochs: HashMap::default(),
         };
 
         for amount in amount_tiers {
@@ -672,69 +795,95 @@ impl EcashRecoveryTracker {
         }
     }
 
-    pub fn handle_output_confirmation(&mut self, peer_id: PeerId, sigs: &MintConsensusItem) {
-        let enough_shares = if let Some((output_data, peer_shares)) =
-            self.pending_outputs.get_mut(&sigs.out_point)
-        {
-            if !sigs.signatures.0.structural_eq(output_data) {
-                warn!(
-                    peer = %peer_id,
-                    "Peer proposed a sig share of wrong structure (different than out_point)",
-                );
-                return;
-            }
-
-            for ((share_amt, share_sig), (output_item_amt, output_data_item)) in
-                sigs.signatures.0.iter_items().zip(output_data.iter_items())
-            {
-                // Guaranteed by the structural_eq check above
-                assert_eq!(share_amt, output_item_amt);
-
-                let amount_key = match self.pub_key_shares[&peer_id].tier(&share_amt) {
-                    Ok(key) => key,
-                    Err(_) => {
-                        error!(
-                            ?peer_id,
-                            amount = ?share_amt,
-                            "Missing public key for the amount. This should not happen."
-                        );
-                        return;
-                    }
-                };
+    /// Key epoch of the keys `peer_id` signed a pending output with, `None` if
+    /// the output isn't pending or the shares aren't valid
+    ///
+    /// Once a valid share was seen for an output all other shares have to be
+    /// signed with keys of the same key epoch.
+    fn valid_share_key_epoch(&self, peer_id: PeerId, sigs: &MintConsensusItem) -> Option<usize> {
+        let (output_data, _) = self.pending_outputs.get(&sigs.out_point)?;
+        if !sigs.signatures.0.structural_eq(output_data) {
+            warn!(
+                peer = %peer_id,
+                "Peer proposed a sig share of wrong structure (different than out_point)",
+            );
+            return None;
+        }
 
-                if !verify_blind_share(output_data_item.0, share_sig.1, *amount_key) {
-                    warn!(?peer_id, "Ignoring invalid contribution share from peer");
-                    return;
-                }
-            }
+        let mut key_epochs = match self.output_key_epochs.get(&sigs.out_point) {
+            Some(key_epoch) => *key_epoch..(*key_epoch + 1),
+            None => 0..self.pub_key_shares.len(),
+        };
+        let key_epoch = key_epochs.find(|key_epoch| {
+            sigs.signatures
+                .0
+                .iter_items()
+                .zip(output_data.iter_items())
+                .all(
+                    |((share_amt, share_sig), (output_item_amt, output_data_item))| {
+                        // Guaranteed by the structural_eq check above
+                        assert_eq!(share_amt, output_item_amt);
+
+                        match self.pub_key_shares[*key_epoch][&peer_id].tier(&share_amt) {
+                            Ok(amount_key) => {
+                                verify_blind_share(output_data_item.0, share_sig.1, *amount_key)
+                            }
+                            Err(_) => {
+                                error!(
+                                    ?peer_id,
+                                    amount = ?share_amt,
+                                    "Missing public key for the amount. This should not happen."
+                                );
+                                false
+                            }
+                        }
+                    },
+                )
+        });
 
-            if let Some(_prev) = peer_shares.insert(
-                peer_id,
-                // We compact the shares to a `Vec<BlindedSignatureShare>` like
-                // we eventually want in the consensus itself: https://github.com/fedimint/fedimint/issues/1053#issue-1477111966
-                sigs.signatures
-                    .0
-                    .iter_items()
-                    .map(|(_, (_, sig_share))| *sig_share)
-                    .collect(),
-            ) {
-                warn!(
-                    out_point = %sigs.out_point,
-                    ?peer_id,
-                    "Duplicate signature share for out_point",
-                );
-            }
+        if key_epoch.is_none() {
+            warn!(?peer_id, "Ignoring invalid contribution share from peer");
+        }
+        key_epoch
+    }
 
-            self.threshold <= peer_shares.len()
-        } else {
-            false
+    pub fn handle_output_confirmation(&mut self, peer_id: PeerId, sigs: &MintConsensusItem) {
+        let key_epoch = match self.valid_share_key_epoch(peer_id, sigs) {
+            Some(key_epoch) => key_epoch,
+            None => return,
         };
+        self.output_key_epochs.insert(sigs.out_point, key_epoch);
+
+        let enough_shares =
+            if let Some((_, peer_shares)) = self.pending_outputs.get_mut(&sigs.out_point) {
+                if let Some(_prev) = peer_shares.insert(
+                    peer_id,
+                    // We compact the shares to a `Vec<BlindedSignatureShare>` like
+                    // we eventually want in the consensus itself: https://github.com/fedimint/fedimint/issues/1053#issue-1477111966
+                    sigs.signatures
+                        .0
+                        .iter_items()
+                        .map(|(_, (_, sig_share))| *sig_share)
+                        .collect(),
+                ) {
+                    warn!(
+                        out_point = %sigs.out_point,
+                        ?peer_id,
+                        "Duplicate signature share for out_point",
+                    );
+                }
+
+                self.threshold <= peer_shares.len()
+            } else {
+                false
+            };
 
         if enough_shares {
             let (output_data, sig_shares) = self
                 .pending_outputs
                 .remove(&sigs.out_point)
                 .expect("must be in the map already");
+            self.output_key_epochs.remove(&sigs.out_point);
 
             for (item_i, (item_amt, item)) in output_data.iter_items().enumerate() {
                 let iss_request = if let Some(iss_request) = item.1 {
@@ -755,8 +904,7 @@ impl EcashRecoveryTracker {
                 let note = iss_request
                     .finalize(
                         sig,
-                        *self
-                            .tbs_pks
+                        *self.tbs_pks[key_epoch]
                             .tier(&item_amt)
                             .expect("must have keys for all amounts here"),
                     )
@@ -777,6 +925,10 @@ impl EcashRecoveryTracker {
     ) {
         match item {
             ConsensusItem::ClientConfigSignatureShare(_) => {}
+            ConsensusItem::FederationMetaSignatureShare(_) => {}
+            ConsensusItem::UpgradeSignal(_) => {}
+            ConsensusItem::ModuleVersionVote(_) => {}
+            ConsensusItem::ModuleParamsVote(_) => {}
             ConsensusItem::EpochOutcomeSignatureShare(_) => {}
             ConsensusItem::Transaction(tx) => {
                 let txid = tx.tx_hash();
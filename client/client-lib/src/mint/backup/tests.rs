@@ -3,6 +3,7 @@ use std::collections::{BTreeMap, HashMap};
 use anyhow::Result;
 use fedimint_api::{
     core::{self, DynOutput, LEGACY_HARDCODED_INSTANCE_ID_MINT},
+    encoding::Encodable,
     msats, Amount, OutPoint, PeerId, Tiered, TieredMulti,
 };
 use fedimint_core::{epoch::ConsensusItem, transaction::Transaction};
@@ -71,6 +72,8 @@ impl MicroMintClient {
                 .collect(),
             epoch_count: 0,
             next_note_idx: self.next_note_idx.clone(),
+            metadata: None,
+            extensions: BTreeMap::new(),
         }
     }
 
@@ -268,6 +271,8 @@ fn sanity_ecash_backup_decode_encode() -> Result<()> {
             [(Amount::from_msats(1), NoteIndex::from_u64(3))].into_iter(),
         ),
         epoch_count: 0,
+        metadata: None,
+        extensions: BTreeMap::new(),
     };
 
     let encoded = orig.encode()?;
@@ -277,6 +282,28 @@ fn sanity_ecash_backup_decode_encode() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn ecash_backup_without_metadata_decodes() -> Result<()> {
+    let next_note_idx =
+        Tiered::from_iter([(Amount::from_msats(1), NoteIndex::from_u64(3))].into_iter());
+
+    // Encoding of a backup made before the metadata was added
+    let mut encoded = vec![];
+    TieredMulti::<SpendableNote>::default().consensus_encode(&mut encoded)?;
+    Vec::<(OutputFinalizationKey, NoteIssuanceRequests)>::new().consensus_encode(&mut encoded)?;
+    5u64.consensus_encode(&mut encoded)?;
+    next_note_idx.consensus_encode(&mut encoded)?;
+    encoded.resize(PlaintextEcashBackup::get_alignment_size(encoded.len()), 0);
+
+    let decoded = PlaintextEcashBackup::decode(&encoded)?;
+    assert_eq!(decoded.next_note_idx, next_note_idx);
+    assert_eq!(decoded.epoch_count, 5);
+    assert_eq!(decoded.metadata, None);
+    assert!(decoded.extensions.is_empty());
+
+    Ok(())
+}
+
 #[test]
 fn sanity_ecash_backup_encrypt_decrypt() -> Result<()> {
     let orig = PlaintextEcashBackup {
@@ -286,6 +313,8 @@ fn sanity_ecash_backup_encrypt_decrypt() -> Result<()> {
             [(Amount::from_msats(1), NoteIndex::from_u64(3))].into_iter(),
         ),
         epoch_count: 1,
+        metadata: Some("Savings".to_string()),
+        extensions: BTreeMap::from([(3, vec![1, 2, 3])]),
     };
 
     let secret = DerivableSecret::new_root(&[1; 32], &[1, 32]);
@@ -333,8 +362,8 @@ fn sanity_check_recovery_fresh_backup() {
         empty_backup_c1,
         c1.secret.clone(),
         gap_limit,
-        fed.tbs_pks.clone(),
-        fed.pub_key_shares.clone(),
+        vec![fed.tbs_pks.clone()],
+        vec![fed.pub_key_shares.clone()],
     );
 
     let (output_c1_a, iss_reqs_c1_a) = c1.generate_output([1, 2, 4]);
@@ -519,8 +548,8 @@ fn sanity_check_recovery_non_empty_backup() {
         backup_c1,
         c1.secret.clone(),
         gap_limit,
-        fed.tbs_pks.clone(),
-        fed.pub_key_shares.clone(),
+        vec![fed.tbs_pks.clone()],
+        vec![fed.pub_key_shares.clone()],
     );
 
     // Spend the notes, which should remove them from the tracker
@@ -588,8 +617,8 @@ fn sanity_check_recovery_bn_reuse_with_invalid_amount() {
         backup_c1,
         c1.secret.clone(),
         gap_limit,
-        fed.tbs_pks.clone(),
-        fed.pub_key_shares,
+        vec![fed.tbs_pks.clone()],
+        vec![fed.pub_key_shares],
     );
 
     let (output_c1_b, _iss_reqs_c1_b) = c1.generate_output([1, 2, 4]);
@@ -673,8 +702,8 @@ fn sanity_check_recovery_bn_reuse_with_valid_amount() {
         backup_c1,
         c1.secret.clone(),
         gap_limit,
-        fed.tbs_pks.clone(),
-        fed.pub_key_shares.clone(),
+        vec![fed.tbs_pks.clone()],
+        vec![fed.pub_key_shares.clone()],
     );
 
     let (output_c1_b, iss_reqs_c1_b) = c1.generate_output([1, 2, 4]);
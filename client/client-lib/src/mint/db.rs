@@ -4,6 +4,7 @@ use fedimint_api::{Amount, OutPoint, TieredMulti, TransactionId};
 use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
+use crate::mint::note_selection::NoteSelection;
 use crate::mint::{NoteIssuanceRequests, SpendableNote};
 use crate::modules::mint::Nonce;
 
@@ -15,6 +16,8 @@ pub enum DbKeyPrefix {
     PendingNotes = 0x27,
     NextECashNoteIndex = 0x2a,
     NotesPerDenomination = 0x2b,
+    NoteSelection = 0x3f,
+    BackupMetadata = 0x43,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -82,3 +85,22 @@ impl_db_prefix_const!(
 pub struct NotesPerDenominationKey;
 
 impl_db_prefix_const!(key = NotesPerDenominationKey, value = u16, prefix = 0);
+
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct NoteSelectionKey;
+
+impl_db_prefix_const!(
+    key = NoteSelectionKey,
+    value = NoteSelection,
+    prefix = DbKeyPrefix::NoteSelection
+);
+
+/// Free-form text included in every e-cash backup, e.g. to label the wallet
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct BackupMetadataKey;
+
+impl_db_prefix_const!(
+    key = BackupMetadataKey,
+    value = String,
+    prefix = DbKeyPrefix::BackupMetadata
+);
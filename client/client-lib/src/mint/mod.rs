@@ -1,5 +1,6 @@
 pub mod db;
 
+use std::cmp::max;
 use std::fmt;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -21,7 +22,12 @@ use tbs::{blind_message, unblind_signature, AggregatePublicKey, BlindedSignature
 use thiserror::Error;
 use tracing::{debug, error, trace, warn};
 
-use crate::mint::db::{NextECashNoteIndexKey, NotesPerDenominationKey, PendingNotesKey};
+use crate::api::MintFederationApi;
+use crate::mint::db::{
+    NextECashNoteIndexKey, NoteSelectionKey, NotesPerDenominationKey, PendingNotesKey,
+};
+use crate::mint::note_selection::NoteSelection;
+use crate::mint::signature_shares::SignatureShareAggregation;
 use crate::modules::mint::config::MintClientConfig;
 use crate::modules::mint::{
     BlindNonce, Mint, MintInput, MintOutput, MintOutputBlindSignatures, MintOutputOutcome, Nonce,
@@ -33,10 +39,16 @@ use crate::utils::ClientContext;
 use crate::{ChildId, DerivableSecret, FuturesUnordered, MintDecoder};
 
 pub mod backup;
+pub mod note_selection;
+pub mod signature_shares;
+pub mod transfer;
 
 const MINT_E_CASH_TYPE_CHILD_ID: ChildId = ChildId(0);
 const MINT_E_CASH_BACKUP_SNAPSHOT_TYPE_CHILD_ID: ChildId = ChildId(1);
 const MINT_E_CASH_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum number of surplus notes consolidated when spending, keeps
+/// transactions well below the size limit
+const MAX_CONSOLIDATION_NOTES: usize = 16;
 
 /// Federation module client for the Mint module. It can both create transaction
 /// inputs and outputs of the mint type.
@@ -262,6 +274,25 @@ impl MintClient {
             .unwrap_or(self.config.max_notes_per_denomination - 1)
     }
 
+    /// Sets the strategy used to choose the notes to spend unless a spend
+    /// overrides it
+    pub async fn set_note_selection(&self, selection: NoteSelection) {
+        let mut dbtx = self.start_dbtx().await;
+        dbtx.insert_entry(&NoteSelectionKey, &selection)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB error");
+    }
+
+    pub async fn note_selection(&self) -> NoteSelection {
+        self.start_dbtx()
+            .await
+            .get_value(&NoteSelectionKey)
+            .await
+            .expect("DB Error")
+            .unwrap_or_default()
+    }
+
     /// Generates unsigned ecash, along with the private keys that can spend it
     async fn create_ecash(
         &self,
@@ -297,8 +328,43 @@ impl MintClient {
         (note_finalization_data, sig_req.0)
     }
 
+    /// Selects notes to spend `amount` as transaction input using the
+    /// configured [`NoteSelection`]
     pub async fn select_input(&self, amount: Amount) -> Result<(Vec<KeyPair>, Input)> {
-        Self::ecash_input(self.select_notes(amount).await?)
+        let selection = self.note_selection().await;
+        self.select_input_with(amount, selection).await
+    }
+
+    /// Selects notes to spend `amount` as transaction input. Since the caller
+    /// receives change, [`NoteSelection::MinimizeNotes`] also spends surplus
+    /// notes of tiers holding more notes than targeted, consolidating them
+    /// into fewer larger notes.
+    pub async fn select_input_with(
+        &self,
+        amount: Amount,
+        selection: NoteSelection,
+    ) -> Result<(Vec<KeyPair>, Input)> {
+        let notes = self.notes().await;
+        let selected_notes = match selection {
+            NoteSelection::MinimizeNotes => {
+                let notes_per_denomination = self
+                    .notes_per_denomination(&mut self.start_dbtx().await)
+                    .await;
+                let fees = &self.config.fee_consensus;
+                notes.select_notes_consolidating(
+                    amount,
+                    notes_per_denomination.into(),
+                    MAX_CONSOLIDATION_NOTES,
+                    fees.note_spend_abs + fees.note_issuance_abs,
+                )
+            }
+            selection => selection.select(&notes, amount),
+        }
+        .ok_or_else(|| {
+            MintClientError::InsufficientBalance(amount, TieredMulti::total_amount(&notes))
+        })?;
+
+        Self::ecash_input(selected_notes)
     }
 
     pub fn ecash_input(ecash: TieredMulti<SpendableNote>) -> Result<(Vec<KeyPair>, Input)> {
@@ -402,9 +468,20 @@ impl MintClient {
         NoteIssuanceRequest::new(ctx, secret)
     }
 
+    /// Selects notes worth at least `amount` using the configured
+    /// [`NoteSelection`]
     pub async fn select_notes(&self, amount: Amount) -> Result<TieredMulti<SpendableNote>> {
+        let selection = self.note_selection().await;
+        self.select_notes_with(amount, selection).await
+    }
+
+    pub async fn select_notes_with(
+        &self,
+        amount: Amount,
+        selection: NoteSelection,
+    ) -> Result<TieredMulti<SpendableNote>> {
         let notes = self.notes().await;
-        let selected_notes = notes.select_notes(amount).ok_or_else(|| {
+        let selected_notes = selection.select(&notes, amount).ok_or_else(|| {
             MintClientError::InsufficientBalance(amount, TieredMulti::total_amount(&notes))
         })?;
 
@@ -475,17 +552,46 @@ impl MintClient {
                 NoteFinalizationError::UnknownIssuance,
             ))?;
 
-        let bsig = self
+        // Without note expiry everything is signed with the initial keyset
+        let key_epoch = if self.config.note_expiry.is_some() {
+            self.context
+                .api
+                .fetch_output_key_epoch(outpoint)
+                .await
+                .map_err(OutputOutcomeError::Federation)?
+        } else {
+            0
+        };
+        let (tbs_pks, peer_tbs_pks) = self
+            .config
+            .keyset(key_epoch)
+            .ok_or(MintClientError::UnknownKeyEpoch(key_epoch))?;
+
+        let strategy =
+            SignatureShareAggregation::new(issuance.clone(), tbs_pks.clone(), peer_tbs_pks.clone());
+        let bsig = match self
             .context
             .api
-            .fetch_output_outcome::<OutputOutcome>(outpoint, &self.context.decoders)
-            .await?
-            .try_into_variant::<MintOutputOutcome>()?
-            .as_ref()
-            .cloned()
-            .ok_or(MintClientError::OutputNotReadyYet(outpoint))?;
+            .fetch_blind_signatures(outpoint, strategy)
+            .await
+        {
+            Ok(bsig) => bsig,
+            Err(e) => {
+                // Too few peers have signed yet or the transaction was rejected, only the
+                // transaction outcome tells us which
+                trace!(%outpoint, %e, "Could not combine blind signature shares");
+                self.context
+                    .api
+                    .fetch_output_outcome::<OutputOutcome>(outpoint, &self.context.decoders)
+                    .await?
+                    .try_into_variant::<MintOutputOutcome>()?
+                    .as_ref()
+                    .cloned()
+                    .ok_or(MintClientError::OutputNotReadyYet(outpoint))?
+            }
+        };
 
-        let notes = issuance.finalize(bsig, &self.config.tbs_pks)?;
+        let notes = issuance.finalize(bsig, tbs_pks)?;
 
         for (amount, note) in notes.into_iter() {
             let key = NoteKey {
@@ -502,6 +608,75 @@ impl MintClient {
         Ok(())
     }
 
+    /// Key epoch of the keyset that signed `note`, `None` if none of the
+    /// federation's keysets did
+    pub fn note_key_epoch(&self, amount: Amount, note: &Note) -> Option<u32> {
+        (0..self.config.keyset_count() as u32).find(|key_epoch| {
+            self.config
+                .keyset(*key_epoch)
+                .and_then(|(tbs_pks, _)| tbs_pks.get(amount))
+                .map_or(false, |tbs_pk| note.verify(*tbs_pk))
+        })
+    }
+
+    /// Notes that are still redeemable in `current_key_epoch` but won't be
+    /// after the next key rotation
+    pub async fn aging_notes(&self, current_key_epoch: u32) -> TieredMulti<SpendableNote> {
+        let expiry = match &self.config.note_expiry {
+            Some(expiry) => expiry,
+            None => return TieredMulti::default(),
+        };
+        // The federation stops rotating once it used its last keyset
+        let next_key_epoch = (current_key_epoch + 1).min(self.config.keyset_count() as u32 - 1);
+
+        self.notes()
+            .await
+            .into_iter()
+            .filter(|(amount, note)| {
+                self.note_key_epoch(*amount, &note.note)
+                    .map_or(false, |key_epoch| {
+                        expiry.is_redeemable(key_epoch, current_key_epoch)
+                            && !expiry.is_redeemable(key_epoch, next_key_epoch)
+                    })
+            })
+            .collect()
+    }
+
+    /// Notes of amount tiers the federation removed from its tier set, which
+    /// have to be reissued into the current tiers before their grace period
+    /// ends
+    pub async fn retired_tier_notes(&self) -> TieredMulti<SpendableNote> {
+        self.notes()
+            .await
+            .into_iter()
+            .filter(|(amount, _)| self.config.tbs_pks.get(*amount).is_none())
+            .collect()
+    }
+
+    /// Up to [`MAX_CONSOLIDATION_NOTES`] notes of tiers holding more than
+    /// `notes_per_tier` notes, starting at the lowest tier. Reissuing them
+    /// turns many small notes into fewer larger ones.
+    ///
+    /// Tiers are never drained below the number of notes targeted when
+    /// issuing change, otherwise the reissuance would refill them.
+    pub async fn surplus_notes(&self, notes_per_tier: u16) -> TieredMulti<SpendableNote> {
+        let notes_per_tier = max(
+            notes_per_tier,
+            self.notes_per_denomination(&mut self.start_dbtx().await)
+                .await,
+        );
+        let fees = &self.config.fee_consensus;
+        self.notes()
+            .await
+            .select_notes_consolidating(
+                Amount::ZERO,
+                notes_per_tier.into(),
+                MAX_CONSOLIDATION_NOTES,
+                fees.note_spend_abs + fees.note_issuance_abs,
+            )
+            .expect("Selecting no amount always succeeds")
+    }
+
     pub async fn list_active_issuances(&self) -> Vec<(OutPoint, NoteIssuanceRequests)> {
         self.context
             .db
@@ -650,6 +825,8 @@ pub enum MintClientError {
     InvalidOutcomeType(OutPoint),
     #[error("One of the notes meant to be spent is unspendable")]
     ReceivedUspendableNote,
+    #[error("The federation signed with keys of unknown key epoch {0}")]
+    UnknownKeyEpoch(u32),
 }
 
 impl MintClientError {
@@ -754,6 +931,7 @@ mod tests {
                         Amount::from_sats(10),
                         Amount::from_sats(20),
                     ],
+                    note_expiry: None,
                 }),
                 &MintGen,
                 module_id,
@@ -772,6 +950,7 @@ mod tests {
             db: Database::new(MemDatabase::new(), module_decode_stubs()),
             api: api.into(),
             secp: secp256k1_zkp::Secp256k1::new(),
+            extensions: Default::default(),
         };
 
         (fed, client_config.cast().unwrap(), client_context)
@@ -933,6 +1112,11 @@ mod tests {
                 fee_consensus: Default::default(),
                 peer_tbs_pks: BTreeMap::default(),
                 max_notes_per_denomination: 0,
+                max_backup_size: 0,
+                note_expiry: None,
+                rotated_tbs_pks: vec![],
+                rotated_peer_tbs_pks: vec![],
+                retired_tiers: None,
             },
             context: Arc::new(ClientContext {
                 decoders: ModuleDecoderRegistry::from_iter([(module_id, MintDecoder.into())]),
@@ -940,6 +1124,7 @@ mod tests {
                 db: Database::new(db, module_decode_stubs()),
                 api: WsFederationApi::new(vec![]).into(),
                 secp: Default::default(),
+                extensions: Default::default(),
             }),
             secret: DerivableSecret::new_root(&[], &[]).child_key(MINT_SECRET_CHILD_ID),
         };
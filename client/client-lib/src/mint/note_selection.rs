@@ -0,0 +1,219 @@
+//! Strategies for choosing which of our notes to spend. Applications weigh the
+//! number of notes spent, the change that has to be issued and what a spend
+//! reveals about the wallet differently, so the strategy is a setting of the
+//! client that can be overridden for single spends.
+
+use std::cmp::{min, Reverse};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::{Amount, TieredMulti};
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Encodable, Decodable,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum NoteSelection {
+    /// Spends the largest notes first to keep transactions small. Since change
+    /// is issued anyway, surplus notes of tiers holding more notes than
+    /// targeted are spent along to consolidate them.
+    #[default]
+    MinimizeNotes,
+    /// Spends the smallest notes first and overshoots the amount by as little
+    /// as possible, so hardly any change has to be issued
+    MinimizeChange,
+    /// Prefers notes of the tiers we hold the most notes of. Clients target
+    /// the same number of notes per tier, so these are the tiers most notes
+    /// are spent in and our spends blend in with those of other users.
+    MaximizeAnonymity,
+}
+
+impl NoteSelection {
+    /// Selects notes worth at least `amount`, returns `None` if all `notes`
+    /// together aren't worth enough
+    pub fn select<C: Clone>(
+        &self,
+        notes: &TieredMulti<C>,
+        amount: Amount,
+    ) -> Option<TieredMulti<C>> {
+        match self {
+            NoteSelection::MinimizeNotes => notes.select_notes(amount),
+            NoteSelection::MinimizeChange => {
+                select_in_tier_order(notes, amount, notes.iter_tiers().copied().collect())
+            }
+            NoteSelection::MaximizeAnonymity => {
+                let mut tiers = notes
+                    .iter()
+                    .map(|(tier, notes)| (*tier, notes.len()))
+                    .collect::<Vec<_>>();
+                tiers.sort_by_key(|(tier, count)| (Reverse(*count), *tier));
+                select_in_tier_order(
+                    notes,
+                    amount,
+                    tiers.into_iter().map(|(tier, _)| tier).collect(),
+                )
+            }
+        }
+    }
+}
+
+/// Takes as many notes of each of the `tiers` in turn as fit into `amount`,
+/// covers the rest with the smallest note possible and finally drops notes
+/// that turned out to be unnecessary
+fn select_in_tier_order<C: Clone>(
+    notes: &TieredMulti<C>,
+    amount: Amount,
+    tiers: Vec<Amount>,
+) -> Option<TieredMulti<C>> {
+    if amount > notes.total_amount() {
+        return None;
+    }
+
+    // Notes of the same tier are interchangeable, so only the number of notes
+    // to select per tier matters
+    let mut selected: BTreeMap<Amount, usize> = BTreeMap::new();
+    let unselected = |selected: &BTreeMap<Amount, usize>, tier: Amount| {
+        notes.get(tier).map(Vec::len).unwrap_or(0) - selected.get(&tier).copied().unwrap_or(0)
+    };
+
+    let mut remaining = amount;
+    for tier in tiers {
+        let count = min(unselected(&selected, tier) as u64, remaining / tier);
+        if count > 0 {
+            *selected.entry(tier).or_default() += count as usize;
+            remaining -= tier * count;
+        }
+    }
+
+    if remaining > Amount::ZERO {
+        let covering = notes
+            .iter_tiers()
+            .copied()
+            .find(|tier| *tier >= remaining && unselected(&selected, *tier) > 0);
+        match covering {
+            Some(tier) => *selected.entry(tier).or_default() += 1,
+            None => {
+                for tier in notes.iter_tiers().copied() {
+                    while remaining > Amount::ZERO && unselected(&selected, tier) > 0 {
+                        *selected.entry(tier).or_default() += 1;
+                        remaining = remaining.saturating_sub(tier);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut total: Amount = selected
+        .iter()
+        .map(|(tier, count)| *tier * *count as u64)
+        .sum();
+    for (tier, count) in selected.iter_mut().rev() {
+        while *count > 0 && total - *tier >= amount {
+            *count -= 1;
+            total -= *tier;
+        }
+    }
+
+    Some(TieredMulti::new(
+        selected
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(tier, count)| {
+                let tier_notes = notes.get(tier).expect("Only tiers we hold are selected");
+                (tier, tier_notes[..count].to_vec())
+            })
+            .collect(),
+    ))
+}
+
+impl fmt::Display for NoteSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NoteSelection::MinimizeNotes => "minimize-notes",
+            NoteSelection::MinimizeChange => "minimize-change",
+            NoteSelection::MaximizeAnonymity => "maximize-anonymity",
+        })
+    }
+}
+
+impl FromStr for NoteSelection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "minimize-notes" => Ok(NoteSelection::MinimizeNotes),
+            "minimize-change" => Ok(NoteSelection::MinimizeChange),
+            "maximize-anonymity" => Ok(NoteSelection::MaximizeAnonymity),
+            _ => Err(anyhow::anyhow!("Unknown note selection: {s}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::{Amount, TieredMulti};
+
+    use super::NoteSelection;
+
+    fn notes(notes: Vec<(u64, usize)>) -> TieredMulti<usize> {
+        notes
+            .into_iter()
+            .flat_map(|(sats, number)| vec![(Amount::from_sats(sats), 0_usize); number])
+            .collect()
+    }
+
+    fn select(
+        selection: NoteSelection,
+        wallet: &TieredMulti<usize>,
+        sats: u64,
+    ) -> TieredMulti<usize> {
+        selection
+            .select(wallet, Amount::from_sats(sats))
+            .expect("Enough notes")
+    }
+
+    #[test]
+    fn minimize_change_spends_small_notes() {
+        let wallet = notes(vec![(1, 4), (2, 2)]);
+        assert_eq!(
+            select(NoteSelection::MinimizeChange, &wallet, 4),
+            notes(vec![(1, 4)])
+        );
+        assert_eq!(
+            select(NoteSelection::MinimizeNotes, &wallet, 4),
+            notes(vec![(2, 2)])
+        );
+
+        let wallet = notes(vec![(2, 1), (5, 1), (6, 1)]);
+        assert_eq!(
+            select(NoteSelection::MinimizeChange, &wallet, 7),
+            notes(vec![(2, 1), (5, 1)])
+        );
+        assert_eq!(
+            select(NoteSelection::MinimizeNotes, &wallet, 7),
+            notes(vec![(2, 1), (6, 1)])
+        );
+        assert_eq!(
+            NoteSelection::MinimizeChange.select(&wallet, Amount::from_sats(14)),
+            None
+        );
+    }
+
+    #[test]
+    fn maximize_anonymity_prefers_common_tiers() {
+        let wallet = notes(vec![(1, 1), (2, 5), (8, 2)]);
+
+        assert_eq!(
+            select(NoteSelection::MaximizeAnonymity, &wallet, 8),
+            notes(vec![(2, 4)])
+        );
+        // Unnecessary notes are dropped again once a larger one is needed
+        assert_eq!(
+            select(NoteSelection::MaximizeAnonymity, &wallet, 12),
+            notes(vec![(2, 2), (8, 1)])
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+
+use fedimint_api::{NumPeers, PeerId, Tiered, TieredMulti, TieredMultiZip};
+use fedimint_core::api::{MemberError, MemberResult};
+use fedimint_core::query::{QueryStep, QueryStrategy};
+use tbs::{combine_valid_shares, verify_blind_share, AggregatePublicKey, PublicKeyShare};
+use tracing::debug;
+
+use super::NoteIssuanceRequests;
+use crate::modules::mint::{
+    MintOutputBlindSignatures, MintOutputSignatureShare, MintOutputSignatureShareResponse,
+};
+
+/// Collects blind signature shares for a single issuance from all peers and
+/// combines them as soon as a threshold of valid shares arrived, without
+/// waiting for the remaining peers. A peer that already combined the shares in
+/// consensus can answer with the verifiable result directly.
+pub struct SignatureShareAggregation {
+    issuance: NoteIssuanceRequests,
+    tbs_pks: Tiered<AggregatePublicKey>,
+    peer_tbs_pks: BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+    valid_shares: BTreeMap<PeerId, MintOutputSignatureShare>,
+    failed_peers: usize,
+}
+
+impl SignatureShareAggregation {
+    pub fn new(
+        issuance: NoteIssuanceRequests,
+        tbs_pks: Tiered<AggregatePublicKey>,
+        peer_tbs_pks: BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+    ) -> Self {
+        Self {
+            issuance,
+            tbs_pks,
+            peer_tbs_pks,
+            valid_shares: BTreeMap::new(),
+            failed_peers: 0,
+        }
+    }
+
+    /// Checks that `share` signs exactly our blinded nonces with the key share
+    /// of `peer`
+    fn verify_share(&self, peer: PeerId, share: &MintOutputSignatureShare) -> Result<(), String> {
+        let peer_pks = self
+            .peer_tbs_pks
+            .get(&peer)
+            .ok_or_else(|| format!("Unknown peer {peer}"))?;
+        if !self.issuance.notes.structural_eq(&share.0) {
+            return Err("Signature share has the wrong structure".to_string());
+        }
+
+        for ((amount, request), (_, (msg, sig))) in
+            self.issuance.notes.iter_items().zip(share.0.iter_items())
+        {
+            if request.recover_blind_nonce().0 != *msg {
+                return Err("Signature share is for a different nonce".to_string());
+            }
+            let pk = peer_pks.tier(&amount).map_err(|e| e.to_string())?;
+            if !verify_blind_share(*msg, *sig, *pk) {
+                return Err("Invalid signature share".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn combine(&self) -> MintOutputBlindSignatures {
+        let threshold = self.peer_tbs_pks.threshold();
+        let peers = self.valid_shares.keys().collect::<Vec<_>>();
+        let bsigs = TieredMultiZip::new(
+            self.valid_shares
+                .values()
+                .map(|share| share.0.iter_items())
+                .collect(),
+        )
+        .map(|(amount, shares)| {
+            let shares = peers
+                .iter()
+                .zip(shares)
+                .map(|(peer, (_msg, sig))| (peer.to_usize(), *sig))
+                .collect::<Vec<_>>();
+            (amount, combine_valid_shares(shares, threshold))
+        })
+        .collect::<TieredMulti<_>>();
+
+        MintOutputBlindSignatures(bsigs)
+    }
+
+    fn fail(&mut self, peer: PeerId, error: MemberError) -> QueryStep<MintOutputBlindSignatures> {
+        self.failed_peers += 1;
+        let errors = BTreeMap::from([(peer, error)]);
+        // Once too many peers failed the remaining ones can't reach the threshold
+        if self.peer_tbs_pks.total() - self.failed_peers < self.peer_tbs_pks.threshold() {
+            QueryStep::Failure(errors)
+        } else {
+            QueryStep::FailMembers(errors)
+        }
+    }
+}
+
+impl QueryStrategy<MintOutputSignatureShareResponse, MintOutputBlindSignatures>
+    for SignatureShareAggregation
+{
+    fn process(
+        &mut self,
+        peer: PeerId,
+        result: MemberResult<MintOutputSignatureShareResponse>,
+    ) -> QueryStep<MintOutputBlindSignatures> {
+        match result {
+            Ok(MintOutputSignatureShareResponse::Combined(bsigs)) => {
+                if self.issuance.finalize(bsigs.clone(), &self.tbs_pks).is_ok() {
+                    QueryStep::Success(bsigs)
+                } else {
+                    self.fail(
+                        peer,
+                        MemberError::InvalidResponse("Invalid blind signatures".to_string()),
+                    )
+                }
+            }
+            Ok(MintOutputSignatureShareResponse::Share(share)) => {
+                if let Err(e) = self.verify_share(peer, &share) {
+                    return self.fail(peer, MemberError::InvalidResponse(e));
+                }
+
+                self.valid_shares.insert(peer, share);
+                if self.valid_shares.len() >= self.peer_tbs_pks.threshold() {
+                    debug!(
+                        shares = self.valid_shares.len(),
+                        "Combining blind signature shares"
+                    );
+                    QueryStep::Success(self.combine())
+                } else {
+                    QueryStep::Continue
+                }
+            }
+            Err(error) => self.fail(peer, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use fedimint_api::{Amount, PeerId, Tiered, TieredMulti};
+    use fedimint_core::query::{QueryStep, QueryStrategy};
+    use secp256k1_zkp::Secp256k1;
+    use tbs::{dealer_keygen, sign_blinded_msg};
+
+    use super::SignatureShareAggregation;
+    use crate::mint::{NoteIssuanceRequest, NoteIssuanceRequests};
+    use crate::modules::mint::{MintOutputSignatureShare, MintOutputSignatureShareResponse};
+    use crate::DerivableSecret;
+
+    #[test]
+    fn test_combines_threshold_of_valid_shares() {
+        let amount = Amount::from_msats(1);
+        let (tbs_pk, pk_shares, sk_shares) = dealer_keygen(3, 4);
+        let (_, _, wrong_sk_shares) = dealer_keygen(3, 4);
+
+        let (request, nonce) =
+            NoteIssuanceRequest::new(&Secp256k1::new(), DerivableSecret::new_root(&[], &[]));
+        let issuance = NoteIssuanceRequests {
+            notes: TieredMulti::from_iter([(amount, request)]),
+        };
+        let peer_tbs_pks = pk_shares
+            .into_iter()
+            .enumerate()
+            .map(|(idx, pk)| (PeerId::from(idx as u16), Tiered::from_iter([(amount, pk)])))
+            .collect::<BTreeMap<_, _>>();
+        let mut strategy = SignatureShareAggregation::new(
+            issuance.clone(),
+            Tiered::from_iter([(amount, tbs_pk)]),
+            peer_tbs_pks,
+        );
+
+        let share_response = |sk| {
+            Ok(MintOutputSignatureShareResponse::Share(
+                MintOutputSignatureShare(TieredMulti::from_iter([(
+                    amount,
+                    (nonce.0, sign_blinded_msg(nonce.0, sk)),
+                )])),
+            ))
+        };
+
+        assert!(matches!(
+            strategy.process(PeerId::from(0), share_response(wrong_sk_shares[0])),
+            QueryStep::FailMembers(_)
+        ));
+        assert!(matches!(
+            strategy.process(PeerId::from(1), share_response(sk_shares[1])),
+            QueryStep::Continue
+        ));
+        assert!(matches!(
+            strategy.process(PeerId::from(2), share_response(sk_shares[2])),
+            QueryStep::Continue
+        ));
+        // The invalid share of peer 0 doesn't prevent reaching the threshold
+        match strategy.process(PeerId::from(3), share_response(sk_shares[3])) {
+            QueryStep::Success(bsigs) => {
+                assert!(issuance.finalize(bsigs, &strategy.tbs_pks).is_ok())
+            }
+            step => panic!("Expected success, got {step:?}"),
+        }
+    }
+}
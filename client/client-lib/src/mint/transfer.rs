@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::io::Cursor;
+use std::str::FromStr;
+
+use bech32::{FromBase32, ToBase32, Variant};
+use fedimint_api::config::FederationId;
+use fedimint_api::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::{Amount, TieredMulti};
+use thiserror::Error;
+
+use super::SpendableNote;
+
+/// Human readable part of ecash transfer strings
+pub const ECASH_TRANSFER_HRP: &str = "fedimint";
+/// Version of the payload format, incremented on incompatible changes
+pub const ECASH_TRANSFER_VERSION: u8 = 0;
+const FEDERATION_ID_LEN: usize = 48;
+
+/// Notes handed to another user out-of-band together with the id of the
+/// federation that issued them.
+///
+/// The string representation is bech32m encoded with the human readable part
+/// [`ECASH_TRANSFER_HRP`] so that typos are detected by the checksum. The data
+/// part consists of the version byte, the 48 byte federation id and the
+/// consensus encoded notes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EcashTransfer {
+    pub federation_id: FederationId,
+    pub notes: TieredMulti<SpendableNote>,
+}
+
+#[derive(Debug, Error)]
+pub enum EcashTransferError {
+    #[error("Invalid bech32 encoding: {0}")]
+    Bech32(#[from] bech32::Error),
+    #[error("Ecash transfer strings have to be bech32m encoded")]
+    WrongVariant,
+    #[error("Not an ecash transfer string, unexpected prefix {0}")]
+    WrongPrefix(String),
+    #[error("Unsupported ecash transfer version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Invalid federation id")]
+    InvalidFederationId,
+    #[error("Invalid notes: {0}")]
+    InvalidNotes(#[from] DecodeError),
+    #[error("Ecash transfer contains trailing data")]
+    TrailingData,
+    #[error("Notes of different federations can't be combined")]
+    FederationMismatch,
+}
+
+impl EcashTransfer {
+    pub fn new(federation_id: FederationId, notes: TieredMulti<SpendableNote>) -> Self {
+        Self {
+            federation_id,
+            notes,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut bytes = vec![ECASH_TRANSFER_VERSION];
+        bytes.extend(self.federation_id.0.to_bytes());
+        self.notes
+            .consensus_encode(&mut bytes)
+            .expect("encodes correctly");
+
+        bech32::encode(ECASH_TRANSFER_HRP, bytes.to_base32(), Variant::Bech32m)
+            .expect("hrp is valid")
+    }
+
+    /// Splits off notes worth exactly `amount`, taking the largest notes that
+    /// still fit first. Returns them together with the remaining notes, or
+    /// `None` if the notes don't add up to `amount` that way.
+    pub fn split(&self, amount: Amount) -> Option<(EcashTransfer, EcashTransfer)> {
+        let mut missing = amount;
+        let (split, rest): (Vec<_>, Vec<_>) =
+            self.notes
+                .clone()
+                .into_iter_items()
+                .rev()
+                .partition(|(note_amount, _)| {
+                    if *note_amount <= missing {
+                        missing -= *note_amount;
+                        true
+                    } else {
+                        false
+                    }
+                });
+        if missing != Amount::ZERO {
+            return None;
+        }
+
+        Some((
+            EcashTransfer::new(self.federation_id.clone(), split.into_iter().collect()),
+            EcashTransfer::new(self.federation_id.clone(), rest.into_iter().collect()),
+        ))
+    }
+
+    /// Combines the notes of two transfers of the same federation into one,
+    /// notes contained in both are only kept once
+    pub fn combine(self, other: EcashTransfer) -> Result<EcashTransfer, EcashTransferError> {
+        if self.federation_id != other.federation_id {
+            return Err(EcashTransferError::FederationMismatch);
+        }
+
+        let mut seen = HashSet::new();
+        let notes = self
+            .notes
+            .into_iter_items()
+            .chain(other.notes.into_iter_items())
+            .filter(|item| seen.insert(*item))
+            .collect();
+        Ok(EcashTransfer::new(self.federation_id, notes))
+    }
+
+    /// Decodes an ecash transfer string, rejecting unknown versions and
+    /// trailing data after the notes
+    pub fn decode(s: &str) -> Result<Self, EcashTransferError> {
+        let (hrp, data, variant) = bech32::decode(s)?;
+        if variant != Variant::Bech32m {
+            return Err(EcashTransferError::WrongVariant);
+        }
+        if hrp != ECASH_TRANSFER_HRP {
+            return Err(EcashTransferError::WrongPrefix(hrp));
+        }
+
+        let bytes = Vec::<u8>::from_base32(&data)?;
+        let (version, rest) = bytes
+            .split_first()
+            .ok_or(EcashTransferError::UnsupportedVersion(0))?;
+        if *version != ECASH_TRANSFER_VERSION {
+            return Err(EcashTransferError::UnsupportedVersion(*version));
+        }
+
+        if rest.len() < FEDERATION_ID_LEN {
+            return Err(EcashTransferError::InvalidFederationId);
+        }
+        let (id_bytes, notes_bytes) = rest.split_at(FEDERATION_ID_LEN);
+        let id_bytes: [u8; FEDERATION_ID_LEN] = id_bytes.try_into().expect("length checked");
+        let federation_id = threshold_crypto::PublicKey::from_bytes(id_bytes)
+            .map(FederationId)
+            .map_err(|_| EcashTransferError::InvalidFederationId)?;
+
+        let mut reader = Cursor::new(notes_bytes);
+        let notes = TieredMulti::<SpendableNote>::consensus_decode(
+            &mut reader,
+            &ModuleDecoderRegistry::default(),
+        )?;
+        if reader.position() != notes_bytes.len() as u64 {
+            return Err(EcashTransferError::TrailingData);
+        }
+
+        Ok(Self {
+            federation_id,
+            notes,
+        })
+    }
+}
+
+impl Display for EcashTransfer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.encode())
+    }
+}
+
+impl FromStr for EcashTransfer {
+    type Err = EcashTransferError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::decode(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bech32::{ToBase32, Variant};
+    use fedimint_api::config::FederationId;
+    use fedimint_api::{Amount, TieredMulti};
+    use secp256k1_zkp::{KeyPair, Secp256k1};
+
+    use super::{EcashTransfer, EcashTransferError, ECASH_TRANSFER_HRP};
+    use crate::mint::SpendableNote;
+    use crate::modules::mint::{Nonce, Note};
+
+    fn transfer() -> EcashTransfer {
+        let secp = Secp256k1::new();
+        let notes = (1..4u8)
+            .map(|i| {
+                let spend_key = KeyPair::from_seckey_slice(&secp, &[i; 32]).unwrap();
+                let note = Note(
+                    Nonce(spend_key.x_only_public_key().0),
+                    tbs::Signature(tbs::Message::from_bytes(&[i]).0),
+                );
+                (
+                    Amount::from_msats(1 << i),
+                    SpendableNote { note, spend_key },
+                )
+            })
+            .collect::<TieredMulti<_>>();
+        EcashTransfer::new(FederationId::dummy(), notes)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let transfer = transfer();
+        let encoded = transfer.to_string();
+        assert!(encoded.starts_with("fedimint1"));
+        assert_eq!(encoded.parse::<EcashTransfer>().unwrap(), transfer);
+    }
+
+    #[test]
+    fn test_split_and_combine() {
+        // Notes of 2, 4 and 8 msat
+        let transfer = transfer();
+
+        let (split, rest) = transfer.split(Amount::from_msats(10)).unwrap();
+        assert_eq!(split.notes.total_amount(), Amount::from_msats(10));
+        assert_eq!(rest.notes.total_amount(), Amount::from_msats(4));
+        assert_eq!(split.federation_id, transfer.federation_id);
+        assert!(transfer.split(Amount::from_msats(5)).is_none());
+        assert!(transfer.split(Amount::from_msats(15)).is_none());
+
+        let combined = split.clone().combine(rest).unwrap();
+        assert_eq!(combined.notes.total_amount(), Amount::from_msats(14));
+        assert_eq!(combined.notes.count_items(), 3);
+
+        // Notes received twice are only kept once
+        let combined = combined.combine(split.clone()).unwrap();
+        assert_eq!(combined.notes.total_amount(), Amount::from_msats(14));
+
+        let other_federation = EcashTransfer::new(
+            FederationId(threshold_crypto::SecretKey::random().public_key()),
+            split.notes.clone(),
+        );
+        assert!(matches!(
+            split.combine(other_federation),
+            Err(EcashTransferError::FederationMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_rejects_invalid_strings() {
+        let encoded = transfer().encode();
+
+        // A single typo is caught by the checksum
+        let mut typo = encoded.clone().into_bytes();
+        let last = typo.len() - 1;
+        typo[last] = if typo[last] == b'q' { b'p' } else { b'q' };
+        assert!(matches!(
+            EcashTransfer::decode(std::str::from_utf8(&typo).unwrap()),
+            Err(EcashTransferError::Bech32(_))
+        ));
+
+        let (_, data, _) = bech32::decode(&encoded).unwrap();
+        let bech32 = bech32::encode(ECASH_TRANSFER_HRP, &data, Variant::Bech32).unwrap();
+        assert!(matches!(
+            EcashTransfer::decode(&bech32),
+            Err(EcashTransferError::WrongVariant)
+        ));
+
+        let wrong_prefix = bech32::encode("lnbc", &data, Variant::Bech32m).unwrap();
+        assert!(matches!(
+            EcashTransfer::decode(&wrong_prefix),
+            Err(EcashTransferError::WrongPrefix(_))
+        ));
+
+        let future_version =
+            bech32::encode(ECASH_TRANSFER_HRP, [1u8].to_base32(), Variant::Bech32m).unwrap();
+        assert!(matches!(
+            EcashTransfer::decode(&future_version),
+            Err(EcashTransferError::UnsupportedVersion(1))
+        ));
+    }
+}
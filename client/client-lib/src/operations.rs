@@ -0,0 +1,163 @@
+//! History of the operations a client performed, so wallets can show what
+//! happened to their funds without keeping books of their own. Operations
+//! are recorded when they are started and stay `Pending` until
+//! [`crate::Client::update_operations`] learns their final outcome from the
+//! federation.
+//!
+//! While the transactions of an operation are in flight each of them is
+//! tracked by an [`OperationStateMachine`] in the client's database, so
+//! [`crate::Client::resume_operations`] can finish them if the client stopped
+//! halfway through.
+
+use bitcoin_hashes::{sha256, Hash};
+use fedimint_api::core::ModuleInstanceId;
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::{Amount, TransactionId};
+use fedimint_core::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+
+use crate::modules::ln::contracts::ContractId;
+
+/// Identifies an operation. It is derived from what the operation consists
+/// of, e.g. its transaction, so it is known as soon as the operation starts
+/// and never changes.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Encodable,
+    Decodable,
+    Serialize,
+    Deserialize,
+)]
+pub struct OperationId(pub sha256::Hash);
+
+impl OperationId {
+    /// Derives an id from a `tag` naming the kind of operation and the `data`
+    /// identifying it among operations of that kind
+    pub fn derive(tag: &str, data: &impl Encodable) -> Self {
+        let mut engine = sha256::Hash::engine();
+        Encodable::consensus_encode(&tag.as_bytes(), &mut engine).expect("Hashing never fails");
+        Encodable::consensus_encode(data, &mut engine).expect("Hashing never fails");
+        OperationId(sha256::Hash::from_engine(engine))
+    }
+}
+
+impl std::fmt::Display for OperationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl std::str::FromStr for OperationId {
+    type Err = bitcoin_hashes::hex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(OperationId(sha256::Hash::from_str(s)?))
+    }
+}
+
+/// What an operation did and what its outcome is tracked by
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize)]
+pub enum OperationKind {
+    /// Claimed on-chain funds sent to a peg-in address as e-cash
+    PegIn { txid: TransactionId },
+    /// Paid e-cash to have the federation send on-chain funds
+    PegOut { txid: TransactionId },
+    /// Took notes out of the wallet to hand them to someone else
+    SpendEcash,
+    /// Reissued notes received from someone else, in one transaction per
+    /// batch of notes
+    ReceiveEcash { txids: Vec<TransactionId> },
+    /// Paid a lightning invoice, through a gateway or to another user of the
+    /// federation
+    LnPay { contract_id: ContractId },
+    /// Claimed the funds of a paid invoice of ours
+    LnReceive {
+        contract_id: ContractId,
+        txid: TransactionId,
+    },
+    /// Operation of a module instance run by a
+    /// [`crate::extensions::ClientExtension`]
+    Module {
+        module_instance_id: ModuleInstanceId,
+        description: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize)]
+pub enum OperationOutcome {
+    /// The federation did not process the operation yet
+    Pending,
+    Succeeded,
+    /// The operation failed, funds it locked were returned if possible
+    Failed {
+        reason: String,
+    },
+}
+
+impl OperationOutcome {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, OperationOutcome::Pending)
+    }
+}
+
+/// Entry of the operation history
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct OperationRecord {
+    pub id: OperationId,
+    pub kind: OperationKind,
+    /// What the operation was worth to the user, e.g. the amount of a paid
+    /// invoice or of pegged out bitcoin, not including fees
+    pub amount: Amount,
+    pub outcome: OperationOutcome,
+    /// Unix timestamp in seconds of when the operation was started
+    pub created_at: u64,
+    /// Unix timestamp in seconds of the last outcome change
+    pub updated_at: u64,
+}
+
+/// Step the transaction of an unfinished operation is at
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub enum OperationState {
+    /// The transaction was built and its inputs are marked spent in our
+    /// database, but it may not have reached the federation yet
+    Created { transaction: Transaction },
+    /// The federation received the transaction, it awaits consensus
+    Submitted,
+    /// The federation accepted the transaction, the notes it issues to us
+    /// still have to be fetched. Lightning payments are tracked by their
+    /// [`crate::ln::outgoing::OutgoingPaymentRecord`] from here on.
+    Accepted,
+}
+
+/// Persisted progress of one transaction of an operation, it is removed once
+/// the transaction's last step completed or it failed
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct OperationStateMachine {
+    pub operation: OperationId,
+    pub state: OperationState,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bitcoin_hashes::{sha256, Hash};
+
+    use super::OperationId;
+
+    #[test]
+    fn test_operation_ids() {
+        let txid = sha256::Hash::hash(b"tx");
+        let id = OperationId::derive("peg-in", &txid);
+        assert_eq!(id, OperationId::derive("peg-in", &txid));
+        assert_ne!(id, OperationId::derive("peg-out", &txid));
+        assert_eq!(id, OperationId::from_str(&id.to_string()).unwrap());
+    }
+}
@@ -0,0 +1,165 @@
+//! Key management of the client. All keys of a client are derived from a
+//! single [`ClientSecret`], which in turn is the seed of a BIP39 mnemonic, so
+//! writing down the mnemonic's words backs up every federation's notes.
+//!
+//! Wallets implementing the same hierarchy can restore each other's funds:
+//!
+//! * The 64 byte BIP39 seed of the mnemonic (with an empty passphrase) is the
+//!   [`ClientSecret`]. It is turned into the root [`DerivableSecret`] by HKDF
+//!   with the salt `Fedimint Client Salt`.
+//! * Child [`MINT_SECRET_CHILD_ID`] (0) of the root is the mint module's
+//!   secret. Its child 0 derives the nonces and blinding keys of e-cash notes,
+//!   child 1 the keys encrypting and signing e-cash backups.
+//! * Child [`FEDERATION_SECRET_CHILD_ID`] (1) of the root is the parent of the
+//!   secrets of wallets holding notes of several federations. Its child with
+//!   the id of the first 8 bytes (little endian) of the sha256 hash of a
+//!   federation's id is the [`ClientSecret`] of that federation's client, which
+//!   derives its module secrets like above. See [`federation_secret`].
+//!
+//! Clients created before mnemonics were introduced have a random
+//! [`ClientSecret`] without a mnemonic, it can only be exported as hex.
+
+use std::fmt::{Debug, Formatter};
+use std::str::FromStr;
+
+pub use bip39::Mnemonic;
+use bitcoin_hashes::hex::{FromHex, ToHex};
+use bitcoin_hashes::{sha256, Hash};
+use fedimint_api::config::FederationId;
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_derive_secret::{ChildId, DerivableSecret};
+use rand::distributions::{Distribution, Standard};
+use rand::{CryptoRng, Rng, RngCore};
+use serde::Serialize;
+
+/// Mint module's secret key derivation child id
+pub const MINT_SECRET_CHILD_ID: ChildId = ChildId(0);
+/// Child of the secret of [`crate::federations::FederationClients`] the
+/// secrets of the federations' clients are derived from
+pub const FEDERATION_SECRET_CHILD_ID: ChildId = ChildId(1);
+
+/// Bytes of entropy of generated mnemonics, resulting in 12 words
+const MNEMONIC_ENTROPY_LEN: usize = 16;
+
+#[derive(Clone, Encodable, Decodable)]
+pub struct ClientSecret(pub(crate) [u8; 64]);
+
+impl ClientSecret {
+    /// The secret of the BIP39 `mnemonic`, its seed with an empty passphrase
+    pub fn from_mnemonic(mnemonic: &Mnemonic) -> Self {
+        ClientSecret(mnemonic.to_seed_normalized(""))
+    }
+
+    pub(crate) fn into_root_secret(self) -> DerivableSecret {
+        const FEDIMINT_CLIENT_NONCE: &[u8] = b"Fedimint Client Salt";
+        DerivableSecret::new_root(&self.0, FEDIMINT_CLIENT_NONCE)
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.0.to_hex()
+    }
+}
+
+/// Generates a new 12 word mnemonic for a client
+pub fn generate_mnemonic(mut rng: impl RngCore + CryptoRng) -> Mnemonic {
+    let mut entropy = [0u8; MNEMONIC_ENTROPY_LEN];
+    rng.fill_bytes(&mut entropy);
+    Mnemonic::from_entropy(&entropy).expect("Entropy has a valid length")
+}
+
+/// Derives the secret of a federation's client, it only depends on the
+/// federation's id so the notes can be restored regardless of the order the
+/// federations were joined in
+pub fn federation_secret(
+    root_secret: &DerivableSecret,
+    federation_id: &FederationId,
+) -> ClientSecret {
+    let id_hash = sha256::Hash::hash(&federation_id.0.to_bytes());
+    let child_id = u64::from_le_bytes(id_hash[..8].try_into().expect("hash has 32 bytes"));
+    ClientSecret(
+        root_secret
+            .child_key(FEDERATION_SECRET_CHILD_ID)
+            .child_key(ChildId(child_id))
+            .to_random_bytes(),
+    )
+}
+
+impl Serialize for ClientSecret {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl Distribution<ClientSecret> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> ClientSecret {
+        let mut secret = [0u8; 64];
+        rng.fill(&mut secret);
+        ClientSecret(secret)
+    }
+}
+
+impl FromStr for ClientSecret {
+    type Err = bitcoin_hashes::hex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = Vec::<u8>::from_hex(s)?;
+        let len = bytes.len();
+        Ok(ClientSecret(bytes.try_into().map_err(|_| {
+            bitcoin_hashes::hex::Error::InvalidLength(128, 2 * len)
+        })?))
+    }
+}
+
+impl Debug for ClientSecret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ClientSecret([redacted])")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bitcoin_hashes::hex::ToHex;
+    use fedimint_api::config::FederationId;
+    use fedimint_derive_secret::DerivableSecret;
+
+    use super::{federation_secret, generate_mnemonic, ClientSecret, Mnemonic};
+
+    #[test]
+    fn test_mnemonic_secret() {
+        // Test vector of BIP39 with an empty passphrase
+        let mnemonic = Mnemonic::from_str(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap();
+        assert_eq!(
+            ClientSecret::from_mnemonic(&mnemonic).0.to_hex(),
+            "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc19a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4"
+        );
+
+        let generated = generate_mnemonic(rand::thread_rng());
+        assert_eq!(generated.word_count(), 12);
+        assert_eq!(
+            Mnemonic::from_str(&generated.to_string()).unwrap(),
+            generated
+        );
+    }
+
+    #[test]
+    fn test_federation_secrets_differ() {
+        let root_secret = DerivableSecret::new_root(&[42; 64], b"test");
+        let federation = FederationId(threshold_crypto::SecretKey::random().public_key());
+        let other = FederationId(threshold_crypto::SecretKey::random().public_key());
+
+        let secret = federation_secret(&root_secret, &federation);
+        assert_eq!(secret.0, federation_secret(&root_secret, &federation).0);
+        assert_ne!(secret.0, federation_secret(&root_secret, &other).0);
+
+        let other_root = DerivableSecret::new_root(&[43; 64], b"test");
+        assert_ne!(secret.0, federation_secret(&other_root, &federation).0);
+    }
+}
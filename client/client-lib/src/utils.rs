@@ -1,30 +1,18 @@
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
 use bitcoin::{secp256k1, Network};
 use bitcoin_hashes::hex::FromHex;
 use fedimint_api::config::ModuleGenRegistry;
+use fedimint_api::core::ModuleInstanceId;
 use fedimint_api::db::Database;
-use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::encoding::Decodable;
 use fedimint_api::module::registry::ModuleDecoderRegistry;
-use fedimint_api::{ParseAmountError, TieredMulti};
+use fedimint_api::ParseAmountError;
 use fedimint_core::api::DynFederationApi;
 use lightning_invoice::Currency;
 
-use crate::mint::SpendableNote;
-
-pub fn parse_ecash(s: &str) -> anyhow::Result<TieredMulti<SpendableNote>> {
-    let bytes = base64::decode(s)?;
-    Ok(Decodable::consensus_decode(
-        &mut std::io::Cursor::new(bytes),
-        &ModuleDecoderRegistry::default(),
-    )?)
-}
-
-pub fn serialize_ecash(c: &TieredMulti<SpendableNote>) -> String {
-    let mut bytes = Vec::new();
-    Encodable::consensus_encode(c, &mut bytes).expect("encodes correctly");
-    base64::encode(&bytes)
-}
+use crate::extensions::ClientExtensionInstance;
 
 pub fn from_hex<D: Decodable>(s: &str) -> Result<D, anyhow::Error> {
     let bytes = Vec::from_hex(s)?;
@@ -67,6 +55,9 @@ pub struct ClientContext {
     pub db: Database,
     pub api: DynFederationApi,
     pub secp: secp256k1_zkp::Secp256k1<secp256k1_zkp::All>,
+    /// Extensions for the module instances of the federation that aren't
+    /// built into the client
+    pub extensions: BTreeMap<ModuleInstanceId, ClientExtensionInstance>,
 }
 
 pub fn network_to_currency(network: Network) -> Currency {
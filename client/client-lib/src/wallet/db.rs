@@ -1,13 +1,18 @@
-use bitcoin::Script;
+use bitcoin::{Script, Transaction};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::impl_db_prefix_const;
 use serde::Serialize;
 use strum_macros::EnumIter;
 
+use super::peg_out::PegOutDescriptor;
+
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
 pub enum DbKeyPrefix {
     PegIn = 0x22,
+    RebalancePegIn = 0x30,
+    PegOutDescriptor = 0x40,
+    NextPegOutIndex = 0x41,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -30,3 +35,35 @@ impl_db_prefix_const!(
     prefix = DbKeyPrefix::PegIn,
     key_prefix = PegInPrefixKey
 );
+
+/// Transaction of the gateway's lightning node paying a peg-in of the gateway
+/// that wasn't claimed yet
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct RebalancePegInKey;
+
+impl_db_prefix_const!(
+    key = RebalancePegInKey,
+    value = Transaction,
+    prefix = DbKeyPrefix::RebalancePegIn
+);
+
+/// Descriptor of the wallet all peg-outs have to go to, if the user restricted
+/// them to one
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct PegOutDescriptorKey;
+
+impl_db_prefix_const!(
+    key = PegOutDescriptorKey,
+    value = PegOutDescriptor,
+    prefix = DbKeyPrefix::PegOutDescriptor
+);
+
+/// Derivation index of the next peg-out address to hand out
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct NextPegOutIndexKey;
+
+impl_db_prefix_const!(
+    key = NextPegOutIndexKey,
+    value = u32,
+    prefix = DbKeyPrefix::NextPegOutIndex
+);
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use bitcoin::Address;
 use bitcoin::KeyPair;
-use db::PegInKey;
+use db::{NextPegOutIndexKey, PegInKey, PegOutDescriptorKey};
 use fedimint_api::core::client::ClientModule;
 use fedimint_api::db::DatabaseTransaction;
 use fedimint_api::module::TransactionItemAmount;
@@ -19,12 +19,14 @@ use crate::modules::wallet::tweakable::Tweakable;
 use crate::modules::wallet::txoproof::{PegInProof, PegInProofError, TxOutProof};
 use crate::modules::wallet::WalletInput;
 use crate::modules::wallet::WalletOutput;
-use crate::modules::wallet::{Wallet, WalletOutputOutcome};
+use crate::modules::wallet::{PegInAcceleration, Wallet, WalletOutputOutcome};
 use crate::outcome::legacy::OutputOutcome;
 use crate::utils::ClientContext;
+use crate::wallet::peg_out::{PegOutAddressProvider, PegOutDescriptor};
 use crate::MemberError;
 
 pub mod db;
+pub mod peg_out;
 
 /// Federation module client for the Wallet module. It can both create
 /// transaction inputs and outputs of the wallet (on-chain) type.
@@ -44,16 +46,17 @@ impl ClientModule for WalletClient {
     }
 
     fn input_amount(&self, input: &WalletInput) -> TransactionItemAmount {
+        let amount = bitcoin::Amount::from_sat(input.tx_output().value);
         TransactionItemAmount {
-            amount: Amount::from_sats(input.tx_output().value),
-            fee: self.config.fee_consensus.peg_in_abs,
+            amount: amount.into(),
+            fee: self.config.fee_consensus.peg_in_fee(amount),
         }
     }
 
     fn output_amount(&self, output: &WalletOutput) -> TransactionItemAmount {
         TransactionItemAmount {
             amount: (output.amount + output.fees.amount()).into(),
-            fee: self.config.fee_consensus.peg_out_abs,
+            fee: self.config.fee_consensus.peg_out_fee(output.amount),
         }
     }
 }
@@ -104,39 +107,40 @@ impl WalletClient {
         address
     }
 
+    /// Finds the output of `btc_transaction` paying to one of our peg-in
+    /// addresses, returns its index and the key the address was tweaked with
+    async fn find_peg_in_output(
+        &self,
+        btc_transaction: &bitcoin::Transaction,
+    ) -> Result<(usize, KeyPair)> {
+        for (idx, out) in btc_transaction.output.iter().enumerate() {
+            debug!(output_script = ?out.script_pubkey);
+            let tweak_secret = self
+                .context
+                .db
+                .begin_transaction()
+                .await
+                .get_value(&PegInKey {
+                    peg_in_script: out.script_pubkey.clone(),
+                })
+                .await
+                .expect("DB error");
+            if let Some(tweak_secret) = tweak_secret {
+                let secret_tweak_key =
+                    bitcoin::KeyPair::from_seckey_slice(&self.context.secp, &tweak_secret)
+                        .expect("sec key was generated and saved by us");
+                return Ok((idx, secret_tweak_key));
+            }
+        }
+        Err(WalletClientError::NoMatchingPegInFound)
+    }
+
     pub async fn create_pegin_input(
         &self,
         txout_proof: TxOutProof,
         btc_transaction: bitcoin::Transaction,
     ) -> Result<(KeyPair, PegInProof)> {
-        let output_function = || async {
-            for (idx, out) in btc_transaction.output.iter().enumerate() {
-                debug!(output_script = ?out.script_pubkey);
-                let result = self
-                    .context
-                    .db
-                    .begin_transaction()
-                    .await
-                    .get_value(&PegInKey {
-                        peg_in_script: out.script_pubkey.clone(),
-                    })
-                    .await
-                    .expect("DB error")
-                    .map(|tweak_secret| (idx, tweak_secret));
-                if result.is_some() {
-                    return result;
-                }
-            }
-            None
-        };
-
-        let (output_idx, secret_tweak_key_bytes) = output_function()
-            .await
-            .ok_or(WalletClientError::NoMatchingPegInFound)?;
-
-        let secret_tweak_key =
-            bitcoin::KeyPair::from_seckey_slice(&self.context.secp, &secret_tweak_key_bytes)
-                .expect("sec key was generated and saved by us");
+        let (output_idx, secret_tweak_key) = self.find_peg_in_output(&btc_transaction).await?;
 
         let peg_in_proof = PegInProof::new(
             txout_proof,
@@ -150,8 +154,9 @@ impl WalletClient {
             .verify(&self.context.secp, &self.config.peg_in_descriptor)
             .map_err(WalletClientError::PegInProofError)?;
 
-        let amount = Amount::from_sats(peg_in_proof.tx_output().value)
-            .saturating_sub(self.config.fee_consensus.peg_in_abs);
+        let deposit = bitcoin::Amount::from_sat(peg_in_proof.tx_output().value);
+        let amount =
+            Amount::from(deposit).saturating_sub(self.config.fee_consensus.peg_in_fee(deposit));
         if amount == Amount::ZERO {
             return Err(WalletClientError::PegInAmountTooSmall);
         }
@@ -161,10 +166,128 @@ impl WalletClient {
         Ok((secret_tweak_key, peg_in_proof))
     }
 
+    /// Creates a request for the federation to accelerate our unconfirmed
+    /// deposit `btc_transaction`
+    pub async fn create_peg_in_acceleration(
+        &self,
+        btc_transaction: bitcoin::Transaction,
+    ) -> Result<PegInAcceleration> {
+        let (output_idx, secret_tweak_key) = self.find_peg_in_output(&btc_transaction).await?;
+        Ok(PegInAcceleration {
+            tx: btc_transaction,
+            output_idx: output_idx as u32,
+            tweak_contract_key: secret_tweak_key.x_only_public_key().0,
+        })
+    }
+
+    /// Restricts peg-outs to addresses of `descriptor`, or lifts the
+    /// restriction if it is `None`. Addresses are handed out from the first
+    /// index of the descriptor on again.
+    pub async fn set_peg_out_descriptor(&self, descriptor: Option<PegOutDescriptor>) {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        match descriptor {
+            Some(descriptor) => {
+                dbtx.insert_entry(&PegOutDescriptorKey, &descriptor)
+                    .await
+                    .expect("DB error");
+            }
+            None => {
+                dbtx.remove_entry(&PegOutDescriptorKey)
+                    .await
+                    .expect("DB error");
+            }
+        }
+        dbtx.remove_entry(&NextPegOutIndexKey)
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB error");
+    }
+
+    pub async fn peg_out_descriptor(&self) -> Option<PegOutDescriptor> {
+        self.context
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&PegOutDescriptorKey)
+            .await
+            .expect("DB error")
+    }
+
+    /// Hands out the next address of the peg-out descriptor. If a `signer`
+    /// holding the descriptor's keys is given, the address is requested from
+    /// it and only returned if it is the one we derive ourselves.
+    pub async fn new_peg_out_address(
+        &self,
+        signer: Option<&dyn PegOutAddressProvider>,
+    ) -> Result<Address> {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        let descriptor = dbtx
+            .get_value(&PegOutDescriptorKey)
+            .await
+            .expect("DB error")
+            .ok_or(WalletClientError::NoPegOutDescriptor)?;
+        let index = dbtx
+            .get_value(&NextPegOutIndexKey)
+            .await
+            .expect("DB error")
+            .unwrap_or(0);
+
+        let address = descriptor
+            .address(&self.context.secp, index, self.config.network)
+            .map_err(WalletClientError::InvalidPegOutDescriptor)?;
+        if let Some(signer) = signer {
+            let signer_address = signer
+                .peg_out_address(index)
+                .await
+                .map_err(WalletClientError::PegOutAddressProvider)?;
+            if signer_address != address {
+                return Err(WalletClientError::UnexpectedPegOutAddress(signer_address));
+            }
+        }
+
+        dbtx.insert_entry(&NextPegOutIndexKey, &(index + 1))
+            .await
+            .expect("DB error");
+        dbtx.commit_tx().await.expect("DB error");
+        Ok(address)
+    }
+
+    /// Checks that we may peg out to `recipient`: if peg-outs are restricted
+    /// to a descriptor it has to be one of the addresses handed out for it
+    pub async fn check_peg_out_address(&self, recipient: &Address) -> Result<()> {
+        let mut dbtx = self.context.db.begin_transaction().await;
+        let descriptor = match dbtx
+            .get_value(&PegOutDescriptorKey)
+            .await
+            .expect("DB error")
+        {
+            Some(descriptor) => descriptor,
+            None => return Ok(()),
+        };
+        let handed_out = dbtx
+            .get_value(&NextPegOutIndexKey)
+            .await
+            .expect("DB error")
+            .unwrap_or(0);
+
+        if recipient.network != self.config.network
+            || descriptor
+                .find_index(&self.context.secp, recipient, 0..handed_out)
+                .is_none()
+        {
+            return Err(WalletClientError::UnexpectedPegOutAddress(
+                recipient.clone(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the output of the Bitcoin transaction paying the peg-out, which
+    /// may be shared with other peg-outs of the same epoch
     pub async fn await_peg_out_outcome(
         &self,
         out_point: fedimint_api::OutPoint,
-    ) -> Result<bitcoin::Txid> {
+    ) -> Result<bitcoin::OutPoint> {
         // TODO: define timeout centrally
         let timeout = std::time::Duration::from_secs(15);
         let outcome: WalletOutputOutcome = self
@@ -191,6 +314,14 @@ pub enum WalletClientError {
     OutputOutcomeError(#[from] OutputOutcomeError),
     #[error("Mint API error: {0}")]
     ApiError(#[from] MemberError),
+    #[error("Peg-outs aren't restricted to a descriptor")]
+    NoPegOutDescriptor,
+    #[error("Can't derive peg-out address: {0}")]
+    InvalidPegOutDescriptor(anyhow::Error),
+    #[error("External signer failed to provide a peg-out address: {0}")]
+    PegOutAddressProvider(anyhow::Error),
+    #[error("Address {0} was not derived from the peg-out descriptor")]
+    UnexpectedPegOutAddress(Address),
 }
 
 #[cfg(test)]
@@ -252,7 +383,10 @@ mod tests {
                     epoch: 0,
                     outputs: vec![SerdeOutputOutcome::from(&DynOutputOutcome::from_typed(
                         module_id,
-                        WalletOutputOutcome(Txid::from_slice([0; 32].as_slice()).unwrap()),
+                        WalletOutputOutcome(bitcoin::OutPoint {
+                            txid: Txid::from_slice([0; 32].as_slice()).unwrap(),
+                            vout: 0,
+                        }),
                     ))],
                 })
             },
@@ -287,10 +421,10 @@ mod tests {
                         .await?)
                     }
                 },
-                &ConfigGenParams::new().attach(WalletGenParams {
-                    network: bitcoin::network::constants::Network::Regtest,
-                    finality_delay: 10,
-                }),
+                &ConfigGenParams::new().attach(WalletGenParams::new(
+                    bitcoin::network::constants::Network::Regtest,
+                    10,
+                )),
                 &WalletGen,
                 module_id,
             )
@@ -307,6 +441,7 @@ mod tests {
             db: Database::new(MemDatabase::new(), module_decode_stubs()),
             api: api.into(),
             secp: secp256k1_zkp::Secp256k1::new(),
+            extensions: Default::default(),
         };
 
         (
@@ -0,0 +1,125 @@
+//! Restricts peg-outs to addresses of a wallet of the user's own, e.g. a
+//! hardware wallet. The client is given the wallet's descriptor (or just its
+//! xpub) and only pegs out to addresses derived from it. Addresses obtained
+//! from an external signer are checked against the descriptor before they are
+//! handed out, so a compromised host can't slip in an address of its own.
+
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::util::bip32::ExtendedPubKey;
+use bitcoin::{Address, Network};
+use fedimint_api::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use miniscript::{Descriptor, DescriptorPublicKey};
+
+/// Descriptor of the wallet peg-outs are sent to. It has to contain a
+/// wildcard, the addresses handed out for peg-outs are derived at increasing
+/// indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PegOutDescriptor(Descriptor<DescriptorPublicKey>);
+
+impl PegOutDescriptor {
+    pub fn address<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        index: u32,
+        network: Network,
+    ) -> anyhow::Result<Address> {
+        Ok(self.0.derived_descriptor(secp, index)?.address(network)?)
+    }
+
+    /// Index of `address` among the ones derived at `indices`, `None` if it
+    /// isn't derived at any of them
+    pub fn find_index<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        address: &Address,
+        indices: Range<u32>,
+    ) -> Option<u32> {
+        indices.into_iter().find(|index| {
+            self.address(secp, *index, address.network)
+                .map_or(false, |derived| &derived == address)
+        })
+    }
+}
+
+/// Parses an output descriptor or an xpub, the latter is taken to be a native
+/// segwit wallet receiving at `<xpub>/0/*`
+impl FromStr for PegOutDescriptor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let descriptor = if ExtendedPubKey::from_str(s).is_ok() {
+            Descriptor::from_str(&format!("wpkh({s}/0/*)"))?
+        } else {
+            Descriptor::from_str(s)?
+        };
+        if !descriptor.has_wildcard() {
+            return Err(anyhow::anyhow!(
+                "Peg-out descriptor has to derive addresses at a wildcard"
+            ));
+        }
+        Ok(PegOutDescriptor(descriptor))
+    }
+}
+
+impl fmt::Display for PegOutDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Encodable for PegOutDescriptor {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        self.to_string().consensus_encode(writer)
+    }
+}
+
+impl Decodable for PegOutDescriptor {
+    fn consensus_decode<D: std::io::Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        PegOutDescriptor::from_str(&String::consensus_decode(d, modules)?)
+            .map_err(|_| DecodeError::from_str("Invalid peg-out descriptor"))
+    }
+}
+
+/// External signer, e.g. a hardware wallet, providing the addresses of the
+/// [`PegOutDescriptor`] so the user can confirm them on the device
+#[async_trait]
+pub trait PegOutAddressProvider: Send + Sync {
+    /// Address the signer derives at `index` of its descriptor
+    async fn peg_out_address(&self, index: u32) -> anyhow::Result<Address>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::Network;
+
+    use super::PegOutDescriptor;
+
+    const XPUB: &str = "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp";
+
+    #[test]
+    fn derives_from_xpub() {
+        let secp = Secp256k1::verification_only();
+        let xpub = PegOutDescriptor::from_str(XPUB).unwrap();
+        let descriptor = PegOutDescriptor::from_str(&format!("wpkh({XPUB}/0/*)")).unwrap();
+        assert_eq!(xpub, descriptor);
+        assert_eq!(PegOutDescriptor::from_str(&xpub.to_string()).unwrap(), xpub);
+
+        let address = xpub.address(&secp, 3, Network::Regtest).unwrap();
+        assert_eq!(xpub.find_index(&secp, &address, 0..10), Some(3));
+        assert_eq!(xpub.find_index(&secp, &address, 0..3), None);
+
+        assert!(PegOutDescriptor::from_str(&format!("wpkh({XPUB}/0/1)")).is_err());
+    }
+}
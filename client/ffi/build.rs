@@ -0,0 +1,3 @@
+fn main() {
+    uniffi::generate_scaffolding("src/fedimint.udl").expect("Invalid UDL definition");
+}
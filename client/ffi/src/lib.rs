@@ -0,0 +1,433 @@
+//! UniFFI bindings of the client library, so Kotlin and Swift wallets can use
+//! a federation without hand-written FFI code. The interface is declared in
+//! `src/fedimint.udl`; the bindings for a platform are generated from the
+//! library built for it with
+//! `cargo run --bin uniffi-bindgen generate src/fedimint.udl --language kotlin`
+//! (or `--language swift`).
+//!
+//! All calls block until they finished, wallets have to make them off their
+//! UI thread. Every client drives its futures on a runtime of its own.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use fedimint_api::config::{ClientConfig, ModuleGenRegistry};
+use fedimint_api::core::{
+    LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
+    LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+};
+use fedimint_api::db::Database;
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::module::DynModuleGen;
+use fedimint_api::task::TaskGroup;
+use fedimint_api::Amount;
+use fedimint_core::api::{
+    GlobalFederationApi, IFederationApi, WsClientConnectInfo, WsFederationApi,
+};
+use fedimint_core::config::load_from_file;
+use fedimint_core::meta;
+use fedimint_mint::common::MintDecoder;
+use fedimint_mint::MintGen;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use mint_client::ln::GatewaySelection;
+use mint_client::mint::transfer::EcashTransfer;
+use mint_client::mint::MintClientError;
+use mint_client::modules::ln::common::LightningDecoder;
+use mint_client::modules::ln::LightningGen;
+use mint_client::modules::wallet::common::WalletDecoder;
+use mint_client::modules::wallet::WalletGen;
+use mint_client::operations::{OperationKind, OperationOutcome, OperationRecord};
+use mint_client::{events, module_decode_stubs, Client, ClientError, Mnemonic, UserClientConfig};
+use tokio::runtime::Runtime;
+
+uniffi::include_scaffolding!("fedimint");
+
+const CONFIG_FILE: &str = "client.json";
+const DB_FILE: &str = "client.db";
+/// Gateways tried before a lightning payment is given up
+const MAX_PAYMENT_ATTEMPTS: usize = 3;
+
+/// Errors reported to wallets, they only tell what kind of failure happened
+/// and carry the message of the underlying error
+#[derive(Debug, thiserror::Error)]
+pub enum FedimintError {
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("Database error: {0}")]
+    Database(String),
+    #[error("Insufficient balance: {0}")]
+    InsufficientBalance(String),
+    #[error("Federation error: {0}")]
+    Federation(String),
+}
+
+impl From<ClientError> for FedimintError {
+    fn from(e: ClientError) -> Self {
+        match &e {
+            ClientError::MintClientError(MintClientError::InsufficientBalance(..)) => {
+                FedimintError::InsufficientBalance(e.to_string())
+            }
+            ClientError::MintApiError(_) => FedimintError::Network(e.to_string()),
+            _ => FedimintError::Federation(e.to_string()),
+        }
+    }
+}
+
+pub enum OperationStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+pub struct Operation {
+    pub id: String,
+    pub kind: String,
+    pub amount_msat: u64,
+    pub status: OperationStatus,
+    pub failure_reason: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl From<OperationRecord> for Operation {
+    fn from(record: OperationRecord) -> Self {
+        let kind = match record.kind {
+            OperationKind::PegIn { .. } => "peg-in",
+            OperationKind::PegOut { .. } => "peg-out",
+            OperationKind::SpendEcash => "spend-ecash",
+            OperationKind::ReceiveEcash { .. } => "receive-ecash",
+            OperationKind::LnPay { .. } => "ln-pay",
+            OperationKind::LnReceive { .. } => "ln-receive",
+            OperationKind::Module { .. } => "module",
+        };
+        let (status, failure_reason) = match record.outcome {
+            OperationOutcome::Pending => (OperationStatus::Pending, None),
+            OperationOutcome::Succeeded => (OperationStatus::Succeeded, None),
+            OperationOutcome::Failed { reason } => (OperationStatus::Failed, Some(reason)),
+        };
+        Operation {
+            id: record.id.to_string(),
+            kind: kind.to_string(),
+            amount_msat: record.amount.msats,
+            status,
+            failure_reason,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+pub struct FederationMeta {
+    pub name: String,
+    pub icon_url: Option<String>,
+    pub message_of_the_day: Option<String>,
+    pub fee_schedule_msat: HashMap<String, u64>,
+}
+
+impl From<meta::FederationMeta> for FederationMeta {
+    fn from(meta: meta::FederationMeta) -> Self {
+        FederationMeta {
+            name: meta.name,
+            icon_url: meta.icon_url,
+            message_of_the_day: meta.message_of_the_day,
+            fee_schedule_msat: meta
+                .fee_schedule
+                .into_iter()
+                .map(|(operation, fee)| (operation, fee.msats))
+                .collect(),
+        }
+    }
+}
+
+pub enum ClientEvent {
+    BalanceChanged { balance_msat: u64 },
+    OperationStarted { operation: Operation },
+    OperationFinished { operation: Operation },
+    FederationMetaChanged { meta: FederationMeta },
+}
+
+impl From<events::ClientEvent> for ClientEvent {
+    fn from(event: events::ClientEvent) -> Self {
+        match event {
+            events::ClientEvent::BalanceChanged { balance } => ClientEvent::BalanceChanged {
+                balance_msat: balance.msats,
+            },
+            events::ClientEvent::OperationStarted(record) => ClientEvent::OperationStarted {
+                operation: record.into(),
+            },
+            events::ClientEvent::OperationFinished(record) => ClientEvent::OperationFinished {
+                operation: record.into(),
+            },
+            events::ClientEvent::FederationMetaChanged(meta) => {
+                ClientEvent::FederationMetaChanged { meta: meta.into() }
+            }
+        }
+    }
+}
+
+/// Events of a client from the time it was subscribed to on
+pub struct EventStream {
+    runtime: Arc<Runtime>,
+    events: Mutex<BoxStream<'static, events::ClientEvent>>,
+}
+
+impl EventStream {
+    /// Waits for the next event
+    pub fn next(&self) -> ClientEvent {
+        let mut events = self.events.lock().expect("Lock poisoned");
+        self.runtime
+            .block_on(events.next())
+            .expect("Event stream never ends")
+            .into()
+    }
+}
+
+/// Client of one federation, its config and database are kept in a directory
+/// chosen by the wallet
+pub struct FedimintClient {
+    runtime: Arc<Runtime>,
+    client: Client<UserClientConfig>,
+}
+
+impl FedimintClient {
+    /// Joins the federation the `invite` code is for and stores its client in
+    /// `data_dir`. If a `mnemonic` is given the client derives its keys from
+    /// it, so funds backed up under it can be restored.
+    pub fn join(
+        data_dir: String,
+        invite: String,
+        mnemonic: Option<String>,
+    ) -> Result<Self, FedimintError> {
+        let data_dir = PathBuf::from(data_dir);
+        let cfg_path = data_dir.join(CONFIG_FILE);
+        if cfg_path.exists() {
+            return Err(FedimintError::InvalidInput(
+                "a federation was already joined in this directory".into(),
+            ));
+        }
+        let connect = WsClientConnectInfo::from_str(&invite)
+            .map_err(|e| FedimintError::InvalidInput(format!("invalid invite code: {e}")))?;
+        let mnemonic = mnemonic
+            .map(|words| Mnemonic::from_str(&words))
+            .transpose()
+            .map_err(|e| FedimintError::InvalidInput(format!("invalid mnemonic: {e}")))?;
+
+        let runtime = new_runtime()?;
+        let cfg: ClientConfig = runtime.block_on(async {
+            let api = Arc::new(WsFederationApi::from_urls(&connect))
+                as Arc<dyn IFederationApi + Send + Sync + 'static>;
+            api.download_client_config_from_all_peers(&connect.id, module_gens())
+                .await
+                .map_err(|e| FedimintError::Network(e.to_string()))
+        })?;
+
+        std::fs::create_dir_all(&data_dir).map_err(|e| FedimintError::Database(e.to_string()))?;
+        let db = open_db(&data_dir)?;
+        if let Some(mnemonic) = mnemonic {
+            runtime.block_on(Client::<UserClientConfig>::import_mnemonic(&db, &mnemonic))?;
+        }
+        let writer =
+            std::fs::File::create(cfg_path).map_err(|e| FedimintError::Database(e.to_string()))?;
+        serde_json::to_writer_pretty(writer, &cfg)
+            .map_err(|e| FedimintError::Database(e.to_string()))?;
+
+        Ok(Self::new(runtime, UserClientConfig(cfg), db))
+    }
+
+    /// Opens the client of a federation joined before in `data_dir`
+    pub fn open(data_dir: String) -> Result<Self, FedimintError> {
+        let data_dir = PathBuf::from(data_dir);
+        let cfg: UserClientConfig = load_from_file(&data_dir.join(CONFIG_FILE))
+            .map_err(|e| FedimintError::InvalidInput(format!("no federation joined: {e}")))?;
+        let db = open_db(&data_dir)?;
+        Ok(Self::new(new_runtime()?, cfg, db))
+    }
+
+    fn new(runtime: Runtime, cfg: UserClientConfig, db: Database) -> Self {
+        let decoders = ModuleDecoderRegistry::from_iter([
+            (LEGACY_HARDCODED_INSTANCE_ID_LN, LightningDecoder.into()),
+            (LEGACY_HARDCODED_INSTANCE_ID_MINT, MintDecoder.into()),
+            (LEGACY_HARDCODED_INSTANCE_ID_WALLET, WalletDecoder.into()),
+        ]);
+        let client = runtime.block_on(async {
+            let client = Client::new(cfg, decoders, module_gens(), db, Default::default()).await;
+            // Finish operations the wallet was closed in the middle of
+            client.resume_operations().await;
+            client
+        });
+        FedimintClient {
+            runtime: Arc::new(runtime),
+            client,
+        }
+    }
+
+    pub fn federation_id(&self) -> String {
+        self.client.config().as_ref().federation_id.to_string()
+    }
+
+    /// The federation's name, icon, message of the day and fee schedule, taken
+    /// from the cache if it was fetched less than `max_age_secs` ago
+    pub fn federation_meta(&self, max_age_secs: u64) -> Result<FederationMeta, FedimintError> {
+        let meta = self.runtime.block_on(
+            self.client
+                .federation_meta(Duration::from_secs(max_age_secs)),
+        )?;
+        Ok(meta.into())
+    }
+
+    /// Words of the mnemonic the client's keys are derived from, `None` for
+    /// clients created before mnemonics were introduced
+    pub fn mnemonic(&self) -> Option<String> {
+        self.runtime
+            .block_on(self.client.export_mnemonic())
+            .map(|mnemonic| mnemonic.to_string())
+    }
+
+    pub fn balance_msat(&self) -> u64 {
+        self.runtime.block_on(self.client.balance()).msats
+    }
+
+    /// Takes notes worth `amount_msat` out of the wallet and returns them as
+    /// an e-cash transfer string to hand to the recipient
+    pub fn spend_ecash(&self, amount_msat: u64) -> Result<String, FedimintError> {
+        let notes = self.runtime.block_on(
+            self.client
+                .spend_ecash(Amount::from_msats(amount_msat), rand::rngs::OsRng),
+        )?;
+        Ok(
+            EcashTransfer::new(self.client.config().as_ref().federation_id.clone(), notes)
+                .to_string(),
+        )
+    }
+
+    /// Reissues the notes of an e-cash transfer string, returns the id of the
+    /// reissuing transaction
+    pub fn receive_ecash(&self, notes: String) -> Result<String, FedimintError> {
+        let transfer = EcashTransfer::decode(&notes)
+            .map_err(|e| FedimintError::InvalidInput(e.to_string()))?;
+        if transfer.federation_id != self.client.config().as_ref().federation_id {
+            return Err(FedimintError::InvalidInput(
+                "notes were issued by a different federation".into(),
+            ));
+        }
+        let out_point = self
+            .runtime
+            .block_on(self.client.reissue(transfer.notes, rand::rngs::OsRng))?;
+        Ok(out_point.txid.to_string())
+    }
+
+    /// Pays a BOLT11 invoice through the cheapest gateway, returns the id of
+    /// the funded contract
+    pub fn pay_invoice(&self, bolt11: String) -> Result<String, FedimintError> {
+        let invoice = lightning_invoice::Invoice::from_str(&bolt11)
+            .map_err(|e| FedimintError::InvalidInput(format!("invalid invoice: {e}")))?;
+        let contract_id = self.runtime.block_on(self.client.pay_invoice(
+            invoice,
+            GatewaySelection::LowestFee,
+            MAX_PAYMENT_ATTEMPTS,
+            rand::rngs::OsRng,
+        ))?;
+        Ok(contract_id.to_string())
+    }
+
+    /// Creates a BOLT11 invoice paid to us through a gateway. The funds are
+    /// claimed by [`FedimintClient::claim_incoming_payments`].
+    pub fn create_invoice(
+        &self,
+        amount_msat: u64,
+        description: String,
+        expiry_secs: Option<u64>,
+    ) -> Result<String, FedimintError> {
+        let confirmed = self
+            .runtime
+            .block_on(self.client.generate_confirmed_invoice(
+                Amount::from_msats(amount_msat),
+                description,
+                rand::rngs::OsRng,
+                expiry_secs,
+            ))?;
+        Ok(confirmed.invoice.to_string())
+    }
+
+    /// Claims the funds of our paid invoices, returns how many were claimed
+    pub fn claim_incoming_payments(&self) -> Result<u32, FedimintError> {
+        let claimed = self.runtime.block_on(
+            self.client
+                .claim_pending_incoming_contracts(rand::rngs::OsRng),
+        )?;
+        Ok(claimed.len() as u32)
+    }
+
+    /// The operation history, newest first
+    pub fn list_operations(&self) -> Vec<Operation> {
+        self.runtime
+            .block_on(self.client.list_operations(None, usize::MAX))
+            .into_iter()
+            .map(Operation::from)
+            .collect()
+    }
+
+    /// Learns the outcomes of pending operations from the federation, returns
+    /// the operations that finished
+    pub fn update_operations(&self) -> Vec<Operation> {
+        self.runtime
+            .block_on(self.client.update_operations())
+            .into_iter()
+            .map(Operation::from)
+            .collect()
+    }
+
+    /// Uploads an encrypted backup of our notes to the federation
+    pub fn backup(&self) -> Result<(), FedimintError> {
+        self.runtime
+            .block_on(self.client.mint_client().back_up_ecash_to_federation())
+            .map_err(|e| FedimintError::Federation(e.to_string()))
+    }
+
+    /// Restores our notes from the federation's backup, checking up to
+    /// `gap_limit` notes issued after it was made. The client has to have
+    /// joined with the mnemonic the backup was made under.
+    pub fn restore(&self, gap_limit: u32) -> Result<(), FedimintError> {
+        self.runtime.block_on(async {
+            let mut task_group = TaskGroup::new();
+            self.client
+                .mint_client()
+                .restore_ecash_from_federation(gap_limit as usize, &mut task_group)
+                .await
+                .map_err(|e| FedimintError::Federation(e.to_string()))?
+                .map_err(|_| FedimintError::Federation("restore was cancelled".into()))
+        })
+    }
+
+    pub fn subscribe_events(&self) -> Arc<EventStream> {
+        let events = self.runtime.block_on(self.client.subscribe_events());
+        Arc::new(EventStream {
+            runtime: self.runtime.clone(),
+            events: Mutex::new(events),
+        })
+    }
+}
+
+fn new_runtime() -> Result<Runtime, FedimintError> {
+    Runtime::new().map_err(|e| FedimintError::Database(format!("couldn't start runtime: {e}")))
+}
+
+fn module_gens() -> ModuleGenRegistry {
+    ModuleGenRegistry::from(vec![
+        DynModuleGen::from(WalletGen),
+        DynModuleGen::from(MintGen),
+        DynModuleGen::from(LightningGen),
+    ])
+}
+
+fn open_db(data_dir: &Path) -> Result<Database, FedimintError> {
+    let db = fedimint_rocksdb::RocksDb::open(data_dir.join(DB_FILE))
+        .map_err(|e| FedimintError::Database(e.to_string()))?;
+    Ok(Database::new(db, module_decode_stubs()))
+}
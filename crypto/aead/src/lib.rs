@@ -19,12 +19,20 @@ pub fn get_random_nonce() -> ring::aead::Nonce {
 /// Encrypt `plaintext` using `key`.
 ///
 /// Prefixes the ciphertext with a nonce.
-pub fn encrypt(mut plaintext: Vec<u8>, key: &LessSafeKey) -> Result<Vec<u8>> {
+pub fn encrypt(plaintext: Vec<u8>, key: &LessSafeKey) -> Result<Vec<u8>> {
+    encrypt_with_aad(plaintext, key, &[])
+}
+
+/// Encrypt `plaintext` using `key`, additionally authenticating `aad` which
+/// has to be supplied again for decryption.
+///
+/// Prefixes the ciphertext with a nonce.
+pub fn encrypt_with_aad(mut plaintext: Vec<u8>, key: &LessSafeKey, aad: &[u8]) -> Result<Vec<u8>> {
     let nonce = get_random_nonce();
     // prefix ciphertext with nonce
     let mut ciphertext: Vec<u8> = nonce.as_ref().to_vec();
 
-    key.seal_in_place_append_tag(nonce, Aad::empty(), &mut plaintext)
+    key.seal_in_place_append_tag(nonce, Aad::from(aad), &mut plaintext)
         .map_err(|_| anyhow::format_err!("Encryption failed due to unspecified aead error"))?;
 
     ciphertext.append(&mut plaintext);
@@ -36,6 +44,17 @@ pub fn encrypt(mut plaintext: Vec<u8>, key: &LessSafeKey) -> Result<Vec<u8>> {
 ///
 /// Expect nonce in the prefix, like [`encrypt`] produces.
 pub fn decrypt<'c>(ciphertext: &'c mut [u8], key: &LessSafeKey) -> Result<&'c [u8]> {
+    decrypt_with_aad(ciphertext, key, &[])
+}
+
+/// Decrypts a `ciphertext` using `key` that was encrypted with the same `aad`.
+///
+/// Expect nonce in the prefix, like [`encrypt_with_aad`] produces.
+pub fn decrypt_with_aad<'c>(
+    ciphertext: &'c mut [u8],
+    key: &LessSafeKey,
+    aad: &[u8],
+) -> Result<&'c [u8]> {
     if ciphertext.len() < NONCE_LEN {
         bail!("Ciphertext too short: {}", ciphertext.len());
     }
@@ -44,7 +63,7 @@ pub fn decrypt<'c>(ciphertext: &'c mut [u8], key: &LessSafeKey) -> Result<&'c [u
 
     key.open_in_place(
         Nonce::assume_unique_for_key(nonce_bytes.try_into().expect("nonce size known")),
-        Aad::empty(),
+        Aad::from(aad),
         encrypted_bytes,
     )
     .map_err(|_| format_err!("Decryption failed due to unspecified aead error"))?;
@@ -88,13 +107,31 @@ pub fn encrypted_read(key: &LessSafeKey, file: PathBuf) -> Result<Vec<u8>> {
 /// discourage rainbow attacks.  HMAC-SHA256 is used for the authentication
 /// code.  All crypto is from the widely-used `ring` crate we also use for TLS.
 pub fn get_key(password: Option<String>, salt_path: PathBuf) -> Result<LessSafeKey> {
+    get_key_with_context(password, salt_path, &[])
+}
+
+/// Like [`get_key`] but mixes `context` into the salt, so keys for different
+/// purposes (e.g. configs and database) can be derived from the same password
+/// and salt file without being the same.
+pub fn get_key_with_context(
+    password: Option<String>,
+    salt_path: PathBuf,
+    context: &[u8],
+) -> Result<LessSafeKey> {
     let password = match password {
         None => rpassword::prompt_password("Enter a password to encrypt configs: ").unwrap(),
         Some(password) => password,
     };
 
     let salt_str = fs::read_to_string(salt_path)?;
-    let salt = hex::decode(salt_str)?;
+    let mut salt = hex::decode(salt_str)?;
+    salt.extend_from_slice(context);
+    derive_key(&password, &salt)
+}
+
+/// Stretches `password` into a key with PBKDF2, see [`get_key`] for the
+/// parameters used
+pub fn derive_key(password: &str, salt: &[u8]) -> Result<LessSafeKey> {
     let mut key = [0u8; ring::digest::SHA256_OUTPUT_LEN];
     let algo = ring::pbkdf2::PBKDF2_HMAC_SHA256;
     ring::pbkdf2::derive(
@@ -104,7 +141,7 @@ pub fn get_key(password: Option<String>, salt_path: PathBuf) -> Result<LessSafeK
         } else {
             ITERATIONS_PROD.unwrap()
         },
-        &salt,
+        salt,
         password.as_bytes(),
         &mut key,
     );
@@ -1,5 +1,7 @@
 use std::ffi::OsStr;
+use std::str::FromStr;
 
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 /// Name of the env value used for passing bitcoind rpc url to modules that need
@@ -10,20 +12,62 @@ pub const FM_BITCOIND_RPC_ENV: &str = "FM_BITCOIND_RPC";
 /// it
 pub const FM_ELECTRUM_RPC_ENV: &str = "FM_ELECTRUM_RPC";
 
+/// Name of the env value used for passing esplora api url to modules that need
+/// it
+pub const FM_ESPLORA_RPC_ENV: &str = "FM_ESPLORA_RPC";
+
+/// Name of the env value used for passing a comma separated list of additional
+/// fee estimation sources, which have to expose an esplora compatible api (e.g.
+/// `https://mempool.space/api`)
+pub const FM_FEE_SOURCES_ENV: &str = "FM_FEE_SOURCES";
+
+/// Name of the env value used for passing the rpc url of a (possibly pruned)
+/// bitcoind with `blockfilterindex=1`, which is only queried for the blocks
+/// whose BIP158 compact filters match our scripts
+pub const FM_BITCOIND_FILTERS_RPC_ENV: &str = "FM_BITCOIND_FILTERS_RPC";
+
 /// Default url that will be used if [`FM_BITCOIND_RPC_ENV`] is not set
 pub const FM_BITCOIND_RPC_DEFAULT_FALLBACK: &str = "http://127.0.0.1:8332";
 
 /// Bitcoin RPC backend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "url", rename_all = "snake_case")]
 pub enum BitcoindRpcBackend {
     /// Bitcoin Core RPC
     Bitcoind(Url),
     /// Electrum RPC
     Electrum(Url),
+    /// Esplora HTTP API
+    Esplora(Url),
+    /// Bitcoin Core RPC using BIP158 compact block filters
+    CompactFilters(Url),
 }
 
+impl FromStr for BitcoindRpcBackend {
+    type Err = anyhow::Error;
+
+    /// Parses `<kind>:<url>`, e.g. `esplora:https://blockstream.info/api`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, url) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow::format_err!("Expected <kind>:<url>, got {s}"))?;
+        let url = Url::parse(url)?;
+        Ok(match kind {
+            "bitcoind" => BitcoindRpcBackend::Bitcoind(url),
+            "electrum" => BitcoindRpcBackend::Electrum(url),
+            "esplora" => BitcoindRpcBackend::Esplora(url),
+            "compact_filters" => BitcoindRpcBackend::CompactFilters(url),
+            kind => anyhow::bail!("Unknown bitcoin backend {kind}"),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BitcoinRpcBackendType {
     Bitcoind,
     Electrum,
+    Esplora,
+    CompactFilters,
 }
 
 /// Get the value of url the module would use by reading it from process
@@ -34,17 +78,28 @@ pub fn read_bitcoin_backend_from_global_env() -> anyhow::Result<BitcoindRpcBacke
     select_bitcoin_backend_from_envs(
         std::env::var_os(FM_BITCOIND_RPC_ENV).as_deref(),
         std::env::var_os(FM_ELECTRUM_RPC_ENV).as_deref(),
+        std::env::var_os(FM_ESPLORA_RPC_ENV).as_deref(),
+        std::env::var_os(FM_BITCOIND_FILTERS_RPC_ENV).as_deref(),
     )
 }
 
+/// Selects the backend from the values of [`FM_BITCOIND_FILTERS_RPC_ENV`],
+/// [`FM_BITCOIND_RPC_ENV`], [`FM_ELECTRUM_RPC_ENV`] and [`FM_ESPLORA_RPC_ENV`],
+/// in that order of precedence, falling back to a local bitcoind
 pub fn select_bitcoin_backend_from_envs(
     bitcoind_rpc: Option<&OsStr>,
     electrum_rpc: Option<&OsStr>,
+    esplora_rpc: Option<&OsStr>,
+    bitcoind_filters_rpc: Option<&OsStr>,
 ) -> anyhow::Result<BitcoindRpcBackend> {
-    Ok(if let Some(val) = bitcoind_rpc {
+    Ok(if let Some(val) = bitcoind_filters_rpc {
+        BitcoindRpcBackend::CompactFilters(fm_rpc_env_value_to_url(val)?)
+    } else if let Some(val) = bitcoind_rpc {
         BitcoindRpcBackend::Bitcoind(fm_bitcoind_rpc_env_value_to_url(Some(val))?)
     } else if let Some(val) = electrum_rpc {
-        BitcoindRpcBackend::Electrum(fm_electrum_rpc_env_value_to_url(val)?)
+        BitcoindRpcBackend::Electrum(fm_rpc_env_value_to_url(val)?)
+    } else if let Some(val) = esplora_rpc {
+        BitcoindRpcBackend::Esplora(fm_rpc_env_value_to_url(val)?)
     } else {
         BitcoindRpcBackend::Bitcoind(fm_bitcoind_rpc_env_value_to_url(None)?)
     })
@@ -65,8 +120,25 @@ fn fm_bitcoind_rpc_env_value_to_url(value: Option<&OsStr>) -> anyhow::Result<Url
     })
 }
 
-pub fn fm_electrum_rpc_env_value_to_url(value: &OsStr) -> anyhow::Result<Url> {
+/// Parses the url of an electrum or esplora backend from the value of its env
+/// variable
+pub fn fm_rpc_env_value_to_url(value: &OsStr) -> anyhow::Result<Url> {
     Ok(Url::parse(value.to_str().ok_or_else(|| {
         anyhow::format_err!("Url not ascii text")
     })?)?)
 }
+
+/// Parses the urls of the fee sources from the value of [`FM_FEE_SOURCES_ENV`]
+pub fn fm_fee_sources_env_value_to_urls(value: Option<&OsStr>) -> anyhow::Result<Vec<Url>> {
+    let Some(value) = value else {
+        return Ok(vec![]);
+    };
+    value
+        .to_str()
+        .ok_or_else(|| anyhow::format_err!("Url not ascii text"))?
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(|url| Ok(Url::parse(url)?))
+        .collect()
+}
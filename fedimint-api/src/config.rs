@@ -4,7 +4,7 @@ use std::hash::Hash;
 use std::ops::Mul;
 use std::str::FromStr;
 
-use anyhow::{bail, format_err};
+use anyhow::{bail, format_err, Context};
 use bitcoin::secp256k1;
 use bitcoin_hashes::hex;
 use bitcoin_hashes::hex::{FromHex, ToHex};
@@ -15,7 +15,7 @@ use fedimint_api::core::{
     ModuleInstanceId, ModuleKind, LEGACY_HARDCODED_INSTANCE_ID_LN,
     LEGACY_HARDCODED_INSTANCE_ID_MINT, LEGACY_HARDCODED_INSTANCE_ID_WALLET,
 };
-use fedimint_api::encoding::Encodable;
+use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::BitcoinHash;
 use fedimint_api::ModuleDecoderRegistry;
 use serde::de::DeserializeOwned;
@@ -26,7 +26,7 @@ use threshold_crypto::group::{Curve, Group, GroupEncoding};
 use threshold_crypto::{G1Projective, G2Projective, Signature};
 use url::Url;
 
-use crate::module::DynModuleGen;
+use crate::module::{DynModuleGen, IModuleGen};
 use crate::PeerId;
 
 /// [`serde_json::Value`] that must contain `kind: String` field
@@ -101,6 +101,9 @@ pub struct ApiEndpoint {
     pub name: String,
 }
 
+/// Default maximum size of an encoded transaction in bytes
+pub const DEFAULT_MAX_TRANSACTION_SIZE: u64 = 1_000_000;
+
 /// Total client config
 ///
 /// This includes global settings and client-side module configs.
@@ -114,6 +117,12 @@ pub struct ClientConfig {
     pub nodes: Vec<ApiEndpoint>,
     /// Threshold pubkey for authenticating epoch history
     pub epoch_pk: threshold_crypto::PublicKey,
+    /// Maximum size of an encoded transaction the federation accepts, `None`
+    /// for federations created before the limit existed. Only hashed if set,
+    /// so their config hash stays the same.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[encodable_ignore]
+    pub max_transaction_size: Option<u64>,
     /// Configs from other client modules
     #[encodable_ignore]
     pub modules: BTreeMap<ModuleInstanceId, ClientModuleConfig>,
@@ -135,7 +144,7 @@ pub struct ConfigResponse {
 ///
 /// Stable id so long as guardians membership does not change
 /// Unique id so long as guardians do not all collude
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, Hash, PartialEq, Encodable)]
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, Hash, PartialEq, Encodable, Decodable)]
 pub struct FederationId(pub threshold_crypto::PublicKey);
 
 /// Display as a hex encoding
@@ -192,6 +201,9 @@ impl ClientConfig {
 
         let mut engine = HashEngine::default();
         self.consensus_encode(&mut engine)?;
+        if let Some(max_transaction_size) = self.max_transaction_size {
+            max_transaction_size.consensus_encode(&mut engine)?;
+        }
         for (k, v) in modules.iter() {
             k.consensus_encode(&mut engine)?;
             v.consensus_encode(&mut engine)?;
@@ -200,6 +212,20 @@ impl ClientConfig {
         Ok(sha256::Hash::from_engine(engine))
     }
 
+    /// Validates the configs of all modules, see
+    /// [`crate::module::ModuleGen::validate_client_config`]
+    pub fn validate(&self, module_config_gens: &ModuleGenRegistry) -> anyhow::Result<()> {
+        for (module_instance_id, v) in self.modules.iter() {
+            let kind = v.kind();
+            module_config_gens
+                .get(kind)
+                .ok_or_else(|| format_err!("module config gen not found: {kind}"))?
+                .validate_client_config(v.value().clone())
+                .with_context(|| format!("Invalid config of module {module_instance_id}"))?;
+        }
+        Ok(())
+    }
+
     pub fn get_module<T: DeserializeOwned>(&self, id: ModuleInstanceId) -> anyhow::Result<T> {
         if let Some(client_cfg) = self.modules.get(&id) {
             Ok(serde_json::from_value(client_cfg.0.value().clone())?)
@@ -253,6 +279,13 @@ impl ConfigGenParams {
         self
     }
 
+    /// Add the params of all modules in `other`, replacing ones given for the
+    /// same module before
+    pub fn extend(mut self, other: ConfigGenParams) -> Self {
+        self.0.extend(other.0);
+        self
+    }
+
     /// Retrieve a typed config generation parameters for a module
     pub fn get<P: ModuleGenParams>(&self) -> anyhow::Result<P> {
         let value = self
@@ -280,6 +313,25 @@ impl ModuleGenRegistry {
         self.0.get(k)
     }
 
+    /// Add the generator of another module kind, e.g. of a module not
+    /// maintained as part of Fedimint
+    ///
+    /// # Panics
+    /// If a generator for the same module kind was added before
+    pub fn attach<T>(mut self, gen: T) -> Self
+    where
+        T: IModuleGen + Send + Sync + 'static,
+    {
+        let gen = DynModuleGen::from(gen);
+        let kind = gen.module_kind();
+        assert!(
+            !self.0.contains_key(&kind),
+            "Module kind {kind} registered twice"
+        );
+        self.0.insert(kind, gen);
+        self
+    }
+
     /// Return legacy initialization order. See [`LegacyInitOrderIter`].
     pub fn legacy_init_order_iter(&self) -> LegacyInitOrderIter {
         for hardcoded_module in ["mint", "ln", "wallet"] {
@@ -17,5 +17,9 @@ where
 {
     let key = ModuleInstanceId::consensus_decode(&mut d, modules)?;
 
-    decode_fn(d, modules.get_expect(key), key)
+    let decoder = modules.get(key).ok_or_else(|| {
+        DecodeError::new_custom(anyhow::format_err!("Unknown module instance id {key}"))
+    })?;
+
+    decode_fn(d, decoder, key)
 }
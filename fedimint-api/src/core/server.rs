@@ -56,6 +56,14 @@ pub trait IServerModule: Debug {
 
     fn versions(&self) -> (ModuleConsensusVersion, &[ApiVersion]);
 
+    /// Called in the epoch in which a threshold of guardians agreed to switch
+    /// the module to consensus `version`
+    async fn activate_consensus_version(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        version: ModuleConsensusVersion,
+    );
+
     /// Blocks until a new `consensus_proposal` is available.
     async fn await_consensus_proposal(&self, dbtx: &mut DatabaseTransaction<'_>);
 
@@ -136,9 +144,10 @@ pub trait IServerModule: Debug {
     /// This function may only be called after `begin_consensus_epoch` and
     /// before `end_consensus_epoch`. Data is only written to the database
     /// once all transactions have been processed.
-    async fn apply_output<'a>(
-        &self,
-        dbtx: &mut DatabaseTransaction<'a>,
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        interconnect: &'a dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'b>,
         output: &DynOutput,
         out_point: OutPoint,
     ) -> Result<TransactionItemAmount, ModuleError>;
@@ -202,6 +211,14 @@ where
         <Self as ServerModule>::versions(self)
     }
 
+    async fn activate_consensus_version(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        version: ModuleConsensusVersion,
+    ) {
+        <Self as ServerModule>::activate_consensus_version(self, dbtx, version).await
+    }
+
     /// Blocks until a new `consensus_proposal` is available.
     async fn await_consensus_proposal(&self, dbtx: &mut DatabaseTransaction<'_>) {
         <Self as ServerModule>::await_consensus_proposal(self, dbtx).await
@@ -359,14 +376,16 @@ where
     /// This function may only be called after `begin_consensus_epoch` and
     /// before `end_consensus_epoch`. Data is only written to the database
     /// once all transactions have been processed.
-    async fn apply_output<'a>(
-        &self,
-        dbtx: &mut DatabaseTransaction<'a>,
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        interconnect: &'a dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'b>,
         output: &DynOutput,
         out_point: OutPoint,
     ) -> Result<TransactionItemAmount, ModuleError> {
         <Self as ServerModule>::apply_output(
             self,
+            interconnect,
             dbtx,
             output
                 .as_any()
@@ -7,7 +7,7 @@ use async_trait::async_trait;
 use bitcoin_hashes::hex::ToHex;
 use futures::stream;
 
-use super::{IDatabase, IDatabaseTransaction};
+use super::{IDatabase, IDatabaseTransaction, ReadOnlyDatabaseTransaction};
 use crate::db::PrefixStream;
 
 #[derive(Debug, Default)]
@@ -75,6 +75,13 @@ impl IDatabase for MemDatabase {
         memtx.set_tx_savepoint().await;
         Box::new(memtx)
     }
+
+    async fn begin_read_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        // Transactions operate on a copy of the data, which is a snapshot already
+        Box::new(ReadOnlyDatabaseTransaction::new(
+            self.begin_transaction().await,
+        ))
+    }
 }
 
 // In-memory database transaction should only be used for test code and never
@@ -114,7 +121,7 @@ impl<'a> IDatabaseTransaction<'a> for MemTransaction<'a> {
             .tx_data
             .range::<Vec<u8>, _>((key_prefix.to_vec())..)
             .take_while(|(key, _)| key.starts_with(key_prefix))
-            .map(|(key, value)| (key.clone(), value.clone()))
+            .map(|(key, value)| (key.clone(), Ok(value.clone())))
             .collect::<Vec<_>>();
         data.reverse();
 
@@ -210,6 +217,11 @@ mod tests {
         fedimint_api::db::verify_prevent_nonrepeatable_reads(database()).await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_snapshot_read() {
+        fedimint_api::db::verify_snapshot_read(database()).await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_rollback_to_savepoint() {
         fedimint_api::db::verify_rollback_to_savepoint(database()).await;
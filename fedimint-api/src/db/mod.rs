@@ -1,14 +1,18 @@
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::future::Future;
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::{error::Error, marker::PhantomData};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use async_trait::async_trait;
 use futures::future::BoxFuture;
 use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::{debug, instrument, trace, warn};
+use tracing::{debug, info, instrument, trace, warn};
 
 use crate::{
     core::ModuleInstanceId,
@@ -17,13 +21,19 @@ use crate::{
 };
 
 pub mod mem_impl;
+pub mod notifications;
 
 pub use tests::*;
 
+use crate::db::notifications::{Notifications, NotifyingTransaction};
 use crate::module::registry::ModuleDecoderRegistry;
 
 pub const MODULE_GLOBAL_PREFIX: u8 = 0xff;
 
+/// Prefix of the [`DatabaseVersionKey`], reserved in the global as well as in
+/// every module's isolated keyspace
+pub const DATABASE_VERSION_PREFIX: u8 = 0x50;
+
 pub trait DatabaseKeyPrefixConst {
     const DB_PREFIX: u8;
     type Key: DatabaseKey + Debug;
@@ -46,11 +56,26 @@ pub trait DatabaseValue: Sized + SerializableDatabaseValue {
     fn from_bytes(data: &[u8], modules: &ModuleDecoderRegistry) -> Result<Self, DecodingError>;
 }
 
-pub type PrefixStream<'a> = Pin<Box<dyn Stream<Item = (Vec<u8>, Vec<u8>)> + Send + 'a>>;
+/// Stream of key-value pairs, a value that can't be read (e.g. because it fails
+/// to decrypt) is returned as an error so the key is still known
+pub type PrefixStream<'a> = Pin<Box<dyn Stream<Item = (Vec<u8>, Result<Vec<u8>>)> + Send + 'a>>;
 
 #[async_trait]
 pub trait IDatabase: Debug + Send + Sync {
     async fn begin_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>>;
+
+    /// Begins a transaction that reads from a consistent snapshot of the
+    /// database. Writes committed by other transactions after the snapshot was
+    /// taken must not become visible to it, and it must neither block nor
+    /// abort these writers, so it is suited for long-running reads. Writing
+    /// through it returns an error, see [`ReadOnlyDatabaseTransaction`].
+    async fn begin_read_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>>;
+
+    /// Writes a consistent copy of the database to a new directory next to it
+    /// and returns its path. Backends that can't do so return an error.
+    async fn backup(&self) -> Result<PathBuf> {
+        bail!("The database backend doesn't support backups")
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -64,6 +89,7 @@ pub struct Database {
 #[derive(Debug)]
 struct DatabaseInner<Db: IDatabase + ?Sized> {
     module_decoders: ModuleDecoderRegistry,
+    notifications: Notifications,
     db: Db,
 }
 
@@ -96,6 +122,7 @@ impl Database {
         let inner = DatabaseInner {
             db,
             module_decoders,
+            notifications: Notifications::default(),
         };
 
         Self {
@@ -118,7 +145,10 @@ impl Database {
 
     pub async fn begin_transaction(&self) -> DatabaseTransaction {
         let dbtx = DatabaseTransaction::new(
-            self.inner_db.db.begin_transaction().await,
+            Box::new(NotifyingTransaction::new(
+                self.inner_db.db.begin_transaction().await,
+                &self.inner_db.notifications,
+            )),
             self.inner_db.module_decoders.clone(),
         );
 
@@ -128,6 +158,58 @@ impl Database {
         }
     }
 
+    /// Begins a read-only transaction over a snapshot of the database, see
+    /// [`IDatabase::begin_read_transaction`]. It doesn't need to be committed.
+    /// See [`IDatabase::backup`], always copies the whole database even if
+    /// this handle is isolated to a module
+    pub async fn backup(&self) -> Result<PathBuf> {
+        self.inner_db.db.backup().await
+    }
+
+    pub async fn begin_read_transaction(&self) -> DatabaseTransaction {
+        let dbtx = DatabaseTransaction::new(
+            self.inner_db.db.begin_read_transaction().await,
+            self.inner_db.module_decoders.clone(),
+        );
+
+        match self.module_instance_id {
+            Some(module_instance_id) => dbtx.new_module_tx(module_instance_id),
+            None => dbtx,
+        }
+    }
+
+    /// Returns a future that resolves once a transaction that wrote to (or
+    /// removed) a key starting with `key_prefix` was committed. The interest is
+    /// registered when calling this function, so it should be called before
+    /// reading the current state to not miss any writes in between. The future
+    /// may resolve spuriously, so the state has to be checked again after.
+    ///
+    /// Only writes through this [`Database`] (or handles cloned from it) are
+    /// noticed, not writes of other processes to the same backend.
+    pub fn wait_key_prefix_write(
+        &self,
+        key_prefix: &impl DatabaseKeyPrefix,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        self.wait_raw_key_prefix_write(&key_prefix.to_bytes())
+    }
+
+    /// Same as [`Database::wait_key_prefix_write`] for a raw `key_prefix`
+    /// relative to this database's (possibly module-isolated) keyspace
+    pub fn wait_raw_key_prefix_write(
+        &self,
+        key_prefix: &[u8],
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let mut global_prefix = vec![];
+        if let Some(module_instance_id) = self.module_instance_id {
+            global_prefix.push(MODULE_GLOBAL_PREFIX);
+            module_instance_id
+                .consensus_encode(&mut global_prefix)
+                .expect("Error encoding module instance id as prefix");
+        }
+        global_prefix.extend_from_slice(key_prefix);
+        self.inner_db.notifications.register(&global_prefix)
+    }
+
     /// Runs a closure with a reference to a database transaction and tries to
     /// commit the transaction if the closure returns `Ok` and rolls it back
     /// otherwise. If committing fails the closure is run again for up to
@@ -195,6 +277,168 @@ impl Database {
     }
 }
 
+/// Version of the encoding of the data stored in a database (or in a module's
+/// isolated part of it). Needs to be incremented every time a key or value
+/// encoding changes in a backwards-incompatible way, together with registering
+/// a migration from the previous version.
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
+pub struct DatabaseVersion(pub u64);
+
+impl DatabaseVersion {
+    pub fn increment(&self) -> DatabaseVersion {
+        DatabaseVersion(self.0 + 1)
+    }
+}
+
+impl std::fmt::Display for DatabaseVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Key under which the [`DatabaseVersion`] of the global database and of each
+/// module's isolated database is recorded
+#[derive(Debug, Encodable, Decodable)]
+pub struct DatabaseVersionKey;
+
+crate::impl_db_prefix_const!(
+    key = DatabaseVersionKey,
+    value = DatabaseVersion,
+    prefix = DATABASE_VERSION_PREFIX
+);
+
+/// A migration of the database from one [`DatabaseVersion`] to the next one.
+///
+/// Migrations are run inside the same database transaction as all other
+/// migrations of that database, so they either all apply or none of them.
+pub type DatabaseMigration =
+    for<'r, 'tx> fn(&'r mut DatabaseTransaction<'tx>) -> BoxFuture<'r, Result<()>>;
+
+/// Migrations of a database, the migration stored under version `v` migrates
+/// the database from version `v` to `v + 1`
+pub type MigrationMap = BTreeMap<DatabaseVersion, DatabaseMigration>;
+
+/// Brings the database (or module-isolated database) `db` belonging to `kind`
+/// up to `target_db_version` by running all registered `migrations` in order
+/// and recording the new version. All migrations run inside a single
+/// transaction.
+///
+/// A database without a recorded version that contains no data is assumed to
+/// be freshly created and is set to `target_db_version` without running any
+/// migrations. A non-empty database without a recorded version is assumed to
+/// be at version 0.
+///
+/// If `dry_run` is set the migrations are executed but the transaction is
+/// discarded instead of committed, which allows checking that an upgrade will
+/// succeed before performing it.
+///
+/// Returns the version the database was at before migrating.
+pub async fn apply_migrations(
+    db: &Database,
+    kind: String,
+    target_db_version: DatabaseVersion,
+    migrations: MigrationMap,
+    dry_run: bool,
+) -> Result<DatabaseVersion> {
+    let mut dbtx = db.begin_transaction().await;
+
+    let current_db_version = match dbtx.get_value(&DatabaseVersionKey).await? {
+        Some(version) => version,
+        None => {
+            let is_empty = dbtx.raw_find_by_prefix(&[]).await.next().await.is_none();
+            if is_empty {
+                info!(%kind, version = %target_db_version, "Initializing database version");
+                dbtx.insert_new_entry(&DatabaseVersionKey, &target_db_version)
+                    .await?;
+                if dry_run {
+                    dbtx.abort_tx();
+                } else {
+                    dbtx.commit_tx().await?;
+                }
+                return Ok(target_db_version);
+            }
+            DatabaseVersion(0)
+        }
+    };
+
+    if target_db_version < current_db_version {
+        bail!(
+            "Database of {kind} is at version {current_db_version}, which is newer than the \
+             supported version {target_db_version}"
+        );
+    }
+
+    if current_db_version == target_db_version {
+        debug!(%kind, version = %current_db_version, "Database is up to date");
+        dbtx.abort_tx();
+        return Ok(current_db_version);
+    }
+
+    let mut version = current_db_version;
+    while version < target_db_version {
+        let Some(migration) = migrations.get(&version) else {
+            bail!("Missing migration of {kind} database from version {version}");
+        };
+
+        info!(%kind, from = %version, to = %version.increment(), dry_run, "Migrating database");
+        migration(&mut dbtx).await?;
+        version = version.increment();
+    }
+
+    dbtx.insert_entry(&DatabaseVersionKey, &target_db_version)
+        .await?;
+
+    if dry_run {
+        info!(%kind, from = %current_db_version, to = %target_db_version, "Dry run of database migrations succeeded");
+        dbtx.abort_tx();
+    } else {
+        dbtx.commit_tx().await?;
+    }
+
+    Ok(current_db_version)
+}
+
+/// Helper for [`DatabaseMigration`]s that changed the encoding of a value:
+/// decodes all values stored under the prefix of `KP` in their previous
+/// encoding `Old` and overwrites them with the result of `migrate`.
+pub async fn migrate_values<KP, Old>(
+    dbtx: &mut DatabaseTransaction<'_>,
+    mut migrate: impl FnMut(Old) -> Result<KP::Value>,
+) -> Result<()>
+where
+    KP: DatabaseKeyPrefixConst,
+    Old: Decodable,
+{
+    let entries = dbtx
+        .raw_find_by_prefix(&[KP::DB_PREFIX])
+        .await
+        .collect::<Vec<_>>()
+        .await;
+
+    for (key_bytes, value_bytes) in entries {
+        let value_bytes = value_bytes?;
+        let old = Old::consensus_decode(&mut std::io::Cursor::new(&value_bytes), &dbtx.decoders)
+            .map_err(|e| DecodingError::Other(e.0))?;
+        dbtx.raw_insert_bytes(&key_bytes, migrate(old)?.to_bytes())
+            .await?;
+    }
+
+    Ok(())
+}
+
 /// Fedimint requires that the database implementation implement Snapshot
 /// Isolation. Snapshot Isolation is a database isolation level that guarantees
 /// consistent reads from the time that the snapshot was created (at transaction
@@ -266,6 +510,49 @@ pub trait IDatabaseTransaction<'a>: 'a + Send {
     async fn set_tx_savepoint(&mut self);
 }
 
+/// Wraps a backend transaction, rejecting all writes. Backends can use it to
+/// implement [`IDatabase::begin_read_transaction`] on top of a transaction
+/// that already reads from a snapshot. Committing it discards the inner
+/// transaction.
+pub struct ReadOnlyDatabaseTransaction<'a>(Box<dyn IDatabaseTransaction<'a>>);
+
+impl<'a> ReadOnlyDatabaseTransaction<'a> {
+    pub fn new(inner: Box<dyn IDatabaseTransaction<'a>>) -> ReadOnlyDatabaseTransaction<'a> {
+        ReadOnlyDatabaseTransaction(inner)
+    }
+}
+
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for ReadOnlyDatabaseTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, _key: &[u8], _value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        bail!("Cannot insert into a read only transaction")
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.0.raw_get_bytes(key).await
+    }
+
+    async fn raw_remove_entry(&mut self, _key: &[u8]) -> Result<Option<Vec<u8>>> {
+        bail!("Cannot remove from a read only transaction")
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixStream<'_> {
+        self.0.raw_find_by_prefix(key_prefix).await
+    }
+
+    async fn raw_remove_by_prefix(&mut self, _key_prefix: &[u8]) -> Result<()> {
+        bail!("Cannot remove from a read only transaction")
+    }
+
+    async fn commit_tx(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {}
+
+    async fn set_tx_savepoint(&mut self) {}
+}
+
 // TODO: use macro again
 #[doc = " A handle to a type-erased database implementation"]
 #[derive(Clone)]
@@ -364,6 +651,9 @@ impl<'a> IDatabaseTransaction<'a> for ModuleDatabaseTransaction<'a> {
 /// to isolate modules/module instances from each other inside the database,
 /// which allows the same module to be instantiated twice or two different
 /// modules to use the same key.
+///
+/// It is the only way to write to a module's part of the database, the parent
+/// transaction rejects raw writes to keys under [`MODULE_GLOBAL_PREFIX`].
 struct IsolatedDatabaseTransaction<'isolated, 'parent: 'isolated, T: Send + Encodable> {
     inner_tx: &'isolated mut DatabaseTransaction<'parent>,
     prefix: Vec<u8>,
@@ -397,6 +687,7 @@ impl<'isolated, 'parent, T: Send + Encodable + 'isolated> IDatabaseTransaction<'
         let mut key_with_prefix = self.prefix.clone();
         key_with_prefix.extend_from_slice(key);
         self.inner_tx
+            .tx
             .raw_insert_bytes(key_with_prefix.as_slice(), value)
             .await
     }
@@ -413,6 +704,7 @@ impl<'isolated, 'parent, T: Send + Encodable + 'isolated> IDatabaseTransaction<'
         let mut key_with_prefix = self.prefix.clone();
         key_with_prefix.extend_from_slice(key);
         self.inner_tx
+            .tx
             .raw_remove_entry(key_with_prefix.as_slice())
             .await
     }
@@ -450,6 +742,9 @@ pub struct DatabaseTransaction<'a> {
     tx: Box<dyn IDatabaseTransaction<'a>>,
     decoders: ModuleDecoderRegistry,
     commit_tracker: CommitTracker,
+    /// Module instance whose namespace all keys are relative to, `None` for
+    /// the global keyspace
+    module_instance_id: Option<ModuleInstanceId>,
 }
 
 impl<'a> std::ops::Deref for DatabaseTransaction<'a> {
@@ -490,9 +785,21 @@ impl<'parent> DatabaseTransaction<'parent> {
                 is_committed: false,
                 has_writes: false,
             },
+            module_instance_id: None,
         }
     }
 
+    /// Module instance this transaction is isolated to, if any
+    pub fn module_instance_id(&self) -> Option<ModuleInstanceId> {
+        self.module_instance_id
+    }
+
+    /// Returns a transaction that can only access the namespace of
+    /// `module_instance_id`, all keys are transparently prefixed with it.
+    ///
+    /// # Panics
+    /// If this transaction is already isolated to a module, modules must not
+    /// reach into the namespaces of others.
     pub fn with_module_prefix<'isolated>(
         &'isolated mut self,
         module_instance_id: ModuleInstanceId,
@@ -500,6 +807,7 @@ impl<'parent> DatabaseTransaction<'parent> {
     where
         'parent: 'isolated,
     {
+        self.assert_not_isolated(module_instance_id);
         let decoders = self.decoders.clone();
         let isolated = Box::new(IsolatedDatabaseTransaction::new(self, module_instance_id));
         DatabaseTransaction {
@@ -511,13 +819,17 @@ impl<'parent> DatabaseTransaction<'parent> {
                 is_committed: true,
                 has_writes: true,
             },
+            module_instance_id: Some(module_instance_id),
         }
     }
 
+    /// Same as [`DatabaseTransaction::with_module_prefix`], but the returned
+    /// transaction owns this one and can be committed
     pub fn new_module_tx(
         self,
         module_instance_id: ModuleInstanceId,
     ) -> DatabaseTransaction<'parent> {
+        self.assert_not_isolated(module_instance_id);
         let decoders = self.decoders.clone();
         let commit_tracker = self.commit_tracker.clone();
         let wrapped = ModuleDatabaseTransaction::new(self, module_instance_id);
@@ -525,7 +837,57 @@ impl<'parent> DatabaseTransaction<'parent> {
             tx: Box::new(wrapped),
             decoders,
             commit_tracker,
+            module_instance_id: Some(module_instance_id),
+        }
+    }
+
+    fn assert_not_isolated(&self, module_instance_id: ModuleInstanceId) {
+        if let Some(isolated_to) = self.module_instance_id {
+            panic!(
+                "Cannot isolate a transaction of module instance {isolated_to} to module instance {module_instance_id}"
+            );
+        }
+    }
+
+    /// Rejects raw writes of the global keyspace into the namespace of a
+    /// module, these have to go through
+    /// [`DatabaseTransaction::with_module_prefix`]. Empty keys are rejected
+    /// too, as a prefix they cover every namespace, so wiping the whole
+    /// keyspace requires [`DatabaseTransaction::raw_unchecked`].
+    fn check_namespace(&self, key: &[u8]) -> Result<()> {
+        if key.is_empty() {
+            bail!("Writing with an empty key requires unchecked raw access");
+        }
+        if self.module_instance_id.is_none() && key.first() == Some(&MODULE_GLOBAL_PREFIX) {
+            bail!("Writing to module keys requires a module-isolated transaction");
         }
+        Ok(())
+    }
+
+    /// Raw access to the whole keyspace, including the namespaces of all
+    /// modules, for tools working on the database as a whole like imports or
+    /// repairs
+    pub fn raw_unchecked(&mut self) -> &mut dyn IDatabaseTransaction<'parent> {
+        &mut *self.tx
+    }
+
+    pub async fn raw_insert_bytes(
+        &mut self,
+        key: &[u8],
+        value: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>> {
+        self.check_namespace(key)?;
+        self.tx.raw_insert_bytes(key, value).await
+    }
+
+    pub async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.check_namespace(key)?;
+        self.tx.raw_remove_entry(key).await
+    }
+
+    pub async fn raw_remove_by_prefix(&mut self, key_prefix: &[u8]) -> Result<()> {
+        self.check_namespace(key_prefix)?;
+        self.tx.raw_remove_by_prefix(key_prefix).await
     }
 
     pub async fn commit_tx(mut self) -> Result<()> {
@@ -533,6 +895,12 @@ impl<'parent> DatabaseTransaction<'parent> {
         return self.tx.commit_tx().await;
     }
 
+    /// Drops the transaction without committing it, discarding all of its
+    /// writes on purpose (so no warning about uncommitted writes is logged)
+    pub fn abort_tx(mut self) {
+        self.commit_tracker.has_writes = false;
+    }
+
     #[instrument(level = "debug", skip_all, fields(?key), ret)]
     pub async fn get_value<K>(&mut self, key: &K) -> Result<Option<K::Value>>
     where
@@ -566,7 +934,7 @@ impl<'parent> DatabaseTransaction<'parent> {
             .await
             .map(move |(key_bytes, value_bytes)| {
                 let key = KP::Key::from_bytes(&key_bytes, &decoders)?;
-                let value = decode_value(&value_bytes, &decoders)?;
+                let value = decode_value(&value_bytes?, &decoders)?;
                 Ok((key, value))
             })
     }
@@ -634,6 +1002,82 @@ impl<'parent> DatabaseTransaction<'parent> {
         self.commit_tracker.has_writes = true;
         self.raw_remove_by_prefix(&key_prefix.to_bytes()).await
     }
+
+    /// Returns all entries starting with the prefix byte of `KP` whose value
+    /// can't be read or whose key or value can't be decoded as `KP::Key` or
+    /// `KP::Value`
+    pub async fn find_undecodable_entries<KP>(&mut self) -> Vec<InvalidDbEntry>
+    where
+        KP: DatabaseKeyPrefixConst,
+    {
+        let decoders = self.decoders.clone();
+        self.raw_find_by_prefix(&[KP::DB_PREFIX])
+            .await
+            .filter_map(|(key_bytes, value_bytes)| {
+                let reason = match value_bytes {
+                    Ok(value_bytes) => KP::Key::from_bytes(&key_bytes, &decoders)
+                        .and_then(|_| decode_value::<KP::Value>(&value_bytes, &decoders))
+                        .err()
+                        .map(|e| InvalidDbEntryReason::Undecodable(e.to_string())),
+                    Err(e) => Some(InvalidDbEntryReason::Unreadable(e.to_string())),
+                };
+                futures::future::ready(reason.map(|reason| InvalidDbEntry {
+                    key: key_bytes,
+                    reason,
+                }))
+            })
+            .collect()
+            .await
+    }
+
+    /// Returns all entries whose key doesn't start with one of the
+    /// `known_prefixes` or the [`DATABASE_VERSION_PREFIX`]
+    pub async fn find_orphaned_entries(&mut self, known_prefixes: &[u8]) -> Vec<InvalidDbEntry> {
+        self.raw_find_by_prefix(&[])
+            .await
+            .filter_map(|(key_bytes, _)| {
+                let is_known = key_bytes.first().map_or(false, |prefix| {
+                    *prefix == DATABASE_VERSION_PREFIX || known_prefixes.contains(prefix)
+                });
+                futures::future::ready((!is_known).then_some(InvalidDbEntry {
+                    key: key_bytes,
+                    reason: InvalidDbEntryReason::Orphaned,
+                }))
+            })
+            .collect()
+            .await
+    }
+}
+
+/// Entry of the database the current code can't make sense of, found by an
+/// integrity check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidDbEntry {
+    /// Raw key of the entry, relative to the checked (e.g. module) keyspace
+    pub key: Vec<u8>,
+    pub reason: InvalidDbEntryReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidDbEntryReason {
+    /// The key doesn't start with any prefix known to the owner of the
+    /// keyspace
+    Orphaned,
+    /// The key or value failed to decode
+    Undecodable(String),
+    /// The value couldn't be read from the backend, e.g. because it failed to
+    /// decrypt
+    Unreadable(String),
+}
+
+impl std::fmt::Display for InvalidDbEntryReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidDbEntryReason::Orphaned => write!(f, "orphaned"),
+            InvalidDbEntryReason::Undecodable(e) => write!(f, "undecodable: {e}"),
+            InvalidDbEntryReason::Unreadable(e) => write!(f, "unreadable: {e}"),
+        }
+    }
 }
 
 impl<T> DatabaseKeyPrefix for T
@@ -1105,6 +1549,40 @@ mod tests {
         assert_eq!(returned_keys, expected_keys);
     }
 
+    pub async fn verify_snapshot_read(db: Database) {
+        let mut dbtx = db.begin_transaction().await;
+        assert!(dbtx.insert_entry(&TestKey(1), &TestVal(1)).await.is_ok());
+        dbtx.commit_tx().await.expect("DB Error");
+
+        let mut read_dbtx = db.begin_read_transaction().await;
+        assert_eq!(
+            read_dbtx.get_value(&TestKey(1)).await.unwrap(),
+            Some(TestVal(1))
+        );
+
+        // Writers must be able to commit while the read transaction is open
+        let mut dbtx = db.begin_transaction().await;
+        assert!(dbtx.insert_entry(&TestKey(1), &TestVal(2)).await.is_ok());
+        assert!(dbtx.insert_entry(&TestKey(2), &TestVal(3)).await.is_ok());
+        dbtx.commit_tx().await.expect("DB Error");
+
+        assert_eq!(
+            read_dbtx.get_value(&TestKey(1)).await.unwrap(),
+            Some(TestVal(1))
+        );
+        let returned_keys = read_dbtx
+            .find_by_prefix(&DbPrefixTestPrefix)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(returned_keys.len(), 1);
+
+        assert!(read_dbtx
+            .insert_entry(&TestKey(3), &TestVal(4))
+            .await
+            .is_err());
+    }
+
     pub async fn verify_phantom_entry(db: Database) {
         let mut dbtx = db.begin_transaction().await;
 
@@ -1232,13 +1710,15 @@ mod tests {
             .is_ok());
 
         // If the wildcard character ('%') is not handled properly, this will make
-        // find_by_prefix return 5 results instead of 4
+        // find_by_prefix return 3 results instead of 2
         assert!(dbtx
             .insert_entry(&TestKey(101), &TestVal(100))
             .await
             .is_ok());
 
-        let expected_keys = 4;
+        // Inserting the same key multiple times overwrites the value, so only two
+        // distinct keys are returned
+        let expected_keys = 2;
         let returned_keys = dbtx
             .find_by_prefix(&PercentPrefixTestPrefix)
             .await
@@ -1465,6 +1945,297 @@ mod tests {
         test_dbtx.commit_tx().await.expect("DB Error");
     }
 
+    #[cfg(test)]
+    #[tokio::test]
+    async fn test_apply_migrations() {
+        use futures::future::BoxFuture;
+
+        use crate::db::mem_impl::MemDatabase;
+        use crate::db::{
+            apply_migrations, DatabaseMigration, DatabaseTransaction, DatabaseVersion,
+            DatabaseVersionKey, MigrationMap,
+        };
+        use crate::ModuleDecoderRegistry;
+
+        fn migrate_v0<'r, 'tx>(
+            dbtx: &'r mut DatabaseTransaction<'tx>,
+        ) -> BoxFuture<'r, anyhow::Result<()>> {
+            Box::pin(async move {
+                let old = dbtx.remove_entry(&TestKey(1)).await?.expect("exists");
+                dbtx.insert_new_entry(&AltTestKey(1), &old).await?;
+                Ok(())
+            })
+        }
+
+        fn migrate_v1<'r, 'tx>(
+            dbtx: &'r mut DatabaseTransaction<'tx>,
+        ) -> BoxFuture<'r, anyhow::Result<()>> {
+            Box::pin(async move {
+                let TestVal(old) = dbtx.get_value(&AltTestKey(1)).await?.expect("exists");
+                dbtx.insert_entry(&AltTestKey(1), &TestVal(old * 2)).await?;
+                Ok(())
+            })
+        }
+
+        let migrations = MigrationMap::from([
+            (DatabaseVersion(0), migrate_v0 as DatabaseMigration),
+            (DatabaseVersion(1), migrate_v1 as DatabaseMigration),
+        ]);
+
+        // A fresh database is initialized to the target version
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        apply_migrations(
+            &db,
+            "test".to_string(),
+            DatabaseVersion(2),
+            migrations.clone(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            db.begin_transaction()
+                .await
+                .get_value(&DatabaseVersionKey)
+                .await
+                .unwrap(),
+            Some(DatabaseVersion(2))
+        );
+
+        // A database without version but with data is migrated from version 0
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&TestKey(1), &TestVal(21)).await.unwrap();
+        dbtx.commit_tx().await.unwrap();
+
+        // Dry run doesn't change anything
+        apply_migrations(
+            &db,
+            "test".to_string(),
+            DatabaseVersion(2),
+            migrations.clone(),
+            true,
+        )
+        .await
+        .unwrap();
+        let mut dbtx = db.begin_transaction().await;
+        assert_eq!(dbtx.get_value(&DatabaseVersionKey).await.unwrap(), None);
+        assert_eq!(
+            dbtx.get_value(&TestKey(1)).await.unwrap(),
+            Some(TestVal(21))
+        );
+        dbtx.abort_tx();
+
+        let from = apply_migrations(
+            &db,
+            "test".to_string(),
+            DatabaseVersion(2),
+            migrations.clone(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(from, DatabaseVersion(0));
+
+        let mut dbtx = db.begin_transaction().await;
+        assert_eq!(
+            dbtx.get_value(&DatabaseVersionKey).await.unwrap(),
+            Some(DatabaseVersion(2))
+        );
+        assert_eq!(dbtx.get_value(&TestKey(1)).await.unwrap(), None);
+        assert_eq!(
+            dbtx.get_value(&AltTestKey(1)).await.unwrap(),
+            Some(TestVal(42))
+        );
+
+        // Downgrades are refused
+        assert!(apply_migrations(
+            &db,
+            "test".to_string(),
+            DatabaseVersion(1),
+            migrations,
+            false
+        )
+        .await
+        .is_err());
+    }
+
+    #[cfg(test)]
+    #[tokio::test]
+    async fn test_migrate_values() {
+        use crate::db::mem_impl::MemDatabase;
+        use crate::db::{migrate_values, DatabaseKeyPrefix};
+        use crate::ModuleDecoderRegistry;
+
+        #[derive(Debug, Encodable, Decodable)]
+        struct OldTestVal(u32, u32);
+
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let mut dbtx = db.begin_transaction().await;
+        for (key, old) in [(1, OldTestVal(1, 2)), (2, OldTestVal(3, 4))] {
+            dbtx.raw_insert_bytes(
+                &TestKey(key).to_bytes(),
+                old.consensus_encode_to_vec().unwrap(),
+            )
+            .await
+            .unwrap();
+        }
+        dbtx.insert_entry(&AltTestKey(1), &TestVal(7))
+            .await
+            .unwrap();
+
+        migrate_values::<DbPrefixTestPrefix, OldTestVal>(&mut dbtx, |old| {
+            Ok(TestVal(u64::from(old.0 + old.1)))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(dbtx.get_value(&TestKey(1)).await.unwrap(), Some(TestVal(3)));
+        assert_eq!(dbtx.get_value(&TestKey(2)).await.unwrap(), Some(TestVal(7)));
+        // Values under other prefixes aren't touched
+        assert_eq!(
+            dbtx.get_value(&AltTestKey(1)).await.unwrap(),
+            Some(TestVal(7))
+        );
+        dbtx.commit_tx().await.unwrap();
+    }
+
+    #[cfg(test)]
+    #[tokio::test]
+    async fn test_find_invalid_entries() {
+        use crate::db::mem_impl::MemDatabase;
+        use crate::db::{
+            DatabaseKeyPrefix, IDatabaseTransaction, InvalidDbEntry, InvalidDbEntryReason,
+        };
+        use crate::ModuleDecoderRegistry;
+
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&TestKey(1), &TestVal(1)).await.unwrap();
+        // Value too short to be a `TestVal`
+        dbtx.raw_insert_bytes(&TestKey(2).to_bytes(), vec![0x01])
+            .await
+            .unwrap();
+        dbtx.raw_insert_bytes(&[0x99, 0x01], vec![0x01])
+            .await
+            .unwrap();
+
+        let undecodable = dbtx.find_undecodable_entries::<DbPrefixTestPrefix>().await;
+        assert_eq!(undecodable.len(), 1);
+        assert_eq!(undecodable[0].key, TestKey(2).to_bytes());
+        assert!(matches!(
+            undecodable[0].reason,
+            InvalidDbEntryReason::Undecodable(_)
+        ));
+
+        let known_prefixes = [TestDbKeyPrefix::Test as u8];
+        assert_eq!(
+            dbtx.find_orphaned_entries(&known_prefixes).await,
+            vec![InvalidDbEntry {
+                key: vec![0x99, 0x01],
+                reason: InvalidDbEntryReason::Orphaned,
+            }]
+        );
+        dbtx.commit_tx().await.unwrap();
+    }
+
+    #[cfg(test)]
+    #[tokio::test]
+    async fn test_wait_key_prefix_write() {
+        use futures::FutureExt;
+
+        use crate::db::mem_impl::MemDatabase;
+        use crate::ModuleDecoderRegistry;
+
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let module_db = db.new_isolated(TEST_MODULE_PREFIX);
+
+        let mut test_written = Box::pin(db.wait_key_prefix_write(&DbPrefixTestPrefix));
+        let mut module_test_written =
+            Box::pin(module_db.wait_key_prefix_write(&DbPrefixTestPrefix));
+
+        // Writes to other prefixes or uncommitted writes don't notify
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&AltTestKey(1), &TestVal(1))
+            .await
+            .unwrap();
+        dbtx.commit_tx().await.unwrap();
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&TestKey(1), &TestVal(1)).await.unwrap();
+        assert!((&mut test_written).now_or_never().is_none());
+
+        dbtx.commit_tx().await.unwrap();
+        assert!((&mut test_written).now_or_never().is_some());
+        assert!((&mut module_test_written).now_or_never().is_none());
+
+        let mut module_dbtx = module_db.begin_transaction().await;
+        module_dbtx
+            .insert_entry(&TestKey(1), &TestVal(1))
+            .await
+            .unwrap();
+        module_dbtx.commit_tx().await.unwrap();
+        assert!((&mut module_test_written).now_or_never().is_some());
+    }
+
+    #[cfg(test)]
+    #[tokio::test]
+    async fn test_module_namespace_enforced() {
+        use crate::db::mem_impl::MemDatabase;
+        use crate::db::MODULE_GLOBAL_PREFIX;
+        use crate::ModuleDecoderRegistry;
+
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let mut dbtx = db.begin_transaction().await;
+
+        let mut module_key = vec![MODULE_GLOBAL_PREFIX];
+        TEST_MODULE_PREFIX
+            .consensus_encode(&mut module_key)
+            .unwrap();
+        module_key.push(TestDbKeyPrefix::Test as u8);
+        assert!(dbtx.raw_insert_bytes(&module_key, vec![42]).await.is_err());
+        assert!(dbtx.raw_remove_entry(&module_key).await.is_err());
+        assert!(dbtx
+            .raw_remove_by_prefix(&[MODULE_GLOBAL_PREFIX])
+            .await
+            .is_err());
+        assert!(dbtx.raw_insert_bytes(&[], vec![42]).await.is_err());
+        assert!(dbtx.raw_remove_entry(&[]).await.is_err());
+        assert!(dbtx.raw_remove_by_prefix(&[]).await.is_err());
+
+        {
+            let mut module_dbtx = dbtx.with_module_prefix(TEST_MODULE_PREFIX);
+            assert_eq!(module_dbtx.module_instance_id(), Some(TEST_MODULE_PREFIX));
+            // Keys starting with the module prefix are just keys inside the module
+            module_dbtx
+                .raw_insert_bytes(&[MODULE_GLOBAL_PREFIX], vec![1])
+                .await
+                .unwrap();
+            module_dbtx
+                .raw_insert_bytes(&[TestDbKeyPrefix::Test as u8], vec![42])
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            dbtx.raw_get_bytes(&module_key).await.unwrap(),
+            Some(vec![42])
+        );
+        dbtx.commit_tx().await.unwrap();
+
+        let module_db = db.new_isolated(TEST_MODULE_PREFIX);
+        let mut module_dbtx = module_db.begin_transaction().await;
+        let nested = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = module_dbtx.with_module_prefix(ALT_MODULE_PREFIX);
+        }));
+        assert!(nested.is_err());
+
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.raw_unchecked()
+            .raw_remove_by_prefix(&[])
+            .await
+            .unwrap();
+        assert!(dbtx.raw_find_by_prefix(&[]).await.next().await.is_none());
+    }
+
     #[cfg(test)]
     #[tokio::test]
     async fn test_autocommit() {
@@ -1473,7 +2244,9 @@ mod tests {
         use anyhow::anyhow;
         use async_trait::async_trait;
 
-        use crate::db::{AutocommitError, IDatabase, IDatabaseTransaction};
+        use crate::db::{
+            AutocommitError, IDatabase, IDatabaseTransaction, ReadOnlyDatabaseTransaction,
+        };
         use crate::ModuleDecoderRegistry;
 
         #[derive(Debug)]
@@ -1484,6 +2257,12 @@ mod tests {
             async fn begin_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
                 Box::new(FakeTransaction(PhantomData))
             }
+
+            async fn begin_read_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+                Box::new(ReadOnlyDatabaseTransaction::new(Box::new(FakeTransaction(
+                    PhantomData,
+                ))))
+            }
         }
 
         #[derive(Debug)]
@@ -0,0 +1,149 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::channel::oneshot;
+
+use super::{IDatabaseTransaction, PrefixStream};
+
+/// Registry of tasks waiting for writes to key prefixes, woken up once a
+/// transaction writing to a matching key commits
+#[derive(Debug, Default)]
+pub struct Notifications {
+    waiters: Mutex<Vec<(Vec<u8>, oneshot::Sender<()>)>>,
+}
+
+impl Notifications {
+    /// Registers interest in writes to keys starting with `key_prefix`, the
+    /// returned future resolves once such a write was committed. Registration
+    /// happens immediately, not when the future is first polled.
+    pub fn register(&self, key_prefix: &[u8]) -> impl Future<Output = ()> + Send + 'static {
+        let (sender, receiver) = oneshot::channel();
+
+        let mut waiters = self.waiters.lock().expect("Lock poisoned");
+        // Waiters that gave up don't need to be notified anymore
+        waiters.retain(|(_, sender)| !sender.is_canceled());
+        waiters.push((key_prefix.to_vec(), sender));
+
+        async move {
+            // The sender is only dropped without sending if the database is
+            // dropped, in which case no more writes will happen anyway
+            let _ = receiver.await;
+        }
+    }
+
+    /// Wakes up all waiters whose prefix matches one of the `written` keys or
+    /// key prefixes
+    pub fn notify(&self, written: &BTreeSet<Vec<u8>>) {
+        if written.is_empty() {
+            return;
+        }
+
+        let mut waiters = self.waiters.lock().expect("Lock poisoned");
+        let (woken, remaining): (Vec<_>, Vec<_>) = std::mem::take(&mut *waiters)
+            .into_iter()
+            .partition(|(key_prefix, _)| {
+                written.iter().any(|key| {
+                    // The second condition catches prefix removals affecting the watched
+                    // prefix, it may cause spurious wake ups otherwise
+                    key.starts_with(key_prefix) || key_prefix.starts_with(key)
+                })
+            });
+        *waiters = remaining;
+
+        for (_, sender) in woken {
+            let _ = sender.send(());
+        }
+    }
+}
+
+/// Records the keys written by a transaction and notifies waiters registered
+/// in [`Notifications`] about them after a successful commit
+pub(super) struct NotifyingTransaction<'a> {
+    inner: Box<dyn IDatabaseTransaction<'a>>,
+    notifications: &'a Notifications,
+    written: BTreeSet<Vec<u8>>,
+}
+
+impl<'a> NotifyingTransaction<'a> {
+    pub fn new(
+        inner: Box<dyn IDatabaseTransaction<'a>>,
+        notifications: &'a Notifications,
+    ) -> NotifyingTransaction<'a> {
+        NotifyingTransaction {
+            inner,
+            notifications,
+            written: BTreeSet::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for NotifyingTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        self.written.insert(key.to_vec());
+        self.inner.raw_insert_bytes(key, value).await
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.inner.raw_get_bytes(key).await
+    }
+
+    async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.written.insert(key.to_vec());
+        self.inner.raw_remove_entry(key).await
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixStream<'_> {
+        self.inner.raw_find_by_prefix(key_prefix).await
+    }
+
+    async fn raw_remove_by_prefix(&mut self, key_prefix: &[u8]) -> Result<()> {
+        self.written.insert(key_prefix.to_vec());
+        self.inner.raw_remove_by_prefix(key_prefix).await
+    }
+
+    async fn commit_tx(self: Box<Self>) -> Result<()> {
+        self.inner.commit_tx().await?;
+        self.notifications.notify(&self.written);
+        Ok(())
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        self.inner.rollback_tx_to_savepoint().await
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        self.inner.set_tx_savepoint().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use futures::FutureExt;
+
+    use super::Notifications;
+
+    #[test]
+    fn test_notify_matching_prefixes() {
+        let notifications = Notifications::default();
+        let mut exact = Box::pin(notifications.register(&[0x01, 0x02]));
+        let mut shorter = Box::pin(notifications.register(&[0x01]));
+        let mut other = Box::pin(notifications.register(&[0x03]));
+
+        notifications.notify(&BTreeSet::from([vec![0x01, 0x02, 0x03]]));
+        assert!((&mut exact).now_or_never().is_some());
+        assert!((&mut shorter).now_or_never().is_some());
+        assert!((&mut other).now_or_never().is_none());
+
+        // Removing a whole prefix wakes up waiters on longer prefixes
+        let mut longer = Box::pin(notifications.register(&[0x03, 0x01]));
+        notifications.notify(&BTreeSet::from([vec![0x03]]));
+        assert!((&mut other).now_or_never().is_some());
+        assert!((&mut longer).now_or_never().is_some());
+    }
+}
@@ -338,7 +338,9 @@ impl Decodable for SystemTime {
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
         let duration = Duration::consensus_decode(d, modules)?;
-        Ok(UNIX_EPOCH + duration)
+        UNIX_EPOCH
+            .checked_add(duration)
+            .ok_or_else(|| DecodeError::from_str("SystemTime out of range"))
     }
 }
 
@@ -358,7 +360,11 @@ impl Decodable for Duration {
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
         let secs = Decodable::consensus_decode(d, modules)?;
-        let nsecs = Decodable::consensus_decode(d, modules)?;
+        let nsecs: u32 = Decodable::consensus_decode(d, modules)?;
+        // `Duration::new` panics if the nanoseconds overflow the seconds
+        if nsecs >= 1_000_000_000 {
+            return Err(DecodeError::from_str("Duration nanoseconds out of range"));
+        }
         Ok(Duration::new(secs, nsecs))
     }
 }
@@ -695,6 +701,19 @@ mod tests {
         test_roundtrip(SystemTime::now());
     }
 
+    #[test]
+    fn test_time_out_of_range() {
+        let decoders = ModuleDecoderRegistry::default();
+
+        let nanos_overflow = (u64::MAX, 1_000_000_000u32)
+            .consensus_encode_to_vec()
+            .unwrap();
+        assert!(Duration::consensus_decode(&mut Cursor::new(&nanos_overflow), &decoders).is_err());
+
+        let secs_overflow = (u64::MAX, 0u32).consensus_encode_to_vec().unwrap();
+        assert!(SystemTime::consensus_decode(&mut Cursor::new(&secs_overflow), &decoders).is_err());
+    }
+
     #[test]
     fn test_derive_empty_enum_decode() {
         #[derive(Debug, Encodable, Decodable)]
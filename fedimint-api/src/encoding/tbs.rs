@@ -58,6 +58,17 @@ impl Encodable for threshold_crypto::PublicKey {
     }
 }
 
+impl Decodable for threshold_crypto::PublicKey {
+    fn consensus_decode<D: std::io::Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let bytes = <[u8; 48]>::consensus_decode(d, modules)?;
+        threshold_crypto::PublicKey::from_bytes(bytes)
+            .map_err(|_| DecodeError::from_str("Error decoding threshold public key"))
+    }
+}
+
 impl Encodable for tbs::AggregatePublicKey {
     fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
         self.0.to_compressed().consensus_encode(writer)
@@ -114,4 +125,10 @@ mod tests {
         let bkey = BlindingKey::random();
         test_roundtrip(bkey);
     }
+
+    #[test_log::test]
+    fn test_threshold_public_key() {
+        let pk = threshold_crypto::SecretKey::random().public_key();
+        test_roundtrip(pk);
+    }
 }
@@ -1,6 +1,7 @@
 use std::fmt::{Display, Formatter};
 
 use futures::StreamExt;
+use serde::{Deserialize, Serialize};
 
 use crate::db::{DatabaseKeyPrefix, DatabaseKeyPrefixConst, DatabaseTransaction};
 
@@ -46,6 +47,23 @@ impl Audit {
     }
 }
 
+/// Balance sheet of the federation as returned by the `/audit` endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditSummary {
+    pub items: Vec<AuditItem>,
+    pub net_assets: AuditItem,
+}
+
+impl From<Audit> for AuditSummary {
+    fn from(audit: Audit) -> Self {
+        let net_assets = audit.sum();
+        AuditSummary {
+            items: audit.items,
+            net_assets,
+        }
+    }
+}
+
 impl Display for Audit {
     fn fmt(&self, formatter: &mut Formatter) -> std::fmt::Result {
         formatter.write_str("- Balance Sheet -")?;
@@ -56,6 +74,7 @@ impl Display for Audit {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditItem {
     pub name: String,
     pub milli_sat: i64,
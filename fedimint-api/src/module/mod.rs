@@ -20,7 +20,7 @@ use tracing::instrument;
 use crate::cancellable::Cancellable;
 use crate::config::{ConfigGenParams, DkgPeerMsg, ServerModuleConfig};
 use crate::core::{Decoder, DynDecoder, ModuleInstanceId, ModuleKind};
-use crate::db::{Database, DatabaseTransaction};
+use crate::db::{Database, DatabaseTransaction, DatabaseVersion, InvalidDbEntry, MigrationMap};
 use crate::encoding::{Decodable, DecodeError, Encodable};
 use crate::module::audit::Audit;
 use crate::module::interconnect::ModuleInterconect;
@@ -70,6 +70,10 @@ impl ApiError {
     pub fn bad_request(message: String) -> Self {
         Self::new(400, message)
     }
+
+    pub fn unauthorized() -> Self {
+        Self::new(401, "Invalid authentication".to_string())
+    }
 }
 
 #[async_trait]
@@ -153,6 +157,10 @@ pub struct ApiEndpoint<M> {
     /// `/` e.g. `/transaction`. E.g. this API endpoint would be reachable
     /// under `/module/module_instance_id/transaction` depending on the
     /// module name returned by `[FedertionModule::api_base_name]`.
+    ///
+    /// Paths of module endpoints starting with `/admin/` require the
+    /// guardian's admin password, their parameters have to be wrapped in an
+    /// `AuthenticatedRequest` by callers.
     pub path: &'static str,
     /// Handler for the API call that takes the following arguments:
     ///   * Reference to the module which defined it
@@ -269,6 +277,10 @@ pub trait IModuleGen: Debug {
 
     fn module_kind(&self) -> ModuleKind;
 
+    fn database_version(&self) -> DatabaseVersion;
+
+    fn get_database_migrations(&self) -> MigrationMap;
+
     /// Initialize the [`DynServerModule`] instance from its config
     async fn init(
         &self,
@@ -301,11 +313,21 @@ pub trait IModuleGen: Debug {
 
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()>;
 
+    fn validate_client_config(&self, config: serde_json::Value) -> anyhow::Result<()>;
+
+    fn apply_params_change(
+        &self,
+        consensus: serde_json::Value,
+        change: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value>;
+
     async fn dump_database(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
         prefix_names: Vec<String>,
     ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_>;
+
+    async fn check_database(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<InvalidDbEntry>;
 }
 
 dyn_newtype_define!(
@@ -345,7 +367,26 @@ pub struct CoreConsensusVersion(pub u32);
 /// by running two instances of the module at the same time (each of different
 /// `ModuleKind` version), allow users to slowly migrate to a new one.
 /// This avoids complex and error-prone server-side consensus-migration logic.
-#[derive(Debug, Copy, Clone)]
+///
+/// A module instance starts with the version recorded in the federation's
+/// consensus config and switches to another one supported by its
+/// [`ModuleGen::versions`] once a threshold of guardians voted for it, see
+/// [`ServerModule::activate_consensus_version`].
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
 pub struct ModuleConsensusVersion(pub u32);
 
 /// Api version supported by a core server or a client/server module at a given
@@ -411,6 +452,21 @@ pub trait ModuleGen: Debug + Sized {
     /// versions, purely for information, setup UI and sanity checking purposes.
     fn versions(&self, core: CoreConsensusVersion) -> &[ModuleConsensusVersion];
 
+    /// Version of the module's database encoding implemented by this code.
+    ///
+    /// Increment it together with adding a migration to
+    /// [`Self::get_database_migrations`] whenever the encoding of the module's
+    /// keys or values changes.
+    fn database_version(&self) -> DatabaseVersion {
+        DatabaseVersion(0)
+    }
+
+    /// Migrations of the module's database, run at startup before
+    /// [`Self::init`]. See [`crate::db::apply_migrations`].
+    fn get_database_migrations(&self) -> MigrationMap {
+        MigrationMap::new()
+    }
+
     /// Initialize the [`DynServerModule`] instance from its config
     async fn init(
         &self,
@@ -443,11 +499,33 @@ pub trait ModuleGen: Debug + Sized {
 
     fn hash_client_module(&self, config: serde_json::Value) -> anyhow::Result<sha256::Hash>;
 
+    /// Checks a client config received from the federation for internal
+    /// consistency before a client starts using it
+    fn validate_client_config(&self, config: serde_json::Value) -> anyhow::Result<()>;
+
+    /// Applies a change of consensus parameters the guardians voted for, e.g.
+    /// new fees, to the module's `consensus` config and returns the new one.
+    /// The format of `change` is up to the module. Modules that don't support
+    /// changing their parameters reject all changes.
+    fn apply_params_change(
+        &self,
+        _consensus: serde_json::Value,
+        _change: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        anyhow::bail!("The module doesn't support changing its consensus parameters")
+    }
+
     async fn dump_database(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
         prefix_names: Vec<String>,
     ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_>;
+
+    /// Returns all entries of the module's isolated database that are
+    /// orphaned or can't be decoded, see
+    /// [`DatabaseTransaction::find_orphaned_entries`] and
+    /// [`DatabaseTransaction::find_undecodable_entries`]
+    async fn check_database(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<InvalidDbEntry>;
 }
 
 #[async_trait]
@@ -467,6 +545,14 @@ where
         <Self as ModuleGen>::versions(self, core).to_vec()
     }
 
+    fn database_version(&self) -> DatabaseVersion {
+        <Self as ModuleGen>::database_version(self)
+    }
+
+    fn get_database_migrations(&self) -> MigrationMap {
+        <Self as ModuleGen>::get_database_migrations(self)
+    }
+
     async fn init(
         &self,
         cfg: ServerModuleConfig,
@@ -521,6 +607,18 @@ where
         <Self as ModuleGen>::validate_config(self, identity, config)
     }
 
+    fn validate_client_config(&self, config: serde_json::Value) -> anyhow::Result<()> {
+        <Self as ModuleGen>::validate_client_config(self, config)
+    }
+
+    fn apply_params_change(
+        &self,
+        consensus: serde_json::Value,
+        change: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        <Self as ModuleGen>::apply_params_change(self, consensus, change)
+    }
+
     async fn dump_database(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -528,6 +626,10 @@ where
     ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
         <Self as ModuleGen>::dump_database(self, dbtx, prefix_names).await
     }
+
+    async fn check_database(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<InvalidDbEntry> {
+        <Self as ModuleGen>::check_database(self, dbtx).await
+    }
 }
 
 pub enum ConsensusProposal<CI> {
@@ -609,6 +711,18 @@ pub trait ServerModule: Debug + Sized {
     /// versions it supports in it
     fn versions(&self) -> (ModuleConsensusVersion, &[ApiVersion]);
 
+    /// Called in the epoch in which a threshold of guardians agreed to run
+    /// this module instance with consensus `version` (one of the versions
+    /// returned by [`ModuleGen::versions`]), after all its transactions were
+    /// processed. Modules implementing multiple versions have to apply the
+    /// new rules from the next epoch on and persist the switch in `dbtx`.
+    async fn activate_consensus_version<'a>(
+        &'a self,
+        _dbtx: &mut DatabaseTransaction<'_>,
+        _version: ModuleConsensusVersion,
+    ) {
+    }
+
     /// Blocks until a new `consensus_proposal` is available.
     async fn await_consensus_proposal<'a>(&'a self, dbtx: &mut DatabaseTransaction<'_>);
 
@@ -693,6 +807,7 @@ pub trait ServerModule: Debug + Sized {
     /// once all transactions have been processed.
     async fn apply_output<'a, 'b>(
         &'a self,
+        interconnect: &'a dyn ModuleInterconect,
         dbtx: &mut DatabaseTransaction<'b>,
         output: &'a <Self::Decoder as Decoder>::Output,
         out_point: OutPoint,
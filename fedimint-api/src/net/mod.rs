@@ -1 +1,18 @@
+use std::ffi::OsStr;
+
 pub mod peers;
+
+/// Name of the env value used for passing the `host:port` of a SOCKS5 proxy to
+/// modules, which have to make their outbound connections through it
+pub const FM_SOCKS5_PROXY_ENV: &str = "FM_SOCKS5_PROXY";
+
+/// Parses the proxy address from the value of [`FM_SOCKS5_PROXY_ENV`]
+pub fn fm_socks5_proxy_env_value(value: Option<&OsStr>) -> anyhow::Result<Option<&str>> {
+    value
+        .map(|value| {
+            value
+                .to_str()
+                .ok_or_else(|| anyhow::format_err!("{FM_SOCKS5_PROXY_ENV} not ascii text"))
+        })
+        .transpose()
+}
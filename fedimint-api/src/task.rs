@@ -96,7 +96,7 @@ impl TaskGroup {
         let new_tg = Self::new();
         self.make_handle()
             .on_shutdown({
-                let new_tg = self.clone();
+                let new_tg = new_tg.clone();
                 Box::new(move || {
                     Box::pin(async move {
                         new_tg.shutdown().await;
@@ -353,21 +353,24 @@ impl TaskHandle {
 #[cfg(not(target_family = "wasm"))]
 mod imp {
     pub use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+    use tracing::Instrument;
 
     use super::*;
 
+    // Tasks stay in the span they were spawned from, so their logs keep its
+    // fields, e.g. the peer id of the guardian running them
     pub(crate) fn spawn<F>(future: F) -> Option<JoinHandle<()>>
     where
         F: Future<Output = ()> + Send + 'static,
     {
-        Some(tokio::spawn(future))
+        Some(tokio::spawn(future.in_current_span()))
     }
 
     pub(crate) fn spawn_local<F>(future: F) -> Option<JoinHandle<()>>
     where
         F: Future<Output = ()> + 'static,
     {
-        Some(tokio::task::spawn_local(future))
+        Some(tokio::task::spawn_local(future.in_current_span()))
     }
 
     pub fn block_in_place<F, R>(f: F) -> R
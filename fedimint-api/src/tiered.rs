@@ -89,12 +89,22 @@ impl Tiered<SecretKeyShare> {
 impl Tiered<()> {
     /// Generates denominations as powers of 2 up to and including `max`
     pub fn gen_denominations(max: Amount) -> Tiered<()> {
+        Self::gen_denominations_with_base(2, max)
+    }
+
+    /// Generates denominations as powers of `base` (starting at 1 msat) up to
+    /// and including `max`
+    ///
+    /// # Panics
+    /// * If `base` is smaller than 2
+    pub fn gen_denominations_with_base(base: u64, max: Amount) -> Tiered<()> {
+        assert!(base >= 2, "Denomination base has to be at least 2");
         let mut amounts = vec![];
 
-        let mut denomination = Amount::from_msats(1);
-        while denomination <= max {
-            amounts.push((denomination, ()));
-            denomination = denomination * 2;
+        let mut denomination = Some(Amount::from_msats(1));
+        while let Some(amount) = denomination.filter(|amount| *amount <= max) {
+            amounts.push((amount, ()));
+            denomination = amount.msats.checked_mul(base).map(Amount::from_msats);
         }
 
         amounts.into_iter().collect()
@@ -142,4 +152,20 @@ mod tests {
         // should produce [1, 2, 4, 8, 16]
         assert_eq!(denominations.tiers().collect::<Vec<&Amount>>().len(), 5);
     }
+
+    #[test]
+    fn tier_generation_with_base() {
+        let denominations = Tiered::gen_denominations_with_base(10, Amount::from_msats(5000));
+        assert_eq!(
+            denominations
+                .tiers()
+                .map(|amount| amount.msats)
+                .collect::<Vec<_>>(),
+            vec![1, 10, 100, 1000]
+        );
+
+        // Doesn't overflow for large maximums
+        let denominations = Tiered::gen_denominations_with_base(10, Amount::from_msats(u64::MAX));
+        assert_eq!(denominations.tiers().count(), 20);
+    }
 }
@@ -182,6 +182,45 @@ where
 
         Some(selected.into_iter().collect::<TieredMulti<C>>())
     }
+
+    /// Like [`TieredMulti::select_notes`], but additionally selects up to
+    /// `max_extra_notes` notes of tiers that would otherwise keep more than
+    /// `notes_per_tier` notes, starting at the lowest tier. The caller turns
+    /// these into change, consolidating many small notes into fewer larger
+    /// ones. Tiers not worth more than `min_tier` (the cost of spending and
+    /// reissuing a note) are never consolidated.
+    pub fn select_notes_consolidating(
+        &self,
+        amount: Amount,
+        notes_per_tier: usize,
+        max_extra_notes: usize,
+        min_tier: Amount,
+    ) -> Option<TieredMulti<C>> {
+        let selected = self.select_notes(amount)?;
+
+        // Notes of the same tier are interchangeable, so only the number of
+        // notes to select per tier matters
+        let mut extra_notes = max_extra_notes;
+        let consolidated = self
+            .0
+            .iter()
+            .filter_map(|(tier, notes)| {
+                let selected_notes = selected.get(*tier).map(|v| v.len()).unwrap_or(0);
+                let surplus = (notes.len() - selected_notes).saturating_sub(notes_per_tier);
+                let consolidate = if *tier > min_tier {
+                    min(surplus, extra_notes)
+                } else {
+                    0
+                };
+                extra_notes -= consolidate;
+
+                let count = selected_notes + consolidate;
+                (count > 0).then(|| (*tier, notes[..count].to_vec()))
+            })
+            .collect();
+
+        Some(TieredMulti(consolidated))
+    }
 }
 
 impl TieredMulti<()> {
@@ -425,6 +464,57 @@ mod test {
         assert_eq!(starting.select_notes(Amount::from_sats(100)), None);
     }
 
+    #[test]
+    fn select_notes_consolidating_selects_surplus_of_lowest_tiers() {
+        let starting = notes(vec![
+            (Amount::from_sats(1), 10),
+            (Amount::from_sats(2), 5),
+            (Amount::from_sats(4), 6),
+            (Amount::from_sats(8), 1),
+        ]);
+
+        // The payment is made with the 8 and one 2 sat note, the surplus over 3
+        // notes per tier is consolidated
+        assert_eq!(
+            starting.select_notes_consolidating(Amount::from_sats(10), 3, 100, Amount::ZERO),
+            Some(notes(vec![
+                (Amount::from_sats(1), 7),
+                (Amount::from_sats(2), 2),
+                (Amount::from_sats(4), 3),
+                (Amount::from_sats(8), 1),
+            ]))
+        );
+
+        // At most `max_extra_notes` are consolidated, lowest tiers first
+        assert_eq!(
+            starting.select_notes_consolidating(Amount::from_sats(10), 3, 6, Amount::ZERO),
+            Some(notes(vec![
+                (Amount::from_sats(1), 6),
+                (Amount::from_sats(2), 1),
+                (Amount::from_sats(8), 1),
+            ]))
+        );
+
+        // Tiers not worth the fees aren't consolidated
+        assert_eq!(
+            starting.select_notes_consolidating(
+                Amount::from_sats(10),
+                3,
+                100,
+                Amount::from_sats(2)
+            ),
+            Some(notes(vec![
+                (Amount::from_sats(2), 1),
+                (Amount::from_sats(4), 3),
+                (Amount::from_sats(8), 1),
+            ]))
+        );
+        assert_eq!(
+            starting.select_notes_consolidating(Amount::from_sats(100), 3, 100, Amount::ZERO),
+            None
+        );
+    }
+
     #[test]
     fn select_notes_avg_test() {
         let max_amount = Amount::from_sats(1000000);
@@ -4,6 +4,16 @@ pub use std::time::SystemTime;
 #[cfg(target_family = "wasm")]
 pub use wasm::SystemTime;
 
+/// Current time as [`std::time::SystemTime`] for types shared with the
+/// server, unlike [`std::time::SystemTime::now`] this also works in the
+/// browser
+pub fn now() -> std::time::SystemTime {
+    std::time::UNIX_EPOCH
+        + SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Current time is after the epoch")
+}
+
 #[cfg(target_family = "wasm")]
 mod wasm {
     use std::ops::Add;
@@ -5,15 +5,18 @@ use ::bitcoincore_rpc::jsonrpc::error::RpcError;
 use ::bitcoincore_rpc::{jsonrpc, Auth, RpcApi};
 use anyhow::{bail, format_err, Context};
 use bitcoin::consensus::Encodable;
+use bitcoin::util::bip158::BlockFilter;
 use bitcoin_hashes::hex::ToHex;
 use electrum_client::ElectrumApi;
 use fedimint_api::bitcoin_rpc::BitcoindRpcBackend;
 use jsonrpc::error::Error as JsonError;
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use tracing::warn;
 use url::Url;
 
 use super::*;
+use crate::esplora::{socks5_http_client, EsploraClient};
 
 // <https://github.com/bitcoin/bitcoin/blob/ec0a4ad67769109910e3685da9c56c1b9f42414e/src/rpc/protocol.h#L48>
 const RPC_VERIFY_ALREADY_IN_CHAIN: i32 = -27;
@@ -46,32 +49,154 @@ pub fn from_url_to_url_auth(url: &Url) -> Result<(String, Auth)> {
     ))
 }
 
+/// Creates the client for `backend`, connecting through the SOCKS5 proxy at
+/// `host:port` if one is given
 pub fn make_bitcoin_rpc_backend(
     backend: &BitcoindRpcBackend,
+    proxy: Option<&str>,
     task_handle: TaskHandle,
 ) -> Result<DynBitcoindRpc> {
     match backend {
-        BitcoindRpcBackend::Bitcoind(url) => make_bitcoind_rpc(url, task_handle)
+        BitcoindRpcBackend::Bitcoind(url) => make_bitcoind_rpc(url, proxy, task_handle)
             .context("bitcoind rpc backend initialization failed"),
-        BitcoindRpcBackend::Electrum(url) => make_electrum_rpc(url, task_handle)
+        BitcoindRpcBackend::Electrum(url) => make_electrum_rpc(url, proxy, task_handle)
             .context("electrum rpc backend initialization failed"),
+        BitcoindRpcBackend::Esplora(url) => make_esplora_rpc(url, proxy, task_handle)
+            .context("esplora rpc backend initialization failed"),
+        BitcoindRpcBackend::CompactFilters(url) => {
+            make_compact_filters_rpc(url, proxy, task_handle)
+                .context("compact filters rpc backend initialization failed")
+        }
     }
 }
 
-pub fn make_bitcoind_rpc(url: &Url, task_handle: TaskHandle) -> Result<DynBitcoindRpc> {
-    let (url, auth) = from_url_to_url_auth(url)?;
-    let bitcoind_client =
-        ::bitcoincore_rpc::Client::new(&url, auth).map_err(anyhow::Error::from)?;
+pub fn make_bitcoind_rpc(
+    url: &Url,
+    proxy: Option<&str>,
+    task_handle: TaskHandle,
+) -> Result<DynBitcoindRpc> {
+    let retry_client = RetryClient::new(Client(bitcoind_client(url, proxy)?), task_handle);
+
+    Ok(retry_client.into())
+}
+
+/// Like [`make_bitcoind_rpc`], but fails over to the next of the prioritized
+/// `urls` whenever a call fails
+pub fn make_bitcoind_failover_rpc(
+    urls: &[Url],
+    proxy: Option<&str>,
+    task_handle: TaskHandle,
+) -> Result<DynBitcoindRpc> {
+    let backends = urls
+        .iter()
+        .map(|url| {
+            let client = bitcoind_client(url, proxy)?;
+            Ok((client.addr.clone(), Client(client).into()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if backends.is_empty() {
+        bail!("No bitcoind RPC urls given");
+    }
+
+    Ok(RetryClient::new(FailoverClient::new(backends), task_handle).into())
+}
+
+/// Creates a backend for a (possibly pruned) bitcoind with `blockfilterindex=1`
+pub fn make_compact_filters_rpc(
+    url: &Url,
+    proxy: Option<&str>,
+    task_handle: TaskHandle,
+) -> Result<DynBitcoindRpc> {
     let retry_client = RetryClient::new(
-        Client(ErrorReporting::new(url, bitcoind_client)),
+        CompactFiltersClient(Client(bitcoind_client(url, proxy)?)),
         task_handle,
     );
 
     Ok(retry_client.into())
 }
 
-pub fn make_electrum_rpc(url: &Url, _task_handle: TaskHandle) -> Result<DynBitcoindRpc> {
-    Ok(ElectrumClient::new(url)?.into())
+pub fn make_electrum_rpc(
+    url: &Url,
+    proxy: Option<&str>,
+    _task_handle: TaskHandle,
+) -> Result<DynBitcoindRpc> {
+    Ok(ElectrumClient::new(url, proxy)?.into())
+}
+
+pub fn make_esplora_rpc(
+    url: &Url,
+    proxy: Option<&str>,
+    task_handle: TaskHandle,
+) -> Result<DynBitcoindRpc> {
+    Ok(RetryClient::new(EsploraClient::new(url, proxy)?, task_handle).into())
+}
+
+fn bitcoind_client(
+    url: &Url,
+    proxy: Option<&str>,
+) -> Result<ErrorReporting<::bitcoincore_rpc::Client>> {
+    let (url, auth) = from_url_to_url_auth(url)?;
+    let bitcoind_client = match proxy {
+        Some(proxy) => ::bitcoincore_rpc::Client::from_jsonrpc(jsonrpc::Client::with_transport(
+            Socks5Transport::new(url.clone(), auth, proxy)?,
+        )),
+        None => ::bitcoincore_rpc::Client::new(&url, auth).map_err(anyhow::Error::from)?,
+    };
+
+    Ok(ErrorReporting::new(url, bitcoind_client))
+}
+
+/// [`jsonrpc::Transport`] sending requests through a SOCKS5 proxy, which the
+/// HTTP transport of `bitcoincore_rpc` doesn't support
+///
+/// Requests are only sent from within [`fedimint_api::task::block_in_place`],
+/// so they can block on the async HTTP client.
+struct Socks5Transport {
+    client: reqwest::Client,
+    url: String,
+    auth: Option<(String, Option<String>)>,
+}
+
+impl Socks5Transport {
+    fn new(url: String, auth: Auth, proxy: &str) -> Result<Self> {
+        let (user, pass) = auth.get_user_pass()?;
+        Ok(Self {
+            client: socks5_http_client(proxy)?,
+            url,
+            auth: user.map(|user| (user, pass)),
+        })
+    }
+
+    fn post<T: DeserializeOwned>(&self, body: &impl Serialize) -> Result<T, JsonError> {
+        tokio::runtime::Handle::current()
+            .block_on(async {
+                let mut request = self.client.post(&self.url).json(body);
+                if let Some((user, pass)) = &self.auth {
+                    request = request.basic_auth(user, pass.as_ref());
+                }
+                // bitcoind answers failed calls with an error status, but still
+                // puts the error into the response body
+                request.send().await?.json().await
+            })
+            .map_err(|e| JsonError::Transport(e.into()))
+    }
+}
+
+impl jsonrpc::Transport for Socks5Transport {
+    fn send_request(&self, request: jsonrpc::Request) -> Result<jsonrpc::Response, JsonError> {
+        self.post(&request)
+    }
+
+    fn send_batch(
+        &self,
+        requests: &[jsonrpc::Request],
+    ) -> Result<Vec<jsonrpc::Response>, JsonError> {
+        self.post(&requests)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (via SOCKS5 proxy)", self.url)
+    }
 }
 
 /// Wrapper around [`bitcoincore_rpc::Client`] logging failures
@@ -142,6 +267,13 @@ where
         })
     }
 
+    async fn is_synced(&self) -> Result<bool> {
+        let info = fedimint_api::task::block_in_place(|| {
+            self.0.get_blockchain_info().map_err(anyhow::Error::from)
+        })?;
+        Ok(!info.initial_block_download)
+    }
+
     async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
         fedimint_api::task::block_in_place(|| {
             bitcoincore_rpc::RpcApi::get_block_hash(&self.0, height)
@@ -182,13 +314,123 @@ where
             Ok(_) => Ok(()),
         })
     }
+
+    async fn get_unspent_output(
+        &self,
+        outpoint: &OutPoint,
+        _script_pubkey: &Script,
+    ) -> Result<Option<TxOut>> {
+        let tx_out = fedimint_api::task::block_in_place(|| {
+            self.0
+                .get_tx_out(&outpoint.txid, outpoint.vout, Some(true))
+                .map_err(anyhow::Error::from)
+        })?;
+        Ok(tx_out.map(|tx_out| TxOut {
+            value: tx_out.value.to_sat(),
+            script_pubkey: Script::from(tx_out.script_pub_key.hex),
+        }))
+    }
+}
+
+/// Bitcoind backend for guardians on constrained hardware
+///
+/// Instead of downloading every block that might contain one of our
+/// transactions it first matches the transaction's scripts against the BIP158
+/// compact filter of the block, so the node only needs to keep recent blocks.
+/// Peg-in proofs and UTXOs are verified the same way as with a full node.
+#[derive(Debug)]
+struct CompactFiltersClient<T>(Client<T>);
+
+#[async_trait]
+impl<T> IBitcoindRpc for CompactFiltersClient<T>
+where
+    T: ::bitcoincore_rpc::RpcApi + Debug + Send + Sync,
+{
+    fn backend_type(&self) -> BitcoinRpcBackendType {
+        BitcoinRpcBackendType::CompactFilters
+    }
+
+    async fn get_network(&self) -> Result<Network> {
+        self.0.get_network().await
+    }
+
+    async fn get_block_height(&self) -> Result<u64> {
+        self.0.get_block_height().await
+    }
+
+    async fn is_synced(&self) -> Result<bool> {
+        self.0.is_synced().await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        self.0.get_block_hash(height).await
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        self.0.get_block(hash).await
+    }
+
+    async fn get_fee_rate(&self, confirmation_target: u16) -> Result<Option<Feerate>> {
+        self.0.get_fee_rate(confirmation_target).await
+    }
+
+    async fn submit_transaction(&self, transaction: Transaction) -> Result<()> {
+        self.0.submit_transaction(transaction).await
+    }
+
+    async fn was_transaction_confirmed_in(
+        &self,
+        transaction: &Transaction,
+        height: u64,
+    ) -> Result<bool> {
+        let block_hash = self.0.get_block_hash(height).await?;
+        let filter = fedimint_api::task::block_in_place(|| {
+            self.0
+                 .0
+                .get_block_filter(&block_hash)
+                .map_err(anyhow::Error::from)
+        })?;
+        let filter = BlockFilter::new(&filter.filter);
+
+        let mut scripts = transaction
+            .output
+            .iter()
+            .map(|output| output.script_pubkey.as_bytes());
+        if !filter.match_any(&block_hash, &mut scripts)? {
+            return Ok(false);
+        }
+
+        // Filters have false positives, so we still need to check the block
+        let txid = transaction.txid();
+        Ok(self
+            .0
+            .get_block(&block_hash)
+            .await?
+            .txdata
+            .iter()
+            .any(|tx| tx.txid() == txid))
+    }
+
+    async fn get_unspent_output(
+        &self,
+        outpoint: &OutPoint,
+        script_pubkey: &Script,
+    ) -> Result<Option<TxOut>> {
+        self.0.get_unspent_output(outpoint, script_pubkey).await
+    }
 }
 
 pub struct ElectrumClient(electrum_client::Client);
 
 impl ElectrumClient {
-    fn new(url: &Url) -> anyhow::Result<Self> {
-        Ok(Self(electrum_client::Client::new(url.as_str())?))
+    fn new(url: &Url, proxy: Option<&str>) -> anyhow::Result<Self> {
+        let config = electrum_client::ConfigBuilder::new()
+            .socks5(proxy.map(electrum_client::Socks5Config::new))?
+            .build();
+        Ok(Self(electrum_client::Client::from_config(
+            url.as_str(),
+            config,
+        )?))
     }
 }
 
@@ -208,17 +450,7 @@ impl IBitcoindRpc for ElectrumClient {
         let resp = fedimint_api::task::block_in_place(|| {
             self.0.server_features().map_err(anyhow::Error::from)
         })?;
-        Ok(match resp.genesis_hash.to_hex().as_str() {
-            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f" => Network::Bitcoin,
-            // https://blockstream.info/testnet/block/000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943
-            "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943" => Network::Testnet,
-            // https://explorer.bc-2.jp/block/00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6
-            "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6" => Network::Signet,
-            hash => {
-                warn!("Unknown genesis hash {hash} - assuming regtest");
-                Network::Regtest
-            }
-        })
+        Ok(network_from_genesis_hash(&resp.genesis_hash.to_hex()))
     }
 
     async fn get_block_height(&self) -> Result<u64> {
@@ -291,4 +523,24 @@ impl IBitcoindRpc for ElectrumClient {
                 }))
         })
     }
+
+    async fn get_unspent_output(
+        &self,
+        outpoint: &OutPoint,
+        script_pubkey: &Script,
+    ) -> Result<Option<TxOut>> {
+        fedimint_api::task::block_in_place(|| {
+            Ok(self
+                .0
+                .script_list_unspent(script_pubkey)?
+                .into_iter()
+                .find(|unspent| {
+                    unspent.tx_hash == outpoint.txid && unspent.tx_pos == outpoint.vout as usize
+                })
+                .map(|unspent| TxOut {
+                    value: unspent.value,
+                    script_pubkey: script_pubkey.clone(),
+                }))
+        })
+    }
 }
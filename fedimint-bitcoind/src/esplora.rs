@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin_hashes::hex::ToHex;
+use serde::Deserialize;
+use url::Url;
+
+use super::*;
+
+/// [`IBitcoindRpc`] implementation talking to an [Esplora](https://github.com/Blockstream/esplora/blob/master/API.md)
+/// HTTP API, so guardians don't need to run their own bitcoind
+pub struct EsploraClient {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl EsploraClient {
+    /// Creates a client for the API at `url`, connecting through the SOCKS5
+    /// proxy at `host:port` if one is given
+    pub fn new(url: &Url, proxy: Option<&str>) -> Result<Self> {
+        let client = match proxy {
+            Some(proxy) => socks5_http_client(proxy)?,
+            None => reqwest::Client::new(),
+        };
+        Ok(Self {
+            client,
+            url: url.clone(),
+        })
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{path}", self.url.as_str().trim_end_matches('/'))
+    }
+
+    async fn get(&self, path: &str) -> Result<reqwest::Response> {
+        Ok(self
+            .client
+            .get(self.endpoint(path))
+            .send()
+            .await?
+            .error_for_status()?)
+    }
+
+    async fn get_text(&self, path: &str) -> Result<String> {
+        Ok(self.get(path).await?.text().await?)
+    }
+
+    /// Like [`Self::get`], but returns `None` if the resource doesn't exist
+    async fn get_optional(&self, path: &str) -> Result<Option<reqwest::Response>> {
+        let response = self.client.get(self.endpoint(path)).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?))
+    }
+}
+
+/// HTTP client connecting through the SOCKS5 proxy at `host:port`, host names
+/// are resolved by the proxy so onion services can be reached
+pub(crate) fn socks5_http_client(proxy: &str) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(format!("socks5h://{proxy}"))?)
+        .build()?)
+}
+
+#[derive(Deserialize)]
+struct OutputSpend {
+    spent: bool,
+}
+
+impl fmt::Debug for EsploraClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EsploraClient")
+    }
+}
+
+#[async_trait]
+impl IBitcoindRpc for EsploraClient {
+    fn backend_type(&self) -> BitcoinRpcBackendType {
+        BitcoinRpcBackendType::Esplora
+    }
+
+    async fn get_network(&self) -> Result<Network> {
+        let genesis_hash = self.get_block_hash(0).await?;
+        Ok(network_from_genesis_hash(&genesis_hash.to_string()))
+    }
+
+    async fn get_block_height(&self) -> Result<u64> {
+        Ok(self.get_text("blocks/tip/height").await?.trim().parse()?)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        Ok(BlockHash::from_str(
+            self.get_text(&format!("block-height/{height}"))
+                .await?
+                .trim(),
+        )?)
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        let bytes = self
+            .get(&format!("block/{hash}/raw"))
+            .await?
+            .bytes()
+            .await?;
+        Ok(deserialize(&bytes)?)
+    }
+
+    async fn get_fee_rate(&self, confirmation_target: u16) -> Result<Option<Feerate>> {
+        // Maps confirmation targets to sat/vB, only some targets are available
+        let estimates = self
+            .get("fee-estimates")
+            .await?
+            .json::<HashMap<String, f64>>()
+            .await?;
+        let estimate = estimates
+            .into_iter()
+            .filter_map(|(target, fee_rate)| Some((target.parse::<u16>().ok()?, fee_rate)))
+            .filter(|(target, _)| *target <= confirmation_target)
+            .max_by_key(|(target, _)| *target)
+            .map(|(_, fee_rate)| Feerate {
+                sats_per_kvb: (fee_rate * 1000f64).ceil() as u64,
+            });
+        Ok(estimate)
+    }
+
+    async fn submit_transaction(&self, transaction: Transaction) -> Result<()> {
+        let response = self
+            .client
+            .post(self.endpoint("tx"))
+            .body(serialize(&transaction).to_hex())
+            .send()
+            .await?;
+        if response.status().is_client_error() {
+            // Retrying a rejected transaction won't help, e.g. if it is already confirmed
+            let error = response.text().await?;
+            warn!(txid = %transaction.txid(), %error, "Esplora rejected transaction");
+            return Ok(());
+        }
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    async fn get_unspent_output(
+        &self,
+        outpoint: &OutPoint,
+        _script_pubkey: &Script,
+    ) -> Result<Option<TxOut>> {
+        let Some(response) = self
+            .get_optional(&format!("tx/{}/raw", outpoint.txid))
+            .await?
+        else {
+            return Ok(None);
+        };
+        let transaction: Transaction = deserialize(&response.bytes().await?)?;
+        let Some(output) = transaction.output.get(outpoint.vout as usize) else {
+            return Ok(None);
+        };
+
+        let spend = self
+            .get(&format!("tx/{}/outspend/{}", outpoint.txid, outpoint.vout))
+            .await?
+            .json::<OutputSpend>()
+            .await?;
+        Ok((!spend.spent).then(|| output.clone()))
+    }
+}
@@ -1,39 +1,53 @@
 use std::fmt::Debug;
 use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::bail;
 pub use anyhow::Result;
 use async_trait::async_trait;
-use bitcoin::{Block, BlockHash, Network, Transaction};
+use bitcoin::{Block, BlockHash, Network, OutPoint, Script, Transaction, TxOut};
 use fedimint_api::{
     bitcoin_rpc::BitcoinRpcBackendType, dyn_newtype_define, task::TaskHandle, Feerate,
 };
-use tracing::info;
+use tracing::{info, warn};
 
 #[cfg(feature = "bitcoincore-rpc")]
 pub mod bitcoincore_rpc;
+#[cfg(feature = "bitcoincore-rpc")]
+pub mod esplora;
 
 /// Trait that allows interacting with the Bitcoin blockchain
 ///
 /// Functions may panic if if the bitcoind node is not reachable.
 #[async_trait]
 pub trait IBitcoindRpc: Debug + Send + Sync {
-    /// `true` if it's real-bitcoin (not electrum) backend and thus supports
-    /// `get_block` call
+    /// Type of the backend, only electrum doesn't support the `get_block` call
     ///
     /// This is a bit of a workaround to support electrum.
     fn backend_type(&self) -> BitcoinRpcBackendType {
         BitcoinRpcBackendType::Bitcoind
     }
 
+    /// Name of the backend calls currently go to, only set if calls fail over
+    /// between several backends
+    fn active_backend(&self) -> Option<String> {
+        None
+    }
+
     /// Returns the Bitcoin network the node is connected to
     async fn get_network(&self) -> Result<bitcoin::Network>;
 
     /// Returns the current block height
     async fn get_block_height(&self) -> Result<u64>;
 
+    /// Returns whether the node finished its initial block download, backends
+    /// that can't tell report `true` as long as they respond
+    async fn is_synced(&self) -> Result<bool> {
+        self.get_block_height().await.map(|_| true)
+    }
+
     /// Returns the block hash at a given height
     ///
     /// # Panics
@@ -70,6 +84,35 @@ pub trait IBitcoindRpc: Debug + Send + Sync {
     ) -> Result<bool> {
         bail!("is_transaction_confirmed_in call not supported in standard (non-electrum) backends")
     }
+
+    /// Returns the output at `outpoint` if it exists and isn't spent, neither
+    /// in a block nor in the mempool
+    ///
+    /// `script_pubkey` is the script the output is expected to pay to, since
+    /// electrum can only look up outputs by script.
+    async fn get_unspent_output(
+        &self,
+        _outpoint: &OutPoint,
+        _script_pubkey: &Script,
+    ) -> Result<Option<TxOut>> {
+        bail!("get_unspent_output call not supported by this backend")
+    }
+}
+
+/// Determines the network from the hex encoded hash of its genesis block,
+/// unknown ones are assumed to be regtest
+pub fn network_from_genesis_hash(genesis_hash: &str) -> Network {
+    match genesis_hash {
+        "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f" => Network::Bitcoin,
+        // https://blockstream.info/testnet/block/000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943
+        "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943" => Network::Testnet,
+        // https://explorer.bc-2.jp/block/00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6
+        "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6" => Network::Signet,
+        hash => {
+            warn!("Unknown genesis hash {hash} - assuming regtest");
+            Network::Regtest
+        }
+    }
 }
 
 dyn_newtype_define! {
@@ -127,6 +170,10 @@ where
         self.inner.backend_type()
     }
 
+    fn active_backend(&self) -> Option<String> {
+        self.inner.active_backend()
+    }
+
     async fn get_network(&self) -> Result<Network> {
         self.retry_call(|| async { self.inner.get_network().await })
             .await
@@ -137,6 +184,11 @@ where
             .await
     }
 
+    async fn is_synced(&self) -> Result<bool> {
+        self.retry_call(|| async { self.inner.is_synced().await })
+            .await
+    }
+
     async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
         self.retry_call(|| async { self.inner.get_block_hash(height).await })
             .await
@@ -169,4 +221,135 @@ where
         })
         .await
     }
+
+    async fn get_unspent_output(
+        &self,
+        outpoint: &OutPoint,
+        script_pubkey: &Script,
+    ) -> Result<Option<TxOut>> {
+        self.retry_call(|| async { self.inner.get_unspent_output(outpoint, script_pubkey).await })
+            .await
+    }
+}
+
+/// Wrapper around a prioritized list of backends of the same type that
+/// switches to the next one whenever a call fails
+///
+/// The failed call still returns its error, wrapped in a [`RetryClient`] it is
+/// retried on the next backend.
+#[derive(Debug)]
+pub struct FailoverClient {
+    backends: Vec<(String, DynBitcoindRpc)>,
+    active: AtomicUsize,
+}
+
+impl FailoverClient {
+    /// Creates a client using the first of the named `backends` until it fails
+    ///
+    /// # Panics
+    /// If `backends` is empty
+    pub fn new(backends: Vec<(String, DynBitcoindRpc)>) -> Self {
+        assert!(!backends.is_empty(), "Need at least one bitcoin backend");
+        Self {
+            backends,
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    async fn failover_call<T, F, R>(&self, call_fn: F) -> Result<T>
+    where
+        F: Fn(DynBitcoindRpc) -> R,
+        R: Future<Output = Result<T>>,
+    {
+        let active = self.active.load(Ordering::Relaxed);
+        let result = call_fn(self.backends[active].1.clone()).await;
+        if let Err(e) = &result {
+            let next = (active + 1) % self.backends.len();
+            // Concurrent failing calls only fail over once
+            if next != active
+                && self
+                    .active
+                    .compare_exchange(active, next, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+            {
+                warn!(
+                    failed = self.backends[active].0,
+                    next = self.backends[next].0,
+                    "Bitcoin backend failed: {e:?}, failing over"
+                );
+            }
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl IBitcoindRpc for FailoverClient {
+    fn backend_type(&self) -> BitcoinRpcBackendType {
+        self.backends[0].1.backend_type()
+    }
+
+    fn active_backend(&self) -> Option<String> {
+        Some(self.backends[self.active.load(Ordering::Relaxed)].0.clone())
+    }
+
+    async fn get_network(&self) -> Result<Network> {
+        self.failover_call(|rpc| async move { rpc.get_network().await })
+            .await
+    }
+
+    async fn get_block_height(&self) -> Result<u64> {
+        self.failover_call(|rpc| async move { rpc.get_block_height().await })
+            .await
+    }
+
+    async fn is_synced(&self) -> Result<bool> {
+        self.failover_call(|rpc| async move { rpc.is_synced().await })
+            .await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        self.failover_call(|rpc| async move { rpc.get_block_hash(height).await })
+            .await
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        self.failover_call(|rpc| async move { rpc.get_block(hash).await })
+            .await
+    }
+
+    async fn get_fee_rate(&self, confirmation_target: u16) -> Result<Option<Feerate>> {
+        self.failover_call(|rpc| async move { rpc.get_fee_rate(confirmation_target).await })
+            .await
+    }
+
+    async fn submit_transaction(&self, transaction: Transaction) -> Result<()> {
+        self.failover_call(|rpc| {
+            let transaction = transaction.clone();
+            async move { rpc.submit_transaction(transaction).await }
+        })
+        .await
+    }
+
+    async fn was_transaction_confirmed_in(
+        &self,
+        transaction: &Transaction,
+        height: u64,
+    ) -> Result<bool> {
+        self.failover_call(|rpc| async move {
+            rpc.was_transaction_confirmed_in(transaction, height).await
+        })
+        .await
+    }
+
+    async fn get_unspent_output(
+        &self,
+        outpoint: &OutPoint,
+        script_pubkey: &Script,
+    ) -> Result<Option<TxOut>> {
+        self.failover_call(
+            |rpc| async move { rpc.get_unspent_output(outpoint, script_pubkey).await },
+        )
+        .await
+    }
 }
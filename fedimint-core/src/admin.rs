@@ -0,0 +1,186 @@
+//! Admin API of a single guardian, used by its operator to inspect and manage
+//! the guardian remotely. All endpoints but `/audit` require the guardian's
+//! admin password.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug};
+
+use async_trait::async_trait;
+use fedimint_api::core::ModuleInstanceId;
+use fedimint_api::module::audit::AuditSummary;
+use fedimint_api::module::ModuleConsensusVersion;
+use fedimint_api::PeerId;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{
+    erased_no_param, erased_single_param, FederationApiExt, FederationResult, IFederationApi,
+};
+use crate::epoch::{ModuleParamsVote, ModuleVersionVote};
+use crate::query::TrustAllPeers;
+
+/// Password authenticating requests to the admin endpoints of a guardian
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiAuth(pub String);
+
+impl Debug for ApiAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ApiAuth(...)")
+    }
+}
+
+/// Params of an admin endpoint together with the password
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthenticatedRequest<T> {
+    pub auth: ApiAuth,
+    pub params: T,
+}
+
+impl AuthenticatedRequest<()> {
+    pub fn new(auth: ApiAuth) -> Self {
+        AuthenticatedRequest { auth, params: () }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerConnectionStatus {
+    Connected,
+    Disconnected,
+}
+
+/// Response of the `/admin/status` endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuardianStatus {
+    pub our_peer_id: PeerId,
+    /// The version of the code the guardian runs
+    pub code_version: String,
+    pub epoch_count: u64,
+    pub peers: BTreeMap<PeerId, PeerConnectionStatus>,
+    /// Whether the guardian signals that it is ready to halt consensus for an
+    /// upgrade, see [`GuardianAdminApi::signal_upgrade`]
+    pub upgrade_signaled: bool,
+    /// Consensus versions the guardian votes to switch module instances to,
+    /// see [`GuardianAdminApi::vote_module_version`]
+    #[serde(default)]
+    pub module_version_votes: BTreeMap<ModuleInstanceId, ModuleConsensusVersion>,
+    /// Module params changes the guardian votes for, see
+    /// [`GuardianAdminApi::vote_module_params`]
+    #[serde(default)]
+    pub module_params_votes: BTreeMap<ModuleInstanceId, ModuleParamsVote>,
+}
+
+/// Admin endpoints, to be called on a [`crate::api::WsFederationApi`] that
+/// only contains the guardian to be managed
+#[cfg_attr(target_family = "wasm", async_trait(? Send))]
+#[cfg_attr(not(target_family = "wasm"), async_trait)]
+pub trait GuardianAdminApi {
+    async fn guardian_status(&self, auth: ApiAuth) -> FederationResult<GuardianStatus>;
+
+    /// The balance sheet of the federation, doesn't require authentication
+    async fn audit(&self) -> FederationResult<AuditSummary>;
+
+    /// Writes a copy of the guardian's database next to it and returns the path
+    /// on the guardian's machine
+    async fn backup_database(&self, auth: ApiAuth) -> FederationResult<String>;
+
+    /// Signals that the guardian is ready for an upgrade. Once a threshold of
+    /// guardians signaled, all of them halt consensus after the same epoch so
+    /// they can be restarted with the new version.
+    async fn signal_upgrade(&self, auth: ApiAuth) -> FederationResult<()>;
+
+    /// Votes to switch a module instance to another consensus version its
+    /// code supports. The federation switches in the epoch in which a
+    /// threshold of guardians voted for the same version, voting for the
+    /// active version withdraws the vote.
+    async fn vote_module_version(
+        &self,
+        auth: ApiAuth,
+        vote: ModuleVersionVote,
+    ) -> FederationResult<()>;
+
+    /// Votes to change the consensus params of a module instance from the
+    /// vote's activation epoch on. Once a threshold of guardians voted for
+    /// the same change, all of them halt consensus before that epoch and
+    /// apply the change to their configs on restart. Returns the vote as it
+    /// is proposed, with its change normalized.
+    async fn vote_module_params(
+        &self,
+        auth: ApiAuth,
+        vote: ModuleParamsVote,
+    ) -> FederationResult<ModuleParamsVote>;
+
+    async fn connected_peers(
+        &self,
+        auth: ApiAuth,
+    ) -> FederationResult<BTreeMap<PeerId, PeerConnectionStatus>>;
+}
+
+#[cfg_attr(target_family = "wasm", async_trait(? Send))]
+#[cfg_attr(not(target_family = "wasm"), async_trait)]
+impl<T: ?Sized> GuardianAdminApi for T
+where
+    T: IFederationApi + Send + Sync,
+{
+    async fn guardian_status(&self, auth: ApiAuth) -> FederationResult<GuardianStatus> {
+        request_admin(self, "/admin/status", auth).await
+    }
+
+    async fn audit(&self) -> FederationResult<AuditSummary> {
+        self.request_with_strategy(TrustAllPeers, "/audit".to_owned(), erased_no_param())
+            .await
+    }
+
+    async fn backup_database(&self, auth: ApiAuth) -> FederationResult<String> {
+        request_admin(self, "/admin/backup_db", auth).await
+    }
+
+    async fn signal_upgrade(&self, auth: ApiAuth) -> FederationResult<()> {
+        request_admin(self, "/admin/signal_upgrade", auth).await
+    }
+
+    async fn vote_module_version(
+        &self,
+        auth: ApiAuth,
+        vote: ModuleVersionVote,
+    ) -> FederationResult<()> {
+        self.request_with_strategy(
+            TrustAllPeers,
+            "/admin/vote_module_version".to_owned(),
+            erased_single_param(&AuthenticatedRequest { auth, params: vote }),
+        )
+        .await
+    }
+
+    async fn vote_module_params(
+        &self,
+        auth: ApiAuth,
+        vote: ModuleParamsVote,
+    ) -> FederationResult<ModuleParamsVote> {
+        self.request_with_strategy(
+            TrustAllPeers,
+            "/admin/vote_module_params".to_owned(),
+            erased_single_param(&AuthenticatedRequest { auth, params: vote }),
+        )
+        .await
+    }
+
+    async fn connected_peers(
+        &self,
+        auth: ApiAuth,
+    ) -> FederationResult<BTreeMap<PeerId, PeerConnectionStatus>> {
+        request_admin(self, "/admin/connected_peers", auth).await
+    }
+}
+
+async fn request_admin<Api, Ret>(api: &Api, method: &str, auth: ApiAuth) -> FederationResult<Ret>
+where
+    Api: IFederationApi + Send + Sync + ?Sized,
+    Ret: serde::de::DeserializeOwned + Debug,
+{
+    api.request_with_strategy(
+        TrustAllPeers,
+        method.to_owned(),
+        erased_single_param(&AuthenticatedRequest::new(auth)),
+    )
+    .await
+}
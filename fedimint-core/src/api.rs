@@ -1,18 +1,21 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{self, Debug};
 use std::pin::Pin;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use std::{cmp, result};
 
 use async_trait::async_trait;
+use bech32::{FromBase32, ToBase32, Variant};
 use bitcoin_hashes::sha256;
 use fedimint_api::config::{
     ApiEndpoint, ClientConfig, ConfigResponse, FederationId, ModuleGenRegistry,
 };
-use fedimint_api::core::DynOutputOutcome;
+use fedimint_api::core::{DynOutputOutcome, ModuleInstanceId};
 use fedimint_api::fmt_utils::AbbreviateDebug;
 use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::module::ModuleConsensusVersion;
 use fedimint_api::task::sleep;
 use fedimint_api::task::{RwLock, RwLockWriteGuard};
 use fedimint_api::{dyn_newtype_define, NumPeers, OutPoint, PeerId, TransactionId};
@@ -33,11 +36,12 @@ use threshold_crypto::PublicKey;
 use tracing::{debug, error, instrument, trace, warn};
 use url::Url;
 
-use crate::epoch::{SerdeEpochHistory, SignedEpochOutcome};
+use crate::epoch::{SerdeEpochHistory, SignedEpochOutcome, TransactionProof};
+use crate::meta::{FederationMeta, SignedFederationMeta};
 use crate::outcome::TransactionStatus;
 use crate::query::{
-    CurrentConsensus, EventuallyConsistent, QueryStep, QueryStrategy, UnionResponses,
-    VerifiableResponse,
+    CurrentConsensus, EventuallyConsistent, QueryStep, QueryStrategy, QueryStrategyKind,
+    UnionResponses, VerifiableResponse, VerifiedConsensus,
 };
 use crate::transaction::{SerdeTransaction, Transaction};
 use crate::CoreError;
@@ -295,7 +299,8 @@ pub trait FederationApiExt: IFederationApi {
                         QueryStep::Success(response) => return Ok(response),
                     }
                 }
-                None => return Err(FederationError(BTreeMap::new())),
+                // All members failed without the strategy giving up earlier
+                None => return Err(FederationError(member_errors)),
             }
         }
     }
@@ -332,6 +337,21 @@ pub trait FederationApiExt: IFederationApi {
         .await
     }
 
+    /// Make an aggregate request to federation, trusting the response once
+    /// as many peers agree as `kind` requires
+    async fn request_with_kind<Ret>(
+        &self,
+        kind: QueryStrategyKind,
+        method: String,
+        params: Vec<Value>,
+    ) -> FederationResult<Ret>
+    where
+        Ret: serde::de::DeserializeOwned + Eq + Debug + Clone + Send + 'static,
+    {
+        self.request_with_strategy(kind.strategy(self.all_members()), method, params)
+            .await
+    }
+
     async fn request_eventually_consistent<Ret>(
         &self,
         method: String,
@@ -362,6 +382,20 @@ dyn_newtype_define! {
 pub trait GlobalFederationApi {
     async fn submit_transaction(&self, tx: Transaction) -> FederationResult<TransactionId>;
     async fn fetch_tx_outcome(&self, txid: &TransactionId) -> FederationResult<TransactionStatus>;
+    /// Fetch the outcome of a transaction using the query strategy of `kind`.
+    /// Verifying outcomes against signed epochs is up to the caller.
+    async fn fetch_tx_outcome_with(
+        &self,
+        txid: &TransactionId,
+        kind: QueryStrategyKind,
+    ) -> FederationResult<TransactionStatus>;
+    /// Fetch a proof that the federation with the epoch public key `epoch_pk`
+    /// accepted a transaction, trusting the first peer returning a valid one
+    async fn fetch_transaction_proof(
+        &self,
+        txid: &TransactionId,
+        epoch_pk: PublicKey,
+    ) -> FederationResult<TransactionProof>;
 
     async fn fetch_epoch_history(
         &self,
@@ -394,8 +428,26 @@ pub trait GlobalFederationApi {
         module_gens: ModuleGenRegistry,
     ) -> FederationResult<ClientConfig>;
 
+    /// Like [`Self::download_client_config`], but downloads the config from
+    /// all peers of this API and only returns it if they agree on it and on
+    /// the hash of the consensus config
+    async fn download_client_config_from_all_peers(
+        &self,
+        id: &FederationId,
+        module_gens: ModuleGenRegistry,
+    ) -> FederationResult<ClientConfig>;
+
     /// Fetches the server consensus hash if enough peers agree on it
     async fn consensus_config_hash(&self) -> FederationResult<sha256::Hash>;
+
+    /// Fetches the federation's meta, trusting the first peer returning one
+    /// signed by the guardians of the federation `id`
+    async fn fetch_federation_meta(&self, id: &FederationId) -> FederationResult<FederationMeta>;
+
+    /// Fetches the consensus versions the module instances currently run with
+    async fn fetch_module_versions(
+        &self,
+    ) -> FederationResult<BTreeMap<ModuleInstanceId, ModuleConsensusVersion>>;
 }
 
 #[cfg_attr(target_family = "wasm", async_trait(? Send))]
@@ -405,6 +457,7 @@ where
     T: IFederationApi + Send + Sync + 'static,
 {
     /// Submit a transaction for inclusion
+    #[instrument(skip_all, fields(txid = %tx.tx_hash()))]
     async fn submit_transaction(&self, tx: Transaction) -> FederationResult<TransactionId> {
         self.request_current_consensus(
             "/transaction".to_owned(),
@@ -415,10 +468,43 @@ where
 
     /// Fetch the outcome of an entire transaction
     async fn fetch_tx_outcome(&self, tx: &TransactionId) -> FederationResult<TransactionStatus> {
-        self.request_current_consensus("/fetch_transaction".to_owned(), erased_single_param(&tx))
+        self.fetch_tx_outcome_with(tx, QueryStrategyKind::CurrentConsensus)
             .await
     }
 
+    async fn fetch_tx_outcome_with(
+        &self,
+        tx: &TransactionId,
+        kind: QueryStrategyKind,
+    ) -> FederationResult<TransactionStatus> {
+        self.request_with_kind(
+            kind,
+            "/fetch_transaction".to_owned(),
+            erased_single_param(&tx),
+        )
+        .await
+    }
+
+    async fn fetch_transaction_proof(
+        &self,
+        txid: &TransactionId,
+        epoch_pk: PublicKey,
+    ) -> FederationResult<TransactionProof> {
+        let verified_txid = *txid;
+        let qs = VerifiableResponse::new(
+            self.all_members().total(),
+            false,
+            move |proof: &TransactionProof| proof.verify(&verified_txid, &epoch_pk).is_ok(),
+        );
+
+        self.request_with_strategy(
+            qs,
+            "/fetch_transaction_proof".to_owned(),
+            erased_single_param(txid),
+        )
+        .await
+    }
+
     async fn fetch_epoch_history(
         &self,
         epoch: u64,
@@ -458,7 +544,9 @@ where
             strategy: VerifiableResponse::new(
                 self.all_members().one_honest(),
                 true,
-                move |epoch: &SignedEpochOutcome| epoch.verify_sig(&epoch_pk).is_ok(),
+                move |epoch: &SignedEpochOutcome| {
+                    epoch.has_valid_hash() && epoch.verify_sig(&epoch_pk).is_ok()
+                },
             ),
         };
 
@@ -507,6 +595,7 @@ where
     }
 
     // TODO should become part of the API
+    #[instrument(skip_all, fields(txid = %outpoint.txid, out_idx = outpoint.out_idx))]
     async fn await_output_outcome<R: DynTryIntoOutcome + Send>(
         &self,
         outpoint: OutPoint,
@@ -543,15 +632,7 @@ where
         let qs = VerifiableResponse::new(
             self.all_members().total(),
             false,
-            move |config: &ConfigResponse| {
-                let hash = config.client.consensus_hash(&module_gens).expect("Hashes");
-
-                if let Some(sig) = &config.client_hash_signature {
-                    id.0.verify(sig, hash)
-                } else {
-                    false
-                }
-            },
+            move |config: &ConfigResponse| verify_config_response(&id, &module_gens, config),
         );
 
         self.request_with_strategy(qs, "/config".to_owned(), erased_no_param())
@@ -559,11 +640,75 @@ where
             .map(|cfg| cfg.client)
     }
 
+    async fn download_client_config_from_all_peers(
+        &self,
+        id: &FederationId,
+        module_gens: ModuleGenRegistry,
+    ) -> FederationResult<ClientConfig> {
+        let id = id.clone();
+        // Responses only count as equal if the consensus hashes match as well
+        let qs = VerifiedConsensus::new(self.all_members().total(), move |config| {
+            verify_config_response(&id, &module_gens, config)
+        });
+
+        self.request_with_strategy(qs, "/config".to_owned(), erased_no_param())
+            .await
+            .map(|cfg: ConfigResponse| cfg.client)
+    }
+
     async fn consensus_config_hash(&self) -> FederationResult<sha256::Hash> {
         self.request_current_consensus("/config".to_owned(), erased_no_param())
             .await
             .map(|cfg: ConfigResponse| cfg.consensus_hash)
     }
+
+    async fn fetch_federation_meta(&self, id: &FederationId) -> FederationResult<FederationMeta> {
+        let id = id.clone();
+        let qs = VerifiableResponse::new(
+            self.all_members().total(),
+            false,
+            move |meta: &SignedFederationMeta| meta.verify(&id),
+        );
+
+        self.request_with_strategy(qs, "/meta".to_owned(), erased_no_param())
+            .await
+            .map(|signed: SignedFederationMeta| signed.meta)
+    }
+
+    async fn fetch_module_versions(
+        &self,
+    ) -> FederationResult<BTreeMap<ModuleInstanceId, ModuleConsensusVersion>> {
+        self.request_current_consensus("/module_versions".to_owned(), erased_no_param())
+            .await
+    }
+}
+
+/// Checks that a config served by a peer belongs to the federation `id` and was
+/// signed by its guardians
+fn verify_config_response(
+    id: &FederationId,
+    module_gens: &ModuleGenRegistry,
+    config: &ConfigResponse,
+) -> bool {
+    // Make sure the client can rely on the config, e.g. that the mint's amount
+    // tiers agree between all peers
+    if let Err(e) = config.client.validate(module_gens) {
+        warn!("Received invalid client config: {e:?}");
+        return false;
+    }
+
+    if &config.client.federation_id != id {
+        warn!("Received client config of another federation");
+        return false;
+    }
+
+    let hash = config.client.consensus_hash(module_gens).expect("Hashes");
+
+    if let Some(sig) = &config.client_hash_signature {
+        id.0.verify(sig, hash)
+    } else {
+        false
+    }
 }
 
 /// Mint API client that will try to run queries against all `members` expecting
@@ -584,8 +729,9 @@ struct FederationMember<C> {
 
 /// Information required for client to construct [`WsFederationApi`] instance
 ///
-/// Can be used to download the configs and bootstrap a client
-#[derive(Debug, Serialize, Deserialize)]
+/// Can be used to download the configs and bootstrap a client. It is shared as
+/// an invite code, see its [`Display`](fmt::Display) implementation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WsClientConnectInfo {
     /// Urls that support the federation API (expected to be in PeerId order)
     pub urls: Vec<Url>,
@@ -620,6 +766,41 @@ impl From<&ClientConfig> for WsClientConnectInfo {
     }
 }
 
+/// Human readable part of invite codes
+const INVITE_CODE_HRP: &str = "fed";
+
+/// Formats the connect info as invite code, the bech32m encoding of its JSON
+impl fmt::Display for WsClientConnectInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_vec(self).map_err(|_| fmt::Error)?;
+        let code = bech32::encode(INVITE_CODE_HRP, json.to_base32(), Variant::Bech32m)
+            .map_err(|_| fmt::Error)?;
+        f.write_str(&code)
+    }
+}
+
+/// Parses an invite code or, for compatibility, the connect info as JSON
+impl FromStr for WsClientConnectInfo {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let connect: WsClientConnectInfo = if s.starts_with('{') {
+            serde_json::from_str(s)?
+        } else {
+            let (hrp, data, variant) = bech32::decode(s)?;
+            if hrp != INVITE_CODE_HRP || variant != Variant::Bech32m {
+                return Err(anyhow::anyhow!("Not a federation invite code"));
+            }
+            serde_json::from_slice(&Vec::<u8>::from_base32(&data)?)?
+        };
+        if connect.urls.is_empty() {
+            return Err(anyhow::anyhow!("Invite code doesn't contain any guardian"));
+        }
+        Ok(connect)
+    }
+}
+
 #[cfg_attr(target_family = "wasm", async_trait(? Send))]
 #[cfg_attr(not(target_family = "wasm"), async_trait)]
 impl<C: JsonRpcClient + Debug + Send + Sync> IFederationApi for WsFederationApi<C> {
@@ -1031,4 +1212,29 @@ mod tests {
             "exactly one of two request should succeed"
         );
     }
+
+    #[test]
+    fn invite_code_roundtrip() {
+        let connect = WsClientConnectInfo {
+            urls: vec![
+                Url::parse("ws://127.0.0.1:5000").unwrap(),
+                Url::parse("wss://guardian.example.com").unwrap(),
+            ],
+            id: FederationId(threshold_crypto::SecretKey::random().public_key()),
+        };
+
+        let invite = connect.to_string();
+        assert!(invite.starts_with("fed1"));
+        assert_eq!(WsClientConnectInfo::from_str(&invite).unwrap(), connect);
+
+        let json = serde_json::to_string(&connect).unwrap();
+        assert_eq!(WsClientConnectInfo::from_str(&json).unwrap(), connect);
+
+        assert!(WsClientConnectInfo::from_str(&invite[..invite.len() - 1]).is_err());
+        let no_guardians = WsClientConnectInfo {
+            urls: vec![],
+            ..connect
+        };
+        assert!(WsClientConnectInfo::from_str(&no_guardians.to_string()).is_err());
+    }
 }
@@ -2,15 +2,18 @@ use std::collections::BTreeSet;
 use std::collections::{BTreeMap, HashSet};
 
 use bitcoin_hashes::sha256::Hash as Sha256;
+use bitcoin_hashes::Hash;
 use fedimint_api::core::DynModuleConsensusItem as ModuleConsensusItem;
+use fedimint_api::core::ModuleInstanceId;
 use fedimint_api::encoding::{Decodable, DecodeError, Encodable, UnzipConsensus};
 use fedimint_api::module::registry::ModuleDecoderRegistry;
-use fedimint_api::module::SerdeModuleEncoding;
+use fedimint_api::module::{ModuleConsensusVersion, SerdeModuleEncoding};
 use fedimint_api::{PeerId, TransactionId};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use threshold_crypto::{PublicKey, PublicKeySet, Signature, SignatureShare};
 
+use crate::merkle::{merkle_root, TxInclusionProof};
 use crate::transaction::Transaction;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, UnzipConsensus, Encodable, Decodable)]
@@ -19,6 +22,43 @@ pub enum ConsensusItem {
     EpochOutcomeSignatureShare(SerdeSignatureShare),
     Transaction(Transaction),
     Module(ModuleConsensusItem),
+    FederationMetaSignatureShare(SerdeSignatureShare),
+    /// The guardian is ready to halt consensus for an upgrade, proposed until
+    /// a threshold of guardians signaled in the same epoch
+    UpgradeSignal(UpgradeSignal),
+    /// The guardian wants a module instance to run with another consensus
+    /// version, proposed until a threshold of guardians voted for it in the
+    /// same epoch
+    ModuleVersionVote(ModuleVersionVote),
+    /// The guardian wants to change consensus parameters of a module instance
+    /// from an epoch on, proposed until a threshold of guardians voted for the
+    /// same change in the same epoch
+    ModuleParamsVote(ModuleParamsVote),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
+pub struct UpgradeSignal;
+
+#[derive(
+    Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Encodable, Decodable,
+)]
+pub struct ModuleVersionVote {
+    pub module_instance_id: ModuleInstanceId,
+    pub version: ModuleConsensusVersion,
+}
+
+/// Change of a module instance's consensus parameters, see
+/// [`fedimint_api::module::ModuleGen::apply_params_change`]
+#[derive(
+    Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Encodable, Decodable,
+)]
+pub struct ModuleParamsVote {
+    pub module_instance_id: ModuleInstanceId,
+    /// First epoch that runs with the new parameters
+    pub activation_epoch: u64,
+    /// JSON encoded change in the format defined by the module, normalized so
+    /// guardians voting for the same change propose equal items
+    pub change: String,
 }
 
 pub type SerdeConsensusItem = SerdeModuleEncoding<ConsensusItem>;
@@ -55,6 +95,97 @@ pub struct EpochOutcome {
     pub rejected_txs: BTreeSet<TransactionId>,
 }
 
+/// Summary of an [`EpochOutcome`] whose hash the federation signs. It commits
+/// to the accepted transactions through a merkle tree, so their acceptance
+/// can be proven without the rest of the epoch.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct EpochHeader {
+    pub epoch: u64,
+    pub last_hash: Option<Sha256>,
+    /// Hash of the epoch's items and rejected transactions
+    pub items_hash: Sha256,
+    /// Root of the merkle tree over [`EpochOutcome::accepted_txids`]
+    pub tx_root: Sha256,
+}
+
+impl EpochOutcome {
+    /// Hash identifying the epoch, the hash of its [`EpochHeader`]
+    pub fn hash(&self) -> Sha256 {
+        self.header().consensus_hash().expect("Hashes")
+    }
+
+    /// Hash of the whole encoded outcome, which identified epochs before they
+    /// had an [`EpochHeader`]. Epochs stored by older versions are signed over
+    /// this hash and keep it, so verifiers have to accept either.
+    pub fn legacy_hash(&self) -> Sha256 {
+        self.consensus_hash().expect("Hashes")
+    }
+
+    pub fn header(&self) -> EpochHeader {
+        let mut engine = Sha256::engine();
+        self.items
+            .consensus_encode(&mut engine)
+            .expect("Hashing never fails");
+        self.rejected_txs
+            .consensus_encode(&mut engine)
+            .expect("Hashing never fails");
+
+        EpochHeader {
+            epoch: self.epoch,
+            last_hash: self.last_hash,
+            items_hash: Sha256::from_engine(engine),
+            tx_root: merkle_root(&self.accepted_txids()),
+        }
+    }
+
+    /// Ids of the transactions that were accepted in this epoch, in the order
+    /// they were first contributed
+    pub fn accepted_txids(&self) -> Vec<TransactionId> {
+        self.items
+            .iter()
+            .flat_map(|(_, items)| items)
+            .filter_map(|item| match item {
+                ConsensusItem::Transaction(tx) => Some(tx.tx_hash()),
+                _ => None,
+            })
+            .filter(|txid| !self.rejected_txs.contains(txid))
+            .unique()
+            .collect()
+    }
+
+    /// Proves that the transaction `txid` was accepted in this epoch
+    pub fn prove_transaction(&self, txid: &TransactionId) -> Option<TxInclusionProof> {
+        let txids = self.accepted_txids();
+        let index = txids.iter().position(|accepted| accepted == txid)?;
+        TxInclusionProof::new(&txids, index)
+    }
+}
+
+/// Proof that the federation accepted a transaction, consisting of the signed
+/// header of the epoch that accepted it and the transaction's path to the
+/// header's [`EpochHeader::tx_root`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct TransactionProof {
+    pub header: EpochHeader,
+    pub signature: SerdeSignature,
+    pub inclusion: TxInclusionProof,
+}
+
+impl TransactionProof {
+    /// Checks that the federation with the epoch public key `pk` accepted the
+    /// transaction `txid`, returning the epoch it was accepted in
+    pub fn verify(&self, txid: &TransactionId, pk: &PublicKey) -> Result<u64, EpochVerifyError> {
+        let hash = self.header.consensus_hash().expect("Hashes");
+        if !pk.verify(&self.signature.0, hash) {
+            return Err(EpochVerifyError::InvalidSignature);
+        }
+        if self.inclusion.root(txid) != Some(self.header.tx_root) {
+            return Err(EpochVerifyError::TransactionNotIncluded);
+        }
+        Ok(self.header.epoch)
+    }
+}
+
 impl SignedEpochOutcome {
     pub fn new(
         epoch: u64,
@@ -74,12 +205,27 @@ impl SignedEpochOutcome {
         };
 
         SignedEpochOutcome {
-            hash: outcome.consensus_hash().expect("Hashes"),
+            hash: outcome.hash(),
             outcome,
             signature: None,
         }
     }
 
+    /// Proves that the transaction `txid` was accepted in this epoch, `None`
+    /// if it wasn't, the epoch isn't signed yet or it was signed over its
+    /// [`EpochOutcome::legacy_hash`] and thus has no signed header
+    pub fn prove_transaction(&self, txid: &TransactionId) -> Option<TransactionProof> {
+        if self.hash != self.outcome.hash() {
+            return None;
+        }
+
+        Some(TransactionProof {
+            header: self.outcome.header(),
+            signature: self.signature.clone()?,
+            inclusion: self.outcome.prove_transaction(txid)?,
+        })
+    }
+
     pub fn add_sig_to_prev(
         &self,
         pks: &PublicKeySet,
@@ -140,19 +286,28 @@ impl SignedEpochOutcome {
             match prev_epoch {
                 None => return Err(EpochVerifyError::MissingPreviousEpoch),
                 Some(prev_epoch) => {
-                    if prev_epoch.outcome.consensus_hash().ok() != self.outcome.last_hash {
+                    if !prev_epoch.has_valid_hash()
+                        || Some(prev_epoch.hash) != self.outcome.last_hash
+                    {
                         return Err(EpochVerifyError::InvalidPreviousEpochHash);
                     }
                 }
             }
         }
 
-        if Some(self.hash) == self.outcome.consensus_hash().ok() {
+        if self.has_valid_hash() {
             Ok(())
         } else {
             Err(EpochVerifyError::InvalidEpochHash)
         }
     }
+
+    /// Whether `hash` is the hash of the outcome, either the current
+    /// [`EpochOutcome::hash`] or the [`EpochOutcome::legacy_hash`] of epochs
+    /// signed before the header was introduced
+    pub fn has_valid_hash(&self) -> bool {
+        self.hash == self.outcome.hash() || self.hash == self.outcome.legacy_hash()
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -163,6 +318,7 @@ pub enum EpochVerifyError {
     InvalidEpochHash,
     InvalidPreviousEpochHash,
     NotEnoughValidSigShares(HashSet<PeerId>),
+    TransactionNotIncluded,
 }
 
 impl Encodable for SerdeSignature {
@@ -213,6 +369,7 @@ mod tests {
 
     use crate::epoch::{ConsensusItem, SerdeSignatureShare, Sha256};
     use crate::epoch::{EpochOutcome, EpochVerifyError, SerdeSignature, SignedEpochOutcome};
+    use crate::transaction::Transaction;
 
     fn signed_history(
         epoch: u16,
@@ -220,7 +377,7 @@ mod tests {
         sk: &SecretKey,
     ) -> SignedEpochOutcome {
         let missing_sig = history(epoch, prev_epoch, None);
-        let signature = sk.sign(missing_sig.outcome.consensus_hash().expect("Hashes"));
+        let signature = sk.sign(missing_sig.outcome.hash());
         history(epoch, prev_epoch, Some(SerdeSignature(signature)))
     }
 
@@ -239,7 +396,7 @@ mod tests {
         };
 
         SignedEpochOutcome {
-            hash: outcome.consensus_hash().expect("Hashes"),
+            hash: outcome.hash(),
             outcome,
             signature,
         }
@@ -334,6 +491,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn verifies_legacy_epochs() {
+        let sk: SecretKey = SecretKey::random();
+        let pk = sk.public_key();
+
+        let mut epoch0 = history(0, &None, None);
+        epoch0.hash = epoch0.outcome.legacy_hash();
+        epoch0.signature = Some(SerdeSignature(sk.sign(epoch0.hash)));
+        let epoch1 = signed_history(1, &Some(epoch0.clone()), &sk);
+
+        assert_eq!(epoch0.verify_hash(&None), Ok(()));
+        assert_eq!(epoch0.verify_sig(&pk), Ok(()));
+        assert_eq!(epoch1.verify_hash(&Some(epoch0.clone())), Ok(()));
+        assert_eq!(epoch1.verify_sig(&pk), Ok(()));
+        assert_eq!(epoch0.prove_transaction(&Hash::hash(b"tx")), None);
+    }
+
+    #[test]
+    fn proves_accepted_transactions() {
+        let sk: SecretKey = SecretKey::random();
+        let pk = sk.public_key();
+        let tx = Transaction {
+            inputs: vec![],
+            outputs: vec![],
+            signature: None,
+        };
+        let txid = tx.tx_hash();
+
+        let mut epoch = history(0, &None, None);
+        epoch.outcome.items = vec![
+            (
+                PeerId::from(0),
+                vec![ConsensusItem::Transaction(tx.clone())],
+            ),
+            (PeerId::from(1), vec![ConsensusItem::Transaction(tx)]),
+        ];
+        epoch.hash = epoch.outcome.hash();
+        assert_eq!(epoch.outcome.accepted_txids(), vec![txid]);
+        assert_eq!(epoch.prove_transaction(&txid), None); // not signed yet
+
+        epoch.signature = Some(SerdeSignature(sk.sign(epoch.hash)));
+        let proof = epoch.prove_transaction(&txid).unwrap();
+        assert_eq!(proof.verify(&txid, &pk), Ok(0));
+        assert_eq!(
+            proof.verify(&Hash::hash(b"other"), &pk),
+            Err(EpochVerifyError::TransactionNotIncluded)
+        );
+        assert_eq!(
+            proof.verify(&txid, &SecretKey::random().public_key()),
+            Err(EpochVerifyError::InvalidSignature)
+        );
+
+        epoch.outcome.rejected_txs.insert(txid);
+        assert_eq!(epoch.outcome.prove_transaction(&txid), None);
+    }
+
     #[test]
     fn verifies_sigs() {
         let sk: SecretKey = SecretKey::random();
@@ -1,9 +1,13 @@
 use thiserror::Error;
 
+pub mod admin;
 pub mod api;
 /// Fedimint toplevel config
 pub mod config;
 pub mod epoch;
+pub mod merkle;
+pub mod meta;
+pub mod nostr;
 pub mod outcome;
 pub mod query;
 pub mod transaction;
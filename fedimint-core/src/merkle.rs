@@ -0,0 +1,153 @@
+//! Merkle tree over the transactions accepted in an epoch. Its root is part of
+//! the threshold signed [`crate::epoch::EpochHeader`], so a light client can
+//! check that a transaction was accepted using a [`TxInclusionProof`] instead
+//! of downloading the whole epoch.
+//!
+//! Leaves and inner nodes are hashed with different prefixes so an inner node
+//! can never be passed off as a transaction. A node without a sibling is moved
+//! up to the next level unchanged.
+
+use bitcoin_hashes::sha256::Hash as Sha256;
+use bitcoin_hashes::Hash;
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::TransactionId;
+use serde::{Deserialize, Serialize};
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+fn leaf_hash(txid: &TransactionId) -> Sha256 {
+    let mut engine = Sha256::engine();
+    LEAF_PREFIX
+        .consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    txid.consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    Sha256::from_engine(engine)
+}
+
+fn node_hash(left: &Sha256, right: &Sha256) -> Sha256 {
+    let mut engine = Sha256::engine();
+    NODE_PREFIX
+        .consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    left.consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    right
+        .consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    Sha256::from_engine(engine)
+}
+
+/// Hashes one level of the tree into the next one
+fn next_level(level: &[Sha256]) -> Vec<Sha256> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!("chunks have one or two elements"),
+        })
+        .collect()
+}
+
+/// Root of the tree over `txids`, the hash of nothing if there are none
+pub fn merkle_root(txids: &[TransactionId]) -> Sha256 {
+    let mut level: Vec<Sha256> = txids.iter().map(leaf_hash).collect();
+    if level.is_empty() {
+        return Sha256::hash(&[]);
+    }
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Path from a transaction's leaf to the root of the tree
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct TxInclusionProof {
+    /// Position of the transaction among the accepted ones
+    pub index: u64,
+    /// Number of transactions the tree was built from
+    pub num_txs: u64,
+    /// Siblings of the nodes on the path, starting at the leaf
+    pub siblings: Vec<Sha256>,
+}
+
+impl TxInclusionProof {
+    /// Proves that the transaction at `index` of `txids` is part of the tree,
+    /// returns `None` if there is no such transaction
+    pub fn new(txids: &[TransactionId], index: usize) -> Option<Self> {
+        if index >= txids.len() {
+            return None;
+        }
+
+        let mut siblings = vec![];
+        let mut level: Vec<Sha256> = txids.iter().map(leaf_hash).collect();
+        let mut position = index;
+        while level.len() > 1 {
+            if let Some(sibling) = level.get(position ^ 1) {
+                siblings.push(*sibling);
+            }
+            level = next_level(&level);
+            position /= 2;
+        }
+
+        Some(TxInclusionProof {
+            index: index as u64,
+            num_txs: txids.len() as u64,
+            siblings,
+        })
+    }
+
+    /// Root of the tree if `txid` is the proven transaction, `None` if the
+    /// proof is malformed
+    pub fn root(&self, txid: &TransactionId) -> Option<Sha256> {
+        if self.index >= self.num_txs {
+            return None;
+        }
+
+        let mut siblings = self.siblings.iter();
+        let mut hash = leaf_hash(txid);
+        let mut position = self.index;
+        let mut width = self.num_txs;
+        while width > 1 {
+            if position % 2 == 1 {
+                hash = node_hash(siblings.next()?, &hash);
+            } else if position + 1 < width {
+                hash = node_hash(&hash, siblings.next()?);
+            }
+            position /= 2;
+            width = (width + 1) / 2;
+        }
+
+        if siblings.next().is_some() {
+            return None;
+        }
+        Some(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::Hash;
+    use fedimint_api::TransactionId;
+
+    use super::{merkle_root, TxInclusionProof};
+
+    #[test]
+    fn proves_every_transaction() {
+        for num_txs in 1..=9u8 {
+            let txids: Vec<TransactionId> =
+                (0..num_txs).map(|i| TransactionId::hash(&[i])).collect();
+            let root = merkle_root(&txids);
+
+            for (index, txid) in txids.iter().enumerate() {
+                let proof = TxInclusionProof::new(&txids, index).unwrap();
+                assert_eq!(proof.root(txid), Some(root));
+                assert_ne!(proof.root(&TransactionId::hash(b"other")), Some(root));
+            }
+            assert_eq!(TxInclusionProof::new(&txids, txids.len()), None);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Information about a federation wallets display to their users, like its
+//! name and a message of the day. The guardians threshold sign it with the
+//! federation's auth key, so clients can rely on it no matter which guardian
+//! served it.
+
+use std::collections::BTreeMap;
+
+use fedimint_api::config::FederationId;
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::Amount;
+use serde::{Deserialize, Serialize};
+
+use crate::epoch::SerdeSignature;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct FederationMeta {
+    pub name: String,
+    /// URL of an image representing the federation
+    pub icon_url: Option<String>,
+    /// Announcement of the guardians, e.g. about planned downtime
+    pub message_of_the_day: Option<String>,
+    /// Fees by operation (e.g. `peg-in`) as advertised by the guardians, the
+    /// fees actually charged are the ones in the modules' configs
+    pub fee_schedule: BTreeMap<String, Amount>,
+}
+
+/// Response of the `/meta` endpoint, the signature is missing until the
+/// guardians agreed on it in consensus
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedFederationMeta {
+    pub meta: FederationMeta,
+    pub signature: Option<SerdeSignature>,
+}
+
+impl SignedFederationMeta {
+    /// Checks that the guardians of the federation `id` signed the meta
+    pub fn verify(&self, id: &FederationId) -> bool {
+        let hash = self.meta.consensus_hash().expect("Hashes");
+        self.signature
+            .as_ref()
+            .map_or(false, |signature| id.0.verify(&signature.0, hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::config::FederationId;
+    use fedimint_api::encoding::Encodable;
+    use fedimint_api::Amount;
+
+    use super::{FederationMeta, SignedFederationMeta};
+    use crate::epoch::SerdeSignature;
+
+    #[test]
+    fn verifies_guardian_signature() {
+        let sk = threshold_crypto::SecretKey::random();
+        let id = FederationId(sk.public_key());
+        let meta = FederationMeta {
+            name: "Test Federation".to_string(),
+            message_of_the_day: Some("Maintenance tonight".to_string()),
+            fee_schedule: [("peg-in".to_string(), Amount::from_sats(1000))].into(),
+            ..Default::default()
+        };
+        let signature = SerdeSignature(sk.sign(meta.consensus_hash().unwrap()));
+
+        let mut signed = SignedFederationMeta {
+            meta,
+            signature: Some(signature),
+        };
+        assert!(signed.verify(&id));
+        assert!(!signed.verify(&FederationId(
+            threshold_crypto::SecretKey::random().public_key()
+        )));
+
+        signed.meta.message_of_the_day = None;
+        assert!(!signed.verify(&id));
+        signed.signature = None;
+        assert!(!signed.verify(&id));
+    }
+}
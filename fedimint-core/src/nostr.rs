@@ -0,0 +1,384 @@
+//! Announcements of federations on Nostr relays, so wallets can discover
+//! federations without being handed an invite code.
+//!
+//! Guardians publish a [`FederationAnnouncement`] as parameterized replaceable
+//! event of kind [`FEDERATION_ANNOUNCEMENT_KIND`] (NIP-87) identified by the
+//! federation id, so relays only keep the latest one of every guardian. The
+//! announcement contains the guardian-signed [`SignedFederationMeta`], which
+//! binds it to the federation id of the invite code. Since anybody can
+//! publish announcements, clients still have to verify the config they
+//! download against the federation id when joining.
+
+use std::time::UNIX_EPOCH;
+
+use anyhow::{bail, ensure, format_err};
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{schnorr, KeyPair, Message, Secp256k1, XOnlyPublicKey};
+use fedimint_api::config::FederationId;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::WsClientConnectInfo;
+use crate::meta::{FederationMeta, SignedFederationMeta};
+
+/// Event kind of federation announcements, see NIP-87
+pub const FEDERATION_ANNOUNCEMENT_KIND: u32 = 38173;
+
+/// A signed Nostr event as defined in NIP-01
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NostrEvent {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u32,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+impl NostrEvent {
+    /// Creates an event signed with `keypair`, timestamped with the current
+    /// time
+    pub fn sign(keypair: &KeyPair, kind: u32, tags: Vec<Vec<String>>, content: String) -> Self {
+        let secp = Secp256k1::new();
+        let pubkey = XOnlyPublicKey::from_keypair(keypair).0.to_string();
+        let created_at = fedimint_api::time::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        let id = event_id(&pubkey, created_at, kind, &tags, &content);
+        let sig = secp.sign_schnorr_no_aux_rand(
+            &Message::from_slice(&id.into_inner()).expect("Hashes are valid messages"),
+            keypair,
+        );
+
+        NostrEvent {
+            id: id.into_inner().to_hex(),
+            pubkey,
+            created_at,
+            kind,
+            tags,
+            content,
+            sig: sig.to_string(),
+        }
+    }
+
+    /// Checks that the id commits to the event and that the author signed it
+    pub fn verify(&self) -> anyhow::Result<()> {
+        let pubkey: XOnlyPublicKey = self.pubkey.parse()?;
+        let sig: schnorr::Signature = self.sig.parse()?;
+        let id = event_id(
+            &self.pubkey,
+            self.created_at,
+            self.kind,
+            &self.tags,
+            &self.content,
+        );
+        ensure!(
+            id.into_inner().to_hex() == self.id,
+            "Event id doesn't match"
+        );
+
+        Secp256k1::verification_only()
+            .verify_schnorr(
+                &sig,
+                &Message::from_slice(&id.into_inner()).expect("Hashes are valid messages"),
+                &pubkey,
+            )
+            .map_err(|_| format_err!("Invalid event signature"))
+    }
+
+    /// Value of the first tag named `name`
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|tag| tag.first().map(String::as_str) == Some(name))
+            .and_then(|tag| tag.get(1))
+            .map(String::as_str)
+    }
+}
+
+fn event_id(
+    pubkey: &str,
+    created_at: u64,
+    kind: u32,
+    tags: &[Vec<String>],
+    content: &str,
+) -> sha256::Hash {
+    let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    sha256::Hash::hash(serialized.as_bytes())
+}
+
+/// Content of a federation announcement event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FederationAnnouncement {
+    pub invite_code: String,
+    pub meta: SignedFederationMeta,
+    /// Nostr keys of the guardians announcing the federation, announcements
+    /// by other keys are ignored
+    pub guardian_pubkeys: Vec<String>,
+}
+
+impl FederationAnnouncement {
+    /// Creates the announcement event of a guardian holding `keypair`
+    pub fn to_event(&self, keypair: &KeyPair) -> anyhow::Result<NostrEvent> {
+        let connect_info: WsClientConnectInfo = self.invite_code.parse()?;
+        Ok(NostrEvent::sign(
+            keypair,
+            FEDERATION_ANNOUNCEMENT_KIND,
+            vec![
+                vec!["d".to_string(), connect_info.id.to_string()],
+                vec!["u".to_string(), self.invite_code.clone()],
+            ],
+            serde_json::to_string(self)?,
+        ))
+    }
+}
+
+/// A federation announcement that passed [`VerifiedAnnouncement::verify`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiedAnnouncement {
+    /// Nostr key of the guardian that published the announcement
+    pub author: String,
+    pub created_at: u64,
+    pub federation_id: FederationId,
+    pub connect_info: WsClientConnectInfo,
+    pub meta: FederationMeta,
+}
+
+impl VerifiedAnnouncement {
+    /// Checks that `event` is a federation announcement signed by one of the
+    /// guardian keys it lists and carrying meta signed by the federation of
+    /// its invite code
+    pub fn verify(event: &NostrEvent) -> anyhow::Result<Self> {
+        ensure!(
+            event.kind == FEDERATION_ANNOUNCEMENT_KIND,
+            "Not a federation announcement"
+        );
+        event.verify()?;
+
+        let announcement: FederationAnnouncement = serde_json::from_str(&event.content)?;
+        if !announcement.guardian_pubkeys.contains(&event.pubkey) {
+            bail!("Announcement wasn't published by one of its guardians");
+        }
+        let connect_info: WsClientConnectInfo = announcement.invite_code.parse()?;
+        if event.tag("d") != Some(connect_info.id.to_string().as_str()) {
+            bail!("Announcement is tagged with another federation id");
+        }
+        if !announcement.meta.verify(&connect_info.id) {
+            bail!("Federation meta isn't signed by the federation");
+        }
+
+        Ok(VerifiedAnnouncement {
+            author: event.pubkey.clone(),
+            created_at: event.created_at,
+            federation_id: connect_info.id.clone(),
+            connect_info,
+            meta: announcement.meta.meta,
+        })
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub use relay::{fetch_events, publish_event};
+
+/// Minimal relay client speaking the NIP-01 websocket protocol
+#[cfg(not(target_family = "wasm"))]
+mod relay {
+    use std::time::Duration;
+
+    use anyhow::{bail, format_err};
+    use fedimint_api::task::timeout;
+    use jsonrpsee_client_transport::ws::{Receiver, Sender, Uri, WsTransportClientBuilder};
+    use jsonrpsee_core::client::{ReceivedMessage, TransportReceiverT, TransportSenderT};
+    use serde_json::{json, Value};
+    use url::Url;
+
+    use super::NostrEvent;
+
+    /// How long we wait for a relay to answer
+    const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Publishes `event` to `relay`, failing unless the relay accepts it
+    pub async fn publish_event(relay: &Url, event: &NostrEvent) -> anyhow::Result<()> {
+        timeout(RELAY_TIMEOUT, try_publish_event(relay, event))
+            .await
+            .map_err(|_| format_err!("Relay {relay} timed out"))?
+    }
+
+    async fn try_publish_event(relay: &Url, event: &NostrEvent) -> anyhow::Result<()> {
+        let (mut sender, mut receiver) = connect(relay).await?;
+        sender.send(json!(["EVENT", event]).to_string()).await?;
+        let accepted = loop {
+            let message = receive(&mut receiver).await?;
+            match message.first().and_then(Value::as_str) {
+                Some("OK") if message.get(1) == Some(&json!(event.id)) => break message,
+                Some("NOTICE") => bail!("Relay refused the event: {message:?}"),
+                _ => {}
+            }
+        };
+        let _ = sender.close().await;
+
+        if accepted.get(2).and_then(Value::as_bool) != Some(true) {
+            bail!(
+                "Relay rejected the event: {}",
+                accepted.get(3).and_then(Value::as_str).unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+
+    /// Fetches the stored events matching the NIP-01 `filter` from `relay`
+    pub async fn fetch_events(relay: &Url, filter: Value) -> anyhow::Result<Vec<NostrEvent>> {
+        timeout(RELAY_TIMEOUT, try_fetch_events(relay, filter))
+            .await
+            .map_err(|_| format_err!("Relay {relay} timed out"))?
+    }
+
+    async fn try_fetch_events(relay: &Url, filter: Value) -> anyhow::Result<Vec<NostrEvent>> {
+        const SUBSCRIPTION: &str = "fedimint";
+
+        let (mut sender, mut receiver) = connect(relay).await?;
+        sender
+            .send(json!(["REQ", SUBSCRIPTION, filter]).to_string())
+            .await?;
+
+        let mut events = vec![];
+        loop {
+            let message = receive(&mut receiver).await?;
+            if message.get(1) != Some(&json!(SUBSCRIPTION)) {
+                continue;
+            }
+            match message.first().and_then(Value::as_str) {
+                Some("EVENT") => {
+                    if let Some(event) = message.get(2) {
+                        events.push(serde_json::from_value(event.clone())?);
+                    }
+                }
+                Some("EOSE") => break,
+                Some("CLOSED") => bail!("Relay closed the subscription: {message:?}"),
+                _ => {}
+            }
+        }
+
+        let _ = sender
+            .send(json!(["CLOSE", SUBSCRIPTION]).to_string())
+            .await;
+        let _ = sender.close().await;
+        Ok(events)
+    }
+
+    async fn connect(relay: &Url) -> anyhow::Result<(Sender, Receiver)> {
+        let uri: Uri = relay.as_str().parse()?;
+        Ok(WsTransportClientBuilder::default().build(uri).await?)
+    }
+
+    /// Receives the next message, a JSON array
+    async fn receive(receiver: &mut Receiver) -> anyhow::Result<Vec<Value>> {
+        loop {
+            match receiver.receive().await? {
+                ReceivedMessage::Text(text) => return Ok(serde_json::from_str(&text)?),
+                ReceivedMessage::Bytes(bytes) => return Ok(serde_json::from_slice(&bytes)?),
+                ReceivedMessage::Pong => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::{KeyPair, Secp256k1, SecretKey, XOnlyPublicKey};
+    use fedimint_api::config::FederationId;
+    use fedimint_api::encoding::Encodable;
+
+    use super::{FederationAnnouncement, NostrEvent, VerifiedAnnouncement};
+    use crate::api::WsClientConnectInfo;
+    use crate::epoch::SerdeSignature;
+    use crate::meta::{FederationMeta, SignedFederationMeta};
+
+    fn keypair(byte: u8) -> KeyPair {
+        let sk = SecretKey::from_slice(&[byte; 32]).unwrap();
+        KeyPair::from_secret_key(&Secp256k1::new(), &sk)
+    }
+
+    fn announcement(
+        federation_sk: &threshold_crypto::SecretKey,
+        guardians: &[&KeyPair],
+    ) -> FederationAnnouncement {
+        let connect_info = WsClientConnectInfo {
+            urls: vec!["ws://guardian.example:5000".parse().unwrap()],
+            id: FederationId(federation_sk.public_key()),
+        };
+        let meta = FederationMeta {
+            name: "Test Federation".to_string(),
+            ..Default::default()
+        };
+        let signature = SerdeSignature(federation_sk.sign(meta.consensus_hash().unwrap()));
+        FederationAnnouncement {
+            invite_code: connect_info.to_string(),
+            meta: SignedFederationMeta {
+                meta,
+                signature: Some(signature),
+            },
+            guardian_pubkeys: guardians
+                .iter()
+                .map(|keypair| XOnlyPublicKey::from_keypair(keypair).0.to_string())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn verifies_event_signature() {
+        let event = NostrEvent::sign(&keypair(1), 1, vec![], "hello".to_string());
+        event.verify().unwrap();
+
+        let mut tampered = event.clone();
+        tampered.content = "bye".to_string();
+        assert!(tampered.verify().is_err());
+
+        let mut forged = event;
+        forged.pubkey = XOnlyPublicKey::from_keypair(&keypair(2)).0.to_string();
+        assert!(forged.verify().is_err());
+    }
+
+    #[test]
+    fn verifies_announcement() {
+        let federation_sk = threshold_crypto::SecretKey::random();
+        let guardian = keypair(1);
+        let announcement = announcement(&federation_sk, &[&guardian]);
+
+        let event = announcement.to_event(&guardian).unwrap();
+        let verified = VerifiedAnnouncement::verify(&event).unwrap();
+        assert_eq!(
+            verified.federation_id,
+            FederationId(federation_sk.public_key())
+        );
+        assert_eq!(verified.meta, announcement.meta.meta);
+
+        // Only listed guardians may announce the federation
+        let outsider = announcement.to_event(&keypair(2)).unwrap();
+        assert!(VerifiedAnnouncement::verify(&outsider).is_err());
+
+        // The meta has to be signed by the federation of the invite code
+        let mut unsigned = announcement.clone();
+        unsigned.meta.signature = None;
+        let event = unsigned.to_event(&guardian).unwrap();
+        assert!(VerifiedAnnouncement::verify(&event).is_err());
+
+        let other_federation = announcement_for_other_federation(&announcement);
+        let event = other_federation.to_event(&guardian).unwrap();
+        assert!(VerifiedAnnouncement::verify(&event).is_err());
+    }
+
+    fn announcement_for_other_federation(
+        announcement: &FederationAnnouncement,
+    ) -> FederationAnnouncement {
+        let mut connect_info: WsClientConnectInfo = announcement.invite_code.parse().unwrap();
+        connect_info.id = FederationId(threshold_crypto::SecretKey::random().public_key());
+        FederationAnnouncement {
+            invite_code: connect_info.to_string(),
+            ..announcement.clone()
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use std::collections::{BTreeMap, BTreeSet, HashSet};
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::mem;
+use std::str::FromStr;
 
-use fedimint_api::PeerId;
+use fedimint_api::{NumPeers, PeerId};
 use jsonrpsee_core::Error as JsonRpcError;
 use jsonrpsee_types::error::CallError as RpcCallError;
+use serde::{Deserialize, Serialize};
 use tracing::debug;
 
 use crate::api;
@@ -71,6 +73,37 @@ impl<R: Debug + Eq + Clone> QueryStrategy<R> for VerifiableResponse<R> {
     }
 }
 
+/// Returns when `required` responses that pass `verifier` are equal, e.g. to
+/// cross-check signed data between several peers
+pub struct VerifiedConsensus<R> {
+    verifier: Box<dyn Fn(&R) -> bool + Send + Sync>,
+    current: CurrentConsensus<R>,
+}
+
+impl<R> VerifiedConsensus<R> {
+    pub fn new(required: usize, verifier: impl Fn(&R) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            verifier: Box::new(verifier),
+            current: CurrentConsensus::new(required),
+        }
+    }
+}
+
+impl<R: Debug + Eq + Clone> QueryStrategy<R> for VerifiedConsensus<R> {
+    fn process(&mut self, peer: PeerId, result: api::MemberResult<R>) -> QueryStep<R> {
+        match result {
+            Ok(result) if (self.verifier)(&result) => self.current.process(peer, Ok(result)),
+            Ok(_) => self.current.process(
+                peer,
+                Err(MemberError::InvalidResponse(
+                    "Invalid signature".to_string(),
+                )),
+            ),
+            error => self.current.process(peer, error),
+        }
+    }
+}
+
 /// Returns the deduplicated union of `required` number of responses
 pub struct UnionResponses<R> {
     responses: HashSet<PeerId>,
@@ -279,10 +312,86 @@ impl<R: Eq + Clone + Debug> QueryStrategy<R> for CurrentConsensus<R> {
     }
 }
 
+/// How many peers have to agree on a response before it is trusted, chosen per
+/// call to trade the latency of a request against the trust put into the peers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueryStrategyKind {
+    /// Trusts the first peer to respond
+    Fastest,
+    /// Waits until enough peers agree that at least one of them is honest
+    #[default]
+    CurrentConsensus,
+    /// Waits until as many peers agree as are needed to reach consensus
+    ThresholdConsensus,
+    /// Waits until all peers agree
+    AllPeers,
+    /// Like [`QueryStrategyKind::ThresholdConsensus`], but responses that can
+    /// be checked against the federation's threshold signatures, like client
+    /// configs and transaction outcomes, are only trusted once they were
+    /// verified
+    Verified,
+}
+
+impl QueryStrategyKind {
+    /// Strategy of this kind for a request to `peers`
+    pub fn strategy<R>(self, peers: &BTreeSet<PeerId>) -> Box<dyn QueryStrategy<R> + Send>
+    where
+        R: Debug + Eq + Clone + Send + 'static,
+    {
+        match self {
+            QueryStrategyKind::Fastest => Box::new(TrustAllPeers),
+            QueryStrategyKind::CurrentConsensus => {
+                Box::new(CurrentConsensus::new(peers.one_honest()))
+            }
+            QueryStrategyKind::ThresholdConsensus | QueryStrategyKind::Verified => {
+                Box::new(CurrentConsensus::new(peers.threshold()))
+            }
+            QueryStrategyKind::AllPeers => Box::new(CurrentConsensus::new(peers.total())),
+        }
+    }
+}
+
+impl fmt::Display for QueryStrategyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QueryStrategyKind::Fastest => "fastest",
+            QueryStrategyKind::CurrentConsensus => "current-consensus",
+            QueryStrategyKind::ThresholdConsensus => "threshold-consensus",
+            QueryStrategyKind::AllPeers => "all-peers",
+            QueryStrategyKind::Verified => "verified",
+        })
+    }
+}
+
+impl FromStr for QueryStrategyKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fastest" => Ok(QueryStrategyKind::Fastest),
+            "current-consensus" => Ok(QueryStrategyKind::CurrentConsensus),
+            "threshold-consensus" => Ok(QueryStrategyKind::ThresholdConsensus),
+            "all-peers" => Ok(QueryStrategyKind::AllPeers),
+            "verified" => Ok(QueryStrategyKind::Verified),
+            _ => Err(anyhow::anyhow!("Unknown query strategy: {s}")),
+        }
+    }
+}
+
 pub trait QueryStrategy<IR, OR = IR> {
     fn process(&mut self, peer_id: PeerId, response: api::MemberResult<IR>) -> QueryStep<OR>;
 }
 
+impl<IR, OR, S> QueryStrategy<IR, OR> for Box<S>
+where
+    S: QueryStrategy<IR, OR> + ?Sized,
+{
+    fn process(&mut self, peer_id: PeerId, response: api::MemberResult<IR>) -> QueryStep<OR> {
+        (**self).process(peer_id, response)
+    }
+}
+
 /// Results from the strategy handling a response from a peer
 ///
 /// Note that the implementation driving the [`QueryStrategy`] returning
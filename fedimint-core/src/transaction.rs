@@ -81,6 +81,22 @@ impl Transaction {
             Err(TransactionError::InvalidSignature)
         }
     }
+
+    /// Validate that the encoded transaction does not exceed `max_size` bytes,
+    /// if the federation has a limit
+    pub fn validate_size(&self, max_size: Option<u64>) -> Result<(), TransactionError> {
+        let Some(max_size) = max_size else {
+            return Ok(());
+        };
+        let size = self
+            .consensus_encode_to_vec()
+            .expect("write to vec can't fail")
+            .len() as u64;
+        if size > max_size {
+            return Err(TransactionError::TransactionTooLarge { size, max_size });
+        }
+        Ok(())
+    }
 }
 
 /// Aggregate a stream of public keys.
@@ -167,4 +183,6 @@ pub enum TransactionError {
     InvalidSignature,
     #[error("The transaction did not have a signature although there were inputs to be signed")]
     MissingSignature,
+    #[error("The transaction is too large (size={size}, max={max_size})")]
+    TransactionTooLarge { size: u64, max_size: u64 },
 }
@@ -1,17 +1,18 @@
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
 
 use erased_serde::Serialize;
 use fedimint_api::{
     config::ModuleGenRegistry,
     db::DatabaseTransaction,
     encoding::Encodable,
-    module::{DynModuleGen, __reexports::serde_json, registry::ModuleDecoderRegistry},
+    module::{__reexports::serde_json, registry::ModuleDecoderRegistry, DynModuleGen},
     push_db_key_items, push_db_pair_items, push_db_pair_items_no_serde,
 };
 use fedimint_ln::LightningGen;
 use fedimint_mint::MintGen;
 use fedimint_rocksdb::RocksDbReadOnly;
 use fedimint_server::config::io::{read_server_configs, SALT_FILE};
+use fedimint_server::encrypted_db::{EncryptedDbTransaction, DB_KEY_CONTEXT};
 use fedimint_server::{config::ServerConfig, db as ConsensusRange};
 use fedimint_wallet::WalletGen;
 use futures::StreamExt;
@@ -79,10 +80,18 @@ impl<'a> DatabaseDump<'a> {
         ]);
 
         let salt_path = cfg_dir.join(SALT_FILE);
-        let key = aead::get_key(password, salt_path).unwrap();
-        let cfg = read_server_configs(&key, cfg_dir).unwrap();
+        let key = aead::get_key(password.clone(), salt_path).unwrap();
+        let cfg = read_server_configs(&key, cfg_dir.clone()).unwrap();
         let decoders = module_inits.decoders(cfg.iter_module_instances()).unwrap();
-        let dbtx = DatabaseTransaction::new(Box::new(read_only), decoders);
+        let dbtx = if cfg.local.encrypt_database {
+            let db_key =
+                aead::get_key_with_context(password, cfg_dir.join(SALT_FILE), DB_KEY_CONTEXT)
+                    .unwrap();
+            let encrypted = EncryptedDbTransaction::new(Box::new(read_only), Arc::new(db_key));
+            DatabaseTransaction::new(Box::new(encrypted), decoders)
+        } else {
+            DatabaseTransaction::new(Box::new(read_only), decoders)
+        };
 
         DatabaseDump {
             serialized: BTreeMap::new(),
@@ -213,6 +222,84 @@ impl<'a> DatabaseDump<'a> {
                         "Client Config Signature"
                     );
                 }
+                ConsensusRange::DbKeyPrefix::FederationMetaSignature => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::FederationMetaSignatureKeyPrefix,
+                        ConsensusRange::FederationMetaSignatureKey,
+                        fedimint_core::epoch::SerdeSignature,
+                        consensus,
+                        "Federation Meta Signatures"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::UpgradeSignal => {
+                    let signal = dbtx
+                        .get_value(&ConsensusRange::UpgradeSignalKey)
+                        .await
+                        .unwrap();
+                    if let Some(signal) = signal {
+                        consensus.insert("UpgradeSignal".to_string(), Box::new(signal));
+                    }
+                }
+                ConsensusRange::DbKeyPrefix::ModuleVersionVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::ModuleVersionVoteKeyPrefix,
+                        ConsensusRange::ModuleVersionVoteKey,
+                        fedimint_api::module::ModuleConsensusVersion,
+                        consensus,
+                        "Module Version Votes"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::ActiveModuleVersion => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::ActiveModuleVersionKeyPrefix,
+                        ConsensusRange::ActiveModuleVersionKey,
+                        fedimint_api::module::ModuleConsensusVersion,
+                        consensus,
+                        "Active Module Versions"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::ModuleParamsVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::ModuleParamsVoteKeyPrefix,
+                        ConsensusRange::ModuleParamsVoteKey,
+                        fedimint_core::epoch::ModuleParamsVote,
+                        consensus,
+                        "Module Params Votes"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::ScheduledModuleParams => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::ScheduledModuleParamsKeyPrefix,
+                        ConsensusRange::ScheduledModuleParamsKey,
+                        ConsensusRange::ScheduledModuleParams,
+                        consensus,
+                        "Scheduled Module Params"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::DatabaseEncrypted => {
+                    let encrypted = dbtx
+                        .get_value(&ConsensusRange::DatabaseEncryptedKey)
+                        .await
+                        .unwrap();
+                    consensus.insert(
+                        "DatabaseEncrypted".to_string(),
+                        Box::new(encrypted.is_some()),
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::DatabaseVersion => {
+                    let version = dbtx
+                        .get_value(&fedimint_api::db::DatabaseVersionKey)
+                        .await
+                        .unwrap();
+                    if let Some(version) = version {
+                        consensus.insert("DatabaseVersion".to_string(), Box::new(version));
+                    }
+                }
                 // Module is a global prefix for all module data
                 ConsensusRange::DbKeyPrefix::Module => {}
             }
@@ -253,6 +340,125 @@ impl<'a> DatabaseDump<'a> {
                         "Lightning Gateways"
                     );
                 }
+                ClientLightningRange::DbKeyPrefix::GatewayReliability => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientLightningRange::GatewayReliabilityKeyPrefix,
+                        ClientLightningRange::GatewayReliabilityKey,
+                        mint_client::ln::GatewayReliability,
+                        ln_client,
+                        "Gateway Reliability"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::ReceiveOffer => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientLightningRange::ReceiveOfferKeyPrefix,
+                        ClientLightningRange::ReceiveOfferKey,
+                        mint_client::ln::incoming::ReceiveOffer,
+                        ln_client,
+                        "Receive Offers"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::OfferRegistration => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientLightningRange::OfferRegistrationKeyPrefix,
+                        ClientLightningRange::OfferRegistrationKey,
+                        fedimint_ln::contracts::account::AccountContract,
+                        ln_client,
+                        "Offer Registrations"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::OfferPayIndex => {
+                    let pay_index = dbtx
+                        .get_value(&ClientLightningRange::OfferPayIndexKey)
+                        .await
+                        .unwrap();
+                    if let Some(pay_index) = pay_index {
+                        ln_client.insert("OfferPayIndex".to_string(), Box::new(pay_index));
+                    }
+                }
+                ClientLightningRange::DbKeyPrefix::ContractEvidence => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientLightningRange::ContractEvidenceKeyPrefix,
+                        ClientLightningRange::ContractEvidenceKey,
+                        mint_client::ln::proof::ContractEvidence,
+                        ln_client,
+                        "Contract Evidence"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::IncomingClaim => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientLightningRange::IncomingClaimKeyPrefix,
+                        ClientLightningRange::IncomingClaimKey,
+                        fedimint_api::OutPoint,
+                        ln_client,
+                        "Incoming Claims"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::OutgoingPaymentParts => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientLightningRange::OutgoingPaymentPartsKeyPrefix,
+                        ClientLightningRange::OutgoingPaymentPartsKey,
+                        mint_client::ln::outgoing::OutgoingPaymentParts,
+                        ln_client,
+                        "Outgoing Payment Parts"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::GatewayPayment => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientLightningRange::GatewayPaymentKeyPrefix,
+                        ClientLightningRange::GatewayPaymentKey,
+                        mint_client::ln::GatewayPayment,
+                        ln_client,
+                        "Gateway Payments"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::InternalPayment => {
+                    // Only list the contracts, their values are secret keys
+                    push_db_key_items!(
+                        dbtx,
+                        ClientLightningRange::InternalPaymentKeyPrefix,
+                        ClientLightningRange::InternalPaymentKey,
+                        ln_client,
+                        "Internal Payments"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::OutgoingPaymentRecord => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientLightningRange::OutgoingPaymentRecordKeyPrefix,
+                        ClientLightningRange::OutgoingPaymentRecordKey,
+                        mint_client::ln::outgoing::OutgoingPaymentRecord,
+                        ln_client,
+                        "Outgoing Payment Records"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::WatchtowerRefund => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientLightningRange::WatchtowerRefundKeyPrefix,
+                        ClientLightningRange::WatchtowerRefundKey,
+                        mint_client::ln::watchtower::WatchtowerRefundIssuance,
+                        ln_client,
+                        "Watchtower Refunds"
+                    );
+                }
+                ClientLightningRange::DbKeyPrefix::WatchedRefund => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientLightningRange::WatchedRefundKeyPrefix,
+                        ClientLightningRange::WatchedRefundKey,
+                        mint_client::ln::watchtower::WatchtowerRefund,
+                        ln_client,
+                        "Watched Refunds"
+                    );
+                }
                 ClientLightningRange::DbKeyPrefix::OutgoingContractAccount => {
                     push_db_pair_items!(
                         dbtx,
@@ -349,6 +555,24 @@ impl<'a> DatabaseDump<'a> {
                         mint_client.insert("NotesPerDenomination".to_string(), Box::new(notes));
                     }
                 }
+                ClientMintRange::DbKeyPrefix::NoteSelection => {
+                    let selection = dbtx
+                        .get_value(&ClientMintRange::NoteSelectionKey)
+                        .await
+                        .unwrap();
+                    if let Some(selection) = selection {
+                        mint_client.insert("NoteSelection".to_string(), Box::new(selection));
+                    }
+                }
+                ClientMintRange::DbKeyPrefix::BackupMetadata => {
+                    let metadata = dbtx
+                        .get_value(&ClientMintRange::BackupMetadataKey)
+                        .await
+                        .unwrap();
+                    if let Some(metadata) = metadata {
+                        mint_client.insert("BackupMetadata".to_string(), Box::new(metadata));
+                    }
+                }
             }
         }
 
@@ -377,6 +601,36 @@ impl<'a> DatabaseDump<'a> {
                         "Peg Ins"
                     );
                 }
+                ClientWalletRange::DbKeyPrefix::RebalancePegIn => {
+                    let transaction = dbtx
+                        .get_value(&ClientWalletRange::RebalancePegInKey)
+                        .await
+                        .unwrap();
+                    if let Some(transaction) = transaction {
+                        wallet_client.insert("RebalancePegIn".to_string(), Box::new(transaction));
+                    }
+                }
+                ClientWalletRange::DbKeyPrefix::PegOutDescriptor => {
+                    let descriptor = dbtx
+                        .get_value(&ClientWalletRange::PegOutDescriptorKey)
+                        .await
+                        .unwrap();
+                    if let Some(descriptor) = descriptor {
+                        wallet_client.insert(
+                            "PegOutDescriptor".to_string(),
+                            Box::new(descriptor.to_string()),
+                        );
+                    }
+                }
+                ClientWalletRange::DbKeyPrefix::NextPegOutIndex => {
+                    let index = dbtx
+                        .get_value(&ClientWalletRange::NextPegOutIndexKey)
+                        .await
+                        .unwrap();
+                    if let Some(index) = index {
+                        wallet_client.insert("NextPegOutIndex".to_string(), Box::new(index));
+                    }
+                }
             }
         }
 
@@ -405,6 +659,79 @@ impl<'a> DatabaseDump<'a> {
                         client.insert("Client Secret".to_string(), Box::new(secret));
                     }
                 }
+                ClientRange::DbKeyPrefix::FederationConfig => {
+                    let dbtx = &mut self.read_only;
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientRange::FederationConfigKeyPrefix,
+                        ClientRange::FederationConfigKey,
+                        mint_client::UserClientConfig,
+                        client,
+                        "Federation Configs"
+                    );
+                }
+                ClientRange::DbKeyPrefix::ActiveFederation => {
+                    let federation_id = self
+                        .read_only
+                        .get_value(&ClientRange::ActiveFederationKey)
+                        .await
+                        .unwrap();
+                    if let Some(federation_id) = federation_id {
+                        client.insert("Active Federation".to_string(), Box::new(federation_id));
+                    }
+                }
+                ClientRange::DbKeyPrefix::Operation => {
+                    let dbtx = &mut self.read_only;
+                    push_db_pair_items!(
+                        dbtx,
+                        ClientRange::OperationKeyPrefix,
+                        ClientRange::OperationKey,
+                        mint_client::operations::OperationRecord,
+                        client,
+                        "Operations"
+                    );
+                }
+                ClientRange::DbKeyPrefix::ClientMnemonic => {
+                    let entropy = self
+                        .read_only
+                        .get_value(&ClientRange::ClientMnemonicKey)
+                        .await
+                        .unwrap();
+                    if let Some(entropy) = entropy {
+                        client.insert("Client Mnemonic Entropy".to_string(), Box::new(entropy));
+                    }
+                }
+                ClientRange::DbKeyPrefix::OperationState => {
+                    let dbtx = &mut self.read_only;
+                    push_db_pair_items_no_serde!(
+                        dbtx,
+                        ClientRange::OperationStateKeyPrefix,
+                        ClientRange::OperationStateKey,
+                        mint_client::operations::OperationStateMachine,
+                        client,
+                        "Operation States"
+                    );
+                }
+                ClientRange::DbKeyPrefix::EncryptionParams => {
+                    let params = self
+                        .read_only
+                        .get_value(&ClientRange::EncryptionParamsKey)
+                        .await
+                        .unwrap();
+                    if let Some(params) = params {
+                        client.insert("Encryption Params".to_string(), Box::new(params));
+                    }
+                }
+                ClientRange::DbKeyPrefix::FederationMeta => {
+                    let meta = self
+                        .read_only
+                        .get_value(&ClientRange::FederationMetaKey)
+                        .await
+                        .unwrap();
+                    if let Some(meta) = meta {
+                        client.insert("Federation Meta".to_string(), Box::new(meta));
+                    }
+                }
             }
         }
 
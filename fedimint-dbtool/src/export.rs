@@ -0,0 +1,163 @@
+use std::io::{BufRead, Write};
+
+use anyhow::{bail, ensure, Context, Result};
+use fedimint_api::db::Database;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+/// Identifies a stream as a database export, stored in its header
+pub const EXPORT_FORMAT: &str = "fedimint-db-export";
+
+/// Version of the export format, has to be incremented on incompatible changes
+pub const EXPORT_FORMAT_VERSION: u64 = 1;
+
+/// Number of entries written per transaction during import
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// First line of an export
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportHeader {
+    format: String,
+    version: u64,
+}
+
+/// A single key-value pair, every line after the header contains one
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportEntry {
+    #[serde(with = "hex::serde")]
+    key: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    value: Vec<u8>,
+}
+
+/// Writes all entries of `db` from a consistent snapshot to `writer` as
+/// newline-delimited JSON and returns the number of entries exported.
+///
+/// Keys and values are exported exactly as stored, so the export of an
+/// encrypted database stays encrypted and can only be read with the same
+/// password and salt after importing.
+pub async fn export_database(db: &Database, mut writer: impl Write) -> Result<usize> {
+    let header = ExportHeader {
+        format: EXPORT_FORMAT.to_string(),
+        version: EXPORT_FORMAT_VERSION,
+    };
+    serde_json::to_writer(&mut writer, &header)?;
+    writeln!(writer)?;
+
+    let mut dbtx = db.begin_read_transaction().await;
+    let mut entries = dbtx.raw_find_by_prefix(&[]).await;
+    let mut count = 0;
+    while let Some((key, value)) = entries.next().await {
+        let value = value?;
+        serde_json::to_writer(&mut writer, &ExportEntry { key, value })?;
+        writeln!(writer)?;
+        count += 1;
+    }
+    writer.flush()?;
+
+    Ok(count)
+}
+
+/// Reads an export created by [`export_database`] from `reader` and writes all
+/// of its entries to `db`, returning the number of entries imported. The
+/// target database has to be empty.
+pub async fn import_database(db: &Database, reader: impl BufRead) -> Result<usize> {
+    let mut lines = reader.lines();
+
+    let header_line = lines.next().context("Export is empty")??;
+    let header: ExportHeader =
+        serde_json::from_str(&header_line).context("Export header is invalid")?;
+    ensure!(
+        header.format == EXPORT_FORMAT,
+        "Input is not a database export"
+    );
+    ensure!(
+        header.version == EXPORT_FORMAT_VERSION,
+        "Unsupported export format version {}, expected {}",
+        header.version,
+        EXPORT_FORMAT_VERSION
+    );
+
+    let mut dbtx = db.begin_transaction().await;
+    if dbtx.raw_find_by_prefix(&[]).await.next().await.is_some() {
+        bail!("Target database is not empty");
+    }
+
+    let mut count = 0;
+    for (line_idx, line) in lines.enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let entry: ExportEntry = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid entry on line {}", line_idx + 2))?;
+        dbtx.raw_unchecked()
+            .raw_insert_bytes(&entry.key, entry.value)
+            .await?;
+        count += 1;
+
+        if count % IMPORT_BATCH_SIZE == 0 {
+            dbtx.commit_tx().await?;
+            dbtx = db.begin_transaction().await;
+        }
+    }
+    dbtx.commit_tx().await?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::Database;
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use futures::StreamExt;
+
+    use super::{export_database, import_database};
+
+    fn open_temp_db() -> Database {
+        Database::new(MemDatabase::new(), ModuleDecoderRegistry::default())
+    }
+
+    async fn all_entries(db: &Database) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.raw_find_by_prefix(&[])
+            .await
+            .map(|(key, value)| (key, value.unwrap()))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_export_import_roundtrip() {
+        let source = open_temp_db();
+        let mut dbtx = source.begin_transaction().await;
+        for i in 0..2500u32 {
+            dbtx.raw_insert_bytes(&i.to_be_bytes(), vec![i as u8; 3])
+                .await
+                .unwrap();
+        }
+        dbtx.commit_tx().await.unwrap();
+
+        let mut export = vec![];
+        assert_eq!(export_database(&source, &mut export).await.unwrap(), 2500);
+
+        let target = open_temp_db();
+        assert_eq!(
+            import_database(&target, export.as_slice()).await.unwrap(),
+            2500
+        );
+        assert_eq!(all_entries(&source).await, all_entries(&target).await);
+
+        // Importing into a non-empty database fails
+        assert!(import_database(&target, export.as_slice()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_unknown_version() {
+        let export = "{\"format\":\"fedimint-db-export\",\"version\":99}\n";
+        assert!(import_database(&open_temp_db(), export.as_bytes())
+            .await
+            .is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 use std::path::PathBuf;
 
 use anyhow::Result;
@@ -9,17 +11,21 @@ use fedimint_api::module::registry::ModuleDecoderRegistry;
 use futures::StreamExt;
 
 use crate::dump::DatabaseDump;
+use crate::export::{export_database, import_database};
 
 mod dump;
+mod export;
 
 #[derive(Debug, Clone, Parser)]
 struct Options {
+    /// Path of a RocksDB database or a `sqlite://` or `postgres://` connection
+    /// string
     database: String,
     #[command(subcommand)]
     command: DbCommand,
 }
 
-/// Tool to inspect and manipulate rocksdb databases. All binary arguments
+/// Tool to inspect and manipulate databases. All binary arguments
 /// (keys, values) have to be hex encoded.
 #[derive(Debug, Clone, Subcommand)]
 enum DbCommand {
@@ -55,6 +61,13 @@ enum DbCommand {
         #[arg(required = false)]
         prefixes: Option<String>,
     },
+    /// Export all entries of the database into a backend-independent,
+    /// versioned stream of newline-delimited JSON, written to `output` or
+    /// stdout
+    Export { output: Option<PathBuf> },
+    /// Import an export created with `export` from `input` or stdin into the
+    /// database, which has to be empty
+    Import { input: Option<PathBuf> },
 }
 
 fn hex_parser(hex: &str) -> Result<Bytes> {
@@ -62,9 +75,18 @@ fn hex_parser(hex: &str) -> Result<Bytes> {
     Ok(bytes.into())
 }
 
-async fn open_db(path: &str) -> Result<Database> {
-    let rocksdb = fedimint_rocksdb::RocksDb::open(path)?;
-    Ok(Database::new(rocksdb, ModuleDecoderRegistry::default()))
+async fn open_db(database: &str) -> Result<Database> {
+    let decoders = ModuleDecoderRegistry::default();
+    Ok(if database.starts_with("sqlite://") {
+        Database::new(fedimint_sqlite::SqliteDb::open(database).await?, decoders)
+    } else if database.starts_with("postgres://") || database.starts_with("postgresql://") {
+        Database::new(
+            fedimint_postgres::PostgresDb::open(database).await?,
+            decoders,
+        )
+    } else {
+        Database::new(fedimint_rocksdb::RocksDb::open(database)?, decoders)
+    })
 }
 
 fn print_kv(key: &[u8], value: &[u8]) {
@@ -85,14 +107,15 @@ async fn main() {
                 .collect::<Vec<_>>()
                 .await;
             for (key, value) in prefix_iter {
-                print_kv(&key, &value);
+                print_kv(&key, &value.expect("DB error"));
             }
             dbtx.commit_tx().await.expect("DB Error");
         }
         DbCommand::Write { key, value } => {
             let db = open_db(&options.database).await.expect("Failed to open DB");
             let mut dbtx = db.begin_transaction().await;
-            dbtx.raw_insert_bytes(&key, value.into())
+            dbtx.raw_unchecked()
+                .raw_insert_bytes(&key, value.into())
                 .await
                 .expect("DB error");
             dbtx.commit_tx().await.expect("DB Error");
@@ -100,7 +123,10 @@ async fn main() {
         DbCommand::Delete { key } => {
             let db = open_db(&options.database).await.expect("Failed to open DB");
             let mut dbtx = db.begin_transaction().await;
-            dbtx.raw_remove_entry(&key).await.expect("DB error");
+            dbtx.raw_unchecked()
+                .raw_remove_entry(&key)
+                .await
+                .expect("DB error");
             dbtx.commit_tx().await.expect("DB Error");
         }
         DbCommand::Dump {
@@ -129,5 +155,29 @@ async fn main() {
                 DatabaseDump::new(cfg_dir, options.database, password, modules, prefix_names);
             dbdump.dump_database().await;
         }
+        DbCommand::Export { output } => {
+            let db = open_db(&options.database).await.expect("Failed to open DB");
+            let count = match output {
+                Some(path) => {
+                    let file = File::create(path).expect("Failed to create output file");
+                    export_database(&db, BufWriter::new(file)).await
+                }
+                None => export_database(&db, std::io::stdout().lock()).await,
+            }
+            .expect("Failed to export DB");
+            eprintln!("Exported {count} entries");
+        }
+        DbCommand::Import { input } => {
+            let db = open_db(&options.database).await.expect("Failed to open DB");
+            let count = match input {
+                Some(path) => {
+                    let file = File::open(path).expect("Failed to open input file");
+                    import_database(&db, BufReader::new(file)).await
+                }
+                None => import_database(&db, std::io::stdin().lock()).await,
+            }
+            .expect("Failed to import DB");
+            eprintln!("Imported {count} entries");
+        }
     }
 }
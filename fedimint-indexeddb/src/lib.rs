@@ -0,0 +1,194 @@
+//! IndexedDB-backed database for clients running in the browser.
+//!
+//! IndexedDB only offers asynchronous access to its data through objects
+//! that can't leave the browser's main thread, so all entries are kept in
+//! memory as well. Transactions work on a copy of them like the ones of
+//! [`MemDatabase`](fedimint_api::db::mem_impl::MemDatabase), committing
+//! writes their changes to IndexedDB first and then applies them in memory.
+//! Conflicting transactions are not detected, the database is meant for a
+//! single client per page.
+#![cfg(target_family = "wasm")]
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use fedimint_api::db::{
+    IDatabase, IDatabaseTransaction, PrefixStream, ReadOnlyDatabaseTransaction,
+};
+use futures::stream;
+use js_sys::Uint8Array;
+use rexie::{ObjectStore, Rexie, TransactionMode};
+use send_wrapper::SendWrapper;
+use wasm_bindgen::JsValue;
+
+/// Object store holding all key-value pairs
+const STORE: &str = "fedimint";
+
+pub struct IndexedDb {
+    name: String,
+    data: Mutex<BTreeMap<Vec<u8>, Vec<u8>>>,
+    // Wasm is single-threaded, so the handle never actually leaves its thread
+    rexie: SendWrapper<Rexie>,
+}
+
+/// Changes of a transaction, `None` removes the key
+type Operations = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
+pub struct IndexedDbTransaction<'a> {
+    operations: Operations,
+    tx_data: BTreeMap<Vec<u8>, Vec<u8>>,
+    db: &'a IndexedDb,
+    savepoint: BTreeMap<Vec<u8>, Vec<u8>>,
+    num_savepoint_operations: usize,
+}
+
+impl IndexedDb {
+    /// Opens the IndexedDB database `name` of the current origin, creating
+    /// it if it doesn't exist yet, and loads all of its entries
+    pub async fn open(name: &str) -> Result<IndexedDb> {
+        let rexie = Rexie::builder(name)
+            .version(1)
+            .add_object_store(ObjectStore::new(STORE))
+            .build()
+            .await
+            .map_err(idb_error)?;
+
+        let transaction = rexie
+            .transaction(&[STORE], TransactionMode::ReadOnly)
+            .map_err(idb_error)?;
+        let entries = transaction
+            .store(STORE)
+            .map_err(idb_error)?
+            .get_all(None, None, None, None)
+            .await
+            .map_err(idb_error)?;
+        transaction.done().await.map_err(idb_error)?;
+
+        let data = entries
+            .into_iter()
+            .map(|(key, value)| (to_bytes(&key), to_bytes(&value)))
+            .collect();
+        Ok(IndexedDb {
+            name: name.to_owned(),
+            data: Mutex::new(data),
+            rexie: SendWrapper::new(rexie),
+        })
+    }
+}
+
+impl Debug for IndexedDb {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IndexedDb({})", self.name)
+    }
+}
+
+#[async_trait]
+impl IDatabase for IndexedDb {
+    async fn begin_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        let data = self.data.lock().unwrap().clone();
+        Box::new(IndexedDbTransaction {
+            operations: vec![],
+            tx_data: data.clone(),
+            db: self,
+            savepoint: data,
+            num_savepoint_operations: 0,
+        })
+    }
+
+    async fn begin_read_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        // Transactions operate on a copy of the data, which is a snapshot already
+        Box::new(ReadOnlyDatabaseTransaction::new(
+            self.begin_transaction().await,
+        ))
+    }
+}
+
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for IndexedDbTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        self.operations.push((key.to_vec(), Some(value.clone())));
+        Ok(self.tx_data.insert(key.to_vec(), value))
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.tx_data.get(key).cloned())
+    }
+
+    async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.operations.push((key.to_vec(), None));
+        Ok(self.tx_data.remove(key))
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixStream<'_> {
+        let data = self
+            .tx_data
+            .range::<Vec<u8>, _>((key_prefix.to_vec())..)
+            .take_while(|(key, _)| key.starts_with(key_prefix))
+            .map(|(key, value)| (key.clone(), Ok(value.clone())))
+            .collect::<Vec<_>>();
+
+        Box::pin(stream::iter(data))
+    }
+
+    async fn commit_tx(self: Box<Self>) -> Result<()> {
+        if self.operations.is_empty() {
+            return Ok(());
+        }
+
+        SendWrapper::new(persist(&self.db.rexie, &self.operations)).await?;
+
+        let mut data = self.db.data.lock().unwrap();
+        for (key, value) in self.operations {
+            match value {
+                Some(value) => data.insert(key, value),
+                None => data.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        self.tx_data = self.savepoint.clone();
+        self.operations.truncate(self.num_savepoint_operations);
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        self.savepoint = self.tx_data.clone();
+        self.num_savepoint_operations = self.operations.len();
+    }
+}
+
+/// Writes the `operations` of a transaction to IndexedDB in a single
+/// IndexedDB transaction, so either all or none of them are persisted
+async fn persist(rexie: &Rexie, operations: &Operations) -> Result<()> {
+    let transaction = rexie
+        .transaction(&[STORE], TransactionMode::ReadWrite)
+        .map_err(idb_error)?;
+    let store = transaction.store(STORE).map_err(idb_error)?;
+
+    for (key, value) in operations {
+        let key: JsValue = Uint8Array::from(key.as_slice()).into();
+        match value {
+            Some(value) => {
+                let value: JsValue = Uint8Array::from(value.as_slice()).into();
+                store.put(&value, Some(&key)).await.map_err(idb_error)?;
+            }
+            None => store.delete(&key).await.map_err(idb_error)?,
+        }
+    }
+
+    transaction.done().await.map_err(idb_error)?;
+    Ok(())
+}
+
+fn to_bytes(value: &JsValue) -> Vec<u8> {
+    Uint8Array::new(value).to_vec()
+}
+
+/// IndexedDB errors may hold JS values, which can't be sent between threads
+fn idb_error(error: rexie::Error) -> anyhow::Error {
+    anyhow!("IndexedDB error: {error}")
+}
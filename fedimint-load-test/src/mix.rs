@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, format_err};
+use rand::Rng;
+use serde::Serialize;
+
+/// Operation the load test runs against the federation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OperationType {
+    /// Spends notes and reissues them
+    Reissue,
+    /// Creates an invoice and pays it internally, without a gateway
+    Ln,
+    /// Sends bitcoin to a peg-in address and claims the notes for it
+    PegIn,
+    /// Withdraws notes to a bitcoin address
+    PegOut,
+}
+
+impl OperationType {
+    const ALL: [OperationType; 4] = [
+        OperationType::Reissue,
+        OperationType::Ln,
+        OperationType::PegIn,
+        OperationType::PegOut,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            OperationType::Reissue => "reissue",
+            OperationType::Ln => "ln",
+            OperationType::PegIn => "peg-in",
+            OperationType::PegOut => "peg-out",
+        }
+    }
+}
+
+impl fmt::Display for OperationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OperationType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|op| op.name() == s)
+            .ok_or_else(|| {
+                format_err!("Unknown operation {s}, expected one of reissue, ln, peg-in, peg-out")
+            })
+    }
+}
+
+/// Relative frequencies of the operations, parsed from e.g.
+/// `reissue=8,ln=1,peg-out=1`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationMix(BTreeMap<OperationType, u32>);
+
+impl OperationMix {
+    /// Picks an operation with a probability proportional to its weight
+    pub fn choose(&self, rng: &mut impl Rng) -> OperationType {
+        let total: u32 = self.0.values().sum();
+        let mut pick = rng.gen_range(0..total);
+        for (op, weight) in &self.0 {
+            if pick < *weight {
+                return *op;
+            }
+            pick -= weight;
+        }
+        unreachable!("Pick is smaller than the total weight")
+    }
+
+    pub fn contains(&self, op: OperationType) -> bool {
+        self.0.contains_key(&op)
+    }
+}
+
+impl FromStr for OperationMix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mix = BTreeMap::new();
+        for entry in s.split(',') {
+            let (op, weight) = entry
+                .split_once('=')
+                .ok_or_else(|| format_err!("Expected operation=weight, got {entry}"))?;
+            let weight: u32 = weight.trim().parse()?;
+            if weight == 0 {
+                continue;
+            }
+            if mix.insert(op.trim().parse()?, weight).is_some() {
+                bail!("Operation {op} given twice");
+            }
+        }
+        if mix.is_empty() {
+            bail!("At least one operation needs a weight above zero");
+        }
+        Ok(OperationMix(mix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{OperationMix, OperationType};
+
+    #[test]
+    fn operations_are_chosen_by_weight() {
+        let mix: OperationMix = "reissue=3, ln=1,peg-in=0".parse().unwrap();
+        assert!(mix.contains(OperationType::Ln));
+        assert!(!mix.contains(OperationType::PegIn));
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let reissues = (0..4000)
+            .filter(|_| mix.choose(&mut rng) == OperationType::Reissue)
+            .count();
+        assert!((2800..3200).contains(&reissues), "{reissues} reissues");
+
+        assert!("reissue=1,reissue=2".parse::<OperationMix>().is_err());
+        assert!("reissue=0".parse::<OperationMix>().is_err());
+        assert!("swap=1".parse::<OperationMix>().is_err());
+    }
+}
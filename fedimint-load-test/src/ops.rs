@@ -0,0 +1,134 @@
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{format_err, Context};
+use bitcoin::Address;
+use bitcoincore_rpc::RpcApi;
+use fedimint_api::encoding::Decodable;
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::{Amount, OutPoint};
+use mint_client::modules::wallet::config::WalletClientConfig;
+use mint_client::modules::wallet::txoproof::TxOutProof;
+use mint_client::{Client, UserClientConfig};
+use rand::rngs::OsRng;
+use tokio::time::Instant;
+
+use crate::mix::OperationType;
+
+/// Everything the operations need to run against the federation
+pub struct LoadContext {
+    pub client: Arc<Client<UserClientConfig>>,
+    /// Amount moved by every operation
+    pub amount: Amount,
+    /// Recipient of peg-outs, required if the mix contains any
+    pub peg_out_address: Option<Address>,
+    /// Regtest bitcoind funding peg-ins, required if the mix contains any
+    pub bitcoind: Option<Arc<bitcoincore_rpc::Client>>,
+    /// How long to wait for the federation to accept a transaction
+    pub timeout: Duration,
+}
+
+impl LoadContext {
+    /// Runs `op` to completion, returning how long it took or why it failed
+    pub async fn run(&self, op: OperationType) -> Result<Duration, String> {
+        let start = Instant::now();
+        let result = match op {
+            OperationType::Reissue => self.reissue().await,
+            OperationType::Ln => self.ln().await,
+            OperationType::PegIn => self.peg_in().await,
+            OperationType::PegOut => self.peg_out().await,
+        };
+        result
+            .map(|()| start.elapsed())
+            .map_err(|e| format!("{e:#}"))
+    }
+
+    async fn reissue(&self) -> anyhow::Result<()> {
+        let notes = self.client.spend_ecash(self.amount, OsRng).await?;
+        let out_point = self.client.reissue(notes, OsRng).await?;
+        self.fetch_notes(out_point).await
+    }
+
+    async fn ln(&self) -> anyhow::Result<()> {
+        let invoice = self
+            .client
+            .generate_confirmed_invoice(self.amount, "load test".to_string(), OsRng, None)
+            .await?
+            .invoice;
+        let contract_id = self
+            .client
+            .pay_invoice_internally(&invoice.into(), OsRng)
+            .await?
+            .ok_or_else(|| format_err!("Invoice could not be paid internally"))?;
+        let out_point = self
+            .client
+            .claim_incoming_contract(contract_id, OsRng)
+            .await?;
+        self.fetch_notes(out_point).await
+    }
+
+    async fn peg_in(&self) -> anyhow::Result<()> {
+        let bitcoind = self
+            .bitcoind
+            .clone()
+            .context("Peg-ins need --bitcoind-rpc")?;
+        let amount = bitcoin::Amount::from_sat(self.amount.msats / 1000);
+        let finality_delay = self
+            .client
+            .config()
+            .0
+            .get_first_module_by_kind::<WalletClientConfig>("wallet")?
+            .1
+            .peg_in_finality_delay(amount);
+        let address = self.client.get_new_pegin_address(OsRng).await;
+
+        // The RPC client blocks, so it must not run on the async workers
+        let (height, proof, tx) = tokio::task::spawn_blocking(move || {
+            let txid =
+                bitcoind.send_to_address(&address, amount, None, None, None, None, None, None)?;
+            let mining_address = bitcoind.get_new_address(None, None)?;
+            bitcoind.generate_to_address(1, &mining_address)?;
+            let height = bitcoind.get_block_count()?;
+            bitcoind.generate_to_address(finality_delay.into(), &mining_address)?;
+
+            let tx = bitcoind.get_raw_transaction(&txid, None)?;
+            let proof = TxOutProof::consensus_decode(
+                &mut Cursor::new(bitcoind.get_tx_out_proof(&[txid], None)?),
+                &ModuleDecoderRegistry::default(),
+            )?;
+            anyhow::Ok((height, proof, tx))
+        })
+        .await??;
+
+        self.client
+            .await_consensus_block_height(height)
+            .await
+            .context("Federation did not reach the peg-in block height")?;
+        let txid = self.client.peg_in(proof, tx, OsRng).await?;
+        self.fetch_notes(OutPoint { txid, out_idx: 0 }).await
+    }
+
+    async fn peg_out(&self) -> anyhow::Result<()> {
+        let address = self
+            .peg_out_address
+            .clone()
+            .context("Peg-outs need --peg-out-address")?;
+        let amount = bitcoin::Amount::from_sat(self.amount.msats / 1000);
+        let peg_out = self.client.new_peg_out_with_fees(amount, address).await?;
+        let out_point = self.client.peg_out(peg_out, OsRng).await?;
+        self.client
+            .wallet_client()
+            .await_peg_out_outcome(out_point)
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch_notes(&self, out_point: OutPoint) -> anyhow::Result<()> {
+        self.client
+            .await_output_accepted(out_point, self.timeout)
+            .await?;
+        self.client.fetch_notes(out_point).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::mix::OperationType;
+
+/// Different error messages reported per operation, the rest are counted as
+/// `other`
+const MAX_ERROR_MESSAGES: usize = 10;
+
+/// Outcomes of the operations run so far
+#[derive(Debug, Default)]
+pub struct Stats {
+    operations: BTreeMap<OperationType, OperationStats>,
+    /// Operations not started because too many were running already
+    skipped: u64,
+}
+
+#[derive(Debug, Default)]
+struct OperationStats {
+    latencies: Vec<Duration>,
+    errors: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    pub fn record(&mut self, op: OperationType, result: Result<Duration, String>) {
+        let stats = self.operations.entry(op).or_default();
+        match result {
+            Ok(latency) => stats.latencies.push(latency),
+            Err(error) => {
+                let key = if stats.errors.len() < MAX_ERROR_MESSAGES
+                    || stats.errors.contains_key(&error)
+                {
+                    error
+                } else {
+                    "other".to_string()
+                };
+                *stats.errors.entry(key).or_default() += 1;
+            }
+        }
+    }
+
+    pub fn skip(&mut self) {
+        self.skipped += 1;
+    }
+
+    pub fn report(&self, elapsed: Duration, consensus: Option<ConsensusStats>) -> Report {
+        let operations: BTreeMap<_, _> = self
+            .operations
+            .iter()
+            .map(|(op, stats)| {
+                let mut latencies = stats.latencies.clone();
+                latencies.sort();
+                let report = OperationReport {
+                    succeeded: latencies.len() as u64,
+                    failed: stats.errors.values().sum(),
+                    latency_ms: Percentiles::of_sorted(&latencies),
+                    errors: stats.errors.clone(),
+                };
+                (*op, report)
+            })
+            .collect();
+        let finished: u64 = operations
+            .values()
+            .map(|report| report.succeeded + report.failed)
+            .sum();
+
+        Report {
+            duration_secs: elapsed.as_secs_f64(),
+            finished_per_sec: per_sec(finished, elapsed),
+            skipped: self.skipped,
+            operations,
+            consensus,
+        }
+    }
+}
+
+fn per_sec(count: u64, elapsed: Duration) -> f64 {
+    count as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub duration_secs: f64,
+    pub finished_per_sec: f64,
+    /// Operations not started because `--max-in-flight` were running already,
+    /// a sign that the federation can't keep up with the rate
+    pub skipped: u64,
+    pub operations: BTreeMap<OperationType, OperationReport>,
+    pub consensus: Option<ConsensusStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OperationReport {
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Latencies of the successful operations
+    pub latency_ms: Option<Percentiles>,
+    pub errors: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    fn of_sorted(latencies: &[Duration]) -> Option<Percentiles> {
+        if latencies.is_empty() {
+            return None;
+        }
+        let ms = |p| percentile(latencies, p).as_micros() as f64 / 1000.0;
+        Some(Percentiles {
+            p50: ms(50),
+            p90: ms(90),
+            p99: ms(99),
+            max: ms(100),
+        })
+    }
+}
+
+/// Nearest-rank percentile `p` of the non-empty `sorted` latencies
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (p * sorted.len() + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
+
+/// What the federation processed while the load test ran
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsensusStats {
+    pub epochs: u64,
+    pub epochs_per_sec: f64,
+    /// Transactions of any client, not only ours, that got accepted
+    pub transactions: u64,
+    pub rejected_transactions: u64,
+    pub transactions_per_sec: f64,
+}
+
+impl ConsensusStats {
+    pub fn new(epochs: u64, transactions: u64, rejected: u64, elapsed: Duration) -> Self {
+        ConsensusStats {
+            epochs,
+            epochs_per_sec: per_sec(epochs, elapsed),
+            transactions,
+            rejected_transactions: rejected,
+            transactions_per_sec: per_sec(transactions, elapsed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Percentiles, Stats};
+    use crate::mix::OperationType;
+
+    #[test]
+    fn reports_latency_percentiles_and_errors() {
+        let mut stats = Stats::default();
+        for ms in (1..=100).rev() {
+            stats.record(OperationType::Reissue, Ok(Duration::from_millis(ms)));
+        }
+        for i in 0..12 {
+            stats.record(OperationType::Ln, Err(format!("error {i}")));
+        }
+        stats.record(OperationType::Ln, Err("error 0".to_string()));
+        stats.skip();
+
+        let report = stats.report(Duration::from_secs(10), None);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(report.finished_per_sec, 11.3);
+
+        let reissue = &report.operations[&OperationType::Reissue];
+        assert_eq!(
+            reissue.latency_ms,
+            Some(Percentiles {
+                p50: 50.0,
+                p90: 90.0,
+                p99: 99.0,
+                max: 100.0,
+            })
+        );
+
+        let ln = &report.operations[&OperationType::Ln];
+        assert_eq!((ln.succeeded, ln.failed), (0, 13));
+        assert_eq!(ln.latency_ms, None);
+        assert_eq!(ln.errors["error 0"], 2);
+        assert_eq!(ln.errors["other"], 2);
+    }
+}
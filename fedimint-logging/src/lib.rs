@@ -0,0 +1,151 @@
+//! Logging setup shared by the Fedimint binaries
+//!
+//! Besides printing logs, spans can be exported to an OpenTelemetry collector
+//! over OTLP if the binary was built with the `telemetry` feature. Spans along
+//! the lifecycle of a transaction carry its `txid`, so the spans of the client,
+//! the gateway and every guardian handling a transaction can be found by
+//! searching for it in the tracing backend.
+
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+use url::Url;
+
+/// Builder for the global tracing subscriber of a binary
+pub struct TracingSetup {
+    service_name: &'static str,
+    default_directives: String,
+    stderr: bool,
+    json: bool,
+    otlp_endpoint: Option<Url>,
+    extra_layer: Option<Box<dyn Layer<Registry> + Send + Sync + 'static>>,
+}
+
+impl TracingSetup {
+    /// Logs at `info` level unless `RUST_LOG` is set, spans are reported
+    /// under `service_name`
+    pub fn new(service_name: &'static str) -> Self {
+        TracingSetup {
+            service_name,
+            default_directives: "info".to_string(),
+            stderr: false,
+            json: false,
+            otlp_endpoint: None,
+            extra_layer: None,
+        }
+    }
+
+    /// Filter directives used if `RUST_LOG` isn't set
+    pub fn default_directives(mut self, directives: &str) -> Self {
+        self.default_directives = directives.to_string();
+        self
+    }
+
+    /// Prints logs to stderr instead of stdout
+    pub fn with_stderr(mut self) -> Self {
+        self.stderr = true;
+        self
+    }
+
+    /// Prints every log line as a JSON object carrying the fields of the spans
+    /// it was logged in, e.g. the peer id, epoch or correlation id
+    pub fn json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    /// Exports spans to the OTLP/gRPC collector at `endpoint`, e.g.
+    /// `http://localhost:4317`
+    pub fn otlp_endpoint(mut self, endpoint: Option<Url>) -> Self {
+        self.otlp_endpoint = endpoint;
+        self
+    }
+
+    /// Adds a layer with its own filter, e.g. for `tokio-console`
+    pub fn with_layer(mut self, layer: impl Layer<Registry> + Send + Sync + 'static) -> Self {
+        self.extra_layer = Some(Box::new(layer));
+        self
+    }
+
+    /// Installs the global subscriber, [`shutdown`] has to be called before
+    /// the process exits to export the remaining spans
+    pub fn init(self) -> anyhow::Result<()> {
+        let writer = if self.stderr {
+            BoxMakeWriter::new(std::io::stderr)
+        } else {
+            BoxMakeWriter::new(std::io::stdout)
+        };
+        let fmt_layer = if self.json {
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(false)
+                .with_span_list(true)
+                .with_writer(writer)
+                .boxed()
+        } else {
+            tracing_subscriber::fmt::layer().with_writer(writer).boxed()
+        }
+        .with_filter(self.env_filter());
+
+        let telemetry_layer: Option<Box<dyn Layer<_> + Send + Sync + 'static>> =
+            match &self.otlp_endpoint {
+                None => None,
+                #[cfg(feature = "telemetry")]
+                Some(endpoint) => Some(
+                    otlp_layer(self.service_name, endpoint)?
+                        .with_filter(self.env_filter())
+                        .boxed(),
+                ),
+                #[cfg(not(feature = "telemetry"))]
+                Some(_) => anyhow::bail!(
+                    "{} was built without the telemetry feature and can't export spans",
+                    self.service_name
+                ),
+            };
+
+        tracing_subscriber::registry()
+            .with(self.extra_layer)
+            .with(fmt_layer)
+            .with(telemetry_layer)
+            .try_init()?;
+
+        Ok(())
+    }
+
+    fn env_filter(&self) -> EnvFilter {
+        EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new(&self.default_directives))
+    }
+}
+
+#[cfg(feature = "telemetry")]
+fn otlp_layer<S>(service_name: &'static str, endpoint: &Url) -> anyhow::Result<impl Layer<S>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::sdk::{trace, Resource};
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint.as_str()),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_resource(Resource::new([KeyValue::new("service.name", service_name)])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)?;
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Exports the spans that weren't exported yet, spans ending afterwards are
+/// dropped
+pub fn shutdown() {
+    #[cfg(feature = "telemetry")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
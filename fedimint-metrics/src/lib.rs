@@ -0,0 +1,65 @@
+use std::net::SocketAddr;
+
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use fedimint_api::task::TaskGroup;
+use once_cell::sync::Lazy;
+pub use prometheus::{
+    self, histogram_opts, opts, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Registry,
+};
+use prometheus::{Encoder, TextEncoder};
+use tokio::select;
+use tracing::{debug, error};
+
+/// Registry all metrics of the process are registered with, served by
+/// [`run_api_server`]
+pub static REGISTRY: Lazy<Registry> = Lazy::new(|| {
+    Registry::new_custom(Some("fedimint".into()), None).expect("Prefix is a valid metric name")
+});
+
+/// Default buckets for histograms of durations in seconds, ranging from 100µs
+/// to 10s
+pub const DURATION_BUCKETS: &[f64] = &[
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+    5.0, 10.0,
+];
+
+async fn get_metrics() -> (StatusCode, String) {
+    let metric_families = REGISTRY.gather();
+    let mut buffer = Vec::new();
+    match TextEncoder::new().encode(&metric_families, &mut buffer) {
+        Ok(()) => (
+            StatusCode::OK,
+            String::from_utf8(buffer).expect("Prometheus text format is UTF-8"),
+        ),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// Serves the metrics of [`REGISTRY`] in the Prometheus text format under
+/// `/metrics` until `task_group` is shut down
+pub async fn run_api_server(
+    bind_address: SocketAddr,
+    task_group: &mut TaskGroup,
+) -> anyhow::Result<()> {
+    let app = Router::new().route("/metrics", get(get_metrics));
+    let server = axum::Server::try_bind(&bind_address)?.serve(app.into_make_service());
+    let shutdown_future = task_group.make_handle().make_shutdown_rx().await;
+
+    debug!(%bind_address, "Starting metrics server");
+    task_group
+        .spawn("metrics-server", move |_| async move {
+            select! {
+                _ = shutdown_future => {
+                    debug!("Metrics server shutting down");
+                },
+                Err(err) = server => {
+                    error!(?err, "Metrics server encountered an error");
+                }
+            }
+        })
+        .await;
+    Ok(())
+}
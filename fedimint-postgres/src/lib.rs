@@ -0,0 +1,318 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use fedimint_api::db::{
+    IDatabase, IDatabaseTransaction, PrefixStream, ReadOnlyDatabaseTransaction,
+};
+use futures::stream;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, Error, Executor, PgPool, Postgres, Row, Transaction};
+use tracing::{info, warn};
+
+/// How long a transaction waits for a row lock held by another transaction
+/// before giving up. Failing fast turns write-write conflicts into errors the
+/// caller can retry (see `Database::autocommit`) instead of stalling
+/// consensus.
+const LOCK_TIMEOUT: &str = "1s";
+
+#[derive(Debug)]
+pub struct PostgresDb(PgPool);
+
+pub struct PostgresDbTransaction<'a>(Transaction<'a, Postgres>);
+
+impl PostgresDb {
+    pub async fn open(connection_string: &str) -> Result<PostgresDb, Error> {
+        info!("Connecting to postgres database");
+
+        // Disable statement logging otherwise the queries clutter the log
+        let mut opts = PgConnectOptions::from_str(connection_string)?;
+        opts.disable_statement_logging();
+        let db = PgPoolOptions::new().connect_with(opts).await?;
+
+        sqlx::query("CREATE TABLE IF NOT EXISTS kv (key BYTEA PRIMARY KEY, value BYTEA NOT NULL);")
+            .execute(&db)
+            .await?;
+
+        Ok(PostgresDb(db))
+    }
+
+    /// Rewrites the key-value table, returning the space of deleted entries to
+    /// the operating system. Takes an exclusive lock on the table while
+    /// running.
+    pub async fn compact(&self) -> Result<(), Error> {
+        sqlx::query("VACUUM FULL kv").execute(&self.0).await?;
+        Ok(())
+    }
+
+    /// Total size of the key-value table including indexes, in bytes
+    pub async fn size(&self) -> Result<u64, Error> {
+        let row = sqlx::query("SELECT pg_total_relation_size('kv') AS size")
+            .fetch_one(&self.0)
+            .await?;
+        Ok(row.get::<i64, &str>("size") as u64)
+    }
+}
+
+#[async_trait]
+impl IDatabase for PostgresDb {
+    async fn begin_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        let mut tx = PostgresDbTransaction(self.0.begin().await.unwrap());
+        // Must be the first statement of the transaction to take effect
+        tx.0.execute("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+            .await
+            .expect("Error setting transaction isolation level");
+        tx.0.execute(format!("SET LOCAL lock_timeout = '{LOCK_TIMEOUT}'").as_str())
+            .await
+            .expect("Error setting transaction lock timeout");
+        tx.set_tx_savepoint().await;
+        Box::new(tx)
+    }
+
+    async fn begin_read_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        let mut tx = PostgresDbTransaction(self.0.begin().await.unwrap());
+        // A deferrable read-only serializable transaction waits for a snapshot that
+        // can't conflict with concurrent writers, after which it neither blocks
+        // nor aborts them
+        tx.0.execute("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE, READ ONLY, DEFERRABLE")
+            .await
+            .expect("Error setting transaction isolation level");
+        Box::new(ReadOnlyDatabaseTransaction::new(Box::new(tx)))
+    }
+}
+
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for PostgresDbTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let val = self.raw_get_bytes(key).await?;
+        let query_prepared = sqlx::query(
+            "INSERT INTO kv (key, value) VALUES ($1, $2) \
+             ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+        )
+        .bind(key)
+        .bind(value);
+        self.0.execute(query_prepared).await?;
+        Ok(val)
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let query_prepared = sqlx::query("SELECT value FROM kv WHERE key = $1").bind(key);
+        self.0
+            .fetch_optional(query_prepared)
+            .await
+            .map(|result| result.map(|result| result.get::<Vec<u8>, &str>("value")))
+            .map_err(anyhow::Error::from)
+    }
+
+    async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let query_prepared = sqlx::query("DELETE FROM kv WHERE key = $1 RETURNING value").bind(key);
+        self.0
+            .fetch_optional(query_prepared)
+            .await
+            .map(|result| result.map(|result| result.get::<Vec<u8>, &str>("value")))
+            .map_err(anyhow::Error::from)
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixStream<'_> {
+        // Return entries in key order, matching the iteration order of RocksDB
+        let query = "SELECT key, value FROM kv \
+                     WHERE substring(key from 1 for octet_length($1)) = $1 ORDER BY key ASC";
+        let query_prepared = sqlx::query(query).bind(key_prefix);
+        let results = self.0.fetch_all(query_prepared).await;
+
+        if results.is_err() {
+            warn!("postgres find_by_prefix failed to retrieve key range. Returning empty iterator");
+            return Box::pin(stream::iter(Vec::new()));
+        }
+
+        let rows = results.unwrap().into_iter().map(|row| {
+            (
+                row.get::<Vec<u8>, &str>("key"),
+                Ok(row.get::<Vec<u8>, &str>("value")),
+            )
+        });
+
+        Box::pin(stream::iter(rows))
+    }
+
+    async fn raw_remove_by_prefix(&mut self, key_prefix: &[u8]) -> Result<()> {
+        let query = "DELETE FROM kv WHERE substring(key from 1 for octet_length($1)) = $1";
+        let query_prepared = sqlx::query(query).bind(key_prefix);
+        self.0.execute(query_prepared).await?;
+        Ok(())
+    }
+
+    async fn commit_tx(self: Box<Self>) -> Result<()> {
+        self.0.commit().await.map_err(anyhow::Error::from)
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        let query_prepared = sqlx::query("ROLLBACK TO SAVEPOINT tx_savepoint");
+        self.0.execute(query_prepared).await.unwrap();
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        let query_prepared = sqlx::query("SAVEPOINT tx_savepoint");
+        self.0.execute(query_prepared).await.unwrap();
+    }
+}
+
+/// These tests need a running PostgreSQL server. Set `FM_TEST_POSTGRES_URL` to
+/// a connection string of a user that is allowed to create databases (e.g.
+/// `postgres://postgres@localhost`), otherwise the tests are skipped.
+#[cfg(test)]
+mod fedimint_postgres_tests {
+    use fedimint_api::{
+        core::ModuleInstanceId, db::Database, module::registry::ModuleDecoderRegistry,
+    };
+    use rand::{rngs::OsRng, RngCore};
+    use sqlx::{Connection, Executor, PgConnection};
+
+    use crate::PostgresDb;
+
+    async fn open_temp_db(db_name: &str) -> Option<Database> {
+        let base_url = match std::env::var("FM_TEST_POSTGRES_URL") {
+            Ok(url) => url,
+            Err(_) => {
+                eprintln!("FM_TEST_POSTGRES_URL not set, skipping postgres test");
+                return None;
+            }
+        };
+
+        let db_name = format!("fm_{}_{}", db_name.replace('-', "_"), OsRng.next_u64());
+        let mut conn = PgConnection::connect(&base_url).await.unwrap();
+        conn.execute(format!("CREATE DATABASE {db_name}").as_str())
+            .await
+            .expect("Error creating temporary postgres database");
+
+        let connection_string = format!("{}/{}", base_url.trim_end_matches('/'), db_name);
+        Some(Database::new(
+            PostgresDb::open(connection_string.as_str()).await.unwrap(),
+            ModuleDecoderRegistry::default(),
+        ))
+    }
+
+    async fn open_temp_module_db(
+        db_name: &str,
+        module_instance_id: ModuleInstanceId,
+    ) -> Option<Database> {
+        open_temp_db(db_name)
+            .await
+            .map(|db| db.new_isolated(module_instance_id))
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_insert_elements() {
+        if let Some(db) = open_temp_db("insert-elements").await {
+            fedimint_api::db::verify_insert_elements(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_remove_nonexisting() {
+        if let Some(db) = open_temp_db("remove-nonexisting").await {
+            fedimint_api::db::verify_remove_nonexisting(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_remove_existing() {
+        if let Some(db) = open_temp_db("remove-existing").await {
+            fedimint_api::db::verify_remove_existing(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_read_own_writes() {
+        if let Some(db) = open_temp_db("read-own-writes").await {
+            fedimint_api::db::verify_read_own_writes(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_prevent_dirty_reads() {
+        if let Some(db) = open_temp_db("prevent-dirty-reads").await {
+            fedimint_api::db::verify_prevent_dirty_reads(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_prefix() {
+        if let Some(db) = open_temp_db("find-by-prefix").await {
+            fedimint_api::db::verify_find_by_prefix(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_commit() {
+        if let Some(db) = open_temp_db("commit").await {
+            fedimint_api::db::verify_commit(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_prevent_nonrepeatable_reads() {
+        if let Some(db) = open_temp_db("prevent-nonrepeatable-reads").await {
+            fedimint_api::db::verify_prevent_nonrepeatable_reads(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_snapshot_read() {
+        if let Some(db) = open_temp_db("snapshot-read").await {
+            fedimint_api::db::verify_snapshot_read(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_rollback_to_savepoint() {
+        if let Some(db) = open_temp_db("rollback-to-savepoint").await {
+            fedimint_api::db::verify_rollback_to_savepoint(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_phantom_entry() {
+        if let Some(db) = open_temp_db("phantom-entry").await {
+            fedimint_api::db::verify_phantom_entry(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_write_conflict() {
+        if let Some(db) = open_temp_db("write-conflict").await {
+            fedimint_api::db::expect_write_conflict(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_string_prefix() {
+        if let Some(db) = open_temp_db("verify-string-prefix").await {
+            fedimint_api::db::verify_string_prefix(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_remove_by_prefix() {
+        if let Some(db) = open_temp_db("verify-remove-by-prefix").await {
+            fedimint_api::db::verify_remove_by_prefix(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_module_dbtx() {
+        if let Some(db) = open_temp_db("verify-module-prefix").await {
+            fedimint_api::db::verify_module_prefix(db).await;
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_module_db() {
+        if let (Some(db), Some(module_db)) = (
+            open_temp_db("verify-module-db1").await,
+            open_temp_module_db("verify-module-db2", 1).await,
+        ) {
+            fedimint_api::db::verify_module_db(db, module_db).await;
+        }
+    }
+}
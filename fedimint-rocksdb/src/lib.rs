@@ -1,10 +1,14 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::Result;
 use async_trait::async_trait;
-use fedimint_api::db::{IDatabase, IDatabaseTransaction, PrefixStream};
+use fedimint_api::db::{
+    IDatabase, IDatabaseTransaction, PrefixStream, ReadOnlyDatabaseTransaction,
+};
 use futures::stream;
 pub use rocksdb;
+use rocksdb::checkpoint::Checkpoint;
 use rocksdb::{OptimisticTransactionDB, OptimisticTransactionOptions, WriteOptions};
 use tracing::warn;
 
@@ -25,6 +29,15 @@ impl RocksDb {
     pub fn inner(&self) -> &rocksdb::OptimisticTransactionDB {
         &self.0
     }
+
+    /// Compacts the whole key range of the database at `db_path`, physically
+    /// removing deleted entries. The database must not be opened by anyone
+    /// else at the same time.
+    pub fn compact(db_path: impl AsRef<Path>) -> Result<(), rocksdb::Error> {
+        let db = rocksdb::DB::open_default(db_path)?;
+        db.compact_range::<&[u8], &[u8]>(None, None);
+        Ok(())
+    }
 }
 
 impl RocksDbReadOnly {
@@ -59,6 +72,26 @@ impl IDatabase for RocksDb {
         rocksdb_tx.set_tx_savepoint().await;
         Box::new(rocksdb_tx)
     }
+
+    async fn begin_read_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        // Reads of transactions go through the snapshot taken at their creation
+        Box::new(ReadOnlyDatabaseTransaction::new(
+            self.begin_transaction().await,
+        ))
+    }
+
+    /// Creates a RocksDB checkpoint in `<db path>-backups/<unix time>`, its
+    /// files are hard links to the database's as far as possible so it is
+    /// cheap to create. The checkpoint can be opened like the original.
+    async fn backup(&self) -> Result<PathBuf> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let backups_dir = PathBuf::from(format!("{}-backups", self.0.path().display()));
+        std::fs::create_dir_all(&backups_dir)?;
+
+        let backup_path = backups_dir.join(timestamp.to_string());
+        Checkpoint::new(&self.0)?.create_checkpoint(&backup_path)?;
+        Ok(backup_path)
+    }
 }
 
 #[async_trait]
@@ -100,7 +133,7 @@ impl<'a> IDatabaseTransaction<'a> for RocksDbTransaction<'a> {
                         .starts_with(&prefix)
                         .then_some((key_bytes, value_bytes))
                 })
-                .map(|(key_bytes, value_bytes)| (key_bytes.to_vec(), value_bytes.to_vec()));
+                .map(|(key_bytes, value_bytes)| (key_bytes.to_vec(), Ok(value_bytes.to_vec())));
 
             Box::pin(stream::iter(rocksdb_iter))
         })
@@ -156,7 +189,7 @@ impl IDatabaseTransaction<'_> for RocksDbReadOnly {
                         .starts_with(&prefix)
                         .then_some((key_bytes, value_bytes))
                 })
-                .map(|(key_bytes, value_bytes)| (key_bytes.to_vec(), value_bytes.to_vec()));
+                .map(|(key_bytes, value_bytes)| (key_bytes.to_vec(), Ok(value_bytes.to_vec())));
 
             Box::pin(stream::iter(rocksdb_iter))
         })
@@ -177,6 +210,7 @@ impl IDatabaseTransaction<'_> for RocksDbReadOnly {
 
 #[cfg(test)]
 mod fedimint_rocksdb_tests {
+    use fedimint_api::db::{IDatabase, IDatabaseTransaction};
     use fedimint_api::{db::Database, module::registry::ModuleDecoderRegistry};
 
     use crate::RocksDb;
@@ -246,6 +280,12 @@ mod fedimint_rocksdb_tests {
         .await;
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dbtx_snapshot_read() {
+        fedimint_api::db::verify_snapshot_read(open_temp_db("fcb-rocksdb-test-snapshot-read"))
+            .await;
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_dbtx_rollback_to_savepoint() {
         fedimint_api::db::verify_rollback_to_savepoint(open_temp_db(
@@ -310,4 +350,27 @@ mod fedimint_rocksdb_tests {
         let module_instance_id = 2;
         db.new_isolated(module_instance_id);
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_backup_contains_committed_entries() {
+        let dir = tempfile::Builder::new()
+            .prefix("fcb-rocksdb-test-backup")
+            .tempdir()
+            .unwrap();
+        let db = RocksDb::open(dir.path().join("database")).unwrap();
+
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.raw_insert_bytes(&[0x42], vec![1, 2, 3]).await.unwrap();
+        dbtx.commit_tx().await.unwrap();
+
+        let backup_path = db.backup().await.unwrap();
+        assert!(backup_path.starts_with(dir.path().join("database-backups")));
+
+        let backup = RocksDb::open(backup_path).unwrap();
+        let mut backup_dbtx = backup.begin_transaction().await;
+        assert_eq!(
+            backup_dbtx.raw_get_bytes(&[0x42]).await.unwrap(),
+            Some(vec![1, 2, 3])
+        );
+    }
 }
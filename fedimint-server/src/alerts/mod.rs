@@ -0,0 +1,474 @@
+//! Alerting of the guardian's operator about problems before users notice
+//! them, by webhook and/or email
+//!
+//! The [`AlertMonitor`] periodically checks for peers that stay offline,
+//! stalled consensus, liabilities exceeding assets, large peg-outs and a
+//! nearly full disk. Every alert is sent once when it is raised and again
+//! when it is resolved, peg-outs only once since they don't get resolved.
+
+mod smtp;
+
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, format_err};
+use bitcoin::Txid;
+use fedimint_api::db::Database;
+use fedimint_api::module::registry::ServerModuleRegistry;
+use fedimint_api::task::{sleep, timeout, TaskHandle};
+use fedimint_api::PeerId;
+use fedimint_core::admin::PeerConnectionStatus;
+use fedimint_wallet::db::UnsignedTransactionPrefixKey;
+use futures::StreamExt;
+use serde::Serialize;
+use tracing::{info, warn};
+use url::Url;
+
+pub use self::smtp::SmtpConfig;
+use crate::config::ServerConfig;
+use crate::consensus::{audit, ConsensusProgressTracker, FedimintConsensus};
+use crate::net::peers::PeerStatusTracker;
+
+/// How often the monitor checks for problems
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long sending a single notification may take
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where to send alerts to and when to raise them
+#[derive(Debug, Clone)]
+pub struct AlertConfig {
+    /// Url alerts are POSTed to as JSON, see [`Notification`]
+    pub webhook: Option<Url>,
+    /// Mail server and addresses alerts are emailed to
+    pub smtp: Option<SmtpConfig>,
+    /// How long a peer may be disconnected before we alert
+    pub peer_offline_after: Duration,
+    /// How long an epoch may run without an outcome before we alert
+    pub consensus_stalled_after: Duration,
+    /// Peg-outs paying out at least this amount are reported, `None` disables
+    /// the check
+    pub large_peg_out: Option<bitcoin::Amount>,
+    /// Directory whose file system is checked for free space
+    pub data_dir: PathBuf,
+    /// Percentage of free disk space below which we alert
+    pub min_free_disk_percent: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    PeerOffline,
+    ConsensusStalled,
+    Insolvent,
+    LargePegOut,
+    DiskNearlyFull,
+}
+
+impl AlertKind {
+    /// Whether the alert reports a one-off event rather than a condition that
+    /// gets resolved again
+    fn is_event(self) -> bool {
+        matches!(self, AlertKind::LargePegOut)
+    }
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AlertKind::PeerOffline => "Peer offline",
+            AlertKind::ConsensusStalled => "Consensus stalled",
+            AlertKind::Insolvent => "Liabilities exceed assets",
+            AlertKind::LargePegOut => "Large peg-out",
+            AlertKind::DiskNearlyFull => "Disk nearly full",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A problem found by one of the checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alert {
+    pub kind: AlertKind,
+    /// What the alert is about if there can be several of its kind, e.g. the
+    /// offline peer
+    pub subject: Option<String>,
+    pub message: String,
+}
+
+impl Alert {
+    fn new(kind: AlertKind, subject: Option<String>, message: String) -> Self {
+        Alert {
+            kind,
+            subject,
+            message,
+        }
+    }
+}
+
+/// Body of the webhook requests, also rendered into the emails
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Notification {
+    pub federation: String,
+    pub peer: PeerId,
+    pub kind: AlertKind,
+    pub subject: Option<String>,
+    pub message: String,
+    /// Whether the alert was resolved rather than raised
+    pub resolved: bool,
+}
+
+impl Notification {
+    fn title(&self) -> String {
+        let state = if self.resolved { "Resolved" } else { "Alert" };
+        format!(
+            "[{}] {state}: {} on guardian {}",
+            self.federation, self.kind, self.peer
+        )
+    }
+}
+
+/// Alerts that were raised and not resolved yet, so every alert is only sent
+/// once
+#[derive(Debug, Default)]
+struct AlertState {
+    active: BTreeMap<(AlertKind, Option<String>), Alert>,
+}
+
+impl AlertState {
+    /// Takes the alerts found by the latest checks and returns the ones that
+    /// were raised and resolved since the previous checks
+    fn update(&mut self, alerts: Vec<Alert>) -> Vec<(Alert, bool)> {
+        let current = alerts
+            .into_iter()
+            .map(|alert| ((alert.kind, alert.subject.clone()), alert))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut changes = vec![];
+        for (key, alert) in &self.active {
+            if !current.contains_key(key) && !alert.kind.is_event() {
+                changes.push((alert.clone(), true));
+            }
+        }
+        for (key, alert) in &current {
+            if !self.active.contains_key(key) {
+                changes.push((alert.clone(), false));
+            }
+        }
+
+        self.active = current;
+        changes
+    }
+}
+
+/// Checks the guardian for problems and notifies the operator about them
+pub struct AlertMonitor {
+    config: AlertConfig,
+    cfg: ServerConfig,
+    db: Database,
+    modules: ServerModuleRegistry,
+    peer_status: PeerStatusTracker,
+    consensus_progress: ConsensusProgressTracker,
+    http: reqwest::Client,
+    state: AlertState,
+    disconnected_since: BTreeMap<PeerId, Instant>,
+}
+
+impl AlertMonitor {
+    pub fn new(config: AlertConfig, consensus: &FedimintConsensus) -> anyhow::Result<Self> {
+        if config.webhook.is_none() && config.smtp.is_none() {
+            bail!("Alerts need a webhook or a mail server to be sent to");
+        }
+
+        let http = match &consensus.cfg.local.socks5_proxy {
+            Some(proxy) => reqwest::Client::builder()
+                .proxy(reqwest::Proxy::all(format!("socks5h://{proxy}"))?)
+                .build()?,
+            None => reqwest::Client::new(),
+        };
+
+        Ok(AlertMonitor {
+            config,
+            cfg: consensus.cfg.clone(),
+            db: consensus.db.clone(),
+            modules: consensus.modules.clone(),
+            peer_status: consensus.peer_status.clone(),
+            consensus_progress: consensus.consensus_progress.clone(),
+            http,
+            state: AlertState::default(),
+            disconnected_since: BTreeMap::new(),
+        })
+    }
+
+    pub async fn run(mut self, task_handle: TaskHandle) {
+        info!("Alerting operator via {}", self.channels());
+        while !task_handle.is_shutting_down() {
+            let alerts = self.check().await;
+            for (alert, resolved) in self.state.update(alerts) {
+                self.notify(alert, resolved).await;
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    fn channels(&self) -> String {
+        let mut channels = vec![];
+        if let Some(webhook) = &self.config.webhook {
+            channels.push(format!(
+                "webhook {}",
+                webhook.host_str().unwrap_or_default()
+            ));
+        }
+        if let Some(smtp) = &self.config.smtp {
+            channels.push(format!("email to {}", smtp.to.join(", ")));
+        }
+        channels.join(" and ")
+    }
+
+    async fn check(&mut self) -> Vec<Alert> {
+        let mut alerts = self.check_peers();
+        alerts.extend(self.check_consensus());
+        alerts.extend(self.check_solvency().await);
+        alerts.extend(self.check_peg_outs().await);
+        alerts.extend(self.check_disk());
+        alerts
+    }
+
+    fn check_peers(&mut self) -> Vec<Alert> {
+        let now = Instant::now();
+        let statuses = self.peer_status.statuses();
+        self.disconnected_since
+            .retain(|peer, _| statuses.get(peer) == Some(&PeerConnectionStatus::Disconnected));
+
+        let mut alerts = vec![];
+        for (peer, status) in statuses {
+            if status == PeerConnectionStatus::Connected {
+                continue;
+            }
+            let offline_for = now - *self.disconnected_since.entry(peer).or_insert(now);
+            if self.config.peer_offline_after <= offline_for {
+                alerts.push(Alert::new(
+                    AlertKind::PeerOffline,
+                    Some(peer.to_string()),
+                    format!(
+                        "Peer {peer} has been disconnected for {}s",
+                        offline_for.as_secs()
+                    ),
+                ));
+            }
+        }
+        alerts
+    }
+
+    fn check_consensus(&self) -> Option<Alert> {
+        let running_for = self
+            .consensus_progress
+            .progress()
+            .epoch_running_since?
+            .elapsed();
+        if running_for < self.config.consensus_stalled_after {
+            return None;
+        }
+
+        let peers = self.peer_status.connected_peers();
+        Some(Alert::new(
+            AlertKind::ConsensusStalled,
+            None,
+            format!(
+                "The current epoch has been running for {}s without an outcome, \
+                 connected to {peers} peers",
+                running_for.as_secs()
+            ),
+        ))
+    }
+
+    async fn check_solvency(&self) -> Option<Alert> {
+        let audit = audit(&self.modules, &self.db).await;
+        if 0 <= audit.sum().milli_sat {
+            return None;
+        }
+
+        Some(Alert::new(
+            AlertKind::Insolvent,
+            None,
+            format!("The federation's liabilities exceed its assets:\n{audit}"),
+        ))
+    }
+
+    /// Reports the peg-out transactions still collecting signatures, fee bumps
+    /// of already reported ones are skipped
+    async fn check_peg_outs(&self) -> Vec<Alert> {
+        let Some(threshold) = self.config.large_peg_out else {
+            return vec![];
+        };
+        let wallet_id = match self.cfg.get_module_id_by_kind("wallet") {
+            Ok(wallet_id) => wallet_id,
+            Err(e) => {
+                warn!("Can't check peg-outs: {e}");
+                return vec![];
+            }
+        };
+
+        let mut dbtx = self.db.begin_read_transaction().await;
+        let mut wallet_dbtx = dbtx.with_module_prefix(wallet_id);
+        let unsigned = wallet_dbtx
+            .find_by_prefix(&UnsignedTransactionPrefixKey)
+            .await
+            .filter_map(|res| async move { res.ok() })
+            .collect::<Vec<_>>()
+            .await;
+
+        unsigned
+            .into_iter()
+            .filter(|(_, tx)| tx.replaces.is_none())
+            .filter_map(|(key, tx)| {
+                let outputs: u64 = tx.psbt.unsigned_tx.output.iter().map(|out| out.value).sum();
+                let amount = bitcoin::Amount::from_sat(outputs.saturating_sub(tx.change.to_sat()));
+                (threshold <= amount).then(|| large_peg_out_alert(key.0, amount))
+            })
+            .collect()
+    }
+
+    fn check_disk(&self) -> Option<Alert> {
+        let free_percent = match free_disk_percent(&self.config.data_dir) {
+            Ok(free_percent) => free_percent,
+            Err(e) => {
+                warn!("Can't check free disk space: {e}");
+                return None;
+            }
+        };
+        if f64::from(self.config.min_free_disk_percent) <= free_percent {
+            return None;
+        }
+
+        Some(Alert::new(
+            AlertKind::DiskNearlyFull,
+            None,
+            format!(
+                "Only {free_percent:.1}% of the disk holding {} are free",
+                self.config.data_dir.display()
+            ),
+        ))
+    }
+
+    async fn notify(&self, alert: Alert, resolved: bool) {
+        let notification = Notification {
+            federation: self.cfg.consensus.federation_name.clone(),
+            peer: self.cfg.local.identity,
+            kind: alert.kind,
+            subject: alert.subject,
+            message: alert.message,
+            resolved,
+        };
+        if resolved {
+            info!(kind = ?notification.kind, "{}", notification.message);
+        } else {
+            warn!(kind = ?notification.kind, "{}", notification.message);
+        }
+
+        if let Some(webhook) = &self.config.webhook {
+            let request = self.http.post(webhook.clone()).json(&notification).send();
+            match timeout(SEND_TIMEOUT, request).await {
+                Ok(Ok(response)) if response.status().is_success() => {}
+                Ok(Ok(response)) => warn!("Alert webhook returned {}", response.status()),
+                Ok(Err(e)) => warn!("Could not call alert webhook: {e}"),
+                Err(_) => warn!("Alert webhook timed out"),
+            }
+        }
+
+        if let Some(smtp) = &self.config.smtp {
+            let body = format!("{}\n", notification.message);
+            let send = smtp.send(
+                &notification.title(),
+                &body,
+                self.cfg.local.socks5_proxy.as_deref(),
+            );
+            match timeout(SEND_TIMEOUT, send).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Could not email alert: {e:#}"),
+                Err(_) => warn!("Emailing alert timed out"),
+            }
+        }
+    }
+}
+
+fn large_peg_out_alert(txid: Txid, amount: bitcoin::Amount) -> Alert {
+    Alert::new(
+        AlertKind::LargePegOut,
+        Some(txid.to_string()),
+        format!("Peg-out transaction {txid} paying out {amount} is being signed"),
+    )
+}
+
+/// Percentage of the file system containing `path` that is available to
+/// unprivileged users
+fn free_disk_percent(path: &Path) -> anyhow::Result<f64> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string and `stat` is only read if the call
+    // succeeded and initialized it
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        stat.assume_init()
+    };
+    if stat.f_blocks == 0 {
+        return Err(format_err!("File system reports no blocks"));
+    }
+    Ok(stat.f_bavail as f64 * 100.0 / stat.f_blocks as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use bitcoin::Txid;
+
+    use super::{large_peg_out_alert, Alert, AlertKind, AlertState};
+
+    fn peer_offline(peer: u16) -> Alert {
+        Alert::new(
+            AlertKind::PeerOffline,
+            Some(peer.to_string()),
+            format!("Peer {peer} has been disconnected"),
+        )
+    }
+
+    #[test]
+    fn alerts_are_sent_when_raised_and_resolved() {
+        let mut state = AlertState::default();
+
+        let changes = state.update(vec![peer_offline(1), peer_offline(2)]);
+        assert_eq!(
+            changes,
+            vec![(peer_offline(1), false), (peer_offline(2), false)]
+        );
+
+        // Still offline, nothing to send again
+        assert!(state
+            .update(vec![peer_offline(1), peer_offline(2)])
+            .is_empty());
+
+        let changes = state.update(vec![peer_offline(2)]);
+        assert_eq!(changes, vec![(peer_offline(1), true)]);
+
+        let changes = state.update(vec![]);
+        assert_eq!(changes, vec![(peer_offline(2), true)]);
+    }
+
+    #[test]
+    fn events_are_not_resolved() {
+        let mut state = AlertState::default();
+        let peg_out = large_peg_out_alert(Txid::all_zeros(), bitcoin::Amount::ONE_BTC);
+
+        let changes = state.update(vec![peg_out.clone()]);
+        assert_eq!(changes, vec![(peg_out.clone(), false)]);
+        assert!(state.update(vec![peg_out]).is_empty());
+        assert!(state.update(vec![]).is_empty());
+    }
+}
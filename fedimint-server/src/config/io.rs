@@ -131,15 +131,59 @@ pub fn parse_peer_params(url: String) -> anyhow::Result<PeerServerParams> {
     ensure!(split.len() == 4, "Cert string has wrong number of fields");
     let p2p_url = split[0].parse()?;
     let api_url = split[1].parse()?;
-    let hex_cert = Vec::from_hex(split[3])?;
+    let name = split[2].to_string();
+    // The name and cert are used to set up TLS connections which expect both to
+    // be valid
+    ensure!(
+        rustls::ServerName::try_from(name.as_str()).is_ok(),
+        "Name {name} can't be used in a TLS cert"
+    );
+    let cert = rustls::Certificate(Vec::from_hex(split[3])?);
+    rustls::RootCertStore::empty()
+        .add(&cert)
+        .map_err(|e| format_err!("Invalid TLS cert: {e}"))?;
     Ok(PeerServerParams {
-        cert: rustls::Certificate(hex_cert),
+        cert,
         p2p_url,
         api_url,
-        name: split[2].to_string(),
+        name,
     })
 }
 
+/// Checks the certs of all peers before anyone runs the DKG with them, so
+/// mistakes are found without having to restart it. Returns a description of
+/// every problem found, DKG with certs without problems can still fail if peers
+/// aren't reachable or use other parameters.
+pub fn validate_certs(certs: &[String]) -> Vec<String> {
+    let mut problems = vec![];
+    let mut peers = vec![];
+    for (idx, cert) in certs.iter().enumerate() {
+        match parse_peer_params(cert.clone()) {
+            Ok(params) => peers.push(params),
+            Err(e) => problems.push(format!("Cert #{} is invalid: {e}", idx + 1)),
+        }
+    }
+
+    if peers.is_empty() {
+        problems.push("No certs given".to_string());
+    }
+
+    let duplicates = |what: &str, key: fn(&PeerServerParams) -> String| {
+        peers
+            .iter()
+            .map(key)
+            .duplicates()
+            .map(|value| format!("{what} {value} is used by more than one peer"))
+            .collect::<Vec<_>>()
+    };
+    problems.extend(duplicates("Name", |params| params.name.clone()));
+    problems.extend(duplicates("P2P URL", |params| params.p2p_url.to_string()));
+    problems.extend(duplicates("API URL", |params| params.api_url.to_string()));
+    problems.extend(duplicates("TLS cert", |params| params.cert.0.to_hex()));
+
+    problems
+}
+
 fn gen_tls(
     dir_out_path: &Path,
     p2p_url: Url,
@@ -224,3 +268,52 @@ pub fn encrypted_json_write<T: Serialize + DeserializeOwned>(
     let bytes = serde_json::to_string(obj)?.into_bytes();
     encrypted_write(bytes, key, path.with_extension(ENCRYPTED_EXT))
 }
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::hex::ToHex;
+
+    use crate::config::gen_cert_and_key;
+    use crate::config::io::{parse_peer_params, validate_certs};
+
+    fn cert(name: &str, port: u16) -> String {
+        let (cert, _) = gen_cert_and_key(name).unwrap();
+        format!(
+            "ws://127.0.0.1:{port}/@ws://127.0.0.1:{}/@{name}@{}",
+            port + 1,
+            cert.0.to_hex()
+        )
+    }
+
+    #[test]
+    fn test_validate_certs() {
+        let certs = vec![cert("peer0", 8000), cert("peer1", 8010)];
+        assert!(validate_certs(&certs).is_empty());
+
+        let problems = validate_certs(&[certs[0].clone(), certs[0].clone()]);
+        assert_eq!(problems.len(), 4, "{problems:?}");
+
+        let problems = validate_certs(&[certs[0].clone(), cert("peer0", 8020)]);
+        assert_eq!(
+            problems,
+            vec!["Name peer0 is used by more than one peer".to_string()]
+        );
+
+        let problems = validate_certs(&[certs[0].clone(), "invalid".to_string()]);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("Cert #2 is invalid"));
+        assert_eq!(validate_certs(&[]), vec!["No certs given".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_peer_params_rejects_invalid_tls_params() {
+        let valid = cert("peer0", 8000);
+        assert!(parse_peer_params(valid.clone()).is_ok());
+
+        let invalid_name = valid.replace("@peer0@", "@peer 0@");
+        assert!(parse_peer_params(invalid_name).is_err());
+
+        let invalid_cert = "ws://127.0.0.1:8000/@ws://127.0.0.1:8001/@peer0@00".to_string();
+        assert!(parse_peer_params(invalid_cert).is_err());
+    }
+}
@@ -22,6 +22,16 @@ fn item_message(item: &ConsensusItem) -> String {
     match item {
         ConsensusItem::EpochOutcomeSignatureShare(_) => "Outcome Signature".to_string(),
         ConsensusItem::ClientConfigSignatureShare(_) => "Client Config Signature".to_string(),
+        ConsensusItem::FederationMetaSignatureShare(_) => "Federation Meta Signature".to_string(),
+        ConsensusItem::UpgradeSignal(_) => "Upgrade Signal".to_string(),
+        ConsensusItem::ModuleVersionVote(vote) => format!(
+            "Module Version Vote: module={} version={}",
+            vote.module_instance_id, vote.version.0
+        ),
+        ConsensusItem::ModuleParamsVote(vote) => format!(
+            "Module Params Vote: module={} activation_epoch={} change={}",
+            vote.module_instance_id, vote.activation_epoch, vote.change
+        ),
         // TODO: make this nice again
         ConsensusItem::Module(mci) => {
             format!("Module CI: module={} ci={}", mci.module_instance_id(), mci)
@@ -7,19 +7,24 @@ use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::OsString;
 use std::iter::FromIterator;
 use std::os::unix::prelude::OsStrExt;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 
-use fedimint_api::config::{ConfigResponse, ModuleGenRegistry};
+use fedimint_api::config::{ConfigResponse, JsonWithKind, ModuleGenRegistry};
 use fedimint_api::core::ModuleInstanceId;
 use fedimint_api::db::{Database, DatabaseTransaction};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::module::audit::Audit;
 use fedimint_api::module::registry::{ModuleDecoderRegistry, ModuleRegistry, ServerModuleRegistry};
-use fedimint_api::module::{ModuleError, TransactionItemAmount};
+use fedimint_api::module::{
+    CoreConsensusVersion, ModuleConsensusVersion, ModuleError, TransactionItemAmount,
+};
+use fedimint_api::net::FM_SOCKS5_PROXY_ENV;
 use fedimint_api::server::{DynServerModule, DynVerificationCache};
 use fedimint_api::task::TaskGroup;
-use fedimint_api::{Amount, OutPoint, PeerId, TransactionId};
+use fedimint_api::{Amount, NumPeers, OutPoint, PeerId, TransactionId};
 use fedimint_core::epoch::*;
+use fedimint_core::meta::SignedFederationMeta;
 use fedimint_core::outcome::TransactionStatus;
 use futures::future::select_all;
 use futures::StreamExt;
@@ -28,16 +33,20 @@ use itertools::Itertools;
 use thiserror::Error;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
-use tracing::{debug, error, info, info_span, instrument, trace, warn, Instrument};
+use tracing::{debug, error, field, info, info_span, instrument, trace, warn, Instrument, Span};
 
 use crate::config::ServerConfig;
 use crate::consensus::interconnect::FedimintInterconnect;
 use crate::consensus::TransactionSubmissionError::TransactionReplayError;
 use crate::db::{
-    AcceptedTransactionKey, ClientConfigSignatureKey, DropPeerKey, DropPeerKeyPrefix,
-    EpochHistoryKey, LastEpochKey, RejectedTransactionKey,
+    apply_all_migrations, AcceptedTransactionKey, ActiveModuleVersionKey, ClientConfigSignatureKey,
+    DropPeerKey, DropPeerKeyPrefix, EpochHistoryKey, FederationMetaSignatureKey, LastEpochKey,
+    ModuleParamsVoteKey, ModuleParamsVoteKeyPrefix, ModuleVersionVoteKey,
+    ModuleVersionVoteKeyPrefix, RejectedTransactionKey, ScheduledModuleParams,
+    ScheduledModuleParamsKey, ScheduledModuleParamsKeyPrefix, UpgradeSignalKey,
 };
-use crate::logging::LOG_CONSENSUS;
+use crate::logging::{CorrelationId, CORRELATION_ID, LOG_CONSENSUS};
+use crate::net::peers::PeerStatusTracker;
 use crate::transaction::{Transaction, TransactionError};
 
 pub type HbbftSerdeConsensusOutcome = hbbft::honey_badger::Batch<Vec<SerdeConsensusItem>, PeerId>;
@@ -47,6 +56,10 @@ pub type HbbftMessage = hbbft::honey_badger::Message<PeerId>;
 /// How many txs can be stored in memory before blocking the API
 const TRANSACTION_BUFFER_SIZE: usize = 1000;
 
+/// Version of the core consensus implemented by this code, see
+/// [`CoreConsensusVersion`]
+pub const CORE_CONSENSUS_VERSION: CoreConsensusVersion = CoreConsensusVersion(0);
+
 // TODO remove HBBFT `Batch` from `ConsensusOutcome`
 #[derive(Debug, Clone)]
 pub struct ConsensusOutcomeConversion(pub HbbftConsensusOutcome);
@@ -74,6 +87,41 @@ pub struct ConsensusProposal {
     pub force_new_epoch: bool,
 }
 
+/// Progress of consensus as seen by the epoch loop, can be cloned and read
+/// from other tasks
+#[derive(Debug, Clone, Default)]
+pub struct ConsensusProgressTracker(Arc<RwLock<ConsensusProgress>>);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsensusProgress {
+    /// Last epoch we processed since the start of the guardian
+    pub last_epoch: Option<u64>,
+    pub last_epoch_processed_at: Option<Instant>,
+    /// Since when we are running an epoch that didn't produce an outcome yet,
+    /// `None` while consensus is idle waiting for something to propose
+    pub epoch_running_since: Option<Instant>,
+}
+
+impl ConsensusProgressTracker {
+    pub fn progress(&self) -> ConsensusProgress {
+        *self.0.read().expect("not poisoned")
+    }
+
+    pub(crate) fn epoch_started(&self) {
+        let mut progress = self.0.write().expect("not poisoned");
+        progress
+            .epoch_running_since
+            .get_or_insert_with(Instant::now);
+    }
+
+    pub(crate) fn epoch_processed(&self, epoch: u64) {
+        let mut progress = self.0.write().expect("not poisoned");
+        progress.last_epoch = Some(epoch);
+        progress.last_epoch_processed_at = Some(Instant::now());
+        progress.epoch_running_since = None;
+    }
+}
+
 // TODO: we should make other fields private and get rid of this
 #[non_exhaustive]
 pub struct FedimintConsensus {
@@ -97,6 +145,16 @@ pub struct FedimintConsensus {
     /// Cache of transactions to include in a proposal
     // TODO should be able to eventually remove this Mutex
     pub tx_cache: Mutex<HashSet<Transaction>>,
+
+    /// Correlation ids of the API requests that submitted transactions to us,
+    /// until the transactions are processed
+    correlation_ids: Mutex<HashMap<TransactionId, CorrelationId>>,
+
+    /// Connection status of our peers, set once the connections are started
+    pub peer_status: PeerStatusTracker,
+
+    /// Whether consensus is making progress, updated by the epoch loop
+    pub consensus_progress: ConsensusProgressTracker,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
@@ -116,6 +174,123 @@ struct FundingVerifier {
     fee_amount: Amount,
 }
 
+/// Consensus versions the module instances configured in `cfg` currently run
+/// with, the ones switched to by the guardians or else the ones from the config
+pub async fn active_module_versions(
+    cfg: &ServerConfig,
+    dbtx: &mut DatabaseTransaction<'_>,
+) -> BTreeMap<ModuleInstanceId, ModuleConsensusVersion> {
+    let mut versions = BTreeMap::new();
+    for module_id in cfg.consensus.modules.keys() {
+        let version = match dbtx
+            .get_value(&ActiveModuleVersionKey(*module_id))
+            .await
+            .expect("DB Error")
+        {
+            Some(version) => version,
+            None => cfg
+                .consensus
+                .module_versions
+                .get(module_id)
+                .copied()
+                .unwrap_or_default(),
+        };
+        versions.insert(*module_id, version);
+    }
+    versions
+}
+
+/// Module consensus configs from the scheduled params changes that are active
+/// by the next epoch but differ from the ones in `cfg`
+async fn pending_module_params(
+    cfg: &ServerConfig,
+    dbtx: &mut DatabaseTransaction<'_>,
+) -> anyhow::Result<BTreeMap<ModuleInstanceId, JsonWithKind>> {
+    let next_epoch = dbtx
+        .get_value(&LastEpochKey)
+        .await?
+        .map_or(0, |key| key.0 + 1);
+    let scheduled = dbtx
+        .find_by_prefix(&ScheduledModuleParamsKeyPrefix)
+        .await
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut pending = BTreeMap::new();
+    for res in scheduled {
+        let (key, scheduled) = res?;
+        if next_epoch < scheduled.activation_epoch {
+            continue;
+        }
+        let Some(module_cfg) = cfg.consensus.modules.get(&key.0) else {
+            anyhow::bail!(
+                "Params were scheduled for unknown module instance {}",
+                key.0
+            )
+        };
+        let consensus = JsonWithKind::new(
+            module_cfg.kind().clone(),
+            serde_json::from_str(&scheduled.consensus)?,
+        );
+        if *module_cfg != consensus {
+            pending.insert(key.0, consensus);
+        }
+    }
+    Ok(pending)
+}
+
+/// The federation meta of `cfg` with the guardians' signature once they agreed
+/// on it, as served by the `/meta` endpoint
+pub async fn signed_federation_meta(
+    cfg: &ServerConfig,
+    dbtx: &mut DatabaseTransaction<'_>,
+) -> SignedFederationMeta {
+    let meta = cfg.consensus.federation_meta();
+    let meta_hash = meta.consensus_hash().expect("Hashes");
+    let signature = dbtx
+        .get_value(&FederationMetaSignatureKey(meta_hash))
+        .await
+        .expect("DB error");
+    SignedFederationMeta { meta, signature }
+}
+
+/// Balance sheet of the federation summed up from the audits of all `modules`
+pub async fn audit(modules: &ServerModuleRegistry, db: &Database) -> Audit {
+    // Read all modules from the same snapshot so the balance sheet is consistent
+    // even while consensus keeps processing transactions
+    let mut dbtx = db.begin_read_transaction().await;
+    let mut audit = Audit::default();
+    for (module_instance_id, module) in modules.iter_modules() {
+        module
+            .audit(&mut dbtx.with_module_prefix(module_instance_id), &mut audit)
+            .await
+    }
+    audit
+}
+
+/// Applies the module params changes the guardians agreed on and that are
+/// active by now to the consensus config `cfg`, which then has to be written
+/// to disk. Returns whether the config changed.
+pub async fn apply_scheduled_module_params(
+    cfg: &mut ServerConfig,
+    db: &Database,
+) -> anyhow::Result<bool> {
+    let mut dbtx = db.begin_transaction().await;
+    let pending = pending_module_params(cfg, &mut dbtx).await?;
+    if pending.is_empty() {
+        return Ok(false);
+    }
+
+    for (module_instance_id, consensus) in pending {
+        info!(module_instance_id, "Applying scheduled module params");
+        cfg.consensus.modules.insert(module_instance_id, consensus);
+    }
+    // The guardians need to sign the changed client config again
+    dbtx.remove_entry(&ClientConfigSignatureKey).await?;
+    dbtx.commit_tx().await?;
+    Ok(true)
+}
+
 impl FedimintConsensus {
     pub fn get_env_vars_map() -> BTreeMap<OsString, OsString> {
         std::env::vars_os()
@@ -126,6 +301,16 @@ impl FedimintConsensus {
             .collect()
     }
 
+    /// Like [`Self::get_env_vars_map`], but with the SOCKS5 proxy of the local
+    /// config taking precedence over the one of the process
+    pub fn get_module_env_vars_map(cfg: &ServerConfig) -> BTreeMap<OsString, OsString> {
+        let mut env = Self::get_env_vars_map();
+        if let Some(proxy) = &cfg.local.socks5_proxy {
+            env.insert(FM_SOCKS5_PROXY_ENV.into(), proxy.into());
+        }
+        env
+    }
+
     /// Returns a new consensus with a receiver for handling submitted
     /// transactions
     pub async fn new(
@@ -134,9 +319,21 @@ impl FedimintConsensus {
         module_inits: ModuleGenRegistry,
         task_group: &mut TaskGroup,
     ) -> anyhow::Result<(Self, Receiver<Transaction>)> {
+        apply_all_migrations(&cfg, &db, &module_inits, false).await?;
+
+        let pending_params =
+            pending_module_params(&cfg, &mut db.begin_read_transaction().await).await?;
+        if let Some(module_id) = pending_params.keys().next() {
+            anyhow::bail!(
+                "The config lacks the scheduled params change of module instance {module_id}, apply it first"
+            )
+        }
+
         let mut modules = BTreeMap::new();
 
-        let env = Self::get_env_vars_map();
+        let env = Self::get_module_env_vars_map(&cfg);
+        let module_versions =
+            active_module_versions(&cfg, &mut db.begin_read_transaction().await).await;
 
         for (module_id, module_cfg) in &cfg.consensus.modules {
             let kind = module_cfg.kind();
@@ -144,6 +341,13 @@ impl FedimintConsensus {
             let Some(init) = module_inits.get(kind) else {
                 anyhow::bail!("Detected configuration for unsupported module kind: {kind}")
             };
+            let version = module_versions[module_id];
+            if !init.versions(CORE_CONSENSUS_VERSION).contains(&version) {
+                anyhow::bail!(
+                    "Module instance {module_id} of kind {kind} runs consensus version {}, which this code doesn't support",
+                    version.0
+                )
+            }
             info!(module_instance_id = *module_id, kind = %kind, "Init module");
 
             let module = init
@@ -169,6 +373,9 @@ impl FedimintConsensus {
                 db,
                 tx_sender,
                 tx_cache: Default::default(),
+                correlation_ids: Default::default(),
+                peer_status: Default::default(),
+                consensus_progress: Default::default(),
             },
             tx_receiver,
         ))
@@ -193,6 +400,9 @@ impl FedimintConsensus {
                 db,
                 tx_sender,
                 tx_cache: Default::default(),
+                correlation_ids: Default::default(),
+                peer_status: Default::default(),
+                consensus_progress: Default::default(),
             },
             tx_receiver,
         )
@@ -216,6 +426,7 @@ impl FedimintConsensus {
         self.db.begin_transaction().await
     }
 
+    #[instrument(skip_all, fields(txid = %transaction.tx_hash()))]
     pub async fn submit_transaction(
         &self,
         transaction: Transaction,
@@ -232,6 +443,8 @@ impl FedimintConsensus {
         let tx_hash = transaction.tx_hash();
         debug!(%tx_hash, "Received mint transaction");
 
+        transaction.validate_size(self.cfg.consensus.max_transaction_size)?;
+
         let mut funding_verifier = FundingVerifier::default();
 
         let mut pub_keys = Vec::new();
@@ -274,6 +487,9 @@ impl FedimintConsensus {
 
         funding_verifier.verify_funding()?;
 
+        if let Ok(id) = CORRELATION_ID.try_with(|id| *id) {
+            self.correlation_ids.lock().unwrap().insert(tx_hash, id);
+        }
         self.tx_sender
             .send(transaction)
             .await
@@ -291,7 +507,13 @@ impl FedimintConsensus {
     /// **Note**: `reference_rejected_txs` **must** come from a
     /// validated/trustworthy source and be correct, or it can cause a
     /// panic.
-    #[instrument(skip_all, fields(epoch = consensus_outcome.epoch))]
+    // A root span, so every epoch is exported as its own trace instead of
+    // being part of the guardian's span
+    #[instrument(
+        parent = None,
+        skip_all,
+        fields(peer_id = %self.cfg.local.identity, epoch = consensus_outcome.epoch)
+    )]
     pub async fn process_consensus_outcome(
         &self,
         consensus_outcome: HbbftConsensusOutcome,
@@ -311,6 +533,10 @@ impl FedimintConsensus {
                         let UnzipConsensusItem {
                             epoch_outcome_signature_share: _epoch_outcome_signature_share_cis,
                             client_config_signature_share: _client_config_signature_share_cis,
+                            federation_meta_signature_share: _federation_meta_signature_share_cis,
+                            upgrade_signal: _upgrade_signal_cis,
+                            module_version_vote: module_version_vote_cis,
+                            module_params_vote: module_params_vote_cis,
                             transaction: transaction_cis,
                             module: module_cis,
                         } = consensus_outcome
@@ -336,9 +562,22 @@ impl FedimintConsensus {
                             );
                         }
 
+                        self.process_module_version_votes(dbtx, &module_version_vote_cis)
+                            .await;
+                        self.process_module_params_votes(dbtx, epoch, &module_params_vote_cis)
+                            .await;
+
                         let epoch_history = self
                             .finalize_process_epoch(dbtx, outcome.clone(), rejected_txs)
                             .await;
+
+                        // Consensus halts after this epoch, the signal must not
+                        // carry over to the upgraded guardian
+                        if self.is_upgrade_epoch(&epoch_history) {
+                            dbtx.remove_entry(&UpgradeSignalKey)
+                                .await
+                                .expect("DB Error");
+                        }
                         Result::<_, ()>::Ok(epoch_history)
                     })
                 },
@@ -378,6 +617,179 @@ impl FedimintConsensus {
         }
     }
 
+    /// Switches module instances to the consensus version a threshold of
+    /// guardians voted for in this epoch. The new version applies from the
+    /// next epoch on.
+    async fn process_module_version_votes(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        votes: &[(PeerId, ModuleVersionVote)],
+    ) {
+        if votes.is_empty() {
+            return;
+        }
+
+        let mut voters: BTreeMap<&ModuleVersionVote, BTreeSet<PeerId>> = BTreeMap::new();
+        for (peer, vote) in votes {
+            voters.entry(vote).or_default().insert(*peer);
+        }
+
+        let active = active_module_versions(&self.cfg, dbtx).await;
+        for (vote, peers) in voters {
+            if peers.len() < self.cfg.local.p2p.threshold() {
+                continue;
+            }
+            let Some(current) = active.get(&vote.module_instance_id) else {
+                warn!(
+                    target: LOG_CONSENSUS,
+                    module_instance_id = vote.module_instance_id,
+                    "Guardians voted on the version of an unknown module instance"
+                );
+                continue;
+            };
+            if *current == vote.version {
+                continue;
+            }
+
+            info!(
+                target: LOG_CONSENSUS,
+                module_instance_id = vote.module_instance_id,
+                version = vote.version.0,
+                "Activating module consensus version"
+            );
+            dbtx.insert_entry(
+                &ActiveModuleVersionKey(vote.module_instance_id),
+                &vote.version,
+            )
+            .await
+            .expect("DB Error");
+            self.modules
+                .get_expect(vote.module_instance_id)
+                .activate_consensus_version(
+                    &mut dbtx.with_module_prefix(vote.module_instance_id),
+                    vote.version,
+                )
+                .await;
+        }
+
+        // Our votes are done once their version is active
+        let active = active_module_versions(&self.cfg, dbtx).await;
+        let our_votes = dbtx
+            .find_by_prefix(&ModuleVersionVoteKeyPrefix)
+            .await
+            .map(|res| res.expect("DB Error"))
+            .collect::<Vec<_>>()
+            .await;
+        for (key, version) in our_votes {
+            if active.get(&key.0) == Some(&version) {
+                dbtx.remove_entry(&key).await.expect("DB Error");
+            }
+        }
+    }
+
+    /// Schedules the module parameter changes a threshold of guardians voted
+    /// for in this epoch. Consensus halts before their activation epoch so
+    /// that every guardian restarts with the changed config.
+    async fn process_module_params_votes(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        epoch: u64,
+        votes: &[(PeerId, ModuleParamsVote)],
+    ) {
+        if votes.is_empty() {
+            return;
+        }
+
+        let mut voters: BTreeMap<&ModuleParamsVote, BTreeSet<PeerId>> = BTreeMap::new();
+        for (peer, vote) in votes {
+            voters.entry(vote).or_default().insert(*peer);
+        }
+
+        let mut scheduled = BTreeSet::new();
+        for (vote, peers) in voters {
+            if peers.len() < self.cfg.local.p2p.threshold() {
+                continue;
+            }
+            if vote.activation_epoch <= epoch {
+                warn!(
+                    target: LOG_CONSENSUS,
+                    module_instance_id = vote.module_instance_id,
+                    activation_epoch = vote.activation_epoch,
+                    "Guardians agreed on module params too late for their activation epoch"
+                );
+                continue;
+            }
+            let consensus = match self.changed_module_consensus(dbtx, vote).await {
+                Ok(consensus) => consensus,
+                Err(e) => {
+                    warn!(
+                        target: LOG_CONSENSUS,
+                        module_instance_id = vote.module_instance_id,
+                        "Guardians agreed on invalid module params: {e}"
+                    );
+                    continue;
+                }
+            };
+
+            info!(
+                target: LOG_CONSENSUS,
+                module_instance_id = vote.module_instance_id,
+                activation_epoch = vote.activation_epoch,
+                "Scheduling module params change"
+            );
+            dbtx.insert_entry(
+                &ScheduledModuleParamsKey(vote.module_instance_id),
+                &ScheduledModuleParams {
+                    activation_epoch: vote.activation_epoch,
+                    consensus: consensus.to_string(),
+                },
+            )
+            .await
+            .expect("DB Error");
+            scheduled.insert(vote.clone());
+        }
+
+        // Our votes are done once they are scheduled or can't be anymore
+        let our_votes = dbtx
+            .find_by_prefix(&ModuleParamsVoteKeyPrefix)
+            .await
+            .map(|res| res.expect("DB Error"))
+            .collect::<Vec<_>>()
+            .await;
+        for (key, vote) in our_votes {
+            if scheduled.contains(&vote) || vote.activation_epoch <= epoch + 1 {
+                dbtx.remove_entry(&key).await.expect("DB Error");
+            }
+        }
+    }
+
+    /// Module consensus config resulting from applying the change of `vote`
+    /// on top of the latest config, including changes that are scheduled but
+    /// not active yet
+    async fn changed_module_consensus(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        vote: &ModuleParamsVote,
+    ) -> anyhow::Result<serde_json::Value> {
+        let Some(module_cfg) = self.cfg.consensus.modules.get(&vote.module_instance_id) else {
+            anyhow::bail!("There is no module instance {}", vote.module_instance_id)
+        };
+        let current = match dbtx
+            .get_value(&ScheduledModuleParamsKey(vote.module_instance_id))
+            .await
+            .expect("DB Error")
+        {
+            Some(scheduled) => serde_json::from_str(&scheduled.consensus)?,
+            None => module_cfg.value().clone(),
+        };
+        let change = serde_json::from_str(&vote.change)?;
+
+        self.module_inits
+            .get(module_cfg.kind())
+            .expect("Checked on startup")
+            .apply_params_change(current, change)
+    }
+
     /// Applies all valid fedimint transactions to the database transaction
     /// `dbtx` and returns a set of invalid transactions that were filtered
     /// out
@@ -400,7 +812,15 @@ impl FedimintConsensus {
                 continue;
             }
 
-            let span = info_span!("Processing transaction");
+            let span = info_span!(
+                "Processing transaction",
+                %txid,
+                correlation_id = field::Empty,
+                outcome = field::Empty
+            );
+            if let Some(correlation_id) = self.correlation_ids.lock().unwrap().remove(&txid) {
+                span.record("correlation_id", field::display(correlation_id));
+            }
             async {
                 trace!(?transaction);
                 self.tx_cache.lock().unwrap().remove(&transaction);
@@ -412,6 +832,7 @@ impl FedimintConsensus {
                     .await
                 {
                     Ok(()) => {
+                        Span::current().record("outcome", "accepted");
                         dbtx.insert_entry(
                             &AcceptedTransactionKey(txid),
                             &AcceptedTransaction { epoch, transaction },
@@ -420,6 +841,7 @@ impl FedimintConsensus {
                         .expect("DB Error");
                     }
                     Err(error) => {
+                        Span::current().record("outcome", "rejected");
                         rejected_txs.insert(txid);
                         dbtx.rollback_tx_to_savepoint().await;
                         warn!(target: LOG_CONSENSUS, %error, "Transaction failed");
@@ -450,6 +872,7 @@ impl FedimintConsensus {
 
         self.save_client_config_sig(dbtx, &outcome, &mut drop_peers)
             .await;
+        self.save_federation_meta_sig(dbtx, &outcome).await;
 
         let epoch_history = self
             .save_epoch_history(outcome.clone(), dbtx, &mut drop_peers, rejected_txs)
@@ -528,6 +951,60 @@ impl FedimintConsensus {
         }
     }
 
+    /// Combines the guardians' signature shares of the current federation meta
+    /// once we don't have a signature of it yet. Unlike with the client config
+    /// peers aren't dropped for missing shares, since the meta only affects
+    /// what wallets display.
+    async fn save_federation_meta_sig(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        outcome: &HbbftConsensusOutcome,
+    ) {
+        let meta_hash = self
+            .cfg
+            .consensus
+            .federation_meta()
+            .consensus_hash()
+            .expect("Hashes");
+        let key = FederationMetaSignatureKey(meta_hash);
+        if dbtx.get_value(&key).await.expect("DB error").is_some() {
+            return;
+        }
+
+        let pks = &self.cfg.consensus.auth_pk_set;
+        let shares: BTreeMap<_, _> = outcome
+            .contributions
+            .iter()
+            .flat_map(|(peer, items)| items.iter().map(|i| (*peer, i)))
+            .filter_map(|(peer, item)| match item {
+                ConsensusItem::FederationMetaSignatureShare(SerdeSignatureShare(sig)) => {
+                    Some((peer, sig))
+                }
+                _ => None,
+            })
+            .filter(|(peer, sig)| pks.public_key_share(peer.to_usize()).verify(sig, meta_hash))
+            .map(|(peer, sig)| (peer.to_usize(), sig))
+            .collect();
+
+        if let Ok(final_sig) = pks.combine_signatures(shares) {
+            dbtx.insert_entry(&key, &SerdeSignature(final_sig))
+                .await
+                .expect("DB Error");
+        } else {
+            warn!(
+                target: LOG_CONSENSUS,
+                "Did not receive enough valid federation meta sig shares"
+            )
+        }
+    }
+
+    pub async fn get_federation_meta(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> SignedFederationMeta {
+        signed_federation_meta(&self.cfg, dbtx).await
+    }
+
     pub async fn get_config_with_sig(&self, dbtx: &mut DatabaseTransaction<'_>) -> ConfigResponse {
         let mut client = self.client_cfg.clone();
         let maybe_sig = dbtx.get_value(&ClientConfigSignatureKey).await;
@@ -537,6 +1014,161 @@ impl FedimintConsensus {
         client
     }
 
+    /// Signals that we are ready to halt consensus for an upgrade, see
+    /// [`ConsensusItem::UpgradeSignal`]
+    pub async fn signal_upgrade(&self) {
+        let mut dbtx = self.database_transaction().await;
+        dbtx.insert_entry(&UpgradeSignalKey, &())
+            .await
+            .expect("DB Error");
+        dbtx.commit_tx().await.expect("DB Error");
+    }
+
+    /// Votes to switch a module instance to consensus `version`, which is
+    /// proposed in every epoch until a threshold of guardians voted for it.
+    /// Voting for the currently active version withdraws the vote.
+    pub async fn vote_module_version(
+        &self,
+        module_instance_id: ModuleInstanceId,
+        version: ModuleConsensusVersion,
+    ) -> anyhow::Result<()> {
+        let Some(module_cfg) = self.cfg.consensus.modules.get(&module_instance_id) else {
+            anyhow::bail!("There is no module instance {module_instance_id}")
+        };
+        let kind = module_cfg.kind();
+        let supported = self
+            .module_inits
+            .get(kind)
+            .expect("Checked on startup")
+            .versions(CORE_CONSENSUS_VERSION);
+        if !supported.contains(&version) {
+            anyhow::bail!(
+                "Module kind {kind} doesn't support consensus version {}",
+                version.0
+            );
+        }
+
+        let mut dbtx = self.database_transaction().await;
+        let active = active_module_versions(&self.cfg, &mut dbtx).await;
+        if active[&module_instance_id] == version {
+            dbtx.remove_entry(&ModuleVersionVoteKey(module_instance_id))
+                .await
+                .expect("DB Error");
+        } else {
+            dbtx.insert_entry(&ModuleVersionVoteKey(module_instance_id), &version)
+                .await
+                .expect("DB Error");
+        }
+        dbtx.commit_tx().await.expect("DB Error");
+        Ok(())
+    }
+
+    /// Our pending votes for module consensus versions, see
+    /// [`Self::vote_module_version`]
+    pub async fn module_version_votes(&self) -> BTreeMap<ModuleInstanceId, ModuleConsensusVersion> {
+        let mut dbtx = self.database_transaction().await;
+        dbtx.find_by_prefix(&ModuleVersionVoteKeyPrefix)
+            .await
+            .map(|res| {
+                let (key, version) = res.expect("DB Error");
+                (key.0, version)
+            })
+            .collect()
+            .await
+    }
+
+    /// Consensus versions the module instances currently run with
+    pub async fn module_versions(&self) -> BTreeMap<ModuleInstanceId, ModuleConsensusVersion> {
+        let mut dbtx = self.database_transaction().await;
+        active_module_versions(&self.cfg, &mut dbtx).await
+    }
+
+    /// Votes to change the consensus params of a module instance from
+    /// `activation_epoch` on, see [`IModuleGen::apply_params_change`]. The
+    /// vote is proposed in every epoch until a threshold of guardians voted
+    /// for the same change, and replaces any earlier vote for the instance.
+    ///
+    /// [`IModuleGen::apply_params_change`]: fedimint_api::module::IModuleGen::apply_params_change
+    pub async fn vote_module_params(
+        &self,
+        vote: ModuleParamsVote,
+    ) -> anyhow::Result<ModuleParamsVote> {
+        let epoch_count = self.get_epoch_count().await;
+        if vote.activation_epoch <= epoch_count {
+            anyhow::bail!(
+                "Activation epoch {} must be after the next epoch {epoch_count}",
+                vote.activation_epoch
+            );
+        }
+        // Guardians must agree on the exact string, so we normalize it
+        let change: serde_json::Value = serde_json::from_str(&vote.change)?;
+        let vote = ModuleParamsVote {
+            change: serde_json::to_string(&change)?,
+            ..vote
+        };
+
+        let mut dbtx = self.database_transaction().await;
+        self.changed_module_consensus(&mut dbtx, &vote).await?;
+        dbtx.insert_entry(&ModuleParamsVoteKey(vote.module_instance_id), &vote)
+            .await
+            .expect("DB Error");
+        dbtx.commit_tx().await.expect("DB Error");
+        Ok(vote)
+    }
+
+    /// Our pending votes for module params changes, see
+    /// [`Self::vote_module_params`]
+    pub async fn module_params_votes(&self) -> BTreeMap<ModuleInstanceId, ModuleParamsVote> {
+        let mut dbtx = self.database_transaction().await;
+        dbtx.find_by_prefix(&ModuleParamsVoteKeyPrefix)
+            .await
+            .map(|res| {
+                let (key, vote) = res.expect("DB Error");
+                (key.0, vote)
+            })
+            .collect()
+            .await
+    }
+
+    /// Whether module params change with the epoch after `epoch`, in which
+    /// case all guardians halt consensus after it to apply them
+    pub async fn is_module_params_epoch(&self, epoch: &SignedEpochOutcome) -> bool {
+        let mut dbtx = self.database_transaction().await;
+        dbtx.find_by_prefix(&ScheduledModuleParamsKeyPrefix)
+            .await
+            .map(|res| res.expect("DB Error"))
+            .collect::<Vec<_>>()
+            .await
+            .iter()
+            .any(|(_, scheduled)| scheduled.activation_epoch == epoch.outcome.epoch + 1)
+    }
+
+    pub async fn upgrade_signaled(&self) -> bool {
+        self.database_transaction()
+            .await
+            .get_value(&UpgradeSignalKey)
+            .await
+            .expect("DB Error")
+            .is_some()
+    }
+
+    /// Whether a threshold of guardians signaled an upgrade in `epoch`, in
+    /// which case all guardians halt consensus after it
+    pub fn is_upgrade_epoch(&self, epoch: &SignedEpochOutcome) -> bool {
+        let signaling_peers = epoch
+            .outcome
+            .items
+            .iter()
+            .filter(|(_, items)| {
+                items
+                    .iter()
+                    .any(|item| matches!(item, ConsensusItem::UpgradeSignal(_)))
+            })
+            .map(|(peer, _)| *peer)
+            .collect::<HashSet<_>>();
+        signaling_peers.len() >= self.cfg.local.p2p.threshold()
+    }
+
     pub async fn get_epoch_count(&self) -> u64 {
         self.db
             .begin_transaction()
@@ -550,7 +1182,7 @@ impl FedimintConsensus {
 
     pub async fn epoch_history(&self, epoch: u64) -> Option<SignedEpochOutcome> {
         self.db
-            .begin_transaction()
+            .begin_read_transaction()
             .await
             .get_value(&EpochHistoryKey(epoch))
             .await
@@ -614,15 +1246,35 @@ impl FedimintConsensus {
         current
     }
 
+    /// Resolves once any module wants to trigger a new epoch. A module is asked
+    /// again every time its part of the database was written to, so it can
+    /// react to writes of other tasks without polling.
     pub async fn await_consensus_proposal(&self) {
         let proposal_futures = self
             .modules
             .iter_modules()
             .map(|(module_instance_id, module)| {
                 Box::pin(async move {
-                    let mut dbtx = self.database_transaction().await;
-                    let mut module_dbtx = dbtx.with_module_prefix(module_instance_id);
-                    module.await_consensus_proposal(&mut module_dbtx).await
+                    loop {
+                        // Register before reading so no write in between is missed
+                        let module_written = self
+                            .db
+                            .new_isolated(module_instance_id)
+                            .wait_raw_key_prefix_write(&[]);
+
+                        let mut dbtx = self.database_transaction().await;
+                        let mut module_dbtx = dbtx.with_module_prefix(module_instance_id);
+                        tokio::select! {
+                            () = module.await_consensus_proposal(&mut module_dbtx) => return,
+                            () = module_written => {
+                                trace!(
+                                    target: LOG_CONSENSUS,
+                                    module_instance_id,
+                                    "Module database was written to, checking proposal again"
+                                );
+                            }
+                        }
+                    }
                 })
             })
             .collect::<Vec<_>>();
@@ -687,6 +1339,50 @@ impl FedimintConsensus {
             items.push(item);
         }
 
+        // Likewise for the federation meta, which changes whenever the guardians
+        // update it in their configs
+        let meta = self.get_federation_meta(&mut dbtx).await;
+        if meta.signature.is_none() {
+            let meta_hash = meta.meta.consensus_hash().expect("Hashes");
+            let sig = self.cfg.private.auth_sks.0.sign(meta_hash);
+            let item = ConsensusItem::FederationMetaSignatureShare(SerdeSignatureShare(sig));
+            items.push(item);
+        }
+
+        if dbtx
+            .get_value(&UpgradeSignalKey)
+            .await
+            .expect("DB Error")
+            .is_some()
+        {
+            items.push(ConsensusItem::UpgradeSignal(UpgradeSignal));
+        }
+
+        let module_version_votes = dbtx
+            .find_by_prefix(&ModuleVersionVoteKeyPrefix)
+            .await
+            .map(|res| res.expect("DB Error"))
+            .collect::<Vec<_>>()
+            .await;
+        items.extend(module_version_votes.into_iter().map(|(key, version)| {
+            ConsensusItem::ModuleVersionVote(ModuleVersionVote {
+                module_instance_id: key.0,
+                version,
+            })
+        }));
+
+        let module_params_votes = dbtx
+            .find_by_prefix(&ModuleParamsVoteKeyPrefix)
+            .await
+            .map(|res| res.expect("DB Error"))
+            .collect::<Vec<_>>()
+            .await;
+        items.extend(
+            module_params_votes
+                .into_iter()
+                .map(|(_, vote)| ConsensusItem::ModuleParamsVote(vote)),
+        );
+
         ConsensusProposal {
             items,
             drop_peers,
@@ -713,6 +1409,8 @@ impl FedimintConsensus {
             return Err(TransactionReplayError(tx_hash));
         }
 
+        transaction.validate_size(self.cfg.consensus.max_transaction_size)?;
+
         let mut pub_keys = Vec::new();
         for input in transaction.inputs.iter() {
             let meta = self
@@ -724,6 +1422,10 @@ impl FedimintConsensus {
                     input,
                     caches.get_cache(input.module_instance_id()),
                 )
+                .instrument(info_span!(
+                    "Applying input",
+                    module_instance_id = input.module_instance_id()
+                ))
                 .await
                 .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
             pub_keys.push(meta.puk_keys);
@@ -740,10 +1442,16 @@ impl FedimintConsensus {
                 .modules
                 .get_expect(output.module_instance_id())
                 .apply_output(
+                    &self.build_interconnect(),
                     &mut dbtx.with_module_prefix(output.module_instance_id()),
                     &output,
                     out_point,
                 )
+                .instrument(info_span!(
+                    "Applying output",
+                    module_instance_id = output.module_instance_id(),
+                    out_idx = idx
+                ))
                 .await
                 .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
             funding_verifier.add_output(amount);
@@ -806,6 +1514,22 @@ impl FedimintConsensus {
         None
     }
 
+    /// Proves that the transaction `txid` was accepted, which is only possible
+    /// once the epoch accepting it was signed
+    pub async fn transaction_proof(&self, txid: TransactionId) -> Option<TransactionProof> {
+        let accepted: AcceptedTransaction = self
+            .db
+            .begin_read_transaction()
+            .await
+            .get_value(&AcceptedTransactionKey(txid))
+            .await
+            .expect("DB error")?;
+
+        self.epoch_history(accepted.epoch)
+            .await?
+            .prove_transaction(&txid)
+    }
+
     fn build_verification_caches<'a>(
         &self,
         transactions: impl Iterator<Item = &'a Transaction> + Send,
@@ -829,17 +1553,10 @@ impl FedimintConsensus {
     }
 
     pub async fn audit(&self) -> Audit {
-        let mut dbtx = self.database_transaction().await;
-        let mut audit = Audit::default();
-        for (module_instance_id, module) in self.modules.iter_modules() {
-            module
-                .audit(&mut dbtx.with_module_prefix(module_instance_id), &mut audit)
-                .await
-        }
-        audit
+        audit(&self.modules, &self.db).await
     }
 
-    fn build_interconnect(&self) -> FedimintInterconnect {
+    pub fn build_interconnect(&self) -> FedimintInterconnect {
         FedimintInterconnect { fedimint: self }
     }
 }
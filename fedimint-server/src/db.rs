@@ -1,15 +1,30 @@
 use std::fmt::Debug;
 
-use fedimint_api::db::MODULE_GLOBAL_PREFIX;
+use anyhow::Context;
+use bitcoin::hashes::sha256;
+use fedimint_api::config::ModuleGenRegistry;
+use fedimint_api::core::ModuleInstanceId;
+use fedimint_api::db::{
+    apply_migrations, Database, DatabaseVersion, DatabaseVersionKey, IDatabaseTransaction,
+    InvalidDbEntry, InvalidDbEntryReason, MigrationMap, DATABASE_VERSION_PREFIX,
+    MODULE_GLOBAL_PREFIX,
+};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::impl_db_prefix_const;
+use fedimint_api::module::ModuleConsensusVersion;
 use fedimint_api::{PeerId, TransactionId};
-use fedimint_core::epoch::{SerdeSignature, SignedEpochOutcome};
+use fedimint_core::epoch::{ModuleParamsVote, SerdeSignature, SignedEpochOutcome};
+use futures::{future, StreamExt, TryStreamExt};
 use serde::Serialize;
+use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
+use crate::config::ServerConfig;
 use crate::consensus::AcceptedTransaction;
 
+/// Version of the encoding of the server's global (non-module) database keys
+pub const GLOBAL_DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
 pub enum DbKeyPrefix {
@@ -19,6 +34,14 @@ pub enum DbKeyPrefix {
     EpochHistory = 0x05,
     LastEpoch = 0x06,
     ClientConfigSignature = 0x07,
+    FederationMetaSignature = 0x08,
+    UpgradeSignal = 0x09,
+    ModuleVersionVote = 0x0a,
+    ActiveModuleVersion = 0x0b,
+    ModuleParamsVote = 0x0c,
+    ScheduledModuleParams = 0x0d,
+    DatabaseEncrypted = 0x0e,
+    DatabaseVersion = DATABASE_VERSION_PREFIX,
     Module = MODULE_GLOBAL_PREFIX,
 }
 
@@ -101,3 +124,326 @@ impl_db_prefix_const!(
     prefix = DbKeyPrefix::ClientConfigSignature,
     key_prefix = ClientConfigSignatureKeyPrefix
 );
+
+/// Signature of the federation meta with the given hash, a new one is created
+/// whenever the guardians change the meta
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct FederationMetaSignatureKey(pub sha256::Hash);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct FederationMetaSignatureKeyPrefix;
+
+impl_db_prefix_const!(
+    key = FederationMetaSignatureKey,
+    value = SerdeSignature,
+    prefix = DbKeyPrefix::FederationMetaSignature,
+    key_prefix = FederationMetaSignatureKeyPrefix
+);
+
+/// Present while the guardian operator signals readiness for an upgrade, see
+/// [`crate::consensus::FedimintConsensus::signal_upgrade`]
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct UpgradeSignalKey;
+
+impl_db_prefix_const!(
+    key = UpgradeSignalKey,
+    value = (),
+    prefix = DbKeyPrefix::UpgradeSignal
+);
+
+/// Consensus version our guardian votes to run a module instance with, see
+/// [`crate::consensus::FedimintConsensus::vote_module_version`]
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ModuleVersionVoteKey(pub ModuleInstanceId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ModuleVersionVoteKeyPrefix;
+
+impl_db_prefix_const!(
+    key = ModuleVersionVoteKey,
+    value = ModuleConsensusVersion,
+    prefix = DbKeyPrefix::ModuleVersionVote,
+    key_prefix = ModuleVersionVoteKeyPrefix
+);
+
+/// Consensus version a module instance was switched to by a threshold of
+/// guardians, overriding the one in the consensus config
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ActiveModuleVersionKey(pub ModuleInstanceId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ActiveModuleVersionKeyPrefix;
+
+impl_db_prefix_const!(
+    key = ActiveModuleVersionKey,
+    value = ModuleConsensusVersion,
+    prefix = DbKeyPrefix::ActiveModuleVersion,
+    key_prefix = ActiveModuleVersionKeyPrefix
+);
+
+/// Change of a module instance's consensus parameters our guardian votes for,
+/// see [`crate::consensus::FedimintConsensus::vote_module_params`]
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ModuleParamsVoteKey(pub ModuleInstanceId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ModuleParamsVoteKeyPrefix;
+
+impl_db_prefix_const!(
+    key = ModuleParamsVoteKey,
+    value = ModuleParamsVote,
+    prefix = DbKeyPrefix::ModuleParamsVote,
+    key_prefix = ModuleParamsVoteKeyPrefix
+);
+
+/// Consensus config a threshold of guardians agreed to run a module instance
+/// with from `activation_epoch` on
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize)]
+pub struct ScheduledModuleParams {
+    pub activation_epoch: u64,
+    /// JSON encoded module consensus config
+    pub consensus: String,
+}
+
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ScheduledModuleParamsKey(pub ModuleInstanceId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ScheduledModuleParamsKeyPrefix;
+
+impl_db_prefix_const!(
+    key = ScheduledModuleParamsKey,
+    value = ScheduledModuleParams,
+    prefix = DbKeyPrefix::ScheduledModuleParams,
+    key_prefix = ScheduledModuleParamsKeyPrefix
+);
+
+/// Marks a database whose values are encrypted, see
+/// [`check_database_encryption`]
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct DatabaseEncryptedKey;
+
+impl_db_prefix_const!(
+    key = DatabaseEncryptedKey,
+    value = (),
+    prefix = DbKeyPrefix::DatabaseEncrypted
+);
+
+/// Migrations of the server's global database, see
+/// [`fedimint_api::db::apply_migrations`]
+pub fn get_global_database_migrations() -> MigrationMap {
+    MigrationMap::new()
+}
+
+/// Brings the global database and the databases of all modules configured in
+/// `cfg` up to the versions supported by this code. With `dry_run` the
+/// migrations are executed but not committed.
+pub async fn apply_all_migrations(
+    cfg: &ServerConfig,
+    db: &Database,
+    module_inits: &ModuleGenRegistry,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    apply_migrations(
+        db,
+        "fedimint-server".to_string(),
+        GLOBAL_DATABASE_VERSION,
+        get_global_database_migrations(),
+        dry_run,
+    )
+    .await?;
+
+    for (module_id, kind) in cfg.iter_module_instances() {
+        let Some(init) = module_inits.get(kind) else {
+            anyhow::bail!("Detected configuration for unsupported module kind: {kind}")
+        };
+
+        apply_migrations(
+            &db.new_isolated(module_id),
+            kind.to_string(),
+            init.database_version(),
+            init.get_database_migrations(),
+            dry_run,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Makes sure `db` is opened with the encryption it was created with, since
+/// values written with the other setting would be unreadable.
+///
+/// An encrypted database is marked when it is created, so it can't be opened
+/// without encryption later. Encryption can only be enabled for a database
+/// that is still empty or already encrypted, the latter gets marked if it was
+/// created before the marker existed.
+pub async fn check_database_encryption(db: &Database, encrypted: bool) -> anyhow::Result<()> {
+    let mut dbtx = db.begin_transaction().await;
+    let is_marked = dbtx
+        .get_value(&DatabaseEncryptedKey)
+        .await
+        .context("Database could not be decrypted, wrong password?")?
+        .is_some();
+
+    match (encrypted, is_marked) {
+        (false, true) => {
+            anyhow::bail!("The database is encrypted, but database encryption is disabled")
+        }
+        (true, false) => {
+            let first_value = dbtx
+                .raw_unchecked()
+                .raw_find_by_prefix(&[])
+                .await
+                .next()
+                .await
+                .map(|(_, value)| value);
+            if let Some(Err(e)) = first_value {
+                return Err(
+                    e.context("Database encryption can only be enabled for a fresh database")
+                );
+            }
+            dbtx.insert_new_entry(&DatabaseEncryptedKey, &()).await?;
+            dbtx.commit_tx().await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Deletes the signed outcomes of all epochs older than the last `keep_epochs`
+/// ones, returning the number of removed entries.
+///
+/// Peers that fall further behind than the retained history can no longer
+/// catch up from this guardian, so the horizon should be generous. At least
+/// two epochs are always kept since the next epoch signs the previous one.
+pub async fn prune_epoch_history(db: &Database, keep_epochs: u64) -> anyhow::Result<usize> {
+    let keep_epochs = keep_epochs.max(2);
+    let mut dbtx = db.begin_transaction().await;
+
+    let Some(last_epoch) = dbtx.get_value(&LastEpochKey).await? else {
+        return Ok(0);
+    };
+    let Some(horizon) = (last_epoch.0 + 1).checked_sub(keep_epochs) else {
+        return Ok(0);
+    };
+
+    let expired = dbtx
+        .find_by_prefix(&EpochHistoryKeyPrefix)
+        .await
+        .map(|res| res.map(|(key, _)| key))
+        .try_filter(|key| future::ready(key.0 < horizon))
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    for key in &expired {
+        dbtx.remove_entry(key).await?;
+    }
+    dbtx.commit_tx().await?;
+
+    Ok(expired.len())
+}
+
+/// Walks the global database and the databases of all modules configured in
+/// `cfg`, returning all entries that are orphaned or can't be decoded. Keys of
+/// the returned entries are relative to the global keyspace.
+pub async fn check_database(
+    cfg: &ServerConfig,
+    db: &Database,
+    module_inits: &ModuleGenRegistry,
+) -> anyhow::Result<Vec<InvalidDbEntry>> {
+    let mut dbtx = db.begin_read_transaction().await;
+
+    let known_prefixes = DbKeyPrefix::iter().map(|p| p as u8).collect::<Vec<_>>();
+    let mut invalid = dbtx.find_orphaned_entries(&known_prefixes).await;
+    for table in DbKeyPrefix::iter() {
+        invalid.extend(match table {
+            DbKeyPrefix::AcceptedTransaction => {
+                dbtx.find_undecodable_entries::<AcceptedTransactionKey>()
+                    .await
+            }
+            DbKeyPrefix::DropPeer => dbtx.find_undecodable_entries::<DropPeerKey>().await,
+            DbKeyPrefix::RejectedTransaction => {
+                dbtx.find_undecodable_entries::<RejectedTransactionKey>()
+                    .await
+            }
+            DbKeyPrefix::EpochHistory => dbtx.find_undecodable_entries::<EpochHistoryKey>().await,
+            DbKeyPrefix::LastEpoch => dbtx.find_undecodable_entries::<LastEpochKey>().await,
+            DbKeyPrefix::ClientConfigSignature => {
+                dbtx.find_undecodable_entries::<ClientConfigSignatureKey>()
+                    .await
+            }
+            DbKeyPrefix::FederationMetaSignature => {
+                dbtx.find_undecodable_entries::<FederationMetaSignatureKey>()
+                    .await
+            }
+            DbKeyPrefix::UpgradeSignal => dbtx.find_undecodable_entries::<UpgradeSignalKey>().await,
+            DbKeyPrefix::ModuleVersionVote => {
+                dbtx.find_undecodable_entries::<ModuleVersionVoteKey>()
+                    .await
+            }
+            DbKeyPrefix::ActiveModuleVersion => {
+                dbtx.find_undecodable_entries::<ActiveModuleVersionKey>()
+                    .await
+            }
+            DbKeyPrefix::ModuleParamsVote => {
+                dbtx.find_undecodable_entries::<ModuleParamsVoteKey>().await
+            }
+            DbKeyPrefix::ScheduledModuleParams => {
+                dbtx.find_undecodable_entries::<ScheduledModuleParamsKey>()
+                    .await
+            }
+            DbKeyPrefix::DatabaseEncrypted => {
+                dbtx.find_undecodable_entries::<DatabaseEncryptedKey>()
+                    .await
+            }
+            DbKeyPrefix::DatabaseVersion => {
+                dbtx.find_undecodable_entries::<DatabaseVersionKey>().await
+            }
+            // Checked per module instance below
+            DbKeyPrefix::Module => vec![],
+        });
+    }
+
+    let mut module_prefixes = vec![];
+    for (module_id, kind) in cfg.iter_module_instances() {
+        let Some(init) = module_inits.get(kind) else {
+            anyhow::bail!("Detected configuration for unsupported module kind: {kind}")
+        };
+
+        let mut module_prefix = vec![MODULE_GLOBAL_PREFIX];
+        module_id.consensus_encode(&mut module_prefix)?;
+
+        let mut module_dbtx = dbtx.with_module_prefix(module_id);
+        let mut module_invalid = init.check_database(&mut module_dbtx).await;
+        module_invalid.extend(
+            module_dbtx
+                .find_undecodable_entries::<DatabaseVersionKey>()
+                .await,
+        );
+        invalid.extend(module_invalid.into_iter().map(|entry| InvalidDbEntry {
+            key: [module_prefix.as_slice(), entry.key.as_slice()].concat(),
+            ..entry
+        }));
+
+        module_prefixes.push(module_prefix);
+    }
+
+    // Module data of instances that aren't configured (anymore)
+    invalid.extend(
+        dbtx.raw_find_by_prefix(&[MODULE_GLOBAL_PREFIX])
+            .await
+            .filter(|(key, _)| {
+                future::ready(!module_prefixes.iter().any(|prefix| key.starts_with(prefix)))
+            })
+            .map(|(key, _)| InvalidDbEntry {
+                key,
+                reason: InvalidDbEntryReason::Orphaned,
+            })
+            .collect::<Vec<_>>()
+            .await,
+    );
+
+    Ok(invalid)
+}
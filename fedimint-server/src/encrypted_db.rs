@@ -0,0 +1,247 @@
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use aead::{decrypt_with_aad, encrypt_with_aad, LessSafeKey};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use fedimint_api::db::{IDatabase, IDatabaseTransaction, PrefixStream};
+use futures::StreamExt;
+
+/// Context mixed into the password-derived key so the database key differs
+/// from the one used for `private.encrypt`, see [`aead::get_key_with_context`]
+pub const DB_KEY_CONTEXT: &[u8] = b"fedimint-database";
+
+/// Wraps a database backend, encrypting all values before they are handed to
+/// it and decrypting them when read back.
+///
+/// Keys stay in plaintext since backends need them for prefix lookups, so
+/// anything encoded in a key (e.g. note nonces, contract ids) is still visible
+/// to someone with access to the storage. Each value is authenticated together
+/// with its key so entries can't be swapped.
+///
+/// Values of an existing unencrypted database can't be read through this
+/// wrapper, encryption has to be enabled on a fresh database.
+pub struct EncryptedDb<D> {
+    inner: D,
+    key: Arc<LessSafeKey>,
+}
+
+impl<D> EncryptedDb<D> {
+    pub fn new(inner: D, key: LessSafeKey) -> EncryptedDb<D> {
+        EncryptedDb {
+            inner,
+            key: Arc::new(key),
+        }
+    }
+}
+
+impl<D: Debug> Debug for EncryptedDb<D> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedDb")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<D: IDatabase> IDatabase for EncryptedDb<D> {
+    async fn begin_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        Box::new(EncryptedDbTransaction::new(
+            self.inner.begin_transaction().await,
+            self.key.clone(),
+        ))
+    }
+
+    async fn begin_read_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        Box::new(EncryptedDbTransaction::new(
+            self.inner.begin_read_transaction().await,
+            self.key.clone(),
+        ))
+    }
+
+    /// The copy stays encrypted with the same key
+    async fn backup(&self) -> Result<PathBuf> {
+        self.inner.backup().await
+    }
+}
+
+/// Transaction of an [`EncryptedDb`], can also be used to wrap a transaction
+/// obtained directly from a backend (e.g. a read-only RocksDB)
+pub struct EncryptedDbTransaction<'a> {
+    inner: Box<dyn IDatabaseTransaction<'a>>,
+    key: Arc<LessSafeKey>,
+}
+
+impl<'a> EncryptedDbTransaction<'a> {
+    pub fn new(
+        inner: Box<dyn IDatabaseTransaction<'a>>,
+        key: Arc<LessSafeKey>,
+    ) -> EncryptedDbTransaction<'a> {
+        EncryptedDbTransaction { inner, key }
+    }
+}
+
+fn decrypt_value(key: &LessSafeKey, db_key: &[u8], mut value: Vec<u8>) -> Result<Vec<u8>> {
+    Ok(decrypt_with_aad(&mut value, key, db_key)?.to_vec())
+}
+
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for EncryptedDbTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let encrypted = encrypt_with_aad(value, &self.key, key)?;
+        self.inner
+            .raw_insert_bytes(key, encrypted)
+            .await?
+            .map(|old| decrypt_value(&self.key, key, old))
+            .transpose()
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.inner
+            .raw_get_bytes(key)
+            .await?
+            .map(|value| decrypt_value(&self.key, key, value))
+            .transpose()
+    }
+
+    async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.inner
+            .raw_remove_entry(key)
+            .await?
+            .map(|value| decrypt_value(&self.key, key, value))
+            .transpose()
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixStream<'_> {
+        let key = self.key.clone();
+        let stream = self.inner.raw_find_by_prefix(key_prefix).await;
+        Box::pin(stream.map(move |(db_key, value)| {
+            let value = value
+                .and_then(|value| decrypt_value(&key, &db_key, value))
+                .context("Database value could not be decrypted, wrong password?");
+            (db_key, value)
+        }))
+    }
+
+    async fn raw_remove_by_prefix(&mut self, key_prefix: &[u8]) -> Result<()> {
+        self.inner.raw_remove_by_prefix(key_prefix).await
+    }
+
+    async fn commit_tx(self: Box<Self>) -> Result<()> {
+        self.inner.commit_tx().await
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        self.inner.rollback_tx_to_savepoint().await
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        self.inner.set_tx_savepoint().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use aead::{LessSafeKey, UnboundKey};
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::{Database, IDatabase, IDatabaseTransaction};
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use futures::StreamExt;
+
+    use super::{EncryptedDb, EncryptedDbTransaction};
+    use crate::db::check_database_encryption;
+
+    fn key(byte: u8) -> LessSafeKey {
+        let key = UnboundKey::new(&ring::aead::CHACHA20_POLY1305, &[byte; 32]).unwrap();
+        LessSafeKey::new(key)
+    }
+
+    fn open_temp_db() -> Database {
+        Database::new(
+            EncryptedDb::new(MemDatabase::new(), key(42)),
+            ModuleDecoderRegistry::default(),
+        )
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_insert_elements() {
+        fedimint_api::db::verify_insert_elements(open_temp_db()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_remove_existing() {
+        fedimint_api::db::verify_remove_existing(open_temp_db()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_read_own_writes() {
+        fedimint_api::db::verify_read_own_writes(open_temp_db()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_prefix() {
+        fedimint_api::db::verify_find_by_prefix(open_temp_db()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_snapshot_read() {
+        fedimint_api::db::verify_snapshot_read(open_temp_db()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_commit() {
+        fedimint_api::db::verify_commit(open_temp_db()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_remove_by_prefix() {
+        fedimint_api::db::verify_remove_by_prefix(open_temp_db()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_module_dbtx() {
+        fedimint_api::db::verify_module_prefix(open_temp_db()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_encrypted_db_is_marked() {
+        let db = open_temp_db();
+        check_database_encryption(&db, true).await.unwrap();
+        check_database_encryption(&db, true).await.unwrap();
+        assert!(check_database_encryption(&db, false).await.is_err());
+
+        let plain_db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        check_database_encryption(&plain_db, false).await.unwrap();
+        assert!(plain_db
+            .begin_transaction()
+            .await
+            .raw_find_by_prefix(&[])
+            .await
+            .next()
+            .await
+            .is_none());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_wrong_key_returns_errors() {
+        let mem_db = MemDatabase::new();
+        let mut dbtx: Box<dyn IDatabaseTransaction<'_>> = Box::new(EncryptedDbTransaction::new(
+            mem_db.begin_transaction().await,
+            Arc::new(key(42)),
+        ));
+        dbtx.raw_insert_bytes(&[0x01], vec![1, 2, 3]).await.unwrap();
+        dbtx.commit_tx().await.unwrap();
+
+        let mut dbtx =
+            EncryptedDbTransaction::new(mem_db.begin_transaction().await, Arc::new(key(43)));
+        assert!(dbtx.raw_get_bytes(&[0x01]).await.is_err());
+
+        let entries = dbtx.raw_find_by_prefix(&[]).await.collect::<Vec<_>>().await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, vec![0x01]);
+        assert!(entries[0].1.is_err());
+    }
+}
@@ -32,7 +32,7 @@ use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::Receiver;
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::{info, warn};
+use tracing::{info, instrument, warn};
 
 use crate::consensus::{
     ConsensusProposal, FedimintConsensus, HbbftConsensusOutcome, HbbftSerdeConsensusOutcome,
@@ -45,12 +45,21 @@ use crate::net::connect::{Connector, TlsTcpConnector};
 use crate::net::peers::PeerSlice;
 use crate::net::peers::{PeerConnector, ReconnectPeerConnections};
 
+/// Alerting the operator about problems of the guardian
+pub mod alerts;
+
 /// The actual implementation of the federated mint
 pub mod consensus;
 
 /// Provides interfaces for ACID-compliant data store backends
 pub mod db;
 
+/// Encryption-at-rest for database backends
+pub mod encrypted_db;
+
+/// Database wrapper recording per-module and per-prefix operation metrics
+pub mod metrics_db;
+
 /// Networking for mint-to-mint and client-to-mint communiccation
 pub mod net;
 
@@ -63,6 +72,9 @@ pub mod multiplexed;
 /// Logging targets and helpers
 pub mod logging;
 
+/// Deterministic simulation of a federation's consensus for tests
+pub mod simulation;
+
 type PeerMessage = (PeerId, EpochMessage);
 
 /// how many epochs ahead of consensus to rejoin
@@ -104,6 +116,10 @@ pub struct FedimintServer {
 
 impl FedimintServer {
     /// Start all the components of the mint and plug them together
+    ///
+    /// Everything runs in a span with our peer id, so logs of several
+    /// guardians can be told apart.
+    #[instrument(name = "Guardian", skip_all, fields(peer_id = %cfg.local.identity))]
     pub async fn run(
         cfg: ServerConfig,
         consensus: FedimintConsensus,
@@ -150,8 +166,9 @@ impl FedimintServer {
         decoders: ModuleDecoderRegistry,
         task_group: &mut TaskGroup,
     ) -> Self {
-        let connector: PeerConnector<EpochMessage> =
-            TlsTcpConnector::new(cfg.tls_config()).into_dyn();
+        let connector: PeerConnector<EpochMessage> = TlsTcpConnector::new(cfg.tls_config())
+            .with_socks5_proxy(cfg.local.socks5_proxy.clone())
+            .into_dyn();
 
         Self::new_with(
             cfg.clone(),
@@ -172,23 +189,31 @@ impl FedimintServer {
         decoders: ModuleDecoderRegistry,
         task_group: &mut TaskGroup,
     ) -> Self {
-        cfg.validate_config(&cfg.local.identity, &consensus.module_inits)
-            .expect("invalid config");
+        let connections = ReconnectPeerConnections::new_with_status(
+            cfg.network_config(),
+            connector,
+            consensus.peer_status.clone(),
+            task_group,
+        )
+        .await
+        .into_dyn();
 
-        let connections =
-            ReconnectPeerConnections::new(cfg.network_config(), connector, task_group)
-                .await
-                .into_dyn();
+        Self::new_with_connections(cfg, consensus, tx_receiver, connections, decoders)
+    }
 
-        let net_info = NetworkInfo::new(
-            cfg.local.identity,
-            cfg.private.hbbft_sks.inner().clone(),
-            cfg.consensus.hbbft_pk_set.clone(),
-            cfg.local.p2p.keys().copied(),
-        );
+    /// Like [`Self::new_with`], but with already established `connections` to
+    /// the peers, e.g. of a [`simulation::SimulatedNetwork`]
+    pub fn new_with_connections(
+        cfg: ServerConfig,
+        consensus: FedimintConsensus,
+        tx_receiver: Receiver<Transaction>,
+        connections: PeerConnections<EpochMessage>,
+        decoders: ModuleDecoderRegistry,
+    ) -> Self {
+        cfg.validate_config(&cfg.local.identity, &consensus.module_inits)
+            .expect("invalid config");
 
-        let hbbft: HoneyBadger<Vec<SerdeConsensusItem>, _> =
-            HoneyBadger::builder(Arc::new(net_info)).build();
+        let hbbft = Self::new_hbbft(&cfg);
 
         let api_endpoints = cfg
             .consensus
@@ -213,6 +238,17 @@ impl FedimintServer {
         }
     }
 
+    fn new_hbbft(cfg: &ServerConfig) -> HoneyBadger<Vec<SerdeConsensusItem>, PeerId> {
+        let net_info = NetworkInfo::new(
+            cfg.local.identity,
+            cfg.private.hbbft_sks.inner().clone(),
+            cfg.consensus.hbbft_pk_set.clone(),
+            cfg.local.p2p.keys().copied(),
+        );
+
+        HoneyBadger::builder(Arc::new(net_info)).build()
+    }
+
     /// Loop `run_conensus_epoch` until shut down
     async fn run_consensus(mut self, task_handle: TaskHandle) {
         // FIXME: reusing the wallet CI leads to duplicate randomness beacons, not a
@@ -239,6 +275,23 @@ impl FedimintServer {
                     .await
                     .expect("failed to process epoch");
             }
+
+            if let Some(epoch) = &self.last_processed_epoch {
+                if consensus.is_upgrade_epoch(epoch) {
+                    info!(
+                        epoch = epoch.outcome.epoch,
+                        "Guardians signaled an upgrade, halting consensus. Restart with the new version to continue."
+                    );
+                    break;
+                }
+                if consensus.is_module_params_epoch(epoch).await {
+                    info!(
+                        epoch = epoch.outcome.epoch,
+                        "Module params change with the next epoch, halting consensus. Restart to apply them."
+                    );
+                    break;
+                }
+            }
         }
 
         info!("Consensus task shut down");
@@ -261,6 +314,15 @@ impl FedimintServer {
         self.request_rejoin(1).await;
     }
 
+    /// Discards the state of unfinished epochs and starts consensus again from
+    /// the last epoch in the database, like a restart of the guardian would
+    pub async fn restart_consensus(&mut self) {
+        self.hbbft = Self::new_hbbft(&self.cfg);
+        self.run_empty_epochs = 0;
+        self.last_processed_epoch = None;
+        self.start_consensus().await;
+    }
+
     /// Returns the next epoch that we need to process, based on our saved
     /// history
     fn next_epoch_to_process(&self) -> u64 {
@@ -296,9 +358,7 @@ impl FedimintServer {
                             .map(|(peer, items)| (*peer, items.clone()))
                             .collect(),
                         last_outcome.epoch,
-                        self.last_processed_epoch
-                            .as_ref()
-                            .and_then(|epoch| epoch.outcome.consensus_hash().ok()),
+                        self.last_processed_epoch.as_ref().map(|epoch| epoch.hash),
                         None,
                         true,
                     )
@@ -339,6 +399,9 @@ impl FedimintServer {
                             rejected_txs.clone(),
                         )
                         .await;
+                    self.consensus
+                        .consensus_progress
+                        .epoch_processed(epoch.outcome.epoch);
                     self.last_processed_epoch = Some(epoch);
                 }
             }
@@ -364,6 +427,7 @@ impl FedimintServer {
             }
             self.save_txs_to_consensus_cache();
             let proposal = proposal.await;
+            self.consensus.consensus_progress.epoch_started();
             let epoch = self.hbbft.epoch();
             self.hbbft.skip_to_epoch(epoch + 1);
             return Ok(vec![HbbftConsensusOutcome {
@@ -385,6 +449,7 @@ impl FedimintServer {
         self.save_txs_to_consensus_cache();
 
         let proposal = proposal.await;
+        self.consensus.consensus_progress.epoch_started();
         for peer in proposal.drop_peers.iter() {
             self.connections.ban_peer(*peer).await;
         }
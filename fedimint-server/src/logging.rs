@@ -4,3 +4,28 @@ pub const LOG_NET_PEER: &str = "net::peer";
 pub const LOG_NET_PEER_DKG: &str = "net::peer::dkg";
 pub const LOG_NET_API: &str = "net::api";
 pub const LOG_DB: &str = "db";
+
+/// Id of a single API request, logged with everything done on its behalf
+///
+/// A transaction submitted by the request keeps the id until consensus
+/// processed it, so log aggregation can follow it from the API through the
+/// epoch that included it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CorrelationId(u64);
+
+impl CorrelationId {
+    pub fn random() -> Self {
+        CorrelationId(rand::random())
+    }
+}
+
+impl std::fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+tokio::task_local! {
+    /// Correlation id of the API request being handled
+    pub static CORRELATION_ID: CorrelationId;
+}
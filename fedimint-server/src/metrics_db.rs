@@ -0,0 +1,247 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use fedimint_api::core::ModuleInstanceId;
+use fedimint_api::db::{IDatabase, IDatabaseTransaction, PrefixStream, MODULE_GLOBAL_PREFIX};
+use fedimint_metrics::{
+    histogram_opts, opts, HistogramVec, IntCounterVec, DURATION_BUCKETS, REGISTRY,
+};
+use once_cell::sync::Lazy;
+
+static DB_OPERATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    let counter = IntCounterVec::new(
+        opts!("db_operations_total", "Number of database operations"),
+        &["operation", "module", "prefix"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(counter.clone())).unwrap();
+    counter
+});
+
+static DB_OPERATION_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    let histogram = HistogramVec::new(
+        histogram_opts!(
+            "db_operation_duration_seconds",
+            "Duration of database operations",
+            DURATION_BUCKETS.to_vec()
+        ),
+        &["operation", "module", "prefix"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(histogram.clone())).unwrap();
+    histogram
+});
+
+static DB_WRITTEN_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    let counter = IntCounterVec::new(
+        opts!(
+            "db_written_bytes_total",
+            "Size of the keys and values inserted into the database"
+        ),
+        &["module", "prefix"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(counter.clone())).unwrap();
+    counter
+});
+
+static DB_COMMIT_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    let histogram = HistogramVec::new(
+        histogram_opts!(
+            "db_commit_duration_seconds",
+            "Duration of database commits, recorded for every module the transaction wrote to",
+            DURATION_BUCKETS.to_vec()
+        ),
+        &["module"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(histogram.clone())).unwrap();
+    histogram
+});
+
+/// Labels identifying the part of the database a (possibly partial) key
+/// belongs to: the module instance (or `global`) and the key prefix inside of
+/// its keyspace
+fn key_labels(key: &[u8]) -> (String, String) {
+    let format_prefix = |prefix: Option<&u8>| {
+        prefix.map_or_else(|| "none".to_string(), |prefix| format!("0x{prefix:02x}"))
+    };
+
+    match key {
+        [MODULE_GLOBAL_PREFIX, id_0, id_1, rest @ ..] => {
+            let module_instance_id = ModuleInstanceId::from_le_bytes([*id_0, *id_1]);
+            (module_instance_id.to_string(), format_prefix(rest.first()))
+        }
+        _ => ("global".to_string(), format_prefix(key.first())),
+    }
+}
+
+async fn observe<F: Future>(operation: &str, key: &[u8], f: F) -> F::Output {
+    let (module, prefix) = key_labels(key);
+    let labels = [operation, module.as_str(), prefix.as_str()];
+
+    let start = Instant::now();
+    let result = f.await;
+    DB_OPERATION_DURATION
+        .with_label_values(&labels)
+        .observe(start.elapsed().as_secs_f64());
+    DB_OPERATIONS.with_label_values(&labels).inc();
+    result
+}
+
+/// Wraps a database backend, recording the number, duration, and written bytes
+/// of operations per module and key prefix as well as commit durations in
+/// [`fedimint_metrics::REGISTRY`]
+#[derive(Debug)]
+pub struct MetricsDb<D>(D);
+
+impl<D> MetricsDb<D> {
+    pub fn new(inner: D) -> MetricsDb<D> {
+        MetricsDb(inner)
+    }
+}
+
+#[async_trait]
+impl<D: IDatabase> IDatabase for MetricsDb<D> {
+    async fn begin_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        Box::new(MetricsDbTransaction {
+            inner: self.0.begin_transaction().await,
+            written_modules: BTreeSet::new(),
+        })
+    }
+
+    async fn begin_read_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        Box::new(MetricsDbTransaction {
+            inner: self.0.begin_read_transaction().await,
+            written_modules: BTreeSet::new(),
+        })
+    }
+
+    async fn backup(&self) -> Result<PathBuf> {
+        self.0.backup().await
+    }
+}
+
+struct MetricsDbTransaction<'a> {
+    inner: Box<dyn IDatabaseTransaction<'a>>,
+    /// Module labels of all keys written in this transaction
+    written_modules: BTreeSet<String>,
+}
+
+impl<'a> MetricsDbTransaction<'a> {
+    fn record_write(&mut self, key: &[u8], bytes: usize) {
+        let (module, prefix) = key_labels(key);
+        DB_WRITTEN_BYTES
+            .with_label_values(&[module.as_str(), prefix.as_str()])
+            .inc_by(bytes as u64);
+        self.written_modules.insert(module);
+    }
+}
+
+#[async_trait]
+impl<'a> IDatabaseTransaction<'a> for MetricsDbTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        self.record_write(key, key.len() + value.len());
+        observe("insert", key, self.inner.raw_insert_bytes(key, value)).await
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        observe("get", key, self.inner.raw_get_bytes(key)).await
+    }
+
+    async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.record_write(key, 0);
+        observe("remove", key, self.inner.raw_remove_entry(key)).await
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> PrefixStream<'_> {
+        observe(
+            "find_by_prefix",
+            key_prefix,
+            self.inner.raw_find_by_prefix(key_prefix),
+        )
+        .await
+    }
+
+    async fn raw_remove_by_prefix(&mut self, key_prefix: &[u8]) -> Result<()> {
+        self.record_write(key_prefix, 0);
+        observe(
+            "remove_by_prefix",
+            key_prefix,
+            self.inner.raw_remove_by_prefix(key_prefix),
+        )
+        .await
+    }
+
+    async fn commit_tx(self: Box<Self>) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.commit_tx().await;
+        let elapsed = start.elapsed().as_secs_f64();
+        for module in &self.written_modules {
+            DB_COMMIT_DURATION
+                .with_label_values(&[module.as_str()])
+                .observe(elapsed);
+        }
+        result
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        self.inner.rollback_tx_to_savepoint().await
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        self.inner.set_tx_savepoint().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::Database;
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+
+    use super::{key_labels, MetricsDb};
+
+    fn open_temp_db() -> Database {
+        Database::new(
+            MetricsDb::new(MemDatabase::new()),
+            ModuleDecoderRegistry::default(),
+        )
+    }
+
+    #[test]
+    fn test_key_labels() {
+        assert_eq!(
+            key_labels(&[0x05, 0x01]),
+            ("global".to_string(), "0x05".to_string())
+        );
+        assert_eq!(
+            key_labels(&[0xff, 0x02, 0x00, 0x10, 0x01]),
+            ("2".to_string(), "0x10".to_string())
+        );
+        assert_eq!(
+            key_labels(&[0xff, 0x02, 0x00]),
+            ("2".to_string(), "none".to_string())
+        );
+        assert_eq!(key_labels(&[]), ("global".to_string(), "none".to_string()));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_insert_elements() {
+        fedimint_api::db::verify_insert_elements(open_temp_db()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_find_by_prefix() {
+        fedimint_api::db::verify_find_by_prefix(open_temp_db()).await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_module_dbtx() {
+        fedimint_api::db::verify_module_prefix(open_temp_db()).await;
+    }
+}
@@ -1,5 +1,7 @@
 //! Implements the client API through which users interact with the federation
+use std::collections::BTreeMap;
 use std::fmt::Formatter;
+use std::future::Future;
 use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 use std::time::Duration;
@@ -7,13 +9,19 @@ use std::time::Duration;
 use anyhow::Context;
 use fedimint_api::config::ConfigResponse;
 use fedimint_api::core::ModuleInstanceId;
+use fedimint_api::module::audit::AuditSummary;
+use fedimint_api::module::ModuleConsensusVersion;
 use fedimint_api::server::DynServerModule;
 use fedimint_api::{
     module::{api_endpoint, ApiEndpoint, ApiError},
     task::TaskHandle,
-    TransactionId,
+    PeerId, TransactionId,
 };
-use fedimint_core::epoch::SerdeEpochHistory;
+use fedimint_core::admin::{ApiAuth, AuthenticatedRequest, GuardianStatus, PeerConnectionStatus};
+use fedimint_core::epoch::{
+    ModuleParamsVote, ModuleVersionVote, SerdeEpochHistory, TransactionProof,
+};
+use fedimint_core::meta::SignedFederationMeta;
 use fedimint_core::outcome::TransactionStatus;
 use futures::FutureExt;
 use jsonrpsee::{
@@ -21,11 +29,11 @@ use jsonrpsee::{
     types::{error::CallError, ErrorObject},
     RpcModule,
 };
-use tracing::{debug, error};
+use tracing::{debug, error, info_span, Instrument};
 
 use crate::config::ServerConfig;
 use crate::consensus::FedimintConsensus;
-use crate::logging::LOG_NET_API;
+use crate::logging::{CorrelationId, CORRELATION_ID, LOG_NET_API};
 use crate::transaction::SerdeTransaction;
 
 /// A state of fedimint server passed to each rpc handler callback
@@ -103,38 +111,61 @@ fn attach_endpoints(
         let handler: &'static _ = Box::leak(endpoint.handler);
 
         rpc_module
-            .register_async_method(path, move |params, state| async move {
-                let params = params.one::<serde_json::Value>()?;
-                let fedimint = &state.fedimint;
-                let dbtx = fedimint.database_transaction().await;
-                // Using AssertUnwindSafe here is far from ideal. In theory this means we could
-                // end up with an inconsistent state in theory. In practice most API functions
-                // are only reading and the few that do write anything are atomic. Lastly, this
-                // is only the last line of defense
-                AssertUnwindSafe((handler)(fedimint, dbtx, params, module_instance_id))
-                    .catch_unwind()
-                    .await
-                    .map_err(|_| {
-                        error!(
-                            target: LOG_NET_API,
-                            path, "API handler panicked, DO NOT IGNORE, FIX IT!!!"
-                        );
-                        jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
-                            500,
-                            "API handler panicked",
-                            None::<()>,
-                        )))
-                    })?
-                    .map_err(|e| {
-                        jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
-                            e.code, e.message, None::<()>,
-                        )))
-                    })
+            .register_async_method(path, move |params, state| {
+                let peer_id = state.fedimint.cfg.local.identity;
+                in_request_context(path, peer_id, module_instance_id, async move {
+                    let params = params.one::<serde_json::Value>()?;
+                    let fedimint = &state.fedimint;
+                    let dbtx = fedimint.database_transaction().await;
+                    // Using AssertUnwindSafe here is far from ideal. In theory this means we could
+                    // end up with an inconsistent state in theory. In practice most API functions
+                    // are only reading and the few that do write anything are atomic. Lastly, this
+                    // is only the last line of defense
+                    AssertUnwindSafe((handler)(fedimint, dbtx, params, module_instance_id))
+                        .catch_unwind()
+                        .await
+                        .map_err(|_| {
+                            error!(
+                                target: LOG_NET_API,
+                                path, "API handler panicked, DO NOT IGNORE, FIX IT!!!"
+                            );
+                            jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
+                                500,
+                                "API handler panicked",
+                                None::<()>,
+                            )))
+                        })?
+                        .map_err(|e| {
+                            jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
+                                e.code, e.message, None::<()>,
+                            )))
+                        })
+                })
             })
             .expect("Failed to register async method");
     }
 }
 
+/// Runs the handler of an API request in a span with a new correlation id,
+/// which transactions submitted by it keep until they are processed
+fn in_request_context<F: Future>(
+    path: &'static str,
+    peer_id: PeerId,
+    module_instance_id: Option<ModuleInstanceId>,
+    handler: F,
+) -> impl Future<Output = F::Output> {
+    let correlation_id = CorrelationId::random();
+    let span = info_span!(
+        target: LOG_NET_API,
+        "API request",
+        path,
+        %peer_id,
+        %correlation_id,
+        module_instance_id
+    );
+    CORRELATION_ID.scope(correlation_id, handler.instrument(span))
+}
+
 fn attach_endpoints_erased(
     rpc_module: &mut RpcModule<RpcHandlerCtx>,
     module_instance: ModuleInstanceId,
@@ -148,46 +179,62 @@ fn attach_endpoints_erased(
         let path: &'static _ =
             Box::leak(format!("/module/{}{}", module_instance, endpoint.path).into_boxed_str());
         let handler: &'static _ = Box::leak(endpoint.handler);
+        let is_admin = endpoint.path.starts_with("/admin/");
 
         rpc_module
-            .register_async_method(path, move |params, state| async move {
-                // Hack to avoid Sync/Send issues
-                let params = params.one::<serde_json::Value>()?;
-                let fedimint = &state.fedimint;
-                let dbtx = fedimint.database_transaction().await;
-                // Using AssertUnwindSafe here is far from ideal. In theory this means we could
-                // end up with an inconsistent state in theory. In practice most API functions
-                // are only reading and the few that do write anything are atomic. Lastly, this
-                // is only the last line of defense
-                AssertUnwindSafe((handler)(
-                    fedimint.modules.get_expect(module_instance),
-                    dbtx,
-                    params,
-                    Some(module_instance),
-                ))
-                .catch_unwind()
-                .await
-                .map_err(|_| {
-                    error!(
-                        target: LOG_NET_API,
-                        path, "API handler panicked, DO NOT IGNORE, FIX IT!!!"
-                    );
-                    jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
-                        500,
-                        "API handler panicked",
-                        None::<()>,
-                    )))
-                })?
-                .map_err(|e| {
-                    jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
-                        e.code, e.message, None::<()>,
-                    )))
+            .register_async_method(path, move |params, state| {
+                let peer_id = state.fedimint.cfg.local.identity;
+                in_request_context(path, peer_id, Some(module_instance), async move {
+                    // Hack to avoid Sync/Send issues
+                    let params = params.one::<serde_json::Value>()?;
+                    let fedimint = &state.fedimint;
+                    let params = if is_admin {
+                        let request = serde_json::from_value::<
+                            AuthenticatedRequest<serde_json::Value>,
+                        >(params)
+                        .map_err(|e| api_error(ApiError::bad_request(e.to_string())))?;
+                        check_auth(fedimint, &request.auth).map_err(api_error)?;
+                        request.params
+                    } else {
+                        params
+                    };
+                    let dbtx = fedimint.database_transaction().await;
+                    // Using AssertUnwindSafe here is far from ideal. In theory this means we could
+                    // end up with an inconsistent state in theory. In practice most API functions
+                    // are only reading and the few that do write anything are atomic. Lastly, this
+                    // is only the last line of defense
+                    AssertUnwindSafe((handler)(
+                        fedimint.modules.get_expect(module_instance),
+                        dbtx,
+                        params,
+                        Some(module_instance),
+                    ))
+                    .catch_unwind()
+                    .await
+                    .map_err(|_| {
+                        error!(
+                            target: LOG_NET_API,
+                            path, "API handler panicked, DO NOT IGNORE, FIX IT!!!"
+                        );
+                        jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
+                            500,
+                            "API handler panicked",
+                            None::<()>,
+                        )))
+                    })?
+                    .map_err(api_error)
                 })
             })
             .expect("Failed to register async method");
     }
 }
 
+fn api_error(e: ApiError) -> jsonrpsee::core::Error {
+    jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
+        e.code, e.message, None::<()>,
+    )))
+}
+
 fn server_endpoints() -> Vec<ApiEndpoint<FedimintConsensus>> {
     vec![
         api_endpoint! {
@@ -215,6 +262,12 @@ fn server_endpoints() -> Vec<ApiEndpoint<FedimintConsensus>> {
                 Ok(tx_status)
             }
         },
+        api_endpoint! {
+            "/fetch_transaction_proof",
+            async |fedimint: &FedimintConsensus, _dbtx, tx_hash: TransactionId| -> TransactionProof {
+                fedimint.transaction_proof(tx_hash).await.ok_or_else(|| ApiError::not_found(String::from("transaction proof not found")))
+            }
+        },
         api_endpoint! {
             "/fetch_epoch_history",
             async |fedimint: &FedimintConsensus, _dbtx, epoch: u64| -> SerdeEpochHistory {
@@ -228,11 +281,100 @@ fn server_endpoints() -> Vec<ApiEndpoint<FedimintConsensus>> {
                 Ok(fedimint.get_epoch_count().await)
             }
         },
+        api_endpoint! {
+            "/audit",
+            async |fedimint: &FedimintConsensus, _dbtx, _v: ()| -> AuditSummary {
+                Ok(fedimint.audit().await.into())
+            }
+        },
         api_endpoint! {
             "/config",
             async |fedimint: &FedimintConsensus, dbtx, _v: ()| -> ConfigResponse {
                 Ok(fedimint.get_config_with_sig(dbtx).await)
             }
         },
+        api_endpoint! {
+            "/meta",
+            async |fedimint: &FedimintConsensus, dbtx, _v: ()| -> SignedFederationMeta {
+                Ok(fedimint.get_federation_meta(dbtx).await)
+            }
+        },
+        api_endpoint! {
+            "/module_versions",
+            async |fedimint: &FedimintConsensus, _dbtx, _v: ()| -> BTreeMap<ModuleInstanceId, ModuleConsensusVersion> {
+                Ok(fedimint.module_versions().await)
+            }
+        },
+        api_endpoint! {
+            "/admin/status",
+            async |fedimint: &FedimintConsensus, _dbtx, request: AuthenticatedRequest<()>| -> GuardianStatus {
+                check_auth(fedimint, &request.auth)?;
+                Ok(GuardianStatus {
+                    our_peer_id: fedimint.cfg.local.identity,
+                    code_version: fedimint.cfg.consensus.code_version.clone(),
+                    epoch_count: fedimint.get_epoch_count().await,
+                    peers: fedimint.peer_status.statuses(),
+                    upgrade_signaled: fedimint.upgrade_signaled().await,
+                    module_version_votes: fedimint.module_version_votes().await,
+                    module_params_votes: fedimint.module_params_votes().await,
+                })
+            }
+        },
+        api_endpoint! {
+            "/admin/connected_peers",
+            async |fedimint: &FedimintConsensus, _dbtx, request: AuthenticatedRequest<()>| -> BTreeMap<PeerId, PeerConnectionStatus> {
+                check_auth(fedimint, &request.auth)?;
+                Ok(fedimint.peer_status.statuses())
+            }
+        },
+        api_endpoint! {
+            "/admin/backup_db",
+            async |fedimint: &FedimintConsensus, _dbtx, request: AuthenticatedRequest<()>| -> String {
+                check_auth(fedimint, &request.auth)?;
+                let path = fedimint.db.backup().await.map_err(|e| ApiError::new(500, e.to_string()))?;
+                Ok(path.display().to_string())
+            }
+        },
+        api_endpoint! {
+            "/admin/signal_upgrade",
+            async |fedimint: &FedimintConsensus, _dbtx, request: AuthenticatedRequest<()>| -> () {
+                check_auth(fedimint, &request.auth)?;
+                fedimint.signal_upgrade().await;
+                Ok(())
+            }
+        },
+        api_endpoint! {
+            "/admin/vote_module_version",
+            async |fedimint: &FedimintConsensus, _dbtx, request: AuthenticatedRequest<ModuleVersionVote>| -> () {
+                check_auth(fedimint, &request.auth)?;
+                let vote = request.params;
+                fedimint
+                    .vote_module_version(vote.module_instance_id, vote.version)
+                    .await
+                    .map_err(|e| ApiError::bad_request(e.to_string()))
+            }
+        },
+        api_endpoint! {
+            "/admin/vote_module_params",
+            async |fedimint: &FedimintConsensus, _dbtx, request: AuthenticatedRequest<ModuleParamsVote>| -> ModuleParamsVote {
+                check_auth(fedimint, &request.auth)?;
+                fedimint
+                    .vote_module_params(request.params)
+                    .await
+                    .map_err(|e| ApiError::bad_request(e.to_string()))
+            }
+        },
     ]
 }
+
+/// Checks the password sent along with a request to an `/admin/...` endpoint
+fn check_auth(fedimint: &FedimintConsensus, auth: &ApiAuth) -> Result<(), ApiError> {
+    match &fedimint.cfg.private.api_auth {
+        Some(api_auth) if api_auth == auth => Ok(()),
+        Some(_) => Err(ApiError::unauthorized()),
+        None => Err(ApiError::new(
+            403,
+            "The admin API is disabled since no password is set".to_string(),
+        )),
+    }
+}
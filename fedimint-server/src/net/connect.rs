@@ -17,6 +17,7 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
 use tokio_rustls::rustls::RootCertStore;
 use tokio_rustls::{rustls, TlsAcceptor, TlsConnector, TlsStream};
+use tokio_socks::tcp::Socks5Stream;
 use url::Url;
 
 use crate::net::framed::{AnyFramedTransport, BidiFramed, FramedTransport};
@@ -66,6 +67,8 @@ pub struct TlsTcpConnector {
     /// understands
     cert_store: RootCertStore,
     peer_names: HashMap<PeerId, String>,
+    /// `host:port` of a SOCKS5 proxy to dial peers through
+    socks5_proxy: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -96,6 +99,24 @@ impl TlsTcpConnector {
             peer_certs: Arc::new(PeerCertStore::new(cfg.peer_certs)),
             cert_store,
             peer_names: cfg.peer_names,
+            socks5_proxy: None,
+        }
+    }
+
+    /// Dials peers through the SOCKS5 proxy at `host:port` if one is given,
+    /// incoming connections are still accepted directly
+    pub fn with_socks5_proxy(mut self, socks5_proxy: Option<String>) -> TlsTcpConnector {
+        self.socks5_proxy = socks5_proxy;
+        self
+    }
+
+    async fn dial(&self, destination: Url) -> Result<TcpStream, anyhow::Error> {
+        let host_port = parse_host_port(destination)?;
+        match &self.socks5_proxy {
+            Some(proxy) => Ok(Socks5Stream::connect(proxy.as_str(), host_port)
+                .await?
+                .into_inner()),
+            None => Ok(TcpStream::connect(host_port).await?),
         }
     }
 }
@@ -176,10 +197,7 @@ where
 
         let connector = TlsConnector::from(Arc::new(cfg));
         let tls_conn = connector
-            .connect(
-                fake_domain,
-                TcpStream::connect(parse_host_port(destination)?).await?,
-            )
+            .connect(fake_domain, self.dial(destination).await?)
             .await?;
 
         let (_, tls_session) = tls_conn.get_ref();
@@ -2,7 +2,7 @@
 //! one
 use std::convert::TryInto;
 use std::fmt::Debug;
-use std::io::{Read, Write};
+use std::io::Write;
 use std::marker::PhantomData;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -180,6 +180,12 @@ impl<T> BincodeCodec<T> {
     }
 }
 
+impl<T> Default for BincodeCodec<T> {
+    fn default() -> Self {
+        BincodeCodec::new()
+    }
+}
+
 impl<T> tokio_util::codec::Encoder<T> for BincodeCodec<T>
 where
     T: serde::Serialize + Debug,
@@ -224,18 +230,24 @@ where
         }
 
         let length = u64::from_le_bytes(src[0..8].try_into().expect("correct length"));
-        if src.len() < (length as usize) + 8 {
+        // The length is controlled by the peer and must not overflow
+        let frame_len = usize::try_from(length)
+            .ok()
+            .and_then(|length| length.checked_add(8))
+            .ok_or_else(|| anyhow::format_err!("Message length {length} too large"))?;
+        if src.len() < frame_len {
             trace!(length, buffern_len = src.len(), "Received partial message");
             return Ok(None);
         } else {
             trace!(length, "Received full message");
         }
 
-        src.reader()
-            .read_exact(&mut [0u8; 8][..])
-            .expect("minimum length checked");
+        src.advance(8);
+        let frame = src.split_to(frame_len - 8);
 
-        Ok(bincode::deserialize_from(src.reader()).map(Option::Some)?)
+        // Deserialize from the frame only, so a malformed message can't consume
+        // the bytes of the next one
+        Ok(bincode::deserialize(&frame).map(Option::Some)?)
     }
 }
 
@@ -243,11 +255,13 @@ where
 mod tests {
     use std::time::Duration;
 
+    use bytes::BytesMut;
     use futures::{SinkExt, StreamExt};
     use serde::{Deserialize, Serialize};
     use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+    use tokio_util::codec::Decoder;
 
-    use crate::net::framed::BidiFramed;
+    use crate::net::framed::{BidiFramed, BincodeCodec};
 
     #[tokio::test]
     async fn test_roundtrip() {
@@ -314,4 +328,22 @@ mod tests {
 
         assert!(received.is_err());
     }
+
+    #[test]
+    fn test_malformed_frames() {
+        let mut codec = BincodeCodec::<(u64, u64)>::default();
+
+        // Declared lengths that don't fit a `usize` must not panic
+        let mut buf = BytesMut::from(&u64::MAX.to_le_bytes()[..]);
+        assert!(codec.decode(&mut buf).is_err());
+
+        // A message must not read into the following frame
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&8u64.to_le_bytes());
+        buf.extend_from_slice(&42u64.to_le_bytes());
+        buf.extend_from_slice(&8u64.to_le_bytes());
+        buf.extend_from_slice(&42u64.to_le_bytes());
+        assert!(codec.decode(&mut buf).is_err());
+        assert_eq!(buf.len(), 16);
+    }
 }
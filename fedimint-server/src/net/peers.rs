@@ -5,10 +5,11 @@
 //! main implementation is [`ReconnectPeerConnections`], see these for details.
 
 use std::cmp::min;
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
 use std::net::SocketAddr;
 use std::ops::Sub;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
 use async_trait::async_trait;
@@ -16,6 +17,7 @@ use fedimint_api::cancellable::{Cancellable, Cancelled};
 use fedimint_api::net::peers::IPeerConnections;
 use fedimint_api::task::{TaskGroup, TaskHandle};
 use fedimint_api::PeerId;
+use fedimint_core::admin::PeerConnectionStatus;
 use futures::future::select_all;
 use futures::{SinkExt, StreamExt};
 use hbbft::Target;
@@ -48,6 +50,31 @@ pub type PeerConnector<M> = AnyConnector<PeerMessage<M>>;
 /// authenticated and encrypted.
 pub struct ReconnectPeerConnections<T> {
     connections: HashMap<PeerId, PeerConnection<T>>,
+    status: PeerStatusTracker,
+}
+
+/// Connection status of all peers as last seen by their connection state
+/// machines, can be cloned and read from other tasks
+#[derive(Debug, Clone, Default)]
+pub struct PeerStatusTracker(Arc<RwLock<BTreeMap<PeerId, PeerConnectionStatus>>>);
+
+impl PeerStatusTracker {
+    pub fn statuses(&self) -> BTreeMap<PeerId, PeerConnectionStatus> {
+        self.0.read().expect("not poisoned").clone()
+    }
+
+    pub fn connected_peers(&self) -> usize {
+        self.0
+            .read()
+            .expect("not poisoned")
+            .values()
+            .filter(|status| **status == PeerConnectionStatus::Connected)
+            .count()
+    }
+
+    fn set(&self, peer: PeerId, status: PeerConnectionStatus) {
+        self.0.write().expect("not poisoned").insert(peer, status);
+    }
 }
 
 struct PeerConnection<T> {
@@ -89,6 +116,7 @@ struct CommonPeerConnectionState<M> {
     connect: SharedAnyConnector<PeerMessage<M>>,
     incoming_connections: Receiver<AnyFramedTransport<PeerMessage<M>>>,
     last_received: Option<MessageId>,
+    status: PeerStatusTracker,
 }
 
 struct DisconnectedPeerConnectionState {
@@ -113,11 +141,22 @@ where
     /// network config and a [`Connector`](crate::net::connect::Connector).
     /// See [`ReconnectPeerConnections`] for requirements on the
     /// `Connector`.
-    #[instrument(skip_all)]
     pub async fn new(
         cfg: NetworkConfig,
         connect: PeerConnector<T>,
         task_group: &mut TaskGroup,
+    ) -> Self {
+        Self::new_with_status(cfg, connect, PeerStatusTracker::default(), task_group).await
+    }
+
+    /// Like [`Self::new`], but reports the connection status of the peers to
+    /// an existing `status` tracker
+    #[instrument(skip_all)]
+    pub async fn new_with_status(
+        cfg: NetworkConfig,
+        connect: PeerConnector<T>,
+        status: PeerStatusTracker,
+        task_group: &mut TaskGroup,
     ) -> Self {
         let shared_connector: SharedAnyConnector<PeerMessage<T>> = connect.into();
 
@@ -137,6 +176,7 @@ where
                             peer_address.clone(),
                             shared_connector.clone(),
                             connection_receiver,
+                            status.clone(),
                             task_group,
                         ),
                     ),
@@ -150,7 +190,15 @@ where
             })
             .await;
 
-        ReconnectPeerConnections { connections }
+        ReconnectPeerConnections {
+            connections,
+            status,
+        }
+    }
+
+    /// Handle to the connection status of all peers that stays up to date
+    pub fn status(&self) -> PeerStatusTracker {
+        self.status.clone()
     }
 
     async fn run_listen_task(
@@ -288,9 +336,17 @@ where
                     .await
             }
         }
-        .map(|new_state| PeerConnectionStateMachine {
-            common,
-            state: new_state,
+        .map(|new_state| {
+            let status = match new_state {
+                PeerConnectionState::Connected(_) => PeerConnectionStatus::Connected,
+                PeerConnectionState::Disconnected(_) => PeerConnectionStatus::Disconnected,
+            };
+            common.status.set(common.peer, status);
+
+            PeerConnectionStateMachine {
+                common,
+                state: new_state,
+            }
         })
     }
 }
@@ -553,6 +609,7 @@ where
         peer_address: Url,
         connect: SharedAnyConnector<PeerMessage<M>>,
         incoming_connections: Receiver<AnyFramedTransport<PeerMessage<M>>>,
+        status: PeerStatusTracker,
         task_group: &mut TaskGroup,
     ) -> PeerConnection<M> {
         let (outgoing_sender, outgoing_receiver) = tokio::sync::mpsc::channel::<M>(1024);
@@ -568,6 +625,7 @@ where
                     peer_address,
                     connect,
                     incoming_connections,
+                    status,
                     &handle,
                 )
                 .await
@@ -596,8 +654,10 @@ where
         peer_address: Url,
         connect: SharedAnyConnector<PeerMessage<M>>,
         incoming_connections: Receiver<AnyFramedTransport<PeerMessage<M>>>,
+        status: PeerStatusTracker,
         task_handle: &TaskHandle,
     ) {
+        status.set(peer, PeerConnectionStatus::Disconnected);
         let common = CommonPeerConnectionState {
             resend_queue: Default::default(),
             incoming,
@@ -607,6 +667,7 @@ where
             connect,
             incoming_connections,
             last_received: None,
+            status,
         };
         let initial_state = common.disconnect(0);
 
@@ -0,0 +1,408 @@
+//! Runs the consensus of a whole federation in a single task, so scenarios
+//! can be replayed exactly instead of depending on the timing of real
+//! networks and task schedulers.
+//!
+//! The servers are connected by a [`SimulatedNetwork`] that delivers messages
+//! after a latency drawn from a seeded RNG, measured in virtual time. The
+//! [`Simulation`] repeatedly lets servers propose and delivers the message
+//! due next, so the order of all events only depends on the seed. A failing
+//! scenario can be replayed by running it again with the seed it logged, see
+//! [`Simulation::seed_from_env`].
+//!
+//! Unlike a real federation every server proposes in every epoch, whether it
+//! has something to propose or not.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, format_err};
+use async_trait::async_trait;
+use bitcoin_hashes::sha256;
+use fedimint_api::cancellable::Cancellable;
+use fedimint_api::net::peers::{IPeerConnections, PeerConnections};
+use fedimint_api::PeerId;
+use hbbft::Epoched;
+use rand::rngs::{OsRng, StdRng};
+use rand::{Rng, RngCore, SeedableRng};
+use tracing::{debug, info};
+
+use crate::consensus::HbbftConsensusOutcome;
+use crate::{EpochMessage, FedimintServer};
+
+/// Environment variable to replay a simulation with the seed a failed run
+/// logged
+pub const FM_SIMULATION_SEED_ENV: &str = "FM_SIMULATION_SEED";
+
+/// Default range of the time a message takes between two servers
+pub const DEFAULT_LATENCY: Range<Duration> = Duration::from_millis(5)..Duration::from_millis(200);
+
+/// Upper bound for the time a server takes to propose once it finished an
+/// epoch
+pub const MAX_PROPOSAL_DELAY: Duration = Duration::from_millis(100);
+
+/// Events per epoch after which a simulation is considered stuck
+const MAX_EVENTS_PER_EPOCH: usize = 100_000;
+
+#[derive(Debug)]
+enum Event {
+    Deliver {
+        from: PeerId,
+        to: PeerId,
+        msg: EpochMessage,
+    },
+    Propose {
+        peer: PeerId,
+    },
+}
+
+#[derive(Debug)]
+struct NetworkState {
+    now: Duration,
+    rng: StdRng,
+    latency: Range<Duration>,
+    /// Ordered by due time, ties are broken by the order of scheduling
+    queue: BTreeMap<(Duration, u64), Event>,
+    next_seq: u64,
+    banned: BTreeSet<(PeerId, PeerId)>,
+    trace: Vec<Delivery>,
+}
+
+/// Message delivered by the simulated network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub time: Duration,
+    pub from: PeerId,
+    pub to: PeerId,
+}
+
+impl NetworkState {
+    fn schedule(&mut self, delay: Duration, event: Event) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.insert((self.now + delay, seq), event);
+    }
+
+    fn random_delay(&mut self, range: Range<Duration>) -> Duration {
+        if range.is_empty() {
+            return range.start;
+        }
+        self.rng.gen_range(range)
+    }
+}
+
+/// In-memory network between the servers of a [`Simulation`] with virtual
+/// time
+#[derive(Debug, Clone)]
+pub struct SimulatedNetwork(Arc<Mutex<NetworkState>>);
+
+impl SimulatedNetwork {
+    fn new(rng: StdRng) -> Self {
+        SimulatedNetwork(Arc::new(Mutex::new(NetworkState {
+            now: Duration::ZERO,
+            rng,
+            latency: DEFAULT_LATENCY,
+            queue: BTreeMap::new(),
+            next_seq: 0,
+            banned: BTreeSet::new(),
+            trace: vec![],
+        })))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, NetworkState> {
+        self.0.lock().expect("lock poisoned")
+    }
+
+    /// Virtual time passed since the simulation started
+    pub fn now(&self) -> Duration {
+        self.lock().now
+    }
+
+    /// Messages delivered so far, which are the same for every run with the
+    /// same seed
+    pub fn trace(&self) -> Vec<Delivery> {
+        self.lock().trace.clone()
+    }
+
+    /// Sets the range the latency of messages sent from now on is drawn from
+    pub fn set_latency(&self, latency: Range<Duration>) {
+        self.lock().latency = latency;
+    }
+
+    /// Connections of `peer` to the other servers
+    pub fn connections(&self, peer: PeerId) -> PeerConnections<EpochMessage> {
+        SimulatedConnections {
+            peer,
+            network: self.clone(),
+        }
+        .into_dyn()
+    }
+
+    fn send(&self, from: PeerId, to: &[PeerId], msg: EpochMessage) {
+        let mut state = self.lock();
+        for to in to {
+            if state.banned.contains(&(*to, from)) {
+                continue;
+            }
+            let latency = state.latency.clone();
+            let delay = state.random_delay(latency);
+            state.schedule(
+                delay,
+                Event::Deliver {
+                    from,
+                    to: *to,
+                    msg: msg.clone(),
+                },
+            );
+        }
+    }
+
+    fn schedule_proposal(&self, peer: PeerId) {
+        let mut state = self.lock();
+        let delay = state.random_delay(Duration::ZERO..MAX_PROPOSAL_DELAY);
+        state.schedule(delay, Event::Propose { peer });
+    }
+
+    /// Removes the event due next and advances the virtual time to it
+    fn next_event(&self) -> Option<Event> {
+        let mut state = self.lock();
+        let ((due, _), event) = state.queue.pop_first()?;
+        state.now = due;
+        if let Event::Deliver { from, to, .. } = &event {
+            state.trace.push(Delivery {
+                time: due,
+                from: *from,
+                to: *to,
+            });
+        }
+        Some(event)
+    }
+
+    /// Removes the next message to `peer`, for code paths outside of the
+    /// simulation loop waiting for messages
+    fn next_message_to(&self, peer: PeerId) -> Option<(PeerId, EpochMessage)> {
+        let mut state = self.lock();
+        let key = state
+            .queue
+            .iter()
+            .find(|(_, event)| matches!(event, Event::Deliver { to, .. } if *to == peer))
+            .map(|(key, _)| *key)?;
+        let Some(Event::Deliver { from, msg, .. }) = state.queue.remove(&key) else {
+            unreachable!("Found a delivery")
+        };
+        state.now = state.now.max(key.0);
+        state.trace.push(Delivery {
+            time: state.now,
+            from,
+            to: peer,
+        });
+        Some((from, msg))
+    }
+}
+
+struct SimulatedConnections {
+    peer: PeerId,
+    network: SimulatedNetwork,
+}
+
+#[async_trait]
+impl IPeerConnections<EpochMessage> for SimulatedConnections {
+    async fn send(&mut self, peers: &[PeerId], msg: EpochMessage) -> Cancellable<()> {
+        self.network.send(self.peer, peers, msg);
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Cancellable<(PeerId, EpochMessage)> {
+        // Nothing else runs while we wait, so without a message we would wait
+        // forever
+        Ok(self
+            .network
+            .next_message_to(self.peer)
+            .expect("Simulated server waits for a message that will never arrive"))
+    }
+
+    async fn ban_peer(&mut self, peer: PeerId) {
+        self.network.lock().banned.insert((self.peer, peer));
+    }
+}
+
+/// Federation whose servers run consensus deterministically for a given seed
+pub struct Simulation {
+    seed: u64,
+    network: SimulatedNetwork,
+    /// Passed to HBBFT, which needs randomness for threshold encryption
+    rng: StdRng,
+    servers: BTreeMap<PeerId, FedimintServer>,
+    /// HBBFT epoch each server last proposed in
+    proposed: BTreeMap<PeerId, u64>,
+    /// Hash of every processed epoch and the server that processed it first
+    epoch_hashes: BTreeMap<u64, (PeerId, sha256::Hash)>,
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        info!(
+            seed,
+            "Starting simulation, set {FM_SIMULATION_SEED_ENV}={seed} to replay it"
+        );
+        let mut rng = StdRng::seed_from_u64(seed);
+        let network = SimulatedNetwork::new(StdRng::seed_from_u64(rng.next_u64()));
+        Simulation {
+            seed,
+            network,
+            rng,
+            servers: BTreeMap::new(),
+            proposed: BTreeMap::new(),
+            epoch_hashes: BTreeMap::new(),
+        }
+    }
+
+    /// Seed from [`FM_SIMULATION_SEED_ENV`] if set, else a random one
+    pub fn seed_from_env() -> u64 {
+        match std::env::var(FM_SIMULATION_SEED_ENV) {
+            Ok(seed) => seed
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid {FM_SIMULATION_SEED_ENV}: {seed}")),
+            Err(_) => OsRng.next_u64(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The network servers added to the simulation have to be connected to,
+    /// see [`FedimintServer::new_with_connections`]
+    pub fn network(&self) -> &SimulatedNetwork {
+        &self.network
+    }
+
+    /// Starts consensus of `server` like [`FedimintServer::run`] would
+    pub async fn add_server(&mut self, mut server: FedimintServer) {
+        let peer = server.cfg.local.identity;
+        server.start_consensus().await;
+        self.network.schedule_proposal(peer);
+        assert!(
+            self.servers.insert(peer, server).is_none(),
+            "Server {peer} added twice"
+        );
+    }
+
+    pub fn server(&self, peer: PeerId) -> &FedimintServer {
+        &self.servers[&peer]
+    }
+
+    pub fn servers(&self) -> impl Iterator<Item = &FedimintServer> {
+        self.servers.values()
+    }
+
+    /// Hashes of the epochs processed so far, which all servers agreed on
+    pub fn epoch_hashes(&self) -> BTreeMap<u64, sha256::Hash> {
+        self.epoch_hashes
+            .iter()
+            .map(|(epoch, (_, hash))| (*epoch, *hash))
+            .collect()
+    }
+
+    /// Runs consensus until every server processed `epochs` more epochs.
+    /// Fails if servers processed different outcomes or consensus got stuck.
+    pub async fn run_epochs(&mut self, epochs: u64) -> anyhow::Result<()> {
+        let target: BTreeMap<PeerId, u64> = self
+            .servers
+            .iter()
+            .map(|(peer, server)| (*peer, server.next_epoch_to_process() + epochs))
+            .collect();
+
+        let mut events = 0;
+        while self
+            .servers
+            .iter()
+            .any(|(peer, server)| server.next_epoch_to_process() < target[peer])
+        {
+            events += 1;
+            if events > MAX_EVENTS_PER_EPOCH * epochs.max(1) as usize {
+                bail!("Simulation with seed {} got stuck", self.seed);
+            }
+
+            match self.network.next_event() {
+                Some(Event::Propose { peer }) => self.propose(peer).await?,
+                Some(Event::Deliver { from, to, msg }) => {
+                    let Some(server) = self.servers.get_mut(&to) else {
+                        continue;
+                    };
+                    let outcomes = server
+                        .handle_message((from, msg))
+                        .await
+                        .map_err(|_| format_err!("Server {to} was cancelled"))?;
+                    self.process_outcomes(to, outcomes).await?;
+                }
+                None => bail!(
+                    "Simulation with seed {} ran out of messages before finishing",
+                    self.seed
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    async fn propose(&mut self, peer: PeerId) -> anyhow::Result<()> {
+        let server = self.servers.get_mut(&peer).expect("Scheduled by us");
+        let epoch = server.hbbft.epoch();
+        if self.proposed.get(&peer) == Some(&epoch) {
+            return Ok(());
+        }
+        self.proposed.insert(peer, epoch);
+
+        server.save_txs_to_consensus_cache();
+        let proposal = server.consensus.get_consensus_proposal().await;
+        for drop_peer in &proposal.drop_peers {
+            server.connections.ban_peer(*drop_peer).await;
+        }
+        debug!(%peer, epoch, time = ?self.network.now(), "Proposing");
+        let step = server
+            .propose_epoch(proposal, &mut self.rng)
+            .await
+            .map_err(|_| format_err!("Server {peer} was cancelled"))?;
+        let outcomes = server
+            .handle_step(step)
+            .await
+            .map_err(|_| format_err!("Server {peer} was cancelled"))?;
+        self.process_outcomes(peer, outcomes).await
+    }
+
+    async fn process_outcomes(
+        &mut self,
+        peer: PeerId,
+        outcomes: Vec<HbbftConsensusOutcome>,
+    ) -> anyhow::Result<()> {
+        if outcomes.is_empty() {
+            return Ok(());
+        }
+
+        let server = self.servers.get_mut(&peer).expect("Exists");
+        for outcome in outcomes {
+            server
+                .process_outcome(outcome)
+                .await
+                .map_err(|e| format_err!("Server {peer} failed to process epoch: {e:?}"))?;
+            let processed = server
+                .last_processed_epoch
+                .as_ref()
+                .expect("Processed an epoch");
+            let (epoch, hash) = (processed.outcome.epoch, processed.outcome.hash());
+            match self.epoch_hashes.get(&epoch) {
+                Some((first_peer, first_hash)) if *first_hash != hash => bail!(
+                    "Servers {first_peer} and {peer} processed different outcomes of epoch {epoch}, replay with seed {}",
+                    self.seed
+                ),
+                Some(_) => {}
+                None => {
+                    self.epoch_hashes.insert(epoch, (peer, hash));
+                }
+            }
+        }
+        self.network.schedule_proposal(peer);
+        Ok(())
+    }
+}
@@ -2,7 +2,9 @@ use std::str::FromStr;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use fedimint_api::db::{IDatabase, IDatabaseTransaction, PrefixStream};
+use fedimint_api::db::{
+    IDatabase, IDatabaseTransaction, PrefixStream, ReadOnlyDatabaseTransaction,
+};
 use futures::stream;
 use sqlx::migrate::MigrateDatabase;
 use sqlx::sqlite::SqliteConnectOptions;
@@ -32,7 +34,7 @@ impl SqliteDb {
         opts.disable_statement_logging();
         let db = SqlitePool::connect_with(opts).await?;
 
-        sqlx::query("CREATE TABLE IF NOT EXISTS kv (key BLOB, value BLOB);")
+        sqlx::query("CREATE TABLE IF NOT EXISTS kv (key BLOB PRIMARY KEY, value BLOB);")
             .execute(&db)
             .await
             .expect("Error while creating the key-value table");
@@ -49,6 +51,13 @@ impl SqliteDb {
 
         Ok(SqliteDb(db))
     }
+
+    /// Rebuilds the database file, returning the space of deleted entries to
+    /// the file system
+    pub async fn compact(&self) -> Result<(), Error> {
+        sqlx::query("VACUUM").execute(&self.0).await?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -58,13 +67,23 @@ impl IDatabase for SqliteDb {
         tx.set_tx_savepoint().await;
         Box::new(tx)
     }
+
+    async fn begin_read_transaction<'a>(&'a self) -> Box<dyn IDatabaseTransaction<'a>> {
+        // A deferred transaction reads from the snapshot established by its first
+        // read until it ends
+        Box::new(ReadOnlyDatabaseTransaction::new(Box::new(
+            SqliteDbTransaction(self.0.begin().await.unwrap()),
+        )))
+    }
 }
 
 #[async_trait]
 impl<'a> IDatabaseTransaction<'a> for SqliteDbTransaction<'a> {
     async fn raw_insert_bytes(&mut self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>> {
         let val = self.raw_get_bytes(key).await.unwrap();
-        let query_prepared = sqlx::query("INSERT INTO kv (key, value) VALUES (?, ?)")
+        // Keys are unique, so inserting an existing key replaces its value like
+        // in the other backends
+        let query_prepared = sqlx::query("INSERT OR REPLACE INTO kv (key, value) VALUES (?, ?)")
             .bind(key)
             .bind(value);
         self.0.execute(query_prepared).await?;
@@ -72,8 +91,7 @@ impl<'a> IDatabaseTransaction<'a> for SqliteDbTransaction<'a> {
     }
 
     async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        let query_prepared =
-            sqlx::query("SELECT value FROM kv WHERE key = ? ORDER BY value DESC LIMIT 1").bind(key);
+        let query_prepared = sqlx::query("SELECT value FROM kv WHERE key = ?").bind(key);
         self.0
             .fetch_optional(query_prepared)
             .await
@@ -82,9 +100,7 @@ impl<'a> IDatabaseTransaction<'a> for SqliteDbTransaction<'a> {
     }
 
     async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        let query_prepared =
-            sqlx::query("SELECT rowid, value FROM kv WHERE key = ? ORDER BY value DESC LIMIT 1")
-                .bind(key);
+        let query_prepared = sqlx::query("SELECT rowid, value FROM kv WHERE key = ?").bind(key);
         let res = self.0.fetch_optional(query_prepared).await;
         if let Ok(Some(row)) = res {
             let rowid = row.get::<i64, &str>("rowid");
@@ -105,7 +121,8 @@ impl<'a> IDatabaseTransaction<'a> for SqliteDbTransaction<'a> {
             str_prefix = format!("{str_prefix}{prefix:02X?}");
         }
         str_prefix = format!("{}{}", str_prefix, "%");
-        let query = "SELECT key, value FROM kv WHERE hex(key) LIKE ? ORDER BY value DESC";
+        // Return entries in key order, matching the iteration order of RocksDB
+        let query = "SELECT key, value FROM kv WHERE hex(key) LIKE ? ORDER BY key ASC";
         let query_prepared = sqlx::query(query).bind(str_prefix);
         let results = self.0.fetch_all(query_prepared).await;
 
@@ -117,7 +134,7 @@ impl<'a> IDatabaseTransaction<'a> for SqliteDbTransaction<'a> {
         let rows = results.unwrap().into_iter().map(|row| {
             (
                 row.get::<Vec<u8>, &str>("key"),
-                row.get::<Vec<u8>, &str>("value"),
+                Ok(row.get::<Vec<u8>, &str>("value")),
             )
         });
 
@@ -227,6 +244,11 @@ mod fedimint_sqlite_tests {
         .await;
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_dbtx_snapshot_read() {
+        fedimint_api::db::verify_snapshot_read(open_temp_db("snapshot-read").await).await;
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_dbtx_rollback_to_savepoint() {
         fedimint_api::db::verify_rollback_to_savepoint(open_temp_db("rollback-to-savepoint").await)
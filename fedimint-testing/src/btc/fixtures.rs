@@ -6,7 +6,7 @@ use std::{
 use async_trait::async_trait;
 use bitcoin::{
     hash_types::Txid, hashes::Hash, util::merkleblock::PartialMerkleTree, Address, Block,
-    BlockHash, BlockHeader, Network, PackedLockTime, Transaction, TxOut,
+    BlockHash, BlockHeader, Network, OutPoint, PackedLockTime, Script, Transaction, TxOut,
 };
 use fedimint_api::{Amount, Feerate};
 use fedimint_bitcoind::{IBitcoindRpc, Result as BitcoinRpcResult};
@@ -190,4 +190,32 @@ impl IBitcoindRpc for FakeBitcoinTest {
         }
         Ok(())
     }
+
+    async fn get_unspent_output(
+        &self,
+        outpoint: &OutPoint,
+        _script_pubkey: &Script,
+    ) -> BitcoinRpcResult<Option<TxOut>> {
+        let blocks = self.blocks.lock().unwrap();
+        let pending = self.pending.lock().unwrap();
+        let transactions = blocks
+            .iter()
+            .flat_map(|block| block.txdata.iter())
+            .chain(pending.iter());
+
+        let mut output = None;
+        for tx in transactions {
+            if tx
+                .input
+                .iter()
+                .any(|input| input.previous_output == *outpoint)
+            {
+                return Ok(None);
+            }
+            if tx.txid() == outpoint.txid {
+                output = tx.output.get(outpoint.vout as usize).cloned();
+            }
+        }
+        Ok(output)
+    }
 }
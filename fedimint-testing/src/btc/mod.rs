@@ -1,5 +1,6 @@
 pub mod bitcoind;
 pub mod fixtures;
+pub mod real;
 
 use async_trait::async_trait;
 use bitcoin::{Address, Transaction};
@@ -0,0 +1,156 @@
+//! Snapshots of consensus encodings that catch accidental wire-format breaks
+//!
+//! Every entry of an [`EncodingCorpus`] is a value with a fixed content,
+//! encoded and compared to a fixture file committed next to the test. The
+//! fixture records the consensus version the encoding belongs to, so an
+//! encoding may only change together with that version. After a deliberate
+//! version bump the fixtures are regenerated with
+//! `FM_UPDATE_ENCODING_CORPUS=1`.
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use bitcoin::hashes::hex::ToHex;
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use serde::{Deserialize, Serialize};
+
+/// Set to regenerate fixtures whose consensus version changed
+pub const UPDATE_ENCODING_CORPUS_ENV: &str = "FM_UPDATE_ENCODING_CORPUS";
+
+/// Content of a fixture file
+#[derive(Debug, Serialize, Deserialize)]
+struct EncodingFixture {
+    /// Consensus versions the encoding was recorded with
+    version: String,
+    #[serde(with = "fedimint_api::hex::serde")]
+    encoding: Vec<u8>,
+}
+
+/// Compares the encodings of values to the fixtures in a directory, see the
+/// [module documentation](self)
+///
+/// Fixtures that don't exist yet are recorded and have to be committed. All
+/// mismatches are collected and reported together by [`Self::finish`].
+#[derive(Debug)]
+pub struct EncodingCorpus {
+    dir: PathBuf,
+    update: bool,
+    failures: Vec<String>,
+}
+
+impl EncodingCorpus {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        EncodingCorpus {
+            dir: dir.into(),
+            update: std::env::var(UPDATE_ENCODING_CORPUS_ENV).map_or(false, |v| v == "1"),
+            failures: vec![],
+        }
+    }
+
+    /// Checks that `value` still encodes like the fixture `name` recorded at
+    /// the same consensus `version`
+    pub fn check_encoding<T: Encodable>(&mut self, name: &str, version: &str, value: &T) {
+        self.check(name, version, value, |_| Ok(()));
+    }
+
+    /// Like [`Self::check_encoding`], but also checks that the fixture decodes
+    /// and encodes back to the same bytes
+    pub fn check_roundtrip<T: Encodable + Decodable>(
+        &mut self,
+        name: &str,
+        version: &str,
+        value: &T,
+        decoders: &ModuleDecoderRegistry,
+    ) {
+        self.check(name, version, value, |bytes| {
+            let mut reader = Cursor::new(bytes);
+            let decoded = T::consensus_decode(&mut reader, decoders)
+                .map_err(|e| format!("fixture does not decode: {e}"))?;
+            if reader.position() != bytes.len() as u64 {
+                return Err("fixture has trailing bytes after decoding".to_string());
+            }
+            if encode(&decoded) != bytes {
+                return Err("fixture encodes differently after decoding".to_string());
+            }
+            Ok(())
+        });
+    }
+
+    fn check<T: Encodable>(
+        &mut self,
+        name: &str,
+        version: &str,
+        value: &T,
+        verify_fixture: impl FnOnce(&[u8]) -> Result<(), String>,
+    ) {
+        let path = self.dir.join(format!("{name}.json"));
+        let encoding = encode(value);
+        let fixture = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str::<EncodingFixture>(&json)
+                .unwrap_or_else(|e| panic!("Invalid fixture {}: {e}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("Recorded new encoding fixture {}", path.display());
+                self.record(&path, version, encoding.clone())
+            }
+            Err(e) => panic!("Could not read fixture {}: {e}", path.display()),
+        };
+
+        let fixture = if fixture.version == version {
+            fixture
+        } else if self.update {
+            self.record(&path, version, encoding.clone())
+        } else {
+            self.failures.push(format!(
+                "{name}: consensus version changed from {} to {version}, regenerate the fixture \
+                 with {UPDATE_ENCODING_CORPUS_ENV}=1",
+                fixture.version
+            ));
+            return;
+        };
+
+        if fixture.encoding != encoding {
+            self.failures.push(format!(
+                "{name}: encoding changed without a consensus version bump\n  fixture: \
+                 {}\n  current: {}",
+                fixture.encoding.to_hex(),
+                encoding.to_hex()
+            ));
+            return;
+        }
+
+        if let Err(e) = verify_fixture(&fixture.encoding) {
+            self.failures.push(format!("{name}: {e}"));
+        }
+    }
+
+    fn record(&self, path: &Path, version: &str, encoding: Vec<u8>) -> EncodingFixture {
+        let fixture = EncodingFixture {
+            version: version.to_string(),
+            encoding,
+        };
+        let json = serde_json::to_string_pretty(&fixture).expect("Serialization can't fail");
+        std::fs::create_dir_all(&self.dir).expect("Could not create fixture directory");
+        std::fs::write(path, json + "\n")
+            .unwrap_or_else(|e| panic!("Could not write fixture {}: {e}", path.display()));
+        fixture
+    }
+
+    /// Panics with all mismatches found so far
+    pub fn finish(self) {
+        if !self.failures.is_empty() {
+            panic!(
+                "{} of the encodings in {} broke:\n{}",
+                self.failures.len(),
+                self.dir.display(),
+                self.failures.join("\n")
+            );
+        }
+    }
+}
+
+fn encode<T: Encodable>(value: &T) -> Vec<u8> {
+    value
+        .consensus_encode_to_vec()
+        .expect("Encoding to vec can't fail")
+}
@@ -0,0 +1,434 @@
+//! Federations of real guardians for tests outside of this repository
+//!
+//! Unlike the fixtures of the integration tests, which step through consensus
+//! epochs by hand, a [`TestFederation`] runs every guardian the way `fedimintd`
+//! does: on its own runtime, talking to its peers over TLS and to a regtest
+//! `bitcoind`. Tests interact with it only through clients and the gateway, so
+//! wallets and modules developed elsewhere can be tested against the real
+//! thing in their own CI:
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use fedimint_api::PeerId;
+//! use fedimint_testing::btc::real::BitcoindProcess;
+//! use fedimint_testing::federation::FederationBuilder;
+//!
+//! let bitcoind = BitcoindProcess::spawn("/tmp/bitcoind".as_ref(), 18443).await?;
+//! let mut fed = FederationBuilder::new(4, bitcoind.rpc_url().clone())
+//!     .spawn()
+//!     .await?;
+//!
+//! let client = fed.new_client().await;
+//! fed.fund_client(&client, bitcoin::Amount::from_sat(10_000))
+//!     .await?;
+//!
+//! // A federation of 4 keeps working with one guardian down
+//! fed.degrade_peer(PeerId::from(3)).await?;
+//! # fed.shutdown().await
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::{format_err, Context};
+use bitcoin::KeyPair;
+use fedimint_api::config::{ClientConfig, ConfigGenParams, ModuleGenParams, ModuleGenRegistry};
+use fedimint_api::db::mem_impl::MemDatabase;
+use fedimint_api::db::Database;
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::module::IModuleGen;
+use fedimint_api::task::TaskGroup;
+use fedimint_api::{sats, OutPoint, PeerId};
+use fedimint_ln::{GatewayFees, GatewayLimits};
+use fedimint_mint::MintGenParams;
+use fedimint_server::config::{ServerConfig, ServerConfigParams};
+use fedimint_server::consensus::FedimintConsensus;
+use fedimint_server::FedimintServer;
+use fedimint_wallet::config::WalletClientConfig;
+use fedimint_wallet::WalletGenParams;
+use ln_gateway::actor::GatewayActor;
+use ln_gateway::client::{DynGatewayClientBuilder, MemDbFactory, StandardGatewayClientBuilder};
+use ln_gateway::config::GatewayConfig;
+use ln_gateway::ln::LnRpc;
+use ln_gateway::rpc::GatewayRequest;
+use ln_gateway::LnGateway;
+use mint_client::{Client, GatewayClient, GatewayClientConfig, UserClient, UserClientConfig};
+use rand::rngs::OsRng;
+use url::Url;
+
+use crate::btc::real::RealBitcoinTest;
+use crate::btc::BitcoinTest;
+
+/// Ports are taken from here so federations of concurrent tests don't clash,
+/// far from the ones of the integration test fixtures
+static BASE_PORT: AtomicU16 = AtomicU16::new(38173);
+
+/// Ports every guardian gets, p2p and API use the first two
+const PORTS_PER_PEER: u16 = 10;
+
+/// How long guardians and gateways get to finish their tasks on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for the federation to accept a transaction
+const TX_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Configures a federation before [spawning](Self::spawn) it
+pub struct FederationBuilder {
+    num_peers: u16,
+    bitcoind_rpc: Url,
+    base_port: Option<u16>,
+    finality_delay: u32,
+    module_gens: ModuleGenRegistry,
+    extra_gen_params: ConfigGenParams,
+}
+
+impl FederationBuilder {
+    /// Federation of `num_peers` guardians running the wallet, mint and
+    /// lightning modules, watching the regtest `bitcoind` at `bitcoind_rpc`
+    pub fn new(num_peers: u16, bitcoind_rpc: Url) -> Self {
+        FederationBuilder {
+            num_peers,
+            bitcoind_rpc,
+            base_port: None,
+            finality_delay: 10,
+            module_gens: fedimintd::module_registry(),
+            extra_gen_params: ConfigGenParams::new(),
+        }
+    }
+
+    /// First port of the range the guardians and the gateway listen on,
+    /// otherwise a range no other federation of this process uses is picked
+    pub fn base_port(mut self, base_port: u16) -> Self {
+        self.base_port = Some(base_port);
+        self
+    }
+
+    /// Blocks a peg-in needs on top of the one confirming it
+    pub fn finality_delay(mut self, finality_delay: u32) -> Self {
+        self.finality_delay = finality_delay;
+        self
+    }
+
+    /// Attach the generator of an additional module kind, like
+    /// `Fedimintd::with_module`
+    pub fn with_module<T>(mut self, gen: T) -> Self
+    where
+        T: IModuleGen + Send + Sync + 'static,
+    {
+        self.module_gens = self.module_gens.attach(gen);
+        self
+    }
+
+    /// Params an attached module needs to generate its config
+    pub fn with_module_gen_params<P: ModuleGenParams>(mut self, params: P) -> Self {
+        self.extra_gen_params = self.extra_gen_params.attach(params);
+        self
+    }
+
+    /// Generates the configs, starts all guardians and waits until they
+    /// reached consensus on the block height
+    pub async fn spawn(self) -> anyhow::Result<TestFederation> {
+        let ports = (self.num_peers + 1) * PORTS_PER_PEER;
+        let base_port = self
+            .base_port
+            .unwrap_or_else(|| BASE_PORT.fetch_add(ports, Ordering::Relaxed));
+
+        let peers = (0..self.num_peers).map(PeerId::from).collect::<Vec<_>>();
+        let wallet_params = WalletGenParams {
+            bitcoind_rpcs: vec![self.bitcoind_rpc.clone()],
+            ..WalletGenParams::new(bitcoin::Network::Regtest, self.finality_delay)
+        };
+        let modules = fedimintd::configure_modules(MintGenParams::new(sats(100000)), wallet_params)
+            .extend(self.extra_gen_params);
+        let params = ServerConfigParams::gen_local(&peers, base_port, "test", modules)?;
+        let configs =
+            ServerConfig::trusted_dealer_gen("", &peers, &params, self.module_gens.clone(), OsRng);
+
+        let decoders = self
+            .module_gens
+            .decoders(configs[&PeerId::from(0)].iter_module_instances())?;
+        let client_config = configs[&PeerId::from(0)]
+            .consensus
+            .to_config_response(&self.module_gens)
+            .client;
+
+        let guardians = configs
+            .into_iter()
+            .map(|(peer, cfg)| {
+                let db = Database::new(MemDatabase::new(), decoders.clone());
+                let mut guardian = Guardian {
+                    cfg,
+                    db,
+                    running: None,
+                };
+                guardian.start(self.module_gens.clone(), decoders.clone());
+                (peer, guardian)
+            })
+            .collect();
+
+        let fed = TestFederation {
+            guardians,
+            client_config,
+            module_gens: self.module_gens,
+            decoders,
+            bitcoin: RealBitcoinTest::new(&self.bitcoind_rpc),
+            gateway_port: base_port + self.num_peers * PORTS_PER_PEER,
+            gateway: None,
+        };
+        fed.bitcoin.prepare_funding_wallet().await;
+        fed.new_client()
+            .await
+            .await_consensus_block_height(0)
+            .await
+            .context("Federation did not start")?;
+        Ok(fed)
+    }
+}
+
+/// A running federation, see the [module documentation](self)
+///
+/// Guardians run on their own threads, so [`Self::shutdown`] has to be called
+/// to stop them before the test ends.
+pub struct TestFederation {
+    guardians: BTreeMap<PeerId, Guardian>,
+    client_config: ClientConfig,
+    module_gens: ModuleGenRegistry,
+    decoders: ModuleDecoderRegistry,
+    bitcoin: RealBitcoinTest,
+    gateway_port: u16,
+    gateway: Option<TaskGroup>,
+}
+
+impl TestFederation {
+    /// Config clients join the federation with
+    pub fn client_config(&self) -> &ClientConfig {
+        &self.client_config
+    }
+
+    /// The `bitcoind` the federation watches, e.g. to mine blocks
+    pub fn bitcoin(&self) -> &RealBitcoinTest {
+        &self.bitcoin
+    }
+
+    /// Guardians that were not degraded
+    pub fn online_peers(&self) -> Vec<PeerId> {
+        self.guardians
+            .iter()
+            .filter(|(_, guardian)| guardian.running.is_some())
+            .map(|(peer, _)| *peer)
+            .collect()
+    }
+
+    /// Client with an empty in-memory database that joined the federation
+    pub async fn new_client(&self) -> UserClient {
+        Client::new(
+            UserClientConfig(self.client_config.clone()),
+            self.decoders.clone(),
+            self.module_gens.clone(),
+            Database::new(MemDatabase::new(), self.decoders.clone()),
+            Default::default(),
+        )
+        .await
+    }
+
+    /// Pegs `amount` in for `client` and waits until it received the e-cash
+    pub async fn fund_client(
+        &self,
+        client: &UserClient,
+        amount: bitcoin::Amount,
+    ) -> anyhow::Result<()> {
+        let finality_delay = self
+            .client_config
+            .get_first_module_by_kind::<WalletClientConfig>("wallet")?
+            .1
+            .peg_in_finality_delay(amount);
+
+        let address = client.get_new_pegin_address(OsRng).await;
+        let (proof, tx) = self.bitcoin.send_and_mine_block(&address, amount).await;
+        let height = self.bitcoin.block_height().await;
+        self.bitcoin.mine_blocks(finality_delay.into()).await;
+
+        client
+            .await_consensus_block_height(height)
+            .await
+            .context("Federation did not reach the peg-in block height")?;
+        let txid = client.peg_in(proof, tx, OsRng).await?;
+        let out_point = OutPoint { txid, out_idx: 0 };
+        client.await_output_accepted(out_point, TX_TIMEOUT).await?;
+        client.fetch_notes(out_point).await?;
+        Ok(())
+    }
+
+    /// Stops a guardian as if its process crashed, its database is kept for
+    /// [`Self::restore_peer`]
+    pub async fn degrade_peer(&mut self, peer: PeerId) -> anyhow::Result<()> {
+        self.guardian(peer)?.stop().await
+    }
+
+    /// Restarts a degraded guardian, which catches up with its peers
+    pub fn restore_peer(&mut self, peer: PeerId) -> anyhow::Result<()> {
+        let module_gens = self.module_gens.clone();
+        let decoders = self.decoders.clone();
+        let guardian = self.guardian(peer)?;
+        if guardian.running.is_some() {
+            anyhow::bail!("Peer {peer} is running already");
+        }
+        guardian.start(module_gens, decoders);
+        Ok(())
+    }
+
+    fn guardian(&mut self, peer: PeerId) -> anyhow::Result<&mut Guardian> {
+        self.guardians
+            .get_mut(&peer)
+            .ok_or_else(|| format_err!("Federation has no peer {peer}"))
+    }
+
+    /// Connects a gateway that routes payments through `ln_rpc` and serves
+    /// its API, returning the federation client it holds the e-cash with
+    ///
+    /// Only one gateway can be started, its port follows those of the
+    /// guardians.
+    pub async fn start_gateway(
+        &mut self,
+        ln_rpc: Arc<dyn LnRpc>,
+    ) -> anyhow::Result<(Arc<GatewayActor>, Arc<GatewayClient>)> {
+        if self.gateway.is_some() {
+            anyhow::bail!("A gateway is running already");
+        }
+
+        let bind_address: SocketAddr = format!("127.0.0.1:{}", self.gateway_port).parse()?;
+        let announce_address = Url::parse(&format!("http://{bind_address}"))?;
+        let node_pub_key = ln_rpc
+            .pubkey()
+            .await
+            .map_err(|e| format_err!("Could not fetch the node pubkey: {e:?}"))?;
+        let ctx = bitcoin::secp256k1::Secp256k1::new();
+        let gw_client_cfg = GatewayClientConfig {
+            mint_channel_id: 0,
+            client_config: self.client_config.clone(),
+            redeem_key: KeyPair::new(&ctx, &mut OsRng),
+            timelock_delta: 10,
+            api: announce_address.clone(),
+            node_pub_key,
+            fees: GatewayFees::default(),
+            limits: GatewayLimits::default(),
+            rebalance: None,
+            watchtower: false,
+        };
+
+        let client_builder: DynGatewayClientBuilder = StandardGatewayClientBuilder::new(
+            PathBuf::new(),
+            MemDbFactory.into(),
+            announce_address.clone(),
+        )
+        .into();
+        let client = Arc::new(
+            client_builder
+                .build(
+                    gw_client_cfg,
+                    self.decoders.clone(),
+                    self.module_gens.clone(),
+                )
+                .await?,
+        );
+
+        let mut task_group = TaskGroup::new();
+        let (sender, receiver) = tokio::sync::mpsc::channel::<GatewayRequest>(100);
+        let gateway = LnGateway::new(
+            GatewayConfig {
+                bind_address,
+                announce_address,
+                password: "password".into(),
+            },
+            self.decoders.clone(),
+            self.module_gens.clone(),
+            ln_rpc,
+            client_builder,
+            sender,
+            receiver,
+            task_group.clone(),
+        )
+        .await;
+        // Registers the gateway with the federation
+        let actor = gateway.connect_federation(client.clone(), vec![]).await?;
+        task_group
+            .spawn("gateway", |_handle| async move {
+                if let Err(e) = gateway.run().await {
+                    tracing::error!("Gateway stopped: {e}");
+                }
+            })
+            .await;
+        self.gateway = Some(task_group);
+
+        Ok((actor, client))
+    }
+
+    /// Stops the gateway and all guardians
+    pub async fn shutdown(mut self) -> anyhow::Result<()> {
+        if let Some(task_group) = self.gateway.take() {
+            task_group.shutdown_join_all(Some(SHUTDOWN_TIMEOUT)).await?;
+        }
+        for guardian in self.guardians.values_mut() {
+            guardian.stop().await?;
+        }
+        Ok(())
+    }
+}
+
+/// A guardian's config and database, which outlive its runs
+struct Guardian {
+    cfg: ServerConfig,
+    db: Database,
+    running: Option<RunningGuardian>,
+}
+
+struct RunningGuardian {
+    task_group: TaskGroup,
+    thread: JoinHandle<anyhow::Result<()>>,
+}
+
+impl Guardian {
+    /// Runs the guardian on a thread with its own runtime, like a separate
+    /// `fedimintd` process
+    fn start(&mut self, module_gens: ModuleGenRegistry, decoders: ModuleDecoderRegistry) {
+        let cfg = self.cfg.clone();
+        let db = self.db.clone();
+        let task_group = TaskGroup::new();
+        let mut tasks = task_group.clone();
+
+        let thread = std::thread::Builder::new()
+            .name(format!("guardian-{}", cfg.local.identity))
+            .spawn(move || -> anyhow::Result<()> {
+                let runtime = tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .build()?;
+                // Consensus runs in a `spawn_local` task, just like in fedimintd
+                let local_task_set = tokio::task::LocalSet::new();
+                local_task_set.block_on(&runtime, async move {
+                    let (consensus, tx_receiver) =
+                        FedimintConsensus::new(cfg.clone(), db, module_gens, &mut tasks).await?;
+                    FedimintServer::run(cfg, consensus, tx_receiver, decoders, &mut tasks).await?;
+                    tasks.join_all(Some(SHUTDOWN_TIMEOUT)).await
+                })
+            })
+            .expect("Could not spawn guardian thread");
+
+        self.running = Some(RunningGuardian { task_group, thread });
+    }
+
+    async fn stop(&mut self) -> anyhow::Result<()> {
+        let Some(RunningGuardian { task_group, thread }) = self.running.take() else {
+            return Ok(());
+        };
+        task_group.shutdown().await;
+        tokio::task::spawn_blocking(move || thread.join())
+            .await?
+            .map_err(|_| format_err!("Guardian {} panicked", self.cfg.local.identity))?
+    }
+}
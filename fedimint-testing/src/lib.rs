@@ -15,6 +15,8 @@ use fedimint_api::module::{ApiError, InputMeta, ModuleError, ModuleGen, Transact
 use fedimint_api::{OutPoint, PeerId, ServerModule};
 
 pub mod btc;
+pub mod encoding;
+pub mod federation;
 pub mod ln;
 
 #[derive(Debug)]
@@ -184,7 +186,7 @@ where
 
                 for (out_point, output) in outputs {
                     member
-                        .apply_output(&mut module_dbtx, output, *out_point)
+                        .apply_output(&fake_ic, &mut module_dbtx, output, *out_point)
                         .await
                         .expect("Faulty output");
                 }
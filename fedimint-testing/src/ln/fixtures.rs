@@ -3,23 +3,30 @@ use std::{
     time::Duration,
 };
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use async_trait::async_trait;
 use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::{PublicKey, SecretKey};
 use bitcoin::{secp256k1, KeyPair};
 use fedimint_api::Amount;
 use fedimint_ln::route_hints::RouteHint;
+use fedimint_ln::GatewayLiquidity;
 use futures::stream;
 use lightning::ln::PaymentSecret;
 use lightning_invoice::{Currency, Invoice, InvoiceBuilder, SignedRawInvoice, DEFAULT_EXPIRY_TIME};
 use ln_gateway::{
     gatewayd::lnrpc_client::{GetRouteHintsResponse, HtlcStream, ILnRpcClient},
     gatewaylnrpc::{
-        CompleteHtlcsRequest, CompleteHtlcsResponse, GetPubKeyResponse, PayInvoiceRequest,
-        PayInvoiceResponse, SubscribeInterceptHtlcsRequest,
+        CompleteHtlcsRequest, CompleteHtlcsResponse, CreateOfferRequest, CreateOfferResponse,
+        FetchInvoiceRequest, FetchInvoiceResponse, GetLiquidityResponse, GetNewAddressResponse,
+        GetPaymentFeaturesResponse, GetPaymentStatusRequest, GetPaymentStatusResponse,
+        GetPubKeyResponse, GetTransactionBlockRequest, GetTransactionBlockResponse,
+        PayInvoiceRequest, PayInvoiceResponse, SubscribeInterceptHtlcsRequest,
+        WaitOfferPaymentRequest, WaitOfferPaymentResponse, WithdrawOnchainRequest,
+        WithdrawOnchainResponse,
     },
     ln::{LightningError, LnRpc},
+    LnGatewayError,
 };
 use mint_client::modules::ln::contracts::Preimage;
 use rand::rngs::OsRng;
@@ -104,6 +111,10 @@ impl LnRpc for FakeLightningTest {
     async fn route_hints(&self) -> std::result::Result<Vec<RouteHint>, Error> {
         Ok(vec![RouteHint(vec![])])
     }
+
+    async fn liquidity(&self) -> std::result::Result<GatewayLiquidity, Error> {
+        Ok(GatewayLiquidity::default())
+    }
 }
 
 #[async_trait]
@@ -120,18 +131,41 @@ impl ILnRpcClient for FakeLightningTest {
         })
     }
 
+    async fn liquidity(&self) -> ln_gateway::Result<GetLiquidityResponse> {
+        // The fake node never runs out of liquidity
+        Ok(GetLiquidityResponse {
+            inbound_msat: Amount::from_sats(21_000_000 * 100_000_000).msats,
+            outbound_msat: Amount::from_sats(21_000_000 * 100_000_000).msats,
+        })
+    }
+
+    async fn payment_features(&self) -> ln_gateway::Result<GetPaymentFeaturesResponse> {
+        Ok(GetPaymentFeaturesResponse { multi_part: false })
+    }
+
     async fn pay(&self, invoice: PayInvoiceRequest) -> ln_gateway::Result<PayInvoiceResponse> {
         let signed = invoice.invoice.parse::<SignedRawInvoice>().unwrap();
-        *self.amount_sent.lock().unwrap() += Invoice::from_signed(signed)
+        let amount = Invoice::from_signed(signed)
             .unwrap()
             .amount_milli_satoshis()
             .unwrap();
+        *self.amount_sent.lock().unwrap() += amount;
 
         Ok(PayInvoiceResponse {
             preimage: self.preimage.0.to_vec(),
+            parts: 1,
+            amount_sent_msat: amount,
         })
     }
 
+    async fn payment_status(
+        &self,
+        _request: GetPaymentStatusRequest,
+    ) -> ln_gateway::Result<GetPaymentStatusResponse> {
+        // Payments of the fake node complete immediately, none are ever in flight
+        Ok(GetPaymentStatusResponse::default())
+    }
+
     async fn subscribe_htlcs<'a>(
         &self,
         _subscription: SubscribeInterceptHtlcsRequest,
@@ -145,4 +179,54 @@ impl ILnRpcClient for FakeLightningTest {
     ) -> ln_gateway::Result<CompleteHtlcsResponse> {
         Ok(CompleteHtlcsResponse {})
     }
+
+    async fn fetch_invoice(
+        &self,
+        _request: FetchInvoiceRequest,
+    ) -> ln_gateway::Result<FetchInvoiceResponse> {
+        Err(LnGatewayError::Other(anyhow!(
+            "The fake node doesn't support offers"
+        )))
+    }
+
+    async fn create_offer(
+        &self,
+        _request: CreateOfferRequest,
+    ) -> ln_gateway::Result<CreateOfferResponse> {
+        Err(LnGatewayError::Other(anyhow!(
+            "The fake node doesn't support offers"
+        )))
+    }
+
+    async fn wait_offer_payment(
+        &self,
+        _request: WaitOfferPaymentRequest,
+    ) -> ln_gateway::Result<WaitOfferPaymentResponse> {
+        // Offers can't be created, so they are never paid
+        futures::future::pending().await
+    }
+
+    async fn new_address(&self) -> ln_gateway::Result<GetNewAddressResponse> {
+        Err(LnGatewayError::Other(anyhow!(
+            "The fake node has no on-chain wallet"
+        )))
+    }
+
+    async fn withdraw_onchain(
+        &self,
+        _request: WithdrawOnchainRequest,
+    ) -> ln_gateway::Result<WithdrawOnchainResponse> {
+        Err(LnGatewayError::Other(anyhow!(
+            "The fake node has no on-chain wallet"
+        )))
+    }
+
+    async fn transaction_block(
+        &self,
+        _request: GetTransactionBlockRequest,
+    ) -> ln_gateway::Result<GetTransactionBlockResponse> {
+        Err(LnGatewayError::Other(anyhow!(
+            "The fake node has no on-chain wallet"
+        )))
+    }
 }
@@ -4,14 +4,26 @@ use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 
 use aead::{encrypted_read, encrypted_write, get_key};
-use clap::{Parser, Subcommand};
+use bitcoin::hashes::{sha256, Hash};
+use clap::{Args, Parser, Subcommand};
+use fedimint_api::bitcoin_rpc::BitcoindRpcBackend;
 use fedimint_api::task::TaskGroup;
 use fedimint_api::Amount;
+use fedimint_mint::config::NoteExpiry;
+use fedimint_mint::{MintGenParams, NoteExpiryParams};
 use fedimint_server::config::io::{
-    create_cert, encrypted_json_write, run_dkg, write_nonprivate_configs, PRIVATE_CONFIG,
-    SALT_FILE, TLS_PK,
+    create_cert, encrypted_json_write, parse_peer_params, run_dkg, validate_certs,
+    write_nonprivate_configs, PRIVATE_CONFIG, SALT_FILE, TLS_CERT, TLS_PK,
 };
+use fedimint_wallet::coin_selection::CoinSelection;
+use fedimint_wallet::config::{
+    FeeConsensus, PegInFinalityDelay, RecoveryTimelock, TimelockedRecovery,
+};
+use fedimint_wallet::keys::CompressedPublicKey;
+use fedimint_wallet::{PegInDescriptor, WalletGenParams};
 use fedimintd::*;
+use itertools::Itertools;
+use tokio::net::lookup_host;
 use tokio_rustls::rustls;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
@@ -65,34 +77,27 @@ enum Command {
         #[arg(long = "bind-api", default_value = "127.0.0.1:8174")]
         bind_api: SocketAddr,
 
-        /// Federation name, same for all peers
-        #[arg(long = "federation-name", default_value = "Hals_trusty_mint")]
-        federation_name: String,
-
-        /// Comma-separated list of connection certs from all peers (including
-        /// ours)
-        #[arg(long = "certs", value_delimiter = ',')]
-        certs: Vec<String>,
-
-        /// Max denomination of notes issued by the federation (in millisats)
-        /// default = 1 BTC
-        #[arg(long = "max_denomination", default_value = "100000000000")]
-        max_denomination: Amount,
-
-        /// The bitcoin network that fedimint will be running on
-        #[arg(long = "network", default_value = "regtest")]
-        network: bitcoin::network::constants::Network,
-
-        /// The number of confirmations a deposit transaction requires before
-        /// accepted by the federation
-        #[arg(long = "finalty", default_value = "10")]
-        finality_delay: u32,
+        #[command(flatten)]
+        params: DkgParams,
 
         /// The password that encrypts the configs, will prompt if not passed in
         #[arg(env = "FM_PASSWORD")]
         password: Option<String>,
     },
 
+    /// Checks the certs and parameters of a DKG without running it, so all
+    /// peers can make sure it will succeed before anyone starts it. Prints a
+    /// fingerprint of the parameters that all peers have to agree on.
+    Validate {
+        /// Directory containing our cert, checks that it is among the certs if
+        /// given
+        #[arg(long = "out-dir")]
+        dir_out_path: Option<PathBuf>,
+
+        #[command(flatten)]
+        params: DkgParams,
+    },
+
     ConfigDecrypt {
         /// Encrypted config file
         #[arg(long = "in-file")]
@@ -126,6 +131,115 @@ enum Command {
     },
 }
 
+/// Parameters of the DKG, all but the bitcoind RPCs have to be the same for
+/// all peers
+#[derive(Args)]
+struct DkgParams {
+    /// Federation name, same for all peers
+    #[arg(long = "federation-name", default_value = "Hals_trusty_mint")]
+    federation_name: String,
+
+    /// Comma-separated list of connection certs from all peers (including
+    /// ours)
+    #[arg(long = "certs", value_delimiter = ',')]
+    certs: Vec<String>,
+
+    /// Max denomination of notes issued by the federation (in millisats)
+    /// default = 1 BTC
+    #[arg(long = "max_denomination", default_value = "100000000000")]
+    max_denomination: Amount,
+
+    /// Note denominations are the powers of this base up to the max
+    /// denomination
+    #[arg(long = "denomination_base", default_value = "2")]
+    denomination_base: u64,
+
+    /// Limits the number of note denominations, dropping the largest ones
+    #[arg(long = "max_tier_count")]
+    max_tier_count: Option<u16>,
+
+    /// Rotates the mint's signing keys every this many blocks and lets
+    /// notes of old key epochs expire, notes never expire if not set
+    #[arg(long = "key-epoch-blocks")]
+    key_epoch_blocks: Option<u32>,
+
+    /// Number of key epochs after the one of their issuance notes stay
+    /// redeemable
+    #[arg(long = "note-validity-key-epochs", default_value = "2")]
+    note_validity_key_epochs: u32,
+
+    /// Number of signing keysets to generate if notes expire, the last one
+    /// stays in use indefinitely and notes signed with it never expire. The
+    /// mint logs an error for every issuance once it is reached.
+    #[arg(long = "key-epochs", default_value = "12")]
+    key_epochs: u32,
+
+    /// The bitcoin network that fedimint will be running on
+    #[arg(long = "network", default_value = "regtest")]
+    network: bitcoin::network::constants::Network,
+
+    /// The number of confirmations a deposit transaction requires before
+    /// accepted by the federation
+    #[arg(long = "finalty", default_value = "10")]
+    finality_delay: u32,
+
+    /// Comma-separated list of `<min amount in sats>:<finality delay>`,
+    /// peg-ins of at least the amount need that many confirmations
+    #[arg(long = "peg-in-finality-delays", value_delimiter = ',')]
+    peg_in_finality_delays: Vec<PegInFinalityDelay>,
+
+    /// Descriptor all funds can be swept to in an emergency once a
+    /// threshold of guardians approved it twice, no sweeps if not set
+    #[arg(long = "recovery-descriptor")]
+    recovery_descriptor: Option<PegInDescriptor>,
+
+    /// Parts per million of each peg-in charged to fund the on-chain fee
+    /// reserve
+    #[arg(long = "peg-in-reserve-ppm", default_value = "0")]
+    peg_in_reserve_ppm: u64,
+
+    /// Parts per million of each peg-out charged to fund the on-chain fee
+    /// reserve
+    #[arg(long = "peg-out-reserve-ppm", default_value = "0")]
+    peg_out_reserve_ppm: u64,
+
+    /// Comma-separated list of the guardians' cold keys that can spend
+    /// the federation's funds once the cold recovery timelock expired,
+    /// no recovery path if empty
+    #[arg(long = "cold-recovery-keys", value_delimiter = ',')]
+    cold_recovery_keys: Vec<CompressedPublicKey>,
+
+    /// Number of cold keys needed to recover, defaults to all of them
+    #[arg(long = "cold-recovery-threshold")]
+    cold_recovery_threshold: Option<usize>,
+
+    /// `older:<blocks>` a UTXO has to be left unspent or `after:<block
+    /// height>` until the cold keys can spend. With `older` the federation
+    /// moves UTXOs after half the timelock, if that fails the cold keys can
+    /// spend them while the federation is still running.
+    #[arg(long = "cold-recovery-timelock")]
+    cold_recovery_timelock: Option<RecoveryTimelock>,
+
+    /// How UTXOs are chosen to fund peg-outs: `minimize-inputs`,
+    /// `consolidate:<max sats/kvB>:<max inputs>` or
+    /// `avoid-recent-deposits:<min amount in sats>:<min age in blocks>`
+    #[arg(long = "coin-selection", default_value = "minimize-inputs")]
+    coin_selection: CoinSelection,
+
+    /// Comma-separated, prioritized list of bitcoind RPC urls of this
+    /// guardian, calls fail over to the next one on errors. Overrides the
+    /// bitcoin backend environment variables if set
+    #[arg(long = "bitcoind-rpcs", value_delimiter = ',')]
+    bitcoind_rpcs: Vec<Url>,
+
+    /// Bitcoin backend of this guardian if it doesn't set `--bitcoind-rpcs`,
+    /// as `<kind>:<url>` with kind `bitcoind`, `electrum`, `esplora` or
+    /// `compact_filters`. Overrides the bitcoin backend environment variables
+    /// if set
+    #[arg(long = "bitcoin-backend")]
+    bitcoin_backend: Option<BitcoindRpcBackend>,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt()
@@ -150,27 +264,25 @@ async fn main() -> anyhow::Result<()> {
         }
         Command::Run {
             dir_out_path,
-            federation_name,
-            certs,
             bind_p2p,
             bind_api,
-            max_denomination,
-            network,
-            finality_delay,
+            params,
             password,
         } => {
+            let (mint_params, wallet_params) = params.module_params()?;
+
             let key = get_key(password, dir_out_path.join(SALT_FILE))?;
             let pk_bytes = encrypted_read(&key, dir_out_path.join(TLS_PK))?;
             let server = if let Ok(v) = run_dkg(
                 bind_p2p,
                 bind_api,
                 &dir_out_path,
-                federation_name,
-                certs,
+                params.federation_name,
+                params.certs,
                 rustls::PrivateKey(pk_bytes),
                 &mut task_group,
                 CODE_VERSION,
-                configure_modules(max_denomination, network, finality_delay),
+                configure_modules(mint_params, wallet_params),
                 module_registry(),
             )
             .await
@@ -184,6 +296,10 @@ async fn main() -> anyhow::Result<()> {
             encrypted_json_write(&server.private, &key, dir_out_path.join(PRIVATE_CONFIG))?;
             write_nonprivate_configs(&server, dir_out_path, &module_registry())
         }
+        Command::Validate {
+            dir_out_path,
+            params,
+        } => validate(dir_out_path, params).await,
         Command::VersionHash => Ok(println!("{CODE_VERSION}")),
         Command::ConfigDecrypt {
             in_file,
@@ -218,6 +334,139 @@ async fn main() -> anyhow::Result<()> {
     }
 }
 
+impl DkgParams {
+    /// Checks the parameters of the modules and builds them
+    fn module_params(&self) -> anyhow::Result<(MintGenParams, WalletGenParams)> {
+        anyhow::ensure!(
+            self.key_epoch_blocks != Some(0),
+            "Key epochs have to be at least one block long"
+        );
+        // Notes of the initial key epoch expire in the first key epoch after their
+        // validity, with fewer keysets the last one is in use by then and no
+        // note ever expires
+        anyhow::ensure!(
+            self.key_epoch_blocks.is_none() || self.note_validity_key_epochs + 2 <= self.key_epochs,
+            "--key-epochs has to be at least --note-validity-key-epochs + 2 for notes to expire"
+        );
+        let mint_params = MintGenParams {
+            note_expiry: self
+                .key_epoch_blocks
+                .map(|key_epoch_blocks| NoteExpiryParams {
+                    policy: NoteExpiry {
+                        key_epoch_blocks,
+                        validity_key_epochs: self.note_validity_key_epochs,
+                    },
+                    key_epochs: self.key_epochs,
+                }),
+            ..MintGenParams::with_denominations(
+                self.denomination_base,
+                self.max_denomination,
+                self.max_tier_count,
+            )?
+        };
+        let timelocked_recovery = if self.cold_recovery_keys.is_empty() {
+            None
+        } else {
+            let timelocked_recovery = TimelockedRecovery {
+                threshold: self
+                    .cold_recovery_threshold
+                    .unwrap_or(self.cold_recovery_keys.len()),
+                cold_keys: self.cold_recovery_keys.clone(),
+                timelock: self.cold_recovery_timelock.ok_or_else(|| {
+                    anyhow::format_err!("--cold-recovery-timelock is required with cold keys")
+                })?,
+            };
+            timelocked_recovery.sanity_check()?;
+            Some(timelocked_recovery)
+        };
+
+        let wallet_params = WalletGenParams {
+            network: self.network,
+            finality_delay: self.finality_delay,
+            peg_in_finality_delays: self.peg_in_finality_delays.clone(),
+            recovery_descriptor: self.recovery_descriptor.clone(),
+            fee_consensus: FeeConsensus {
+                peg_in_reserve_ppm: self.peg_in_reserve_ppm,
+                peg_out_reserve_ppm: self.peg_out_reserve_ppm,
+                ..Default::default()
+            },
+            bitcoind_rpcs: self.bitcoind_rpcs.clone(),
+            bitcoin_backend: self.bitcoin_backend.clone(),
+            timelocked_recovery,
+            coin_selection: self.coin_selection.clone(),
+        };
+        Ok((mint_params, wallet_params))
+    }
+
+    /// Hash of everything the peers have to agree on for the DKG to succeed,
+    /// so they can compare it out-of-band
+    fn fingerprint(&self) -> anyhow::Result<sha256::Hash> {
+        let (mint_params, mut wallet_params) = self.module_params()?;
+        // Every peer uses its own bitcoind
+        wallet_params.bitcoind_rpcs.clear();
+        wallet_params.bitcoin_backend = None;
+        let agreed = serde_json::json!({
+            "federation_name": self.federation_name,
+            "certs": self.certs.iter().sorted().collect::<Vec<_>>(),
+            "mint": mint_params,
+            "wallet": wallet_params,
+        });
+        Ok(sha256::Hash::hash(agreed.to_string().as_bytes()))
+    }
+}
+
+/// Runs all checks of the DKG that don't require the other peers to be
+/// online, printing every problem found
+async fn validate(dir_out_path: Option<PathBuf>, params: DkgParams) -> anyhow::Result<()> {
+    let mut problems = validate_certs(&params.certs);
+
+    if let Some(dir_out_path) = dir_out_path {
+        let our_params = parse_peer_params(fs::read_to_string(dir_out_path.join(TLS_CERT))?)?;
+        if !params.certs.iter().any(|cert| {
+            parse_peer_params(cert.clone()).map_or(false, |params| params.cert == our_params.cert)
+        }) {
+            problems.push("Our cert is not among the certs".to_string());
+        }
+    }
+
+    // Peers only listen once they run the DKG, so we can only check that
+    // their addresses resolve
+    for peer in params
+        .certs
+        .iter()
+        .filter_map(|cert| parse_peer_params(cert.clone()).ok())
+    {
+        for url in [&peer.p2p_url, &peer.api_url] {
+            let resolved = match (url.host_str(), url.port_or_known_default()) {
+                (Some(host), Some(port)) => lookup_host((host, port))
+                    .await
+                    .map(|mut addrs| addrs.next().is_some())
+                    .unwrap_or(false),
+                _ => false,
+            };
+            if !resolved {
+                problems.push(format!("{url} of {} doesn't resolve", peer.name));
+            }
+        }
+    }
+
+    let fingerprint = params.fingerprint();
+    if let Err(e) = &fingerprint {
+        problems.push(format!("Invalid module params: {e}"));
+    }
+
+    if !problems.is_empty() {
+        for problem in &problems {
+            println!("{problem}");
+        }
+        anyhow::bail!("Found {} problems", problems.len());
+    }
+
+    println!("Valid, compare this fingerprint with all peers before running the DKG:");
+    println!("{}", fingerprint?);
+    Ok(())
+}
+
 fn salt_file_path_from_file_path(file_path: &Path) -> PathBuf {
     file_path
         .parent()
@@ -0,0 +1,210 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aead::{encrypt_with_aad, get_key_with_context, LessSafeKey};
+use bitcoin::hashes::hex::ToHex;
+use fedimint_api::db::{
+    Database, IDatabase, IDatabaseTransaction, InvalidDbEntry, InvalidDbEntryReason,
+};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_server::config::io::DB_FILE;
+use fedimint_server::config::io::SALT_FILE;
+use fedimint_server::config::{DatabaseBackend, ServerConfig};
+use fedimint_server::db::check_database_encryption;
+use fedimint_server::encrypted_db::{EncryptedDb, DB_KEY_CONTEXT};
+use fedimint_server::metrics_db::MetricsDb;
+use tracing::warn;
+
+/// Derives the database encryption key from the config password if database
+/// encryption is enabled in `cfg`
+pub fn database_key(
+    cfg: &ServerConfig,
+    data_dir: &Path,
+    password: Option<String>,
+) -> anyhow::Result<Option<LessSafeKey>> {
+    if !cfg.local.encrypt_database {
+        return Ok(None);
+    }
+    let key = get_key_with_context(password, data_dir.join(SALT_FILE), DB_KEY_CONTEXT)?;
+    Ok(Some(key))
+}
+
+/// Opens the database configured in `cfg`, local backends are stored inside
+/// `data_dir`. Values are encrypted if an `encryption_key` is given, operations
+/// are recorded in the metrics registry. Fails if the database was created with
+/// the other encryption setting, see [`check_database_encryption`].
+pub async fn open_database(
+    cfg: &ServerConfig,
+    data_dir: &Path,
+    encryption_key: Option<LessSafeKey>,
+    decoders: ModuleDecoderRegistry,
+) -> anyhow::Result<Database> {
+    let encrypted = encryption_key.is_some();
+    let db = match cfg.local.database {
+        DatabaseBackend::RocksDb => wrap_database(
+            fedimint_rocksdb::RocksDb::open(rocksdb_path(data_dir))?,
+            encryption_key,
+            decoders,
+        ),
+        DatabaseBackend::Sqlite => wrap_database(
+            fedimint_sqlite::SqliteDb::open(&sqlite_connection_string(data_dir)).await?,
+            encryption_key,
+            decoders,
+        ),
+        DatabaseBackend::Postgres {
+            ref connection_string,
+        } => wrap_database(
+            fedimint_postgres::PostgresDb::open(connection_string).await?,
+            encryption_key,
+            decoders,
+        ),
+    };
+    check_database_encryption(&db, encrypted).await?;
+    Ok(db)
+}
+
+fn wrap_database(
+    db: impl IDatabase + 'static,
+    encryption_key: Option<LessSafeKey>,
+    decoders: ModuleDecoderRegistry,
+) -> Database {
+    // Metrics are recorded below the encryption so written bytes reflect what
+    // actually ends up on disk
+    let db = MetricsDb::new(db);
+    match encryption_key {
+        Some(key) => Database::new(EncryptedDb::new(db, key), decoders),
+        None => Database::new(db, decoders),
+    }
+}
+
+/// Lets the backend physically reclaim the space of deleted entries. No
+/// [`Database`] handle to the same database may be open while this runs.
+pub async fn compact_database(cfg: &ServerConfig, data_dir: &Path) -> anyhow::Result<()> {
+    match cfg.local.database {
+        DatabaseBackend::RocksDb => fedimint_rocksdb::RocksDb::compact(rocksdb_path(data_dir))?,
+        DatabaseBackend::Sqlite => {
+            fedimint_sqlite::SqliteDb::open(&sqlite_connection_string(data_dir))
+                .await?
+                .compact()
+                .await?
+        }
+        DatabaseBackend::Postgres {
+            ref connection_string,
+        } => {
+            fedimint_postgres::PostgresDb::open(connection_string)
+                .await?
+                .compact()
+                .await?
+        }
+    }
+    Ok(())
+}
+
+/// Storage used by the database in bytes
+pub async fn database_size(cfg: &ServerConfig, data_dir: &Path) -> anyhow::Result<u64> {
+    match cfg.local.database {
+        DatabaseBackend::RocksDb => dir_size(&rocksdb_path(data_dir)),
+        DatabaseBackend::Sqlite => {
+            // Include the write-ahead log and shared memory files if present
+            let db_file = sqlite_path(data_dir);
+            let mut size = 0;
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = db_file.clone().into_os_string();
+                path.push(suffix);
+                if let Ok(metadata) = std::fs::metadata(path) {
+                    size += metadata.len();
+                }
+            }
+            Ok(size)
+        }
+        DatabaseBackend::Postgres {
+            ref connection_string,
+        } => Ok(fedimint_postgres::PostgresDb::open(connection_string)
+            .await?
+            .size()
+            .await?),
+    }
+}
+
+/// Entry moved out of the database by [`quarantine_entries`]
+#[derive(Debug, serde::Serialize)]
+struct QuarantinedEntry {
+    key: String,
+    value: String,
+    /// Whether `value` is encrypted like in the database
+    encrypted: bool,
+    reason: String,
+}
+
+/// Removes the `entries` (with keys relative to the global keyspace) from the
+/// database after saving them to a JSON file in `data_dir`, returning the path
+/// of the file.
+///
+/// If the database is encrypted with `encryption_key` the values are saved
+/// encrypted the same way, so the file doesn't leak them.
+///
+/// Entries that can't be read are kept, they can't be saved and all of them
+/// are unreadable if e.g. the wrong password was given.
+pub async fn quarantine_entries(
+    db: &Database,
+    data_dir: &Path,
+    encryption_key: Option<&LessSafeKey>,
+    entries: Vec<InvalidDbEntry>,
+) -> anyhow::Result<PathBuf> {
+    let mut dbtx = db.begin_transaction().await;
+
+    let mut quarantined = vec![];
+    for entry in entries {
+        if matches!(entry.reason, InvalidDbEntryReason::Unreadable(_)) {
+            warn!(key = %entry.key.to_hex(), "Not quarantining unreadable database entry");
+            continue;
+        }
+        let Some(value) = dbtx.raw_unchecked().raw_remove_entry(&entry.key).await? else {
+            continue;
+        };
+        let value = match encryption_key {
+            Some(key) => encrypt_with_aad(value, key, &entry.key)?,
+            None => value,
+        };
+        quarantined.push(QuarantinedEntry {
+            key: entry.key.to_hex(),
+            value: value.to_hex(),
+            encrypted: encryption_key.is_some(),
+            reason: entry.reason.to_string(),
+        });
+    }
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let path = data_dir.join(format!("{DB_FILE}-quarantine-{timestamp}.json"));
+    std::fs::write(&path, serde_json::to_string_pretty(&quarantined)?)?;
+
+    // Only delete the entries once they are safely stored in the file
+    dbtx.commit_tx().await?;
+    Ok(path)
+}
+
+fn rocksdb_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(DB_FILE)
+}
+
+fn sqlite_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(DB_FILE).with_extension("sqlite")
+}
+
+fn sqlite_connection_string(data_dir: &Path) -> String {
+    format!("sqlite://{}", sqlite_path(data_dir).display())
+}
+
+fn dir_size(path: &Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
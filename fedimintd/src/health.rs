@@ -0,0 +1,346 @@
+//! Liveness and readiness of the guardian, served over HTTP for orchestrators
+//! and reported to systemd once the guardian is ready
+//!
+//! Besides the overall liveness and readiness every dependency of the guardian
+//! is checked on its own endpoint, so an orchestrator can tell whether to
+//! restart the guardian, its database or its bitcoind.
+
+use std::net::SocketAddr;
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use fedimint_api::db::Database;
+use fedimint_api::task::{sleep, timeout, TaskGroup};
+use fedimint_bitcoind::DynBitcoindRpc;
+use fedimint_server::consensus::ConsensusProgressTracker;
+use fedimint_server::db::LastEpochKey;
+use fedimint_server::net::peers::PeerStatusTracker;
+use serde::Serialize;
+use tokio::select;
+use tracing::{debug, error, info, warn};
+
+/// How often readiness is checked while waiting to notify systemd
+const READY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the database or bitcoind may take to answer a check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Startup progress and dependencies of the guardian, cloned into everything
+/// that advances or reports them
+#[derive(Debug, Clone)]
+pub struct Health {
+    configs_loaded: Arc<AtomicBool>,
+    db: Arc<Mutex<Option<Database>>>,
+    bitcoind: Arc<Mutex<Option<DynBitcoindRpc>>>,
+    /// Set once consensus is started
+    consensus: Arc<Mutex<Option<ConsensusStatus>>>,
+    /// How long an epoch may run without an outcome before consensus counts
+    /// as stalled
+    epoch_timeout: Duration,
+}
+
+#[derive(Debug, Clone)]
+struct ConsensusStatus {
+    peer_status: PeerStatusTracker,
+    /// Number of peers including us needed for consensus
+    threshold: usize,
+    progress: ConsensusProgressTracker,
+}
+
+/// Response of `/health/ready`
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub configs_loaded: bool,
+    pub db_open: bool,
+    pub connected_peers: usize,
+    /// Number of other peers we need to be connected to, unknown until
+    /// consensus is started
+    pub required_peers: Option<usize>,
+}
+
+/// Response of the endpoints checking a single dependency
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckStatus {
+    pub healthy: bool,
+    /// What the check found or why it failed
+    pub message: String,
+}
+
+impl CheckStatus {
+    fn healthy(message: impl Into<String>) -> Self {
+        CheckStatus {
+            healthy: true,
+            message: message.into(),
+        }
+    }
+
+    fn unhealthy(message: impl Into<String>) -> Self {
+        CheckStatus {
+            healthy: false,
+            message: message.into(),
+        }
+    }
+}
+
+impl Health {
+    pub fn new(epoch_timeout: Duration) -> Self {
+        Health {
+            configs_loaded: Default::default(),
+            db: Default::default(),
+            bitcoind: Default::default(),
+            consensus: Default::default(),
+            epoch_timeout,
+        }
+    }
+
+    pub fn set_configs_loaded(&self) {
+        self.configs_loaded.store(true, Ordering::Relaxed);
+    }
+
+    pub fn set_db_open(&self, db: Database) {
+        *self.db.lock().expect("not poisoned") = Some(db);
+    }
+
+    /// Sets the bitcoind backend `/health/bitcoind` checks, separate from the
+    /// one of the wallet so checks don't wait behind its calls
+    pub fn set_bitcoind(&self, bitcoind: DynBitcoindRpc) {
+        *self.bitcoind.lock().expect("not poisoned") = Some(bitcoind);
+    }
+
+    /// Consensus was started, it needs connections to `threshold` peers
+    /// including us to make progress
+    pub fn set_consensus_started(
+        &self,
+        peer_status: PeerStatusTracker,
+        threshold: usize,
+        progress: ConsensusProgressTracker,
+    ) {
+        *self.consensus.lock().expect("not poisoned") = Some(ConsensusStatus {
+            peer_status,
+            threshold,
+            progress,
+        });
+    }
+
+    fn consensus_status(&self) -> Option<ConsensusStatus> {
+        self.consensus.lock().expect("not poisoned").clone()
+    }
+
+    /// Returns the connected and the required number of other peers, the
+    /// latter is unknown until consensus is started
+    fn peer_counts(&self) -> (usize, Option<usize>) {
+        match self.consensus_status() {
+            Some(status) => (
+                status.peer_status.connected_peers(),
+                Some(status.threshold.saturating_sub(1)),
+            ),
+            None => (0, None),
+        }
+    }
+
+    /// The guardian is ready once its configs are loaded, its database is open
+    /// and it is connected to enough peers to reach consensus
+    pub fn readiness(&self) -> Readiness {
+        let configs_loaded = self.configs_loaded.load(Ordering::Relaxed);
+        let db_open = self.db.lock().expect("not poisoned").is_some();
+        let (connected_peers, required_peers) = self.peer_counts();
+
+        Readiness {
+            ready: configs_loaded
+                && db_open
+                && required_peers.map_or(false, |required| connected_peers >= required),
+            configs_loaded,
+            db_open,
+            connected_peers,
+            required_peers,
+        }
+    }
+
+    /// Checks that the database answers a read in time
+    pub async fn check_db(&self) -> CheckStatus {
+        let Some(db) = self.db.lock().expect("not poisoned").clone() else {
+            return CheckStatus::unhealthy("Database is not open yet");
+        };
+
+        let read = async {
+            let mut dbtx = db.begin_read_transaction().await;
+            dbtx.get_value(&LastEpochKey).await
+        };
+        match timeout(CHECK_TIMEOUT, read).await {
+            Ok(Ok(_)) => CheckStatus::healthy("Database answered a read"),
+            Ok(Err(e)) => CheckStatus::unhealthy(format!("Database read failed: {e}")),
+            Err(_) => CheckStatus::unhealthy("Database read timed out"),
+        }
+    }
+
+    /// Checks that the bitcoind backend answers in time and finished its
+    /// initial block download
+    pub async fn check_bitcoind(&self) -> CheckStatus {
+        let Some(bitcoind) = self.bitcoind.lock().expect("not poisoned").clone() else {
+            return CheckStatus::unhealthy("Bitcoin backend is not set up yet");
+        };
+
+        match timeout(CHECK_TIMEOUT, bitcoind.is_synced()).await {
+            Ok(Ok(true)) => CheckStatus::healthy("Bitcoin backend is synced"),
+            Ok(Ok(false)) => {
+                CheckStatus::unhealthy("Bitcoin backend is still in its initial block download")
+            }
+            Ok(Err(e)) => CheckStatus::unhealthy(format!("Bitcoin backend failed: {e}")),
+            Err(_) => CheckStatus::unhealthy("Bitcoin backend did not answer in time"),
+        }
+    }
+
+    /// Checks that we are connected to enough peers to reach consensus
+    pub fn check_peers(&self) -> CheckStatus {
+        match self.peer_counts() {
+            (connected, Some(required)) if connected >= required => CheckStatus::healthy(format!(
+                "Connected to {connected} peers, {required} required"
+            )),
+            (connected, Some(required)) => CheckStatus::unhealthy(format!(
+                "Connected to {connected} peers, {required} required"
+            )),
+            (_, None) => CheckStatus::unhealthy("Consensus is not started yet"),
+        }
+    }
+
+    /// Checks that a running epoch produces an outcome within the epoch
+    /// timeout, consensus that is idle because there is nothing to agree on
+    /// counts as healthy
+    pub fn check_consensus(&self) -> CheckStatus {
+        let Some(status) = self.consensus_status() else {
+            return CheckStatus::unhealthy("Consensus is not started yet");
+        };
+
+        let progress = status.progress.progress();
+        let last_epoch = match progress.last_epoch {
+            Some(epoch) => format!("last processed epoch {epoch}"),
+            None => "no epoch processed yet".to_string(),
+        };
+        match progress.epoch_running_since.map(|since| since.elapsed()) {
+            Some(running) if running > self.epoch_timeout => CheckStatus::unhealthy(format!(
+                "Epoch without outcome for {}s, {last_epoch}",
+                running.as_secs()
+            )),
+            Some(running) => CheckStatus::healthy(format!(
+                "Epoch running for {}s, {last_epoch}",
+                running.as_secs()
+            )),
+            None => CheckStatus::healthy(format!("Idle, {last_epoch}")),
+        }
+    }
+}
+
+/// Serves the health endpoints:
+/// - `/health/live` succeeds as long as the process responds
+/// - `/health/ready` fails with `503` until [`Health::readiness`] reports the
+///   guardian as ready
+/// - `/health/db`, `/health/bitcoind`, `/health/peers` and `/health/consensus`
+///   fail with `503` while the respective [`Health`] check fails
+pub async fn run_health_server(
+    bind_address: SocketAddr,
+    health: Health,
+    task_group: &mut TaskGroup,
+) -> anyhow::Result<()> {
+    let app = Router::new()
+        .route("/health/live", get(|| async { "OK" }))
+        .route("/health/ready", get(ready))
+        .route("/health/db", get(db))
+        .route("/health/bitcoind", get(bitcoind))
+        .route("/health/peers", get(peers))
+        .route("/health/consensus", get(consensus))
+        .with_state(health);
+    let server = axum::Server::try_bind(&bind_address)?.serve(app.into_make_service());
+    let shutdown_future = task_group.make_handle().make_shutdown_rx().await;
+
+    debug!(%bind_address, "Starting health server");
+    task_group
+        .spawn("health-server", move |_| async move {
+            select! {
+                _ = shutdown_future => {
+                    debug!("Health server shutting down");
+                },
+                Err(err) = server => {
+                    error!(?err, "Health server encountered an error");
+                }
+            }
+        })
+        .await;
+    Ok(())
+}
+
+async fn ready(State(health): State<Health>) -> (StatusCode, String) {
+    let readiness = health.readiness();
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        serde_json::to_string(&readiness).expect("serialization can't fail"),
+    )
+}
+
+async fn db(State(health): State<Health>) -> (StatusCode, String) {
+    check_response(health.check_db().await)
+}
+
+async fn bitcoind(State(health): State<Health>) -> (StatusCode, String) {
+    check_response(health.check_bitcoind().await)
+}
+
+async fn peers(State(health): State<Health>) -> (StatusCode, String) {
+    check_response(health.check_peers())
+}
+
+async fn consensus(State(health): State<Health>) -> (StatusCode, String) {
+    check_response(health.check_consensus())
+}
+
+fn check_response(check: CheckStatus) -> (StatusCode, String) {
+    let status = if check.healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        serde_json::to_string(&check).expect("serialization can't fail"),
+    )
+}
+
+/// Sends `READY=1` to systemd once the guardian is ready if it was started by
+/// a service of `Type=notify`, does nothing otherwise
+pub async fn notify_systemd_when_ready(health: Health, task_group: &mut TaskGroup) {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    task_group
+        .spawn("systemd-notify", move |handle| async move {
+            while !health.readiness().ready {
+                if handle.is_shutting_down() {
+                    return;
+                }
+                sleep(READY_POLL_INTERVAL).await;
+            }
+
+            // Abstract socket addresses would need a newer std
+            if socket.to_string_lossy().starts_with('@') {
+                warn!("Abstract NOTIFY_SOCKET addresses are not supported");
+                return;
+            }
+            match UnixDatagram::unbound().and_then(|sock| sock.send_to(b"READY=1\n", &socket)) {
+                Ok(_) => info!("Notified systemd that we are ready"),
+                Err(e) => warn!("Failed to notify systemd: {}", e),
+            }
+        })
+        .await;
+}
@@ -1,37 +1,32 @@
-use bitcoin::Network;
 use fedimint_api::config::{ConfigGenParams, ModuleGenRegistry};
 use fedimint_api::module::DynModuleGen;
-use fedimint_api::{Amount, Tiered};
 use fedimint_ln::LightningGen;
 use fedimint_mint::{MintGen, MintGenParams};
 use fedimint_wallet::{WalletGen, WalletGenParams};
 
+mod daemon;
+pub mod db;
+pub mod health;
+pub mod nostr;
 pub mod ui;
 
+pub use daemon::Fedimintd;
+
 /// Version of the server code (should be the same among peers)
 pub const CODE_VERSION: &str = env!("GIT_HASH");
 
 /// Generates the configuration for the modules configured in the server binary
 pub fn configure_modules(
-    max_denomination: Amount,
-    network: Network,
-    finality_delay: u32,
+    mint_params: MintGenParams,
+    wallet_params: WalletGenParams,
 ) -> ConfigGenParams {
     ConfigGenParams::new()
-        .attach(WalletGenParams {
-            network,
-            // TODO this is not very elegant, but I'm planning to get rid of it in a next commit
-            // anyway
-            finality_delay,
-        })
-        .attach(MintGenParams {
-            mint_amounts: Tiered::gen_denominations(max_denomination)
-                .tiers()
-                .cloned()
-                .collect(),
-        })
+        .attach(wallet_params)
+        .attach(mint_params)
 }
 
+/// Generators of the modules Fedimint maintains, other modules can be attached
+/// with [`Fedimintd::with_module`]
 pub fn module_registry() -> ModuleGenRegistry {
     ModuleGenRegistry::from(vec![
         DynModuleGen::from(WalletGen),
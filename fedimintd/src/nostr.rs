@@ -0,0 +1,102 @@
+//! Opt-in publishing of the federation announcement to Nostr relays, see
+//! [`fedimint_core::nostr`]
+
+use std::time::Duration;
+
+use bitcoin::secp256k1::{KeyPair, Secp256k1, SecretKey, XOnlyPublicKey};
+use fedimint_api::config::ModuleGenRegistry;
+use fedimint_api::db::Database;
+use fedimint_api::task::{sleep, TaskHandle};
+use fedimint_core::api::WsClientConnectInfo;
+use fedimint_core::meta::SignedFederationMeta;
+use fedimint_core::nostr::{publish_event, FederationAnnouncement};
+use fedimint_server::config::ServerConfig;
+use fedimint_server::consensus::signed_federation_meta;
+use tracing::{info, warn};
+use url::Url;
+
+/// How often we check whether the announcement changed
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// Relays may drop events, so the announcement is published again after this
+const REPUBLISH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub struct NostrAnnouncer {
+    relays: Vec<Url>,
+    keypair: KeyPair,
+    guardian_pubkeys: Vec<String>,
+}
+
+impl NostrAnnouncer {
+    /// Announces the federation on `relays` as the guardian holding
+    /// `secret_key`, together with the Nostr keys of the other guardians
+    pub fn new(
+        relays: Vec<Url>,
+        secret_key: SecretKey,
+        other_guardian_pubkeys: Vec<XOnlyPublicKey>,
+    ) -> Self {
+        let keypair = KeyPair::from_secret_key(&Secp256k1::new(), &secret_key);
+        let mut guardian_pubkeys = other_guardian_pubkeys
+            .into_iter()
+            .chain([XOnlyPublicKey::from_keypair(&keypair).0])
+            .map(|pubkey| pubkey.to_string())
+            .collect::<Vec<_>>();
+        guardian_pubkeys.sort();
+        guardian_pubkeys.dedup();
+
+        NostrAnnouncer {
+            relays,
+            keypair,
+            guardian_pubkeys,
+        }
+    }
+
+    /// Publishes the announcement once the guardians signed the federation
+    /// meta and again whenever it changes or [`REPUBLISH_INTERVAL`] passed
+    pub async fn run(
+        self,
+        cfg: ServerConfig,
+        module_gens: ModuleGenRegistry,
+        db: Database,
+        task_handle: TaskHandle,
+    ) {
+        let client_cfg = cfg.consensus.to_config_response(&module_gens).client;
+        let invite_code = WsClientConnectInfo::from(&client_cfg).to_string();
+
+        let mut published: Option<SignedFederationMeta> = None;
+        let mut polls_since_publish = 0;
+        while !task_handle.is_shutting_down() {
+            let meta = signed_federation_meta(&cfg, &mut db.begin_transaction().await).await;
+            let republish = polls_since_publish * POLL_INTERVAL >= REPUBLISH_INTERVAL;
+            if meta.signature.is_some() && (published.as_ref() != Some(&meta) || republish) {
+                let announcement = FederationAnnouncement {
+                    invite_code: invite_code.clone(),
+                    meta: meta.clone(),
+                    guardian_pubkeys: self.guardian_pubkeys.clone(),
+                };
+                self.publish(&announcement).await;
+                published = Some(meta);
+                polls_since_publish = 0;
+            }
+
+            polls_since_publish += 1;
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn publish(&self, announcement: &FederationAnnouncement) {
+        let event = match announcement.to_event(&self.keypair) {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Could not create the federation announcement: {e:#}");
+                return;
+            }
+        };
+
+        for relay in &self.relays {
+            match publish_event(relay, &event).await {
+                Ok(()) => info!(%relay, "Published federation announcement"),
+                Err(e) => warn!(%relay, "Could not publish federation announcement: {e:#}"),
+            }
+        }
+    }
+}
@@ -14,15 +14,17 @@ use axum::{
 use axum_macros::debug_handler;
 use bitcoin::Network;
 use fedimint_api::bitcoin_rpc::BitcoindRpcBackend;
-use fedimint_api::config::{ClientConfig, ModuleGenRegistry};
+use fedimint_api::config::{ClientConfig, ConfigGenParams, ModuleGenRegistry};
 use fedimint_api::task::TaskGroup;
 use fedimint_api::Amount;
 use fedimint_core::api::WsClientConnectInfo;
 use fedimint_core::util::SanitizedUrl;
+use fedimint_mint::MintGenParams;
 use fedimint_server::config::io::{
     create_cert, encrypted_json_write, parse_peer_params, run_dkg, write_nonprivate_configs,
     CONSENSUS_CONFIG, JSON_EXT, PRIVATE_CONFIG, SALT_FILE, TLS_PK,
 };
+use fedimint_wallet::WalletGenParams;
 use http::StatusCode;
 use qrcode_generator::QrCodeEcc;
 use serde::Deserialize;
@@ -33,7 +35,7 @@ use tokio_rustls::rustls;
 use tracing::{debug, error};
 use url::Url;
 
-use crate::{configure_modules, module_registry, CODE_VERSION};
+use crate::{configure_modules, CODE_VERSION};
 
 #[derive(Deserialize, Debug, Clone)]
 #[allow(dead_code)]
@@ -168,6 +170,7 @@ async fn post_guardians(
     let mut dkg_task_group = state.task_group.make_subgroup().await;
     state.dkg_task_group = Some(dkg_task_group.clone());
     let module_gens = state.module_gens.clone();
+    let extra_gen_params = state.extra_gen_params.clone();
     state
         .task_group
         .spawn("admin UI running DKG", move |_| async move {
@@ -183,8 +186,12 @@ async fn post_guardians(
                 rustls::PrivateKey(pk_bytes),
                 &mut dkg_task_group,
                 CODE_VERSION,
-                configure_modules(max_denomination, params.network, params.finality_delay),
-                module_registry(),
+                configure_modules(
+                    MintGenParams::new(max_denomination),
+                    WalletGenParams::new(params.network, params.finality_delay),
+                )
+                .extend(extra_gen_params),
+                module_gens.clone(),
             )
             .await;
 
@@ -240,6 +247,14 @@ async fn params_page(
                 let url_str = format!("{}", SanitizedUrl::new_borrowed(&url));
                 ("electrum", url_str)
             }
+            Ok(BitcoindRpcBackend::Esplora(url)) => {
+                let url_str = format!("{}", SanitizedUrl::new_borrowed(&url));
+                ("esplora", url_str)
+            }
+            Ok(BitcoindRpcBackend::CompactFilters(url)) => {
+                let url_str = format!("{}", SanitizedUrl::new_borrowed(&url));
+                ("bitcoind (compact filters)", url_str)
+            }
             Err(e) => ("error", e.to_string()),
         };
     UrlConnection {
@@ -361,6 +376,8 @@ struct State {
     task_group: TaskGroup,
     dkg_task_group: Option<TaskGroup>,
     module_gens: ModuleGenRegistry,
+    /// Params of modules attached to the daemon besides the built-in ones
+    extra_gen_params: ConfigGenParams,
     dkg_state: Option<DkgState>,
 }
 type MutableState = Arc<Mutex<State>>;
@@ -385,6 +402,7 @@ pub async fn run_ui(
     password: String,
     task_group: TaskGroup,
     module_gens: ModuleGenRegistry,
+    extra_gen_params: ConfigGenParams,
 ) {
     let state = Arc::new(Mutex::new(State {
         params: None,
@@ -394,6 +412,7 @@ pub async fn run_ui(
         task_group: task_group.clone(),
         dkg_task_group: None,
         module_gens,
+        extra_gen_params,
         dkg_state: None,
     }));
 
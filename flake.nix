@@ -534,8 +534,11 @@
             "fedimint-core"
             "fedimint-derive"
             "fedimint-dbtool"
+            "fedimint-metrics"
+            "fedimint-postgres"
             "fedimint-rocksdb"
             "fedimint-server"
+            "fedimint-sqlite"
             "gateway/ln-gateway"
             "modules"
           ];
@@ -562,6 +565,7 @@
             "fedimint-core"
             "fedimint-derive"
             "fedimint-dbtool"
+            "fedimint-metrics"
             "fedimint-rocksdb"
             "fedimint-server"
             "fedimint-build"
@@ -577,6 +581,7 @@
 
           pkgs = {
             mint-client = { };
+            fedimint-indexeddb = { };
           } // lib.optionalAttrs (target.name != "wasm32-unknown-unknown") {
             # broken on wasm32
             fedimint-sqlite = { };
@@ -592,6 +597,7 @@
             "fedimint-core"
             "fedimint-derive"
             "fedimint-dbtool"
+            "fedimint-indexeddb"
             "fedimint-rocksdb"
             "fedimint-sqlite"
             "modules"
@@ -0,0 +1,21 @@
+//! JSON-RPC requests as sent to the API, decoded the way the `/transaction`
+//! endpoint does
+#![no_main]
+
+use fedimint_core::transaction::SerdeTransaction;
+use jsonrpsee_types::{Params, Request};
+use libfuzzer_sys::fuzz_target;
+use serde_json::value::RawValue;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(request) = serde_json::from_slice::<Request>(data) else {
+        return;
+    };
+    let params = Params::new(request.params.as_deref().map(RawValue::get));
+    let Ok(param) = params.one::<serde_json::Value>() else {
+        return;
+    };
+    if let Ok(tx) = serde_json::from_value::<SerdeTransaction>(param) {
+        let _ = tx.try_into_inner(&fedimint_fuzz::decoders());
+    }
+});
@@ -0,0 +1,31 @@
+//! Consensus encodings received from peers and clients, the first byte selects
+//! the type to decode
+#![no_main]
+
+use std::io::Cursor;
+
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_core::epoch::{ConsensusItem, SignedEpochOutcome};
+use fedimint_core::transaction::Transaction;
+use libfuzzer_sys::fuzz_target;
+
+fn decode<T: Decodable + Encodable>(data: &[u8]) {
+    let decoders = fedimint_fuzz::decoders();
+    if let Ok(value) = T::consensus_decode(&mut Cursor::new(data), &decoders) {
+        // Whatever decodes is processed further, which includes encoding it
+        value
+            .consensus_encode_to_vec()
+            .expect("Encoding to vec can't fail");
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((selector, data)) = data.split_first() else {
+        return;
+    };
+    match selector % 3 {
+        0 => decode::<Transaction>(data),
+        1 => decode::<ConsensusItem>(data),
+        _ => decode::<SignedEpochOutcome>(data),
+    }
+});
@@ -0,0 +1,11 @@
+//! Peer certs pasted by guardians before the DKG
+#![no_main]
+
+use fedimint_server::config::io::parse_peer_params;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(cert) = std::str::from_utf8(data) {
+        let _ = parse_peer_params(cert.to_string());
+    }
+});
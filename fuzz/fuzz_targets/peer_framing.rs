@@ -0,0 +1,17 @@
+//! Byte streams received on peer connections, split into frames and
+//! deserialized into epoch messages
+#![no_main]
+
+use bytes::BytesMut;
+use fedimint_server::net::framed::BincodeCodec;
+use fedimint_server::net::peers::PeerMessage;
+use fedimint_server::EpochMessage;
+use libfuzzer_sys::fuzz_target;
+use tokio_util::codec::Decoder;
+
+fuzz_target!(|data: &[u8]| {
+    let mut codec = BincodeCodec::<PeerMessage<EpochMessage>>::default();
+    let mut buf = BytesMut::from(data);
+    // Connections are dropped on the first error
+    while let Ok(Some(_)) = codec.decode(&mut buf) {}
+});
@@ -0,0 +1,19 @@
+//! Shared setup of the fuzz targets in `fuzz_targets/`
+
+use fedimint_api::core::{
+    LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
+    LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_ln::common::LightningDecoder;
+use fedimint_mint::common::MintDecoder;
+use fedimint_wallet::common::WalletDecoder;
+
+/// Decoders of the modules a default federation runs
+pub fn decoders() -> ModuleDecoderRegistry {
+    ModuleDecoderRegistry::from_iter([
+        (LEGACY_HARDCODED_INSTANCE_ID_LN, LightningDecoder.into()),
+        (LEGACY_HARDCODED_INSTANCE_ID_MINT, MintDecoder.into()),
+        (LEGACY_HARDCODED_INSTANCE_ID_WALLET, WalletDecoder.into()),
+    ])
+}
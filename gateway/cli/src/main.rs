@@ -7,11 +7,15 @@ use ln_gateway::{
     config::GatewayConfig,
     rpc::{
         rpc_client::RpcClient, BackupPayload, BalancePayload, ConnectFedPayload,
-        DepositAddressPayload, DepositPayload, RestorePayload, WithdrawPayload,
+        ContractProofPayload, DepositAddressPayload, DepositPayload, DisconnectFedPayload,
+        ListPaymentsPayload, RestorePayload, WithdrawPayload,
     },
 };
+use mint_client::modules::ln::contracts::ContractId;
+use mint_client::modules::ln::{GatewayFees, GatewayLimits};
 use mint_client::modules::wallet::txoproof::TxOutProof;
 use mint_client::utils::from_hex;
+use mint_client::GatewayRebalanceBand;
 use url::Url;
 
 #[derive(Parser)]
@@ -65,15 +69,60 @@ pub enum Commands {
         /// The address to send the funds to
         address: Address,
     },
+    /// List the payments the gateway routed or failed to route, newest first
+    Payments {
+        federation_id: FederationId,
+        /// Maximum number of payments to list
+        #[clap(long, default_value_t = 50)]
+        limit: usize,
+    },
     /// Register federation with the gateway
     ConnectFed {
         /// ConnectInfo code to connect to the federation
         connect: String,
+        /// Flat fee in msat charged per payment routed for this federation
+        #[clap(long, default_value_t = 0)]
+        base_fee_msat: u32,
+        /// Fee in millionths of the payment amount charged per payment routed
+        /// for this federation
+        #[clap(long, default_value_t = 0)]
+        fee_ppm: u32,
+        /// Smallest payment in msat the gateway routes for this federation
+        #[clap(long, default_value_t = 0)]
+        min_payment_msat: u64,
+        /// Largest payment in msat the gateway routes for this federation
+        #[clap(long)]
+        max_payment_msat: Option<u64>,
+        /// Largest total amount in msat of outgoing payments in flight at the
+        /// same time for this federation
+        #[clap(long)]
+        max_in_flight_msat: Option<u64>,
+        /// Lowest e-cash balance in msat the gateway keeps for this
+        /// federation, pegging in from its lightning node below it
+        #[clap(long, requires = "max_ecash_msat")]
+        min_ecash_msat: Option<u64>,
+        /// Highest e-cash balance in msat the gateway keeps for this
+        /// federation, pegging out to its lightning node above it
+        #[clap(long, requires = "min_ecash_msat")]
+        max_ecash_msat: Option<u64>,
+        /// Submit refunds of timed out outgoing contracts on behalf of
+        /// offline users of this federation
+        #[clap(long)]
+        watchtower: bool,
     },
+    /// Stop serving a federation. Its ecash is kept and available again once
+    /// the federation gets connected anew.
+    DisconnectFed { federation_id: FederationId },
     /// Make a backup of snapshot of all ecash
     Backup { federation_id: FederationId },
     /// Restore ecash from last available snapshot or from scratch
     Restore { federation_id: FederationId },
+    /// Prove the settlement of a contract the gateway funded or claimed, e.g.
+    /// to resolve a dispute with a user
+    ContractProof {
+        federation_id: FederationId,
+        contract_id: ContractId,
+    },
 }
 
 #[tokio::main]
@@ -176,17 +225,57 @@ async fn main() {
 
             print_response(response).await;
         }
-        Commands::ConnectFed { connect } => {
+        Commands::ConnectFed {
+            connect,
+            base_fee_msat,
+            fee_ppm,
+            min_payment_msat,
+            max_payment_msat,
+            max_in_flight_msat,
+            min_ecash_msat,
+            max_ecash_msat,
+            watchtower,
+        } => {
+            let rebalance = min_ecash_msat
+                .zip(max_ecash_msat)
+                .map(|(min_ecash, max_ecash)| GatewayRebalanceBand {
+                    min_ecash: fedimint_api::Amount::from_msats(min_ecash),
+                    max_ecash: fedimint_api::Amount::from_msats(max_ecash),
+                });
             let response = client
                 .connect_federation(
                     source_password(cli.rpcpassword),
-                    ConnectFedPayload { connect },
+                    ConnectFedPayload {
+                        connect,
+                        fees: GatewayFees {
+                            base_msat: base_fee_msat,
+                            proportional_millionths: fee_ppm,
+                        },
+                        limits: GatewayLimits {
+                            min_payment: fedimint_api::Amount::from_msats(min_payment_msat),
+                            max_payment: max_payment_msat.map(fedimint_api::Amount::from_msats),
+                            max_in_flight: max_in_flight_msat.map(fedimint_api::Amount::from_msats),
+                        },
+                        rebalance,
+                        watchtower,
+                    },
                 )
                 .await
                 .expect("Failed to connect federation");
 
             print_response(response).await;
         }
+        Commands::DisconnectFed { federation_id } => {
+            let response = client
+                .disconnect_federation(
+                    source_password(cli.rpcpassword),
+                    DisconnectFedPayload { federation_id },
+                )
+                .await
+                .expect("Failed to disconnect federation");
+
+            print_response(response).await;
+        }
         Commands::Backup { federation_id } => {
             let response = client
                 .backup(
@@ -209,6 +298,40 @@ async fn main() {
 
             print_response(response).await;
         }
+        Commands::Payments {
+            federation_id,
+            limit,
+        } => {
+            let response = client
+                .list_payments(
+                    source_password(cli.rpcpassword),
+                    ListPaymentsPayload {
+                        federation_id,
+                        limit,
+                    },
+                )
+                .await
+                .expect("Failed to list payments");
+
+            print_response(response).await;
+        }
+        Commands::ContractProof {
+            federation_id,
+            contract_id,
+        } => {
+            let response = client
+                .contract_proof(
+                    source_password(cli.rpcpassword),
+                    ContractProofPayload {
+                        federation_id,
+                        contract_id,
+                    },
+                )
+                .await
+                .expect("Failed to get contract proof");
+
+            print_response(response).await;
+        }
     }
 }
 
@@ -1,11 +1,14 @@
 use std::{sync::Arc, time::Duration};
 
+use anyhow::anyhow;
 use bitcoin::{Address, Transaction};
 use bitcoin_hashes::sha256;
 use fedimint_api::{task::TaskGroup, Amount, OutPoint, TransactionId};
+use mint_client::ln::{proof::ContractProof, GatewayPayment};
 use mint_client::modules::ln::route_hints::RouteHint;
+use mint_client::modules::ln::GatewayLiquidity;
 use mint_client::modules::{
-    ln::contracts::{ContractId, Preimage},
+    ln::contracts::{outgoing::OutgoingInvoice, ContractId, Preimage},
     wallet::txoproof::TxOutProof,
 };
 use mint_client::{GatewayClient, PaymentParameters};
@@ -14,11 +17,14 @@ use tracing::{debug, info, instrument, warn};
 
 use crate::{ln::LnRpc, rpc::FederationInfo, utils::retry, LnGatewayError, Result};
 
-/// How long a gateway announcement stays valid
-const GW_ANNOUNCEMENT_TTL: Duration = Duration::from_secs(600);
+/// How long a gateway announcement stays valid. The announcement, including
+/// the advertised liquidity, is refreshed every half TTL so clients stop using
+/// the gateway shortly after it goes offline.
+const GW_ANNOUNCEMENT_TTL: Duration = Duration::from_secs(120);
 
 pub struct GatewayActor {
     client: Arc<GatewayClient>,
+    task_group: TaskGroup,
 }
 
 #[derive(Debug, Clone)]
@@ -28,40 +34,59 @@ pub enum BuyPreimage {
 }
 
 impl GatewayActor {
-    pub async fn new(client: Arc<GatewayClient>, route_hints: Vec<RouteHint>) -> Result<Self> {
+    pub async fn new(
+        client: Arc<GatewayClient>,
+        ln_rpc: Arc<dyn LnRpc>,
+        route_hints: Vec<RouteHint>,
+        task_group: TaskGroup,
+    ) -> Result<Self> {
+        // Tasks of the actor run in their own group so they can be stopped when
+        // the federation gets disconnected
+        let mut task_group = task_group.make_subgroup().await;
+
         let register_client = client.clone();
-        tokio::spawn(async move {
-            loop {
-                // Retry gateway registration
-                match retry(
-                    String::from("Register With Federation"),
-                    #[allow(clippy::unit_arg)]
-                    || async {
-                        let gateway_registration = register_client
-                            .config()
-                            .to_gateway_registration_info(route_hints.clone(), GW_ANNOUNCEMENT_TTL);
-                        Ok(register_client
-                            .register_with_federation(gateway_registration.clone())
-                            .await?)
-                    },
-                    Duration::from_secs(1),
-                    5,
-                )
-                .await
-                {
-                    Ok(_) => {
-                        info!("Connected with federation");
-                        tokio::time::sleep(GW_ANNOUNCEMENT_TTL / 2).await;
-                    }
-                    Err(e) => {
-                        warn!("Failed to connect with federation: {}", e);
-                        tokio::time::sleep(GW_ANNOUNCEMENT_TTL / 4).await;
+        task_group
+            .spawn("Register with federation", move |handle| async move {
+                while !handle.is_shutting_down() {
+                    let liquidity = ln_rpc.liquidity().await.unwrap_or_else(|e| {
+                        warn!("Failed to fetch liquidity from LN node: {}", e);
+                        GatewayLiquidity::default()
+                    });
+
+                    // Retry gateway registration
+                    match retry(
+                        String::from("Register With Federation"),
+                        #[allow(clippy::unit_arg)]
+                        || async {
+                            let gateway_registration =
+                                register_client.config().to_gateway_registration_info(
+                                    route_hints.clone(),
+                                    liquidity,
+                                    GW_ANNOUNCEMENT_TTL,
+                                );
+                            Ok(register_client
+                                .register_with_federation(gateway_registration.clone())
+                                .await?)
+                        },
+                        Duration::from_secs(1),
+                        5,
+                    )
+                    .await
+                    {
+                        Ok(_) => {
+                            info!("Connected with federation");
+                            tokio::time::sleep(GW_ANNOUNCEMENT_TTL / 2).await;
+                        }
+                        Err(e) => {
+                            warn!("Failed to connect with federation: {}", e);
+                            tokio::time::sleep(GW_ANNOUNCEMENT_TTL / 4).await;
+                        }
                     }
                 }
-            }
-        });
+            })
+            .await;
 
-        Ok(Self { client })
+        Ok(Self { client, task_group })
     }
 
     async fn fetch_all_notes(&self) {
@@ -227,7 +252,11 @@ impl GatewayActor {
     ) -> Result<Preimage> {
         let rng = rand::rngs::OsRng;
         info!("Awaiting decryption of preimage");
-        match self.client.await_preimage_decryption(out_point).await {
+        match self
+            .client
+            .await_incoming_contract_preimage(out_point, contract_id)
+            .await
+        {
             Ok(preimage) => Ok(preimage),
             Err(error) => {
                 warn!(%error, "Failed to decrypt preimage. Now requesting a refund");
@@ -243,9 +272,18 @@ impl GatewayActor {
     pub async fn buy_preimage_external(
         &self,
         ln_rpc: Arc<dyn LnRpc>,
-        invoice: lightning_invoice::Invoice,
+        invoice: OutgoingInvoice,
         payment_params: &PaymentParameters,
     ) -> Result<Preimage> {
+        let invoice = match invoice {
+            OutgoingInvoice::Bolt11(invoice) => invoice,
+            OutgoingInvoice::Bolt12(_) => {
+                return Err(LnGatewayError::Other(anyhow!(
+                    "Paying BOLT12 invoices is only supported by gatewayd"
+                )));
+            }
+        };
+
         match ln_rpc
             .pay(
                 invoice,
@@ -274,6 +312,28 @@ impl GatewayActor {
             .await?)
     }
 
+    /// Preimage we claimed an outgoing contract with, proving that we paid its
+    /// invoice
+    pub async fn outgoing_contract_preimage(&self, contract_id: ContractId) -> Result<Preimage> {
+        self.client
+            .contract_evidence(contract_id)
+            .await?
+            .preimage
+            .ok_or_else(|| {
+                LnGatewayError::Other(anyhow!("No preimage known for contract {contract_id}"))
+            })
+    }
+
+    pub async fn contract_proof(&self, contract_id: ContractId) -> Result<ContractProof> {
+        Ok(self.client.contract_proof(contract_id).await?)
+    }
+
+    /// Payments we routed or failed to route for this federation, newest
+    /// first
+    pub async fn list_payments(&self, limit: usize) -> Vec<GatewayPayment> {
+        self.client.list_gateway_payments(limit).await
+    }
+
     pub async fn get_deposit_address(&self) -> Result<Address> {
         let rng = rand::rngs::OsRng;
         Ok(self.client.get_new_pegin_address(rng).await)
@@ -348,11 +408,23 @@ impl GatewayActor {
         Ok(self.client.notes().await.total_amount())
     }
 
-    pub fn get_info(&self) -> Result<FederationInfo> {
+    pub async fn get_info(&self) -> Result<FederationInfo> {
         let cfg = self.client.config();
         Ok(FederationInfo {
             federation_id: cfg.client_config.federation_id.clone(),
             mint_pubkey: cfg.redeem_key.x_only_public_key().0,
+            mint_channel_id: cfg.mint_channel_id,
+            balance_msat: self.get_balance().await?.msats,
         })
     }
+
+    /// Short channel id in the route hints of invoices for this federation
+    pub fn mint_channel_id(&self) -> u64 {
+        self.client.config().mint_channel_id
+    }
+
+    /// Stops registering with the federation
+    pub async fn shutdown(&self) {
+        self.task_group.shutdown().await;
+    }
 }
@@ -1,30 +1,48 @@
 use std::{
-    array::TryFromSliceError, collections::HashMap, net::SocketAddr, path::PathBuf, str::FromStr,
-    sync::Arc, time::Duration,
+    array::TryFromSliceError,
+    collections::HashMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
 };
 
 use anyhow::anyhow;
-use bitcoin_hashes::{hex::ToHex, sha256, Hash};
+use bitcoin_hashes::{
+    hex::{FromHex, ToHex},
+    sha256, Hash,
+};
 use clap::Parser;
 use cln_plugin::{options, Builder, Plugin};
 use cln_rpc::{model, ClnRpc};
 use fedimint_api::{task::TaskGroup, Amount};
+use ln_gateway::cln::{channel_liquidity, scid_to_u64};
 use ln_gateway::gatewaylnrpc::{
     complete_htlcs_request::{Action, Cancel, Settle},
     gateway_lightning_server::{GatewayLightning, GatewayLightningServer},
-    CompleteHtlcsRequest, CompleteHtlcsResponse, GetPubKeyRequest, GetPubKeyResponse,
-    PayInvoiceRequest, PayInvoiceResponse, SubscribeInterceptHtlcsRequest,
-    SubscribeInterceptHtlcsResponse,
+    get_payment_status_response::Part,
+    get_route_hints_response::{RouteHint, RouteHintHop},
+    CompleteHtlcsRequest, CompleteHtlcsResponse, CreateOfferRequest, CreateOfferResponse,
+    FetchInvoiceRequest, FetchInvoiceResponse, GetLiquidityRequest, GetLiquidityResponse,
+    GetNewAddressRequest, GetNewAddressResponse, GetPaymentFeaturesRequest,
+    GetPaymentFeaturesResponse, GetPaymentStatusRequest, GetPaymentStatusResponse,
+    GetPubKeyRequest, GetPubKeyResponse, GetRouteHintsRequest, GetRouteHintsResponse,
+    GetTransactionBlockRequest, GetTransactionBlockResponse, PayInvoiceRequest, PayInvoiceResponse,
+    PaymentStatus, SubscribeInterceptHtlcsRequest, SubscribeInterceptHtlcsResponse,
+    WaitOfferPaymentRequest, WaitOfferPaymentResponse, WithdrawOnchainRequest,
+    WithdrawOnchainResponse,
 };
 use serde::{Deserialize, Deserializer, Serialize};
 use thiserror::Error;
 use tokio::{
-    io::{stdin, stdout},
+    io::{stdin, stdout, AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
     sync::{mpsc, oneshot, Mutex},
 };
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{transport::Server, Status};
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 #[derive(Parser)]
 pub struct ClnExtensionOpts {
@@ -99,6 +117,8 @@ pub struct ClnRpcClient {}
 #[allow(dead_code)]
 pub struct ClnRpcService {
     client: Arc<Mutex<ClnRpc>>,
+    /// Path of the RPC socket, used for calls `cln_rpc` has no model for
+    socket: PathBuf,
     interceptor: Arc<ClnHtlcInterceptor>,
     task_group: TaskGroup,
 }
@@ -135,7 +155,7 @@ impl ClnRpcService {
         {
             let config = plugin.configuration();
             let socket = PathBuf::from(config.lightning_dir).join(config.rpc_file);
-            let client = ClnRpc::new(socket).await.expect("connect to ln_socket");
+            let client = ClnRpc::new(&socket).await.expect("connect to ln_socket");
 
             // Parse configurations or read from
             let listen: SocketAddr = match ClnExtensionOpts::try_parse() {
@@ -158,6 +178,7 @@ impl ClnRpcService {
             Ok((
                 Self {
                     client: Arc::new(Mutex::new(client)),
+                    socket,
                     task_group: TaskGroup::new(),
                     interceptor,
                 },
@@ -201,14 +222,163 @@ impl GatewayLightning for ClnRpcService {
             })
     }
 
+    async fn get_route_hints(
+        &self,
+        _request: tonic::Request<GetRouteHintsRequest>,
+    ) -> Result<tonic::Response<GetRouteHintsResponse>, Status> {
+        let mut client = self.client.lock().await;
+
+        let our_pub_key = match client
+            .call(cln_rpc::Request::Getinfo(
+                model::requests::GetinfoRequest {},
+            ))
+            .await
+        {
+            Ok(cln_rpc::Response::Getinfo(model::responses::GetinfoResponse { id, .. })) => id,
+            Ok(_) => panic!("Unexpected response from cln_rpc"),
+            Err(e) => {
+                error!("cln getinfo returned error: {:?}", e);
+                return Err(Status::internal(e.to_string()));
+            }
+        };
+
+        let peers = match client
+            .call(cln_rpc::Request::ListPeers(model::ListpeersRequest {
+                id: None,
+                level: None,
+            }))
+            .await
+        {
+            Ok(cln_rpc::Response::ListPeers(peers)) => peers.peers,
+            Ok(_) => panic!("Unexpected response from cln_rpc"),
+            Err(e) => {
+                error!("cln listpeers returned error: {:?}", e);
+                return Err(Status::internal(e.to_string()));
+            }
+        };
+
+        let active_peer_channels = peers
+            .into_iter()
+            .flat_map(|peer| peer.channels.into_iter().map(move |chan| (peer.id, chan)))
+            .filter_map(|(peer_id, chan)| {
+                if !matches!(
+                    chan.state,
+                    model::ListpeersPeersChannelsState::CHANNELD_NORMAL
+                ) {
+                    return None;
+                }
+
+                let Some(scid) = chan.short_channel_id else {
+                    warn!("Encountered channel without short channel id");
+                    return None;
+                };
+
+                Some((peer_id, scid))
+            })
+            .collect::<Vec<_>>();
+
+        debug!(
+            "Found {} active channels to use as route hints",
+            active_peer_channels.len()
+        );
+
+        let mut route_hints = vec![];
+        for (peer_id, scid) in active_peer_channels {
+            let channels = match client
+                .call(cln_rpc::Request::ListChannels(model::ListchannelsRequest {
+                    short_channel_id: Some(scid),
+                    source: None,
+                    destination: None,
+                }))
+                .await
+            {
+                Ok(cln_rpc::Response::ListChannels(channels)) => channels.channels,
+                Ok(_) => panic!("Unexpected response from cln_rpc"),
+                Err(e) => {
+                    error!("cln listchannels returned error: {:?}", e);
+                    return Err(Status::internal(e.to_string()));
+                }
+            };
+
+            // The channel's direction towards us carries the fees the peer charges
+            let Some(channel) = channels
+                .into_iter()
+                .find(|chan| chan.destination == our_pub_key)
+            else {
+                warn!("Channel {:?} not found in graph", scid);
+                continue;
+            };
+
+            route_hints.push(RouteHint {
+                hops: vec![RouteHintHop {
+                    src_node_id: peer_id.serialize().to_vec(),
+                    short_channel_id: scid_to_u64(scid),
+                    base_msat: channel.base_fee_millisatoshi,
+                    proportional_millionths: channel.fee_per_millionth,
+                    cltv_expiry_delta: channel.delay,
+                    htlc_minimum_msat: channel.htlc_minimum_msat.msat(),
+                    htlc_maximum_msat: channel
+                        .htlc_maximum_msat
+                        .map(|amt| amt.msat())
+                        .unwrap_or_default(),
+                }],
+            });
+        }
+
+        Ok(tonic::Response::new(GetRouteHintsResponse { route_hints }))
+    }
+
+    async fn get_liquidity(
+        &self,
+        _request: tonic::Request<GetLiquidityRequest>,
+    ) -> Result<tonic::Response<GetLiquidityResponse>, Status> {
+        let peers = match self
+            .client
+            .lock()
+            .await
+            .call(cln_rpc::Request::ListPeers(model::ListpeersRequest {
+                id: None,
+                level: None,
+            }))
+            .await
+        {
+            Ok(cln_rpc::Response::ListPeers(peers)) => peers.peers,
+            Ok(_) => panic!("Unexpected response from cln_rpc"),
+            Err(e) => {
+                error!("cln listpeers returned error: {:?}", e);
+                return Err(Status::internal(e.to_string()));
+            }
+        };
+
+        let (inbound, outbound) = channel_liquidity(peers);
+        Ok(tonic::Response::new(GetLiquidityResponse {
+            inbound_msat: inbound.msats,
+            outbound_msat: outbound.msats,
+        }))
+    }
+
+    async fn get_payment_features(
+        &self,
+        _request: tonic::Request<GetPaymentFeaturesRequest>,
+    ) -> Result<tonic::Response<GetPaymentFeaturesResponse>, Status> {
+        // CLN's pay plugin splits payments on its own whenever the invoice allows it
+        Ok(tonic::Response::new(GetPaymentFeaturesResponse {
+            multi_part: true,
+        }))
+    }
+
     async fn pay_invoice(
         &self,
         request: tonic::Request<PayInvoiceRequest>,
     ) -> Result<tonic::Response<PayInvoiceResponse>, tonic::Status> {
+        // CLN never splits payments to invoices that don't support MPP, and the
+        // gateway only disallows splitting for those, so `allow_multi_part`
+        // needs no handling here
         let PayInvoiceRequest {
             invoice,
             max_delay,
             max_fee_percent,
+            allow_multi_part: _,
         } = request.into_inner();
 
         let outcome = self
@@ -232,9 +402,14 @@ impl GatewayLightning for ClnRpcService {
             .await
             .map(|response| match response {
                 cln_rpc::Response::Pay(model::PayResponse {
-                    payment_preimage, ..
+                    payment_preimage,
+                    parts,
+                    amount_sent_msat,
+                    ..
                 }) => PayInvoiceResponse {
                     preimage: payment_preimage.to_vec(),
+                    parts,
+                    amount_sent_msat: amount_sent_msat.msat(),
                 },
                 _ => panic!("Unexpected response from cln pay rpc"),
             })
@@ -246,6 +421,47 @@ impl GatewayLightning for ClnRpcService {
         Ok(tonic::Response::new(outcome))
     }
 
+    async fn get_payment_status(
+        &self,
+        request: tonic::Request<GetPaymentStatusRequest>,
+    ) -> Result<tonic::Response<GetPaymentStatusResponse>, Status> {
+        let GetPaymentStatusRequest { payment_hash } = request.into_inner();
+
+        let params = serde_json::json!({ "payment_hash": payment_hash.to_hex() });
+        let result = call_raw(&self.socket, "listsendpays", params).await?;
+
+        // Every part of a multi-part payment is listed as a separate sendpay
+        let mut preimage = vec![];
+        let mut parts = vec![];
+        for payment in result["payments"].as_array().into_iter().flatten() {
+            let status = match payment["status"].as_str() {
+                Some("pending") => PaymentStatus::Pending,
+                Some("complete") => PaymentStatus::Complete,
+                Some("failed") => PaymentStatus::Failed,
+                status => {
+                    // Never report a part as failed unless we're sure it is
+                    warn!("cln listsendpays returned unknown status {:?}", status);
+                    PaymentStatus::Pending
+                }
+            };
+            if let Some(payment_preimage) = payment["payment_preimage"].as_str() {
+                preimage = Vec::<u8>::from_hex(payment_preimage)
+                    .map_err(|e| Status::internal(e.to_string()))?;
+            }
+            parts.push(Part {
+                amount_msat: parse_msat(&payment["amount_msat"]).unwrap_or_default(),
+                status: status.into(),
+            });
+        }
+
+        let status = payment_status(parts.iter().map(|part| part.status()));
+        Ok(tonic::Response::new(GetPaymentStatusResponse {
+            status: status.into(),
+            preimage,
+            parts,
+        }))
+    }
+
     type SubscribeInterceptHtlcsStream =
         ReceiverStream<Result<SubscribeInterceptHtlcsResponse, Status>>;
 
@@ -328,6 +544,219 @@ impl GatewayLightning for ClnRpcService {
             ));
         }
     }
+
+    async fn fetch_invoice(
+        &self,
+        request: tonic::Request<FetchInvoiceRequest>,
+    ) -> Result<tonic::Response<FetchInvoiceResponse>, Status> {
+        let FetchInvoiceRequest { offer, amount_msat } = request.into_inner();
+
+        let mut params = serde_json::json!({ "offer": offer });
+        if amount_msat != 0 {
+            params["amount_msat"] = amount_msat.into();
+        }
+        let result = call_raw(&self.socket, "fetchinvoice", params).await?;
+
+        let invoice = result["invoice"]
+            .as_str()
+            .ok_or_else(|| Status::internal("cln fetchinvoice returned no invoice"))?;
+        Ok(tonic::Response::new(FetchInvoiceResponse {
+            invoice: invoice.to_owned(),
+        }))
+    }
+
+    async fn create_offer(
+        &self,
+        request: tonic::Request<CreateOfferRequest>,
+    ) -> Result<tonic::Response<CreateOfferResponse>, Status> {
+        let CreateOfferRequest { description } = request.into_inner();
+
+        let params = serde_json::json!({ "amount": "any", "description": description });
+        let result = call_raw(&self.socket, "offer", params).await?;
+
+        let (Some(offer), Some(offer_id)) =
+            (result["bolt12"].as_str(), result["offer_id"].as_str())
+        else {
+            return Err(Status::internal("cln offer returned an incomplete offer"));
+        };
+        let offer_id =
+            Vec::<u8>::from_hex(offer_id).map_err(|e| Status::internal(e.to_string()))?;
+        Ok(tonic::Response::new(CreateOfferResponse {
+            offer: offer.to_owned(),
+            offer_id,
+        }))
+    }
+
+    async fn wait_offer_payment(
+        &self,
+        request: tonic::Request<WaitOfferPaymentRequest>,
+    ) -> Result<tonic::Response<WaitOfferPaymentResponse>, Status> {
+        let WaitOfferPaymentRequest { mut last_pay_index } = request.into_inner();
+
+        loop {
+            let params = serde_json::json!({ "lastpay_index": last_pay_index });
+            let invoice = call_raw(&self.socket, "waitanyinvoice", params).await?;
+
+            let pay_index = invoice["pay_index"]
+                .as_u64()
+                .ok_or_else(|| Status::internal("cln waitanyinvoice returned no pay_index"))?;
+            last_pay_index = pay_index;
+
+            // Skip payments of invoices that weren't requested for an offer
+            let Some(offer_id) = invoice["local_offer_id"].as_str() else {
+                continue;
+            };
+            let offer_id =
+                Vec::<u8>::from_hex(offer_id).map_err(|e| Status::internal(e.to_string()))?;
+            let amount_msat = parse_msat(&invoice["amount_received_msat"])
+                .ok_or_else(|| Status::internal("cln waitanyinvoice returned no amount"))?;
+
+            return Ok(tonic::Response::new(WaitOfferPaymentResponse {
+                pay_index,
+                offer_id,
+                amount_msat,
+            }));
+        }
+    }
+
+    async fn get_new_address(
+        &self,
+        _request: tonic::Request<GetNewAddressRequest>,
+    ) -> Result<tonic::Response<GetNewAddressResponse>, Status> {
+        let params = serde_json::json!({ "addresstype": "bech32" });
+        let result = call_raw(&self.socket, "newaddr", params).await?;
+
+        let address = result["bech32"]
+            .as_str()
+            .ok_or_else(|| Status::internal("cln newaddr returned no address"))?;
+        Ok(tonic::Response::new(GetNewAddressResponse {
+            address: address.to_owned(),
+        }))
+    }
+
+    async fn withdraw_onchain(
+        &self,
+        request: tonic::Request<WithdrawOnchainRequest>,
+    ) -> Result<tonic::Response<WithdrawOnchainResponse>, Status> {
+        let WithdrawOnchainRequest {
+            address,
+            amount_sat,
+        } = request.into_inner();
+
+        let params = serde_json::json!({ "destination": address, "satoshi": amount_sat });
+        let result = call_raw(&self.socket, "withdraw", params).await?;
+
+        let transaction = result["tx"]
+            .as_str()
+            .ok_or_else(|| Status::internal("cln withdraw returned no transaction"))?;
+        let transaction =
+            Vec::<u8>::from_hex(transaction).map_err(|e| Status::internal(e.to_string()))?;
+        Ok(tonic::Response::new(WithdrawOnchainResponse {
+            transaction,
+        }))
+    }
+
+    async fn get_transaction_block(
+        &self,
+        request: tonic::Request<GetTransactionBlockRequest>,
+    ) -> Result<tonic::Response<GetTransactionBlockResponse>, Status> {
+        let GetTransactionBlockRequest { txid } = request.into_inner();
+        let txid = bitcoin::Txid::from_slice(&txid)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?
+            .to_string();
+
+        let result = call_raw(&self.socket, "listtransactions", serde_json::json!({})).await?;
+        let transaction = result["transactions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|tx| tx["hash"].as_str() == Some(&txid))
+            .ok_or_else(|| Status::not_found("transaction isn't known to the cln wallet"))?;
+
+        // Unconfirmed transactions have a block height of 0
+        let height = transaction["blockheight"].as_u64().unwrap_or_default();
+        if height == 0 {
+            return Ok(tonic::Response::new(GetTransactionBlockResponse {
+                block: vec![],
+            }));
+        }
+
+        let params = serde_json::json!({ "height": height });
+        let result = call_raw(&self.socket, "getrawblockbyheight", params).await?;
+        let block = result["block"]
+            .as_str()
+            .ok_or_else(|| Status::internal("cln getrawblockbyheight returned no block"))?;
+        let block = Vec::<u8>::from_hex(block).map_err(|e| Status::internal(e.to_string()))?;
+        Ok(tonic::Response::new(GetTransactionBlockResponse { block }))
+    }
+}
+
+/// Calls a CLN RPC method `cln_rpc` has no typed model for over a dedicated
+/// connection, which also keeps long running calls from blocking others
+async fn call_raw(
+    socket: &Path,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, Status> {
+    let io_error = |e: std::io::Error| {
+        error!("cln {} rpc failed: {:?}", method, e);
+        Status::internal(e.to_string())
+    };
+
+    let mut stream = UnixStream::connect(socket).await.map_err(io_error)?;
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "method": method,
+        "params": params,
+    });
+    stream
+        .write_all(request.to_string().as_bytes())
+        .await
+        .map_err(io_error)?;
+
+    let mut response = vec![];
+    let mut response: serde_json::Value = loop {
+        let mut buf = [0u8; 4096];
+        let read = stream.read(&mut buf).await.map_err(io_error)?;
+        if read == 0 {
+            return Err(Status::internal("cln closed the rpc connection"));
+        }
+        response.extend_from_slice(&buf[..read]);
+
+        match serde_json::from_slice(&response) {
+            Ok(response) => break response,
+            Err(e) if e.is_eof() => continue,
+            Err(e) => return Err(Status::internal(e.to_string())),
+        }
+    };
+
+    if !response["error"].is_null() {
+        error!("cln {} rpc returned error {:?}", method, response["error"]);
+        return Err(Status::internal(response["error"]["message"].to_string()));
+    }
+    Ok(response["result"].take())
+}
+
+/// Parses amounts CLN returns either as number or with a "msat" suffix,
+/// depending on its version
+fn parse_msat(amount: &serde_json::Value) -> Option<u64> {
+    match amount {
+        serde_json::Value::Number(amount) => amount.as_u64(),
+        serde_json::Value::String(amount) => amount.strip_suffix("msat")?.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Status of a payment with the given parts, see `GetPaymentStatusResponse`
+fn payment_status(parts: impl Iterator<Item = PaymentStatus>) -> PaymentStatus {
+    parts.fold(PaymentStatus::Unknown, |status, part| {
+        match (status, part) {
+            (PaymentStatus::Complete, _) | (_, PaymentStatus::Complete) => PaymentStatus::Complete,
+            (PaymentStatus::Pending, _) | (_, PaymentStatus::Pending) => PaymentStatus::Pending,
+            _ => PaymentStatus::Failed,
+        }
+    })
 }
 
 #[derive(Debug, Error)]
@@ -348,6 +777,22 @@ fn htlc_processing_failure() -> serde_json::Value {
     })
 }
 
+/// Number of blocks before the expiry of an intercepted HTLC at which we fail
+/// it if the gateway didn't process it yet
+const HTLC_EXPIRY_SAFETY_MARGIN: u32 = 6;
+/// Lower bound of the time the gateway gets to process an intercepted HTLC
+const MIN_HTLC_PROCESSING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time the gateway may take to process an intercepted HTLC. Payments to held
+/// invoices only complete once their recipient releases them, so we wait for
+/// as long as the HTLC's expiry safely allows instead of a fixed period.
+fn htlc_processing_timeout(cltv_expiry_relative: u32) -> Duration {
+    let blocks = cltv_expiry_relative.saturating_sub(HTLC_EXPIRY_SAFETY_MARGIN);
+    // Assume fast blocks to stay on the safe side
+    let timeout = Duration::from_secs(u64::from(blocks) * 5 * 60);
+    timeout.max(MIN_HTLC_PROCESSING_TIMEOUT)
+}
+
 type HtlcSubscriptionSender = mpsc::Sender<Result<SubscribeInterceptHtlcsResponse, Status>>;
 type HtlcOutcomeSender = oneshot::Sender<serde_json::Value>;
 
@@ -369,6 +814,7 @@ impl ClnHtlcInterceptor {
 
     async fn intercept_htlc(&self, payload: HtlcAccepted) -> serde_json::Value {
         let htlc_expiry = payload.htlc.cltv_expiry;
+        let processing_timeout = htlc_processing_timeout(payload.htlc.cltv_expiry_relative);
 
         let short_channel_id = match payload.onion.short_channel_id {
             Some(scid) => scid,
@@ -378,7 +824,19 @@ impl ClnHtlcInterceptor {
             }
         };
 
-        if let Some(subscription) = self.subscriptions.lock().await.get(&short_channel_id) {
+        let subscription = {
+            let mut subscriptions = self.subscriptions.lock().await;
+            // The subscriber of a federation the gateway disconnected from hung up
+            if subscriptions
+                .get(&short_channel_id)
+                .map_or(false, |subscription| subscription.is_closed())
+            {
+                subscriptions.remove(&short_channel_id);
+            }
+            subscriptions.get(&short_channel_id).cloned()
+        };
+
+        if let Some(subscription) = subscription {
             let payment_hash = payload.htlc.payment_hash.to_vec();
 
             // This has a chance of collission since payment_hashes are not guaranteed to be
@@ -406,7 +864,7 @@ impl ClnHtlcInterceptor {
 
                     // If the gateway does not respond within the HTLC expiry,
                     // Automatically respond with a failure message.
-                    return tokio::time::timeout(Duration::from_secs(30), async {
+                    return tokio::time::timeout(processing_timeout, async {
                         receiver.await.unwrap_or_else(|e| {
                             error!("Failed to receive outcome of intercepted htlc: {:?}", e);
                             htlc_processing_failure()
@@ -442,6 +900,4 @@ impl ClnHtlcInterceptor {
             .insert(short_channel_id, sender);
         receiver
     }
-
-    // TODO: Add a method to remove a HTLC subscriber
 }
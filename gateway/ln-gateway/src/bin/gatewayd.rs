@@ -10,11 +10,14 @@ use fedimint_api::{
     module::{registry::ModuleDecoderRegistry, DynModuleGen},
     task::TaskGroup,
 };
+use fedimint_logging::TracingSetup;
 use ln_gateway::{
     client::{DynGatewayClientBuilder, RocksDbFactory, StandardGatewayClientBuilder},
     gatewayd::{
         gateway::Gateway,
+        health::run_health_server,
         lnrpc_client::{DynLnRpcClient, NetworkLnRpcClient},
+        webhook::{WebhookConfig, WebhookNotifier},
     },
 };
 use mint_client::modules::{
@@ -25,6 +28,9 @@ use mint_client::modules::{
 use tracing::{error, info};
 use url::Url;
 
+/// Webhook notifications that couldn't be delivered, one JSON object per line
+const WEBHOOK_DEAD_LETTERS_FILE: &str = "webhook_dead_letters.jsonl";
+
 #[derive(Parser)]
 pub struct GatewayOpts {
     /// Path to folder containing gateway config and data files
@@ -46,6 +52,43 @@ pub struct GatewayOpts {
     /// Public URL to a Gateway Lightning rpc service
     #[arg(long = "lnrpc-addr", env = "FM_GATEWAY_LIGHTNING_ADDR")]
     pub lnrpc_addr: Url,
+
+    /// `host:port` of a SOCKS5 proxy like Tor's `127.0.0.1:9050` to connect
+    /// to the Lightning rpc service through
+    #[arg(long = "socks5-proxy", env = "FM_GATEWAY_SOCKS5_PROXY")]
+    pub socks5_proxy: Option<String>,
+
+    /// URLs notified about received and failed payments and completed peg-ins
+    /// and peg-outs
+    #[arg(
+        long = "webhook-url",
+        env = "FM_GATEWAY_WEBHOOK_URLS",
+        value_delimiter = ',',
+        requires = "webhook_secret"
+    )]
+    pub webhook_urls: Vec<Url>,
+
+    /// Secret used to sign webhook notifications
+    #[arg(long = "webhook-secret", env = "FM_GATEWAY_WEBHOOK_SECRET")]
+    pub webhook_secret: Option<String>,
+
+    /// Address to serve Prometheus metrics on under `/metrics`
+    #[arg(long = "bind-metrics", env = "FM_GATEWAY_BIND_METRICS")]
+    pub bind_metrics: Option<SocketAddr>,
+
+    /// Address to serve `/health/live`, `/health/ready` and the checks of the
+    /// lightning node and the federations on for orchestrators
+    #[arg(long = "bind-health", env = "FM_GATEWAY_BIND_HEALTH")]
+    pub bind_health: Option<SocketAddr>,
+
+    /// Log JSON objects carrying the fields of the spans they belong to
+    #[arg(long = "log-json", env = "FM_GATEWAY_LOG_JSON")]
+    pub log_json: bool,
+
+    /// OTLP/gRPC collector to export spans to, e.g. `http://localhost:4317`,
+    /// needs the `telemetry` feature
+    #[arg(long = "otlp-endpoint", env = "FM_GATEWAY_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<Url>,
 }
 
 // Fedimint Gateway Binary
@@ -71,9 +114,21 @@ async fn main() -> Result<(), anyhow::Error> {
         listen,
         api_addr,
         lnrpc_addr,
+        socks5_proxy,
         password,
+        webhook_urls,
+        webhook_secret,
+        bind_metrics,
+        bind_health,
+        log_json,
+        otlp_endpoint,
     } = GatewayOpts::parse();
 
+    TracingSetup::new("gatewayd")
+        .json(log_json)
+        .otlp_endpoint(otlp_endpoint)
+        .init()?;
+
     info!(
         "Starting gateway with these configs \n data directory: {:?},\n listen: {},\n api address: {},\n lnrpc address: {} ",
         data_dir, listen, api_addr, lnrpc_addr
@@ -84,10 +139,32 @@ async fn main() -> Result<(), anyhow::Error> {
         StandardGatewayClientBuilder::new(data_dir.clone(), RocksDbFactory.into(), api_addr).into();
 
     // Create task group for controlled shutdown of the gateway
-    let task_group = TaskGroup::new();
+    let mut task_group = TaskGroup::new();
+
+    if let Some(bind_metrics) = bind_metrics {
+        fedimint_metrics::run_api_server(bind_metrics, &mut task_group).await?;
+    }
+
+    // Start delivering webhook notifications
+    let notifier = match webhook_secret {
+        Some(secret) => {
+            WebhookNotifier::new(
+                WebhookConfig {
+                    urls: webhook_urls,
+                    secret,
+                    dead_letter_path: data_dir.join(WEBHOOK_DEAD_LETTERS_FILE),
+                },
+                &mut task_group,
+            )
+            .await
+        }
+        None => WebhookNotifier::disabled(),
+    };
 
     // Create a lightning rpc client
-    let lnrpc: DynLnRpcClient = NetworkLnRpcClient::new(lnrpc_addr).await?.into();
+    let lnrpc: DynLnRpcClient = NetworkLnRpcClient::new(lnrpc_addr, socks5_proxy)
+        .await?
+        .into();
 
     // Create module decoder registry
     let decoders = ModuleDecoderRegistry::from_iter([
@@ -110,15 +187,21 @@ async fn main() -> Result<(), anyhow::Error> {
         decoders,
         module_gens,
         task_group.clone(),
+        notifier,
     )
     .await;
 
-    if let Err(e) = gateway.run(listen, password).await {
+    if let Some(bind_health) = bind_health {
+        run_health_server(bind_health, gateway.health(), &mut task_group).await?;
+    }
+
+    let result = gateway.run(listen, password).await;
+    if let Err(e) = &result {
         task_group.shutdown_join_all(None).await?;
 
         error!("Gateway stopped with error: {}", e);
-        return Err(e.into());
     }
+    fedimint_logging::shutdown();
 
-    Ok(())
+    result.map_err(Into::into)
 }
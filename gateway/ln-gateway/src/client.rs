@@ -17,6 +17,7 @@ use fedimint_server::api::GlobalFederationApi;
 use fedimint_server::api::WsClientConnectInfo;
 use fedimint_server::api::WsFederationApi;
 use fedimint_server::config::load_from_file;
+use mint_client::modules::ln::{GatewayFees, GatewayLimits};
 use mint_client::{module_decode_stubs, Client, GatewayClientConfig};
 use secp256k1::{KeyPair, PublicKey};
 use tracing::{debug, warn};
@@ -96,6 +97,10 @@ pub trait IGatewayClientBuilder: Debug {
 
     /// Load all gateway client configs from the work directory
     fn load_configs(&self) -> Result<Vec<GatewayClientConfig>>;
+
+    /// Delete the persisted configuration of the gateway federation client so
+    /// it isn't loaded again on restart
+    fn remove_config(&self, federation_id: FederationId) -> Result<()>;
 }
 
 dyn_newtype_define! {
@@ -166,6 +171,10 @@ impl IGatewayClientBuilder for StandardGatewayClientBuilder {
             timelock_delta: 10,
             node_pub_key: node_pubkey,
             api: self.gateway_api.clone(),
+            fees: GatewayFees::default(),
+            limits: GatewayLimits::default(),
+            rebalance: None,
+            watchtower: false,
         })
     }
 
@@ -194,6 +203,14 @@ impl IGatewayClientBuilder for StandardGatewayClientBuilder {
         Ok(())
     }
 
+    fn remove_config(&self, federation_id: FederationId) -> Result<()> {
+        let id = federation_id.to_string();
+        let path: PathBuf = self.work_dir.join(format!("{id}.json"));
+
+        debug!("Removing gateway cfg {}", path.display());
+        std::fs::remove_file(path).map_err(|e| LnGatewayError::Other(anyhow::Error::new(e)))
+    }
+
     fn load_configs(&self) -> Result<Vec<GatewayClientConfig>> {
         Ok(std::fs::read_dir(&self.work_dir)
             .map_err(|e| LnGatewayError::Other(anyhow::Error::new(e)))?
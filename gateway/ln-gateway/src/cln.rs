@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -11,6 +12,7 @@ use fedimint_api::Amount;
 use lightning_invoice::Invoice;
 use mint_client::modules::ln::contracts::Preimage;
 use mint_client::modules::ln::route_hints::{RouteHint, RouteHintHop};
+use mint_client::modules::ln::GatewayLiquidity;
 use secp256k1::PublicKey;
 use serde::{Deserialize, Deserializer, Serialize};
 use tokio::io::{stdin, stdout};
@@ -215,7 +217,11 @@ impl LnRpc for ClnRpc {
                 .map_err(|err| anyhow!("Lightning error: {:?}", err))?;
             let channel = match channels_response {
                 Response::ListChannels(channels) => {
-                    let Some(channel) = channels.channels.into_iter().find(|chan| chan.destination == our_pub_key) else {
+                    let Some(channel) = channels
+                        .channels
+                        .into_iter()
+                        .find(|chan| chan.destination == our_pub_key)
+                    else {
                         warn!("Channel {:?} not found in graph", scid);
                         continue;
                     };
@@ -243,16 +249,61 @@ impl LnRpc for ClnRpc {
 
         Ok(route_hints)
     }
+
+    #[instrument(name = "LnRpc::liquidity", skip(self))]
+    async fn liquidity(&self) -> Result<GatewayLiquidity, anyhow::Error> {
+        let mut client_lock = self
+            .rpc_client()
+            .await
+            .map_err(|_| anyhow!("Could not connect to CLN unix socket"))?;
+
+        let peers_response = client_lock
+            .call(Request::ListPeers(ListpeersRequest {
+                id: None,
+                level: None,
+            }))
+            .await?;
+        let peers = match peers_response {
+            Response::ListPeers(peers) => peers.peers,
+            _ => {
+                panic!("Unexpected response")
+            }
+        };
+
+        let (inbound, outbound) = channel_liquidity(peers);
+        Ok(GatewayLiquidity::from_balances(inbound, outbound))
+    }
+}
+
+/// Sums up the amounts we can receive and send over our active channels
+pub fn channel_liquidity(peers: Vec<model::ListpeersPeers>) -> (Amount, Amount) {
+    peers
+        .into_iter()
+        .flat_map(|peer| peer.channels)
+        .filter(|chan| matches!(chan.state, ListpeersPeersChannelsState::CHANNELD_NORMAL))
+        .fold((Amount::ZERO, Amount::ZERO), |(inbound, outbound), chan| {
+            let receivable = chan.receivable_msat.map(|amt| amt.msat()).unwrap_or(0);
+            let spendable = chan.spendable_msat.map(|amt| amt.msat()).unwrap_or(0);
+            (
+                inbound + Amount::from_msats(receivable),
+                outbound + Amount::from_msats(spendable),
+            )
+        })
 }
 
 // TODO: upstream
-fn scid_to_u64(scid: ShortChannelId) -> u64 {
+pub fn scid_to_u64(scid: ShortChannelId) -> u64 {
     let mut scid_num = scid.outnum() as u64;
     scid_num |= (scid.txindex() as u64) << 16;
     scid_num |= (scid.block() as u64) << 40;
     scid_num
 }
 
+/// Parses a short channel id in CLN's `BLOCKxTXINDEXxOUTNUM` format
+pub fn parse_scid(scid: &str) -> Option<u64> {
+    ShortChannelId::from_str(scid).ok().map(scid_to_u64)
+}
+
 /// BOLT 4: <https://github.com/lightning/bolts/blob/master/04-onion-routing.md#failure-messages>
 /// 16399 error code reports unknown payment details.
 ///
@@ -276,9 +327,15 @@ async fn htlc_accepted_hook(
     // Filter and process intercepted HTLCs based on `short_channel_id` value.
     //
     // After https://github.com/fedimint/fedimint/pull/1180,
-    // all HTLCs to Fedimint clients should have route hint with `short_channel_id =
-    // 0u64`, unless the gateway is serving multiple federations.
-    if htlc_accepted.onion.short_channel_id.as_deref() == Some("0x0x0") {
+    // all HTLCs to Fedimint clients have a route hint with the `short_channel_id`
+    // the gateway assigned to their federation. These are small numbers counting
+    // up from 0, so they are formatted as `0x0x<n>`.
+    if htlc_accepted
+        .onion
+        .short_channel_id
+        .as_deref()
+        .map_or(false, |scid| scid.starts_with("0x0x"))
+    {
         let preimage = match plugin
             .state()
             .send(ReceivePaymentPayload { htlc_accepted })
@@ -1,40 +1,82 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use bitcoin::{Address, Transaction};
+use anyhow::anyhow;
+use bitcoin::{util::merkleblock::PartialMerkleTree, Address, Transaction};
 use bitcoin_hashes::{sha256, Hash};
-use fedimint_api::{task::TaskGroup, Amount, OutPoint, TransactionId};
+use fedimint_api::{config::FederationId, task::TaskGroup, Amount, OutPoint, TransactionId};
 use futures::stream::StreamExt;
+use mint_client::ln::{
+    outgoing::OutgoingPaymentParts,
+    proof::{ContractDirection, ContractProof},
+    watchtower::WatchtowerRefund,
+    GatewayPayment,
+};
 use mint_client::modules::{
     ln::{
-        contracts::{ContractId, Preimage},
+        bolt12::{Bolt12Invoice, Bolt12Offer},
+        contracts::{
+            account::AccountContract, outgoing::OutgoingInvoice, ContractId, IdentifyableContract,
+            Preimage,
+        },
         route_hints::RouteHint,
+        GatewayLiquidity,
     },
     wallet::txoproof::TxOutProof,
 };
-use mint_client::{GatewayClient, PaymentParameters};
+use mint_client::{GatewayClient, GatewayRebalanceBand, PaymentParameters};
 use rand::{CryptoRng, RngCore};
 use tracing::{debug, error, info, instrument, warn};
 
 use crate::{
-    gatewayd::lnrpc_client::DynLnRpcClient,
+    gatewayd::{
+        lnrpc_client::DynLnRpcClient,
+        metrics,
+        webhook::{WebhookEvent, WebhookNotifier},
+    },
     gatewaylnrpc::{
         complete_htlcs_request::{Action, Cancel, Settle},
-        CompleteHtlcsRequest, PayInvoiceRequest, PayInvoiceResponse,
-        SubscribeInterceptHtlcsRequest, SubscribeInterceptHtlcsResponse,
+        CompleteHtlcsRequest, CreateOfferRequest, CreateOfferResponse, FetchInvoiceRequest,
+        FetchInvoiceResponse, GetLiquidityResponse, GetNewAddressResponse,
+        GetPaymentFeaturesResponse, GetPaymentStatusRequest, GetPaymentStatusResponse,
+        GetTransactionBlockRequest, GetTransactionBlockResponse, PayInvoiceRequest,
+        PayInvoiceResponse, PaymentStatus, SubscribeInterceptHtlcsRequest,
+        SubscribeInterceptHtlcsResponse, WaitOfferPaymentResponse, WithdrawOnchainRequest,
+        WithdrawOnchainResponse,
     },
     rpc::FederationInfo,
     utils::retry,
     LnGatewayError, Result,
 };
 
-/// How long a gateway announcement stays valid
-const GW_ANNOUNCEMENT_TTL: Duration = Duration::from_secs(600);
+/// How long a gateway announcement stays valid. The announcement, including
+/// the advertised liquidity, is refreshed every half TTL so clients stop using
+/// the gateway shortly after it goes offline.
+const GW_ANNOUNCEMENT_TTL: Duration = Duration::from_secs(120);
+/// How long we wait for the federation to process our peg-in and peg-out
+/// transactions before giving up on notifying webhooks about their completion
+const PEG_COMPLETION_TIMEOUT: Duration = Duration::from_secs(300);
+/// How often we check whether our e-cash balance left its rebalance band
+const REBALANCE_INTERVAL: Duration = Duration::from_secs(600);
+/// How often we check on parts of an outgoing payment that are still in flight
+const PAYMENT_PARTS_INTERVAL: Duration = Duration::from_secs(10);
+/// How often we report our e-cash balance to the metrics
+const BALANCE_METRICS_INTERVAL: Duration = Duration::from_secs(60);
+/// How often we check whether refunds we watch as a watchtower became due
+const WATCHTOWER_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the e-cash of the federation's client is backed up
+const ECASH_BACKUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How often we retry the steps of client operations that failed temporarily
+const OPERATION_RESUME_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Clone)]
 pub struct GatewayActor {
     client: Arc<GatewayClient>,
     lnrpc: DynLnRpcClient,
     task_group: TaskGroup,
+    notifier: WebhookNotifier,
 }
 
 impl GatewayActor {
@@ -43,46 +85,84 @@ impl GatewayActor {
         lnrpc: DynLnRpcClient,
         route_hints: Vec<RouteHint>,
         task_group: TaskGroup,
+        notifier: WebhookNotifier,
     ) -> Result<Self> {
+        // Tasks of the actor run in their own group so they can be stopped when
+        // the federation gets disconnected
+        let mut task_group = task_group.make_subgroup().await;
+
         let register_client = client.clone();
-        tokio::spawn(async move {
-            loop {
-                // Retry gateway registration
-                match retry(
-                    String::from("Register With Federation"),
-                    #[allow(clippy::unit_arg)]
-                    || async {
-                        let gateway_registration = register_client
-                            .config()
-                            .to_gateway_registration_info(route_hints.clone(), GW_ANNOUNCEMENT_TTL);
-                        Ok(register_client
-                            .register_with_federation(gateway_registration.clone())
-                            .await?)
-                    },
-                    Duration::from_secs(1),
-                    5,
-                )
-                .await
-                {
-                    Ok(_) => {
-                        info!("Connected with federation");
-                        tokio::time::sleep(GW_ANNOUNCEMENT_TTL / 2).await;
-                    }
-                    Err(e) => {
-                        warn!("Failed to connect with federation: {}", e);
-                        tokio::time::sleep(GW_ANNOUNCEMENT_TTL / 4).await;
+        let register_lnrpc = lnrpc.clone();
+        task_group
+            .spawn("Register with federation", move |handle| async move {
+                while !handle.is_shutting_down() {
+                    let liquidity = match register_lnrpc.liquidity().await {
+                        Ok(GetLiquidityResponse {
+                            inbound_msat,
+                            outbound_msat,
+                        }) => GatewayLiquidity::from_balances(
+                            Amount::from_msats(inbound_msat),
+                            Amount::from_msats(outbound_msat),
+                        ),
+                        Err(e) => {
+                            warn!("Failed to fetch liquidity from LN node: {}", e);
+                            GatewayLiquidity::default()
+                        }
+                    };
+
+                    // Retry gateway registration
+                    match retry(
+                        String::from("Register With Federation"),
+                        #[allow(clippy::unit_arg)]
+                        || async {
+                            let gateway_registration =
+                                register_client.config().to_gateway_registration_info(
+                                    route_hints.clone(),
+                                    liquidity,
+                                    GW_ANNOUNCEMENT_TTL,
+                                );
+                            Ok(register_client
+                                .register_with_federation(gateway_registration.clone())
+                                .await?)
+                        },
+                        Duration::from_secs(1),
+                        5,
+                    )
+                    .await
+                    {
+                        Ok(_) => {
+                            info!("Connected with federation");
+                            tokio::time::sleep(GW_ANNOUNCEMENT_TTL / 2).await;
+                        }
+                        Err(e) => {
+                            warn!("Failed to connect with federation: {}", e);
+                            tokio::time::sleep(GW_ANNOUNCEMENT_TTL / 4).await;
+                        }
                     }
                 }
-            }
-        });
+            })
+            .await;
 
         let actor = Self {
             client,
             lnrpc,
             task_group,
+            notifier,
         };
 
         actor.subscribe_htlcs().await?;
+        actor.resume_outgoing_payments().await;
+        actor.spawn_balance_metrics().await;
+        actor.spawn_ecash_backup().await;
+        actor.spawn_operation_resumption().await;
+
+        if let Some(band) = actor.client.config().rebalance {
+            actor.spawn_rebalancing(band).await;
+        }
+
+        if actor.client.config().watchtower {
+            actor.spawn_watchtower().await;
+        }
 
         Ok(actor)
     }
@@ -102,8 +182,11 @@ impl GatewayActor {
         tg.spawn(
             "Subscribe to intercepted HTLCs in stream",
             move |subscription| async move {
+                // Stop waiting for HTLCs once the federation gets disconnected
+                let mut stream = stream.take_until(subscription.make_shutdown_rx().await);
                 while let Some(SubscribeInterceptHtlcsResponse {
                     payment_hash,
+                    incoming_amount_msat,
                     outgoing_amount_msat,
                     intercepted_htlc_id,
                     ..
@@ -135,6 +218,10 @@ impl GatewayActor {
                         Ok(hash) => hash,
                         Err(e) => {
                             let fail = "Failed to parse payment hash";
+                            metrics::record_payment_failure(
+                                &actor.federation_id(),
+                                ContractDirection::Incoming,
+                            );
 
                             error!("{}: {:?}", fail, e);
                             let _ = lnrpc_copy
@@ -168,10 +255,37 @@ impl GatewayActor {
                                 // gateway,
                                 // we should either retry completing the htlc or
                                 // reclaim funds from the federation
+                            } else {
+                                actor
+                                    .record_payment(
+                                        hash.into(),
+                                        ContractDirection::Incoming,
+                                        amount_msat,
+                                        Amount::from_msats(
+                                            incoming_amount_msat
+                                                .saturating_sub(outgoing_amount_msat),
+                                        ),
+                                        None,
+                                    )
+                                    .await;
+                                actor.notifier.notify(WebhookEvent::PaymentReceived {
+                                    federation_id: actor.federation_id(),
+                                    payment_hash: hash,
+                                    amount: amount_msat,
+                                });
                             };
                         }
                         Err(e) => {
                             error!("Failed to process intercepted HTLC: {:?}", e);
+                            actor
+                                .record_payment(
+                                    hash.into(),
+                                    ContractDirection::Incoming,
+                                    amount_msat,
+                                    Amount::ZERO,
+                                    Some(e.to_string()),
+                                )
+                                .await;
                             // Note: this specific complete htlc requires no futher action.
                             // If we fail to send the complete htlc message, or get an error result,
                             // lightning node will still cancel HTCL after expiry period lapses.
@@ -194,6 +308,194 @@ impl GatewayActor {
         Ok(())
     }
 
+    /// Resolves outgoing payments that still had parts in flight when we shut
+    /// down, claiming their contracts if they settled in the meantime
+    async fn resume_outgoing_payments(&self) {
+        let actor = self.clone();
+        let mut tg = self.task_group.clone();
+        tg.spawn("Resume outgoing payments", move |_| async move {
+            for account in actor.client.list_pending_outgoing().await {
+                let contract_id = account.contract.contract_id();
+                if actor
+                    .client
+                    .outgoing_payment_parts(contract_id)
+                    .await
+                    .is_none()
+                {
+                    continue;
+                }
+
+                info!(%contract_id, "Resuming outgoing payment with parts in flight");
+                let payment_hash = account.contract.invoice.payment_hash();
+                let outcome = match actor.await_payment_parts(contract_id, payment_hash).await {
+                    Ok(Some(preimage)) => actor
+                        .client
+                        .claim_outgoing_contract(contract_id, preimage, rand::rngs::OsRng)
+                        .await
+                        .map(|_| ()),
+                    Ok(None) => actor.client.abort_outgoing_payment(contract_id).await,
+                    Err(e) => {
+                        warn!(%contract_id, "Failed to resume outgoing payment: {}", e);
+                        continue;
+                    }
+                };
+                if let Err(e) = outcome {
+                    warn!(%contract_id, "Failed to resolve outgoing payment: {}", e);
+                }
+            }
+        })
+        .await;
+    }
+
+    async fn spawn_balance_metrics(&self) {
+        let actor = self.clone();
+        let mut tg = self.task_group.clone();
+        tg.spawn("Report e-cash balance", move |handle| async move {
+            while !handle.is_shutting_down() {
+                // Fetching the balance updates the metrics
+                if let Err(e) = actor.get_balance().await {
+                    debug!("Failed to fetch e-cash balance: {}", e);
+                }
+                fedimint_api::task::sleep(BALANCE_METRICS_INTERVAL).await;
+            }
+        })
+        .await;
+    }
+
+    async fn spawn_rebalancing(&self, band: GatewayRebalanceBand) {
+        let actor = self.clone();
+        let mut tg = self.task_group.clone();
+        tg.spawn("Rebalance e-cash", move |handle| async move {
+            while !handle.is_shutting_down() {
+                if let Err(e) = actor.rebalance(band).await {
+                    warn!("Failed to rebalance e-cash: {}", e);
+                }
+                fedimint_api::task::sleep(REBALANCE_INTERVAL).await;
+            }
+        })
+        .await;
+    }
+
+    async fn spawn_ecash_backup(&self) {
+        let client = self.client.clone();
+        let mut tg = self.task_group.clone();
+        tg.spawn("Back up e-cash", move |handle| async move {
+            client
+                .back_up_periodically(handle, ECASH_BACKUP_INTERVAL)
+                .await
+        })
+        .await;
+    }
+
+    /// Finishes client operations interrupted by a restart right away, and
+    /// later the ones whose steps failed temporarily
+    async fn spawn_operation_resumption(&self) {
+        let client = self.client.clone();
+        let mut tg = self.task_group.clone();
+        tg.spawn("Resume client operations", move |handle| async move {
+            while !handle.is_shutting_down() {
+                client.resume_operations().await;
+                fedimint_api::task::sleep(OPERATION_RESUME_INTERVAL).await;
+            }
+        })
+        .await;
+    }
+
+    async fn spawn_watchtower(&self) {
+        let client = self.client.clone();
+        let mut tg = self.task_group.clone();
+        tg.spawn("Submit watched refunds", move |handle| async move {
+            while !handle.is_shutting_down() {
+                for contract_id in client.submit_due_refunds().await {
+                    info!(%contract_id, "Refunded outgoing contract as watchtower");
+                }
+                fedimint_api::task::sleep(WATCHTOWER_INTERVAL).await;
+            }
+        })
+        .await;
+    }
+
+    /// Pegs out to or in from the on-chain wallet of our lightning node if our
+    /// e-cash balance left `band`. Pegging in takes several checks, we claim
+    /// the peg-in once it has been confirmed before rebalancing any further.
+    async fn rebalance(&self, band: GatewayRebalanceBand) -> Result<()> {
+        if let Some(transaction) = self.client.pending_rebalance_peg_in().await {
+            return self.claim_rebalance_peg_in(transaction).await;
+        }
+
+        let balance = self.get_balance().await?;
+        let GetLiquidityResponse {
+            inbound_msat,
+            outbound_msat,
+        } = self.lnrpc.liquidity().await?;
+        debug!(%balance, inbound_msat, outbound_msat, "Checking e-cash balance");
+
+        if balance > band.max_ecash {
+            let amount = bitcoin::Amount::from_sat((balance - band.target()).msats / 1000);
+            let GetNewAddressResponse { address } = self.lnrpc.new_address().await?;
+            let address = address.parse().map_err(|e| {
+                LnGatewayError::Other(anyhow!("LN node returned invalid address: {e}"))
+            })?;
+
+            let txid = self.withdraw(amount, address).await?;
+            info!(%amount, %txid, "Pegged out excess e-cash to our LN node");
+        } else if balance < band.min_ecash {
+            let amount_sat = (band.target() - balance).msats / 1000;
+            let address = self.get_deposit_address().await?;
+            let WithdrawOnchainResponse { transaction } = self
+                .lnrpc
+                .withdraw_onchain(WithdrawOnchainRequest {
+                    address: address.to_string(),
+                    amount_sat,
+                })
+                .await?;
+            let transaction: Transaction =
+                bitcoin::consensus::deserialize(&transaction).map_err(|e| {
+                    LnGatewayError::Other(anyhow!("LN node returned invalid transaction: {e}"))
+                })?;
+
+            info!(amount_sat, txid = %transaction.txid(), "Pegging in from our LN node");
+            self.client.save_rebalance_peg_in(&transaction).await;
+        }
+
+        Ok(())
+    }
+
+    async fn claim_rebalance_peg_in(&self, transaction: Transaction) -> Result<()> {
+        let txid = transaction.txid();
+        let GetTransactionBlockResponse { block } = self
+            .lnrpc
+            .transaction_block(GetTransactionBlockRequest {
+                txid: txid.into_inner().to_vec(),
+            })
+            .await?;
+        if block.is_empty() {
+            debug!(%txid, "Rebalancing peg-in isn't confirmed yet");
+            return Ok(());
+        }
+
+        let block: bitcoin::Block = bitcoin::consensus::deserialize(&block)
+            .map_err(|e| LnGatewayError::Other(anyhow!("LN node returned invalid block: {e}")))?;
+        let txids = block
+            .txdata
+            .iter()
+            .map(Transaction::txid)
+            .collect::<Vec<_>>();
+        let matches = txids.iter().map(|id| *id == txid).collect::<Vec<_>>();
+        let txout_proof = TxOutProof {
+            block_header: block.header,
+            merkle_proof: PartialMerkleTree::from_txids(&txids, &matches),
+        };
+
+        // Fails until the federation considers the transaction final, so we just
+        // try again on the next check
+        self.deposit(txout_proof, transaction).await?;
+        self.client.remove_rebalance_peg_in().await;
+        info!(%txid, "Claimed rebalancing peg-in");
+
+        Ok(())
+    }
+
     async fn fetch_all_notes(&self) {
         if let Err(e) = self.client.fetch_all_notes().await {
             debug!(error = %e, "Fetching notes failed");
@@ -232,6 +534,20 @@ impl GatewayActor {
         {
             Ok(payment_params) => payment_params,
             Err(e) => {
+                let amount = contract_account
+                    .contract
+                    .invoice
+                    .amount_milli_satoshis()
+                    .unwrap_or_default();
+                self.record_payment(
+                    contract_id,
+                    ContractDirection::Outgoing,
+                    Amount::from_msats(amount),
+                    Amount::ZERO,
+                    Some(e.to_string()),
+                )
+                .await;
+                self.notify_payment_failed(contract_id, &e);
                 self.client
                     .cancel_outgoing_contract(contract_account)
                     .await?;
@@ -260,8 +576,12 @@ impl GatewayActor {
             self.buy_preimage_internal(&payment_params.payment_hash, &payment_params.invoice_amount)
                 .await
         } else {
-            self.buy_preimage_external(contract_account.contract.invoice, &payment_params)
-                .await
+            self.buy_preimage_external(
+                contract_id,
+                contract_account.contract.invoice,
+                &payment_params,
+            )
+            .await
         };
 
         match preimage_res {
@@ -270,11 +590,28 @@ impl GatewayActor {
                     .client
                     .claim_outgoing_contract(contract_id, preimage, rng)
                     .await?;
+                self.record_payment(
+                    contract_id,
+                    ContractDirection::Outgoing,
+                    payment_params.invoice_amount,
+                    contract_account.amount - payment_params.invoice_amount,
+                    None,
+                )
+                .await;
 
                 Ok(outpoint)
             }
             Err(e) => {
                 warn!("Invoice payment failed: {}. Aborting", e);
+                self.record_payment(
+                    contract_id,
+                    ContractDirection::Outgoing,
+                    payment_params.invoice_amount,
+                    Amount::ZERO,
+                    Some(e.to_string()),
+                )
+                .await;
+                self.notify_payment_failed(contract_id, &e);
                 // FIXME: combine both errors?
                 self.client.abort_outgoing_payment(contract_id).await?;
                 Err(e)
@@ -282,6 +619,51 @@ impl GatewayActor {
         }
     }
 
+    /// Records a finished payment in our metrics and payment history
+    async fn record_payment(
+        &self,
+        contract_id: ContractId,
+        direction: ContractDirection,
+        amount: Amount,
+        fee: Amount,
+        error: Option<String>,
+    ) {
+        if error.is_none() {
+            metrics::record_payment_success(&self.federation_id(), direction, amount, fee);
+        } else {
+            metrics::record_payment_failure(&self.federation_id(), direction);
+        }
+
+        let finished_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        self.client
+            .save_gateway_payment(&GatewayPayment {
+                contract_id,
+                direction,
+                amount,
+                fee,
+                error,
+                finished_at,
+            })
+            .await;
+    }
+
+    /// Payments we routed or failed to route for this federation, newest
+    /// first
+    pub async fn list_payments(&self, limit: usize) -> Vec<GatewayPayment> {
+        self.client.list_gateway_payments(limit).await
+    }
+
+    fn notify_payment_failed(&self, contract_id: ContractId, error: &impl std::fmt::Display) {
+        self.notifier.notify(WebhookEvent::PaymentFailed {
+            federation_id: self.federation_id(),
+            contract_id,
+            error: error.to_string(),
+        });
+    }
+
     pub async fn buy_preimage_internal(
         &self,
         payment_hash: &sha256::Hash,
@@ -296,7 +678,11 @@ impl GatewayActor {
             .await?;
 
         debug!("Awaiting decryption of preimage of hash {}", payment_hash);
-        match self.client.await_preimage_decryption(out_point).await {
+        match self
+            .client
+            .await_incoming_contract_preimage(out_point, contract_id)
+            .await
+        {
             Ok(preimage) => {
                 debug!("Decrypted preimage {:?}", preimage);
                 Ok(preimage)
@@ -313,26 +699,102 @@ impl GatewayActor {
 
     pub async fn buy_preimage_external(
         &self,
-        invoice: lightning_invoice::Invoice,
+        contract_id: ContractId,
+        invoice: OutgoingInvoice,
         payment_params: &PaymentParameters,
     ) -> Result<Preimage> {
+        // Splitting the payment lets us use the liquidity of several channels
+        let allow_multi_part = invoice.supports_basic_mpp() && self.supports_multi_part().await;
+
+        // The node pays BOLT11 and BOLT12 invoices alike given their string encoding
         match self
             .lnrpc
             .pay(PayInvoiceRequest {
                 invoice: invoice.to_string(),
                 max_delay: payment_params.max_delay,
                 max_fee_percent: payment_params.max_fee_percent(),
+                allow_multi_part,
             })
             .await
         {
-            Ok(PayInvoiceResponse { preimage, .. }) => {
-                let slice: [u8; 32] = preimage.try_into().expect("Failed to parse preimage");
-                Ok(Preimage(slice))
+            Ok(PayInvoiceResponse {
+                preimage,
+                parts,
+                amount_sent_msat,
+            }) => {
+                debug!(parts, amount_sent_msat, "Paid invoice");
+                parse_preimage(preimage)
+            }
+            Err(e) if allow_multi_part => {
+                // Some parts of a failed multi-part payment may still be in flight and
+                // settle later, in which case we have to claim the contract
+                warn!("Invoice payment failed: {}. Awaiting parts in flight", e);
+                self.await_payment_parts(contract_id, payment_params.payment_hash)
+                    .await?
+                    .ok_or(e)
             }
             Err(e) => Err(e),
         }
     }
 
+    async fn supports_multi_part(&self) -> bool {
+        match self.lnrpc.payment_features().await {
+            Ok(GetPaymentFeaturesResponse { multi_part }) => multi_part,
+            Err(e) => {
+                warn!("Failed to fetch payment features from LN node: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Waits until no part of the payment of an outgoing contract is in flight
+    /// anymore, recording the parts as they resolve. Returns the preimage if
+    /// the payment settled.
+    async fn await_payment_parts(
+        &self,
+        contract_id: ContractId,
+        payment_hash: sha256::Hash,
+    ) -> Result<Option<Preimage>> {
+        loop {
+            let response = self
+                .lnrpc
+                .payment_status(GetPaymentStatusRequest {
+                    payment_hash: payment_hash.into_inner().to_vec(),
+                })
+                .await?;
+            let status = response.status();
+            let GetPaymentStatusResponse {
+                preimage, parts, ..
+            } = response;
+
+            let mut payment_parts = OutgoingPaymentParts::default();
+            for part in parts {
+                let amount = Amount::from_msats(part.amount_msat);
+                match part.status() {
+                    PaymentStatus::Complete => payment_parts.settled.push(amount),
+                    PaymentStatus::Failed => payment_parts.failed.push(amount),
+                    PaymentStatus::Pending | PaymentStatus::Unknown => {
+                        payment_parts.pending.push(amount)
+                    }
+                }
+            }
+            if payment_parts != OutgoingPaymentParts::default() {
+                self.client
+                    .save_outgoing_payment_parts(contract_id, &payment_parts)
+                    .await;
+            }
+
+            match status {
+                PaymentStatus::Complete => return parse_preimage(preimage).map(Some),
+                PaymentStatus::Pending => {
+                    debug!(in_flight = %payment_parts.in_flight(), "Payment parts still in flight");
+                    fedimint_api::task::sleep(PAYMENT_PARTS_INTERVAL).await;
+                }
+                PaymentStatus::Failed | PaymentStatus::Unknown => return Ok(None),
+            }
+        }
+    }
+
     pub async fn await_outgoing_contract_claimed(
         &self,
         contract_id: ContractId,
@@ -344,6 +806,33 @@ impl GatewayActor {
             .await?)
     }
 
+    /// Preimage we claimed an outgoing contract with, proving that we paid its
+    /// invoice
+    pub async fn outgoing_contract_preimage(&self, contract_id: ContractId) -> Result<Preimage> {
+        self.client
+            .contract_evidence(contract_id)
+            .await?
+            .preimage
+            .ok_or_else(|| {
+                LnGatewayError::Other(anyhow!("No preimage known for contract {contract_id}"))
+            })
+    }
+
+    pub async fn contract_proof(&self, contract_id: ContractId) -> Result<ContractProof> {
+        Ok(self.client.contract_proof(contract_id).await?)
+    }
+
+    /// Watches the contract of a user's refund, submitting the refund once the
+    /// contract timed out or got cancelled
+    pub async fn watch_refund(&self, refund: WatchtowerRefund) -> Result<()> {
+        if !self.client.config().watchtower {
+            return Err(LnGatewayError::Other(anyhow!(
+                "The gateway is no watchtower for this federation"
+            )));
+        }
+        Ok(self.client.watch_refund(refund).await?)
+    }
+
     pub async fn get_deposit_address(&self) -> Result<Address> {
         let rng = rand::rngs::OsRng;
         Ok(self.client.get_new_pegin_address(rng).await)
@@ -356,10 +845,30 @@ impl GatewayActor {
     ) -> Result<TransactionId> {
         let rng = rand::rngs::OsRng;
 
-        self.client
+        let txid = self
+            .client
             .peg_in(txout_proof, transaction, rng)
             .await
-            .map_err(LnGatewayError::ClientError)
+            .map_err(LnGatewayError::ClientError)?;
+
+        let client = self.client.clone();
+        let notifier = self.notifier.clone();
+        tokio::spawn(async move {
+            // The change output of the peg-in transaction holds the deposited ecash
+            let outpoint = OutPoint { txid, out_idx: 0 };
+            match client
+                .await_output_accepted(outpoint, PEG_COMPLETION_TIMEOUT)
+                .await
+            {
+                Ok(()) => notifier.notify(WebhookEvent::PegInCompleted {
+                    federation_id: client.config().client_config.federation_id.clone(),
+                    transaction_id: txid,
+                }),
+                Err(e) => warn!(%txid, "Peg-in didn't complete: {}", e),
+            }
+        });
+
+        Ok(txid)
     }
 
     pub async fn withdraw(
@@ -371,16 +880,33 @@ impl GatewayActor {
 
         let rng = rand::rngs::OsRng;
 
-        let peg_out = self
+        let peg_out = self.client.new_peg_out_with_fees(amount, address).await?;
+        let out_point = self
             .client
-            .new_peg_out_with_fees(amount, address)
-            .await
-            .expect("Failed to create pegout with fees");
-        self.client
             .peg_out(peg_out, rng)
             .await
-            .map_err(LnGatewayError::ClientError)
-            .map(|out_point| out_point.txid)
+            .map_err(LnGatewayError::ClientError)?;
+
+        let client = self.client.clone();
+        let notifier = self.notifier.clone();
+        tokio::spawn(async move {
+            let outcome = fedimint_api::task::timeout(
+                PEG_COMPLETION_TIMEOUT,
+                client.wallet_client().await_peg_out_outcome(out_point),
+            )
+            .await;
+            match outcome {
+                Ok(Ok(bitcoin_out_point)) => notifier.notify(WebhookEvent::PegOutCompleted {
+                    federation_id: client.config().client_config.federation_id.clone(),
+                    transaction_id: bitcoin_out_point.txid,
+                    amount,
+                }),
+                Ok(Err(e)) => warn!(txid = %out_point.txid, "Peg-out didn't complete: {}", e),
+                Err(_) => warn!(txid = %out_point.txid, "Timed out waiting for peg-out"),
+            }
+        });
+
+        Ok(out_point.txid)
     }
 
     pub async fn backup(&self) -> Result<()> {
@@ -415,14 +941,127 @@ impl GatewayActor {
     pub async fn get_balance(&self) -> Result<Amount> {
         self.fetch_all_notes().await;
 
-        Ok(self.client.notes().await.total_amount())
+        let balance = self.client.notes().await.total_amount();
+        metrics::record_ecash_balance(&self.federation_id(), balance);
+        Ok(balance)
     }
 
-    pub fn get_info(&self) -> Result<FederationInfo> {
+    /// Requests an invoice for a BOLT12 offer from the offer's node
+    pub async fn fetch_offer_invoice(
+        &self,
+        offer: Bolt12Offer,
+        amount: Option<Amount>,
+    ) -> Result<Bolt12Invoice> {
+        let FetchInvoiceResponse { invoice } = self
+            .lnrpc
+            .fetch_invoice(FetchInvoiceRequest {
+                offer: offer.to_string(),
+                amount_msat: amount.map(|amount| amount.msats).unwrap_or_default(),
+            })
+            .await?;
+
+        invoice
+            .parse()
+            .map_err(|e| LnGatewayError::Other(anyhow!("LN node returned invalid invoice: {e}")))
+    }
+
+    /// Creates a reusable BOLT12 offer whose payments we forward to `account`
+    /// minus our fees
+    pub async fn create_offer(
+        &self,
+        description: String,
+        account: AccountContract,
+    ) -> Result<Bolt12Offer> {
+        let CreateOfferResponse { offer, offer_id } = self
+            .lnrpc
+            .create_offer(CreateOfferRequest { description })
+            .await?;
+
+        let offer_id = sha256::Hash::from_slice(&offer_id).map_err(|e| {
+            LnGatewayError::Other(anyhow!("LN node returned invalid offer id: {e}"))
+        })?;
+        let offer = offer
+            .parse()
+            .map_err(|e| LnGatewayError::Other(anyhow!("LN node returned invalid offer: {e}")))?;
+        self.client.register_offer(offer_id, account).await;
+
+        Ok(offer)
+    }
+
+    /// Index of the last payment to our node's offers this federation
+    /// processed
+    pub async fn last_offer_pay_index(&self) -> u64 {
+        self.client.last_offer_pay_index().await
+    }
+
+    /// Forwards a payment to one of our node's offers to the federation user
+    /// it was created for, if it was created through this federation
+    pub async fn handle_offer_payment(&self, payment: &WaitOfferPaymentResponse) -> Result<()> {
+        if payment.pay_index <= self.client.last_offer_pay_index().await {
+            return Ok(());
+        }
+
+        let offer_id = sha256::Hash::from_slice(&payment.offer_id).map_err(|e| {
+            LnGatewayError::Other(anyhow!("LN node returned invalid offer id: {e}"))
+        })?;
+        if let Some(account) = self.client.offer_account(offer_id).await {
+            let contract_id = account.contract_id();
+            let amount = Amount::from_msats(payment.amount_msat);
+            let fee = self.client.config().fees.fee(amount);
+            if fee < amount {
+                let outpoint = self
+                    .client
+                    .fund_account_contract(account, amount - fee, rand::rngs::OsRng)
+                    .await?;
+                info!(%offer_id, ?outpoint, "Forwarded offer payment to federation user");
+                self.record_payment(contract_id, ContractDirection::Incoming, amount, fee, None)
+                    .await;
+                self.notifier.notify(WebhookEvent::OfferPaymentReceived {
+                    federation_id: self.federation_id(),
+                    offer_id,
+                    amount: amount - fee,
+                });
+            } else {
+                warn!(%offer_id, %amount, "Offer payment doesn't cover our fees");
+            }
+        }
+
+        self.client
+            .save_last_offer_pay_index(payment.pay_index)
+            .await;
+        Ok(())
+    }
+
+    fn federation_id(&self) -> FederationId {
+        self.client.config().client_config.federation_id.clone()
+    }
+
+    pub async fn get_info(&self) -> Result<FederationInfo> {
         let cfg = self.client.config();
         Ok(FederationInfo {
             federation_id: cfg.client_config.federation_id.clone(),
             mint_pubkey: cfg.redeem_key.x_only_public_key().0,
+            mint_channel_id: cfg.mint_channel_id,
+            balance_msat: self.get_balance().await?.msats,
         })
     }
+
+    /// Checks that enough guardians of the federation answer requests
+    pub async fn check_federation(&self) -> Result<()> {
+        self.client.fetch_epoch_count().await?;
+        Ok(())
+    }
+
+    /// Stops registering with the federation and processing its payments
+    pub async fn shutdown(&self) {
+        self.task_group.shutdown().await;
+        metrics::remove_ecash_balance(&self.federation_id());
+    }
+}
+
+fn parse_preimage(preimage: Vec<u8>) -> Result<Preimage> {
+    let preimage: [u8; 32] = preimage
+        .try_into()
+        .map_err(|_| LnGatewayError::Other(anyhow!("LN node returned invalid preimage")))?;
+    Ok(Preimage(preimage))
 }
@@ -18,8 +18,15 @@ use fedimint_api::{
 };
 use fedimint_server::api::WsClientConnectInfo;
 use mint_client::{
-    ln::PayInvoicePayload,
-    modules::ln::{contracts::Preimage, route_hints::RouteHint},
+    ln::{
+        proof::ContractProof, watchtower::RegisterRefundPayload, CreateOfferPayload,
+        CreateOfferResponse, FetchOfferInvoicePayload, FetchOfferInvoiceResponse, GatewayPayment,
+        PayInvoicePayload,
+    },
+    modules::ln::{
+        contracts::{account::AccountContract, Preimage},
+        route_hints::RouteHint,
+    },
     GatewayClient,
 };
 use secp256k1::PublicKey;
@@ -29,29 +36,37 @@ use tracing::{error, info, warn};
 use super::actor::GatewayActor;
 use crate::{
     client::DynGatewayClientBuilder,
-    gatewayd::lnrpc_client::{DynLnRpcClient, GetRouteHintsResponse},
-    gatewaylnrpc::GetPubKeyResponse,
+    gatewayd::{
+        health::GatewayHealth,
+        lnrpc_client::{DynLnRpcClient, GetRouteHintsResponse},
+        metrics,
+        webhook::WebhookNotifier,
+    },
+    gatewaylnrpc::{GetPubKeyResponse, WaitOfferPaymentRequest},
     rpc::{
         rpc_server::run_webserver, BackupPayload, BalancePayload, ConnectFedPayload,
-        DepositAddressPayload, DepositPayload, GatewayInfo, GatewayRequest, GatewayRpcSender,
-        InfoPayload, ReceivePaymentPayload, RestorePayload, WithdrawPayload,
+        ContractProofPayload, DepositAddressPayload, DepositPayload, DisconnectFedPayload,
+        GatewayInfo, GatewayRequest, GatewayRpcSender, InfoPayload, ListPaymentsPayload,
+        ReceivePaymentPayload, RestorePayload, WithdrawPayload,
     },
     LnGatewayError, Result,
 };
 
 const ROUTE_HINT_RETRIES: usize = 10;
 const ROUTE_HINT_RETRY_SLEEP: Duration = Duration::from_secs(2);
+const OFFER_PAYMENT_RETRY_SLEEP: Duration = Duration::from_secs(10);
 
 pub struct Gateway {
     decoders: ModuleDecoderRegistry,
     module_gens: ModuleGenRegistry,
     lnrpc: DynLnRpcClient,
-    actors: Mutex<HashMap<String, Arc<GatewayActor>>>,
+    actors: Arc<Mutex<HashMap<String, Arc<GatewayActor>>>>,
     client_builder: DynGatewayClientBuilder,
     sender: mpsc::Sender<GatewayRequest>,
     receiver: mpsc::Receiver<GatewayRequest>,
     task_group: TaskGroup,
     channel_id_generator: AtomicU64,
+    notifier: WebhookNotifier,
 }
 
 impl Gateway {
@@ -62,6 +77,7 @@ impl Gateway {
         decoders: ModuleDecoderRegistry,
         module_gens: ModuleGenRegistry,
         task_group: TaskGroup,
+        notifier: WebhookNotifier,
     ) -> Self {
         // Create message channels for the webserver
         let (sender, receiver) = mpsc::channel::<GatewayRequest>(100);
@@ -89,12 +105,13 @@ impl Gateway {
 
         let gw = Self {
             lnrpc,
-            actors: Mutex::new(HashMap::new()),
+            actors: Arc::new(Mutex::new(HashMap::new())),
             sender,
             receiver,
             client_builder,
             task_group,
             channel_id_generator: AtomicU64::new(0),
+            notifier,
             decoders: decoders.clone(),
             module_gens: module_gens.clone(),
         };
@@ -159,6 +176,7 @@ impl Gateway {
                 self.lnrpc.clone(),
                 route_hints,
                 self.task_group.clone(),
+                self.notifier.clone(),
             )
             .await?,
         );
@@ -175,6 +193,14 @@ impl Gateway {
         payload: ConnectFedPayload,
         route_hints: Vec<RouteHint>,
     ) -> Result<()> {
+        if let Some(band) = payload.rebalance {
+            if band.min_ecash > band.max_ecash {
+                return Err(LnGatewayError::Other(anyhow!(
+                    "Rebalance band minimum exceeds its maximum"
+                )));
+            }
+        }
+
         let connect: WsClientConnectInfo = serde_json::from_str(&payload.connect).map_err(|e| {
             LnGatewayError::Other(anyhow::anyhow!("Invalid federation member string {}", e))
         })?;
@@ -188,11 +214,21 @@ impl Gateway {
         // overflows
         let channel_id = self.channel_id_generator.fetch_add(1, Ordering::SeqCst);
 
-        let gw_client_cfg = self
+        let mut gw_client_cfg = self
             .client_builder
             .create_config(connect, channel_id, node_pub_key, self.module_gens.clone())
             .await
             .expect("Failed to create gateway client config");
+        let federation_id = gw_client_cfg.client_config.federation_id.to_string();
+        if self.actors.lock().await.contains_key(&federation_id) {
+            return Err(LnGatewayError::Other(anyhow!(
+                "Federation {federation_id} is already connected"
+            )));
+        }
+        gw_client_cfg.fees = payload.fees;
+        gw_client_cfg.limits = payload.limits;
+        gw_client_cfg.rebalance = payload.rebalance;
+        gw_client_cfg.watchtower = payload.watchtower;
 
         let client = Arc::new(
             self.client_builder
@@ -219,14 +255,40 @@ impl Gateway {
         Ok(())
     }
 
+    /// Stops serving a federation at runtime. The federation's client database
+    /// is kept, so its e-cash is available again once the federation gets
+    /// connected anew.
+    async fn handle_disconnect_federation(&self, payload: DisconnectFedPayload) -> Result<()> {
+        let DisconnectFedPayload { federation_id } = payload;
+
+        let actor = self
+            .actors
+            .lock()
+            .await
+            .remove(&federation_id.to_string())
+            .ok_or(LnGatewayError::UnknownFederation)?;
+        actor.shutdown().await;
+
+        if let Err(e) = self.client_builder.remove_config(federation_id.clone()) {
+            warn!("Failed to remove federation client configuration: {}", e);
+        }
+        info!(%federation_id, "Disconnected federation");
+
+        Ok(())
+    }
+
     async fn handle_get_info(&self, _payload: InfoPayload) -> Result<GatewayInfo> {
-        let federations = self
+        let actors = self
             .actors
             .lock()
             .await
-            .iter()
-            .map(|(_, actor)| actor.get_info().expect("Failed to get actor info"))
-            .collect();
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut federations = vec![];
+        for actor in actors {
+            federations.push(actor.get_info().await?);
+        }
 
         Ok(GatewayInfo {
             federations,
@@ -242,7 +304,7 @@ impl Gateway {
         )))
     }
 
-    async fn handle_pay_invoice_msg(&self, payload: PayInvoicePayload) -> Result<()> {
+    async fn handle_pay_invoice_msg(&self, payload: PayInvoicePayload) -> Result<Preimage> {
         let PayInvoicePayload {
             federation_id,
             contract_id,
@@ -253,7 +315,35 @@ impl Gateway {
         actor
             .await_outgoing_contract_claimed(contract_id, outpoint)
             .await?;
-        Ok(())
+        actor.outgoing_contract_preimage(contract_id).await
+    }
+
+    async fn handle_contract_proof_msg(
+        &self,
+        payload: ContractProofPayload,
+    ) -> Result<ContractProof> {
+        self.select_actor(payload.federation_id)
+            .await?
+            .contract_proof(payload.contract_id)
+            .await
+    }
+
+    async fn handle_register_refund_msg(&self, payload: RegisterRefundPayload) -> Result<()> {
+        self.select_actor(payload.federation_id)
+            .await?
+            .watch_refund(payload.refund)
+            .await
+    }
+
+    async fn handle_list_payments_msg(
+        &self,
+        payload: ListPaymentsPayload,
+    ) -> Result<Vec<GatewayPayment>> {
+        Ok(self
+            .select_actor(payload.federation_id)
+            .await?
+            .list_payments(payload.limit)
+            .await)
     }
 
     async fn handle_balance_msg(&self, payload: BalancePayload) -> Result<Amount> {
@@ -310,6 +400,48 @@ impl Gateway {
         self.select_actor(federation_id).await?.restore().await
     }
 
+    async fn handle_fetch_offer_invoice_msg(
+        &self,
+        payload: FetchOfferInvoicePayload,
+    ) -> Result<FetchOfferInvoiceResponse> {
+        let FetchOfferInvoicePayload {
+            federation_id,
+            offer,
+            amount,
+        } = payload;
+
+        let invoice = self
+            .select_actor(federation_id)
+            .await?
+            .fetch_offer_invoice(offer, amount)
+            .await?;
+        Ok(FetchOfferInvoiceResponse { invoice })
+    }
+
+    async fn handle_create_offer_msg(
+        &self,
+        payload: CreateOfferPayload,
+    ) -> Result<CreateOfferResponse> {
+        let CreateOfferPayload {
+            federation_id,
+            description,
+            account_key,
+        } = payload;
+
+        let offer = self
+            .select_actor(federation_id)
+            .await?
+            .create_offer(description, AccountContract { key: account_key })
+            .await?;
+        Ok(CreateOfferResponse { offer })
+    }
+
+    /// Returns the checks of the lightning node and the connected federations
+    /// served by [`run_health_server`](super::health::run_health_server)
+    pub fn health(&self) -> GatewayHealth {
+        GatewayHealth::new(self.lnrpc.clone(), self.actors.clone())
+    }
+
     pub async fn run(mut self, listen: SocketAddr, password: String) -> Result<()> {
         let mut tg = self.task_group.clone();
 
@@ -325,6 +457,48 @@ impl Gateway {
         })
         .await;
 
+        metrics::spawn_ln_node_monitor(self.lnrpc.clone(), &mut tg).await;
+
+        let lnrpc = self.lnrpc.clone();
+        let actors = self.actors.clone();
+        tg.spawn("Offer payments", move |handle| async move {
+            // Resume after the last payment every federation has processed, actors skip
+            // payments they already handled themselves
+            let mut last_pay_index = u64::MAX;
+            for actor in actors.lock().await.values() {
+                last_pay_index = last_pay_index.min(actor.last_offer_pay_index().await);
+            }
+            if last_pay_index == u64::MAX {
+                last_pay_index = 0;
+            }
+
+            while !handle.is_shutting_down() {
+                let payment = match lnrpc
+                    .wait_offer_payment(WaitOfferPaymentRequest { last_pay_index })
+                    .await
+                {
+                    Ok(payment) => payment,
+                    Err(e) => {
+                        warn!("Failed to wait for offer payments: {}", e);
+                        fedimint_api::task::sleep(OFFER_PAYMENT_RETRY_SLEEP).await;
+                        continue;
+                    }
+                };
+                last_pay_index = payment.pay_index;
+
+                let actors = actors.lock().await.values().cloned().collect::<Vec<_>>();
+                for actor in actors {
+                    if let Err(e) = actor.handle_offer_payment(&payment).await {
+                        error!(
+                            pay_index = payment.pay_index,
+                            "Failed to forward offer payment: {}", e
+                        );
+                    }
+                }
+            }
+        })
+        .await;
+
         // TODO: try to drive forward outgoing and incoming payments that were
         // interrupted
         let loop_ctrl = tg.make_handle();
@@ -352,6 +526,11 @@ impl Gateway {
                             })
                             .await;
                     }
+                    GatewayRequest::DisconnectFederation(inner) => {
+                        inner
+                            .handle(|payload| self.handle_disconnect_federation(payload))
+                            .await;
+                    }
                     // TODO: Remove this handler because Gateway uses lnrpc to intercept HTLCs
                     GatewayRequest::ReceivePayment(inner) => {
                         inner
@@ -393,6 +572,31 @@ impl Gateway {
                             .handle(|payload| self.handle_restore_msg(payload))
                             .await;
                     }
+                    GatewayRequest::FetchOfferInvoice(inner) => {
+                        inner
+                            .handle(|payload| self.handle_fetch_offer_invoice_msg(payload))
+                            .await;
+                    }
+                    GatewayRequest::CreateOffer(inner) => {
+                        inner
+                            .handle(|payload| self.handle_create_offer_msg(payload))
+                            .await;
+                    }
+                    GatewayRequest::ContractProof(inner) => {
+                        inner
+                            .handle(|payload| self.handle_contract_proof_msg(payload))
+                            .await;
+                    }
+                    GatewayRequest::ListPayments(inner) => {
+                        inner
+                            .handle(|payload| self.handle_list_payments_msg(payload))
+                            .await;
+                    }
+                    GatewayRequest::RegisterRefund(inner) => {
+                        inner
+                            .handle(|payload| self.handle_register_refund_msg(payload))
+                            .await;
+                    }
                 }
             }
 
@@ -0,0 +1,146 @@
+//! Liveness and readiness of the gateway, served over HTTP for orchestrators
+//!
+//! The lightning node and the connected federations are checked on their own
+//! endpoints, so an orchestrator can tell whether to restart the gateway or
+//! its lightning node.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use fedimint_api::task::{timeout, TaskGroup};
+use futures::future::join_all;
+use serde::Serialize;
+use tokio::{select, sync::Mutex};
+use tracing::{debug, error};
+
+use super::{actor::GatewayActor, lnrpc_client::DynLnRpcClient};
+
+/// How long the lightning node or a federation may take to answer a check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Checks of the dependencies of a running gateway
+#[derive(Clone)]
+pub struct GatewayHealth {
+    lnrpc: DynLnRpcClient,
+    /// Actors of the connected federations by federation id
+    actors: Arc<Mutex<HashMap<String, Arc<GatewayActor>>>>,
+}
+
+/// Response of the endpoints checking a single dependency
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckStatus {
+    pub healthy: bool,
+    /// What the check found or why it failed
+    pub message: String,
+}
+
+impl GatewayHealth {
+    pub fn new(
+        lnrpc: DynLnRpcClient,
+        actors: Arc<Mutex<HashMap<String, Arc<GatewayActor>>>>,
+    ) -> Self {
+        GatewayHealth { lnrpc, actors }
+    }
+
+    /// Checks that the lightning node answers requests in time
+    pub async fn check_lightning(&self) -> CheckStatus {
+        let (healthy, message) = match timeout(CHECK_TIMEOUT, self.lnrpc.pubkey()).await {
+            Ok(Ok(_)) => (true, "Lightning node answered".to_string()),
+            Ok(Err(e)) => (false, format!("Lightning node failed: {e}")),
+            Err(_) => (false, "Lightning node did not answer in time".to_string()),
+        };
+        CheckStatus { healthy, message }
+    }
+
+    /// Checks that enough guardians of every connected federation answer
+    /// requests in time
+    pub async fn check_federations(&self) -> CheckStatus {
+        let actors = self
+            .actors
+            .lock()
+            .await
+            .iter()
+            .map(|(id, actor)| (id.clone(), actor.clone()))
+            .collect::<Vec<_>>();
+
+        let failures = join_all(actors.iter().map(|(id, actor)| async move {
+            match timeout(CHECK_TIMEOUT, actor.check_federation()).await {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(format!("{id}: {e}")),
+                Err(_) => Some(format!("{id}: did not answer in time")),
+            }
+        }))
+        .await
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        if failures.is_empty() {
+            CheckStatus {
+                healthy: true,
+                message: format!("{} federations answered", actors.len()),
+            }
+        } else {
+            CheckStatus {
+                healthy: false,
+                message: format!("Federations failed: {}", failures.join(", ")),
+            }
+        }
+    }
+}
+
+/// Serves the health endpoints:
+/// - `/health/live` succeeds as long as the process responds
+/// - `/health/ready` fails with `503` while the lightning node doesn't answer,
+///   without it the gateway can't route payments of any federation
+/// - `/health/lightning` and `/health/federations` fail with `503` while the
+///   respective [`GatewayHealth`] check fails
+pub async fn run_health_server(
+    bind_address: SocketAddr,
+    health: GatewayHealth,
+    task_group: &mut TaskGroup,
+) -> anyhow::Result<()> {
+    let app = Router::new()
+        .route("/health/live", get(|| async { "OK" }))
+        .route("/health/ready", get(lightning))
+        .route("/health/lightning", get(lightning))
+        .route("/health/federations", get(federations))
+        .with_state(health);
+    let server = axum::Server::try_bind(&bind_address)?.serve(app.into_make_service());
+    let shutdown_future = task_group.make_handle().make_shutdown_rx().await;
+
+    debug!(%bind_address, "Starting health server");
+    task_group
+        .spawn("health-server", move |_| async move {
+            select! {
+                _ = shutdown_future => {
+                    debug!("Health server shutting down");
+                },
+                Err(err) = server => {
+                    error!(?err, "Health server encountered an error");
+                }
+            }
+        })
+        .await;
+    Ok(())
+}
+
+async fn lightning(State(health): State<GatewayHealth>) -> (StatusCode, String) {
+    check_response(health.check_lightning().await)
+}
+
+async fn federations(State(health): State<GatewayHealth>) -> (StatusCode, String) {
+    check_response(health.check_federations().await)
+}
+
+fn check_response(check: CheckStatus) -> (StatusCode, String) {
+    let status = if check.healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        serde_json::to_string(&check).expect("serialization can't fail"),
+    )
+}
@@ -4,28 +4,59 @@ use anyhow::anyhow;
 use async_trait::async_trait;
 use fedimint_api::dyn_newtype_define;
 use futures::stream::BoxStream;
-use mint_client::modules::ln::route_hints::RouteHint;
+use mint_client::modules::ln::route_hints::{RouteHint, RouteHintHop};
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
 use tonic::{
-    transport::{Channel, Endpoint},
+    transport::{Channel, Endpoint, Uri},
     Request,
 };
+use tower::service_fn;
 use tracing::error;
 use url::Url;
 
 use crate::{
     gatewaylnrpc::{
-        gateway_lightning_client::GatewayLightningClient, CompleteHtlcsRequest,
-        CompleteHtlcsResponse, GetPubKeyRequest, GetPubKeyResponse, PayInvoiceRequest,
-        PayInvoiceResponse, SubscribeInterceptHtlcsRequest, SubscribeInterceptHtlcsResponse,
+        gateway_lightning_client::GatewayLightningClient, get_route_hints_response,
+        CompleteHtlcsRequest, CompleteHtlcsResponse, CreateOfferRequest, CreateOfferResponse,
+        FetchInvoiceRequest, FetchInvoiceResponse, GetLiquidityRequest, GetLiquidityResponse,
+        GetNewAddressRequest, GetNewAddressResponse, GetPaymentFeaturesRequest,
+        GetPaymentFeaturesResponse, GetPaymentStatusRequest, GetPaymentStatusResponse,
+        GetPubKeyRequest, GetPubKeyResponse, GetRouteHintsRequest, GetTransactionBlockRequest,
+        GetTransactionBlockResponse, PayInvoiceRequest, PayInvoiceResponse,
+        SubscribeInterceptHtlcsRequest, SubscribeInterceptHtlcsResponse, WaitOfferPaymentRequest,
+        WaitOfferPaymentResponse, WithdrawOnchainRequest, WithdrawOnchainResponse,
     },
     LnGatewayError, Result,
 };
 
-// TODO: Issue 1554: Define gatewaylnpc spec for getting route hints
 pub struct GetRouteHintsResponse {
     pub route_hints: Vec<RouteHint>,
 }
 
+impl TryFrom<get_route_hints_response::RouteHint> for RouteHint {
+    type Error = anyhow::Error;
+
+    fn try_from(route_hint: get_route_hints_response::RouteHint) -> anyhow::Result<Self> {
+        let hops = route_hint
+            .hops
+            .into_iter()
+            .map(|hop| {
+                Ok(RouteHintHop {
+                    src_node_id: secp256k1::PublicKey::from_slice(&hop.src_node_id)?,
+                    short_channel_id: hop.short_channel_id,
+                    base_msat: hop.base_msat,
+                    proportional_millionths: hop.proportional_millionths,
+                    cltv_expiry_delta: hop.cltv_expiry_delta.try_into()?,
+                    htlc_minimum_msat: Some(hop.htlc_minimum_msat).filter(|msat| *msat != 0),
+                    htlc_maximum_msat: Some(hop.htlc_maximum_msat).filter(|msat| *msat != 0),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(RouteHint(hops))
+    }
+}
+
 pub type HtlcStream<'a> =
     BoxStream<'a, std::result::Result<SubscribeInterceptHtlcsResponse, tonic::Status>>;
 
@@ -37,9 +68,22 @@ pub trait ILnRpcClient: Debug + Send + Sync {
     /// Get route hints to the lightning node
     async fn route_hints(&self) -> Result<GetRouteHintsResponse>;
 
+    /// Get the liquidity available in the active channels of the lightning
+    /// node
+    async fn liquidity(&self) -> Result<GetLiquidityResponse>;
+
+    /// Get the payment features supported by the lightning node
+    async fn payment_features(&self) -> Result<GetPaymentFeaturesResponse>;
+
     /// Attempt to pay an invoice using the lightning node
     async fn pay(&self, invoice: PayInvoiceRequest) -> Result<PayInvoiceResponse>;
 
+    /// Get the state of an outgoing payment and its parts
+    async fn payment_status(
+        &self,
+        request: GetPaymentStatusRequest,
+    ) -> Result<GetPaymentStatusResponse>;
+
     /// Subscribe to intercept htlcs that belong to a specific mint identified
     /// by `short_channel_id`
     async fn subscribe_htlcs<'a>(
@@ -50,6 +94,34 @@ pub trait ILnRpcClient: Debug + Send + Sync {
     /// Request completion of an intercepted htlc after processing and
     /// determining an outcome
     async fn complete_htlc(&self, outcome: CompleteHtlcsRequest) -> Result<CompleteHtlcsResponse>;
+
+    /// Request an invoice for a BOLT12 offer from the offer's node
+    async fn fetch_invoice(&self, request: FetchInvoiceRequest) -> Result<FetchInvoiceResponse>;
+
+    /// Create a reusable BOLT12 offer payable to the lightning node
+    async fn create_offer(&self, request: CreateOfferRequest) -> Result<CreateOfferResponse>;
+
+    /// Wait for the next payment to an offer created with `create_offer`
+    async fn wait_offer_payment(
+        &self,
+        request: WaitOfferPaymentRequest,
+    ) -> Result<WaitOfferPaymentResponse>;
+
+    /// Get a fresh address of the lightning node's on-chain wallet
+    async fn new_address(&self) -> Result<GetNewAddressResponse>;
+
+    /// Send funds from the lightning node's on-chain wallet
+    async fn withdraw_onchain(
+        &self,
+        request: WithdrawOnchainRequest,
+    ) -> Result<WithdrawOnchainResponse>;
+
+    /// Get the block that confirmed a transaction of the lightning node's
+    /// on-chain wallet
+    async fn transaction_block(
+        &self,
+        request: GetTransactionBlockRequest,
+    ) -> Result<GetTransactionBlockResponse>;
 }
 
 dyn_newtype_define!(
@@ -74,23 +146,53 @@ pub struct NetworkLnRpcClient {
 }
 
 impl NetworkLnRpcClient {
-    pub async fn new(url: Url) -> Result<Self> {
+    /// Connects to the lnrpc server at `url`, through the SOCKS5 proxy at
+    /// `host:port` if one is given
+    pub async fn new(url: Url, socks5_proxy: Option<String>) -> Result<Self> {
         let endpoint = Endpoint::from_shared(url.to_string()).map_err(|e| {
             error!("Failed to create lnrpc endpoint from url : {:?}", e);
             LnGatewayError::Other(anyhow!("Failed to create lnrpc endpoint from url"))
         })?;
 
-        let client = GatewayLightningClient::connect(endpoint)
-            .await
-            .map_err(|e| {
-                error!("Failed to connect to lnrpc server: {:?}", e);
-                LnGatewayError::Other(anyhow!("Failed to connect to lnrpc server"))
-            })?;
+        let channel = match socks5_proxy {
+            Some(proxy) => {
+                endpoint
+                    .connect_with_connector(service_fn(move |uri| {
+                        socks5_connect(proxy.clone(), uri)
+                    }))
+                    .await
+            }
+            None => endpoint.connect().await,
+        }
+        .map_err(|e| {
+            error!("Failed to connect to lnrpc server: {:?}", e);
+            LnGatewayError::Other(anyhow!("Failed to connect to lnrpc server"))
+        })?;
 
-        Ok(Self { client })
+        Ok(Self {
+            client: GatewayLightningClient::new(channel),
+        })
     }
 }
 
+/// Opens a TCP connection to `uri` through the SOCKS5 proxy at `proxy`, which
+/// also resolves the host name
+async fn socks5_connect(proxy: String, uri: Uri) -> anyhow::Result<TcpStream> {
+    let host = uri
+        .host()
+        .ok_or_else(|| anyhow!("Missing host in lnrpc url {uri}"))?;
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        });
+    Ok(Socks5Stream::connect(proxy.as_str(), (host, port))
+        .await?
+        .into_inner())
+}
+
 #[async_trait]
 impl ILnRpcClient for NetworkLnRpcClient {
     async fn pubkey(&self) -> Result<GetPubKeyResponse> {
@@ -103,7 +205,37 @@ impl ILnRpcClient for NetworkLnRpcClient {
     }
 
     async fn route_hints(&self) -> Result<GetRouteHintsResponse> {
-        unimplemented!()
+        let req = Request::new(GetRouteHintsRequest {});
+
+        let mut client = self.client.clone();
+        let res = client.get_route_hints(req).await?;
+
+        let route_hints = res
+            .into_inner()
+            .route_hints
+            .into_iter()
+            .map(RouteHint::try_from)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(LnGatewayError::Other)?;
+        Ok(GetRouteHintsResponse { route_hints })
+    }
+
+    async fn liquidity(&self) -> Result<GetLiquidityResponse> {
+        let req = Request::new(GetLiquidityRequest {});
+
+        let mut client = self.client.clone();
+        let res = client.get_liquidity(req).await?;
+
+        Ok(res.into_inner())
+    }
+
+    async fn payment_features(&self) -> Result<GetPaymentFeaturesResponse> {
+        let req = Request::new(GetPaymentFeaturesRequest {});
+
+        let mut client = self.client.clone();
+        let res = client.get_payment_features(req).await?;
+
+        Ok(res.into_inner())
     }
 
     async fn pay(&self, invoice: PayInvoiceRequest) -> Result<PayInvoiceResponse> {
@@ -115,6 +247,18 @@ impl ILnRpcClient for NetworkLnRpcClient {
         Ok(res.into_inner())
     }
 
+    async fn payment_status(
+        &self,
+        request: GetPaymentStatusRequest,
+    ) -> Result<GetPaymentStatusResponse> {
+        let req = Request::new(request);
+
+        let mut client = self.client.clone();
+        let res = client.get_payment_status(req).await?;
+
+        Ok(res.into_inner())
+    }
+
     async fn subscribe_htlcs<'a>(
         &self,
         subscription: SubscribeInterceptHtlcsRequest,
@@ -135,4 +279,67 @@ impl ILnRpcClient for NetworkLnRpcClient {
 
         Ok(res.into_inner())
     }
+
+    async fn fetch_invoice(&self, request: FetchInvoiceRequest) -> Result<FetchInvoiceResponse> {
+        let req = Request::new(request);
+
+        let mut client = self.client.clone();
+        let res = client.fetch_invoice(req).await?;
+
+        Ok(res.into_inner())
+    }
+
+    async fn create_offer(&self, request: CreateOfferRequest) -> Result<CreateOfferResponse> {
+        let req = Request::new(request);
+
+        let mut client = self.client.clone();
+        let res = client.create_offer(req).await?;
+
+        Ok(res.into_inner())
+    }
+
+    async fn wait_offer_payment(
+        &self,
+        request: WaitOfferPaymentRequest,
+    ) -> Result<WaitOfferPaymentResponse> {
+        let req = Request::new(request);
+
+        let mut client = self.client.clone();
+        let res = client.wait_offer_payment(req).await?;
+
+        Ok(res.into_inner())
+    }
+
+    async fn new_address(&self) -> Result<GetNewAddressResponse> {
+        let req = Request::new(GetNewAddressRequest {});
+
+        let mut client = self.client.clone();
+        let res = client.get_new_address(req).await?;
+
+        Ok(res.into_inner())
+    }
+
+    async fn withdraw_onchain(
+        &self,
+        request: WithdrawOnchainRequest,
+    ) -> Result<WithdrawOnchainResponse> {
+        let req = Request::new(request);
+
+        let mut client = self.client.clone();
+        let res = client.withdraw_onchain(req).await?;
+
+        Ok(res.into_inner())
+    }
+
+    async fn transaction_block(
+        &self,
+        request: GetTransactionBlockRequest,
+    ) -> Result<GetTransactionBlockResponse> {
+        let req = Request::new(request);
+
+        let mut client = self.client.clone();
+        let res = client.get_transaction_block(req).await?;
+
+        Ok(res.into_inner())
+    }
 }
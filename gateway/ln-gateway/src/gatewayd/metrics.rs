@@ -0,0 +1,134 @@
+//! Prometheus metrics of the gateway, served by
+//! [`fedimint_metrics::run_api_server`] if gatewayd is given a metrics address
+
+use std::time::Duration;
+
+use fedimint_api::{config::FederationId, task::TaskGroup, Amount};
+use fedimint_metrics::{opts, IntCounterVec, IntGauge, IntGaugeVec, REGISTRY};
+use mint_client::ln::proof::ContractDirection;
+use once_cell::sync::Lazy;
+
+use super::lnrpc_client::DynLnRpcClient;
+
+/// How often we check whether the lightning node is reachable
+const LN_NODE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+static PAYMENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    let counter = IntCounterVec::new(
+        opts!(
+            "gateway_payments_total",
+            "Number of payments the gateway routed or failed to route"
+        ),
+        &["federation", "direction", "outcome"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(counter.clone())).unwrap();
+    counter
+});
+
+static PAYMENT_VOLUME: Lazy<IntCounterVec> = Lazy::new(|| {
+    let counter = IntCounterVec::new(
+        opts!(
+            "gateway_payment_volume_msat_total",
+            "Amount of the payments the gateway routed successfully"
+        ),
+        &["federation", "direction"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(counter.clone())).unwrap();
+    counter
+});
+
+static FEE_REVENUE: Lazy<IntCounterVec> = Lazy::new(|| {
+    let counter = IntCounterVec::new(
+        opts!(
+            "gateway_fee_revenue_msat_total",
+            "Fees the gateway charged for routing payments, before lightning routing fees"
+        ),
+        &["federation", "direction"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(counter.clone())).unwrap();
+    counter
+});
+
+static ECASH_BALANCE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let gauge = IntGaugeVec::new(
+        opts!(
+            "gateway_ecash_balance_msat",
+            "E-cash the gateway holds in each connected federation"
+        ),
+        &["federation"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(gauge.clone())).unwrap();
+    gauge
+});
+
+static LN_NODE_UP: Lazy<IntGauge> = Lazy::new(|| {
+    let gauge = IntGauge::with_opts(opts!(
+        "gateway_ln_node_up",
+        "Whether the lightning node answered the last connectivity check"
+    ))
+    .unwrap();
+    REGISTRY.register(Box::new(gauge.clone())).unwrap();
+    gauge
+});
+
+fn direction_label(direction: ContractDirection) -> &'static str {
+    match direction {
+        ContractDirection::Outgoing => "outgoing",
+        ContractDirection::Incoming => "incoming",
+    }
+}
+
+pub fn record_payment_success(
+    federation_id: &FederationId,
+    direction: ContractDirection,
+    amount: Amount,
+    fee: Amount,
+) {
+    let federation = federation_id.to_string();
+    PAYMENTS
+        .with_label_values(&[&federation, direction_label(direction), "success"])
+        .inc();
+    PAYMENT_VOLUME
+        .with_label_values(&[&federation, direction_label(direction)])
+        .inc_by(amount.msats);
+    FEE_REVENUE
+        .with_label_values(&[&federation, direction_label(direction)])
+        .inc_by(fee.msats);
+}
+
+pub fn record_payment_failure(federation_id: &FederationId, direction: ContractDirection) {
+    PAYMENTS
+        .with_label_values(&[
+            &federation_id.to_string(),
+            direction_label(direction),
+            "failure",
+        ])
+        .inc();
+}
+
+pub fn record_ecash_balance(federation_id: &FederationId, balance: Amount) {
+    ECASH_BALANCE
+        .with_label_values(&[&federation_id.to_string()])
+        .set(balance.msats.try_into().unwrap_or(i64::MAX));
+}
+
+/// Stops reporting the balance of a federation that got disconnected
+pub fn remove_ecash_balance(federation_id: &FederationId) {
+    let _ = ECASH_BALANCE.remove_label_values(&[&federation_id.to_string()]);
+}
+
+/// Periodically checks whether the lightning node answers requests
+pub async fn spawn_ln_node_monitor(lnrpc: DynLnRpcClient, task_group: &mut TaskGroup) {
+    task_group
+        .spawn("Monitor LN node connectivity", move |handle| async move {
+            while !handle.is_shutting_down() {
+                LN_NODE_UP.set(lnrpc.pubkey().await.is_ok().into());
+                fedimint_api::task::sleep(LN_NODE_CHECK_INTERVAL).await;
+            }
+        })
+        .await;
+}
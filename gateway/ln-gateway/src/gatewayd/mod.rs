@@ -17,4 +17,7 @@
 
 pub mod actor;
 pub mod gateway;
+pub mod health;
 pub mod lnrpc_client;
+pub mod metrics;
+pub mod webhook;
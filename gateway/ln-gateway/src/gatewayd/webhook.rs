@@ -0,0 +1,258 @@
+//! Push notifications about gateway activity to merchant backends
+//!
+//! Every event is POSTed as JSON to all configured webhook URLs. The request
+//! carries an `X-Fedimint-Signature: sha256=<hex>` header holding the
+//! HMAC-SHA256 of the raw body keyed with the shared webhook secret, which
+//! receivers must check before trusting the notification. Deliveries that
+//! still fail after [`WEBHOOK_MAX_ATTEMPTS`] are appended to a dead letter
+//! file so no notification is lost silently.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use bitcoin::Txid;
+use bitcoin_hashes::{hex::ToHex, hmac, sha256, Hash, HashEngine};
+use fedimint_api::{config::FederationId, task::TaskGroup, Amount, TransactionId};
+use mint_client::modules::ln::contracts::ContractId;
+use rand::RngCore;
+use serde::Serialize;
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+use tracing::{debug, error, warn};
+use url::Url;
+
+/// How often we try to deliver an event to a webhook before giving up
+pub const WEBHOOK_MAX_ATTEMPTS: u32 = 6;
+/// Wait before the first retry, doubled after every failed attempt
+const WEBHOOK_RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const WEBHOOK_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Header carrying the hex encoded HMAC-SHA256 of the request body
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Fedimint-Signature";
+/// Events that haven't been picked up by the delivery task yet, further events
+/// are dropped with a warning instead of stalling payments
+const WEBHOOK_QUEUE_SIZE: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub urls: Vec<Url>,
+    /// Key of the HMAC signing every notification
+    pub secret: String,
+    /// File that events which couldn't be delivered are appended to as JSON
+    /// lines
+    pub dead_letter_path: PathBuf,
+}
+
+/// Gateway activity merchants can subscribe to
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// An intercepted HTLC was settled after a federation user revealed its
+    /// preimage
+    PaymentReceived {
+        federation_id: FederationId,
+        payment_hash: sha256::Hash,
+        amount: Amount,
+    },
+    /// A payment to one of our offers was forwarded to a federation user
+    OfferPaymentReceived {
+        federation_id: FederationId,
+        offer_id: sha256::Hash,
+        amount: Amount,
+    },
+    /// We couldn't pay an invoice on behalf of a federation user, who can
+    /// reclaim the funds of the outgoing contract
+    PaymentFailed {
+        federation_id: FederationId,
+        contract_id: ContractId,
+        error: String,
+    },
+    /// The federation issued ecash for a deposit to the gateway
+    PegInCompleted {
+        federation_id: FederationId,
+        transaction_id: TransactionId,
+    },
+    /// The federation accepted a withdrawal from the gateway and will
+    /// broadcast the bitcoin transaction
+    PegOutCompleted {
+        federation_id: FederationId,
+        transaction_id: Txid,
+        amount: bitcoin::Amount,
+    },
+}
+
+/// Body of a webhook request
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    /// Random id that stays the same across retries so receivers can
+    /// deduplicate notifications
+    id: String,
+    /// Unix timestamp of when the event happened
+    created_at: u64,
+    event: &'a WebhookEvent,
+}
+
+/// Dead letter file entry
+#[derive(Debug, Serialize)]
+struct DeadLetter<'a> {
+    url: &'a Url,
+    attempts: u32,
+    error: String,
+    payload: serde_json::Value,
+}
+
+/// Cheaply cloneable handle queueing events for delivery
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    sender: Option<mpsc::Sender<WebhookEvent>>,
+}
+
+impl WebhookNotifier {
+    /// A notifier that discards all events, used if no webhooks are configured
+    pub fn disabled() -> Self {
+        Self { sender: None }
+    }
+
+    /// Spawns the task delivering queued events to the webhooks in `config`
+    pub async fn new(config: WebhookConfig, task_group: &mut TaskGroup) -> Self {
+        if config.urls.is_empty() {
+            return Self::disabled();
+        }
+
+        let (sender, mut receiver) = mpsc::channel::<WebhookEvent>(WEBHOOK_QUEUE_SIZE);
+        let config = Arc::new(config);
+        let http = reqwest::Client::builder()
+            .timeout(WEBHOOK_REQUEST_TIMEOUT)
+            .build()
+            .expect("Failed to build webhook http client");
+
+        task_group
+            .spawn("Webhook notifications", move |handle| async move {
+                while let Some(event) = receiver.recv().await {
+                    if handle.is_shutting_down() {
+                        break;
+                    }
+
+                    let body = match serde_json::to_vec(&WebhookPayload {
+                        id: random_event_id(),
+                        created_at: SystemTime::now()
+                            .duration_since(SystemTime::UNIX_EPOCH)
+                            .expect("Time went backwards")
+                            .as_secs(),
+                        event: &event,
+                    }) {
+                        Ok(body) => body,
+                        Err(e) => {
+                            error!("Failed to serialize webhook event {:?}: {}", event, e);
+                            continue;
+                        }
+                    };
+                    let signature = sign(config.secret.as_bytes(), &body);
+
+                    // Deliver in the background so one unreachable webhook retrying doesn't
+                    // delay notifications to the others
+                    for url in config.urls.clone() {
+                        tokio::spawn(deliver(
+                            http.clone(),
+                            config.clone(),
+                            url,
+                            body.clone(),
+                            signature.clone(),
+                        ));
+                    }
+                }
+            })
+            .await;
+
+        Self {
+            sender: Some(sender),
+        }
+    }
+
+    /// Queues `event` for delivery without waiting for it
+    pub fn notify(&self, event: WebhookEvent) {
+        if let Some(sender) = &self.sender {
+            if let Err(e) = sender.try_send(event) {
+                warn!("Dropping webhook event: {}", e);
+            }
+        }
+    }
+}
+
+async fn deliver(
+    http: reqwest::Client,
+    config: Arc<WebhookConfig>,
+    url: Url,
+    body: Vec<u8>,
+    signature: String,
+) {
+    let mut delay = WEBHOOK_RETRY_BASE_DELAY;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let error = match http
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(WEBHOOK_SIGNATURE_HEADER, format!("sha256={signature}"))
+            .body(body.clone())
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => {
+                debug!(%url, "Delivered webhook event");
+                return;
+            }
+            Ok(response) => format!("Webhook responded with status {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+
+        if attempts >= WEBHOOK_MAX_ATTEMPTS {
+            error!(%url, attempts, "Giving up on webhook delivery: {}", error);
+            write_dead_letter(&config.dead_letter_path, &url, attempts, error, &body).await;
+            return;
+        }
+
+        warn!(%url, attempts, "Webhook delivery failed, retrying in {:?}: {}", delay, error);
+        fedimint_api::task::sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+async fn write_dead_letter(path: &Path, url: &Url, attempts: u32, error: String, body: &[u8]) {
+    let dead_letter = DeadLetter {
+        url,
+        attempts,
+        error,
+        payload: serde_json::from_slice(body).expect("We serialized the payload ourselves"),
+    };
+    let mut line = serde_json::to_vec(&dead_letter).expect("Serialization can't fail");
+    line.push(b'\n');
+
+    let res = async {
+        tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?
+            .write_all(&line)
+            .await
+    }
+    .await;
+    if let Err(e) = res {
+        error!(?path, "Failed to write webhook dead letter: {}", e);
+    }
+}
+
+fn random_event_id() -> String {
+    let mut id = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut id);
+    id.to_hex()
+}
+
+/// Hex encoded HMAC-SHA256 of `body` under `secret`
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret);
+    engine.input(body);
+    hmac::Hmac::<sha256::Hash>::from_engine(engine).to_hex()
+}
@@ -28,9 +28,14 @@ use fedimint_api::config::ModuleGenRegistry;
 use fedimint_api::{config::FederationId, module::registry::ModuleDecoderRegistry};
 use fedimint_api::{task::TaskGroup, Amount, TransactionId};
 use fedimint_server::api::WsClientConnectInfo;
+use mint_client::ln::proof::ContractProof;
 use mint_client::modules::ln::contracts::Preimage;
 use mint_client::modules::ln::route_hints::RouteHint;
-use mint_client::{ln::PayInvoicePayload, mint::MintClientError, ClientError, GatewayClient};
+use mint_client::{
+    ln::{GatewayPayment, PayInvoicePayload},
+    mint::MintClientError,
+    ClientError, GatewayClient,
+};
 use rpc::{BackupPayload, RestorePayload};
 use thiserror::Error;
 use tokio::sync::{mpsc, Mutex};
@@ -42,9 +47,9 @@ use crate::{
     config::GatewayConfig,
     ln::{LightningError, LnRpc},
     rpc::{
-        rpc_server::run_webserver, BalancePayload, ConnectFedPayload, DepositAddressPayload,
-        DepositPayload, GatewayInfo, GatewayRequest, GatewayRpcSender, InfoPayload,
-        ReceivePaymentPayload, WithdrawPayload,
+        rpc_server::run_webserver, BalancePayload, ConnectFedPayload, ContractProofPayload,
+        DepositAddressPayload, DepositPayload, DisconnectFedPayload, GatewayInfo, GatewayRequest,
+        GatewayRpcSender, InfoPayload, ListPaymentsPayload, ReceivePaymentPayload, WithdrawPayload,
     },
 };
 
@@ -176,13 +181,16 @@ impl LnGateway {
         route_hints: Vec<RouteHint>,
     ) -> Result<Arc<GatewayActor>> {
         let actor = Arc::new(
-            GatewayActor::new(client.clone(), route_hints)
-                .await
-                .expect("Failed to create actor"),
+            GatewayActor::new(
+                client.clone(),
+                self.ln_rpc.clone(),
+                route_hints,
+                self.task_group.clone(),
+            )
+            .await
+            .expect("Failed to create actor"),
         );
 
-        // TODO: Subscribe for HTLC intercept on behalf of this federation
-
         self.actors.lock().await.insert(
             client.config().client_config.federation_id.to_string(),
             actor.clone(),
@@ -211,11 +219,25 @@ impl LnGateway {
         // overflows
         let channel_id = self.channel_id_generator.fetch_add(1, Ordering::SeqCst);
 
-        let gw_client_cfg = self
+        let mut gw_client_cfg = self
             .client_builder
             .create_config(connect, channel_id, node_pub_key, self.module_gens.clone())
             .await
             .expect("Failed to create gateway client config");
+        let federation_id = gw_client_cfg.client_config.federation_id.to_string();
+        if self.actors.lock().await.contains_key(&federation_id) {
+            return Err(LnGatewayError::Other(anyhow::anyhow!(
+                "Federation {federation_id} is already connected"
+            )));
+        }
+        gw_client_cfg.fees = payload.fees;
+        gw_client_cfg.limits = payload.limits;
+        if payload.rebalance.is_some() {
+            warn!("Automatic rebalancing is only supported by gatewayd, ignoring it");
+        }
+        if payload.watchtower {
+            warn!("Watchtowers are only supported by gatewayd, ignoring it");
+        }
 
         let client = Arc::new(
             self.client_builder
@@ -242,14 +264,40 @@ impl LnGateway {
         Ok(())
     }
 
+    /// Stops serving a federation at runtime. The federation's client database
+    /// is kept, so its e-cash is available again once the federation gets
+    /// connected anew.
+    async fn handle_disconnect_federation(&self, payload: DisconnectFedPayload) -> Result<()> {
+        let DisconnectFedPayload { federation_id } = payload;
+
+        let actor = self
+            .actors
+            .lock()
+            .await
+            .remove(&federation_id.to_string())
+            .ok_or(LnGatewayError::UnknownFederation)?;
+        actor.shutdown().await;
+
+        if let Err(e) = self.client_builder.remove_config(federation_id.clone()) {
+            warn!("Failed to remove federation client configuration: {}", e);
+        }
+        info!(%federation_id, "Disconnected federation");
+
+        Ok(())
+    }
+
     async fn handle_get_info(&self, _payload: InfoPayload) -> Result<GatewayInfo> {
-        let federations = self
+        let actors = self
             .actors
             .lock()
             .await
-            .iter()
-            .map(|(_, actor)| actor.get_info().expect("Failed to get actor info"))
-            .collect();
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut federations = vec![];
+        for actor in actors {
+            federations.push(actor.get_info().await?);
+        }
 
         Ok(GatewayInfo {
             federations,
@@ -266,17 +314,24 @@ impl LnGateway {
         let payment_hash = htlc_accepted.htlc.payment_hash;
         debug!("Incoming htlc for payment hash {}", payment_hash);
 
-        // FIXME: Issue 664: We should avoid having a special reference to a federation
-        // all requests, including `ReceivePaymentPayload`, should contain the
-        // federation id
-        //
-        // We use a random federation as the default (works because we only have one
-        // federation registered)
+        // The route hint of the invoice tells us which federation the payment is for
         //
         // TODO: Use subscribe intercept htlc streams to avoid actor selection with
         // every intercepted htlc!
-        let lock = &self.actors.lock().await;
-        let gateway_actor = lock.values().collect::<Vec<_>>()[0];
+        let short_channel_id = htlc_accepted
+            .onion
+            .short_channel_id
+            .as_deref()
+            .and_then(cln::parse_scid)
+            .ok_or(LnGatewayError::UnknownFederation)?;
+        let gateway_actor = self
+            .actors
+            .lock()
+            .await
+            .values()
+            .find(|actor| actor.mint_channel_id() == short_channel_id)
+            .cloned()
+            .ok_or(LnGatewayError::UnknownFederation)?;
         gateway_actor
             .pay_invoice_buy_preimage_finalize(actor::BuyPreimage::Internal(
                 gateway_actor
@@ -286,7 +341,7 @@ impl LnGateway {
             .await
     }
 
-    async fn handle_pay_invoice_msg(&self, payload: PayInvoicePayload) -> Result<()> {
+    async fn handle_pay_invoice_msg(&self, payload: PayInvoicePayload) -> Result<Preimage> {
         let PayInvoicePayload {
             federation_id,
             contract_id,
@@ -297,7 +352,28 @@ impl LnGateway {
         actor
             .await_outgoing_contract_claimed(contract_id, outpoint)
             .await?;
-        Ok(())
+        actor.outgoing_contract_preimage(contract_id).await
+    }
+
+    async fn handle_contract_proof_msg(
+        &self,
+        payload: ContractProofPayload,
+    ) -> Result<ContractProof> {
+        self.select_actor(payload.federation_id)
+            .await?
+            .contract_proof(payload.contract_id)
+            .await
+    }
+
+    async fn handle_list_payments_msg(
+        &self,
+        payload: ListPaymentsPayload,
+    ) -> Result<Vec<GatewayPayment>> {
+        Ok(self
+            .select_actor(payload.federation_id)
+            .await?
+            .list_payments(payload.limit)
+            .await)
     }
 
     async fn handle_balance_msg(&self, payload: BalancePayload) -> Result<Amount> {
@@ -400,6 +476,11 @@ impl LnGateway {
                             })
                             .await;
                     }
+                    GatewayRequest::DisconnectFederation(inner) => {
+                        inner
+                            .handle(|payload| self.handle_disconnect_federation(payload))
+                            .await;
+                    }
                     GatewayRequest::ReceivePayment(inner) => {
                         inner
                             .handle(|payload| self.handle_receive_payment(payload))
@@ -440,6 +521,25 @@ impl LnGateway {
                             .handle(|payload| self.handle_restore_msg(payload))
                             .await;
                     }
+                    GatewayRequest::FetchOfferInvoice(inner) => {
+                        inner.handle(|_| offers_unsupported()).await;
+                    }
+                    GatewayRequest::CreateOffer(inner) => {
+                        inner.handle(|_| offers_unsupported()).await;
+                    }
+                    GatewayRequest::ContractProof(inner) => {
+                        inner
+                            .handle(|payload| self.handle_contract_proof_msg(payload))
+                            .await;
+                    }
+                    GatewayRequest::ListPayments(inner) => {
+                        inner
+                            .handle(|payload| self.handle_list_payments_msg(payload))
+                            .await;
+                    }
+                    GatewayRequest::RegisterRefund(inner) => {
+                        inner.handle(|_| watchtower_unsupported()).await;
+                    }
                 }
             }
 
@@ -455,6 +555,18 @@ impl Drop for LnGateway {
     }
 }
 
+async fn offers_unsupported<T>() -> Result<T> {
+    Err(LnGatewayError::Other(anyhow::anyhow!(
+        "BOLT12 offers are only supported by gatewayd"
+    )))
+}
+
+async fn watchtower_unsupported<T>() -> Result<T> {
+    Err(LnGatewayError::Other(anyhow::anyhow!(
+        "Watchtowers are only supported by gatewayd"
+    )))
+}
+
 #[derive(Debug, Error)]
 pub enum LnGatewayError {
     #[error("Federation client operation error: {0:?}")]
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use mint_client::modules::ln::contracts::Preimage;
 use mint_client::modules::ln::route_hints::RouteHint;
+use mint_client::modules::ln::GatewayLiquidity;
 use secp256k1::PublicKey;
 
 #[async_trait]
@@ -18,6 +19,9 @@ pub trait LnRpc: Send + Sync + 'static {
 
     /// List peer channels that should be used as route hints in invoices
     async fn route_hints(&self) -> Result<Vec<RouteHint>, anyhow::Error>;
+
+    /// Get the liquidity available in the active channels of the node
+    async fn liquidity(&self) -> Result<GatewayLiquidity, anyhow::Error>;
 }
 
 #[derive(Debug)]
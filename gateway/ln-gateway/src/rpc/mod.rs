@@ -10,8 +10,17 @@ use bitcoin_hashes::hex::{FromHex, ToHex};
 use fedimint_api::config::FederationId;
 use fedimint_api::{Amount, TransactionId};
 use futures::Future;
-use mint_client::ln::PayInvoicePayload;
-use mint_client::{modules::ln::contracts::Preimage, modules::wallet::txoproof::TxOutProof};
+use mint_client::ln::proof::ContractProof;
+use mint_client::ln::watchtower::RegisterRefundPayload;
+use mint_client::ln::{
+    CreateOfferPayload, CreateOfferResponse, FetchOfferInvoicePayload, FetchOfferInvoiceResponse,
+    GatewayPayment, PayInvoicePayload,
+};
+use mint_client::modules::ln::contracts::ContractId;
+use mint_client::modules::ln::{GatewayFees, GatewayLimits};
+use mint_client::{
+    modules::ln::contracts::Preimage, modules::wallet::txoproof::TxOutProof, GatewayRebalanceBand,
+};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tokio::sync::{mpsc, oneshot};
 use tracing::error;
@@ -59,6 +68,27 @@ impl GatewayRpcSender {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ConnectFedPayload {
     pub connect: String,
+    /// Fees the gateway charges for routing payments of this federation
+    #[serde(default)]
+    pub fees: GatewayFees,
+    /// Payment sizes the gateway is willing to route for this federation
+    #[serde(default)]
+    pub limits: GatewayLimits,
+    /// E-cash balance the gateway keeps for this federation by pegging in and
+    /// out automatically
+    #[serde(default)]
+    pub rebalance: Option<GatewayRebalanceBand>,
+    /// Whether the gateway submits refunds of timed out outgoing contracts on
+    /// behalf of offline users of this federation
+    #[serde(default)]
+    pub watchtower: bool,
+}
+
+/// Stops serving a federation. Its e-cash stays in the gateway's database for
+/// when the federation gets connected again.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DisconnectFedPayload {
+    pub federation_id: FederationId,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -102,6 +132,25 @@ pub struct DepositPayload {
     pub transaction: Transaction,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContractProofPayload {
+    pub federation_id: FederationId,
+    pub contract_id: ContractId,
+}
+
+/// Lists the payments the gateway finished for a federation, newest first
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ListPaymentsPayload {
+    pub federation_id: FederationId,
+    /// Maximum number of payments to return
+    #[serde(default = "default_payments_limit")]
+    pub limit: usize,
+}
+
+fn default_payments_limit() -> usize {
+    50
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WithdrawPayload {
     pub federation_id: FederationId,
@@ -114,6 +163,9 @@ pub struct WithdrawPayload {
 pub struct FederationInfo {
     pub federation_id: FederationId,
     pub mint_pubkey: XOnlyPublicKey,
+    /// Short channel id in the route hints of invoices for this federation
+    pub mint_channel_id: u64,
+    pub balance_msat: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -126,6 +178,7 @@ pub struct GatewayInfo {
 pub enum GatewayRequest {
     Info(GatewayRequestInner<InfoPayload>),
     ConnectFederation(GatewayRequestInner<ConnectFedPayload>),
+    DisconnectFederation(GatewayRequestInner<DisconnectFedPayload>),
     ReceivePayment(GatewayRequestInner<ReceivePaymentPayload>),
     PayInvoice(GatewayRequestInner<PayInvoicePayload>),
     Balance(GatewayRequestInner<BalancePayload>),
@@ -134,6 +187,11 @@ pub enum GatewayRequest {
     Withdraw(GatewayRequestInner<WithdrawPayload>),
     Backup(GatewayRequestInner<BackupPayload>),
     Restore(GatewayRequestInner<RestorePayload>),
+    FetchOfferInvoice(GatewayRequestInner<FetchOfferInvoicePayload>),
+    CreateOffer(GatewayRequestInner<CreateOfferPayload>),
+    ContractProof(GatewayRequestInner<ContractProofPayload>),
+    ListPayments(GatewayRequestInner<ListPaymentsPayload>),
+    RegisterRefund(GatewayRequestInner<RegisterRefundPayload>),
 }
 
 #[derive(Debug)]
@@ -164,12 +222,17 @@ macro_rules! impl_gateway_request_trait {
 
 impl_gateway_request_trait!(InfoPayload, GatewayInfo, GatewayRequest::Info);
 impl_gateway_request_trait!(ConnectFedPayload, (), GatewayRequest::ConnectFederation);
+impl_gateway_request_trait!(
+    DisconnectFedPayload,
+    (),
+    GatewayRequest::DisconnectFederation
+);
 impl_gateway_request_trait!(
     ReceivePaymentPayload,
     Preimage,
     GatewayRequest::ReceivePayment
 );
-impl_gateway_request_trait!(PayInvoicePayload, (), GatewayRequest::PayInvoice);
+impl_gateway_request_trait!(PayInvoicePayload, Preimage, GatewayRequest::PayInvoice);
 impl_gateway_request_trait!(BalancePayload, Amount, GatewayRequest::Balance);
 impl_gateway_request_trait!(
     DepositAddressPayload,
@@ -180,6 +243,27 @@ impl_gateway_request_trait!(DepositPayload, TransactionId, GatewayRequest::Depos
 impl_gateway_request_trait!(WithdrawPayload, TransactionId, GatewayRequest::Withdraw);
 impl_gateway_request_trait!(BackupPayload, (), GatewayRequest::Backup);
 impl_gateway_request_trait!(RestorePayload, (), GatewayRequest::Restore);
+impl_gateway_request_trait!(
+    FetchOfferInvoicePayload,
+    FetchOfferInvoiceResponse,
+    GatewayRequest::FetchOfferInvoice
+);
+impl_gateway_request_trait!(
+    CreateOfferPayload,
+    CreateOfferResponse,
+    GatewayRequest::CreateOffer
+);
+impl_gateway_request_trait!(
+    ContractProofPayload,
+    ContractProof,
+    GatewayRequest::ContractProof
+);
+impl_gateway_request_trait!(
+    ListPaymentsPayload,
+    Vec<GatewayPayment>,
+    GatewayRequest::ListPayments
+);
+impl_gateway_request_trait!(RegisterRefundPayload, (), GatewayRequest::RegisterRefund);
 
 impl<T> GatewayRequestInner<T>
 where
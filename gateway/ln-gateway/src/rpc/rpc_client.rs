@@ -5,8 +5,8 @@ use serde::Serialize;
 use url::Url;
 
 use super::{
-    BackupPayload, BalancePayload, ConnectFedPayload, DepositAddressPayload, DepositPayload,
-    RestorePayload, WithdrawPayload,
+    BackupPayload, BalancePayload, ConnectFedPayload, ContractProofPayload, DepositAddressPayload,
+    DepositPayload, DisconnectFedPayload, ListPaymentsPayload, RestorePayload, WithdrawPayload,
 };
 
 pub struct RpcClient {
@@ -65,6 +65,15 @@ impl RpcClient {
         self.call(url, password, payload).await
     }
 
+    pub async fn list_payments(
+        &self,
+        password: String,
+        payload: ListPaymentsPayload,
+    ) -> Result<Response, Error> {
+        let url = self.base_url.join("/payments").expect("invalid base url");
+        self.call(url, password, payload).await
+    }
+
     pub async fn connect_federation(
         &self,
         password: String,
@@ -74,6 +83,15 @@ impl RpcClient {
         self.call(url, password, payload).await
     }
 
+    pub async fn disconnect_federation(
+        &self,
+        password: String,
+        payload: DisconnectFedPayload,
+    ) -> Result<Response, Error> {
+        let url = self.base_url.join("/disconnect").expect("invalid base url");
+        self.call(url, password, payload).await
+    }
+
     pub async fn backup(
         &self,
         password: String,
@@ -92,6 +110,18 @@ impl RpcClient {
         self.call(url, password, payload).await
     }
 
+    pub async fn contract_proof(
+        &self,
+        password: String,
+        payload: ContractProofPayload,
+    ) -> Result<Response, Error> {
+        let url = self
+            .base_url
+            .join("/contract_proof")
+            .expect("invalid base url");
+        self.call(url, password, payload).await
+    }
+
     async fn call<P>(
         &self,
         url: Url,
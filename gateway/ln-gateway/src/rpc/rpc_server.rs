@@ -2,14 +2,16 @@ use std::net::SocketAddr;
 
 use axum::{response::IntoResponse, routing::post, Extension, Json, Router};
 use axum_macros::debug_handler;
-use mint_client::ln::PayInvoicePayload;
+use mint_client::ln::watchtower::RegisterRefundPayload;
+use mint_client::ln::{CreateOfferPayload, FetchOfferInvoicePayload, PayInvoicePayload};
 use serde_json::json;
 use tower_http::{auth::RequireAuthorizationLayer, cors::CorsLayer};
 use tracing::instrument;
 
 use super::{
-    BackupPayload, BalancePayload, ConnectFedPayload, DepositAddressPayload, DepositPayload,
-    GatewayRpcSender, InfoPayload, RestorePayload, WithdrawPayload,
+    BackupPayload, BalancePayload, ConnectFedPayload, ContractProofPayload, DepositAddressPayload,
+    DepositPayload, DisconnectFedPayload, GatewayRpcSender, InfoPayload, ListPaymentsPayload,
+    RestorePayload, WithdrawPayload,
 };
 use crate::LnGatewayError;
 
@@ -19,7 +21,11 @@ pub async fn run_webserver(
     sender: GatewayRpcSender,
 ) -> axum::response::Result<()> {
     // Public routes on gateway webserver
-    let routes = Router::new().route("/pay_invoice", post(pay_invoice));
+    let routes = Router::new()
+        .route("/pay_invoice", post(pay_invoice))
+        .route("/fetch_invoice", post(fetch_invoice))
+        .route("/create_offer", post(create_offer))
+        .route("/register_refund", post(register_refund));
 
     // Authenticated, public routes used for gateway administration
     let admin_routes = Router::new()
@@ -28,9 +34,12 @@ pub async fn run_webserver(
         .route("/address", post(address))
         .route("/deposit", post(deposit))
         .route("/withdraw", post(withdraw))
+        .route("/payments", post(payments))
         .route("/connect", post(connect))
+        .route("/disconnect", post(disconnect))
         .route("/backup", post(backup))
         .route("/restore", post(restore))
+        .route("/contract_proof", post(contract_proof))
         .layer(RequireAuthorizationLayer::bearer(&authkey));
 
     let app = Router::new()
@@ -102,10 +111,52 @@ async fn withdraw(
     Ok(Json(json!({ "fedimint_txid": txid.to_string() })))
 }
 
+/// List recent payments of a gateway federation
+#[debug_handler]
+#[instrument(skip_all, err)]
+async fn payments(
+    Extension(rpc): Extension<GatewayRpcSender>,
+    Json(payload): Json<ListPaymentsPayload>,
+) -> Result<impl IntoResponse, LnGatewayError> {
+    let payments = rpc.send(payload).await?;
+    Ok(Json(json!(payments)))
+}
+
 #[instrument(skip_all, err)]
 async fn pay_invoice(
     Extension(rpc): Extension<GatewayRpcSender>,
     Json(payload): Json<PayInvoicePayload>,
+) -> Result<impl IntoResponse, LnGatewayError> {
+    let preimage = rpc.send(payload).await?;
+    Ok(Json(json!(preimage)))
+}
+
+/// Fetch an invoice for a BOLT12 offer from the offer's node
+#[instrument(skip_all, err)]
+async fn fetch_invoice(
+    Extension(rpc): Extension<GatewayRpcSender>,
+    Json(payload): Json<FetchOfferInvoicePayload>,
+) -> Result<impl IntoResponse, LnGatewayError> {
+    let response = rpc.send(payload).await?;
+    Ok(Json(json!(response)))
+}
+
+/// Create a reusable BOLT12 offer forwarding payments to a federation user
+#[instrument(skip_all, err)]
+async fn create_offer(
+    Extension(rpc): Extension<GatewayRpcSender>,
+    Json(payload): Json<CreateOfferPayload>,
+) -> Result<impl IntoResponse, LnGatewayError> {
+    let response = rpc.send(payload).await?;
+    Ok(Json(json!(response)))
+}
+
+/// Watch the outgoing contract of a federation user, submitting its pre-signed
+/// refund once the contract timed out
+#[instrument(skip_all, err)]
+async fn register_refund(
+    Extension(rpc): Extension<GatewayRpcSender>,
+    Json(payload): Json<RegisterRefundPayload>,
 ) -> Result<impl IntoResponse, LnGatewayError> {
     rpc.send(payload).await?;
     Ok(())
@@ -121,6 +172,16 @@ async fn connect(
     Ok(())
 }
 
+/// Disconnect a federation
+#[instrument(skip_all, err)]
+async fn disconnect(
+    Extension(rpc): Extension<GatewayRpcSender>,
+    Json(payload): Json<DisconnectFedPayload>,
+) -> Result<impl IntoResponse, LnGatewayError> {
+    rpc.send(payload).await?;
+    Ok(())
+}
+
 /// Backup a gateway actor state
 #[instrument(skip_all, err)]
 async fn backup(
@@ -140,3 +201,13 @@ async fn restore(
     rpc.send(payload).await?;
     Ok(())
 }
+
+/// Prove the settlement of a contract the gateway took part in
+#[instrument(skip_all, err)]
+async fn contract_proof(
+    Extension(rpc): Extension<GatewayRpcSender>,
+    Json(payload): Json<ContractProofPayload>,
+) -> Result<impl IntoResponse, LnGatewayError> {
+    let proof = rpc.send(payload).await?;
+    Ok(Json(json!(proof)))
+}
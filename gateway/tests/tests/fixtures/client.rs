@@ -5,7 +5,7 @@ use async_trait::async_trait;
 use bitcoin::{secp256k1, KeyPair};
 use fedimint_api::config::ModuleGenRegistry;
 use fedimint_api::{
-    config::{ClientConfig, FederationId},
+    config::{ClientConfig, FederationId, DEFAULT_MAX_TRANSACTION_SIZE},
     core::LEGACY_HARDCODED_INSTANCE_ID_LN,
     module::registry::ModuleDecoderRegistry,
     PeerId,
@@ -15,6 +15,7 @@ use ln_gateway::{
     client::{DynDbFactory, IGatewayClientBuilder},
     LnGatewayError,
 };
+use mint_client::modules::ln::{GatewayFees, GatewayLimits};
 use mint_client::{module_decode_stubs, Client, GatewayClient, GatewayClientConfig};
 use secp256k1::{PublicKey, Secp256k1};
 use url::Url;
@@ -86,6 +87,7 @@ impl IGatewayClientBuilder for TestGatewayClientBuilder {
             federation_id: FederationId(auth_pk),
             epoch_pk: threshold_crypto::SecretKey::random().public_key(),
             nodes: [].into(),
+            max_transaction_size: Some(DEFAULT_MAX_TRANSACTION_SIZE),
             modules: [].into(),
         };
 
@@ -100,6 +102,10 @@ impl IGatewayClientBuilder for TestGatewayClientBuilder {
             timelock_delta: 10,
             node_pub_key: node_pubkey,
             api: self.gateway_api.clone(),
+            fees: GatewayFees::default(),
+            limits: GatewayLimits::default(),
+            rebalance: None,
+            watchtower: false,
         })
     }
 
@@ -112,4 +118,9 @@ impl IGatewayClientBuilder for TestGatewayClientBuilder {
         // noop: return empty config list
         Ok([].into())
     }
+
+    fn remove_config(&self, _federation_id: FederationId) -> Result<(), LnGatewayError> {
+        // noop: configs are never saved
+        Ok(())
+    }
 }
@@ -10,7 +10,7 @@ use fedimint_testing::{
 };
 use ln_gateway::{
     client::{DynGatewayClientBuilder, MemDbFactory},
-    gatewayd::{gateway::Gateway, lnrpc_client::DynLnRpcClient},
+    gatewayd::{gateway::Gateway, lnrpc_client::DynLnRpcClient, webhook::WebhookNotifier},
 };
 use mint_client::{module_decode_stubs, modules::wallet::WalletGen};
 use url::Url;
@@ -48,6 +48,7 @@ pub async fn fixtures(api_addr: Url) -> Result<Fixtures> {
         decoders,
         module_gens,
         task_group.clone(),
+        WebhookNotifier::disabled(),
     )
     .await;
     let bitcoin = Box::new(FakeBitcoinTest::new());
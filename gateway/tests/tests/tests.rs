@@ -27,12 +27,14 @@ use fedimint_core::api::WsClientConnectInfo;
 use fixtures::{fixtures, Fixtures};
 use ln_gateway::rpc::rpc_client::{Error, Response};
 use ln_gateway::{
+    gatewayd::webhook,
     rpc::{
         rpc_client::RpcClient, BalancePayload, ConnectFedPayload, DepositAddressPayload,
-        DepositPayload, WithdrawPayload,
+        DepositPayload, DisconnectFedPayload, WithdrawPayload,
     },
     utils::retry,
 };
+use mint_client::modules::ln::{GatewayFees, GatewayLimits};
 use tracing_subscriber::EnvFilter;
 use url::Url;
 
@@ -75,6 +77,10 @@ async fn test_gateway_authentication() -> Result<()> {
             urls: vec![],
             id: FederationId::dummy(),
         })?,
+        fees: GatewayFees::default(),
+        limits: GatewayLimits::default(),
+        rebalance: None,
+        watchtower: false,
     };
     test_auth(&gw_password, move |pw| {
         client_ref.connect_federation(pw, payload.clone())
@@ -137,6 +143,17 @@ async fn test_gateway_authentication() -> Result<()> {
     };
     test_auth(&gw_password, |pw| client_ref.withdraw(pw, payload.clone())).await?;
 
+    // Test gateway authentication on `disconnect_federation` function
+    // * `disconnect_federation` with correct password succeeds
+    // * `disconnect_federation` with incorrect password fails
+    let payload = DisconnectFedPayload {
+        federation_id: FederationId::dummy(),
+    };
+    test_auth(&gw_password, |pw| {
+        client_ref.disconnect_federation(pw, payload.clone())
+    })
+    .await?;
+
     task_group.shutdown_join_all(None).await
 }
 
@@ -179,3 +196,12 @@ where
 
     Ok(())
 }
+
+#[test]
+fn test_webhook_signature() {
+    // HMAC-SHA256 test case 2 of RFC 4231
+    assert_eq!(
+        webhook::sign(b"Jefe", b"what do ya want for nothing?"),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
@@ -0,0 +1,356 @@
+//! Consensus encodings of the types guardians and clients exchange, compared
+//! to the fixtures in `tests/encoding_corpus`. A failure means the wire format
+//! changed: either revert the change or bump the consensus version of the
+//! core or the module it belongs to and regenerate the fixtures with
+//! `FM_UPDATE_ENCODING_CORPUS=1`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::hashes::sha256;
+use bitcoin::secp256k1::{ecdsa, schnorr, KeyPair, Message, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::{Address, Network, Txid};
+use fedimint_api::config::{ApiEndpoint, ClientConfig, FederationId, DEFAULT_MAX_TRANSACTION_SIZE};
+use fedimint_api::core::{
+    DynInput, DynModuleConsensusItem, DynOutput, LEGACY_HARDCODED_INSTANCE_ID_LN,
+    LEGACY_HARDCODED_INSTANCE_ID_MINT, LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use fedimint_api::module::{ModuleConsensusVersion, ModuleGen};
+use fedimint_api::{Amount, BitcoinHash, Feerate, OutPoint, PeerId, Tiered, TieredMulti};
+use fedimint_core::epoch::{
+    ConsensusItem, EpochOutcome, ModuleParamsVote, ModuleVersionVote, SerdeSignature,
+    SerdeSignatureShare, SignedEpochOutcome, UpgradeSignal,
+};
+use fedimint_core::transaction::Transaction;
+use fedimint_ln::common::LightningDecoder;
+use fedimint_ln::config::LightningClientConfig;
+use fedimint_ln::contracts::account::AccountContract;
+use fedimint_ln::contracts::{Contract, ContractId, Preimage};
+use fedimint_ln::{ContractOutput, LightningGen, LightningInput, LightningOutput};
+use fedimint_mint::common::MintDecoder;
+use fedimint_mint::config::MintClientConfig;
+use fedimint_mint::{
+    BlindNonce, MintConsensusItem, MintGen, MintInput, MintOutput, MintOutputSignatureShare, Nonce,
+    Note,
+};
+use fedimint_server::consensus::CORE_CONSENSUS_VERSION;
+use fedimint_testing::encoding::EncodingCorpus;
+use fedimint_wallet::common::WalletDecoder;
+use fedimint_wallet::{
+    PegOut, PegOutFees, PegOutSignatureItem, RoundConsensusItem, WalletConsensusItem, WalletGen,
+    WalletOutput,
+};
+use tbs::Scalar;
+use url::Url;
+
+const CORPUS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/encoding_corpus");
+
+#[test]
+fn consensus_encodings_are_stable() {
+    let decoders = ModuleDecoderRegistry::from_iter([
+        (LEGACY_HARDCODED_INSTANCE_ID_LN, LightningDecoder.into()),
+        (LEGACY_HARDCODED_INSTANCE_ID_MINT, MintDecoder.into()),
+        (LEGACY_HARDCODED_INSTANCE_ID_WALLET, WalletDecoder.into()),
+    ]);
+    let core = format!("core-{}", CORE_CONSENSUS_VERSION.0);
+    let mint = module_version(&MintGen);
+    let ln = module_version(&LightningGen);
+    let wallet = module_version(&WalletGen);
+    // Core items embed module items, so they depend on all versions
+    let all = [core.as_str(), &mint, &ln, &wallet].join(" ");
+    let mut corpus = EncodingCorpus::new(CORPUS_DIR);
+
+    corpus.check_roundtrip("mint-input", &mint, &mint_input(), &decoders);
+    corpus.check_roundtrip("mint-output", &mint, &mint_output(), &decoders);
+    corpus.check_roundtrip("mint-consensus-item", &mint, &mint_item(), &decoders);
+    corpus.check_encoding("mint-client-config", &mint, &mint_config());
+
+    corpus.check_roundtrip("ln-input", &ln, &ln_input(), &decoders);
+    corpus.check_roundtrip("ln-output", &ln, &ln_output(), &decoders);
+    corpus.check_encoding("ln-client-config", &ln, &ln_config());
+
+    corpus.check_roundtrip("wallet-output", &wallet, &wallet_output(), &decoders);
+    for (name, item) in wallet_items() {
+        corpus.check_roundtrip(&name, &wallet, &item, &decoders);
+    }
+
+    corpus.check_roundtrip("transaction", &all, &transaction(), &decoders);
+    for (name, item) in consensus_items() {
+        corpus.check_roundtrip(&name, &all, &item, &decoders);
+    }
+    corpus.check_roundtrip("signed-epoch-outcome", &all, &epoch_outcome(), &decoders);
+    corpus.check_encoding("client-config", &core, &client_config());
+
+    corpus.finish();
+}
+
+/// Newest consensus version the module supports
+fn module_version<G: ModuleGen>(gen: &G) -> String {
+    let version = gen
+        .versions(CORE_CONSENSUS_VERSION)
+        .iter()
+        .max()
+        .expect("Modules support at least one version");
+    format!("{}-{}", G::KIND, version.0)
+}
+
+fn secret_key(n: u8) -> SecretKey {
+    SecretKey::from_slice(&[n; 32]).expect("Valid secret key")
+}
+
+fn key_pair(n: u8) -> KeyPair {
+    KeyPair::from_secret_key(&Secp256k1::new(), &secret_key(n))
+}
+
+fn x_only_key(n: u8) -> XOnlyPublicKey {
+    key_pair(n).x_only_public_key().0
+}
+
+fn threshold_key() -> threshold_crypto::SecretKey {
+    threshold_crypto::SecretKey::from_mut(&mut Scalar::from(5u64))
+}
+
+fn threshold_sig_share(msg: &[u8]) -> SerdeSignatureShare {
+    SerdeSignatureShare(
+        threshold_crypto::SecretKeyShare::from_mut(&mut Scalar::from(6u64)).sign(msg),
+    )
+}
+
+fn tbs_key() -> tbs::SecretKeyShare {
+    tbs::SecretKeyShare(Scalar::from(7u64))
+}
+
+fn blinded_message(msg: &[u8]) -> tbs::BlindedMessage {
+    tbs::blind_message(
+        tbs::Message::from_bytes(msg),
+        tbs::BlindingKey(Scalar::from(8u64)),
+    )
+}
+
+fn mint_input() -> MintInput {
+    let msg = tbs::Message::from_bytes(b"note");
+    let signature = tbs::Signature(tbs::sign_blinded_msg(tbs::BlindedMessage(msg.0), tbs_key()).0);
+    MintInput(TieredMulti::from_iter([(
+        Amount::from_sats(1),
+        Note(Nonce(x_only_key(1)), signature),
+    )]))
+}
+
+fn mint_output() -> MintOutput {
+    MintOutput(TieredMulti::from_iter([(
+        Amount::from_sats(1),
+        BlindNonce(blinded_message(b"nonce")),
+    )]))
+}
+
+fn mint_item() -> MintConsensusItem {
+    let msg = blinded_message(b"nonce");
+    MintConsensusItem {
+        out_point: OutPoint {
+            txid: BitcoinHash::hash(b"mint"),
+            out_idx: 1,
+        },
+        signatures: MintOutputSignatureShare(TieredMulti::from_iter([(
+            Amount::from_sats(1),
+            (msg, tbs::sign_blinded_msg(msg, tbs_key())),
+        )])),
+    }
+}
+
+fn mint_config() -> MintClientConfig {
+    let pk = tbs_key().to_pub_key_share();
+    MintClientConfig {
+        tbs_pks: Tiered::from_iter([(Amount::from_sats(1), tbs::AggregatePublicKey(pk.0))]),
+        fee_consensus: fedimint_mint::config::FeeConsensus {
+            note_issuance_abs: Amount::from_msats(1),
+            note_spend_abs: Amount::from_msats(2),
+        },
+        peer_tbs_pks: BTreeMap::from([(
+            PeerId::from(0),
+            Tiered::from_iter([(Amount::from_sats(1), pk)]),
+        )]),
+        max_notes_per_denomination: 3,
+        max_backup_size: 1024,
+        note_expiry: None,
+        rotated_tbs_pks: vec![],
+        rotated_peer_tbs_pks: vec![],
+        retired_tiers: None,
+    }
+}
+
+fn ln_input() -> LightningInput {
+    LightningInput {
+        contract_id: ContractId::hash(b"contract"),
+        amount: Amount::from_sats(2),
+        witness: Some(Preimage([2; 32])),
+    }
+}
+
+fn ln_output() -> LightningOutput {
+    LightningOutput::Contract(ContractOutput {
+        amount: Amount::from_sats(2),
+        contract: Contract::Account(AccountContract { key: x_only_key(2) }),
+    })
+}
+
+fn ln_config() -> LightningClientConfig {
+    LightningClientConfig {
+        threshold_pub_key: threshold_key().public_key(),
+        fee_consensus: fedimint_ln::config::FeeConsensus {
+            contract_input: Amount::from_msats(3),
+            contract_output: Amount::from_msats(4),
+        },
+    }
+}
+
+fn wallet_output() -> WalletOutput {
+    let recipient = bitcoin::PublicKey::new(secret_key(3).public_key(&Secp256k1::new()));
+    WalletOutput(PegOut {
+        recipient: Address::p2wpkh(&recipient, Network::Regtest).expect("Key is compressed"),
+        amount: bitcoin::Amount::from_sat(1000),
+        fees: PegOutFees {
+            fee_rate: Feerate { sats_per_kvb: 2000 },
+            total_weight: 871,
+        },
+    })
+}
+
+fn wallet_items() -> Vec<(String, WalletConsensusItem)> {
+    let msg = Message::from_slice(&[4; 32]).expect("Valid message");
+    let signature: ecdsa::Signature = Secp256k1::new().sign_ecdsa(&msg, &secret_key(4));
+    vec![
+        (
+            "wallet-consensus-item-round".to_string(),
+            WalletConsensusItem::RoundConsensus(RoundConsensusItem {
+                block_height: 100,
+                fee_rate: Feerate { sats_per_kvb: 2000 },
+                randomness: [4; 32],
+            }),
+        ),
+        (
+            "wallet-consensus-item-peg-out-signature".to_string(),
+            WalletConsensusItem::PegOutSignature(PegOutSignatureItem {
+                txid: Txid::hash(b"peg-out"),
+                signature: vec![signature],
+            }),
+        ),
+    ]
+}
+
+fn transaction() -> Transaction {
+    let inputs = vec![
+        DynInput::from_typed(LEGACY_HARDCODED_INSTANCE_ID_MINT, mint_input()),
+        DynInput::from_typed(LEGACY_HARDCODED_INSTANCE_ID_LN, ln_input()),
+    ];
+    let outputs = vec![
+        DynOutput::from_typed(LEGACY_HARDCODED_INSTANCE_ID_MINT, mint_output()),
+        DynOutput::from_typed(LEGACY_HARDCODED_INSTANCE_ID_LN, ln_output()),
+        DynOutput::from_typed(LEGACY_HARDCODED_INSTANCE_ID_WALLET, wallet_output()),
+    ];
+    let msg = Message::from_slice(&Transaction::tx_hash_from_parts(&inputs, &outputs)[..])
+        .expect("Hash has the right length");
+    let signature: schnorr::Signature =
+        Secp256k1::new().sign_schnorr_no_aux_rand(&msg, &key_pair(5));
+    Transaction {
+        inputs,
+        outputs,
+        signature: Some(signature),
+    }
+}
+
+fn consensus_items() -> Vec<(String, ConsensusItem)> {
+    let (_, wallet_item) = wallet_items().remove(0);
+    [
+        (
+            "client-config-signature-share",
+            ConsensusItem::ClientConfigSignatureShare(threshold_sig_share(b"client config")),
+        ),
+        (
+            "epoch-outcome-signature-share",
+            ConsensusItem::EpochOutcomeSignatureShare(threshold_sig_share(b"epoch")),
+        ),
+        ("transaction", ConsensusItem::Transaction(transaction())),
+        (
+            "module-mint",
+            ConsensusItem::Module(DynModuleConsensusItem::from_typed(
+                LEGACY_HARDCODED_INSTANCE_ID_MINT,
+                mint_item(),
+            )),
+        ),
+        (
+            "module-wallet",
+            ConsensusItem::Module(DynModuleConsensusItem::from_typed(
+                LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+                wallet_item,
+            )),
+        ),
+        (
+            "federation-meta-signature-share",
+            ConsensusItem::FederationMetaSignatureShare(threshold_sig_share(b"meta")),
+        ),
+        (
+            "upgrade-signal",
+            ConsensusItem::UpgradeSignal(UpgradeSignal),
+        ),
+        (
+            "module-version-vote",
+            ConsensusItem::ModuleVersionVote(ModuleVersionVote {
+                module_instance_id: LEGACY_HARDCODED_INSTANCE_ID_MINT,
+                version: ModuleConsensusVersion(1),
+            }),
+        ),
+        (
+            "module-params-vote",
+            ConsensusItem::ModuleParamsVote(ModuleParamsVote {
+                module_instance_id: LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+                activation_epoch: 10,
+                change: r#"{"finality_delay":6}"#.to_string(),
+            }),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, item)| (format!("consensus-item-{name}"), item))
+    .collect()
+}
+
+fn epoch_outcome() -> SignedEpochOutcome {
+    let outcome = EpochOutcome {
+        epoch: 1,
+        last_hash: Some(sha256::Hash::hash(b"epoch 0")),
+        items: vec![
+            (
+                PeerId::from(0),
+                consensus_items()
+                    .into_iter()
+                    .map(|(_, item)| item)
+                    .collect(),
+            ),
+            (
+                PeerId::from(1),
+                vec![ConsensusItem::Transaction(transaction())],
+            ),
+        ],
+        rejected_txs: BTreeSet::from([BitcoinHash::hash(b"rejected")]),
+    };
+    let hash = outcome.hash();
+    SignedEpochOutcome {
+        outcome,
+        hash,
+        signature: Some(SerdeSignature(threshold_key().sign(hash))),
+    }
+}
+
+fn client_config() -> ClientConfig {
+    let pk = threshold_key().public_key();
+    ClientConfig {
+        federation_name: "encoding corpus".to_string(),
+        federation_id: FederationId(pk),
+        nodes: vec![ApiEndpoint {
+            url: Url::parse("ws://127.0.0.1:18174").expect("Valid url"),
+            name: "peer-0".to_string(),
+        }],
+        epoch_pk: pk,
+        max_transaction_size: Some(DEFAULT_MAX_TRANSACTION_SIZE),
+        modules: BTreeMap::new(),
+    }
+}
@@ -0,0 +1,41 @@
+//! Tests of `fedimint_testing::federation`, which need a regtest `bitcoind`
+//! and thus only run with `FM_TEST_DISABLE_MOCKS=1`, see the README
+
+use std::env;
+use std::time::Duration;
+
+use anyhow::Result;
+use fedimint_api::bitcoin_rpc::FM_BITCOIND_RPC_ENV;
+use fedimint_api::{sats, PeerId};
+use fedimint_testing::federation::FederationBuilder;
+use rand::rngs::OsRng;
+use url::Url;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spawned_federation_tolerates_a_degraded_peer() -> Result<()> {
+    if env::var("FM_TEST_DISABLE_MOCKS").map_or(true, |mocks| mocks != "1") {
+        return Ok(());
+    }
+    let bitcoind_rpc = Url::parse(&env::var(FM_BITCOIND_RPC_ENV)?)?;
+    let mut fed = FederationBuilder::new(4, bitcoind_rpc)
+        .finality_delay(2)
+        .spawn()
+        .await?;
+
+    let client = fed.new_client().await;
+    fed.fund_client(&client, bitcoin::Amount::from_sat(10_000))
+        .await?;
+    assert_eq!(client.notes().await.total_amount(), sats(10_000));
+
+    fed.degrade_peer(PeerId::from(3)).await?;
+    let notes = client.spend_ecash(sats(5_000), OsRng).await?;
+    let out_point = client.reissue(notes, OsRng).await?;
+    client
+        .await_output_accepted(out_point, Duration::from_secs(60))
+        .await?;
+    client.fetch_notes(out_point).await?;
+    assert_eq!(client.notes().await.total_amount(), sats(10_000));
+
+    fed.restore_peer(PeerId::from(3))?;
+    fed.shutdown().await
+}
@@ -0,0 +1,211 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use fedimint_api::cancellable::{Cancellable, Cancelled};
+use fedimint_api::net::peers::{IPeerConnections, PeerConnections};
+use fedimint_api::PeerId;
+use fedimint_server::EpochMessage;
+use tokio::time::{sleep_until, Instant};
+use tracing::debug;
+
+/// What happens to the messages sent from one peer to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkFault {
+    /// Messages are lost
+    Drop,
+    /// Messages arrive late
+    Delay(Duration),
+    /// Messages arrive twice
+    Duplicate,
+    /// Messages are held back until the network is healed, like the
+    /// reconnecting connections would after an outage
+    Partition,
+}
+
+#[derive(Debug, Default)]
+struct FaultState {
+    links: BTreeMap<(PeerId, PeerId), LinkFault>,
+    /// Messages peers may still send before they crash
+    crash_after: BTreeMap<PeerId, usize>,
+    crashed: BTreeSet<PeerId>,
+}
+
+/// Faults injected into the p2p network of a [`super::FederationTest`]
+#[derive(Debug, Clone, Default)]
+pub struct NetworkFaults(Arc<Mutex<FaultState>>);
+
+impl NetworkFaults {
+    fn lock(&self) -> std::sync::MutexGuard<'_, FaultState> {
+        self.0.lock().expect("lock poisoned")
+    }
+
+    /// Applies `fault` to all messages sent from `from` to `to` from now on
+    pub fn set_link(&self, from: u16, to: u16, fault: LinkFault) {
+        self.lock()
+            .links
+            .insert((PeerId::from(from), PeerId::from(to)), fault);
+    }
+
+    /// Holds back all messages between the two groups of peers until
+    /// [`Self::heal`] is called
+    pub fn partition(&self, group_a: &[u16], group_b: &[u16]) {
+        for a in group_a {
+            for b in group_b {
+                self.set_link(*a, *b, LinkFault::Partition);
+                self.set_link(*b, *a, LinkFault::Partition);
+            }
+        }
+    }
+
+    /// Removes all link faults, delivering the messages held back by
+    /// partitions
+    pub fn heal(&self) {
+        self.lock().links.clear();
+    }
+
+    /// Crashes `peer` after it sent `messages` more messages, so it can crash
+    /// in the middle of an epoch. A crashed peer stops sending and receiving
+    /// until it is restarted with [`super::FederationTest::restart_peer`].
+    pub fn crash_after(&self, peer: u16, messages: usize) {
+        let peer = PeerId::from(peer);
+        let mut state = self.lock();
+        if messages == 0 {
+            state.crashed.insert(peer);
+        } else {
+            state.crash_after.insert(peer, messages);
+        }
+    }
+
+    pub fn is_crashed(&self, peer: PeerId) -> bool {
+        self.lock().crashed.contains(&peer)
+    }
+
+    pub(super) fn recover(&self, peer: PeerId) {
+        let mut state = self.lock();
+        state.crashed.remove(&peer);
+        state.crash_after.remove(&peer);
+    }
+
+    fn link(&self, from: PeerId, to: PeerId) -> Option<LinkFault> {
+        self.lock().links.get(&(from, to)).copied()
+    }
+
+    /// Counts a message sent by `peer`, returns false if it crashed before
+    fn count_sent(&self, peer: PeerId) -> bool {
+        let mut state = self.lock();
+        if state.crashed.contains(&peer) {
+            return false;
+        }
+        if let Some(remaining) = state.crash_after.get_mut(&peer) {
+            *remaining -= 1;
+            if *remaining == 0 {
+                debug!(%peer, "Crashing peer");
+                state.crash_after.remove(&peer);
+                state.crashed.insert(peer);
+            }
+        }
+        true
+    }
+}
+
+/// Decorates the connections of a peer with the [`NetworkFaults`] of the
+/// federation. Link faults are applied when receiving, crashes when sending,
+/// so everything a peer sent before crashing still arrives.
+pub struct FaultyConnections {
+    peer: PeerId,
+    connections: PeerConnections<EpochMessage>,
+    faults: NetworkFaults,
+    /// Messages received but not delivered yet, by the time they are due
+    held: BTreeMap<(Instant, u64), (PeerId, EpochMessage)>,
+    next_seq: u64,
+}
+
+impl FaultyConnections {
+    pub fn new(
+        peer: PeerId,
+        connections: PeerConnections<EpochMessage>,
+        faults: NetworkFaults,
+    ) -> Self {
+        FaultyConnections {
+            peer,
+            connections,
+            faults,
+            held: BTreeMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    fn hold(&mut self, due: Instant, msg: (PeerId, EpochMessage)) {
+        self.held.insert((due, self.next_seq), msg);
+        self.next_seq += 1;
+    }
+
+    /// Removes the next held message that is due and not partitioned anymore
+    fn next_due(&mut self) -> Option<(PeerId, EpochMessage)> {
+        let now = Instant::now();
+        let key = self
+            .held
+            .iter()
+            .find(|((due, _), (from, _))| {
+                *due <= now && self.faults.link(*from, self.peer) != Some(LinkFault::Partition)
+            })
+            .map(|(key, _)| *key)?;
+        self.held.remove(&key)
+    }
+}
+
+#[async_trait]
+impl IPeerConnections<EpochMessage> for FaultyConnections {
+    async fn send(&mut self, peers: &[PeerId], msg: EpochMessage) -> Cancellable<()> {
+        for peer in peers {
+            if !self.faults.count_sent(self.peer) {
+                return Err(Cancelled);
+            }
+            self.connections.send(&[*peer], msg.clone()).await?;
+        }
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Cancellable<(PeerId, EpochMessage)> {
+        loop {
+            if self.faults.is_crashed(self.peer) {
+                return Err(Cancelled);
+            }
+            if let Some(msg) = self.next_due() {
+                return Ok(msg);
+            }
+
+            // Partitioned messages are checked again periodically, so they
+            // are delivered soon after the network was healed
+            let wake_up = self
+                .held
+                .keys()
+                .next()
+                .map_or(Duration::from_millis(100), |(due, _)| {
+                    due.saturating_duration_since(Instant::now())
+                        .min(Duration::from_millis(100))
+                });
+            let (from, msg) = tokio::select! {
+                msg = self.connections.receive() => msg?,
+                () = sleep_until(Instant::now() + wake_up) => continue,
+            };
+
+            match self.faults.link(from, self.peer) {
+                None => return Ok((from, msg)),
+                Some(LinkFault::Drop) => {}
+                Some(LinkFault::Delay(delay)) => self.hold(Instant::now() + delay, (from, msg)),
+                Some(LinkFault::Duplicate) => {
+                    self.hold(Instant::now(), (from, msg.clone()));
+                    return Ok((from, msg));
+                }
+                Some(LinkFault::Partition) => self.hold(Instant::now(), (from, msg)),
+            }
+        }
+    }
+
+    async fn ban_peer(&mut self, peer: PeerId) {
+        self.connections.ban_peer(peer).await;
+    }
+}
@@ -26,8 +26,8 @@ use fedimint_api::core::{
 use fedimint_api::db::mem_impl::MemDatabase;
 use fedimint_api::db::Database;
 use fedimint_api::module::registry::{ModuleDecoderRegistry, ModuleRegistry};
-use fedimint_api::module::DynModuleGen;
-use fedimint_api::net::peers::IMuxPeerConnections;
+use fedimint_api::module::{DynModuleGen, ModuleConsensusVersion};
+use fedimint_api::net::peers::{IMuxPeerConnections, IPeerConnections};
 use fedimint_api::server::DynServerModule;
 use fedimint_api::task::{timeout, TaskGroup};
 use fedimint_api::OutPoint;
@@ -36,9 +36,9 @@ use fedimint_api::TieredMulti;
 use fedimint_api::{sats, Amount};
 use fedimint_bitcoind::DynBitcoindRpc;
 use fedimint_core::api::WsFederationApi;
-use fedimint_ln::{LightningGateway, LightningGen};
+use fedimint_ln::{GatewayFees, GatewayLimits, GatewayLiquidity, LightningGateway, LightningGen};
 use fedimint_mint::db::NonceKeyPrefix;
-use fedimint_mint::{MintGen, MintOutput};
+use fedimint_mint::{MintGen, MintGenParams, MintOutput};
 use fedimint_server::config::ServerConfigParams;
 use fedimint_server::config::{connect, ServerConfig};
 use fedimint_server::consensus::{ConsensusProposal, HbbftConsensusOutcome};
@@ -46,16 +46,17 @@ use fedimint_server::consensus::{FedimintConsensus, TransactionSubmissionError};
 use fedimint_server::multiplexed::PeerConnectionMultiplexer;
 use fedimint_server::net::connect::mock::MockNetwork;
 use fedimint_server::net::connect::{Connector, TlsTcpConnector};
-use fedimint_server::net::peers::PeerConnector;
+use fedimint_server::net::peers::{PeerConnector, ReconnectPeerConnections};
+use fedimint_server::simulation::Simulation;
 use fedimint_server::{consensus, EpochMessage, FedimintServer};
 use fedimint_testing::{
-    btc::{fixtures::FakeBitcoinTest, BitcoinTest},
+    btc::{fixtures::FakeBitcoinTest, real::RealBitcoinTest, BitcoinTest},
     ln::{fixtures::FakeLightningTest, LightningTest},
 };
 use fedimint_wallet::config::WalletConfig;
 use fedimint_wallet::db::UTXOKey;
 use fedimint_wallet::Wallet;
-use fedimint_wallet::{SpendableUTXO, WalletGen};
+use fedimint_wallet::{SpendableUTXO, WalletGen, WalletGenParams};
 use futures::executor::block_on;
 use futures::future::{join_all, select_all};
 use futures::FutureExt;
@@ -76,21 +77,25 @@ use mint_client::transaction::TransactionBuilder;
 use mint_client::{
     mint::SpendableNote, Client, GatewayClient, GatewayClientConfig, UserClient, UserClientConfig,
 };
-use rand::rngs::OsRng;
-use rand::RngCore;
-use real::{RealBitcoinTest, RealLightningTest};
+use rand::rngs::{OsRng, StdRng};
+use rand::{RngCore, SeedableRng};
+use real::RealLightningTest;
 use tokio::sync::Mutex;
 use tracing::info;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
 use url::Url;
 
+use crate::fixtures::faults::FaultyConnections;
 use crate::fixtures::utils::LnRpcAdapter;
 use crate::ConsensusItem;
 
+mod faults;
 mod real;
 mod utils;
 
+pub use faults::{LinkFault, NetworkFaults};
+
 const DEFAULT_P2P_PORT: u16 = 8173;
 const BASE_PORT_INIT: u16 = DEFAULT_P2P_PORT + 20000;
 static BASE_PORT: AtomicU16 = AtomicU16::new(BASE_PORT_INIT);
@@ -174,9 +179,8 @@ pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
 
     let peers = (0..num_peers).map(PeerId::from).collect::<Vec<_>>();
     let modules = fedimintd::configure_modules(
-        sats(100000),
-        bitcoin::network::constants::Network::Regtest,
-        10,
+        MintGenParams::new(sats(100000)),
+        WalletGenParams::new(bitcoin::network::constants::Network::Regtest, 10),
     );
     let params = ServerConfigParams::gen_local(&peers, base_port, "test", modules).unwrap();
     let max_evil = hbbft::util::max_faulty(peers.len());
@@ -215,9 +219,14 @@ pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
                     fedimint_api::bitcoin_rpc::BitcoindRpcBackend::Electrum(_) => {
                         panic!("Electrum backend not supported for tests")
                     }
+                    fedimint_api::bitcoin_rpc::BitcoindRpcBackend::Esplora(_) => {
+                        panic!("Esplora backend not supported for tests")
+                    }
+                    fedimint_api::bitcoin_rpc::BitcoindRpcBackend::CompactFilters(url) => url,
                 };
             let bitcoin_rpc = fedimint_bitcoind::bitcoincore_rpc::make_bitcoind_rpc(
                 &bitcoin_rpc_url,
+                None,
                 task_group.make_handle(),
             )
             .expect("Could not create bitcoinrpc");
@@ -414,6 +423,80 @@ pub async fn create_user_client(
     UserClient::new_with_api(config, decoders, module_gens, db, api, Default::default()).await
 }
 
+/// Creates a federation with FAKE Bitcoin whose consensus runs in a
+/// [`Simulation`], so it behaves the same in every run with the same `seed`
+pub async fn simulation(num_peers: u16, seed: u64, task_group: &mut TaskGroup) -> Simulation {
+    let mut simulation = Simulation::new(seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let peers = (0..num_peers).map(PeerId::from).collect::<Vec<_>>();
+    let base_port = BASE_PORT.fetch_add(num_peers * 10, Ordering::Relaxed);
+    let modules = fedimintd::configure_modules(
+        MintGenParams::new(sats(100000)),
+        WalletGenParams::new(bitcoin::network::constants::Network::Regtest, 10),
+    );
+    let params = ServerConfigParams::gen_local(&peers, base_port, "test", modules).unwrap();
+    let module_inits = ModuleGenRegistry::from(vec![
+        DynModuleGen::from(WalletGen),
+        DynModuleGen::from(MintGen),
+        DynModuleGen::from(LightningGen),
+    ]);
+    let server_config =
+        ServerConfig::trusted_dealer_gen("", &peers, &params, module_inits.clone(), &mut rng);
+    let bitcoin: DynBitcoindRpc = FakeBitcoinTest::new().into();
+    let env_vars = FedimintConsensus::get_env_vars_map();
+
+    for cfg in server_config.into_values() {
+        let decoders = module_inits.decoders(cfg.iter_module_instances()).unwrap();
+        let db = Database::new(MemDatabase::new(), decoders);
+
+        let mut modules = BTreeMap::new();
+        for (kind, gen) in module_inits.legacy_init_order_iter() {
+            let id = cfg.get_module_id_by_kind(kind.clone()).unwrap();
+            let module = if kind.as_str() == "wallet" {
+                Wallet::new_with_bitcoind(
+                    cfg.get_module_config_typed(id).unwrap(),
+                    db.new_isolated(id),
+                    bitcoin.clone(),
+                    task_group,
+                )
+                .await
+                .expect("Couldn't create wallet")
+                .into()
+            } else {
+                gen.init(
+                    cfg.get_module_config(id).unwrap(),
+                    db.new_isolated(id),
+                    &env_vars,
+                    task_group,
+                )
+                .await
+                .unwrap()
+            };
+            modules.insert(id, module);
+        }
+
+        let (consensus, tx_receiver) = FedimintConsensus::new_with_modules(
+            cfg.clone(),
+            db,
+            module_inits.clone(),
+            ModuleRegistry::from(modules),
+        );
+        let decoders = consensus.decoders();
+        let connections = simulation.network().connections(cfg.local.identity);
+        let server = FedimintServer::new_with_connections(
+            cfg,
+            consensus,
+            tx_receiver,
+            connections,
+            decoders,
+        );
+        simulation.add_server(server).await;
+    }
+
+    simulation
+}
+
 async fn distributed_config(
     code_version: &str,
     peers: &[PeerId],
@@ -513,6 +596,9 @@ impl GatewayTest {
             api: Url::parse("http://example.com")
                 .expect("Could not parse URL to generate GatewayClientConfig API endpoint"),
             route_hints: vec![],
+            fees: GatewayFees::default(),
+            limits: GatewayLimits::default(),
+            liquidity: GatewayLiquidity::default(),
             valid_until: SystemTime::now(),
         };
 
@@ -526,6 +612,10 @@ impl GatewayTest {
             timelock_delta: 10,
             api: announce_addr.clone(),
             node_pub_key,
+            fees: GatewayFees::default(),
+            limits: GatewayLimits::default(),
+            rebalance: None,
+            watchtower: false,
         };
 
         // Create federation client builder for the gateway
@@ -674,6 +764,7 @@ pub struct FederationTest {
     servers: Vec<Arc<Mutex<ServerTest>>>,
     last_consensus: Arc<Mutex<HbbftConsensusOutcome>>,
     max_balance_sheet: Arc<AtomicI64>,
+    pub faults: NetworkFaults,
     pub wallet: WalletConfig,
     pub cfg: ServerConfig,
     decoders: ModuleDecoderRegistry,
@@ -775,6 +866,7 @@ impl FederationTest {
             cfg: self.cfg.clone(),
             last_consensus: self.last_consensus.clone(),
             max_balance_sheet: self.max_balance_sheet.clone(),
+            faults: self.faults.clone(),
             decoders: self.decoders.clone(),
             mint_id: self.mint_id,
             ln_id: self.ln_id,
@@ -813,6 +905,83 @@ impl FederationTest {
         self.mint_notes_for_user(user, amount).await;
     }
 
+    /// Signals readiness for an upgrade on all fed members
+    pub async fn signal_upgrade(&self) {
+        for server in &self.servers {
+            server
+                .lock()
+                .await
+                .fedimint
+                .consensus
+                .signal_upgrade()
+                .await;
+        }
+    }
+
+    /// Votes for a module consensus version on all fed members
+    pub async fn vote_module_version(
+        &self,
+        module_instance_id: ModuleInstanceId,
+        version: ModuleConsensusVersion,
+    ) -> anyhow::Result<()> {
+        for server in &self.servers {
+            server
+                .lock()
+                .await
+                .fedimint
+                .consensus
+                .vote_module_version(module_instance_id, version)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Consensus versions the module instances run with, as seen by the first
+    /// fed member
+    pub async fn module_versions(&self) -> BTreeMap<ModuleInstanceId, ModuleConsensusVersion> {
+        self.servers[0]
+            .lock()
+            .await
+            .fedimint
+            .consensus
+            .module_versions()
+            .await
+    }
+
+    /// Returns true if the last epoch processed by all fed members was the one
+    /// after which they halt for an upgrade
+    pub async fn halted_for_upgrade(&self) -> bool {
+        for server in &self.servers {
+            let s = server.lock().await;
+            let halted = s
+                .fedimint
+                .last_processed_epoch
+                .as_ref()
+                .map_or(false, |epoch| s.fedimint.consensus.is_upgrade_epoch(epoch));
+            if !halted {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns true if any fed member still signals readiness for an upgrade
+    pub async fn upgrade_signaled(&self) -> bool {
+        for server in &self.servers {
+            if server
+                .lock()
+                .await
+                .fedimint
+                .consensus
+                .upgrade_signaled()
+                .await
+            {
+                return true;
+            }
+        }
+        false
+    }
+
     /// Inserts notes directly into the databases of federation nodes, runs
     /// consensus to sign them then fetches the notes for the user client.
     pub async fn mint_notes_for_user<C: AsRef<ClientConfig> + Clone + Send>(
@@ -948,6 +1117,7 @@ impl FederationTest {
                         .modules
                         .get_expect(self.mint_id)
                         .apply_output(
+                            &svr.fedimint.consensus.build_interconnect(),
                             &mut dbtx.with_module_prefix(self.mint_id),
                             &core::DynOutput::from_typed(self.mint_id, MintOutput(notes.clone())),
                             out_point,
@@ -1033,9 +1203,10 @@ impl FederationTest {
             let mut task_group = TaskGroup::new();
             for (i, server) in self.servers.iter().enumerate() {
                 let server = server.clone();
+                let faults = self.faults.clone();
                 task_group
                     .spawn(format!("server-{i}-consensu_epoch"), move |_| async {
-                        Self::consensus_epoch(server, Duration::from_millis(0)).await
+                        Self::consensus_epoch(server, faults, Duration::from_millis(0)).await
                     })
                     .await;
             }
@@ -1107,7 +1278,11 @@ impl FederationTest {
                     .iter()
                     .zip(durations)
                     .map(|(server, duration)| {
-                        Box::pin(Self::consensus_epoch(server.clone(), duration))
+                        Box::pin(Self::consensus_epoch(
+                            server.clone(),
+                            self.faults.clone(),
+                            duration,
+                        ))
                     }),
             )
             .await;
@@ -1135,15 +1310,43 @@ impl FederationTest {
         Ok(())
     }
 
+    /// Restarts a peer that crashed because of [`NetworkFaults::crash_after`],
+    /// discarding messages it didn't receive and the state of unfinished
+    /// epochs. Like after [`Self::rejoin_consensus`] it has to catch up with
+    /// the other peers.
+    #[allow(clippy::await_holding_refcell_ref)]
+    pub async fn restart_peer(&self, peer: u16) {
+        let peer = PeerId::from(peer);
+        self.faults.recover(peer);
+        for server in &self.servers {
+            let mut s = server.lock().await;
+            if s.fedimint.cfg.local.identity != peer {
+                continue;
+            }
+            while timeout(Duration::from_millis(500), s.fedimint.connections.receive())
+                .await
+                .is_ok()
+            {
+                // clear message buffers, simulating a restarted connection
+            }
+            s.fedimint.restart_consensus().await;
+        }
+    }
+
     // Necessary to allow servers to progress concurrently, should be fine since the
     // same server will never run an epoch concurrently with itself.
     #[allow(clippy::await_holding_refcell_ref)]
     async fn consensus_epoch(
         server: Arc<Mutex<ServerTest>>,
+        faults: NetworkFaults,
         delay: Duration,
     ) -> anyhow::Result<()> {
         tokio::time::sleep(delay).await;
         let mut server = server.lock().await;
+        let peer = server.fedimint.cfg.local.identity;
+        if faults.is_crashed(peer) {
+            return Ok(());
+        }
         let consensus = server.fedimint.consensus.clone();
 
         let overrider = server.override_proposal.clone();
@@ -1152,10 +1355,16 @@ impl FederationTest {
             .dropped_peers
             .append(&mut consensus.get_consensus_proposal().await.drop_peers);
 
-        server.last_consensus = server
+        server.last_consensus = match server
             .fedimint
             .run_consensus_epoch(proposal, &mut rng())
-            .await?;
+            .await
+        {
+            Ok(outcomes) => outcomes,
+            // the peer crashed in the middle of the epoch
+            Err(_) if faults.is_crashed(peer) => return Ok(()),
+            Err(e) => return Err(e),
+        };
 
         for outcome in server.last_consensus.clone() {
             server
@@ -1223,6 +1432,7 @@ impl FederationTest {
         >,
         task_group: &mut TaskGroup,
     ) -> Self {
+        let faults = NetworkFaults::default();
         let servers = join_all(server_config.values().map(|cfg| async {
             let btc_rpc = bitcoin_gen();
             let decoders = module_inits.decoders(cfg.iter_module_instances()).unwrap();
@@ -1262,15 +1472,23 @@ impl FederationTest {
             );
             let decoders = consensus.decoders();
 
-            let fedimint = FedimintServer::new_with(
+            let connections = ReconnectPeerConnections::new_with_status(
+                cfg.network_config(),
+                connect_gen(cfg),
+                consensus.peer_status.clone(),
+                &mut task_group,
+            )
+            .await
+            .into_dyn();
+            let connections =
+                FaultyConnections::new(cfg.local.identity, connections, faults.clone()).into_dyn();
+            let fedimint = FedimintServer::new_with_connections(
                 cfg.clone(),
                 consensus,
                 tx_receiver,
-                connect_gen(cfg),
+                connections,
                 decoders,
-                &mut task_group,
-            )
-            .await;
+            );
 
             let cfg = cfg.clone();
             let consensus = fedimint.consensus.clone();
@@ -1306,6 +1524,7 @@ impl FederationTest {
             servers,
             max_balance_sheet,
             last_consensus,
+            faults,
             decoders: module_inits.decoders(cfg.iter_module_instances()).unwrap(),
             cfg,
             wallet,
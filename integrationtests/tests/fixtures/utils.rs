@@ -6,6 +6,7 @@ use async_trait::async_trait;
 use bitcoin::secp256k1::PublicKey;
 use fedimint_ln::contracts::Preimage;
 use fedimint_ln::route_hints::RouteHint;
+use fedimint_ln::GatewayLiquidity;
 use ln_gateway::ln::{LightningError, LnRpc};
 use tokio::sync::Mutex;
 
@@ -68,4 +69,8 @@ impl LnRpc for LnRpcAdapter {
     async fn route_hints(&self) -> Result<Vec<RouteHint>, Error> {
         Ok(vec![RouteHint(vec![])])
     }
+
+    async fn liquidity(&self) -> Result<GatewayLiquidity, Error> {
+        self.client.liquidity().await
+    }
 }
@@ -5,28 +5,36 @@ use std::time::Duration;
 use anyhow::Result;
 use assert_matches::assert_matches;
 use bitcoin::{Amount, KeyPair};
+use fedimint_api::core::LEGACY_HARDCODED_INSTANCE_ID_MINT;
+use fedimint_api::module::ModuleConsensusVersion;
 use fedimint_api::task::TaskGroup;
-use fedimint_api::{msats, sats, TieredMulti};
+use fedimint_api::Feerate;
+use fedimint_api::{msats, sats, PeerId, TieredMulti};
 use fedimint_ln::contracts::{Preimage, PreimageDecryptionShare};
 use fedimint_ln::LightningConsensusItem;
 use fedimint_mint::{MintConsensusItem, MintOutputSignatureShare};
 use fedimint_server::consensus::TransactionSubmissionError::TransactionError;
 use fedimint_server::consensus::TransactionSubmissionError::TransactionReplayError;
 use fedimint_server::epoch::ConsensusItem;
+use fedimint_server::outcome::TransactionStatus;
+use fedimint_server::query::QueryStrategyKind;
+use fedimint_server::simulation::Simulation;
 use fedimint_server::transaction::TransactionError::UnbalancedTransaction;
-use fedimint_wallet::PegOutSignatureItem;
-use fedimint_wallet::WalletConsensusItem::PegOutSignature;
+use fedimint_wallet::WalletConsensusItem::{PegOutSignature, RoundConsensus};
+use fedimint_wallet::PEG_OUT_RBF_DELAY;
+use fedimint_wallet::{PegOutSignatureItem, RoundConsensusItem, WalletConsensusItem};
 use fixtures::{rng, secp, sha256};
 use futures::future::{join_all, Either};
 use mint_client::logging::LOG_TEST;
 use mint_client::mint::MintClient;
+use mint_client::operations::OperationId;
 use mint_client::transaction::legacy::Output;
 use mint_client::transaction::TransactionBuilder;
 use mint_client::{ClientError, ConfigVerifyError};
 use threshold_crypto::{SecretKey, SecretKeyShare};
 use tracing::{debug, info, instrument};
 
-use crate::fixtures::{peers, test, unwrap_item, FederationTest};
+use crate::fixtures::{peers, test, unwrap_item, FederationTest, LinkFault};
 
 #[tokio::test(flavor = "multi_thread")]
 async fn peg_in_and_peg_out_with_fees() -> Result<()> {
@@ -63,7 +71,8 @@ async fn peg_in_and_peg_out_with_fees() -> Result<()> {
             .wallet_client()
             .await_peg_out_outcome(out_point)
             .await
-            .unwrap();
+            .unwrap()
+            .txid;
 
         assert!(matches!(
             unwrap_item(&fed.find_module_item(fed.wallet_id).await),
@@ -106,36 +115,89 @@ async fn peg_outs_are_rejected_if_fees_are_too_low() -> Result<()> {
 }
 
 #[tokio::test(flavor = "multi_thread")]
-#[instrument(name = "peg_outs_are_only_allowed_once_per_epoch")]
-async fn peg_outs_are_only_allowed_once_per_epoch() -> Result<()> {
+#[instrument(name = "peg_outs_in_the_same_epoch_are_batched")]
+async fn peg_outs_in_the_same_epoch_are_batched() -> Result<()> {
     test(2, |fed, user, bitcoin, _, _| async move {
         let address1 = bitcoin.get_new_address().await;
         let address2 = bitcoin.get_new_address().await;
 
         fed.mine_and_mint(&user, &*bitcoin, sats(5000)).await;
-        let (fees, _) = user.peg_out(1000, &address1).await;
-        user.peg_out(1000, &address2).await;
-        info!(target: LOG_TEST, ?fees, "Tx fee");
+        let (fees1, out_point1) = user.peg_out(1000, &address1).await;
+        let (fees2, out_point2) = user.peg_out(1000, &address2).await;
+        info!(target: LOG_TEST, ?fees1, ?fees2, "Tx fees");
 
         fed.run_consensus_epochs(2).await;
         fed.broadcast_transactions().await;
 
-        let received1 = bitcoin.mine_block_and_get_received(&address1).await;
-        let received2 = bitcoin.mine_block_and_get_received(&address2).await;
-
-        assert_eq!(received1 + received2, sats(1000));
-        // either first peg-out failed OR second failed leaving us unissued change
-        assert!(received1 == sats(0) || received2 == sats(0));
+        let wallet_client = user.client.wallet_client();
+        let outcome1 = wallet_client
+            .await_peg_out_outcome(out_point1)
+            .await
+            .unwrap();
+        let outcome2 = wallet_client
+            .await_peg_out_outcome(out_point2)
+            .await
+            .unwrap();
+        assert_eq!(outcome1.txid, outcome2.txid);
+        assert_ne!(outcome1.vout, outcome2.vout);
 
         assert_eq!(
-            user.total_notes().await,
-            sats(5000 - 2 * 1000) - fees - fees
+            bitcoin.mine_block_and_get_received(&address1).await,
+            sats(1000)
+        );
+        assert_eq!(
+            bitcoin.mine_block_and_get_received(&address2).await,
+            sats(1000)
         );
-        user.client.reissue_pending_notes(rng()).await.unwrap();
-        fed.run_consensus_epochs(2).await; // reissue the notes from the tx that failed
-        user.client.fetch_all_notes().await.unwrap();
+        user.assert_total_notes(sats(5000 - 2 * 1000) - fees1 - fees2)
+            .await;
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn peg_out_outcomes_follow_fee_bumps() -> Result<()> {
+    test(2, |fed, user, bitcoin, _, _| async move {
+        let bitcoin = bitcoin.lock_exclusive().await;
+        let address = bitcoin.get_new_address().await;
+
+        fed.mine_and_mint(&user, &*bitcoin, sats(5000)).await;
+        let (_, out_point) = user.peg_out(1000, &address).await;
+        fed.run_consensus_epochs(2).await; // peg-out tx + peg out signing epoch
 
-        assert_eq!(user.total_notes().await, sats(5000 - 1000) - fees);
+        let wallet_client = user.client.wallet_client();
+        let original = wallet_client
+            .await_peg_out_outcome(out_point)
+            .await
+            .unwrap();
+
+        // the peg-out isn't broadcast, so it gets stuck once the fee rate rises
+        bitcoin.mine_blocks(PEG_OUT_RBF_DELAY as u64).await;
+        fed.run_consensus_epochs(1).await;
+        let round_consensus = match unwrap_item(&fed.find_module_item(fed.wallet_id).await) {
+            RoundConsensus(round_consensus) => round_consensus.clone(),
+            item => panic!("Unexpected wallet item {item:?}"),
+        };
+        fed.override_proposal(vec![ConsensusItem::Module(
+            fedimint_api::core::DynModuleConsensusItem::from_typed(
+                fed.wallet_id,
+                WalletConsensusItem::RoundConsensus(RoundConsensusItem {
+                    fee_rate: Feerate {
+                        sats_per_kvb: round_consensus.fee_rate.sats_per_kvb + 10_000,
+                    },
+                    ..round_consensus
+                }),
+            ),
+        )])
+        .await;
+        fed.run_consensus_epochs_wait(1).await.unwrap();
+
+        let bumped = wallet_client
+            .await_peg_out_outcome(out_point)
+            .await
+            .unwrap();
+        assert_ne!(bumped.txid, original.txid);
+        assert_eq!(bumped.vout, original.vout);
     })
     .await
 }
@@ -220,6 +282,53 @@ async fn ecash_can_be_exchanged_directly_between_users() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn ecash_can_be_reissued_in_batches() -> Result<()> {
+    test(2, |fed, user_send, bitcoin, _, _| async move {
+        let user_receive = user_send.new_user_with_peers(peers(&[0, 1])).await;
+
+        fed.mine_and_mint(&user_send, &*bitcoin, sats(5000)).await;
+        let ecash = fed.spend_ecash(&user_send, sats(3500)).await;
+
+        // All notes fit into a transaction of the default maximum size
+        let out_points = user_receive
+            .client
+            .reissue_batched(ecash, rng())
+            .await
+            .unwrap();
+        assert_eq!(out_points.len(), 1);
+        fed.run_consensus_epochs(2).await; // process transaction + sign new notes
+
+        user_receive.assert_total_notes(sats(3500)).await;
+        assert_eq!(fed.max_balance_sheet(), 0);
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reissuance_is_resumed_until_notes_are_fetched() -> Result<()> {
+    test(2, |fed, user_send, bitcoin, _, _| async move {
+        let user_receive = user_send.new_user_with_peers(peers(&[0, 1])).await;
+
+        fed.mine_and_mint(&user_send, &*bitcoin, sats(5000)).await;
+        let ecash = fed.spend_ecash(&user_send, sats(3500)).await;
+        let operation = OperationId::derive("receive-ecash", &ecash);
+        user_receive.client.reissue(ecash, rng()).await.unwrap();
+
+        // The federation didn't process the transaction yet
+        assert!(user_receive.client.resume_operations().await.is_empty());
+
+        fed.run_consensus_epochs(2).await; // process transaction + sign new notes
+        assert_eq!(
+            user_receive.client.resume_operations().await,
+            vec![operation]
+        );
+        assert_eq!(user_receive.client.notes().await.total_amount(), sats(3500));
+        assert!(user_receive.client.resume_operations().await.is_empty());
+    })
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn ecash_cannot_double_spent_with_different_nodes() -> Result<()> {
     test(2, |fed, user1, bitcoin, _, _| async move {
@@ -291,6 +400,25 @@ async fn ecash_in_wallet_can_sent_through_a_tx() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn surplus_notes_are_consolidated_into_larger_denominations() -> Result<()> {
+    test(2, |fed, user, _, _, _| async move {
+        user.set_notes_per_denomination(10).await;
+        fed.mint_notes_for_user(&user, msats(10)).await;
+        assert_eq!(user.client.notes().await.count_items(), 10);
+        assert!(user.client.is_idle().await);
+
+        user.set_notes_per_denomination(1).await;
+        let out_points = user.client.consolidate_notes(1, rng()).await.unwrap();
+        assert!(!out_points.is_empty());
+        fed.run_consensus_epochs(2).await; // process transaction + sign new notes
+
+        user.assert_total_notes(msats(10)).await;
+        assert!(user.client.notes().await.count_items() < 10);
+    })
+    .await
+}
+
 async fn drop_peer_3_during_epoch(fed: &FederationTest) -> Result<()> {
     // ensure that peers 1,2,3 create an epoch, so they can see peer 3's bad
     // proposal
@@ -317,6 +445,49 @@ async fn drop_peer_3_during_epoch(fed: &FederationTest) -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn guardians_halt_consensus_once_a_threshold_signaled_an_upgrade() -> Result<()> {
+    test(4, |fed, _, _, _, _| async move {
+        fed.subset_peers(&[0, 1]).await.signal_upgrade().await;
+        fed.run_empty_epochs(1).await;
+        assert!(!fed.halted_for_upgrade().await);
+        assert!(fed.upgrade_signaled().await);
+
+        // With 3 out of 4 guardians the threshold is reached
+        fed.subset_peers(&[2]).await.signal_upgrade().await;
+        fed.run_empty_epochs(1).await;
+        assert!(fed.halted_for_upgrade().await);
+        assert!(!fed.upgrade_signaled().await);
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn guardians_only_vote_for_module_versions_their_code_supports() -> Result<()> {
+    test(4, |fed, _, _, _, _| async move {
+        let mint_id = LEGACY_HARDCODED_INSTANCE_ID_MINT;
+        assert_eq!(
+            fed.module_versions().await[&mint_id],
+            ModuleConsensusVersion(0)
+        );
+        assert!(fed
+            .vote_module_version(mint_id, ModuleConsensusVersion(1))
+            .await
+            .is_err());
+
+        // Voting for the active version only withdraws earlier votes
+        fed.vote_module_version(mint_id, ModuleConsensusVersion(0))
+            .await
+            .unwrap();
+        fed.run_empty_epochs(1).await;
+        assert_eq!(
+            fed.module_versions().await[&mint_id],
+            ModuleConsensusVersion(0)
+        );
+    })
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn drop_peers_who_dont_contribute_peg_out_psbts() -> Result<()> {
     test(4, |fed, user, bitcoin, _, _| async move {
@@ -811,6 +982,7 @@ async fn receive_lightning_payment_invalid_preimage() -> Result<()> {
             payment_hash,
             Preimage(kp.x_only_public_key().0.serialize()),
             None,
+            None,
         );
         let mut builder = TransactionBuilder::default();
         builder.output(Output::LN(offer_output));
@@ -1057,6 +1229,30 @@ async fn can_get_signed_epoch_history() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn tx_outcome_is_verified_against_signed_epoch() -> Result<()> {
+    test(2, |fed, user_send, bitcoin, _, _| async move {
+        let user_receive = user_send.new_user_with_peers(peers(&[0, 1])).await;
+
+        fed.mine_and_mint(&user_send, &*bitcoin, sats(5000)).await;
+        let ecash = fed.spend_ecash(&user_send, sats(3500)).await;
+        let out_point = user_receive.client.reissue(ecash, rng()).await.unwrap();
+        // process transaction + sign the epoch accepting it
+        fed.run_consensus_epochs(2).await;
+
+        for strategy in [QueryStrategyKind::Fastest, QueryStrategyKind::Verified] {
+            assert_matches!(
+                user_receive
+                    .client
+                    .fetch_tx_outcome(out_point.txid, strategy)
+                    .await,
+                Ok(TransactionStatus::Accepted { .. })
+            );
+        }
+    })
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn rejoin_consensus_single_peer() -> Result<()> {
     test(4, |fed, user, bitcoin, _, _| async move {
@@ -1111,6 +1307,110 @@ async fn rejoin_consensus_threshold_peers() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn consensus_survives_faulty_links() -> Result<()> {
+    test(4, |fed, user, bitcoin, _, _| async move {
+        fed.faults.set_link(0, 1, LinkFault::Drop);
+        fed.faults
+            .set_link(1, 2, LinkFault::Delay(Duration::from_millis(200)));
+        fed.faults.set_link(2, 3, LinkFault::Duplicate);
+
+        fed.mine_and_mint(&user, &*bitcoin, sats(5000)).await;
+        let ecash = fed.spend_ecash(&user, sats(2000)).await;
+        user.client.reissue(ecash, rng()).await.unwrap();
+        fed.run_consensus_epochs(2).await; // process transaction + sign new notes
+
+        user.assert_total_notes(sats(5000)).await;
+        assert_eq!(fed.max_balance_sheet(), 0);
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn consensus_recovers_from_partition() -> Result<()> {
+    test(4, |fed, user, bitcoin, _, _| async move {
+        let online_peers = fed.subset_peers(&[0, 1, 2]).await;
+        let peer3 = fed.subset_peers(&[3]).await;
+
+        // The majority keeps processing transactions while peer 3 is cut off
+        fed.faults.partition(&[0, 1, 2], &[3]);
+        online_peers
+            .mine_and_mint(&user, &*bitcoin, sats(5000))
+            .await;
+        user.assert_total_notes(sats(5000)).await;
+
+        // Run until peer 3 has rejoined
+        fed.faults.heal();
+        join_all(vec![
+            Either::Left(async {
+                online_peers.run_consensus_epochs_wait(11).await.unwrap();
+            }),
+            Either::Right(async {
+                peer3.rejoin_consensus().await.unwrap();
+                peer3.run_consensus_epochs_wait(1).await.unwrap();
+            }),
+        ])
+        .await;
+
+        // All peers take part in the reissuance again
+        let ecash = fed.spend_ecash(&user, sats(2000)).await;
+        user.client.reissue(ecash, rng()).await.unwrap();
+        fed.run_consensus_epochs(2).await; // process transaction + sign new notes
+        user.assert_total_notes(sats(5000)).await;
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn consensus_recovers_from_guardian_crash_mid_epoch() -> Result<()> {
+    test(4, |fed, user, bitcoin, _, _| async move {
+        let online_peers = fed.subset_peers(&[0, 1, 2]).await;
+        let peer3 = fed.subset_peers(&[3]).await;
+
+        // Peer 3 crashes after it sent a few messages of the next epoch
+        fed.faults.crash_after(3, 5);
+        fed.mine_and_mint(&user, &*bitcoin, sats(5000)).await;
+        assert!(fed.faults.is_crashed(PeerId::from(3)));
+        user.assert_total_notes(sats(5000)).await;
+
+        // Run until peer 3 has restarted and caught up
+        join_all(vec![
+            Either::Left(async {
+                online_peers.run_consensus_epochs_wait(11).await.unwrap();
+            }),
+            Either::Right(async {
+                fed.restart_peer(3).await;
+                peer3.run_consensus_epochs_wait(1).await.unwrap();
+            }),
+        ])
+        .await;
+
+        let ecash = fed.spend_ecash(&user, sats(2000)).await;
+        user.client.reissue(ecash, rng()).await.unwrap();
+        fed.run_consensus_epochs(2).await; // process transaction + sign new notes
+        user.assert_total_notes(sats(5000)).await;
+        assert_eq!(fed.max_balance_sheet(), 0);
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn simulated_consensus_replays_from_seed() -> Result<()> {
+    let seed = Simulation::seed_from_env();
+    let mut task_group = TaskGroup::new();
+
+    let mut first = fixtures::simulation(4, seed, &mut task_group).await;
+    first.run_epochs(3).await?;
+    let mut second = fixtures::simulation(4, seed, &mut task_group).await;
+    second.run_epochs(3).await?;
+
+    assert!(first.epoch_hashes().len() >= 3);
+    assert_eq!(first.network().trace(), second.network().trace());
+    assert_eq!(first.network().now(), second.network().now());
+
+    task_group.shutdown_join_all(None).await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn ecash_can_be_recovered() -> Result<()> {
     test(2, |fed, user_send, bitcoin, _, _| async move {
@@ -1120,6 +1420,11 @@ async fn ecash_can_be_recovered() -> Result<()> {
         assert_eq!(user_send.total_notes().await, sats(5000));
         assert_eq!(user_receive.total_notes().await, sats(0));
 
+        user_send
+            .client
+            .mint_client()
+            .set_backup_metadata(Some("Savings".to_string()))
+            .await;
         user_send
             .client
             .mint_client()
@@ -1128,6 +1433,11 @@ async fn ecash_can_be_recovered() -> Result<()> {
             .unwrap();
 
         user_send.client.mint_client().wipe_notes().await.unwrap();
+        user_send
+            .client
+            .mint_client()
+            .set_backup_metadata(None)
+            .await;
 
         user_send.assert_total_notes(sats(0)).await;
 
@@ -1142,6 +1452,10 @@ async fn ecash_can_be_recovered() -> Result<()> {
             .unwrap();
 
         assert_eq!(user_send.total_notes().await, sats(5000));
+        assert_eq!(
+            user_send.client.mint_client().backup_metadata().await,
+            Some("Savings".to_string())
+        );
 
         let ecash = fed.spend_ecash(&user_send, sats(3500)).await;
         user_receive.client.reissue(ecash, rng()).await.unwrap();
@@ -1166,14 +1480,20 @@ async fn ecash_can_be_recovered() -> Result<()> {
                                                // notes
         }
 
+        let progress = std::sync::Mutex::new(vec![]);
         user_send
             .client
             .mint_client()
-            .restore_ecash_from_federation(10, &mut task_group)
+            .restore_ecash_from_federation_with_progress(10, &mut task_group, |p| {
+                progress.lock().unwrap().push(p)
+            })
             .await
             .unwrap()
             .unwrap();
         assert_eq!(user_send.total_notes().await, sats(1400));
+        let progress = progress.into_inner().unwrap();
+        assert!(!progress.is_empty());
+        assert_eq!(progress.last().unwrap().percent(), 100);
 
         task_group.join_all(None).await.unwrap();
     })
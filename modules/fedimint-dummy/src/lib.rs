@@ -11,7 +11,7 @@ use fedimint_api::config::{
 };
 use fedimint_api::config::{ModuleConfigResponse, TypedServerModuleConsensusConfig};
 use fedimint_api::core::{ModuleInstanceId, ModuleKind};
-use fedimint_api::db::{Database, DatabaseTransaction};
+use fedimint_api::db::{Database, DatabaseTransaction, InvalidDbEntry};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::module::audit::Audit;
 use fedimint_api::module::interconnect::ModuleInterconect;
@@ -25,9 +25,11 @@ use fedimint_api::task::TaskGroup;
 use fedimint_api::{plugin_types_trait_impl, OutPoint, PeerId, ServerModule};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use strum::IntoEnumIterator;
 use thiserror::Error;
 
 use crate::config::{DummyClientConfig, DummyConfig, DummyConfigConsensus, DummyConfigPrivate};
+use crate::db::{DbKeyPrefix, ExampleKey};
 
 pub mod common;
 pub mod config;
@@ -144,6 +146,11 @@ impl ModuleGen for DummyConfigGenerator {
         serde_json::from_value::<DummyClientConfig>(config)?.consensus_hash()
     }
 
+    fn validate_client_config(&self, config: Value) -> anyhow::Result<()> {
+        serde_json::from_value::<DummyClientConfig>(config)?;
+        Ok(())
+    }
+
     async fn dump_database(
         &self,
         _dbtx: &mut DatabaseTransaction<'_>,
@@ -151,6 +158,17 @@ impl ModuleGen for DummyConfigGenerator {
     ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
         Box::new(BTreeMap::new().into_iter())
     }
+
+    async fn check_database(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<InvalidDbEntry> {
+        let known_prefixes = DbKeyPrefix::iter().map(|p| p as u8).collect::<Vec<_>>();
+        let mut invalid = dbtx.find_orphaned_entries(&known_prefixes).await;
+        for table in DbKeyPrefix::iter() {
+            invalid.extend(match table {
+                DbKeyPrefix::Example => dbtx.find_undecodable_entries::<ExampleKey>().await,
+            });
+        }
+        invalid
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -270,6 +288,7 @@ impl ServerModule for Dummy {
 
     async fn apply_output<'a, 'b>(
         &'a self,
+        _interconnect: &'a dyn ModuleInterconect,
         _dbtx: &mut DatabaseTransaction<'b>,
         _output: &'a DummyOutput,
         _out_point: OutPoint,
@@ -0,0 +1,39 @@
+use std::io;
+
+use fedimint_api::core::Decoder;
+use fedimint_api::encoding::{Decodable, DecodeError};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+
+use crate::{EscrowConsensusItem, EscrowInput, EscrowOutput, EscrowOutputOutcome};
+
+#[derive(Debug, Default, Clone)]
+pub struct EscrowDecoder;
+
+impl Decoder for EscrowDecoder {
+    type Input = EscrowInput;
+    type Output = EscrowOutput;
+    type OutputOutcome = EscrowOutputOutcome;
+    type ConsensusItem = EscrowConsensusItem;
+
+    fn decode_input(&self, mut d: &mut dyn io::Read) -> Result<EscrowInput, DecodeError> {
+        EscrowInput::consensus_decode(&mut d, &ModuleDecoderRegistry::default())
+    }
+
+    fn decode_output(&self, mut d: &mut dyn io::Read) -> Result<EscrowOutput, DecodeError> {
+        EscrowOutput::consensus_decode(&mut d, &ModuleDecoderRegistry::default())
+    }
+
+    fn decode_output_outcome(
+        &self,
+        mut d: &mut dyn io::Read,
+    ) -> Result<EscrowOutputOutcome, DecodeError> {
+        EscrowOutputOutcome::consensus_decode(&mut d, &ModuleDecoderRegistry::default())
+    }
+
+    fn decode_consensus_item(
+        &self,
+        mut r: &mut dyn io::Read,
+    ) -> Result<EscrowConsensusItem, DecodeError> {
+        EscrowConsensusItem::consensus_decode(&mut r, &ModuleDecoderRegistry::default())
+    }
+}
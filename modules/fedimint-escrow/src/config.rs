@@ -0,0 +1,74 @@
+use anyhow::bail;
+use fedimint_api::config::{
+    ClientModuleConfig, TypedClientModuleConfig, TypedServerModuleConfig,
+    TypedServerModuleConsensusConfig,
+};
+use fedimint_api::core::ModuleKind;
+use fedimint_api::encoding::Encodable;
+use fedimint_api::PeerId;
+use serde::{Deserialize, Serialize};
+
+use crate::KIND;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EscrowConfig {
+    /// Contains all configuration that will be encrypted such as private key
+    /// material
+    pub private: EscrowConfigPrivate,
+    /// Contains all configuration that needs to be the same for every
+    /// federation member
+    pub consensus: EscrowConfigConsensus,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Encodable)]
+pub struct EscrowConfigConsensus {
+    /// Limits the number of signatures a release input needs to be checked
+    pub max_release_keys: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EscrowConfigPrivate;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable)]
+pub struct EscrowClientConfig {
+    pub max_release_keys: u32,
+}
+
+impl TypedClientModuleConfig for EscrowClientConfig {
+    fn kind(&self) -> fedimint_api::core::ModuleKind {
+        KIND
+    }
+}
+
+impl TypedServerModuleConsensusConfig for EscrowConfigConsensus {
+    fn to_client_config(&self) -> ClientModuleConfig {
+        ClientModuleConfig::new(
+            KIND,
+            serde_json::to_value(&EscrowClientConfig {
+                max_release_keys: self.max_release_keys,
+            })
+            .expect("Serialization can't fail"),
+        )
+    }
+}
+
+impl TypedServerModuleConfig for EscrowConfig {
+    type Local = ();
+    type Private = EscrowConfigPrivate;
+    type Consensus = EscrowConfigConsensus;
+
+    fn from_parts(_local: Self::Local, private: Self::Private, consensus: Self::Consensus) -> Self {
+        Self { private, consensus }
+    }
+
+    fn to_parts(self) -> (ModuleKind, Self::Local, Self::Private, Self::Consensus) {
+        (KIND, (), self.private, self.consensus)
+    }
+
+    fn validate_config(&self, _identity: &PeerId) -> anyhow::Result<()> {
+        if self.consensus.max_release_keys == 0 {
+            bail!("Escrows need at least one release key");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::{impl_db_prefix_const, OutPoint};
+use serde::Serialize;
+use strum_macros::EnumIter;
+
+use crate::EscrowAccount;
+
+#[repr(u8)]
+#[derive(Clone, EnumIter, Debug)]
+pub enum DbKeyPrefix {
+    Escrow = 0x70,
+}
+
+impl std::fmt::Display for DbKeyPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Escrows by the output that funded them
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct EscrowKey(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct EscrowKeyPrefix;
+
+impl_db_prefix_const!(
+    key = EscrowKey,
+    value = EscrowAccount,
+    prefix = DbKeyPrefix::Escrow,
+    key_prefix = EscrowKeyPrefix
+);
@@ -0,0 +1,535 @@
+//! Escrow contracts: funds are locked until a threshold of designated keys,
+//! e.g. of buyer, seller and arbiter or of external oracles, releases them to
+//! a payee, or until a timeout after which they can be refunded.
+//!
+//! See [`terms`] for how releases are signed.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fmt;
+
+use async_trait::async_trait;
+use bitcoin_hashes::sha256;
+use common::EscrowDecoder;
+use fedimint_api::cancellable::Cancellable;
+use fedimint_api::config::{
+    ConfigGenParams, DkgPeerMsg, ServerModuleConfig, TypedServerModuleConfig,
+};
+use fedimint_api::config::{ModuleConfigResponse, TypedServerModuleConsensusConfig};
+use fedimint_api::core::{ModuleInstanceId, ModuleKind, LEGACY_HARDCODED_INSTANCE_ID_WALLET};
+use fedimint_api::db::{Database, DatabaseTransaction, InvalidDbEntry};
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::module::audit::Audit;
+use fedimint_api::module::interconnect::ModuleInterconect;
+use fedimint_api::module::{
+    api_endpoint, ApiEndpoint, ApiError, ApiVersion, ConsensusProposal, CoreConsensusVersion,
+    InputMeta, IntoModuleError, ModuleConsensusVersion, ModuleError, ModuleGen,
+    TransactionItemAmount,
+};
+use fedimint_api::net::peers::MuxPeerConnections;
+use fedimint_api::server::DynServerModule;
+use fedimint_api::task::TaskGroup;
+use fedimint_api::{
+    plugin_types_trait_impl, push_db_pair_items, Amount, OutPoint, PeerId, ServerModule,
+};
+use futures::StreamExt;
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum::IntoEnumIterator;
+use thiserror::Error;
+use tracing::info;
+
+use crate::config::{EscrowClientConfig, EscrowConfig, EscrowConfigConsensus, EscrowConfigPrivate};
+use crate::db::{DbKeyPrefix, EscrowKey, EscrowKeyPrefix};
+pub use crate::terms::{release_message, EscrowTerms, ReleaseSignature};
+
+pub mod common;
+pub mod config;
+pub mod db;
+pub mod terms;
+
+const KIND: ModuleKind = ModuleKind::from_static_str("escrow");
+
+/// Upper bound for the release keys of an escrow, each of them may have to be
+/// checked when it is released
+const MAX_RELEASE_KEYS: u32 = 20;
+
+/// Escrow module
+#[derive(Debug)]
+pub struct Escrow {
+    pub cfg: EscrowConfig,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct EscrowConsensusItem;
+
+#[derive(Debug, Clone)]
+pub struct EscrowVerificationCache;
+
+#[derive(Debug)]
+pub struct EscrowGen;
+
+#[async_trait]
+impl ModuleGen for EscrowGen {
+    const KIND: ModuleKind = KIND;
+    type Decoder = EscrowDecoder;
+
+    fn decoder(&self) -> EscrowDecoder {
+        EscrowDecoder
+    }
+
+    fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
+        &[ModuleConsensusVersion(0)]
+    }
+
+    async fn init(
+        &self,
+        cfg: ServerModuleConfig,
+        _db: Database,
+        _env: &BTreeMap<OsString, OsString>,
+        _task_group: &mut TaskGroup,
+    ) -> anyhow::Result<DynServerModule> {
+        Ok(Escrow::new(cfg.to_typed()?).into())
+    }
+
+    fn trusted_dealer_gen(
+        &self,
+        peers: &[PeerId],
+        _params: &ConfigGenParams,
+    ) -> BTreeMap<PeerId, ServerModuleConfig> {
+        peers
+            .iter()
+            .map(|&peer| {
+                let config = EscrowConfig {
+                    private: EscrowConfigPrivate,
+                    consensus: EscrowConfigConsensus {
+                        max_release_keys: MAX_RELEASE_KEYS,
+                    },
+                };
+                (peer, config.to_erased())
+            })
+            .collect()
+    }
+
+    async fn distributed_gen(
+        &self,
+        _connections: &MuxPeerConnections<ModuleInstanceId, DkgPeerMsg>,
+        _our_id: &PeerId,
+        _instance_id: ModuleInstanceId,
+        _peers: &[PeerId],
+        _params: &ConfigGenParams,
+        _task_group: &mut TaskGroup,
+    ) -> anyhow::Result<Cancellable<ServerModuleConfig>> {
+        let server = EscrowConfig {
+            private: EscrowConfigPrivate,
+            consensus: EscrowConfigConsensus {
+                max_release_keys: MAX_RELEASE_KEYS,
+            },
+        };
+
+        Ok(Ok(server.to_erased()))
+    }
+
+    fn to_config_response(
+        &self,
+        config: serde_json::Value,
+    ) -> anyhow::Result<ModuleConfigResponse> {
+        let config = serde_json::from_value::<EscrowConfigConsensus>(config)?;
+
+        Ok(ModuleConfigResponse {
+            client: config.to_client_config(),
+            consensus_hash: config.consensus_hash()?,
+        })
+    }
+
+    fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
+        config.to_typed::<EscrowConfig>()?.validate_config(identity)
+    }
+
+    fn hash_client_module(&self, config: Value) -> anyhow::Result<sha256::Hash> {
+        serde_json::from_value::<EscrowClientConfig>(config)?.consensus_hash()
+    }
+
+    fn validate_client_config(&self, config: Value) -> anyhow::Result<()> {
+        serde_json::from_value::<EscrowClientConfig>(config)?;
+        Ok(())
+    }
+
+    async fn dump_database(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        prefix_names: Vec<String>,
+    ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
+        let mut escrow: BTreeMap<String, Box<dyn erased_serde::Serialize + Send>> = BTreeMap::new();
+        let filtered_prefixes = DbKeyPrefix::iter().filter(|f| {
+            prefix_names.is_empty() || prefix_names.contains(&f.to_string().to_lowercase())
+        });
+        for table in filtered_prefixes {
+            match table {
+                DbKeyPrefix::Escrow => {
+                    push_db_pair_items!(
+                        dbtx,
+                        EscrowKeyPrefix,
+                        EscrowKey,
+                        EscrowAccount,
+                        escrow,
+                        "Escrows"
+                    );
+                }
+            }
+        }
+
+        Box::new(escrow.into_iter())
+    }
+
+    async fn check_database(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<InvalidDbEntry> {
+        let known_prefixes = DbKeyPrefix::iter().map(|p| p as u8).collect::<Vec<_>>();
+        let mut invalid = dbtx.find_orphaned_entries(&known_prefixes).await;
+        for table in DbKeyPrefix::iter() {
+            invalid.extend(match table {
+                DbKeyPrefix::Escrow => dbtx.find_undecodable_entries::<EscrowKey>().await,
+            });
+        }
+        invalid
+    }
+}
+
+/// Spends the funds of an escrow
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub enum EscrowInput {
+    /// Releases the funds to `payee`, who has to sign the transaction, with
+    /// the signatures of a threshold of the release keys
+    Release {
+        escrow: OutPoint,
+        payee: XOnlyPublicKey,
+        signatures: Vec<ReleaseSignature>,
+    },
+    /// Returns the funds after the timeout, the transaction has to be signed
+    /// by the refund key
+    Refund { escrow: OutPoint },
+}
+
+impl EscrowInput {
+    pub fn escrow(&self) -> OutPoint {
+        match self {
+            EscrowInput::Release { escrow, .. } | EscrowInput::Refund { escrow } => *escrow,
+        }
+    }
+}
+
+impl fmt::Display for EscrowInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EscrowInput::Release { escrow, payee, .. } => {
+                write!(f, "Escrow release of {escrow:?} to {payee}")
+            }
+            EscrowInput::Refund { escrow } => write!(f, "Escrow refund of {escrow:?}"),
+        }
+    }
+}
+
+/// Locks `amount` until it is released or refunded according to the terms
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct EscrowOutput {
+    pub amount: Amount,
+    pub terms: EscrowTerms,
+}
+
+impl fmt::Display for EscrowOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Escrow of {} releasable by {} of {} keys",
+            self.amount,
+            self.terms.threshold,
+            self.terms.release_keys.len()
+        )
+    }
+}
+
+/// The escrow is identified by the out point of its output, so there is
+/// nothing to return
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct EscrowOutputOutcome;
+
+impl fmt::Display for EscrowOutputOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Escrow locked")
+    }
+}
+
+impl fmt::Display for EscrowConsensusItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Escrow consensus item")
+    }
+}
+
+/// Escrow as stored by the federation and returned by the `/escrow` endpoint
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct EscrowAccount {
+    pub amount: Amount,
+    pub terms: EscrowTerms,
+    pub state: EscrowState,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub enum EscrowState {
+    Locked,
+    Released { payee: XOnlyPublicKey },
+    Refunded,
+}
+
+#[async_trait]
+impl ServerModule for Escrow {
+    type Gen = EscrowGen;
+    type Decoder = EscrowDecoder;
+    type VerificationCache = EscrowVerificationCache;
+
+    fn decoder(&self) -> Self::Decoder {
+        EscrowDecoder
+    }
+
+    fn versions(&self) -> (ModuleConsensusVersion, &[ApiVersion]) {
+        (
+            ModuleConsensusVersion(0),
+            &[ApiVersion { major: 0, minor: 0 }],
+        )
+    }
+
+    async fn await_consensus_proposal(&self, _dbtx: &mut DatabaseTransaction<'_>) {
+        std::future::pending().await
+    }
+
+    async fn consensus_proposal(
+        &self,
+        _dbtx: &mut DatabaseTransaction<'_>,
+    ) -> ConsensusProposal<EscrowConsensusItem> {
+        ConsensusProposal::empty()
+    }
+
+    async fn begin_consensus_epoch<'a, 'b>(
+        &'a self,
+        _dbtx: &mut DatabaseTransaction<'b>,
+        _consensus_items: Vec<(PeerId, EscrowConsensusItem)>,
+    ) {
+    }
+
+    fn build_verification_cache<'a>(
+        &'a self,
+        _inputs: impl Iterator<Item = &'a EscrowInput> + Send,
+    ) -> Self::VerificationCache {
+        EscrowVerificationCache
+    }
+
+    async fn validate_input<'a, 'b>(
+        &self,
+        interconnect: &dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'b>,
+        _verification_cache: &Self::VerificationCache,
+        input: &'a EscrowInput,
+    ) -> Result<InputMeta, ModuleError> {
+        let escrow = dbtx
+            .get_value(&EscrowKey(input.escrow()))
+            .await
+            .expect("DB error")
+            .ok_or(EscrowError::UnknownEscrow)
+            .into_module_error_other()?;
+        if escrow.state != EscrowState::Locked {
+            return Err(EscrowError::AlreadySpent).into_module_error_other();
+        }
+
+        let pub_key = match input {
+            EscrowInput::Release {
+                escrow: out_point,
+                payee,
+                signatures,
+            } => {
+                escrow
+                    .terms
+                    .verify_release(*out_point, payee, signatures)
+                    .into_module_error_other()?;
+                *payee
+            }
+            EscrowInput::Refund { .. } => {
+                if block_height(interconnect).await < escrow.terms.timeout {
+                    return Err(EscrowError::TimeoutNotReached(escrow.terms.timeout))
+                        .into_module_error_other();
+                }
+                escrow.terms.refund_key
+            }
+        };
+
+        Ok(InputMeta {
+            amount: TransactionItemAmount {
+                amount: escrow.amount,
+                fee: Amount::ZERO,
+            },
+            puk_keys: vec![pub_key],
+        })
+    }
+
+    async fn apply_input<'a, 'b, 'c>(
+        &'a self,
+        interconnect: &'a dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'c>,
+        input: &'b EscrowInput,
+        cache: &Self::VerificationCache,
+    ) -> Result<InputMeta, ModuleError> {
+        let meta = self
+            .validate_input(interconnect, dbtx, cache, input)
+            .await?;
+
+        let mut escrow = dbtx
+            .get_value(&EscrowKey(input.escrow()))
+            .await
+            .expect("DB error")
+            .expect("Checked by validation");
+        escrow.state = match input {
+            EscrowInput::Release { payee, .. } => EscrowState::Released { payee: *payee },
+            EscrowInput::Refund { .. } => EscrowState::Refunded,
+        };
+        dbtx.insert_entry(&EscrowKey(input.escrow()), &escrow)
+            .await
+            .expect("DB error");
+
+        info!(escrow = ?input.escrow(), state = ?escrow.state, "Escrow spent");
+        Ok(meta)
+    }
+
+    async fn validate_output(
+        &self,
+        _dbtx: &mut DatabaseTransaction,
+        output: &EscrowOutput,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        if output.amount == Amount::ZERO {
+            return Err(EscrowError::ZeroAmount).into_module_error_other();
+        }
+        output
+            .terms
+            .validate(self.cfg.consensus.max_release_keys)
+            .into_module_error_other()?;
+
+        Ok(TransactionItemAmount {
+            amount: output.amount,
+            fee: Amount::ZERO,
+        })
+    }
+
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        _interconnect: &'a dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'b>,
+        output: &'a EscrowOutput,
+        out_point: OutPoint,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        let amount = self.validate_output(dbtx, output).await?;
+
+        dbtx.insert_new_entry(
+            &EscrowKey(out_point),
+            &EscrowAccount {
+                amount: output.amount,
+                terms: output.terms.clone(),
+                state: EscrowState::Locked,
+            },
+        )
+        .await
+        .expect("DB error");
+
+        info!(escrow = ?out_point, amount = %output.amount, "Escrow locked");
+        Ok(amount)
+    }
+
+    async fn end_consensus_epoch<'a, 'b>(
+        &'a self,
+        _consensus_peers: &HashSet<PeerId>,
+        _dbtx: &mut DatabaseTransaction<'b>,
+    ) -> Vec<PeerId> {
+        vec![]
+    }
+
+    async fn output_status(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        out_point: OutPoint,
+    ) -> Option<EscrowOutputOutcome> {
+        dbtx.get_value(&EscrowKey(out_point))
+            .await
+            .expect("DB error")
+            .map(|_| EscrowOutputOutcome)
+    }
+
+    async fn audit(&self, dbtx: &mut DatabaseTransaction<'_>, audit: &mut Audit) {
+        audit
+            .add_items(dbtx, &EscrowKeyPrefix, |_, escrow| match escrow.state {
+                EscrowState::Locked => -(escrow.amount.msats as i64),
+                EscrowState::Released { .. } | EscrowState::Refunded => 0,
+            })
+            .await;
+    }
+
+    fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
+        vec![api_endpoint! {
+            "/escrow",
+            async |_module: &Escrow, dbtx, escrow: OutPoint| -> EscrowAccount {
+                dbtx
+                    .get_value(&EscrowKey(escrow))
+                    .await
+                    .expect("DB error")
+                    .ok_or_else(|| ApiError::not_found(String::from("Escrow not found")))
+            }
+        }]
+    }
+}
+
+impl Escrow {
+    /// Create new module instance
+    pub fn new(cfg: EscrowConfig) -> Escrow {
+        Escrow { cfg }
+    }
+}
+
+async fn block_height(interconnect: &dyn ModuleInterconect) -> u32 {
+    let body = interconnect
+        .call(
+            LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+            "/block_height".to_owned(),
+            Default::default(),
+        )
+        .await
+        .expect("Wallet module not present or malfunctioning!");
+
+    serde_json::from_value(body).expect("Malformed block height response from wallet module!")
+}
+
+// Must be unique.
+// TODO: we need to provide guidence for allocating these
+pub const MODULE_KEY_ESCROW: u16 = 131;
+plugin_types_trait_impl!(
+    MODULE_KEY_ESCROW,
+    EscrowInput,
+    EscrowOutput,
+    EscrowOutputOutcome,
+    EscrowConsensusItem,
+    EscrowVerificationCache
+);
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
+pub enum EscrowError {
+    #[error("Escrows can have at most {0} release keys")]
+    TooManyReleaseKeys(u32),
+    #[error("The threshold has to be between one and the number of release keys")]
+    InvalidThreshold,
+    #[error("A release signature is invalid")]
+    InvalidReleaseSignature,
+    #[error("Only {0} of the {1} required release keys signed")]
+    NotEnoughReleaseSignatures(usize, u32),
+    #[error("The escrow does not exist")]
+    UnknownEscrow,
+    #[error("The escrow was already released or refunded")]
+    AlreadySpent,
+    #[error("The escrow can only be refunded from block height {0} on")]
+    TimeoutNotReached(u32),
+    #[error("Escrows need a non-zero amount")]
+    ZeroAmount,
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeSet;
+
+use bitcoin_hashes::{sha256, Hash as BitcoinHash, HashEngine};
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::OutPoint;
+use secp256k1::{schnorr, Message, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::EscrowError;
+
+const RELEASE_TAG: &str = "fedimint-escrow-release";
+
+/// Conditions under which the funds locked in an escrow can be spent
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct EscrowTerms {
+    /// Keys that can release the funds together, e.g. of buyer, seller and
+    /// arbiter or of external oracles
+    pub release_keys: Vec<XOnlyPublicKey>,
+    /// Number of `release_keys` that have to sign a release
+    pub threshold: u32,
+    /// Key that can claim the funds back once the timeout passed
+    pub refund_key: XOnlyPublicKey,
+    /// Block height from which on the funds can be refunded
+    pub timeout: u32,
+}
+
+/// Signature of the release key at `key_index` of the terms
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct ReleaseSignature {
+    pub key_index: u32,
+    pub signature: schnorr::Signature,
+}
+
+impl EscrowTerms {
+    pub fn validate(&self, max_release_keys: u32) -> Result<(), EscrowError> {
+        if self.release_keys.len() > max_release_keys as usize {
+            return Err(EscrowError::TooManyReleaseKeys(max_release_keys));
+        }
+        if self.threshold == 0 || self.threshold as usize > self.release_keys.len() {
+            return Err(EscrowError::InvalidThreshold);
+        }
+        Ok(())
+    }
+
+    /// Checks that at least `threshold` different release keys signed the
+    /// release of `escrow` to `payee`
+    pub fn verify_release(
+        &self,
+        escrow: OutPoint,
+        payee: &XOnlyPublicKey,
+        signatures: &[ReleaseSignature],
+    ) -> Result<(), EscrowError> {
+        let message = Message::from(release_message(escrow, payee));
+        let secp = Secp256k1::verification_only();
+
+        let mut signers = BTreeSet::new();
+        for ReleaseSignature {
+            key_index,
+            signature,
+        } in signatures
+        {
+            let key = self
+                .release_keys
+                .get(*key_index as usize)
+                .ok_or(EscrowError::InvalidReleaseSignature)?;
+            secp.verify_schnorr(signature, &message, key)
+                .map_err(|_| EscrowError::InvalidReleaseSignature)?;
+            signers.insert(key_index);
+        }
+
+        if signers.len() < self.threshold as usize {
+            return Err(EscrowError::NotEnoughReleaseSignatures(
+                signers.len(),
+                self.threshold,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Message the release keys sign to release the funds of `escrow` to `payee`,
+/// who then has to sign the transaction spending them
+pub fn release_message(escrow: OutPoint, payee: &XOnlyPublicKey) -> sha256::Hash {
+    let mut engine = sha256::Hash::engine();
+    Encodable::consensus_encode(&RELEASE_TAG.as_bytes(), &mut engine).expect("Hashing never fails");
+    Encodable::consensus_encode(&escrow, &mut engine).expect("Hashing never fails");
+    Encodable::consensus_encode(payee, &mut engine).expect("Hashing never fails");
+    sha256::Hash::from_engine(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::Hash as BitcoinHash;
+    use fedimint_api::{OutPoint, TransactionId};
+    use secp256k1::{KeyPair, Message, Secp256k1};
+
+    use super::{release_message, EscrowTerms, ReleaseSignature};
+    use crate::EscrowError;
+
+    #[test]
+    fn release_needs_threshold_of_signers() {
+        let secp = Secp256k1::new();
+        let keys = (0..3)
+            .map(|_| KeyPair::new(&secp, &mut rand::thread_rng()))
+            .collect::<Vec<_>>();
+        let payee = KeyPair::new(&secp, &mut rand::thread_rng())
+            .x_only_public_key()
+            .0;
+        let terms = EscrowTerms {
+            release_keys: keys.iter().map(|key| key.x_only_public_key().0).collect(),
+            threshold: 2,
+            refund_key: keys[0].x_only_public_key().0,
+            timeout: 1000,
+        };
+        assert_eq!(terms.validate(20), Ok(()));
+        assert_eq!(terms.validate(2), Err(EscrowError::TooManyReleaseKeys(2)));
+
+        let escrow = OutPoint {
+            txid: TransactionId::from_inner([1; 32]),
+            out_idx: 0,
+        };
+        let message = Message::from(release_message(escrow, &payee));
+        let sign = |index: usize| ReleaseSignature {
+            key_index: index as u32,
+            signature: secp.sign_schnorr(&message, &keys[index]),
+        };
+
+        assert_eq!(
+            terms.verify_release(escrow, &payee, &[sign(0), sign(0)]),
+            Err(EscrowError::NotEnoughReleaseSignatures(1, 2))
+        );
+        assert_eq!(
+            terms.verify_release(escrow, &payee, &[sign(0), sign(2)]),
+            Ok(())
+        );
+        assert_eq!(
+            terms.verify_release(escrow, &keys[0].x_only_public_key().0, &[sign(0), sign(2)]),
+            Err(EscrowError::InvalidReleaseSignature)
+        );
+        assert_eq!(
+            terms.verify_release(
+                escrow,
+                &payee,
+                &[
+                    sign(0),
+                    ReleaseSignature {
+                        key_index: 3,
+                        ..sign(1)
+                    }
+                ]
+            ),
+            Err(EscrowError::InvalidReleaseSignature)
+        );
+    }
+}
@@ -0,0 +1,600 @@
+//! Minimal decoding of BOLT12 offers and invoices.
+//!
+//! Only the fields needed to pay an invoice fetched for an offer are extracted.
+//! Invoices are checked to be signed by their node id and can be matched
+//! against the offer they were requested for, so a gateway fetching invoices on
+//! behalf of a user can't substitute an invoice of its own.
+
+use std::fmt::{Display, Formatter};
+use std::io::{Error, Read, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use bitcoin_hashes::{sha256, Hash, HashEngine};
+use fedimint_api::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+const OFFER_HRP: &str = "lno";
+const INVOICE_HRP: &str = "lni";
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const SIGNATURE_TAG: &str = "lightninginvoicesignature";
+
+/// TLV types an invoice copies from the offer it was requested for
+const OFFER_TYPES: RangeInclusive<u64> = 1..=79;
+/// TLV types excluded from the merkle root the signature commits to
+const SIGNATURE_TYPES: RangeInclusive<u64> = 240..=1000;
+
+const OFFER_CURRENCY_TYPE: u64 = 6;
+const OFFER_AMOUNT_TYPE: u64 = 8;
+const OFFER_DESCRIPTION_TYPE: u64 = 10;
+const OFFER_NODE_ID_TYPE: u64 = 22;
+const INVOICE_CREATED_AT_TYPE: u64 = 164;
+const INVOICE_RELATIVE_EXPIRY_TYPE: u64 = 166;
+const INVOICE_PAYMENT_HASH_TYPE: u64 = 168;
+const INVOICE_AMOUNT_TYPE: u64 = 170;
+const INVOICE_FEATURES_TYPE: u64 = 174;
+const INVOICE_NODE_ID_TYPE: u64 = 176;
+const SIGNATURE_TYPE: u64 = 240;
+
+/// Even (compulsory) bit of the `basic_mpp` feature, the odd (optional) bit
+/// follows it
+const BASIC_MPP_FEATURE_BIT: usize = 16;
+
+/// Seconds an invoice is valid for if it doesn't specify a relative expiry
+const DEFAULT_RELATIVE_EXPIRY: u64 = 7200;
+
+/// Reusable payment request of a lightning node (`lno1…`)
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Bolt12Offer {
+    encoded: String,
+    offer_records: Vec<u8>,
+    node_id: secp256k1::PublicKey,
+    amount_msats: Option<u64>,
+    description: Option<String>,
+}
+
+impl Bolt12Offer {
+    /// Node that signs invoices requested for this offer
+    pub fn node_id(&self) -> secp256k1::PublicKey {
+        self.node_id
+    }
+
+    /// Amount the offer asks for, `None` if the payer can choose it or it is
+    /// denominated in a currency other than bitcoin
+    pub fn amount_msats(&self) -> Option<u64> {
+        self.amount_msats
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+}
+
+impl FromStr for Bolt12Offer {
+    type Err = Bolt12Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let records = parse_tlv_stream(&decode_bech32(s, OFFER_HRP)?)?;
+        if let Some(record) = records.iter().find(|r| !OFFER_TYPES.contains(&r.tlv_type)) {
+            return Err(Bolt12Error::UnexpectedRecord(record.tlv_type));
+        }
+
+        let amount_msats = match (
+            find_record(&records, OFFER_CURRENCY_TYPE),
+            find_record(&records, OFFER_AMOUNT_TYPE),
+        ) {
+            (None, Some(amount)) => Some(read_tu64(amount)?),
+            _ => None,
+        };
+        let description = find_record(&records, OFFER_DESCRIPTION_TYPE)
+            .map(|description| String::from_utf8(description.to_vec()))
+            .transpose()
+            .map_err(|_| Bolt12Error::InvalidField("description"))?;
+        // Offers only reachable through blinded paths are signed for by the path's last
+        // node, which we have no way of checking
+        let node_id = read_pubkey(&records, OFFER_NODE_ID_TYPE, "offer_node_id")?;
+
+        Ok(Bolt12Offer {
+            encoded: s.to_owned(),
+            offer_records: offer_records(&records),
+            node_id,
+            amount_msats,
+            description,
+        })
+    }
+}
+
+/// Invoice a node returns when asked to be paid for a [`Bolt12Offer`]
+/// (`lni1…`)
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Bolt12Invoice {
+    encoded: String,
+    offer_records: Vec<u8>,
+    node_id: secp256k1::PublicKey,
+    payment_hash: sha256::Hash,
+    amount_msats: u64,
+    created_at: u64,
+    relative_expiry: u64,
+    basic_mpp: bool,
+}
+
+impl Bolt12Invoice {
+    pub fn payment_hash(&self) -> sha256::Hash {
+        self.payment_hash
+    }
+
+    pub fn amount_msats(&self) -> u64 {
+        self.amount_msats
+    }
+
+    /// Node that signed the invoice and has to be paid
+    pub fn node_id(&self) -> secp256k1::PublicKey {
+        self.node_id
+    }
+
+    /// Unix timestamp in seconds after which the invoice can't be paid anymore
+    pub fn expires_at(&self) -> u64 {
+        self.created_at.saturating_add(self.relative_expiry)
+    }
+
+    /// Whether the payee accepts payments split into multiple parts
+    pub fn supports_basic_mpp(&self) -> bool {
+        self.basic_mpp
+    }
+
+    /// Checks that the invoice was issued for `offer` by the offer's node
+    pub fn is_for_offer(&self, offer: &Bolt12Offer) -> bool {
+        self.node_id == offer.node_id && self.offer_records == offer.offer_records
+    }
+}
+
+impl FromStr for Bolt12Invoice {
+    type Err = Bolt12Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let records = parse_tlv_stream(&decode_bech32(s, INVOICE_HRP)?)?;
+
+        let payment_hash = find_record(&records, INVOICE_PAYMENT_HASH_TYPE)
+            .ok_or(Bolt12Error::MissingField("invoice_payment_hash"))?;
+        let payment_hash = sha256::Hash::from_slice(payment_hash)
+            .map_err(|_| Bolt12Error::InvalidField("invoice_payment_hash"))?;
+        let amount_msats = read_tu64(
+            find_record(&records, INVOICE_AMOUNT_TYPE)
+                .ok_or(Bolt12Error::MissingField("invoice_amount"))?,
+        )?;
+        let created_at = read_tu64(
+            find_record(&records, INVOICE_CREATED_AT_TYPE)
+                .ok_or(Bolt12Error::MissingField("invoice_created_at"))?,
+        )?;
+        let relative_expiry = find_record(&records, INVOICE_RELATIVE_EXPIRY_TYPE)
+            .map(read_tu64)
+            .transpose()?
+            .unwrap_or(DEFAULT_RELATIVE_EXPIRY);
+        let node_id = read_pubkey(&records, INVOICE_NODE_ID_TYPE, "invoice_node_id")?;
+        let basic_mpp = find_record(&records, INVOICE_FEATURES_TYPE).map_or(false, |features| {
+            has_feature(features, BASIC_MPP_FEATURE_BIT)
+        });
+
+        let signature =
+            find_record(&records, SIGNATURE_TYPE).ok_or(Bolt12Error::MissingField("signature"))?;
+        let signature = secp256k1::schnorr::Signature::from_slice(signature)
+            .map_err(|_| Bolt12Error::InvalidField("signature"))?;
+        let message = tagged_hash(
+            &sha256::Hash::hash(SIGNATURE_TAG.as_bytes()),
+            &merkle_root(&records)?[..],
+        );
+        secp256k1::global::SECP256K1
+            .verify_schnorr(&signature, &message.into(), &node_id.x_only_public_key().0)
+            .map_err(|_| Bolt12Error::InvalidSignature)?;
+
+        Ok(Bolt12Invoice {
+            encoded: s.to_owned(),
+            offer_records: offer_records(&records),
+            node_id,
+            payment_hash,
+            amount_msats,
+            created_at,
+            relative_expiry,
+            basic_mpp,
+        })
+    }
+}
+
+macro_rules! impl_string_encoding {
+    ($type:ty) => {
+        impl Display for $type {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                f.write_str(&self.encoded)
+            }
+        }
+
+        impl Encodable for $type {
+            fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+                self.encoded.consensus_encode(writer)
+            }
+        }
+
+        impl Decodable for $type {
+            fn consensus_decode<D: Read>(
+                d: &mut D,
+                modules: &ModuleDecoderRegistry,
+            ) -> Result<Self, DecodeError> {
+                String::consensus_decode(d, modules)?
+                    .parse::<$type>()
+                    .map_err(DecodeError::from_err)
+            }
+        }
+
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.encoded)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?
+                    .parse()
+                    .map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+impl_string_encoding!(Bolt12Offer);
+impl_string_encoding!(Bolt12Invoice);
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum Bolt12Error {
+    #[error("Invalid bech32 encoding")]
+    InvalidEncoding,
+    #[error("Expected prefix {expected} but got {found}")]
+    WrongPrefix { expected: String, found: String },
+    #[error("TLV stream is truncated")]
+    Truncated,
+    #[error("Value is not minimally encoded")]
+    NonMinimalEncoding,
+    #[error("TLV records are not in ascending order")]
+    UnorderedRecords,
+    #[error("Unexpected TLV record of type {0}")]
+    UnexpectedRecord(u64),
+    #[error("Missing field {0}")]
+    MissingField(&'static str),
+    #[error("Invalid field {0}")]
+    InvalidField(&'static str),
+    #[error("Invoice signature is invalid")]
+    InvalidSignature,
+}
+
+struct TlvRecord {
+    tlv_type: u64,
+    /// Encoded type, used for the nonce leaves of the merkle tree
+    type_bytes: Vec<u8>,
+    value: Vec<u8>,
+    /// Entire encoded record
+    bytes: Vec<u8>,
+}
+
+fn find_record(records: &[TlvRecord], tlv_type: u64) -> Option<&[u8]> {
+    records
+        .iter()
+        .find(|record| record.tlv_type == tlv_type)
+        .map(|record| record.value.as_slice())
+}
+
+fn offer_records(records: &[TlvRecord]) -> Vec<u8> {
+    records
+        .iter()
+        .filter(|record| OFFER_TYPES.contains(&record.tlv_type))
+        .flat_map(|record| record.bytes.iter().copied())
+        .collect()
+}
+
+fn read_pubkey(
+    records: &[TlvRecord],
+    tlv_type: u64,
+    name: &'static str,
+) -> Result<secp256k1::PublicKey, Bolt12Error> {
+    let key = find_record(records, tlv_type).ok_or(Bolt12Error::MissingField(name))?;
+    secp256k1::PublicKey::from_slice(key).map_err(|_| Bolt12Error::InvalidField(name))
+}
+
+/// Checks whether either bit of the feature pair starting at the even `bit` is
+/// set in the big-endian feature bitmap `features`
+fn has_feature(features: &[u8], bit: usize) -> bool {
+    let is_set = |bit: usize| {
+        features
+            .len()
+            .checked_sub(bit / 8 + 1)
+            .map_or(false, |idx| features[idx] & (1 << (bit % 8)) != 0)
+    };
+    is_set(bit) || is_set(bit + 1)
+}
+
+/// Decodes bech32 without a checksum, as used by BOLT12. Long strings may be
+/// split with `+` followed by whitespace.
+fn decode_bech32(encoded: &str, hrp: &str) -> Result<Vec<u8>, Bolt12Error> {
+    let encoded: String = encoded
+        .chars()
+        .filter(|c| *c != '+' && !c.is_whitespace())
+        .collect();
+    if encoded.chars().any(char::is_uppercase) && encoded.chars().any(char::is_lowercase) {
+        return Err(Bolt12Error::InvalidEncoding);
+    }
+    let encoded = encoded.to_lowercase();
+
+    let (found_hrp, data) = encoded
+        .rsplit_once('1')
+        .ok_or(Bolt12Error::InvalidEncoding)?;
+    if found_hrp != hrp {
+        return Err(Bolt12Error::WrongPrefix {
+            expected: hrp.to_owned(),
+            found: found_hrp.to_owned(),
+        });
+    }
+
+    let mut bytes = Vec::with_capacity(data.len() * 5 / 8);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in data.chars() {
+        let value = BECH32_CHARSET.find(c).ok_or(Bolt12Error::InvalidEncoding)? as u32;
+        acc = (acc << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+fn read_bigsize(data: &[u8], pos: &mut usize) -> Result<u64, Bolt12Error> {
+    let first = *data.get(*pos).ok_or(Bolt12Error::Truncated)?;
+    *pos += 1;
+    let (len, min) = match first {
+        0xfd => (2, 0xfd),
+        0xfe => (4, 0x1_0000),
+        0xff => (8, 0x1_0000_0000),
+        _ => return Ok(u64::from(first)),
+    };
+    let bytes = data.get(*pos..*pos + len).ok_or(Bolt12Error::Truncated)?;
+    *pos += len;
+
+    let value = bytes.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b));
+    if value < min {
+        return Err(Bolt12Error::NonMinimalEncoding);
+    }
+    Ok(value)
+}
+
+fn read_tu64(value: &[u8]) -> Result<u64, Bolt12Error> {
+    if value.len() > 8 || value.first() == Some(&0) {
+        return Err(Bolt12Error::NonMinimalEncoding);
+    }
+    Ok(value.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b)))
+}
+
+fn parse_tlv_stream(data: &[u8]) -> Result<Vec<TlvRecord>, Bolt12Error> {
+    let mut records: Vec<TlvRecord> = vec![];
+    let mut pos = 0;
+    while pos < data.len() {
+        let start = pos;
+        let tlv_type = read_bigsize(data, &mut pos)?;
+        let type_end = pos;
+        let len =
+            usize::try_from(read_bigsize(data, &mut pos)?).map_err(|_| Bolt12Error::Truncated)?;
+        let end = pos
+            .checked_add(len)
+            .filter(|end| *end <= data.len())
+            .ok_or(Bolt12Error::Truncated)?;
+
+        if records
+            .last()
+            .map_or(false, |last| last.tlv_type >= tlv_type)
+        {
+            return Err(Bolt12Error::UnorderedRecords);
+        }
+        records.push(TlvRecord {
+            tlv_type,
+            type_bytes: data[start..type_end].to_vec(),
+            value: data[pos..end].to_vec(),
+            bytes: data[start..end].to_vec(),
+        });
+        pos = end;
+    }
+    Ok(records)
+}
+
+fn tagged_hash(tag: &sha256::Hash, msg: &[u8]) -> sha256::Hash {
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    engine.input(msg);
+    sha256::Hash::from_engine(engine)
+}
+
+/// Merkle root over all non-signature records as defined in BOLT12: every
+/// record contributes a leaf and a nonce leaf, branches hash their children in
+/// ascending order.
+fn merkle_root(records: &[TlvRecord]) -> Result<sha256::Hash, Bolt12Error> {
+    let first = records.first().ok_or(Bolt12Error::Truncated)?;
+    let leaf_tag = sha256::Hash::hash(b"LnLeaf");
+    let nonce_tag = sha256::Hash::hash(&[b"LnNonce".as_slice(), &first.bytes].concat());
+    let branch_tag = sha256::Hash::hash(b"LnBranch");
+
+    let mut nodes: Vec<sha256::Hash> = records
+        .iter()
+        .filter(|record| !SIGNATURE_TYPES.contains(&record.tlv_type))
+        .flat_map(|record| {
+            [
+                tagged_hash(&leaf_tag, &record.bytes),
+                tagged_hash(&nonce_tag, &record.type_bytes),
+            ]
+        })
+        .collect();
+
+    let num_nodes = nodes.len();
+    let mut step = 2;
+    while step / 2 < num_nodes {
+        for left in (0..num_nodes - step / 2).step_by(step) {
+            let (lesser, greater) = if nodes[left] < nodes[left + step / 2] {
+                (nodes[left], nodes[left + step / 2])
+            } else {
+                (nodes[left + step / 2], nodes[left])
+            };
+            nodes[left] = tagged_hash(&branch_tag, &[&lesser[..], &greater[..]].concat());
+        }
+        step *= 2;
+    }
+    nodes.first().copied().ok_or(Bolt12Error::Truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::hex::FromHex;
+    use bitcoin_hashes::{sha256, Hash};
+
+    use super::{
+        merkle_root, parse_tlv_stream, read_tu64, Bolt12Error, Bolt12Invoice, Bolt12Offer,
+    };
+    use crate::contracts::outgoing::OutgoingInvoice;
+
+    /// Offer of 1000 sats by the node with secret key `0x4141…41`, the key used
+    /// in the BOLT12 test vectors
+    const OFFER: &str = "lno1pqps7sjqpgtyzm3qv4uxzmtsd3jjqer9wd3hy6tsw35k7msjzfpy7nz5yqcnygrfdej82um5wf5k2uckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxg";
+
+    /// Offer of 2000 sats by the same node as [`OFFER`]
+    const OTHER_OFFER: &str =
+        "lno1pqp3apyqpgx5zmn0w35x2u3qdanxvetjzcss9mk8y3wkklfvevcrszlmu23kfrxh49px20665dqwmn4p72pksese";
+
+    /// Invoice for [`OFFER`] signed with the offer's node key, paying 1000 sats
+    /// to the hash of `[0x42; 32]` and supporting multi-part payments
+    const INVOICE: &str = "lni1pqps7sjqpgtyzm3qv4uxzmtsd3jjqer9wd3hy6tsw35k7msjzfpy7nz5yqcnygrfdej82um5wf5k2uckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxdyq3j48ugq4qsyyhk5uj3kkv82yxusugw8ztryn6ppfs5m06hkszyazquude2nsn92qv85ys9wqvpqqq9syypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvx0sgzs583h2je8hfu6slygp32gkn5alxckaag8dq0nvxkjs4sxtfp5t6fhvl04fj7n7tmhjt3yphprhmnm3jq40nnwezmcj5mf64rgcz4un";
+
+    /// [`INVOICE`] with the amount changed to 2000 sats but the original
+    /// signature
+    const TAMPERED_INVOICE: &str = "lni1pqps7sjqpgtyzm3qv4uxzmtsd3jjqer9wd3hy6tsw35k7msjzfpy7nz5yqcnygrfdej82um5wf5k2uckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxdyq3j48ugq4qsyyhk5uj3kkv82yxusugw8ztryn6ppfs5m06hkszyazquude2nsn92qv0gfq9wqvpqqq9syypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvx0sgzs583h2je8hfu6slygp32gkn5alxckaag8dq0nvxkjs4sxtfp5t6fhvl04fj7n7tmhjt3yphprhmnm3jq40nnwezmcj5mf64rgcz4un";
+
+    /// Merkle test vectors of the BOLT12 spec
+    #[test]
+    fn merkle_root_spec_vectors() {
+        let tlv1 = "010203e8";
+        let tlv2 = "02080000010000020003";
+        let tlv3 = "03310266e4598d1d3c415f572a8488830b60f7e744ed9235eb0b1ba93283b315c0351800000000000000010000000000000002";
+
+        for (tlvs, root) in [
+            (
+                tlv1.to_owned(),
+                "b013756c8fee86503a0b4abdab4cddeb1af5d344ca6fc2fa8b6c08938caa6f93",
+            ),
+            (
+                [tlv1, tlv2].concat(),
+                "c3774abbf4815aa54ccaa026bff6581f01f3be5fe814c620a252534f434bc0d1",
+            ),
+            (
+                [tlv1, tlv2, tlv3].concat(),
+                "ab2e79b1283b0b31e0b035258de23782df6b89a38cfa7237bde69aed1a658c5d",
+            ),
+        ] {
+            let records = parse_tlv_stream(&Vec::<u8>::from_hex(&tlvs).unwrap()).unwrap();
+            assert_eq!(
+                merkle_root(&records).unwrap(),
+                sha256::Hash::from_hex(root).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn parses_offer() {
+        let offer: Bolt12Offer = OFFER.parse().unwrap();
+        assert_eq!(offer.amount_msats(), Some(1_000_000));
+        assert_eq!(offer.description(), Some("An example description"));
+        assert_eq!(
+            offer.node_id().to_string(),
+            "02eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619"
+        );
+        assert_eq!(offer.to_string(), OFFER);
+
+        // Long offers may be split into multiple lines
+        let (first, second) = OFFER.split_at(60);
+        let split: Bolt12Offer = format!("{first}+\n  {second}").parse().unwrap();
+        assert_eq!(split.node_id(), offer.node_id());
+    }
+
+    #[test]
+    fn parses_invoice() {
+        let offer: Bolt12Offer = OFFER.parse().unwrap();
+        let invoice: Bolt12Invoice = INVOICE.parse().unwrap();
+
+        assert_eq!(invoice.payment_hash(), sha256::Hash::hash(&[0x42; 32]));
+        assert_eq!(invoice.amount_msats(), 1_000_000);
+        assert_eq!(invoice.node_id(), offer.node_id());
+        assert_eq!(invoice.expires_at(), 1_700_000_000 + 7200);
+        assert!(invoice.supports_basic_mpp());
+        assert!(invoice.is_for_offer(&offer));
+    }
+
+    #[test]
+    fn rejects_invalid_invoices() {
+        assert_eq!(
+            TAMPERED_INVOICE.parse::<Bolt12Invoice>(),
+            Err(Bolt12Error::InvalidSignature)
+        );
+        assert_eq!(
+            OFFER.parse::<Bolt12Invoice>(),
+            Err(Bolt12Error::WrongPrefix {
+                expected: "lni".to_owned(),
+                found: "lno".to_owned(),
+            })
+        );
+        // Offers only contain offer fields
+        assert_eq!(
+            INVOICE.replacen("lni", "lno", 1).parse::<Bolt12Offer>(),
+            Err(Bolt12Error::UnexpectedRecord(164))
+        );
+        assert_eq!(
+            OFFER.replacen('q', "Q", 1).parse::<Bolt12Offer>(),
+            Err(Bolt12Error::InvalidEncoding)
+        );
+        // Invoices requested for another offer of the same node don't match
+        let other_offer: Bolt12Offer = OTHER_OFFER.parse().unwrap();
+        let invoice: Bolt12Invoice = INVOICE.parse().unwrap();
+        assert_eq!(other_offer.node_id(), invoice.node_id());
+        assert!(!invoice.is_for_offer(&other_offer));
+    }
+
+    #[test]
+    fn rejects_invalid_tlv_streams() {
+        assert!(matches!(
+            parse_tlv_stream(&[0x02, 0x00, 0x01, 0x00]),
+            Err(Bolt12Error::UnorderedRecords)
+        ));
+        assert!(matches!(
+            parse_tlv_stream(&[0x01, 0x01, 0x00, 0x01, 0x00]),
+            Err(Bolt12Error::UnorderedRecords)
+        ));
+        assert!(matches!(
+            parse_tlv_stream(&[0x01, 0x05, 0x00]),
+            Err(Bolt12Error::Truncated)
+        ));
+        assert!(matches!(
+            parse_tlv_stream(&[0xfd, 0x00, 0x01, 0x00]),
+            Err(Bolt12Error::NonMinimalEncoding)
+        ));
+        assert_eq!(
+            read_tu64(&[0x00, 0x01]),
+            Err(Bolt12Error::NonMinimalEncoding)
+        );
+        assert_eq!(read_tu64(&[0x01, 0x00]), Ok(256));
+    }
+
+    #[test]
+    fn outgoing_invoice_detects_bolt12() {
+        let invoice: OutgoingInvoice = INVOICE.parse().unwrap();
+        assert!(matches!(invoice, OutgoingInvoice::Bolt12(_)));
+        assert_eq!(invoice.to_string(), INVOICE);
+    }
+}
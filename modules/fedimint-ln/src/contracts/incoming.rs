@@ -10,6 +10,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::contracts::{ContractId, DecryptedPreimage, EncryptedPreimage, IdentifyableContract};
 
+const RELEASE_TAG: &str = "incoming contract release";
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
 pub struct IncomingContractOffer {
     /// Amount for which the user is willing to sell the preimage
@@ -17,6 +19,26 @@ pub struct IncomingContractOffer {
     pub hash: bitcoin_hashes::sha256::Hash,
     pub encrypted_preimage: EncryptedPreimage,
     pub expiry_time: Option<u64>,
+    /// If set the federation only decrypts the preimage once the user
+    /// released it, see [`HoldTerms`]
+    pub hold: Option<HoldTerms>,
+}
+
+/// Terms of a held incoming payment (comparable to a HODL invoice)
+///
+/// Funding a contract created from a held offer does not start the preimage
+/// decryption right away. Instead the user has to release the preimage by
+/// signing the contract's [`IncomingContract::release_message`] with the
+/// release key, e.g. once the goods paid for have been delivered. If they
+/// don't do so before `timelock` the gateway can claim back its funds and
+/// fail the HTLC, refunding the payer.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct HoldTerms {
+    /// Key that has to sign the release of the preimage
+    pub release_key: secp256k1::XOnlyPublicKey,
+    /// Block height from which on the gateway can claim back the funds if the
+    /// preimage wasn't released yet
+    pub timelock: u32,
 }
 
 impl IncomingContractOffer {
@@ -66,7 +88,10 @@ pub struct IncomingContract {
     /// creator to redeem their money.
     pub decrypted_preimage: DecryptedPreimage,
     /// Key that can unlock contract in case the decrypted preimage was invalid
+    /// or it wasn't released before the hold timelock expired
     pub gateway_key: secp256k1::XOnlyPublicKey,
+    /// Hold terms as specified in offer
+    pub hold: Option<HoldTerms>,
 }
 
 /// The funded version of an [`IncomingContract`] contains the [`OutPoint`] of
@@ -82,6 +107,9 @@ pub struct FundedIncomingContract {
     /// already be queried by users, making an additional way of querying
     /// contract states unnecessary for now).
     pub out_point: OutPoint,
+    /// Set while the decryption of a held contract's preimage waits for the
+    /// user to release it
+    pub held: bool,
 }
 
 hash_newtype!(
@@ -97,6 +125,18 @@ impl IdentifyableContract for IncomingContract {
     }
 }
 
+impl IncomingContract {
+    /// Message the user has to sign with the release key of a held contract
+    /// to let the federation decrypt its preimage
+    pub fn release_message(&self) -> bitcoin_hashes::sha256::Hash {
+        let mut engine = bitcoin_hashes::sha256::Hash::engine();
+        Encodable::consensus_encode(&RELEASE_TAG.as_bytes(), &mut engine)
+            .expect("Hashing never fails");
+        Encodable::consensus_encode(&self.contract_id(), &mut engine).expect("Hashing never fails");
+        bitcoin_hashes::sha256::Hash::from_engine(engine)
+    }
+}
+
 impl Encodable for OfferId {
     fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, Error> {
         self.as_inner().consensus_encode(writer)
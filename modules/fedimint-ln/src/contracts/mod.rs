@@ -95,6 +95,7 @@ impl Contract {
             Contract::Account(account) => FundedContract::Account(account),
             Contract::Incoming(incoming) => {
                 FundedContract::Incoming(incoming::FundedIncomingContract {
+                    held: incoming.hold.is_some(),
                     contract: incoming,
                     out_point,
                 })
@@ -1,10 +1,18 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Error, Read, Write};
+use std::str::FromStr;
+
 use bitcoin_hashes::Hash as BitcoinHash;
-use fedimint_api::encoding::{Decodable, Encodable};
-use serde::{Deserialize, Serialize};
+use fedimint_api::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::bolt12::Bolt12Invoice;
 use crate::contracts::{ContractId, IdentifyableContract};
 
 const CANCELLATION_TAG: &str = "outgoing contract cancellation";
+/// Human readable part of BOLT12 invoices followed by the bech32 separator
+const BOLT12_INVOICE_PREFIX: &str = "lni1";
 
 /// Specialized smart contract for outgoing payments.
 ///
@@ -26,7 +34,7 @@ pub struct OutgoingContract {
     pub user_key: secp256k1::XOnlyPublicKey,
     // FIXME: use pruned, privacy friendly version without description etc.
     /// Invoice containing metadata on how to obtain the preimage
-    pub invoice: lightning_invoice::Invoice,
+    pub invoice: OutgoingInvoice,
     /// Flag that can be set by the gateway and allows the client to claim an
     /// early refund
     pub cancelled: bool,
@@ -53,3 +61,151 @@ impl OutgoingContract {
         bitcoin_hashes::sha256::Hash::from_engine(engine)
     }
 }
+
+/// Invoice the gateway has to pay to claim an [`OutgoingContract`]
+///
+/// It is encoded as the invoice string, which keeps the encoding (and thus the
+/// contract id) of contracts for BOLT11 invoices the same as before BOLT12
+/// invoices were supported.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum OutgoingInvoice {
+    Bolt11(lightning_invoice::Invoice),
+    /// Invoice fetched for a BOLT12 offer
+    Bolt12(Bolt12Invoice),
+}
+
+impl OutgoingInvoice {
+    pub fn payment_hash(&self) -> bitcoin_hashes::sha256::Hash {
+        match self {
+            OutgoingInvoice::Bolt11(invoice) => *invoice.payment_hash(),
+            OutgoingInvoice::Bolt12(invoice) => invoice.payment_hash(),
+        }
+    }
+
+    /// Amount to be paid, `None` for BOLT11 invoices without an amount
+    pub fn amount_milli_satoshis(&self) -> Option<u64> {
+        match self {
+            OutgoingInvoice::Bolt11(invoice) => invoice.amount_milli_satoshis(),
+            OutgoingInvoice::Bolt12(invoice) => Some(invoice.amount_msats()),
+        }
+    }
+
+    /// Whether the payee accepts payments split into multiple parts
+    pub fn supports_basic_mpp(&self) -> bool {
+        match self {
+            OutgoingInvoice::Bolt11(invoice) => invoice
+                .features()
+                .map_or(false, |features| features.supports_basic_mpp()),
+            OutgoingInvoice::Bolt12(invoice) => invoice.supports_basic_mpp(),
+        }
+    }
+}
+
+impl From<lightning_invoice::Invoice> for OutgoingInvoice {
+    fn from(invoice: lightning_invoice::Invoice) -> Self {
+        OutgoingInvoice::Bolt11(invoice)
+    }
+}
+
+impl From<Bolt12Invoice> for OutgoingInvoice {
+    fn from(invoice: Bolt12Invoice) -> Self {
+        OutgoingInvoice::Bolt12(invoice)
+    }
+}
+
+impl Display for OutgoingInvoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutgoingInvoice::Bolt11(invoice) => Display::fmt(invoice, f),
+            OutgoingInvoice::Bolt12(invoice) => Display::fmt(invoice, f),
+        }
+    }
+}
+
+impl FromStr for OutgoingInvoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is_bolt12 = s
+            .get(..BOLT12_INVOICE_PREFIX.len())
+            .map_or(false, |prefix| {
+                prefix.eq_ignore_ascii_case(BOLT12_INVOICE_PREFIX)
+            });
+        if is_bolt12 {
+            Ok(OutgoingInvoice::Bolt12(s.parse()?))
+        } else {
+            Ok(OutgoingInvoice::Bolt11(s.parse()?))
+        }
+    }
+}
+
+impl Encodable for OutgoingInvoice {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+        self.to_string().consensus_encode(writer)
+    }
+}
+
+impl Decodable for OutgoingInvoice {
+    fn consensus_decode<D: Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        String::consensus_decode(d, modules)?
+            .parse()
+            .map_err(DecodeError::new_custom)
+    }
+}
+
+impl Serialize for OutgoingInvoice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for OutgoingInvoice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use fedimint_api::encoding::{Decodable, Encodable};
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+
+    use super::OutgoingInvoice;
+
+    #[test]
+    fn bolt11_encoding_is_unchanged() {
+        let invoice: lightning_invoice::Invoice =
+            "lnbc100p1psj9jhxdqud3jxktt5w46x7unfv9kz6mn0v3jsnp4q0d3p2sfluzdx45tqcs\
+h2pu5qc7lgq0xs578ngs6s0s68ua4h7cvspp5q6rmq35js88zp5dvwrv9m459tnk2zunwj5jalqtyxqulh0l\
+5gflssp5nf55ny5gcrfl30xuhzj3nphgj27rstekmr9fw3ny5989s300gyus9qyysgqcqpcrzjqw2sxwe993\
+h5pcm4dxzpvttgza8zhkqxpgffcrf5v25nwpr3cmfg7z54kuqq8rgqqqqqqqq2qqqqq9qq9qrzjqd0ylaqcl\
+j9424x9m8h2vcukcgnm6s56xfgu3j78zyqzhgs4hlpzvznlugqq9vsqqqqqqqlgqqqqqeqq9qrzjqwldmj9d\
+ha74df76zhx6l9we0vjdquygcdt3kssupehe64g6yyp5yz5rhuqqwccqqyqqqqlgqqqqjcqq9qrzjqf9e58a\
+guqr0rcun0ajlvmzq3ek63cw2w282gv3z5uupmuwvgjtq2z55qsqqg6qqqyqqqrtnqqqzq3cqygrzjqvphms\
+ywntrrhqjcraumvc4y6r8v4z5v593trte429v4hredj7ms5z52usqq9ngqqqqqqqlgqqqqqqgq9qrzjq2v0v\
+p62g49p7569ev48cmulecsxe59lvaw3wlxm7r982zxa9zzj7z5l0cqqxusqqyqqqqlgqqqqqzsqygarl9fh3\
+8s0gyuxjjgux34w75dnc6xp2l35j7es3jd4ugt3lu0xzre26yg5m7ke54n2d5sym4xcmxtl8238xxvw5h5h5\
+j5r6drg6k6zcqj0fcwg"
+                .parse()
+                .unwrap();
+        let legacy_encoding = invoice.consensus_encode_to_vec().unwrap();
+
+        let outgoing = OutgoingInvoice::from(invoice);
+        assert_eq!(outgoing.consensus_encode_to_vec().unwrap(), legacy_encoding);
+        assert_eq!(
+            OutgoingInvoice::consensus_decode(
+                &mut Cursor::new(legacy_encoding),
+                &ModuleDecoderRegistry::default()
+            )
+            .unwrap(),
+            outgoing
+        );
+    }
+}
@@ -1,12 +1,25 @@
+use fedimint_api::db::{migrate_values, DatabaseTransaction};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::impl_db_prefix_const;
+use fedimint_api::time::SystemTime;
 use fedimint_api::{OutPoint, PeerId};
+use futures::future::BoxFuture;
 use secp256k1::PublicKey;
 use serde::Serialize;
 use strum_macros::EnumIter;
+use url::Url;
 
-use crate::contracts::{incoming::IncomingContractOffer, ContractId, PreimageDecryptionShare};
-use crate::{ContractAccount, LightningGateway, LightningOutputOutcome};
+use crate::contracts::account::AccountContract;
+use crate::contracts::incoming::{FundedIncomingContract, IncomingContract, IncomingContractOffer};
+use crate::contracts::outgoing::OutgoingContract;
+use crate::contracts::{
+    ContractId, DecryptedPreimage, EncryptedPreimage, FundedContract, PreimageDecryptionShare,
+};
+use crate::route_hints::RouteHint;
+use crate::{
+    ContractAccount, GatewayFees, GatewayLimits, GatewayLiquidity, LightningGateway,
+    LightningOutputOutcome,
+};
 
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
@@ -106,3 +119,139 @@ impl_db_prefix_const!(
     prefix = DbKeyPrefix::LightningGateway,
     key_prefix = LightningGatewayKeyPrefix
 );
+
+/// Encoding of [`LightningGateway`] in database version 0
+#[derive(Debug, Encodable, Decodable)]
+pub struct LightningGatewayV0 {
+    pub mint_channel_id: u64,
+    pub mint_pub_key: secp256k1::XOnlyPublicKey,
+    pub node_pub_key: PublicKey,
+    pub api: Url,
+    pub route_hints: Vec<RouteHint>,
+    pub valid_until: SystemTime,
+}
+
+/// Adds routing fees, limits and liquidity to the gateway registrations. They
+/// get the same defaults as registrations omitting them in the API, until the
+/// gateways refresh their registration.
+pub fn migrate_to_v1<'r, 'tx>(
+    dbtx: &'r mut DatabaseTransaction<'tx>,
+) -> BoxFuture<'r, anyhow::Result<()>> {
+    Box::pin(async move {
+        migrate_values::<LightningGatewayKeyPrefix, LightningGatewayV0>(dbtx, |old| {
+            Ok(LightningGateway {
+                mint_channel_id: old.mint_channel_id,
+                mint_pub_key: old.mint_pub_key,
+                node_pub_key: old.node_pub_key,
+                api: old.api,
+                route_hints: old.route_hints,
+                fees: GatewayFees::default(),
+                limits: GatewayLimits::default(),
+                liquidity: GatewayLiquidity::default(),
+                valid_until: old.valid_until,
+            })
+        })
+        .await
+    })
+}
+
+/// Encoding of [`IncomingContractOffer`] in database version 1
+#[derive(Debug, Encodable, Decodable)]
+pub struct IncomingContractOfferV1 {
+    pub amount: fedimint_api::Amount,
+    pub hash: bitcoin_hashes::sha256::Hash,
+    pub encrypted_preimage: EncryptedPreimage,
+    pub expiry_time: Option<u64>,
+}
+
+/// Encoding of [`ContractAccount`] in database version 1
+#[derive(Debug, Encodable, Decodable)]
+pub struct ContractAccountV1 {
+    pub amount: fedimint_api::Amount,
+    pub contract: FundedContractV1,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Encodable, Decodable)]
+pub enum FundedContractV1 {
+    Account(AccountContract),
+    Incoming(FundedIncomingContractV1),
+    Outgoing(OutgoingContractV1),
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct FundedIncomingContractV1 {
+    pub contract: IncomingContractV1,
+    pub out_point: OutPoint,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct IncomingContractV1 {
+    pub hash: bitcoin_hashes::sha256::Hash,
+    pub encrypted_preimage: EncryptedPreimage,
+    pub decrypted_preimage: DecryptedPreimage,
+    pub gateway_key: secp256k1::XOnlyPublicKey,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OutgoingContractV1 {
+    pub hash: bitcoin_hashes::sha256::Hash,
+    pub gateway_key: secp256k1::XOnlyPublicKey,
+    pub timelock: u32,
+    pub user_key: secp256k1::XOnlyPublicKey,
+    pub invoice: lightning_invoice::Invoice,
+    pub cancelled: bool,
+}
+
+/// Adds the hold terms to offers and incoming contracts, all of them were
+/// created before payments could be held
+pub fn migrate_to_v2<'r, 'tx>(
+    dbtx: &'r mut DatabaseTransaction<'tx>,
+) -> BoxFuture<'r, anyhow::Result<()>> {
+    Box::pin(async move {
+        migrate_values::<OfferKeyPrefix, IncomingContractOfferV1>(dbtx, |old| {
+            Ok(IncomingContractOffer {
+                amount: old.amount,
+                hash: old.hash,
+                encrypted_preimage: old.encrypted_preimage,
+                expiry_time: old.expiry_time,
+                hold: None,
+            })
+        })
+        .await?;
+
+        migrate_values::<ContractKeyPrefix, ContractAccountV1>(dbtx, |old| {
+            let contract = match old.contract {
+                FundedContractV1::Account(account) => FundedContract::Account(account),
+                FundedContractV1::Incoming(incoming) => {
+                    FundedContract::Incoming(FundedIncomingContract {
+                        contract: IncomingContract {
+                            hash: incoming.contract.hash,
+                            encrypted_preimage: incoming.contract.encrypted_preimage,
+                            decrypted_preimage: incoming.contract.decrypted_preimage,
+                            gateway_key: incoming.contract.gateway_key,
+                            hold: None,
+                        },
+                        out_point: incoming.out_point,
+                        held: false,
+                    })
+                }
+                FundedContractV1::Outgoing(outgoing) => {
+                    FundedContract::Outgoing(OutgoingContract {
+                        hash: outgoing.hash,
+                        gateway_key: outgoing.gateway_key,
+                        timelock: outgoing.timelock,
+                        user_key: outgoing.user_key,
+                        invoice: outgoing.invoice.into(),
+                        cancelled: outgoing.cancelled,
+                    })
+                }
+            };
+            Ok(ContractAccount {
+                amount: old.amount,
+                contract,
+            })
+        })
+        .await
+    })
+}
@@ -10,6 +10,7 @@
 
 extern crate core;
 
+pub mod bolt12;
 pub mod common;
 pub mod config;
 pub mod contracts;
@@ -28,7 +29,9 @@ use fedimint_api::config::{
 };
 use fedimint_api::config::{ModuleConfigResponse, TypedServerModuleConsensusConfig};
 use fedimint_api::core::{ModuleInstanceId, ModuleKind, LEGACY_HARDCODED_INSTANCE_ID_WALLET};
-use fedimint_api::db::{Database, DatabaseTransaction};
+use fedimint_api::db::{
+    Database, DatabaseMigration, DatabaseTransaction, DatabaseVersion, InvalidDbEntry, MigrationMap,
+};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::module::audit::Audit;
 use fedimint_api::module::interconnect::ModuleInterconect;
@@ -59,14 +62,14 @@ use crate::config::{
     LightningClientConfig, LightningConfig, LightningConfigConsensus, LightningConfigPrivate,
 };
 use crate::contracts::{
-    incoming::{IncomingContractOffer, OfferId},
+    incoming::{IncomingContract, IncomingContractOffer, OfferId},
     Contract, ContractId, ContractOutcome, DecryptedPreimage, EncryptedPreimage, FundedContract,
     IdentifyableContract, Preimage, PreimageDecryptionShare,
 };
 use crate::db::{
-    AgreedDecryptionShareKey, AgreedDecryptionShareKeyPrefix, ContractKey, ContractKeyPrefix,
-    ContractUpdateKey, ContractUpdateKeyPrefix, OfferKey, OfferKeyPrefix,
-    ProposeDecryptionShareKey, ProposeDecryptionShareKeyPrefix,
+    migrate_to_v1, migrate_to_v2, AgreedDecryptionShareKey, AgreedDecryptionShareKeyPrefix,
+    ContractKey, ContractKeyPrefix, ContractUpdateKey, ContractUpdateKeyPrefix, OfferKey,
+    OfferKeyPrefix, ProposeDecryptionShareKey, ProposeDecryptionShareKeyPrefix,
 };
 
 const KIND: ModuleKind = ModuleKind::from_static_str("ln");
@@ -123,10 +126,11 @@ impl std::fmt::Display for LightningInput {
 
 /// Represents an output of the Lightning module.
 ///
-/// There are three sub-types:
+/// There are four sub-types:
 ///   * Normal contracts users may lock funds in
 ///   * Offers to buy preimages (see `contracts::incoming` docs)
 ///   * Early cancellation of outgoing contracts before their timeout
+///   * Release of the preimage of held incoming contracts
 ///
 /// The offer type exists to register `IncomingContractOffer`s. Instead of
 /// patching in a second way of letting clients submit consensus items outside
@@ -146,6 +150,13 @@ pub enum LightningOutput {
         /// Signature of gateway
         gateway_signature: secp256k1::schnorr::Signature,
     },
+    /// Allow decryption of a held incoming contract's preimage
+    ReleaseIncoming {
+        /// Contract to update
+        contract: ContractId,
+        /// Signature of the hold terms' release key
+        release_signature: secp256k1::schnorr::Signature,
+    },
 }
 
 impl std::fmt::Display for LightningOutput {
@@ -176,6 +187,9 @@ impl std::fmt::Display for LightningOutput {
             LightningOutput::CancelOutgoing { contract, .. } => {
                 write!(f, "LN outgoing contract cancellation {contract}")
             }
+            LightningOutput::ReleaseIncoming { contract, .. } => {
+                write!(f, "LN incoming contract release {contract}")
+            }
         }
     }
 }
@@ -231,10 +245,135 @@ pub struct LightningGateway {
     /// These will be appended with the route hint of the recipient's virtual
     /// channel. To keeps invoices small these should be used sparingly.
     pub route_hints: Vec<route_hints::RouteHint>,
-    /// Limits the validity of the announcement to allow updates
+    /// Fees the gateway charges for routing payments of this federation
+    #[serde(default)]
+    pub fees: GatewayFees,
+    /// Payment sizes the gateway is willing to route for this federation
+    #[serde(default)]
+    pub limits: GatewayLimits,
+    /// Liquidity the gateway's LN node had available when it last refreshed
+    /// the announcement
+    #[serde(default)]
+    pub liquidity: GatewayLiquidity,
+    /// Limits the validity of the announcement to allow updates. Gateways
+    /// refresh their announcement well before it expires, so an expired one
+    /// means the gateway is offline.
     pub valid_until: SystemTime,
 }
 
+/// Fees a gateway charges on top of the invoice amount for routing a payment
+#[derive(
+    Debug, Default, Clone, Copy, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash,
+)]
+pub struct GatewayFees {
+    /// Flat fee charged per payment in msat
+    pub base_msat: u32,
+    /// Fee proportional to the payment amount in millionths. In other words,
+    /// 10000 is 1%.
+    pub proportional_millionths: u32,
+}
+
+impl GatewayFees {
+    /// Fee the gateway charges for routing a payment of `amount`
+    pub fn fee(&self, amount: Amount) -> Amount {
+        let proportional_msat =
+            (amount.msats as u128 * self.proportional_millionths as u128 / 1_000_000) as u64;
+        Amount::from_msats(self.base_msat as u64 + proportional_msat)
+    }
+}
+
+/// Bounds on the payments a gateway is willing to route
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GatewayLimits {
+    /// Smallest invoice amount the gateway will pay or receive
+    pub min_payment: Amount,
+    /// Largest invoice amount the gateway will pay or receive, unbounded if
+    /// `None`
+    pub max_payment: Option<Amount>,
+    /// Largest total amount of outgoing payments the gateway processes at the
+    /// same time, unbounded if `None`
+    pub max_in_flight: Option<Amount>,
+}
+
+impl GatewayLimits {
+    /// Returns true if a single payment of `amount` is within the limits
+    pub fn allows_payment(&self, amount: Amount) -> bool {
+        self.min_payment <= amount && self.max_payment.map_or(true, |max| amount <= max)
+    }
+}
+
+/// Liquidity a gateway advertises for routing payments in both directions.
+///
+/// Balances are only advertised as ranges bounded by powers of two so the
+/// announcement doesn't reveal the exact state of the gateway's channels.
+#[derive(
+    Debug, Default, Clone, Copy, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash,
+)]
+pub struct GatewayLiquidity {
+    /// Amount the gateway can receive over LN on behalf of users, unknown if
+    /// `None`
+    pub inbound: Option<LiquidityRange>,
+    /// Amount the gateway can send over LN on behalf of users, unknown if
+    /// `None`
+    pub outbound: Option<LiquidityRange>,
+}
+
+impl GatewayLiquidity {
+    pub fn from_balances(inbound: Amount, outbound: Amount) -> GatewayLiquidity {
+        GatewayLiquidity {
+            inbound: Some(LiquidityRange::from_balance(inbound)),
+            outbound: Some(LiquidityRange::from_balance(outbound)),
+        }
+    }
+
+    /// Returns false if the gateway certainly can't pay an invoice of
+    /// `amount`
+    pub fn can_send(&self, amount: Amount) -> bool {
+        self.outbound.map_or(true, |range| amount <= range.max)
+    }
+
+    /// Returns false if the gateway certainly can't receive a payment of
+    /// `amount`
+    pub fn can_receive(&self, amount: Amount) -> bool {
+        self.inbound.map_or(true, |range| amount <= range.max)
+    }
+}
+
+/// Range the actual liquidity lies in
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct LiquidityRange {
+    pub min: Amount,
+    pub max: Amount,
+}
+
+impl LiquidityRange {
+    /// Rounds `balance` to the enclosing range `[2^n, 2^(n+1))` msat
+    pub fn from_balance(balance: Amount) -> LiquidityRange {
+        if balance.msats == 0 {
+            return LiquidityRange {
+                min: Amount::ZERO,
+                max: Amount::ZERO,
+            };
+        }
+
+        let min = 1u64 << (63 - balance.msats.leading_zeros());
+        LiquidityRange {
+            min: Amount::from_msats(min),
+            max: Amount::from_msats(min.saturating_mul(2) - 1),
+        }
+    }
+}
+
+impl Default for GatewayLimits {
+    fn default() -> Self {
+        GatewayLimits {
+            min_payment: Amount::ZERO,
+            max_payment: None,
+            max_in_flight: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Encodable, Decodable, Serialize, Deserialize)]
 pub struct LightningConsensusItem {
     pub contract_id: ContractId,
@@ -266,6 +405,17 @@ impl ModuleGen for LightningGen {
         &[ModuleConsensusVersion(0)]
     }
 
+    fn database_version(&self) -> DatabaseVersion {
+        DatabaseVersion(2)
+    }
+
+    fn get_database_migrations(&self) -> MigrationMap {
+        MigrationMap::from([
+            (DatabaseVersion(0), migrate_to_v1 as DatabaseMigration),
+            (DatabaseVersion(1), migrate_to_v2 as DatabaseMigration),
+        ])
+    }
+
     async fn init(
         &self,
         cfg: ServerModuleConfig,
@@ -382,6 +532,11 @@ impl ModuleGen for LightningGen {
         serde_json::from_value::<LightningClientConfig>(config)?.consensus_hash()
     }
 
+    fn validate_client_config(&self, config: serde_json::Value) -> anyhow::Result<()> {
+        serde_json::from_value::<LightningClientConfig>(config)?;
+        Ok(())
+    }
+
     async fn dump_database(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -459,6 +614,32 @@ impl ModuleGen for LightningGen {
 
         Box::new(lightning.into_iter())
     }
+
+    async fn check_database(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<InvalidDbEntry> {
+        let known_prefixes = DbKeyPrefix::iter().map(|p| p as u8).collect::<Vec<_>>();
+        let mut invalid = dbtx.find_orphaned_entries(&known_prefixes).await;
+        for table in DbKeyPrefix::iter() {
+            invalid.extend(match table {
+                DbKeyPrefix::Contract => dbtx.find_undecodable_entries::<ContractKey>().await,
+                DbKeyPrefix::Offer => dbtx.find_undecodable_entries::<OfferKey>().await,
+                DbKeyPrefix::ProposeDecryptionShare => {
+                    dbtx.find_undecodable_entries::<ProposeDecryptionShareKey>()
+                        .await
+                }
+                DbKeyPrefix::AgreedDecryptionShare => {
+                    dbtx.find_undecodable_entries::<AgreedDecryptionShareKey>()
+                        .await
+                }
+                DbKeyPrefix::ContractUpdate => {
+                    dbtx.find_undecodable_entries::<ContractUpdateKey>().await
+                }
+                DbKeyPrefix::LightningGateway => {
+                    dbtx.find_undecodable_entries::<LightningGatewayKey>().await
+                }
+            });
+        }
+        invalid
+    }
 }
 
 #[async_trait]
@@ -572,21 +753,34 @@ impl ServerModule for Lightning {
                 }
             }
             FundedContract::Account(acc_contract) => acc_contract.key,
-            FundedContract::Incoming(incoming) => match incoming.contract.decrypted_preimage {
-                // Once the preimage has been decrypted …
-                DecryptedPreimage::Pending => {
-                    return Err(LightningError::ContractNotReady).into_module_error_other();
-                }
-                // … either the user may spend the funds since they sold a valid preimage …
-                DecryptedPreimage::Some(preimage) => match preimage.to_public_key() {
-                    Ok(pub_key) => pub_key,
-                    Err(_) => {
-                        return Err(LightningError::InvalidPreimage).into_module_error_other()
+            FundedContract::Incoming(incoming) => {
+                let hold_expired = match &incoming.contract.hold {
+                    Some(hold) if incoming.held => {
+                        hold.timelock <= block_height(interconnect).await
                     }
-                },
-                // … or the gateway may claim back funds for not receiving the advertised preimage.
-                DecryptedPreimage::Invalid => incoming.contract.gateway_key,
-            },
+                    _ => false,
+                };
+
+                match incoming.contract.decrypted_preimage {
+                    // If the user didn't release the preimage of a held contract in time the
+                    // gateway may claim back its funds, …
+                    DecryptedPreimage::Pending if hold_expired => incoming.contract.gateway_key,
+                    // … otherwise once the preimage has been decrypted …
+                    DecryptedPreimage::Pending => {
+                        return Err(LightningError::ContractNotReady).into_module_error_other();
+                    }
+                    // … either the user may spend the funds since they sold a valid preimage …
+                    DecryptedPreimage::Some(preimage) => match preimage.to_public_key() {
+                        Ok(pub_key) => pub_key,
+                        Err(_) => {
+                            return Err(LightningError::InvalidPreimage).into_module_error_other()
+                        }
+                    },
+                    // … or the gateway may claim back funds for not receiving the advertised
+                    // preimage.
+                    DecryptedPreimage::Invalid => incoming.contract.gateway_key,
+                }
+            }
         };
 
         Ok(InputMeta {
@@ -647,6 +841,10 @@ impl ServerModule for Lightning {
                         ))
                         .into_module_error_other();
                     }
+
+                    if incoming.hold != offer.hold {
+                        return Err(LightningError::HoldTermsMismatch).into_module_error_other();
+                    }
                 }
 
                 if contract.amount == Amount::ZERO {
@@ -694,11 +892,47 @@ impl ServerModule for Lightning {
 
                 Ok(TransactionItemAmount::ZERO)
             }
+            LightningOutput::ReleaseIncoming {
+                contract,
+                release_signature,
+            } => {
+                let contract_account = dbtx
+                    .get_value(&ContractKey(*contract))
+                    .await
+                    .expect("DB error")
+                    .ok_or(LightningError::UnknownContract(*contract))
+                    .into_module_error_other()?;
+
+                let incoming_contract = match &contract_account.contract {
+                    FundedContract::Incoming(incoming) if incoming.held => incoming,
+                    _ => {
+                        return Err(LightningError::NotHeldIncomingContract)
+                            .into_module_error_other();
+                    }
+                };
+                let hold = incoming_contract
+                    .contract
+                    .hold
+                    .as_ref()
+                    .expect("Only contracts with hold terms are held");
+
+                secp256k1::global::SECP256K1
+                    .verify_schnorr(
+                        release_signature,
+                        &incoming_contract.contract.release_message().into(),
+                        &hold.release_key,
+                    )
+                    .map_err(|_| LightningError::InvalidReleaseSignature)
+                    .into_module_error_other()?;
+
+                Ok(TransactionItemAmount::ZERO)
+            }
         }
     }
 
     async fn apply_output<'a, 'b>(
         &'a self,
+        interconnect: &'a dyn ModuleInterconect,
         dbtx: &mut DatabaseTransaction<'b>,
         output: &'a LightningOutput,
         out_point: OutPoint,
@@ -741,18 +975,10 @@ impl ServerModule for Lightning {
                         .expect("DB error")
                         .expect("offer exists if output is valid");
 
-                    let decryption_share = self
-                        .cfg
-                        .private
-                        .threshold_sec_key
-                        .decrypt_share(&incoming.encrypted_preimage.0)
-                        .expect("We checked for decryption share validity on contract creation");
-                    dbtx.insert_new_entry(
-                        &ProposeDecryptionShareKey(contract.contract.contract_id()),
-                        &PreimageDecryptionShare(decryption_share),
-                    )
-                    .await
-                    .expect("DB Error");
+                    // Decryption of held contracts only starts once they get released
+                    if incoming.hold.is_none() {
+                        self.propose_decryption_share(dbtx, incoming).await;
+                    }
                     dbtx.remove_entry(&OfferKey(offer.hash))
                         .await
                         .expect("DB Error");
@@ -794,6 +1020,40 @@ impl ServerModule for Lightning {
                     .await
                     .expect("DB Error");
             }
+            LightningOutput::ReleaseIncoming { contract, .. } => {
+                let mut contract_account = dbtx
+                    .get_value(&ContractKey(*contract))
+                    .await
+                    .expect("DB error")
+                    .expect("Contract exists if output is valid");
+
+                let incoming_contract = match &mut contract_account.contract {
+                    FundedContract::Incoming(incoming) => incoming,
+                    _ => {
+                        panic!("Contract type was checked in validate_output");
+                    }
+                };
+
+                // After the timelock the gateway may already have failed the HTLC, so the
+                // preimage must not be revealed anymore
+                let timelock = incoming_contract
+                    .contract
+                    .hold
+                    .as_ref()
+                    .expect("Only contracts with hold terms are held")
+                    .timelock;
+                if timelock <= block_height(interconnect).await {
+                    return Err(LightningError::HoldExpired).into_module_error_other();
+                }
+
+                incoming_contract.held = false;
+                self.propose_decryption_share(dbtx, &incoming_contract.contract)
+                    .await;
+
+                dbtx.insert_entry(&ContractKey(*contract), &contract_account)
+                    .await
+                    .expect("DB Error");
+            }
         }
 
         Ok(amount)
@@ -1044,6 +1304,27 @@ impl Lightning {
             .verify_decryption_share(&share.0, &message.0)
     }
 
+    /// Starts the threshold decryption of an incoming contract's preimage by
+    /// proposing our decryption share in the next consensus round
+    async fn propose_decryption_share(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        incoming: &IncomingContract,
+    ) {
+        let decryption_share = self
+            .cfg
+            .private
+            .threshold_sec_key
+            .decrypt_share(&incoming.encrypted_preimage.0)
+            .expect("We checked for decryption share validity on contract creation");
+        dbtx.insert_new_entry(
+            &ProposeDecryptionShareKey(incoming.contract_id()),
+            &PreimageDecryptionShare(decryption_share),
+        )
+        .await
+        .expect("DB Error");
+    }
+
     pub async fn get_offer(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -1080,7 +1361,7 @@ impl Lightning {
         stream
             .filter_map(|res| async {
                 let gw = res.expect("DB error").1;
-                // FIXME: actually remove from DB
+                // Expired announcements are removed on the next registration
                 if gw.valid_until > SystemTime::now() {
                     Some(gw)
                 } else {
@@ -1096,6 +1377,22 @@ impl Lightning {
         dbtx: &mut DatabaseTransaction<'_>,
         gateway: LightningGateway,
     ) {
+        // Gateways that stopped refreshing their announcement are offline, so we
+        // forget about them
+        let now = SystemTime::now();
+        let expired = dbtx
+            .find_by_prefix(&LightningGatewayKeyPrefix)
+            .await
+            .filter_map(|res| async {
+                let (key, gw) = res.expect("DB error");
+                (gw.valid_until <= now).then_some(key)
+            })
+            .collect::<Vec<LightningGatewayKey>>()
+            .await;
+        for key in expired {
+            dbtx.remove_entry(&key).await.expect("DB error");
+        }
+
         dbtx.insert_entry(&LightningGatewayKey(gateway.node_pub_key), &gateway)
             .await
             .expect("DB error");
@@ -1206,4 +1503,12 @@ pub enum LightningError {
     NotOutgoingContract,
     #[error("Cancellation request wasn't properly signed")]
     InvalidCancellationSignature,
+    #[error("Incoming contract doesn't match the hold terms of its offer")]
+    HoldTermsMismatch,
+    #[error("Only held incoming contracts can be released")]
+    NotHeldIncomingContract,
+    #[error("The hold timelock of the incoming contract already expired")]
+    HoldExpired,
+    #[error("Release request wasn't properly signed")]
+    InvalidReleaseSignature,
 }
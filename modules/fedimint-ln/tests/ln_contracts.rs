@@ -5,7 +5,7 @@ use fedimint_api::core::LEGACY_HARDCODED_INSTANCE_ID_LN;
 use fedimint_api::{Amount, OutPoint};
 use fedimint_ln::config::LightningClientConfig;
 use fedimint_ln::contracts::account::AccountContract;
-use fedimint_ln::contracts::incoming::{IncomingContract, IncomingContractOffer};
+use fedimint_ln::contracts::incoming::{HoldTerms, IncomingContract, IncomingContractOffer};
 use fedimint_ln::contracts::outgoing::OutgoingContract;
 use fedimint_ln::contracts::{
     AccountContractOutcome, Contract, ContractOutcome, DecryptedPreimage, EncryptedPreimage,
@@ -110,7 +110,7 @@ j5r6drg6k6zcqj0fcwg"
         gateway_key: gw_pk,
         timelock: 42,
         user_key: user_pk,
-        invoice,
+        invoice: invoice.into(),
         cancelled: false,
     });
 
@@ -201,6 +201,7 @@ async fn test_incoming() {
                 .threshold_pub_key,
         ),
         expiry_time: None,
+        hold: None,
     };
     let offer_output = LightningOutput::Offer(offer.clone());
     let offer_out_point = OutPoint {
@@ -229,6 +230,7 @@ async fn test_incoming() {
         decrypted_preimage: DecryptedPreimage::Pending, /* TODO: check what happens if this is
                                                          * not pending */
         gateway_key: gw_pk,
+        hold: None,
     });
     let incoming_output = LightningOutput::Contract(ContractOutput {
         amount: Amount::from_sats(42),
@@ -278,3 +280,145 @@ async fn test_incoming() {
 
     // TODO: test faulty encrypted preimage
 }
+
+#[test_log::test(tokio::test)]
+async fn test_held_incoming() {
+    let mut rng = secp256k1::rand::rngs::OsRng;
+
+    let mut fed = FakeFed::<Lightning>::new(
+        4,
+        |cfg, _db| async move { Ok(Lightning::new(cfg.to_typed()?)) },
+        &ConfigGenParams::new(),
+        &LightningGen,
+        LEGACY_HARDCODED_INSTANCE_ID_LN,
+    )
+    .await
+    .unwrap();
+
+    let ctx = secp256k1::Secp256k1::new();
+    let gw_pk = KeyPair::new(&ctx, &mut rng).x_only_public_key().0;
+    let user_pk = KeyPair::new(&ctx, &mut rng).x_only_public_key().0;
+    let release_kp = KeyPair::new(&ctx, &mut rng);
+
+    let preimage = Preimage(user_pk.serialize());
+    let hash = secp256k1::hashes::sha256::Hash::hash(&preimage.0);
+    let hold = HoldTerms {
+        release_key: release_kp.x_only_public_key().0,
+        timelock: 42,
+    };
+
+    let offer = IncomingContractOffer {
+        amount: Amount::from_sats(42),
+        hash,
+        encrypted_preimage: EncryptedPreimage::new(
+            preimage.clone(),
+            &fed.client_cfg_typed::<LightningClientConfig>()
+                .unwrap()
+                .threshold_pub_key,
+        ),
+        expiry_time: None,
+        hold: Some(hold.clone()),
+    };
+    let offer_out_point = OutPoint {
+        txid: sha256::Hash::hash(b"").into(),
+        out_idx: 0,
+    };
+    let offer_output = LightningOutput::Offer(offer.clone());
+    fed.consensus_round(&[], &[(offer_out_point, offer_output)])
+        .await;
+
+    let incoming = IncomingContract {
+        hash,
+        encrypted_preimage: offer.encrypted_preimage,
+        decrypted_preimage: DecryptedPreimage::Pending,
+        gateway_key: gw_pk,
+        hold: Some(hold),
+    };
+
+    // The gateway may not strip the hold terms from the contract
+    let unheld_output = LightningOutput::Contract(ContractOutput {
+        amount: Amount::from_sats(42),
+        contract: Contract::Incoming(IncomingContract {
+            hold: None,
+            ..incoming.clone()
+        }),
+    });
+    assert!(!fed.verify_output(&unheld_output).await);
+
+    let incoming_output = LightningOutput::Contract(ContractOutput {
+        amount: Amount::from_sats(42),
+        contract: Contract::Incoming(incoming.clone()),
+    });
+    let incoming_out_point = OutPoint {
+        txid: sha256::Hash::hash(b"").into(),
+        out_idx: 1,
+    };
+    fed.consensus_round(&[], &[(incoming_out_point, incoming_output)])
+        .await;
+
+    // Decryption doesn't start before the preimage is released
+    fed.consensus_round(&[], &[]).await;
+    match fed.output_outcome(incoming_out_point).await.unwrap() {
+        LightningOutputOutcome::Contract { outcome, .. } => {
+            assert_eq!(
+                outcome,
+                ContractOutcome::Incoming(DecryptedPreimage::Pending)
+            );
+        }
+        _ => panic!(),
+    };
+
+    let incoming_input = LightningInput {
+        contract_id: incoming.contract_id(),
+        amount: Amount::from_sats(42),
+        witness: None,
+    };
+    fed.set_block_height(0);
+    let error = fed.verify_input(&incoming_input).await.unwrap_err();
+    assert_eq!(
+        format!("{error}"),
+        format!("{}", LightningError::ContractNotReady)
+    );
+
+    // After the timelock the gateway could claim back its funds
+    fed.set_block_height(42);
+    let meta = fed.verify_input(&incoming_input).await.unwrap();
+    assert_eq!(meta.keys, vec![gw_pk]);
+
+    // Instead the user releases the preimage just in time
+    fed.set_block_height(41);
+
+    let invalid_release = LightningOutput::ReleaseIncoming {
+        contract: incoming.contract_id(),
+        release_signature: ctx.sign_schnorr(
+            &incoming.release_message().into(),
+            &KeyPair::new(&ctx, &mut rng),
+        ),
+    };
+    assert!(!fed.verify_output(&invalid_release).await);
+
+    let release = LightningOutput::ReleaseIncoming {
+        contract: incoming.contract_id(),
+        release_signature: ctx.sign_schnorr(&incoming.release_message().into(), &release_kp),
+    };
+    let release_out_point = OutPoint {
+        txid: sha256::Hash::hash(b"").into(),
+        out_idx: 2,
+    };
+    fed.consensus_round(&[], &[(release_out_point, release)])
+        .await;
+    fed.consensus_round(&[], &[]).await;
+    match fed.output_outcome(incoming_out_point).await.unwrap() {
+        LightningOutputOutcome::Contract { outcome, .. } => {
+            assert_eq!(
+                outcome,
+                ContractOutcome::Incoming(DecryptedPreimage::Some(preimage))
+            );
+        }
+        _ => panic!(),
+    };
+
+    // Once released only the user can claim the funds
+    let meta = fed.verify_input(&incoming_input).await.unwrap();
+    assert_eq!(meta.keys, vec![user_pk]);
+}
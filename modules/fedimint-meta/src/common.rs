@@ -0,0 +1,39 @@
+use std::io;
+
+use fedimint_api::core::Decoder;
+use fedimint_api::encoding::{Decodable, DecodeError};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+
+use crate::{MetaConsensusItem, MetaInput, MetaOutput, MetaOutputOutcome};
+
+#[derive(Debug, Default, Clone)]
+pub struct MetaDecoder;
+
+impl Decoder for MetaDecoder {
+    type Input = MetaInput;
+    type Output = MetaOutput;
+    type OutputOutcome = MetaOutputOutcome;
+    type ConsensusItem = MetaConsensusItem;
+
+    fn decode_input(&self, mut d: &mut dyn io::Read) -> Result<MetaInput, DecodeError> {
+        MetaInput::consensus_decode(&mut d, &ModuleDecoderRegistry::default())
+    }
+
+    fn decode_output(&self, mut d: &mut dyn io::Read) -> Result<MetaOutput, DecodeError> {
+        MetaOutput::consensus_decode(&mut d, &ModuleDecoderRegistry::default())
+    }
+
+    fn decode_output_outcome(
+        &self,
+        mut d: &mut dyn io::Read,
+    ) -> Result<MetaOutputOutcome, DecodeError> {
+        MetaOutputOutcome::consensus_decode(&mut d, &ModuleDecoderRegistry::default())
+    }
+
+    fn decode_consensus_item(
+        &self,
+        mut r: &mut dyn io::Read,
+    ) -> Result<MetaConsensusItem, DecodeError> {
+        MetaConsensusItem::consensus_decode(&mut r, &ModuleDecoderRegistry::default())
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, format_err};
+use fedimint_api::config::{
+    ClientModuleConfig, TypedClientModuleConfig, TypedServerModuleConfig,
+    TypedServerModuleConsensusConfig,
+};
+use fedimint_api::core::ModuleKind;
+use fedimint_api::encoding::Encodable;
+use fedimint_api::PeerId;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+
+use crate::KIND;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MetaConfig {
+    /// Contains all configuration that will be encrypted such as private key
+    /// material
+    pub private: MetaConfigPrivate,
+    /// Contains all configuration that needs to be the same for every
+    /// federation member
+    pub consensus: MetaConfigConsensus,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Encodable)]
+pub struct MetaConfigConsensus {
+    /// Keys the guardians sign their votes with
+    pub peer_vote_keys: BTreeMap<PeerId, PublicKey>,
+    /// Number of guardians that have to vote for the same value
+    pub threshold: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MetaConfigPrivate {
+    pub vote_key: SecretKey,
+}
+
+/// Clients only read the meta through the API, so they need no config
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable)]
+pub struct MetaClientConfig;
+
+impl TypedClientModuleConfig for MetaClientConfig {
+    fn kind(&self) -> fedimint_api::core::ModuleKind {
+        KIND
+    }
+}
+
+impl TypedServerModuleConsensusConfig for MetaConfigConsensus {
+    fn to_client_config(&self) -> ClientModuleConfig {
+        ClientModuleConfig::new(
+            KIND,
+            serde_json::to_value(&MetaClientConfig).expect("Serialization can't fail"),
+        )
+    }
+}
+
+impl TypedServerModuleConfig for MetaConfig {
+    type Local = ();
+    type Private = MetaConfigPrivate;
+    type Consensus = MetaConfigConsensus;
+
+    fn from_parts(_local: Self::Local, private: Self::Private, consensus: Self::Consensus) -> Self {
+        Self { private, consensus }
+    }
+
+    fn to_parts(self) -> (ModuleKind, Self::Local, Self::Private, Self::Consensus) {
+        (KIND, (), self.private, self.consensus)
+    }
+
+    fn validate_config(&self, identity: &PeerId) -> anyhow::Result<()> {
+        let our_key = self
+            .consensus
+            .peer_vote_keys
+            .get(identity)
+            .ok_or_else(|| format_err!("Missing our vote key"))?;
+        if *our_key
+            != PublicKey::from_secret_key(&Secp256k1::signing_only(), &self.private.vote_key)
+        {
+            bail!("Our vote key does not match our public key");
+        }
+        if self.consensus.threshold == 0
+            || self.consensus.threshold as usize > self.consensus.peer_vote_keys.len()
+        {
+            bail!("Invalid vote threshold {}", self.consensus.threshold);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::impl_db_prefix_const;
+use fedimint_api::PeerId;
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use crate::vote::MetaVote;
+
+#[repr(u8)]
+#[derive(Clone, EnumIter, Debug)]
+pub enum DbKeyPrefix {
+    MetaEntry = 0x90,
+    MetaVote = 0x91,
+    PendingMetaVote = 0x92,
+}
+
+impl std::fmt::Display for DbKeyPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Current value of a key, kept after the key got removed so its revision is
+/// not reused
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct MetaEntry {
+    pub revision: u64,
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct MetaEntryKey(pub String);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MetaEntryKeyPrefix;
+
+impl_db_prefix_const!(
+    key = MetaEntryKey,
+    value = MetaEntry,
+    prefix = DbKeyPrefix::MetaEntry,
+    key_prefix = MetaEntryKeyPrefix
+);
+
+/// Votes for the current revision of a key the federation agreed on
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct MetaVoteKey(pub String, pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MetaVoteKeyPrefix;
+
+impl_db_prefix_const!(
+    key = MetaVoteKey,
+    value = MetaVote,
+    prefix = DbKeyPrefix::MetaVote,
+    key_prefix = MetaVoteKeyPrefix
+);
+
+/// Votes submitted to us that we propose until the federation agreed on them
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct PendingMetaVoteKey(pub String, pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PendingMetaVoteKeyPrefix;
+
+impl_db_prefix_const!(
+    key = PendingMetaVoteKey,
+    value = MetaVote,
+    prefix = DbKeyPrefix::PendingMetaVote,
+    key_prefix = PendingMetaVoteKeyPrefix
+);
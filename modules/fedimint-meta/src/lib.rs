@@ -0,0 +1,667 @@
+//! Key/value metadata of the federation, e.g. announced fees, recommended
+//! gateways or the hash of the terms of service, that all clients can read
+//! and only a threshold of guardians can change.
+//!
+//! A key changes once a threshold of guardians signed a [`MetaVote`] for the
+//! same value, see [`vote`] for how votes are created and submitted.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ffi::OsString;
+use std::fmt;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use bitcoin_hashes::sha256;
+use common::MetaDecoder;
+use fedimint_api::cancellable::{Cancellable, Cancelled};
+use fedimint_api::config::{
+    ConfigGenParams, DkgPeerMsg, ServerModuleConfig, TypedServerModuleConfig,
+};
+use fedimint_api::config::{ModuleConfigResponse, TypedServerModuleConsensusConfig};
+use fedimint_api::core::{ModuleInstanceId, ModuleKind};
+use fedimint_api::db::{Database, DatabaseTransaction, InvalidDbEntry};
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::module::audit::Audit;
+use fedimint_api::module::interconnect::ModuleInterconect;
+use fedimint_api::module::{
+    api_endpoint, ApiEndpoint, ApiError, ApiVersion, ConsensusProposal, CoreConsensusVersion,
+    InputMeta, IntoModuleError, ModuleConsensusVersion, ModuleError, ModuleGen,
+    TransactionItemAmount,
+};
+use fedimint_api::net::peers::MuxPeerConnections;
+use fedimint_api::server::DynServerModule;
+use fedimint_api::task::TaskGroup;
+use fedimint_api::{
+    plugin_types_trait_impl, push_db_pair_items, NumPeers, OutPoint, PeerId, ServerModule,
+};
+use futures::StreamExt;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum::IntoEnumIterator;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::config::{MetaClientConfig, MetaConfig, MetaConfigConsensus, MetaConfigPrivate};
+use crate::db::{
+    DbKeyPrefix, MetaEntry, MetaEntryKey, MetaEntryKeyPrefix, MetaVoteKey, MetaVoteKeyPrefix,
+    PendingMetaVoteKey, PendingMetaVoteKeyPrefix,
+};
+pub use crate::vote::{MetaKeyStatus, MetaVote};
+
+pub mod common;
+pub mod config;
+pub mod db;
+pub mod vote;
+
+const KIND: ModuleKind = ModuleKind::from_static_str("meta");
+
+/// Meta module
+#[derive(Debug)]
+pub struct Meta {
+    pub cfg: MetaConfig,
+}
+
+/// A vote a guardian proposes on behalf of the peer that signed it
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct MetaConsensusItem(pub MetaVote);
+
+#[derive(Debug, Clone)]
+pub struct MetaVerificationCache;
+
+#[derive(Debug)]
+pub struct MetaGen;
+
+#[async_trait]
+impl ModuleGen for MetaGen {
+    const KIND: ModuleKind = KIND;
+    type Decoder = MetaDecoder;
+
+    fn decoder(&self) -> MetaDecoder {
+        MetaDecoder
+    }
+
+    fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
+        &[ModuleConsensusVersion(0)]
+    }
+
+    async fn init(
+        &self,
+        cfg: ServerModuleConfig,
+        _db: Database,
+        _env: &BTreeMap<OsString, OsString>,
+        _task_group: &mut TaskGroup,
+    ) -> anyhow::Result<DynServerModule> {
+        Ok(Meta::new(cfg.to_typed()?).into())
+    }
+
+    fn trusted_dealer_gen(
+        &self,
+        peers: &[PeerId],
+        _params: &ConfigGenParams,
+    ) -> BTreeMap<PeerId, ServerModuleConfig> {
+        let secp = secp256k1::Secp256k1::new();
+        let vote_keys = peers
+            .iter()
+            .map(|&peer| (peer, secp.generate_keypair(&mut OsRng)))
+            .collect::<BTreeMap<_, _>>();
+        let peer_vote_keys = vote_keys
+            .iter()
+            .map(|(&peer, (_, pk))| (peer, *pk))
+            .collect::<BTreeMap<_, _>>();
+
+        vote_keys
+            .into_iter()
+            .map(|(peer, (sk, _))| {
+                let config = MetaConfig {
+                    private: MetaConfigPrivate { vote_key: sk },
+                    consensus: MetaConfigConsensus {
+                        peer_vote_keys: peer_vote_keys.clone(),
+                        threshold: peers.threshold() as u32,
+                    },
+                };
+                (peer, config.to_erased())
+            })
+            .collect()
+    }
+
+    async fn distributed_gen(
+        &self,
+        connections: &MuxPeerConnections<ModuleInstanceId, DkgPeerMsg>,
+        our_id: &PeerId,
+        instance_id: ModuleInstanceId,
+        peers: &[PeerId],
+        _params: &ConfigGenParams,
+        _task_group: &mut TaskGroup,
+    ) -> anyhow::Result<Cancellable<ServerModuleConfig>> {
+        let secp = secp256k1::Secp256k1::new();
+        let (sk, pk) = secp.generate_keypair(&mut OsRng);
+        let mut peer_vote_keys = BTreeMap::from([(*our_id, pk)]);
+
+        if let Err(Cancelled) = connections
+            .send(peers, instance_id, DkgPeerMsg::PublicKey(pk))
+            .await
+        {
+            return Ok(Err(Cancelled));
+        }
+
+        while peer_vote_keys.len() < peers.len() {
+            match connections.receive(instance_id).await {
+                Ok((peer, DkgPeerMsg::PublicKey(key))) => {
+                    peer_vote_keys.insert(peer, key);
+                }
+                Ok((peer, msg)) => {
+                    bail!("Invalid message received from: {peer}: {msg:?}");
+                }
+                Err(Cancelled) => {
+                    return Ok(Err(Cancelled));
+                }
+            }
+        }
+
+        let server = MetaConfig {
+            private: MetaConfigPrivate { vote_key: sk },
+            consensus: MetaConfigConsensus {
+                peer_vote_keys,
+                threshold: peers.threshold() as u32,
+            },
+        };
+
+        Ok(Ok(server.to_erased()))
+    }
+
+    fn to_config_response(
+        &self,
+        config: serde_json::Value,
+    ) -> anyhow::Result<ModuleConfigResponse> {
+        let config = serde_json::from_value::<MetaConfigConsensus>(config)?;
+
+        Ok(ModuleConfigResponse {
+            client: config.to_client_config(),
+            consensus_hash: config.consensus_hash()?,
+        })
+    }
+
+    fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
+        config.to_typed::<MetaConfig>()?.validate_config(identity)
+    }
+
+    fn hash_client_module(&self, config: Value) -> anyhow::Result<sha256::Hash> {
+        serde_json::from_value::<MetaClientConfig>(config)?.consensus_hash()
+    }
+
+    fn validate_client_config(&self, config: Value) -> anyhow::Result<()> {
+        serde_json::from_value::<MetaClientConfig>(config)?;
+        Ok(())
+    }
+
+    async fn dump_database(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        prefix_names: Vec<String>,
+    ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
+        let mut meta: BTreeMap<String, Box<dyn erased_serde::Serialize + Send>> = BTreeMap::new();
+        let filtered_prefixes = DbKeyPrefix::iter().filter(|f| {
+            prefix_names.is_empty() || prefix_names.contains(&f.to_string().to_lowercase())
+        });
+        for table in filtered_prefixes {
+            match table {
+                DbKeyPrefix::MetaEntry => {
+                    push_db_pair_items!(
+                        dbtx,
+                        MetaEntryKeyPrefix,
+                        MetaEntryKey,
+                        MetaEntry,
+                        meta,
+                        "Meta Entries"
+                    );
+                }
+                DbKeyPrefix::MetaVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        MetaVoteKeyPrefix,
+                        MetaVoteKey,
+                        MetaVote,
+                        meta,
+                        "Meta Votes"
+                    );
+                }
+                DbKeyPrefix::PendingMetaVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PendingMetaVoteKeyPrefix,
+                        PendingMetaVoteKey,
+                        MetaVote,
+                        meta,
+                        "Pending Meta Votes"
+                    );
+                }
+            }
+        }
+
+        Box::new(meta.into_iter())
+    }
+
+    async fn check_database(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<InvalidDbEntry> {
+        let known_prefixes = DbKeyPrefix::iter().map(|p| p as u8).collect::<Vec<_>>();
+        let mut invalid = dbtx.find_orphaned_entries(&known_prefixes).await;
+        for table in DbKeyPrefix::iter() {
+            invalid.extend(match table {
+                DbKeyPrefix::MetaEntry => dbtx.find_undecodable_entries::<MetaEntryKey>().await,
+                DbKeyPrefix::MetaVote => dbtx.find_undecodable_entries::<MetaVoteKey>().await,
+                DbKeyPrefix::PendingMetaVote => {
+                    dbtx.find_undecodable_entries::<PendingMetaVoteKey>().await
+                }
+            });
+        }
+        invalid
+    }
+}
+
+#[derive(
+    Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable, Default,
+)]
+pub struct MetaInput;
+
+impl fmt::Display for MetaInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MetaInput")
+    }
+}
+
+#[derive(
+    Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable, Default,
+)]
+pub struct MetaOutput;
+
+impl fmt::Display for MetaOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MetaOutput")
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct MetaOutputOutcome;
+
+impl fmt::Display for MetaOutputOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MetaOutputOutcome")
+    }
+}
+
+impl fmt::Display for MetaConsensusItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Meta vote of {} for {} revision {}",
+            self.0.peer, self.0.key, self.0.revision
+        )
+    }
+}
+
+#[async_trait]
+impl ServerModule for Meta {
+    type Gen = MetaGen;
+    type Decoder = MetaDecoder;
+    type VerificationCache = MetaVerificationCache;
+
+    fn decoder(&self) -> Self::Decoder {
+        MetaDecoder
+    }
+
+    fn versions(&self) -> (ModuleConsensusVersion, &[ApiVersion]) {
+        (
+            ModuleConsensusVersion(0),
+            &[ApiVersion { major: 0, minor: 0 }],
+        )
+    }
+
+    async fn await_consensus_proposal(&self, dbtx: &mut DatabaseTransaction<'_>) {
+        // Votes are written to the database, which makes the server ask again
+        if !self.consensus_proposal(dbtx).await.forces_new_epoch() {
+            std::future::pending().await
+        }
+    }
+
+    async fn consensus_proposal(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> ConsensusProposal<MetaConsensusItem> {
+        ConsensusProposal::new_auto_trigger(
+            dbtx.find_by_prefix(&PendingMetaVoteKeyPrefix)
+                .await
+                .map(|res| MetaConsensusItem(res.expect("DB error").1))
+                .collect::<Vec<_>>()
+                .await,
+        )
+    }
+
+    async fn begin_consensus_epoch<'a, 'b>(
+        &'a self,
+        dbtx: &mut DatabaseTransaction<'b>,
+        consensus_items: Vec<(PeerId, MetaConsensusItem)>,
+    ) {
+        let mut changed_keys = BTreeSet::new();
+        for (proposer, MetaConsensusItem(vote)) in consensus_items {
+            let pending_key = PendingMetaVoteKey(vote.key.clone(), vote.peer);
+            if dbtx
+                .get_value(&pending_key)
+                .await
+                .expect("DB error")
+                .as_ref()
+                == Some(&vote)
+            {
+                dbtx.remove_entry(&pending_key).await.expect("DB error");
+            }
+
+            if let Err(e) = self.check_vote(dbtx, &vote).await {
+                warn!(%proposer, peer = %vote.peer, key = %vote.key, %e, "Ignoring meta vote");
+                continue;
+            }
+
+            dbtx.insert_entry(&MetaVoteKey(vote.key.clone(), vote.peer), &vote)
+                .await
+                .expect("DB error");
+            changed_keys.insert(vote.key);
+        }
+
+        for key in changed_keys {
+            self.activate_agreed_value(dbtx, key).await;
+        }
+    }
+
+    fn build_verification_cache<'a>(
+        &'a self,
+        _inputs: impl Iterator<Item = &'a MetaInput> + Send,
+    ) -> Self::VerificationCache {
+        MetaVerificationCache
+    }
+
+    async fn validate_input<'a, 'b>(
+        &self,
+        _interconnect: &dyn ModuleInterconect,
+        _dbtx: &mut DatabaseTransaction<'b>,
+        _verification_cache: &Self::VerificationCache,
+        _input: &'a MetaInput,
+    ) -> Result<InputMeta, ModuleError> {
+        Err(MetaError::NoTransactions).into_module_error_other()
+    }
+
+    async fn apply_input<'a, 'b, 'c>(
+        &'a self,
+        _interconnect: &'a dyn ModuleInterconect,
+        _dbtx: &mut DatabaseTransaction<'c>,
+        _input: &'b MetaInput,
+        _cache: &Self::VerificationCache,
+    ) -> Result<InputMeta, ModuleError> {
+        Err(MetaError::NoTransactions).into_module_error_other()
+    }
+
+    async fn validate_output(
+        &self,
+        _dbtx: &mut DatabaseTransaction,
+        _output: &MetaOutput,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        Err(MetaError::NoTransactions).into_module_error_other()
+    }
+
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        _interconnect: &'a dyn ModuleInterconect,
+        _dbtx: &mut DatabaseTransaction<'b>,
+        _output: &'a MetaOutput,
+        _out_point: OutPoint,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        Err(MetaError::NoTransactions).into_module_error_other()
+    }
+
+    async fn end_consensus_epoch<'a, 'b>(
+        &'a self,
+        _consensus_peers: &HashSet<PeerId>,
+        _dbtx: &mut DatabaseTransaction<'b>,
+    ) -> Vec<PeerId> {
+        vec![]
+    }
+
+    async fn output_status(
+        &self,
+        _dbtx: &mut DatabaseTransaction<'_>,
+        _out_point: OutPoint,
+    ) -> Option<MetaOutputOutcome> {
+        None
+    }
+
+    async fn audit(&self, _dbtx: &mut DatabaseTransaction<'_>, _audit: &mut Audit) {}
+
+    fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
+        vec![
+            api_endpoint! {
+                "/get",
+                async |_module: &Meta, dbtx, key: String| -> Option<String> {
+                    Ok(dbtx
+                        .get_value(&MetaEntryKey(key))
+                        .await
+                        .expect("DB error")
+                        .and_then(|entry| entry.value))
+                }
+            },
+            api_endpoint! {
+                "/list",
+                async |module: &Meta, dbtx, _params: ()| -> BTreeMap<String, String> {
+                    Ok(module.entries(dbtx).await)
+                }
+            },
+            api_endpoint! {
+                "/status",
+                async |module: &Meta, dbtx, key: String| -> MetaKeyStatus {
+                    Ok(module.key_status(dbtx, key).await)
+                }
+            },
+            api_endpoint! {
+                "/vote",
+                async |module: &Meta, dbtx, vote: MetaVote| -> () {
+                    module
+                        .submit_vote(dbtx, vote)
+                        .await
+                        .map_err(|e| ApiError::bad_request(e.to_string()))
+                }
+            },
+        ]
+    }
+}
+
+impl Meta {
+    /// Create new module instance
+    pub fn new(cfg: MetaConfig) -> Meta {
+        Meta { cfg }
+    }
+
+    /// Checks a vote submitted through the API and remembers it, so we
+    /// propose it to the other guardians until the federation accepts it
+    async fn submit_vote(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        vote: MetaVote,
+    ) -> Result<(), MetaError> {
+        self.check_vote(dbtx, &vote).await?;
+
+        info!(peer = %vote.peer, key = %vote.key, "Proposing meta vote");
+        dbtx.insert_entry(&PendingMetaVoteKey(vote.key.clone(), vote.peer), &vote)
+            .await
+            .expect("DB error");
+        Ok(())
+    }
+
+    async fn check_vote(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        vote: &MetaVote,
+    ) -> Result<(), MetaError> {
+        if !vote.is_within_limits() {
+            return Err(MetaError::TooLarge);
+        }
+        if !vote.verify(&self.cfg.consensus.peer_vote_keys) {
+            return Err(MetaError::InvalidSignature);
+        }
+        let revision = self.current_entry(dbtx, &vote.key).await.revision;
+        if vote.revision != revision {
+            return Err(MetaError::WrongRevision(revision));
+        }
+        Ok(())
+    }
+
+    /// Sets `key` to the value a threshold of guardians voted for, if there
+    /// is one, and discards all votes for the old revision
+    async fn activate_agreed_value(&self, dbtx: &mut DatabaseTransaction<'_>, key: String) {
+        let votes = self.votes(dbtx, &key).await;
+
+        let mut vote_counts = BTreeMap::<_, usize>::new();
+        for value in votes.values() {
+            *vote_counts.entry(value).or_default() += 1;
+        }
+        let Some(value) = vote_counts
+            .into_iter()
+            .find(|(_, count)| *count >= self.cfg.consensus.threshold as usize)
+            .map(|(value, _)| value.clone())
+        else {
+            return;
+        };
+
+        let revision = self.current_entry(dbtx, &key).await.revision + 1;
+        info!(%key, ?value, revision, "Guardians changed meta");
+        dbtx.insert_entry(&MetaEntryKey(key.clone()), &MetaEntry { revision, value })
+            .await
+            .expect("DB error");
+
+        for peer in votes.keys() {
+            dbtx.remove_entry(&MetaVoteKey(key.clone(), *peer))
+                .await
+                .expect("DB error");
+            dbtx.remove_entry(&PendingMetaVoteKey(key.clone(), *peer))
+                .await
+                .expect("DB error");
+        }
+    }
+
+    /// All keys that currently have a value
+    async fn entries(&self, dbtx: &mut DatabaseTransaction<'_>) -> BTreeMap<String, String> {
+        dbtx.find_by_prefix(&MetaEntryKeyPrefix)
+            .await
+            .filter_map(|res| async move {
+                let (MetaEntryKey(key), entry) = res.expect("DB error");
+                entry.value.map(|value| (key, value))
+            })
+            .collect()
+            .await
+    }
+
+    async fn current_entry(&self, dbtx: &mut DatabaseTransaction<'_>, key: &str) -> MetaEntry {
+        dbtx.get_value(&MetaEntryKey(key.to_string()))
+            .await
+            .expect("DB error")
+            .unwrap_or_default()
+    }
+
+    async fn votes(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        key: &str,
+    ) -> BTreeMap<PeerId, Option<String>> {
+        dbtx.find_by_prefix(&MetaVoteKeyPrefix)
+            .await
+            .filter_map(|res| async move {
+                let (MetaVoteKey(vote_key, peer), vote) = res.expect("DB error");
+                (vote_key == key).then_some((peer, vote.value))
+            })
+            .collect()
+            .await
+    }
+
+    async fn key_status(&self, dbtx: &mut DatabaseTransaction<'_>, key: String) -> MetaKeyStatus {
+        let entry = self.current_entry(dbtx, &key).await;
+        MetaKeyStatus {
+            value: entry.value,
+            revision: entry.revision,
+            votes: self.votes(dbtx, &key).await,
+        }
+    }
+}
+
+// Must be unique.
+// TODO: we need to provide guidence for allocating these
+pub const MODULE_KEY_META: u16 = 129;
+plugin_types_trait_impl!(
+    MODULE_KEY_META,
+    MetaInput,
+    MetaOutput,
+    MetaOutputOutcome,
+    MetaConsensusItem,
+    MetaVerificationCache
+);
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
+pub enum MetaError {
+    #[error("The meta module has no inputs or outputs")]
+    NoTransactions,
+    #[error("Key or value of the vote are too large")]
+    TooLarge,
+    #[error("The vote is not signed by the guardian's vote key")]
+    InvalidSignature,
+    #[error("The vote is not for the current revision {0} of the key")]
+    WrongRevision(u64),
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::config::ConfigGenParams;
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::{Database, DatabaseVersion, DatabaseVersionKey};
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use fedimint_api::module::ModuleGen;
+    use fedimint_api::PeerId;
+    use futures::executor::block_on;
+
+    use crate::db::{MetaEntry, MetaEntryKey};
+    use crate::{Meta, MetaGen};
+
+    #[test]
+    fn list_skips_database_version() {
+        let peer = PeerId::from(0);
+        let cfg = MetaGen
+            .trusted_dealer_gen(&[peer], &ConfigGenParams::new())
+            .remove(&peer)
+            .unwrap();
+        let meta = Meta::new(cfg.to_typed().unwrap());
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+
+        block_on(async {
+            let mut dbtx = db.begin_transaction().await;
+            dbtx.insert_entry(&DatabaseVersionKey, &DatabaseVersion(0))
+                .await
+                .unwrap();
+            dbtx.insert_entry(
+                &MetaEntryKey("welcome".to_string()),
+                &MetaEntry {
+                    revision: 1,
+                    value: Some("gm".to_string()),
+                },
+            )
+            .await
+            .unwrap();
+            dbtx.insert_entry(
+                &MetaEntryKey("removed".to_string()),
+                &MetaEntry {
+                    revision: 2,
+                    value: None,
+                },
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                meta.entries(&mut dbtx).await,
+                [("welcome".to_string(), "gm".to_string())].into()
+            );
+        });
+    }
+}
@@ -0,0 +1,170 @@
+//! Guardians change the meta of the federation by signing votes with their
+//! meta vote key. Votes are created offline with `fedimintd sign-meta-vote`
+//! and can be submitted to any guardian, which proposes them until the
+//! federation accepted them.
+//!
+//! Every vote names the revision of the key it changes, so once a value got
+//! activated old votes for that key can't be replayed anymore.
+
+use std::collections::BTreeMap;
+use std::hash::Hasher;
+
+use bitcoin_hashes::{sha256, Hash as BitcoinHash, HashEngine};
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::PeerId;
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+
+const META_VOTE_TAG: &[u8] = b"fedimint-meta-vote";
+
+/// Keys longer than this many bytes are rejected
+pub const MAX_KEY_LEN: usize = 64;
+/// Values longer than this many bytes are rejected
+pub const MAX_VALUE_LEN: usize = 4096;
+
+/// A guardian's signed vote to set `key` to `value`, or to remove it if
+/// `value` is `None`
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable)]
+pub struct MetaVote {
+    pub peer: PeerId,
+    pub key: String,
+    /// Revision of the key the vote changes, see [`MetaKeyStatus::revision`]
+    pub revision: u64,
+    pub value: Option<String>,
+    pub signature: secp256k1::ecdsa::Signature,
+}
+
+impl MetaVote {
+    /// Signs the vote of `peer` to change revision `revision` of `key`
+    pub fn new(
+        vote_key: &SecretKey,
+        peer: PeerId,
+        key: String,
+        revision: u64,
+        value: Option<String>,
+    ) -> Self {
+        let message = vote_message(peer, &key, revision, &value);
+        let signature = Secp256k1::signing_only().sign_ecdsa(&message, vote_key);
+        MetaVote {
+            peer,
+            key,
+            revision,
+            value,
+            signature,
+        }
+    }
+
+    /// Checks that the vote was signed by the vote key of its peer
+    pub fn verify(&self, peer_vote_keys: &BTreeMap<PeerId, PublicKey>) -> bool {
+        let Some(key) = peer_vote_keys.get(&self.peer) else {
+            return false;
+        };
+        let message = vote_message(self.peer, &self.key, self.revision, &self.value);
+        Secp256k1::verification_only()
+            .verify_ecdsa(&message, &self.signature, key)
+            .is_ok()
+    }
+
+    /// Whether key and value are small enough to be stored
+    pub fn is_within_limits(&self) -> bool {
+        self.key.len() <= MAX_KEY_LEN
+            && self
+                .value
+                .as_ref()
+                .map_or(true, |value| value.len() <= MAX_VALUE_LEN)
+    }
+}
+
+impl std::hash::Hash for MetaVote {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.peer.hash(state);
+        self.key.hash(state);
+        self.revision.hash(state);
+        self.value.hash(state);
+        self.signature.serialize_der().hash(state);
+    }
+}
+
+impl PartialEq for MetaVote {
+    fn eq(&self, other: &MetaVote) -> bool {
+        self.peer == other.peer
+            && self.key == other.key
+            && self.revision == other.revision
+            && self.value == other.value
+            && self.signature == other.signature
+    }
+}
+
+impl Eq for MetaVote {}
+
+/// State of a key as returned by the `/status` endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetaKeyStatus {
+    pub value: Option<String>,
+    /// Number of times the value was changed, votes have to name it
+    pub revision: u64,
+    /// Values the guardians voted for that didn't reach the threshold yet
+    pub votes: BTreeMap<PeerId, Option<String>>,
+}
+
+fn vote_message(peer: PeerId, key: &str, revision: u64, value: &Option<String>) -> Message {
+    let mut engine = sha256::Hash::engine();
+    engine.input(META_VOTE_TAG);
+    peer.consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    // Encoded like a `String`
+    key.as_bytes()
+        .consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    revision
+        .consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    value
+        .consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    Message::from_slice(&sha256::Hash::from_engine(engine)).expect("Hash has the right length")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use fedimint_api::PeerId;
+    use rand::rngs::OsRng;
+    use secp256k1::Secp256k1;
+
+    use super::{MetaVote, MAX_VALUE_LEN};
+
+    #[test]
+    fn verifies_vote_signature() {
+        let secp = Secp256k1::new();
+        let (sk, pk) = secp.generate_keypair(&mut OsRng);
+        let (_, other_pk) = secp.generate_keypair(&mut OsRng);
+        let keys = BTreeMap::from([(PeerId::from(0), pk), (PeerId::from(1), other_pk)]);
+
+        let mut vote = MetaVote::new(
+            &sk,
+            PeerId::from(0),
+            "tos_hash".to_string(),
+            0,
+            Some("abcd".to_string()),
+        );
+        assert!(vote.verify(&keys));
+        assert!(vote.is_within_limits());
+
+        vote.revision = 1;
+        assert!(!vote.verify(&keys));
+        vote.revision = 0;
+        vote.peer = PeerId::from(1);
+        assert!(!vote.verify(&keys));
+
+        let vote = MetaVote::new(
+            &sk,
+            PeerId::from(0),
+            "motd".to_string(),
+            0,
+            Some("x".repeat(MAX_VALUE_LEN + 1)),
+        );
+        assert!(!vote.is_within_limits());
+    }
+}
@@ -2,6 +2,8 @@ use std::collections::{BTreeMap, HashMap};
 use std::iter::FromIterator;
 
 use anyhow::bail;
+use bitcoin_hashes::sha256;
+use bitcoin_hashes::Hash;
 use fedimint_api::config::{
     ClientModuleConfig, TypedClientModuleConfig, TypedServerModuleConfig,
     TypedServerModuleConsensusConfig,
@@ -15,6 +17,20 @@ use tbs::{Aggregatable, AggregatePublicKey, PublicKeyShare};
 
 use crate::KIND;
 
+/// Default maximum size of the encrypted ecash backup a user can store in bytes
+pub const DEFAULT_MAX_BACKUP_SIZE: u64 = 64 * 1024;
+
+/// Default minimum time between two backups of the same user in seconds
+pub const DEFAULT_BACKUP_MIN_INTERVAL_SECS: u64 = 60;
+
+fn default_max_backup_size() -> u64 {
+    DEFAULT_MAX_BACKUP_SIZE
+}
+
+fn default_backup_min_interval_secs() -> u64 {
+    DEFAULT_BACKUP_MIN_INTERVAL_SECS
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MintConfig {
     /// Contains all configuration that will be encrypted such as private key
@@ -33,12 +49,129 @@ pub struct MintConfigConsensus {
     pub fee_consensus: FeeConsensus,
     /// The maximum amount of change a client can request
     pub max_notes_per_denomination: u16,
+    /// The maximum size of an encrypted ecash backup a user can store. Backup
+    /// limits are only enforced by each guardian's API, so they aren't part of
+    /// the consensus hash and configs without them keep theirs.
+    #[serde(default = "default_max_backup_size")]
+    #[encodable_ignore]
+    pub max_backup_size: u64,
+    /// How many seconds the timestamps of two backups of the same user have
+    /// to be apart at least
+    #[serde(default = "default_backup_min_interval_secs")]
+    #[encodable_ignore]
+    pub backup_min_interval_secs: u64,
+    /// If set, notes can only be redeemed for a limited number of key epochs
+    /// after the one they were issued in. Only hashed if set, see
+    /// [`MintConfigConsensus::config_hash`].
+    #[serde(default)]
+    #[encodable_ignore]
+    pub note_expiry: Option<NoteExpiry>,
+    /// Public keys of the keysets the federation rotates to after the initial
+    /// one, one per key epoch. Only hashed if there are any.
+    #[serde(default)]
+    #[encodable_ignore]
+    pub rotated_peer_tbs_pks: Vec<BTreeMap<PeerId, Tiered<PublicKeyShare>>>,
+    /// Amount tiers removed from the tier set whose notes are still
+    /// redeemable. Only hashed if set.
+    #[serde(default)]
+    #[encodable_ignore]
+    pub retired_tiers: Option<RetiredTiers>,
+}
+
+/// Amount tiers the federation removed from its tier set, e.g. in a consensus
+/// upgrade. No new notes are issued in them, but existing notes can still be
+/// redeemed during a grace period, giving clients time to reissue them into
+/// the current tiers.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable)]
+pub struct RetiredTiers {
+    /// Public key shares of every peer for the retired tiers
+    pub peer_tbs_pks: BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+    /// Consensus block height from which notes of the retired tiers are no
+    /// longer accepted
+    pub redeemable_until: u32,
+}
+
+impl RetiredTiers {
+    /// Whether notes of the retired tiers are redeemable at consensus
+    /// `block_height`
+    pub fn is_redeemable(&self, block_height: u32) -> bool {
+        block_height < self.redeemable_until
+    }
+
+    /// Whether `amount` is one of the retired tiers
+    pub fn contains(&self, amount: Amount) -> bool {
+        self.peer_tbs_pks
+            .values()
+            .next()
+            .map_or(false, |pks| pks.get(amount).is_some())
+    }
+
+    /// Checks that the retired tiers are disjoint from the current `tiers` and
+    /// that every peer of the federation has keys for all of them
+    fn validate<T>(
+        &self,
+        peer_tbs_pks: &BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+        tiers: &Tiered<T>,
+    ) -> anyhow::Result<()> {
+        if !self.peer_tbs_pks.keys().eq(peer_tbs_pks.keys()) {
+            bail!("Peers of the retired tiers don't match the federation's");
+        }
+        let retired = match self.peer_tbs_pks.values().next() {
+            Some(retired) => retired,
+            None => bail!("Retired tiers without any keys"),
+        };
+        if self
+            .peer_tbs_pks
+            .values()
+            .any(|pks| !pks.structural_eq(retired))
+        {
+            bail!("Retired tiers of the peers don't match");
+        }
+        if let Some(amount) = retired.tiers().find(|amount| tiers.get(**amount).is_some()) {
+            bail!("Tier {amount} is both current and retired");
+        }
+        Ok(())
+    }
+}
+
+/// Policy limiting for how long issued notes stay redeemable
+///
+/// The federation signs with a new keyset every `key_epoch_blocks` blocks.
+/// Notes signed in a key epoch can be redeemed until `validity_key_epochs`
+/// further key epochs have passed, afterwards they no longer represent a
+/// liability of the federation. Once all configured keysets were used the last
+/// one stays in use, so notes issued with it never expire.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable)]
+pub struct NoteExpiry {
+    /// Number of blocks after which the federation rotates its signing keys
+    pub key_epoch_blocks: u32,
+    /// Number of key epochs after the one of their issuance notes can still be
+    /// redeemed in
+    pub validity_key_epochs: u32,
+}
+
+impl NoteExpiry {
+    /// Key epoch new notes get signed in at consensus `block_height`, given
+    /// the number of available keysets
+    pub fn key_epoch(&self, block_height: u32, keyset_count: usize) -> u32 {
+        let last_key_epoch = keyset_count.saturating_sub(1) as u32;
+        (block_height / self.key_epoch_blocks).min(last_key_epoch)
+    }
+
+    /// Whether notes issued in `note_key_epoch` are still redeemable in
+    /// `current_key_epoch`
+    pub fn is_redeemable(&self, note_key_epoch: u32, current_key_epoch: u32) -> bool {
+        current_key_epoch <= note_key_epoch.saturating_add(self.validity_key_epochs)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MintConfigPrivate {
     /// Secret keys for blind-signing ecash of varying note denominations
     pub tbs_sks: Tiered<tbs::SecretKeyShare>,
+    /// Secret keys of the keysets rotated to after the initial one
+    #[serde(default)]
+    pub rotated_tbs_sks: Vec<Tiered<tbs::SecretKeyShare>>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable)]
@@ -47,6 +180,127 @@ pub struct MintClientConfig {
     pub fee_consensus: FeeConsensus,
     pub peer_tbs_pks: BTreeMap<PeerId, Tiered<tbs::PublicKeyShare>>,
     pub max_notes_per_denomination: u16,
+    /// Not part of the consensus hash, see
+    /// [`MintConfigConsensus::max_backup_size`]
+    #[serde(default = "default_max_backup_size")]
+    #[encodable_ignore]
+    pub max_backup_size: u64,
+    /// Only hashed if set, see [`MintClientConfig::config_hash`]
+    #[serde(default)]
+    #[encodable_ignore]
+    pub note_expiry: Option<NoteExpiry>,
+    /// Aggregate public keys of the rotated keysets, one per key epoch after
+    /// the initial one
+    #[serde(default)]
+    #[encodable_ignore]
+    pub rotated_tbs_pks: Vec<Tiered<AggregatePublicKey>>,
+    #[serde(default)]
+    #[encodable_ignore]
+    pub rotated_peer_tbs_pks: Vec<BTreeMap<PeerId, Tiered<tbs::PublicKeyShare>>>,
+    #[serde(default)]
+    #[encodable_ignore]
+    pub retired_tiers: Option<RetiredTiers>,
+}
+
+impl MintClientConfig {
+    /// Hash of the config the guardians agree on. Fields added after the
+    /// first federations were created are only hashed if set, so their
+    /// configs keep the hash they had.
+    pub fn config_hash(&self) -> anyhow::Result<sha256::Hash> {
+        let mut engine = sha256::HashEngine::default();
+        self.consensus_encode(&mut engine)?;
+        if let Some(note_expiry) = &self.note_expiry {
+            note_expiry.consensus_encode(&mut engine)?;
+        }
+        if !self.rotated_tbs_pks.is_empty() {
+            self.rotated_tbs_pks.consensus_encode(&mut engine)?;
+            self.rotated_peer_tbs_pks.consensus_encode(&mut engine)?;
+        }
+        if let Some(retired_tiers) = &self.retired_tiers {
+            retired_tiers.consensus_encode(&mut engine)?;
+        }
+        Ok(sha256::Hash::from_engine(engine))
+    }
+
+    /// Number of keysets notes may be signed with, including the initial one
+    pub fn keyset_count(&self) -> usize {
+        1 + self.rotated_tbs_pks.len()
+    }
+
+    /// Aggregate and per-peer public keys of all key epochs, starting with the
+    /// initial keyset
+    pub fn keysets(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            &Tiered<AggregatePublicKey>,
+            &BTreeMap<PeerId, Tiered<tbs::PublicKeyShare>>,
+        ),
+    > {
+        std::iter::once((&self.tbs_pks, &self.peer_tbs_pks))
+            .chain(self.rotated_tbs_pks.iter().zip(&self.rotated_peer_tbs_pks))
+    }
+
+    /// Aggregate and per-peer public keys of `key_epoch`
+    pub fn keyset(
+        &self,
+        key_epoch: u32,
+    ) -> Option<(
+        &Tiered<AggregatePublicKey>,
+        &BTreeMap<PeerId, Tiered<tbs::PublicKeyShare>>,
+    )> {
+        self.keysets().nth(key_epoch as usize)
+    }
+
+    /// Key epoch the federation issues new notes in at consensus
+    /// `block_height`
+    pub fn key_epoch(&self, block_height: u32) -> u32 {
+        self.note_expiry.as_ref().map_or(0, |expiry| {
+            expiry.key_epoch(block_height, self.keyset_count())
+        })
+    }
+
+    /// Checks that the aggregate keys and the keys of every peer cover the
+    /// same amount tiers, otherwise the client could request notes in tiers
+    /// some peers can't sign or verify
+    pub fn validate_tiers(&self) -> anyhow::Result<()> {
+        if !self
+            .tbs_pks
+            .tiers()
+            .any(|amount| *amount == Amount::from_msats(1))
+        {
+            bail!("No msat 1 denomination");
+        }
+        for (peer, peer_tbs_pks) in &self.peer_tbs_pks {
+            if !peer_tbs_pks.structural_eq(&self.tbs_pks) {
+                bail!("Amount tiers of peer {peer} don't match the federation's");
+            }
+        }
+        if self.rotated_tbs_pks.len() != self.rotated_peer_tbs_pks.len() {
+            bail!("Number of rotated aggregate and peer keysets differ");
+        }
+        for (tbs_pks, peer_tbs_pks) in self.rotated_tbs_pks.iter().zip(&self.rotated_peer_tbs_pks) {
+            if !tbs_pks.structural_eq(&self.tbs_pks)
+                || peer_tbs_pks
+                    .values()
+                    .any(|pks| !pks.structural_eq(&self.tbs_pks))
+            {
+                bail!("Amount tiers of a rotated keyset don't match the federation's");
+            }
+        }
+        if let Some(retired_tiers) = &self.retired_tiers {
+            retired_tiers.validate(&self.peer_tbs_pks, &self.tbs_pks)?;
+        }
+        Ok(())
+    }
+
+    /// Aggregate public keys of the retired amount tiers
+    pub fn retired_tbs_pks(&self) -> HashMap<Amount, AggregatePublicKey> {
+        self.retired_tiers
+            .as_ref()
+            .map(|retired_tiers| aggregate_pub_keys(&retired_tiers.peer_tbs_pks))
+            .unwrap_or_default()
+    }
 }
 
 impl TypedClientModuleConfig for MintClientConfig {
@@ -55,16 +309,23 @@ impl TypedClientModuleConfig for MintClientConfig {
     }
 }
 
+/// Aggregates the public key shares of all peers into the federation's public
+/// keys
+pub fn aggregate_pub_keys(
+    peer_tbs_pks: &BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+) -> HashMap<Amount, AggregatePublicKey> {
+    TieredMultiZip::new(peer_tbs_pks.values().map(|keys| keys.iter()).collect())
+        .map(|(amt, keys)| {
+            // TODO: avoid this through better aggregation API allowing references or
+            let keys = keys.into_iter().copied().collect::<Vec<_>>();
+            (amt, keys.aggregate(peer_tbs_pks.threshold()))
+        })
+        .collect()
+}
+
 impl TypedServerModuleConsensusConfig for MintConfigConsensus {
     fn to_client_config(&self) -> ClientModuleConfig {
-        let pub_key: HashMap<Amount, AggregatePublicKey> =
-            TieredMultiZip::new(self.peer_tbs_pks.values().map(|keys| keys.iter()).collect())
-                .map(|(amt, keys)| {
-                    // TODO: avoid this through better aggregation API allowing references or
-                    let keys = keys.into_iter().copied().collect::<Vec<_>>();
-                    (amt, keys.aggregate(self.peer_tbs_pks.threshold()))
-                })
-                .collect();
+        let pub_key = aggregate_pub_keys(&self.peer_tbs_pks);
 
         ClientModuleConfig::new(
             KIND,
@@ -73,6 +334,15 @@ impl TypedServerModuleConsensusConfig for MintConfigConsensus {
                 fee_consensus: self.fee_consensus.clone(),
                 peer_tbs_pks: self.peer_tbs_pks.clone(),
                 max_notes_per_denomination: self.max_notes_per_denomination,
+                max_backup_size: self.max_backup_size,
+                note_expiry: self.note_expiry.clone(),
+                rotated_tbs_pks: self
+                    .rotated_peer_tbs_pks
+                    .iter()
+                    .map(|peer_tbs_pks| Tiered::from_iter(aggregate_pub_keys(peer_tbs_pks)))
+                    .collect(),
+                rotated_peer_tbs_pks: self.rotated_peer_tbs_pks.clone(),
+                retired_tiers: self.retired_tiers.clone(),
             })
             .expect("Serialization can't fail"),
         )
@@ -97,15 +367,12 @@ impl TypedServerModuleConfig for MintConfig {
             .private
             .tbs_sks
             .iter()
+            // Tiers retired by a vote are only removed from the consensus config
+            .filter(|(amount, _)| !self.consensus.is_retired(*amount))
             .map(|(amount, sk)| (amount, sk.to_pub_key_share()))
             .collect();
-        let pks: BTreeMap<Amount, PublicKeyShare> = self
-            .consensus
-            .peer_tbs_pks
-            .get(identity)
-            .unwrap()
-            .as_map()
-            .clone();
+        let our_pks = self.consensus.peer_tbs_pks.get(identity).unwrap();
+        let pks: BTreeMap<Amount, PublicKeyShare> = our_pks.as_map().clone();
         if sks != pks {
             bail!("Mint private key doesn't match pubkey share");
         }
@@ -113,6 +380,124 @@ impl TypedServerModuleConfig for MintConfig {
             bail!("No msat 1 denomination");
         }
 
+        if self.private.rotated_tbs_sks.len() != self.consensus.rotated_peer_tbs_pks.len() {
+            bail!("Number of rotated secret and public keysets differ");
+        }
+        for (rotated_sks, rotated_pks) in self
+            .private
+            .rotated_tbs_sks
+            .iter()
+            .zip(&self.consensus.rotated_peer_tbs_pks)
+        {
+            let rotated_pks = rotated_pks
+                .get(identity)
+                .ok_or_else(|| anyhow::format_err!("No rotated pubkey share for us"))?;
+            if &rotated_sks.to_public() != rotated_pks
+                || !rotated_pks.structural_eq(&self.private.tbs_sks)
+            {
+                bail!("Rotated mint private key doesn't match pubkey share");
+            }
+        }
+        match &self.consensus.note_expiry {
+            Some(expiry) if expiry.key_epoch_blocks == 0 => {
+                bail!("Key epochs have to be at least one block long");
+            }
+            // Without a schedule the rotated keys would never be used
+            None if !self.consensus.rotated_peer_tbs_pks.is_empty() => {
+                bail!("Rotated keysets require note expiry to be configured");
+            }
+            _ => {}
+        }
+        if let Some(retired_tiers) = &self.consensus.retired_tiers {
+            retired_tiers.validate(&self.consensus.peer_tbs_pks, our_pks)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MintConfig {
+    /// Drops our secret keys of the retired tiers, which are still in the
+    /// private config since retiring tiers only changes the consensus config
+    pub fn remove_retired_sks(&mut self) {
+        self.private.tbs_sks = self
+            .private
+            .tbs_sks
+            .iter()
+            .filter(|(amount, _)| !self.consensus.is_retired(*amount))
+            .map(|(amount, sk)| (amount, *sk))
+            .collect();
+    }
+}
+
+impl MintConfigConsensus {
+    /// Hash of the config the guardians agree on, see
+    /// [`MintClientConfig::config_hash`]
+    pub fn config_hash(&self) -> anyhow::Result<sha256::Hash> {
+        let mut engine = sha256::HashEngine::default();
+        self.consensus_encode(&mut engine)?;
+        if let Some(note_expiry) = &self.note_expiry {
+            note_expiry.consensus_encode(&mut engine)?;
+        }
+        if !self.rotated_peer_tbs_pks.is_empty() {
+            self.rotated_peer_tbs_pks.consensus_encode(&mut engine)?;
+        }
+        if let Some(retired_tiers) = &self.retired_tiers {
+            retired_tiers.consensus_encode(&mut engine)?;
+        }
+        Ok(sha256::Hash::from_engine(engine))
+    }
+
+    /// Whether `amount` is one of the retired tiers
+    pub fn is_retired(&self, amount: Amount) -> bool {
+        self.retired_tiers
+            .as_ref()
+            .map_or(false, |retired_tiers| retired_tiers.contains(amount))
+    }
+
+    /// Removes `tiers` from the tier set, keeping their public keys so notes
+    /// issued in them stay redeemable until consensus block height
+    /// `redeemable_until`. All guardians have to apply the same change, e.g.
+    /// by voting for it with [`crate::MintParamsChange::retire_tiers`].
+    pub fn retire_tiers(&mut self, tiers: &[Amount], redeemable_until: u32) -> anyhow::Result<()> {
+        if !self.rotated_peer_tbs_pks.is_empty() {
+            bail!("Retiring tiers of a federation rotating its keys is not supported");
+        }
+        let current = self
+            .peer_tbs_pks
+            .values()
+            .next()
+            .ok_or_else(|| anyhow::format_err!("No public keys"))?;
+        if let Some(amount) = tiers.iter().find(|amount| current.get(**amount).is_none()) {
+            bail!("Tier {amount} is not part of the tier set");
+        }
+        if tiers.contains(&Amount::from_msats(1)) {
+            bail!("The 1 msat tier can't be retired");
+        }
+
+        let is_retired = |amount: &Amount| tiers.contains(amount);
+        let mut retired_peer_tbs_pks = self
+            .retired_tiers
+            .take()
+            .map(|retired| retired.peer_tbs_pks)
+            .unwrap_or_default();
+        for (peer, pks) in self.peer_tbs_pks.iter_mut() {
+            let (retired, current): (Vec<_>, Vec<_>) =
+                pks.iter().partition(|(amount, _)| is_retired(amount));
+            let peer_retired = retired_peer_tbs_pks.entry(*peer).or_default();
+            for (amount, pk) in retired {
+                peer_retired.insert(amount, *pk);
+            }
+            *pks = current
+                .into_iter()
+                .map(|(amount, pk)| (amount, *pk))
+                .collect();
+        }
+
+        self.retired_tiers = Some(RetiredTiers {
+            peer_tbs_pks: retired_peer_tbs_pks,
+            redeemable_until,
+        });
         Ok(())
     }
 }
@@ -17,6 +17,11 @@ pub enum DbKeyPrefix {
     OutputOutcome = 0x13,
     MintAuditItem = 0x14,
     EcashBackup = 0x15,
+    OutputKeyEpoch = 0x16,
+    DenominationStats = 0x17,
+    KeyEpochLiability = 0x18,
+    ConsensusBlockHeight = 0x19,
+    NonceKeyEpoch = 0x1a,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -89,6 +94,66 @@ impl_db_prefix_const!(
     key_prefix = OutputOutcomeKeyPrefix
 );
 
+/// Key epoch whose keys an issuance was signed with, only recorded if notes
+/// expire
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct OutputKeyEpochKey(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OutputKeyEpochKeyPrefix;
+
+impl_db_prefix_const!(
+    key = OutputKeyEpochKey,
+    value = u32,
+    prefix = DbKeyPrefix::OutputKeyEpoch,
+    key_prefix = OutputKeyEpochKeyPrefix
+);
+
+/// Key epoch of the note a spent nonce belongs to, only recorded if notes
+/// expire so the nonce can be pruned once the key epoch expired
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct NonceKeyEpochKey(pub Nonce);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct NonceKeyEpochKeyPrefix;
+
+impl_db_prefix_const!(
+    key = NonceKeyEpochKey,
+    value = u32,
+    prefix = DbKeyPrefix::NonceKeyEpoch,
+    key_prefix = NonceKeyEpochKeyPrefix
+);
+
+/// Value of the notes signed in a key epoch that weren't redeemed yet, only
+/// recorded if notes expire
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct KeyEpochLiabilityKey(pub u32);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct KeyEpochLiabilityKeyPrefix;
+
+impl_db_prefix_const!(
+    key = KeyEpochLiabilityKey,
+    value = Amount,
+    prefix = DbKeyPrefix::KeyEpochLiability,
+    key_prefix = KeyEpochLiabilityKeyPrefix
+);
+
+/// Consensus block height the mint last fetched from the wallet while
+/// processing transactions, used where the wallet can't be queried
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct ConsensusBlockHeightKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ConsensusBlockHeightKeyPrefix;
+
+impl_db_prefix_const!(
+    key = ConsensusBlockHeightKey,
+    value = u32,
+    prefix = DbKeyPrefix::ConsensusBlockHeight,
+    key_prefix = ConsensusBlockHeightKeyPrefix
+);
+
 /// Represents the amounts of issued (signed) and redeemed (verified) notes for
 /// auditing
 #[derive(Debug, Clone, Encodable, Decodable, Serialize)]
@@ -109,6 +174,35 @@ impl_db_prefix_const!(
     key_prefix = MintAuditItemKeyPrefix
 );
 
+/// Number of notes issued and redeemed per denomination tier
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct DenominationStatsKey(pub Amount);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct DenominationStatsKeyPrefix;
+
+impl_db_prefix_const!(
+    key = DenominationStatsKey,
+    value = DenominationStats,
+    prefix = DbKeyPrefix::DenominationStats,
+    key_prefix = DenominationStatsKeyPrefix
+);
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize,
+)]
+pub struct DenominationStats {
+    pub issued: u64,
+    pub redeemed: u64,
+}
+
+impl DenominationStats {
+    /// Notes of the tier that were issued but not redeemed yet
+    pub fn outstanding(&self) -> u64 {
+        self.issued.saturating_sub(self.redeemed)
+    }
+}
+
 /// Key used to store user's ecash backups
 #[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
 pub struct EcashBackupKey(pub secp256k1_zkp::XOnlyPublicKey);
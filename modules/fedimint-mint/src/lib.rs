@@ -3,18 +3,23 @@ use std::ffi::OsString;
 use std::hash::Hash;
 use std::iter::FromIterator;
 use std::ops::Sub;
+use std::time::{Duration, SystemTime};
 
+use anyhow::bail;
 use async_trait::async_trait;
 pub use common::{BackupRequest, SignedBackupRequest};
 use config::FeeConsensus;
-use db::{ECashUserBackupSnapshot, EcashBackupKey, NonceKeyPrefix};
+use db::{
+    DenominationStats, DenominationStatsKey, DenominationStatsKeyPrefix, ECashUserBackupSnapshot,
+    EcashBackupKey, NonceKeyPrefix,
+};
 use fedimint_api::cancellable::{Cancellable, Cancelled};
 use fedimint_api::config::{
     ConfigGenParams, DkgPeerMsg, ModuleGenParams, ServerModuleConfig, TypedServerModuleConfig,
 };
 use fedimint_api::config::{ModuleConfigResponse, TypedServerModuleConsensusConfig};
-use fedimint_api::core::{ModuleInstanceId, ModuleKind};
-use fedimint_api::db::{Database, DatabaseTransaction};
+use fedimint_api::core::{ModuleInstanceId, ModuleKind, LEGACY_HARDCODED_INSTANCE_ID_WALLET};
+use fedimint_api::db::{Database, DatabaseTransaction, InvalidDbEntry};
 use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::module::audit::Audit;
 use fedimint_api::module::interconnect::ModuleInterconect;
@@ -44,17 +49,22 @@ use secp256k1_zkp::SECP256K1;
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use tbs::{
-    combine_valid_shares, dealer_keygen, sign_blinded_msg, verify_blind_share, Aggregatable,
-    AggregatePublicKey, PublicKeyShare, SecretKeyShare,
+    combine_valid_shares, dealer_keygen, sign_blinded_msg, verify_blind_share, AggregatePublicKey,
+    PublicKeyShare, SecretKeyShare,
 };
 use thiserror::Error;
 use threshold_crypto::group::Curve;
 use tracing::{debug, error, info, warn};
 
 use crate::common::MintDecoder;
-use crate::config::{MintClientConfig, MintConfig, MintConfigConsensus, MintConfigPrivate};
+use crate::config::{
+    aggregate_pub_keys, MintClientConfig, MintConfig, MintConfigConsensus, MintConfigPrivate,
+    NoteExpiry, DEFAULT_BACKUP_MIN_INTERVAL_SECS, DEFAULT_MAX_BACKUP_SIZE,
+};
 use crate::db::{
-    DbKeyPrefix, EcashBackupKeyPrefix, MintAuditItemKey, MintAuditItemKeyPrefix, NonceKey,
+    ConsensusBlockHeightKey, ConsensusBlockHeightKeyPrefix, DbKeyPrefix, EcashBackupKeyPrefix,
+    KeyEpochLiabilityKey, KeyEpochLiabilityKeyPrefix, MintAuditItemKey, MintAuditItemKeyPrefix,
+    NonceKey, NonceKeyEpochKey, NonceKeyEpochKeyPrefix, OutputKeyEpochKey, OutputKeyEpochKeyPrefix,
     OutputOutcomeKey, OutputOutcomeKeyPrefix, ProposedPartialSignatureKey,
     ProposedPartialSignaturesKeyPrefix, ReceivedPartialSignatureKey,
     ReceivedPartialSignatureKeyOutputPrefix, ReceivedPartialSignaturesKeyPrefix,
@@ -70,12 +80,28 @@ const KIND: ModuleKind = ModuleKind::from_static_str("mint");
 /// By default, the maximum notes per denomination when change-making for users
 const DEFAULT_MAX_NOTES_PER_DENOMINATION: u16 = 3;
 
+/// How far a backup's timestamp may be ahead of our clock
+const MAX_BACKUP_CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// Maximum number of notes whose status can be queried in a single request
+pub const MAX_NOTE_STATUS_BATCH: usize = 1000;
+
 /// Data structures taking into account different amount tiers
 
 /// Federated mint member mint
 #[derive(Debug)]
 pub struct Mint {
     cfg: MintConfig,
+    /// Keys of every key epoch, starting with the initial keyset
+    keysets: Vec<MintKeyset>,
+    /// Aggregate public keys of the retired amount tiers, notes are verified
+    /// but not signed with them
+    retired_pub_key: HashMap<Amount, AggregatePublicKey>,
+}
+
+/// Keys used to sign and verify the notes of a single key epoch
+#[derive(Debug)]
+struct MintKeyset {
     sec_key: Tiered<SecretKeyShare>,
     pub_key_shares: BTreeMap<PeerId, Tiered<PublicKeyShare>>,
     pub_key: HashMap<Amount, AggregatePublicKey>,
@@ -106,6 +132,17 @@ pub struct MintOutputSignatureShare(
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
 pub struct MintOutputBlindSignatures(pub TieredMulti<tbs::BlindedSignature>);
 
+/// Response of a single peer to a signature share request for a
+/// [`MintOutput`], allows clients to combine the shares themselves instead of
+/// waiting for the federation to do so in the next epoch
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub enum MintOutputSignatureShareResponse {
+    /// The peer's own signature share, not combined with the others yet
+    Share(MintOutputSignatureShare),
+    /// The shares were already combined and the peer's own share deleted
+    Combined(MintOutputBlindSignatures),
+}
+
 /// An verifiable one time use IOU from the mint.
 ///
 /// Digital version of a "note of deposit" in a free-banking era.
@@ -145,7 +182,10 @@ pub struct BlindNonce(pub tbs::BlindedMessage);
 
 #[derive(Debug, Clone)]
 pub struct VerifiedNotes {
-    valid_notes: HashMap<Note, Amount>,
+    /// Amount tier and key epoch of every note with a valid signature
+    valid_notes: HashMap<Note, (Amount, u32)>,
+    /// Amount tier of every note with a valid signature of a retired tier
+    retired_notes: HashMap<Note, Amount>,
 }
 
 #[derive(Debug)]
@@ -181,43 +221,69 @@ impl ModuleGen for MintGen {
         params: &ConfigGenParams,
     ) -> BTreeMap<PeerId, ServerModuleConfig> {
         let params = params.get::<MintGenParams>().expect("Invalid mint params");
+        let mint_amounts = &params.mint_amounts;
 
-        let tbs_keys = params
-            .mint_amounts
+        // One keyset per key epoch, the first one is the initial keyset
+        let tbs_keys = (0..params.keyset_count())
+            .map(|_| {
+                mint_amounts
+                    .iter()
+                    .map(|&amount| {
+                        let (tbs_pk, tbs_pks, tbs_sks) =
+                            dealer_keygen(peers.threshold(), peers.len());
+                        (amount, (tbs_pk, tbs_pks, tbs_sks))
+                    })
+                    .collect::<HashMap<_, _>>()
+            })
+            .collect::<Vec<_>>();
+        let mut peer_tbs_pks = tbs_keys
             .iter()
-            .map(|&amount| {
-                let (tbs_pk, tbs_pks, tbs_sks) = dealer_keygen(peers.threshold(), peers.len());
-                (amount, (tbs_pk, tbs_pks, tbs_sks))
+            .map(|keyset| {
+                peers
+                    .iter()
+                    .map(|&key_peer| {
+                        let keys = mint_amounts
+                            .iter()
+                            .map(|amount| (*amount, keyset[amount].1[key_peer.to_usize()]))
+                            .collect();
+                        (key_peer, keys)
+                    })
+                    .collect::<BTreeMap<_, _>>()
             })
-            .collect::<HashMap<_, _>>();
+            .collect::<Vec<_>>();
+        let rotated_peer_tbs_pks = peer_tbs_pks.split_off(1);
+        let peer_tbs_pks = peer_tbs_pks.remove(0);
+        let tbs_sks = |peer: PeerId| {
+            tbs_keys
+                .iter()
+                .map(|keyset| {
+                    mint_amounts
+                        .iter()
+                        .map(|amount| (*amount, keyset[amount].2[peer.to_usize()]))
+                        .collect::<Tiered<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
 
         let mint_cfg: BTreeMap<_, MintConfig> = peers
             .iter()
             .map(|&peer| {
+                let mut tbs_sks = tbs_sks(peer);
+                let rotated_tbs_sks = tbs_sks.split_off(1);
                 let config = MintConfig {
                     consensus: MintConfigConsensus {
-                        peer_tbs_pks: peers
-                            .iter()
-                            .map(|&key_peer| {
-                                let keys = params
-                                    .mint_amounts
-                                    .iter()
-                                    .map(|amount| {
-                                        (*amount, tbs_keys[amount].1[key_peer.to_usize()])
-                                    })
-                                    .collect();
-                                (key_peer, keys)
-                            })
-                            .collect(),
+                        peer_tbs_pks: peer_tbs_pks.clone(),
                         fee_consensus: FeeConsensus::default(),
                         max_notes_per_denomination: DEFAULT_MAX_NOTES_PER_DENOMINATION,
+                        max_backup_size: DEFAULT_MAX_BACKUP_SIZE,
+                        backup_min_interval_secs: DEFAULT_BACKUP_MIN_INTERVAL_SECS,
+                        note_expiry: params.note_expiry.clone().map(|params| params.policy),
+                        rotated_peer_tbs_pks: rotated_peer_tbs_pks.clone(),
+                        retired_tiers: None,
                     },
                     private: MintConfigPrivate {
-                        tbs_sks: params
-                            .mint_amounts
-                            .iter()
-                            .map(|amount| (*amount, tbs_keys[amount].2[peer.to_usize()]))
-                            .collect(),
+                        tbs_sks: tbs_sks.remove(0),
+                        rotated_tbs_sks,
                     },
                 };
                 (peer, config)
@@ -255,13 +321,13 @@ impl ModuleGen for MintGen {
         _task_group: &mut TaskGroup,
     ) -> anyhow::Result<Cancellable<ServerModuleConfig>> {
         let params = params.get::<MintGenParams>().expect("Invalid mint params");
+        let mint_amounts = &params.mint_amounts;
 
-        let mut dkg = DkgRunner::multi(
-            params.mint_amounts.to_vec(),
-            peers.threshold(),
-            our_id,
-            peers,
-        );
+        // Run one DKG per key epoch and amount tier
+        let dkg_keys = (0..params.keyset_count() as u32)
+            .flat_map(|key_epoch| mint_amounts.iter().map(move |amount| (key_epoch, *amount)))
+            .collect();
+        let mut dkg = DkgRunner::multi(dkg_keys, peers.threshold(), our_id, peers);
         let g2 = if let Ok(g2) = dkg
             .run_g2(module_instance_id, connections, &mut OsRng)
             .await
@@ -271,20 +337,25 @@ impl ModuleGen for MintGen {
             return Ok(Err(Cancelled));
         };
 
-        let amounts_keys = g2
-            .into_iter()
-            .map(|(amount, keys)| (amount, keys.tbs()))
-            .collect::<HashMap<_, _>>();
-
-        let server = MintConfig {
-            private: MintConfigPrivate {
-                tbs_sks: amounts_keys
+        let mut keysets = (0..params.keyset_count())
+            .map(|_| HashMap::new())
+            .collect::<Vec<_>>();
+        for ((key_epoch, amount), keys) in g2 {
+            keysets[key_epoch as usize].insert(amount, keys.tbs());
+        }
+        let mut tbs_sks = keysets
+            .iter()
+            .map(|amounts_keys| {
+                amounts_keys
                     .iter()
                     .map(|(amount, (_, sks))| (*amount, *sks))
-                    .collect(),
-            },
-            consensus: MintConfigConsensus {
-                peer_tbs_pks: peers
+                    .collect::<Tiered<SecretKeyShare>>()
+            })
+            .collect::<Vec<_>>();
+        let mut peer_tbs_pks = keysets
+            .iter()
+            .map(|amounts_keys| {
+                peers
                     .iter()
                     .map(|peer| {
                         let pks = amounts_keys
@@ -297,9 +368,26 @@ impl ModuleGen for MintGen {
 
                         (*peer, pks)
                     })
-                    .collect(),
+                    .collect::<BTreeMap<_, _>>()
+            })
+            .collect::<Vec<_>>();
+        let rotated_tbs_sks = tbs_sks.split_off(1);
+        let rotated_peer_tbs_pks = peer_tbs_pks.split_off(1);
+
+        let server = MintConfig {
+            private: MintConfigPrivate {
+                tbs_sks: tbs_sks.remove(0),
+                rotated_tbs_sks,
+            },
+            consensus: MintConfigConsensus {
+                peer_tbs_pks: peer_tbs_pks.remove(0),
                 fee_consensus: Default::default(),
                 max_notes_per_denomination: DEFAULT_MAX_NOTES_PER_DENOMINATION,
+                max_backup_size: DEFAULT_MAX_BACKUP_SIZE,
+                backup_min_interval_secs: DEFAULT_BACKUP_MIN_INTERVAL_SECS,
+                note_expiry: params.note_expiry.clone().map(|params| params.policy),
+                rotated_peer_tbs_pks,
+                retired_tiers: None,
             },
         };
 
@@ -314,7 +402,7 @@ impl ModuleGen for MintGen {
 
         Ok(ModuleConfigResponse {
             client: config.to_client_config(),
-            consensus_hash: config.consensus_hash()?,
+            consensus_hash: config.config_hash()?,
         })
     }
 
@@ -326,7 +414,35 @@ impl ModuleGen for MintGen {
         &self,
         config: serde_json::Value,
     ) -> anyhow::Result<bitcoin_hashes::sha256::Hash> {
-        serde_json::from_value::<MintClientConfig>(config)?.consensus_hash()
+        serde_json::from_value::<MintClientConfig>(config)?.config_hash()
+    }
+
+    fn validate_client_config(&self, config: serde_json::Value) -> anyhow::Result<()> {
+        serde_json::from_value::<MintClientConfig>(config)?.validate_tiers()
+    }
+
+    fn apply_params_change(
+        &self,
+        consensus: serde_json::Value,
+        change: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        let mut consensus = serde_json::from_value::<MintConfigConsensus>(consensus)?;
+        let change = serde_json::from_value::<MintParamsChange>(change)?;
+
+        if let Some(fee_consensus) = change.fee_consensus {
+            consensus.fee_consensus = fee_consensus;
+        }
+        if let Some(max_notes_per_denomination) = change.max_notes_per_denomination {
+            if max_notes_per_denomination == 0 {
+                bail!("At least one note per denomination has to be allowed");
+            }
+            consensus.max_notes_per_denomination = max_notes_per_denomination;
+        }
+        if let Some(retire_tiers) = change.retire_tiers {
+            consensus.retire_tiers(&retire_tiers.tiers, retire_tiers.redeemable_until)?;
+        }
+
+        Ok(serde_json::to_value(consensus)?)
     }
 
     async fn dump_database(
@@ -393,22 +509,200 @@ impl ModuleGen for MintGen {
                         "User Ecash Backup"
                     );
                 }
+                DbKeyPrefix::OutputKeyEpoch => {
+                    push_db_pair_items!(
+                        dbtx,
+                        OutputKeyEpochKeyPrefix,
+                        OutputKeyEpochKey,
+                        u32,
+                        mint,
+                        "Output Key Epochs"
+                    );
+                }
+                DbKeyPrefix::DenominationStats => {
+                    push_db_pair_items!(
+                        dbtx,
+                        DenominationStatsKeyPrefix,
+                        DenominationStatsKey,
+                        DenominationStats,
+                        mint,
+                        "Denomination Stats"
+                    );
+                }
+                DbKeyPrefix::KeyEpochLiability => {
+                    push_db_pair_items!(
+                        dbtx,
+                        KeyEpochLiabilityKeyPrefix,
+                        KeyEpochLiabilityKey,
+                        fedimint_api::Amount,
+                        mint,
+                        "Key Epoch Liabilities"
+                    );
+                }
+                DbKeyPrefix::ConsensusBlockHeight => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusBlockHeightKeyPrefix,
+                        ConsensusBlockHeightKey,
+                        u32,
+                        mint,
+                        "Consensus Block Height"
+                    );
+                }
+                DbKeyPrefix::NonceKeyEpoch => {
+                    push_db_pair_items!(
+                        dbtx,
+                        NonceKeyEpochKeyPrefix,
+                        NonceKeyEpochKey,
+                        u32,
+                        mint,
+                        "Nonce Key Epochs"
+                    );
+                }
             }
         }
 
         Box::new(mint.into_iter())
     }
+
+    async fn check_database(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<InvalidDbEntry> {
+        let known_prefixes = DbKeyPrefix::iter().map(|p| p as u8).collect::<Vec<_>>();
+        let mut invalid = dbtx.find_orphaned_entries(&known_prefixes).await;
+        for table in DbKeyPrefix::iter() {
+            invalid.extend(match table {
+                DbKeyPrefix::NoteNonce => dbtx.find_undecodable_entries::<NonceKey>().await,
+                DbKeyPrefix::ProposedPartialSig => {
+                    dbtx.find_undecodable_entries::<ProposedPartialSignatureKey>()
+                        .await
+                }
+                DbKeyPrefix::ReceivedPartialSig => {
+                    dbtx.find_undecodable_entries::<ReceivedPartialSignatureKey>()
+                        .await
+                }
+                DbKeyPrefix::OutputOutcome => {
+                    dbtx.find_undecodable_entries::<OutputOutcomeKey>().await
+                }
+                DbKeyPrefix::MintAuditItem => {
+                    dbtx.find_undecodable_entries::<MintAuditItemKey>().await
+                }
+                DbKeyPrefix::EcashBackup => dbtx.find_undecodable_entries::<EcashBackupKey>().await,
+                DbKeyPrefix::OutputKeyEpoch => {
+                    dbtx.find_undecodable_entries::<OutputKeyEpochKey>().await
+                }
+                DbKeyPrefix::DenominationStats => {
+                    dbtx.find_undecodable_entries::<DenominationStatsKey>()
+                        .await
+                }
+                DbKeyPrefix::KeyEpochLiability => {
+                    dbtx.find_undecodable_entries::<KeyEpochLiabilityKey>()
+                        .await
+                }
+                DbKeyPrefix::ConsensusBlockHeight => {
+                    dbtx.find_undecodable_entries::<ConsensusBlockHeightKey>()
+                        .await
+                }
+                DbKeyPrefix::NonceKeyEpoch => {
+                    dbtx.find_undecodable_entries::<NonceKeyEpochKey>().await
+                }
+            });
+        }
+        invalid
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MintGenParams {
     pub mint_amounts: Vec<Amount>,
+    /// Makes notes expire if set, see [`NoteExpiry`]
+    #[serde(default)]
+    pub note_expiry: Option<NoteExpiryParams>,
+}
+
+/// Parameters for generating the keysets of a federation whose notes expire
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteExpiryParams {
+    pub policy: NoteExpiry,
+    /// Number of keysets to generate including the initial one, the
+    /// federation stops rotating keys once all of them were used and logs an
+    /// error for every issuance from then on
+    pub key_epochs: u32,
+}
+
+impl MintGenParams {
+    /// Denominations of all powers of 2 up to `max_denomination`
+    pub fn new(max_denomination: Amount) -> MintGenParams {
+        MintGenParams {
+            mint_amounts: Tiered::gen_denominations(max_denomination)
+                .tiers()
+                .copied()
+                .collect(),
+            note_expiry: None,
+        }
+    }
+
+    /// Denominations of all powers of `base` (starting at 1 msat) up to
+    /// `max_denomination`, limited to the `max_tier_count` smallest ones if
+    /// set. More tiers allow representing amounts with fewer notes, fewer
+    /// tiers lead to larger anonymity sets per tier.
+    pub fn with_denominations(
+        base: u64,
+        max_denomination: Amount,
+        max_tier_count: Option<u16>,
+    ) -> anyhow::Result<MintGenParams> {
+        if base < 2 {
+            bail!("Denomination base has to be at least 2");
+        }
+        if max_denomination < Amount::from_msats(1) {
+            bail!("Max denomination has to be at least 1 msat");
+        }
+        if max_tier_count == Some(0) {
+            bail!("At least one denomination is required");
+        }
+
+        Ok(MintGenParams {
+            mint_amounts: Tiered::gen_denominations_with_base(base, max_denomination)
+                .tiers()
+                .take(max_tier_count.map_or(usize::MAX, usize::from))
+                .copied()
+                .collect(),
+            note_expiry: None,
+        })
+    }
+
+    /// Number of keysets to generate including the initial one
+    pub fn keyset_count(&self) -> usize {
+        self.note_expiry
+            .as_ref()
+            .map_or(1, |params| params.key_epochs.max(1) as usize)
+    }
 }
 
 impl ModuleGenParams for MintGenParams {
     const MODULE_NAME: &'static str = "mint";
 }
 
+/// Consensus parameters of a running mint the guardians can change by vote,
+/// fields that aren't set keep their value
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MintParamsChange {
+    #[serde(default)]
+    pub fee_consensus: Option<FeeConsensus>,
+    #[serde(default)]
+    pub max_notes_per_denomination: Option<u16>,
+    /// Amount tiers to remove from the tier set, see
+    /// [`MintConfigConsensus::retire_tiers`]
+    #[serde(default)]
+    pub retire_tiers: Option<RetireTiers>,
+}
+
+/// Amount tiers to retire and the consensus block height until which their
+/// notes stay redeemable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetireTiers {
+    pub tiers: Vec<Amount>,
+    pub redeemable_until: u32,
+}
+
 #[autoimpl(Deref, DerefMut using self.0)]
 #[derive(
     Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable, Default,
@@ -421,6 +715,22 @@ impl std::fmt::Display for MintInput {
     }
 }
 
+/// Status of a note that was received but not claimed (reissued) yet
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub enum NoteStatus {
+    /// The note is valid and unspent, it can be claimed by reissuing it
+    Unspent,
+    /// The note is valid but was already spent
+    Spent,
+    /// The mint doesn't issue notes of the note's amount tier
+    InvalidTier,
+    /// The note's signature is invalid
+    InvalidSignature,
+    /// The note is valid and unspent but can't be redeemed anymore, since its
+    /// key epoch expired or the grace period of its retired tier ended
+    Expired,
+}
+
 #[autoimpl(Deref, DerefMut using self.0)]
 #[derive(
     Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable, Default,
@@ -526,38 +836,78 @@ impl ServerModule for Mint {
         // We build a lookup table for checking the validity of all notes for certain
         // amounts. This calculation can happen massively in parallel since
         // verification is a pure function and thus has no side effects.
-        let valid_notes = inputs
+        let notes = inputs
             .flat_map(|inputs| inputs.0.iter_items())
+            .collect::<Vec<_>>();
+        let valid_notes = notes
+            .iter()
             .par_bridge()
             .filter_map(|(amount, note)| {
-                let amount_key = self.pub_key.get(&amount)?;
-                if note.verify(*amount_key) {
-                    Some((*note, amount))
-                } else {
-                    None
-                }
+                let key_epoch = self.keysets.iter().position(|keyset| {
+                    keyset
+                        .pub_key
+                        .get(amount)
+                        .map_or(false, |amount_key| note.verify(*amount_key))
+                })?;
+                Some((**note, (*amount, key_epoch as u32)))
+            })
+            .collect();
+        let retired_notes = notes
+            .iter()
+            .par_bridge()
+            .filter_map(|(amount, note)| {
+                let amount_key = self.retired_pub_key.get(amount)?;
+                note.verify(*amount_key).then_some((**note, *amount))
             })
             .collect();
 
-        VerifiedNotes { valid_notes }
+        VerifiedNotes {
+            valid_notes,
+            retired_notes,
+        }
     }
 
     async fn validate_input<'a, 'b>(
         &self,
-        _interconnect: &dyn ModuleInterconect,
+        interconnect: &dyn ModuleInterconect,
         dbtx: &mut DatabaseTransaction<'b>,
         verification_cache: &Self::VerificationCache,
         input: &'a MintInput,
     ) -> Result<InputMeta, ModuleError> {
+        let current_key_epoch = match &self.cfg.consensus.note_expiry {
+            Some(expiry) => Some((expiry, self.current_key_epoch(interconnect).await)),
+            None => None,
+        };
         for (amount, note) in input.iter_items() {
-            let note_valid = verification_cache
+            let note_key_epoch = verification_cache
                 .valid_notes
                 .get(note) // We validated the note
-                .map(|notet_amount| *notet_amount == amount) // It has the right amount tier
-                .unwrap_or(false); // If we didn't validate the note return false
+                .filter(|(note_amount, _)| *note_amount == amount) // It has the right amount tier
+                .map(|(_, key_epoch)| *key_epoch);
+
+            let note_key_epoch = match note_key_epoch {
+                Some(key_epoch) => key_epoch,
+                // Retired tiers only exist for the initial keyset
+                None if verification_cache.retired_notes.get(note) == Some(&amount) => {
+                    let retired_tiers = self
+                        .cfg
+                        .consensus
+                        .retired_tiers
+                        .as_ref()
+                        .expect("Notes only verify under retired tiers if there are some");
+                    if !retired_tiers.is_redeemable(block_height(interconnect).await) {
+                        return Err(MintError::RetiredTier(amount)).into_module_error_other();
+                    }
+                    0
+                }
+                // If we didn't validate the note it's invalid
+                None => return Err(MintError::InvalidSignature).into_module_error_other(),
+            };
 
-            if !note_valid {
-                return Err(MintError::InvalidSignature).into_module_error_other();
+            if let Some((expiry, current_key_epoch)) = current_key_epoch {
+                if !expiry.is_redeemable(note_key_epoch, current_key_epoch) {
+                    return Err(MintError::ExpiredNote(note_key_epoch)).into_module_error_other();
+                }
             }
 
             if dbtx
@@ -592,6 +942,7 @@ impl ServerModule for Mint {
         let meta = self
             .validate_input(interconnect, dbtx, cache, input)
             .await?;
+        self.record_block_height(interconnect, dbtx).await;
 
         for (amount, note) in input.iter_items() {
             let key = NonceKey(note.0);
@@ -599,6 +950,22 @@ impl ServerModule for Mint {
             dbtx.insert_new_entry(&MintAuditItemKey::Redemption(key), &amount)
                 .await
                 .expect("DB Error");
+            Self::update_denomination_stats(dbtx, amount, |stats| stats.redeemed += 1).await;
+            if self.cfg.consensus.note_expiry.is_some() {
+                // Notes of retired tiers aren't in the valid notes, they are from the initial
+                // key epoch
+                let key_epoch = cache
+                    .valid_notes
+                    .get(note)
+                    .map_or(0, |(_, key_epoch)| *key_epoch);
+                dbtx.insert_new_entry(&NonceKeyEpochKey(note.0), &key_epoch)
+                    .await
+                    .expect("DB Error");
+                Self::update_key_epoch_liability(dbtx, key_epoch, |liability| {
+                    liability.saturating_sub(amount)
+                })
+                .await;
+            }
         }
 
         Ok(meta)
@@ -618,7 +985,7 @@ impl ServerModule for Mint {
         }
 
         if let Some(amount) = output.iter_items().find_map(|(amount, _)| {
-            if self.pub_key.get(&amount).is_none() {
+            if self.keysets[0].pub_key.get(&amount).is_none() {
                 Some(amount)
             } else {
                 None
@@ -636,16 +1003,35 @@ impl ServerModule for Mint {
 
     async fn apply_output<'a, 'b>(
         &'a self,
+        interconnect: &'a dyn ModuleInterconect,
         dbtx: &mut DatabaseTransaction<'b>,
         output: &'a MintOutput,
         out_point: OutPoint,
     ) -> Result<TransactionItemAmount, ModuleError> {
         let amount = self.validate_output(dbtx, output).await?;
 
+        let key_epoch = self.current_key_epoch(interconnect).await;
+        self.record_block_height(interconnect, dbtx).await;
+        if self.cfg.consensus.note_expiry.is_some() {
+            if key_epoch as usize + 1 == self.keysets.len() {
+                error!(
+                    key_epoch,
+                    "Note key epochs are used up, notes signed with the last keyset never expire"
+                );
+            }
+            dbtx.insert_new_entry(&OutputKeyEpochKey(out_point), &key_epoch)
+                .await
+                .expect("DB Error");
+            Self::update_key_epoch_liability(dbtx, key_epoch, |liability| {
+                liability + output.total_amount()
+            })
+            .await;
+        }
+
         // TODO: move actual signing to worker thread
         // TODO: get rid of clone
         let partial_sig = self
-            .blind_sign(output.clone().0)
+            .blind_sign(output.clone().0, key_epoch)
             .into_module_error_other()?;
 
         dbtx.insert_new_entry(&ProposedPartialSignatureKey { out_point }, &partial_sig)
@@ -657,6 +1043,9 @@ impl ServerModule for Mint {
         )
         .await
         .expect("DB Error");
+        for (amount, _) in output.iter_items() {
+            Self::update_denomination_stats(dbtx, amount, |stats| stats.issued += 1).await;
+        }
 
         Ok(amount)
     }
@@ -668,6 +1057,7 @@ impl ServerModule for Mint {
     ) -> Vec<PeerId> {
         struct IssuanceData {
             out_point: OutPoint,
+            key_epoch: u32,
             // TODO: remove Option, make it mandatory
             // TODO: make it only the message, remove msg from PartialSigResponse
             our_contribution: Option<MintOutputSignatureShare>,
@@ -693,9 +1083,11 @@ impl ServerModule for Mint {
         for (out_point, signature_shares) in issuance_requests_iter {
             let proposal_key = ProposedPartialSignatureKey { out_point };
             let our_contribution = dbtx.get_value(&proposal_key).await.expect("DB error");
+            let key_epoch = self.output_key_epoch(dbtx, out_point).await;
 
             issuance_requests.push(IssuanceData {
                 out_point,
+                key_epoch,
                 our_contribution,
                 signature_shares,
             });
@@ -705,6 +1097,7 @@ impl ServerModule for Mint {
             .into_par_iter()
             .map(|issuance_data| {
                 let (bsig, errors) = self.combine(
+                    issuance_data.key_epoch,
                     issuance_data.our_contribution.clone(),
                     issuance_data.signature_shares.clone(),
                 );
@@ -830,6 +1223,24 @@ impl ServerModule for Mint {
                 MintAuditItemKey::RedemptionTotal => v.msats as i64,
             })
             .await;
+
+        // Notes of expired key epochs can't be redeemed anymore, so their unredeemed
+        // value is no liability. The block height is only updated while
+        // processing mint transactions, so they may be accounted for slightly
+        // longer than necessary.
+        if let Some(expiry) = &self.cfg.consensus.note_expiry {
+            let current_key_epoch =
+                expiry.key_epoch(self.recorded_block_height(dbtx).await, self.keysets.len());
+            audit
+                .add_items(dbtx, &KeyEpochLiabilityKeyPrefix, |k, v| {
+                    if expiry.is_redeemable(k.0, current_key_epoch) {
+                        0
+                    } else {
+                        v.msats as i64
+                    }
+                })
+                .await;
+        }
     }
 
     fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
@@ -849,11 +1260,109 @@ impl ServerModule for Mint {
                         .handle_recover_request(dbtx, id).await)
                 }
             },
+            api_endpoint! {
+                "/output_key_epoch",
+                async |module: &Mint, dbtx, out_point: OutPoint| -> u32 {
+                    Ok(module.output_key_epoch(dbtx, out_point).await)
+                }
+            },
+            api_endpoint! {
+                "/note_status",
+                async |module: &Mint, dbtx, notes: MintInput| -> Vec<NoteStatus> {
+                    module.handle_note_status_request(dbtx, notes).await
+                }
+            },
+            api_endpoint! {
+                "/denomination_stats",
+                async |module: &Mint, dbtx, _params: ()| -> Tiered<DenominationStats> {
+                    Ok(module.denomination_stats(dbtx).await)
+                }
+            },
+            api_endpoint! {
+                "/signature_share",
+                async |module: &Mint, dbtx, out_point: OutPoint| -> MintOutputSignatureShareResponse {
+                    module.handle_signature_share_request(dbtx, out_point).await
+                }
+            },
         ]
     }
 }
 
 impl Mint {
+    async fn update_denomination_stats(
+        dbtx: &mut DatabaseTransaction<'_>,
+        amount: Amount,
+        update: impl FnOnce(&mut DenominationStats),
+    ) {
+        let key = DenominationStatsKey(amount);
+        let mut stats = dbtx
+            .get_value(&key)
+            .await
+            .expect("DB Error")
+            .unwrap_or_default();
+        update(&mut stats);
+        dbtx.insert_entry(&key, &stats).await.expect("DB Error");
+    }
+
+    async fn update_key_epoch_liability(
+        dbtx: &mut DatabaseTransaction<'_>,
+        key_epoch: u32,
+        update: impl FnOnce(Amount) -> Amount,
+    ) {
+        let key = KeyEpochLiabilityKey(key_epoch);
+        let liability = dbtx
+            .get_value(&key)
+            .await
+            .expect("DB Error")
+            .unwrap_or(Amount::ZERO);
+        dbtx.insert_entry(&key, &update(liability))
+            .await
+            .expect("DB Error");
+    }
+
+    /// Fetches the consensus block height from the wallet and remembers it for
+    /// the audit and API endpoints, which can't query other modules. Only
+    /// needed if notes expire or tiers were retired.
+    async fn record_block_height(
+        &self,
+        interconnect: &dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) {
+        if self.cfg.consensus.note_expiry.is_none() && self.cfg.consensus.retired_tiers.is_none() {
+            return;
+        }
+        dbtx.insert_entry(&ConsensusBlockHeightKey, &block_height(interconnect).await)
+            .await
+            .expect("DB Error");
+    }
+
+    /// Consensus block height last seen while processing mint transactions
+    async fn recorded_block_height(&self, dbtx: &mut DatabaseTransaction<'_>) -> u32 {
+        dbtx.get_value(&ConsensusBlockHeightKey)
+            .await
+            .expect("DB Error")
+            .unwrap_or(0)
+    }
+
+    /// Returns how many notes of each denomination tier were issued and
+    /// redeemed, allowing to spot anomalies like unusual activity in a single
+    /// tier
+    async fn denomination_stats(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> Tiered<DenominationStats> {
+        dbtx.find_by_prefix(&DenominationStatsKeyPrefix)
+            .await
+            .map(|res| {
+                let (key, stats) = res.expect("DB error");
+                (key.0, stats)
+            })
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
     async fn handle_backup_request(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -864,6 +1373,16 @@ impl Mint {
             .map_err(|_| ApiError::bad_request("invalid request".into()))?;
 
         debug!(id = %request.id, len = request.payload.len(), "Received user e-cash backup request");
+        if request.payload.len() as u64 > self.cfg.consensus.max_backup_size {
+            debug!(id = %request.id, len = request.payload.len(), "Received user e-cash backup request exceeding the size limit - ignoring");
+            return Err(ApiError::bad_request("backup too large".into()));
+        }
+        // Timestamps are chosen by the user, bounding them by our clock makes the
+        // minimum interval between backups an effective rate limit
+        if request.timestamp > SystemTime::now() + MAX_BACKUP_CLOCK_SKEW {
+            debug!(id = %request.id, "Received user e-cash backup request with future timestamp - ignoring");
+            return Err(ApiError::bad_request("timestamp in the future".into()));
+        }
         if let Some(prev) = dbtx
             .get_value(&EcashBackupKey(request.id))
             .await
@@ -873,6 +1392,11 @@ impl Mint {
                 debug!(id = %request.id, len = request.payload.len(), "Received user e-cash backup request with old timestamp - ignoring");
                 return Err(ApiError::bad_request("timestamp too small".into()));
             }
+            let min_interval = Duration::from_secs(self.cfg.consensus.backup_min_interval_secs);
+            if request.timestamp < prev.timestamp + min_interval {
+                debug!(id = %request.id, "Received user e-cash backup request too soon after the previous one - ignoring");
+                return Err(ApiError::bad_request("backups are rate limited".into()));
+            }
         }
 
         info!(id = %request.id, len = request.payload.len(), "Storing new user e-cash backup");
@@ -889,6 +1413,124 @@ impl Mint {
         Ok(())
     }
 
+    async fn handle_signature_share_request(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        out_point: OutPoint,
+    ) -> Result<MintOutputSignatureShareResponse, ApiError> {
+        if let Some(bsigs) = dbtx
+            .get_value(&OutputOutcomeKey(out_point))
+            .await
+            .expect("DB error")
+        {
+            return Ok(MintOutputSignatureShareResponse::Combined(bsigs));
+        }
+
+        dbtx.get_value(&ProposedPartialSignatureKey { out_point })
+            .await
+            .expect("DB error")
+            .map(MintOutputSignatureShareResponse::Share)
+            .ok_or_else(|| ApiError::not_found(format!("No signature share for {out_point}")))
+    }
+
+    /// Key epoch whose keys the notes of `out_point` were signed with
+    async fn output_key_epoch(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        out_point: OutPoint,
+    ) -> u32 {
+        dbtx.get_value(&OutputKeyEpochKey(out_point))
+            .await
+            .expect("DB error")
+            .unwrap_or(0)
+    }
+
+    /// Checks the status of received notes without spending them, so
+    /// receivers that accepted them while offline can reconcile later. Expiry
+    /// is judged by the block height last seen while processing mint
+    /// transactions.
+    ///
+    /// The statuses are returned in the iteration order of `notes`.
+    async fn handle_note_status_request(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        notes: MintInput,
+    ) -> Result<Vec<NoteStatus>, ApiError> {
+        if notes.count_items() > MAX_NOTE_STATUS_BATCH {
+            return Err(ApiError::bad_request(format!(
+                "at most {MAX_NOTE_STATUS_BATCH} notes can be checked at once"
+            )));
+        }
+
+        let mut statuses = Vec::with_capacity(notes.count_items());
+        for (amount, note) in notes.iter_items() {
+            statuses.push(self.note_status(dbtx, amount, note).await);
+        }
+        Ok(statuses)
+    }
+
+    async fn note_status(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        amount: Amount,
+        note: &Note,
+    ) -> NoteStatus {
+        let retired_key = self.retired_pub_key.get(&amount);
+        if retired_key.is_none() && self.keysets[0].pub_key.get(&amount).is_none() {
+            return NoteStatus::InvalidTier;
+        }
+        let key_epoch = self.keysets.iter().position(|keyset| {
+            keyset
+                .pub_key
+                .get(&amount)
+                .map_or(false, |key| note.verify(*key))
+        });
+        let is_retired = retired_key.map_or(false, |key| note.verify(*key));
+        if key_epoch.is_none() && !is_retired {
+            return NoteStatus::InvalidSignature;
+        }
+
+        if dbtx
+            .get_value(&NonceKey(note.0))
+            .await
+            .expect("DB error")
+            .is_some()
+        {
+            return NoteStatus::Spent;
+        }
+
+        let block_height = self.recorded_block_height(dbtx).await;
+        let is_expired = match (key_epoch, &self.cfg.consensus.note_expiry) {
+            (Some(key_epoch), Some(expiry)) => !expiry.is_redeemable(
+                key_epoch as u32,
+                expiry.key_epoch(block_height, self.keysets.len()),
+            ),
+            (Some(_), None) => false,
+            (None, _) => self
+                .cfg
+                .consensus
+                .retired_tiers
+                .as_ref()
+                .map_or(false, |retired_tiers| {
+                    !retired_tiers.is_redeemable(block_height)
+                }),
+        };
+        if is_expired {
+            NoteStatus::Expired
+        } else {
+            NoteStatus::Unspent
+        }
+    }
+
+    /// Key epoch new notes get signed in, always the initial one if notes
+    /// don't expire
+    async fn current_key_epoch(&self, interconnect: &dyn ModuleInterconect) -> u32 {
+        match &self.cfg.consensus.note_expiry {
+            Some(expiry) => expiry.key_epoch(block_height(interconnect).await, self.keysets.len()),
+            None => 0,
+        }
+    }
+
     async fn handle_recover_request(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -906,8 +1548,13 @@ impl Mint {
     /// * If the amount tiers for secret and public keys are inconsistent
     /// * If the pub key belonging to the secret key share is not in the pub key
     ///   list.
-    pub fn new(cfg: MintConfig) -> Mint {
+    pub fn new(mut cfg: MintConfig) -> Mint {
+        cfg.remove_retired_sks();
         assert!(cfg.private.tbs_sks.tiers().count() > 0);
+        assert_eq!(
+            cfg.private.rotated_tbs_sks.len(),
+            cfg.consensus.rotated_peer_tbs_pks.len()
+        );
 
         // The amount tiers are implicitly provided by the key sets, make sure they are
         // internally consistent.
@@ -937,39 +1584,58 @@ impl Mint {
                 .collect()
         );
 
-        let aggregate_pub_keys = TieredMultiZip::new(
-            cfg.consensus
-                .peer_tbs_pks
-                .values()
-                .map(|keys| keys.iter())
-                .collect(),
+        let keysets = std::iter::once((
+            cfg.private.tbs_sks.clone(),
+            cfg.consensus.peer_tbs_pks.clone(),
+        ))
+        .chain(
+            cfg.private
+                .rotated_tbs_sks
+                .iter()
+                .cloned()
+                .zip(cfg.consensus.rotated_peer_tbs_pks.iter().cloned()),
         )
-        .map(|(amt, keys)| {
-            // TODO: avoid this through better aggregation API allowing references or
-            let keys = keys.into_iter().copied().collect::<Vec<_>>();
-            (amt, keys.aggregate(cfg.consensus.peer_tbs_pks.threshold()))
+        .map(|(sec_key, pub_key_shares)| {
+            assert!(pub_key_shares
+                .values()
+                .all(|pk| pk.structural_eq(&cfg.private.tbs_sks)));
+            assert_eq!(pub_key_shares[&our_id], sec_key.to_public());
+
+            MintKeyset {
+                pub_key: aggregate_pub_keys(&pub_key_shares),
+                sec_key,
+                pub_key_shares,
+            }
         })
         .collect();
 
+        let retired_pub_key = cfg
+            .consensus
+            .retired_tiers
+            .as_ref()
+            .map(|retired_tiers| aggregate_pub_keys(&retired_tiers.peer_tbs_pks))
+            .unwrap_or_default();
+
         Mint {
-            cfg: cfg.clone(),
-            sec_key: cfg.private.tbs_sks,
-            pub_key_shares: cfg.consensus.peer_tbs_pks,
-            pub_key: aggregate_pub_keys,
+            cfg,
+            keysets,
+            retired_pub_key,
         }
     }
 
     pub fn pub_key(&self) -> HashMap<Amount, AggregatePublicKey> {
-        self.pub_key.clone()
+        self.keysets[0].pub_key.clone()
     }
 
     fn blind_sign(
         &self,
         output: TieredMulti<BlindNonce>,
+        key_epoch: u32,
     ) -> Result<MintOutputSignatureShare, MintError> {
+        let keyset = &self.keysets[key_epoch as usize];
         Ok(MintOutputSignatureShare(output.map(
             |amt, msg| -> Result<_, InvalidAmountTierError> {
-                let sec_key = self.sec_key.tier(&amt)?;
+                let sec_key = keyset.sec_key.tier(&amt)?;
                 let blind_signature = sign_blinded_msg(msg.0, *sec_key);
                 Ok((msg.0, blind_signature))
             },
@@ -978,6 +1644,7 @@ impl Mint {
 
     fn combine(
         &self,
+        key_epoch: u32,
         our_contribution: Option<MintOutputSignatureShare>,
         partial_sigs: Vec<(PeerId, MintOutputSignatureShare)>,
     ) -> (
@@ -1061,13 +1728,14 @@ impl Mint {
                 .into_iter()
                 .zip(peer_ids)
                 .filter_map(|((msg, sig), peer)| {
-                    let amount_key = match self.pub_key_shares[&peer].tier(&amt) {
-                        Ok(key) => key,
-                        Err(_) => {
-                            peer_errors.push((peer, PeerErrorType::InvalidAmountTier));
-                            return None;
-                        }
-                    };
+                    let amount_key =
+                        match self.keysets[key_epoch as usize].pub_key_shares[&peer].tier(&amt) {
+                            Ok(key) => key,
+                            Err(_) => {
+                                peer_errors.push((peer, PeerErrorType::InvalidAmountTier));
+                                return None;
+                            }
+                        };
 
                     if msg != ref_msg {
                         peer_errors.push((peer, PeerErrorType::DifferentNonce));
@@ -1225,6 +1893,42 @@ pub enum CombineError {
     MultiplePeerContributions(PeerId, usize),
 }
 
+/// Deletes the spent nonces of notes whose key epoch expired as of the last
+/// consensus block height the mint saw, returning how many were deleted. Such
+/// notes are rejected as expired before their nonce is checked, so they can't
+/// be double spent, but their status is reported as expired instead of spent
+/// afterwards. Nonces spent before their key epoch was recorded are kept.
+pub async fn prune_expired_nonces(
+    cfg: &MintConfigConsensus,
+    dbtx: &mut DatabaseTransaction<'_>,
+) -> anyhow::Result<usize> {
+    let Some(expiry) = &cfg.note_expiry else {
+        return Ok(0);
+    };
+    let block_height = dbtx.get_value(&ConsensusBlockHeightKey).await?.unwrap_or(0);
+    let current_key_epoch = expiry.key_epoch(block_height, 1 + cfg.rotated_peer_tbs_pks.len());
+
+    let expired = dbtx
+        .find_by_prefix(&NonceKeyEpochKeyPrefix)
+        .await
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .filter_map(|res| match res {
+            Ok((key, key_epoch)) => {
+                (!expiry.is_redeemable(key_epoch, current_key_epoch)).then_some(Ok(key))
+            }
+            Err(e) => Some(Err(e)),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    for key in &expired {
+        dbtx.remove_entry(&NonceKey(key.0)).await?;
+        dbtx.remove_entry(key).await?;
+    }
+    Ok(expired.len())
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
 pub enum MintError {
     #[error("One of the supplied notes had an invalid mint signature")]
@@ -1239,6 +1943,23 @@ pub enum MintError {
     InvalidSignature,
     #[error("Exceeded maximum notes per denomination {0}, found {1}")]
     ExceededMaxNotes(u16, usize),
+    #[error("One of the notes was issued in key epoch {0} and expired")]
+    ExpiredNote(u32),
+    #[error("The grace period for redeeming notes of the retired amount tier {0} ended")]
+    RetiredTier(Amount),
+}
+
+async fn block_height(interconnect: &dyn ModuleInterconect) -> u32 {
+    let body = interconnect
+        .call(
+            LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+            "/block_height".to_owned(),
+            Default::default(),
+        )
+        .await
+        .expect("Wallet module not present or malfunctioning!");
+
+    serde_json::from_value(body).expect("Malformed block height response from wallet module!")
 }
 
 impl From<InvalidAmountTierError> for MintError {
@@ -1249,17 +1970,36 @@ impl From<InvalidAmountTierError> for MintError {
 
 #[cfg(test)]
 mod test {
+    use std::time::{Duration, SystemTime};
+
+    use async_trait::async_trait;
+    use bitcoin_hashes::Hash;
     use fedimint_api::config::{
-        ClientModuleConfig, ConfigGenParams, ServerModuleConfig, TypedServerModuleConsensusConfig,
+        ClientModuleConfig, ConfigGenParams, ServerModuleConfig, TypedServerModuleConfig,
+        TypedServerModuleConsensusConfig,
     };
-    use fedimint_api::module::ModuleGen;
-    use fedimint_api::{Amount, PeerId, TieredMulti};
+    use fedimint_api::core::ModuleInstanceId;
+    use fedimint_api::db::mem_impl::MemDatabase;
+    use fedimint_api::db::{Database, DatabaseTransaction};
+    use fedimint_api::module::audit::Audit;
+    use fedimint_api::module::interconnect::ModuleInterconect;
+    use fedimint_api::module::registry::ModuleDecoderRegistry;
+    use fedimint_api::module::{ApiError, ModuleGen};
+    use fedimint_api::{Amount, OutPoint, PeerId, ServerModule, TieredMulti, TransactionId};
+    use futures::executor::block_on;
+    use secp256k1_zkp::{KeyPair, SECP256K1};
     use tbs::{blind_message, unblind_signature, verify, AggregatePublicKey, BlindingKey, Message};
 
-    use crate::config::{FeeConsensus, MintClientConfig};
+    use crate::config::{
+        FeeConsensus, MintClientConfig, NoteExpiry, DEFAULT_BACKUP_MIN_INTERVAL_SECS,
+        DEFAULT_MAX_BACKUP_SIZE,
+    };
+    use crate::db::{DenominationStats, NonceKey};
     use crate::{
-        BlindNonce, CombineError, Mint, MintConfig, MintConfigConsensus, MintConfigPrivate,
-        MintGen, MintGenParams, PeerErrorType,
+        prune_expired_nonces, BackupRequest, BlindNonce, CombineError, Mint, MintConfig,
+        MintConfigConsensus, MintConfigPrivate, MintGen, MintGenParams, MintInput, MintOutput,
+        MintParamsChange, Nonce, Note, NoteExpiryParams, NoteStatus, PeerErrorType, RetireTiers,
+        MAX_NOTE_STATUS_BATCH,
     };
 
     const THRESHOLD: usize = 1;
@@ -1271,6 +2011,7 @@ mod test {
             &peers,
             &ConfigGenParams::new().attach(MintGenParams {
                 mint_amounts: vec![Amount::from_sats(1)],
+                note_expiry: None,
             }),
         );
         let client_cfg = mint_cfg[&PeerId::from(0)]
@@ -1299,6 +2040,89 @@ mod test {
         (agg_pk, mints)
     }
 
+    /// Answers the block height queries of the mint like the wallet would
+    struct FakeWallet(u32);
+
+    #[async_trait]
+    impl ModuleInterconect for FakeWallet {
+        async fn call(
+            &self,
+            _module_id: ModuleInstanceId,
+            path: String,
+            _data: serde_json::Value,
+        ) -> Result<serde_json::Value, ApiError> {
+            assert_eq!(path, "/block_height");
+            Ok(serde_json::to_value(self.0).unwrap())
+        }
+    }
+
+    fn blind_output(amount: Amount) -> MintOutput {
+        let keypair = KeyPair::from_seckey_slice(SECP256K1, &rand::random::<[u8; 32]>()).unwrap();
+        let nonce = Nonce(keypair.x_only_public_key().0);
+        MintOutput(TieredMulti::from_iter([(
+            amount,
+            BlindNonce(blind_message(nonce.to_message(), BlindingKey::random())),
+        )]))
+    }
+
+    /// Signs a note of `amount` with the keys of `key_epoch`
+    fn issue_note(mints: &[Mint], amount: Amount, key_epoch: u32) -> Note {
+        let keypair = KeyPair::from_seckey_slice(SECP256K1, &rand::random::<[u8; 32]>()).unwrap();
+        let nonce = Nonce(keypair.x_only_public_key().0);
+        let bkey = BlindingKey::random();
+        let blind_notes =
+            TieredMulti::from_iter([(amount, BlindNonce(blind_message(nonce.to_message(), bkey)))]);
+        let psigs = mints
+            .iter()
+            .enumerate()
+            .map(|(id, m)| {
+                (
+                    PeerId::from(id as u16),
+                    m.blind_sign(blind_notes.clone(), key_epoch).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let (bsig_res, _) = mints[0].combine(key_epoch, Some(psigs[0].1.clone()), psigs);
+        let (_, bsig) = bsig_res.unwrap().0.into_iter_items().next().unwrap();
+        Note(nonce, unblind_signature(bkey, bsig))
+    }
+
+    async fn net_assets(mint: &Mint, dbtx: &mut DatabaseTransaction<'_>) -> i64 {
+        let mut audit = Audit::default();
+        mint.audit(dbtx, &mut audit).await;
+        audit.sum().milli_sat
+    }
+
+    fn out_point(out_idx: u64) -> OutPoint {
+        OutPoint {
+            txid: TransactionId::from_inner([0; 32]),
+            out_idx,
+        }
+    }
+
+    #[test_log::test]
+    fn test_mint_gen_params() {
+        let params =
+            MintGenParams::with_denominations(10, Amount::from_sats(1000), Some(3)).unwrap();
+        assert_eq!(
+            params.mint_amounts,
+            vec![
+                Amount::from_msats(1),
+                Amount::from_msats(10),
+                Amount::from_msats(100)
+            ]
+        );
+        assert_eq!(
+            MintGenParams::new(Amount::from_msats(16))
+                .mint_amounts
+                .len(),
+            5
+        );
+
+        assert!(MintGenParams::with_denominations(1, Amount::from_sats(1), None).is_err());
+        assert!(MintGenParams::with_denominations(10, Amount::from_sats(1), Some(0)).is_err());
+    }
+
     #[test_log::test]
     fn test_issuance() {
         let (pk, mut mints) = build_mints();
@@ -1321,7 +2145,7 @@ mod test {
             .map(move |(id, m)| {
                 (
                     PeerId::from(id as u16),
-                    m.blind_sign(blind_notes.clone()).unwrap(),
+                    m.blind_sign(blind_notes.clone(), 0).unwrap(),
                 )
             })
             .collect::<Vec<_>>();
@@ -1330,7 +2154,7 @@ mod test {
         let mint = &mut mints[0];
 
         // Test happy path
-        let (bsig_res, errors) = mint.combine(Some(our_sig.clone()), psigs.clone());
+        let (bsig_res, errors) = mint.combine(0, Some(our_sig.clone()), psigs.clone());
         assert!(errors.0.is_empty());
 
         let bsig = bsig_res.unwrap();
@@ -1342,8 +2166,11 @@ mod test {
         });
 
         // Test threshold sig shares
-        let (bsig_res, errors) =
-            mint.combine(Some(our_sig.clone()), psigs[..(MINTS - THRESHOLD)].to_vec());
+        let (bsig_res, errors) = mint.combine(
+            0,
+            Some(our_sig.clone()),
+            psigs[..(MINTS - THRESHOLD)].to_vec(),
+        );
         assert!(bsig_res.is_ok());
         assert!(errors.0.is_empty());
 
@@ -1354,7 +2181,7 @@ mod test {
 
         // Test too few sig shares
         let few_sigs = psigs[..(MINTS - THRESHOLD - 1)].to_vec();
-        let (bsig_res, errors) = mint.combine(Some(our_sig.clone()), few_sigs.clone());
+        let (bsig_res, errors) = mint.combine(0, Some(our_sig.clone()), few_sigs.clone());
         assert_eq!(
             bsig_res,
             Err(CombineError::TooFewShares(
@@ -1365,12 +2192,13 @@ mod test {
         assert!(errors.0.is_empty());
 
         // Test no own share
-        let (bsig_res, errors) = mint.combine(None, psigs[1..].to_vec());
+        let (bsig_res, errors) = mint.combine(0, None, psigs[1..].to_vec());
         assert_eq!(bsig_res, Err(CombineError::NoOwnContribution));
         assert!(errors.0.is_empty());
 
         // Test multiple peer contributions
         let (bsig_res, errors) = mint.combine(
+            0,
             Some(our_sig.clone()),
             psigs
                 .iter()
@@ -1386,6 +2214,7 @@ mod test {
 
         // Test wrong length response
         let (bsig_res, errors) = mint.combine(
+            0,
             Some(our_sig.clone()),
             psigs
                 .iter()
@@ -1404,6 +2233,7 @@ mod test {
             .contains(&(PeerId::from(1), PeerErrorType::DifferentStructureSigShare)));
 
         let (bsig_res, errors) = mint.combine(
+            0,
             Some(our_sig.clone()),
             psigs
                 .iter()
@@ -1424,6 +2254,7 @@ mod test {
 
         let bmsg = blind_message(Message::from_bytes(b"test"), BlindingKey::random());
         let (bsig_res, errors) = mint.combine(
+            0,
             Some(our_sig),
             psigs
                 .iter()
@@ -1457,6 +2288,11 @@ mod test {
                     .peer_tbs_pks,
                 fee_consensus: FeeConsensus::default(),
                 max_notes_per_denomination: 0,
+                max_backup_size: 0,
+                backup_min_interval_secs: 0,
+                note_expiry: None,
+                rotated_peer_tbs_pks: vec![],
+                retired_tiers: None,
             },
             private: MintConfigPrivate {
                 tbs_sks: mint_server_cfg1[0]
@@ -1464,7 +2300,478 @@ mod test {
                     .unwrap()
                     .private
                     .tbs_sks,
+                rotated_tbs_sks: vec![],
             },
         });
     }
+
+    #[test_log::test]
+    fn test_note_expiry() {
+        let expiry = NoteExpiry {
+            key_epoch_blocks: 100,
+            validity_key_epochs: 1,
+        };
+        assert_eq!(expiry.key_epoch(99, 3), 0);
+        assert_eq!(expiry.key_epoch(100, 3), 1);
+        // Once all keysets were used the last one stays in use
+        assert_eq!(expiry.key_epoch(1000, 3), 2);
+        assert!(expiry.is_redeemable(0, 1));
+        assert!(!expiry.is_redeemable(0, 2));
+
+        let peers = (0..MINTS as u16).map(PeerId::from).collect::<Vec<_>>();
+        let mint_cfg = MintGen.trusted_dealer_gen(
+            &peers,
+            &ConfigGenParams::new().attach(MintGenParams {
+                note_expiry: Some(NoteExpiryParams {
+                    policy: expiry,
+                    key_epochs: 3,
+                }),
+                ..MintGenParams::new(Amount::from_sats(1))
+            }),
+        );
+        let client_cfg = mint_cfg[&PeerId::from(0)]
+            .to_typed::<MintConfig>()
+            .unwrap()
+            .consensus
+            .to_client_config()
+            .cast::<MintClientConfig>()
+            .unwrap();
+        client_cfg.validate_tiers().unwrap();
+        assert_eq!(client_cfg.keyset_count(), 3);
+        let mints = mint_cfg
+            .into_values()
+            .map(|config| Mint::new(config.to_typed().unwrap()))
+            .collect::<Vec<_>>();
+
+        // Notes signed in a rotated key epoch are only valid under its keys
+        let amount = Amount::from_sats(1);
+        let keypair = KeyPair::from_seckey_slice(SECP256K1, &[42; 32]).unwrap();
+        let nonce = Nonce(keypair.x_only_public_key().0);
+        let bkey = BlindingKey::random();
+        let blind_notes =
+            TieredMulti::from_iter([(amount, BlindNonce(blind_message(nonce.to_message(), bkey)))]);
+        let psigs = mints
+            .iter()
+            .enumerate()
+            .map(|(id, m)| {
+                (
+                    PeerId::from(id as u16),
+                    m.blind_sign(blind_notes.clone(), 2).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let (bsig_res, errors) = mints[0].combine(2, Some(psigs[0].1.clone()), psigs.clone());
+        assert!(errors.0.is_empty());
+        let (_, bsig) = bsig_res.unwrap().0.into_iter_items().next().unwrap();
+        let note = Note(nonce, unblind_signature(bkey, bsig));
+
+        let (tbs_pks, _) = client_cfg.keyset(2).unwrap();
+        assert!(note.verify(*tbs_pks.get(amount).unwrap()));
+        assert!(!note.verify(*client_cfg.tbs_pks.get(amount).unwrap()));
+
+        let input = MintInput(TieredMulti::from_iter([(amount, note)]));
+        let cache = mints[0].build_verification_cache(std::iter::once(&input));
+        assert_eq!(cache.valid_notes[&note], (amount, 2));
+    }
+
+    #[test_log::test]
+    fn test_expired_notes() {
+        let peers = (0..MINTS as u16).map(PeerId::from).collect::<Vec<_>>();
+        let mints = MintGen
+            .trusted_dealer_gen(
+                &peers,
+                &ConfigGenParams::new().attach(MintGenParams {
+                    note_expiry: Some(NoteExpiryParams {
+                        policy: NoteExpiry {
+                            key_epoch_blocks: 100,
+                            validity_key_epochs: 1,
+                        },
+                        key_epochs: 3,
+                    }),
+                    ..MintGenParams::new(Amount::from_sats(1))
+                }),
+            )
+            .into_values()
+            .map(|config| Mint::new(config.to_typed().unwrap()))
+            .collect::<Vec<_>>();
+        let amount = Amount::from_sats(1);
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+
+        block_on(async {
+            let mut dbtx = db.begin_transaction().await;
+
+            // Issue two notes in the first key epoch and redeem one of them
+            for out_idx in 0..2 {
+                mints[0]
+                    .apply_output(
+                        &FakeWallet(0),
+                        &mut dbtx,
+                        &blind_output(amount),
+                        out_point(out_idx),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let input = MintInput(TieredMulti::from_iter([(
+                amount,
+                issue_note(&mints, amount, 0),
+            )]));
+            let cache = mints[0].build_verification_cache(std::iter::once(&input));
+            mints[0]
+                .apply_input(&FakeWallet(0), &mut dbtx, &input, &cache)
+                .await
+                .unwrap();
+            assert_eq!(net_assets(&mints[0], &mut dbtx).await, -1000);
+
+            // Still redeemable in the next key epoch
+            mints[0]
+                .apply_output(
+                    &FakeWallet(100),
+                    &mut dbtx,
+                    &blind_output(amount),
+                    out_point(2),
+                )
+                .await
+                .unwrap();
+            assert_eq!(net_assets(&mints[0], &mut dbtx).await, -2000);
+
+            // Once the first key epoch expired its unredeemed note is no liability anymore
+            mints[0]
+                .apply_output(
+                    &FakeWallet(200),
+                    &mut dbtx,
+                    &blind_output(amount),
+                    out_point(3),
+                )
+                .await
+                .unwrap();
+            assert_eq!(net_assets(&mints[0], &mut dbtx).await, -2000);
+
+            let notes = MintInput(TieredMulti::from_iter([
+                (amount, issue_note(&mints, amount, 0)),
+                (amount, issue_note(&mints, amount, 1)),
+            ]));
+            assert_eq!(
+                mints[0]
+                    .handle_note_status_request(&mut dbtx, notes)
+                    .await
+                    .unwrap(),
+                vec![NoteStatus::Expired, NoteStatus::Unspent]
+            );
+
+            // The nonce of the redeemed note isn't needed once its key epoch expired
+            assert_eq!(
+                prune_expired_nonces(&mints[0].cfg.consensus, &mut dbtx)
+                    .await
+                    .unwrap(),
+                1
+            );
+            assert_eq!(
+                mints[0]
+                    .handle_note_status_request(&mut dbtx, input)
+                    .await
+                    .unwrap(),
+                vec![NoteStatus::Expired]
+            );
+        });
+    }
+
+    #[test_log::test]
+    fn test_retire_tiers() {
+        let peers = (0..MINTS as u16).map(PeerId::from).collect::<Vec<_>>();
+        let configs = MintGen
+            .trusted_dealer_gen(
+                &peers,
+                &ConfigGenParams::new().attach(MintGenParams {
+                    mint_amounts: vec![Amount::from_msats(1), Amount::from_sats(1)],
+                    note_expiry: None,
+                }),
+            )
+            .into_values()
+            .collect::<Vec<_>>();
+        let retired_amount = Amount::from_sats(1);
+
+        // Issue a note in the tier before it gets retired
+        let old_mints = configs
+            .iter()
+            .map(|config| Mint::new(config.to_typed().unwrap()))
+            .collect::<Vec<_>>();
+        let keypair = KeyPair::from_seckey_slice(SECP256K1, &[42; 32]).unwrap();
+        let nonce = Nonce(keypair.x_only_public_key().0);
+        let bkey = BlindingKey::random();
+        let blind_notes = TieredMulti::from_iter([(
+            retired_amount,
+            BlindNonce(blind_message(nonce.to_message(), bkey)),
+        )]);
+        let psigs = old_mints
+            .iter()
+            .enumerate()
+            .map(|(id, m)| {
+                (
+                    PeerId::from(id as u16),
+                    m.blind_sign(blind_notes.clone(), 0).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let (bsig_res, _) = old_mints[0].combine(0, Some(psigs[0].1.clone()), psigs.clone());
+        let (_, bsig) = bsig_res.unwrap().0.into_iter_items().next().unwrap();
+        let note = Note(nonce, unblind_signature(bkey, bsig));
+
+        let mints = configs
+            .into_iter()
+            .enumerate()
+            .map(|(id, config)| {
+                // Guardians retire tiers by voting for the change, which only
+                // touches the consensus config
+                let change = MintParamsChange {
+                    retire_tiers: Some(RetireTiers {
+                        tiers: vec![retired_amount],
+                        redeemable_until: 100,
+                    }),
+                    ..Default::default()
+                };
+                let consensus = MintGen
+                    .apply_params_change(
+                        config.consensus.value().clone(),
+                        serde_json::to_value(change).unwrap(),
+                    )
+                    .unwrap();
+                let mut config = config.to_typed::<MintConfig>().unwrap();
+                config.consensus = serde_json::from_value(consensus).unwrap();
+                config.validate_config(&PeerId::from(id as u16)).unwrap();
+                Mint::new(config)
+            })
+            .collect::<Vec<_>>();
+
+        let client_cfg = mints[0]
+            .cfg
+            .consensus
+            .to_client_config()
+            .cast::<MintClientConfig>()
+            .unwrap();
+        client_cfg.validate_tiers().unwrap();
+        assert!(client_cfg.tbs_pks.get(retired_amount).is_none());
+        assert!(client_cfg.retired_tbs_pks().contains_key(&retired_amount));
+
+        // Notes of the retired tier still verify, but no new ones are issued
+        let input = MintInput(TieredMulti::from_iter([(retired_amount, note)]));
+        let cache = mints[0].build_verification_cache(std::iter::once(&input));
+        assert!(cache.valid_notes.is_empty());
+        assert_eq!(cache.retired_notes[&note], retired_amount);
+
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        block_on(async {
+            let mut dbtx = db.begin_transaction().await;
+            assert!(mints[0]
+                .validate_output(&mut dbtx, &MintOutput(blind_notes))
+                .await
+                .is_err());
+        });
+    }
+
+    #[test_log::test]
+    fn test_params_change() {
+        let (mint_cfg, _) = build_configs();
+        let consensus = mint_cfg[0].consensus.value().clone();
+        let fees = FeeConsensus {
+            note_issuance_abs: Amount::from_msats(10),
+            note_spend_abs: Amount::from_msats(20),
+        };
+
+        let changed = MintGen
+            .apply_params_change(
+                consensus.clone(),
+                serde_json::to_value(MintParamsChange {
+                    fee_consensus: Some(fees.clone()),
+                    ..Default::default()
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        let changed = serde_json::from_value::<MintConfigConsensus>(changed).unwrap();
+        let unchanged = serde_json::from_value::<MintConfigConsensus>(consensus.clone()).unwrap();
+        assert_eq!(changed.fee_consensus, fees);
+        assert_eq!(
+            changed.max_notes_per_denomination,
+            unchanged.max_notes_per_denomination
+        );
+        assert_eq!(changed.peer_tbs_pks, unchanged.peer_tbs_pks);
+
+        assert!(MintGen
+            .apply_params_change(
+                consensus,
+                serde_json::json!({ "max_notes_per_denomination": 0 })
+            )
+            .is_err());
+    }
+
+    #[test_log::test]
+    fn test_backup_limits() {
+        let (_, mints) = build_mints();
+        let mint = &mints[0];
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let keypair = KeyPair::from_seckey_slice(SECP256K1, &[42; 32]).unwrap();
+        let backup = |payload: Vec<u8>, timestamp: SystemTime| {
+            BackupRequest {
+                id: keypair.x_only_public_key().0,
+                payload,
+                timestamp,
+            }
+            .sign(&keypair)
+            .unwrap()
+        };
+        let interval = Duration::from_secs(DEFAULT_BACKUP_MIN_INTERVAL_SECS);
+        let start = SystemTime::now() - 2 * interval;
+
+        block_on(async {
+            let mut dbtx = db.begin_transaction().await;
+
+            let too_large = vec![0; DEFAULT_MAX_BACKUP_SIZE as usize + 1];
+            assert!(mint
+                .handle_backup_request(&mut dbtx, backup(too_large, start))
+                .await
+                .is_err());
+            let in_future = SystemTime::now() + Duration::from_secs(3600);
+            assert!(mint
+                .handle_backup_request(&mut dbtx, backup(vec![0; 100], in_future))
+                .await
+                .is_err());
+
+            assert!(mint
+                .handle_backup_request(&mut dbtx, backup(vec![0; 100], start))
+                .await
+                .is_ok());
+            assert!(mint
+                .handle_backup_request(&mut dbtx, backup(vec![1; 100], start + interval / 2))
+                .await
+                .is_err());
+            assert!(mint
+                .handle_backup_request(&mut dbtx, backup(vec![2; 100], start + interval))
+                .await
+                .is_ok());
+            assert_eq!(
+                mint.handle_recover_request(&mut dbtx, keypair.x_only_public_key().0)
+                    .await
+                    .unwrap()
+                    .data,
+                vec![2; 100]
+            );
+        });
+    }
+
+    #[test_log::test]
+    fn test_denomination_stats() {
+        let (_, mints) = build_mints();
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let amount = Amount::from_sats(1);
+
+        block_on(async {
+            let mut dbtx = db.begin_transaction().await;
+            for out_idx in 0..3 {
+                mints[0]
+                    .apply_output(
+                        &FakeWallet(0),
+                        &mut dbtx,
+                        &blind_output(amount),
+                        out_point(out_idx),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let input = MintInput(TieredMulti::from_iter([(
+                amount,
+                issue_note(&mints, amount, 0),
+            )]));
+            let cache = mints[0].build_verification_cache(std::iter::once(&input));
+            mints[0]
+                .apply_input(&FakeWallet(0), &mut dbtx, &input, &cache)
+                .await
+                .unwrap();
+
+            let stats = mints[0].denomination_stats(&mut dbtx).await;
+            assert_eq!(
+                stats.get(amount),
+                Some(&DenominationStats {
+                    issued: 3,
+                    redeemed: 1
+                })
+            );
+            assert_eq!(stats.iter().count(), 1);
+
+            // The outstanding notes match the liabilities in the audit
+            let outstanding = stats.get(amount).unwrap().outstanding();
+            assert_eq!(outstanding, 2);
+            assert_eq!(
+                net_assets(&mints[0], &mut dbtx).await,
+                -((outstanding * amount.msats) as i64)
+            );
+        });
+    }
+
+    #[test_log::test]
+    fn test_note_status() {
+        let (_, mints) = build_mints();
+        let amount = Amount::from_sats(1);
+        let keypair = KeyPair::from_seckey_slice(SECP256K1, &[42; 32]).unwrap();
+        let nonce = Nonce(keypair.x_only_public_key().0);
+        let bkey = BlindingKey::random();
+        let blind_notes =
+            TieredMulti::from_iter([(amount, BlindNonce(blind_message(nonce.to_message(), bkey)))]);
+        let psigs = mints
+            .iter()
+            .enumerate()
+            .map(|(id, m)| {
+                (
+                    PeerId::from(id as u16),
+                    m.blind_sign(blind_notes.clone(), 0).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let (bsig_res, _) = mints[0].combine(0, Some(psigs[0].1.clone()), psigs.clone());
+        let (_, bsig) = bsig_res.unwrap().0.into_iter_items().next().unwrap();
+        let note = Note(nonce, unblind_signature(bkey, bsig));
+        let forged = Note(nonce, tbs::Signature(Message::from_bytes(&[1]).0));
+
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        block_on(async {
+            let mut dbtx = db.begin_transaction().await;
+            let input = |notes: Vec<(Amount, Note)>| MintInput(TieredMulti::from_iter(notes));
+
+            assert_eq!(
+                mints[0]
+                    .handle_note_status_request(
+                        &mut dbtx,
+                        input(vec![
+                            (amount, note),
+                            (amount, forged),
+                            (Amount::from_sats(1000), note),
+                        ])
+                    )
+                    .await
+                    .unwrap(),
+                vec![
+                    NoteStatus::Unspent,
+                    NoteStatus::InvalidSignature,
+                    NoteStatus::InvalidTier
+                ]
+            );
+
+            dbtx.insert_new_entry(&NonceKey(nonce), &())
+                .await
+                .expect("DB error");
+            assert_eq!(
+                mints[0]
+                    .handle_note_status_request(&mut dbtx, input(vec![(amount, note)]))
+                    .await
+                    .unwrap(),
+                vec![NoteStatus::Spent]
+            );
+
+            let too_many = vec![(amount, note); MAX_NOTE_STATUS_BATCH + 1];
+            assert!(mints[0]
+                .handle_note_status_request(&mut dbtx, input(too_many))
+                .await
+                .is_err());
+        });
+    }
 }
@@ -0,0 +1,41 @@
+use std::io;
+
+use fedimint_api::core::Decoder;
+use fedimint_api::encoding::{Decodable, DecodeError};
+use fedimint_api::module::registry::ModuleDecoderRegistry;
+
+use crate::{
+    StabilityPoolConsensusItem, StabilityPoolInput, StabilityPoolOutput, StabilityPoolOutputOutcome,
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct StabilityPoolDecoder;
+
+impl Decoder for StabilityPoolDecoder {
+    type Input = StabilityPoolInput;
+    type Output = StabilityPoolOutput;
+    type OutputOutcome = StabilityPoolOutputOutcome;
+    type ConsensusItem = StabilityPoolConsensusItem;
+
+    fn decode_input(&self, mut d: &mut dyn io::Read) -> Result<StabilityPoolInput, DecodeError> {
+        StabilityPoolInput::consensus_decode(&mut d, &ModuleDecoderRegistry::default())
+    }
+
+    fn decode_output(&self, mut d: &mut dyn io::Read) -> Result<StabilityPoolOutput, DecodeError> {
+        StabilityPoolOutput::consensus_decode(&mut d, &ModuleDecoderRegistry::default())
+    }
+
+    fn decode_output_outcome(
+        &self,
+        mut d: &mut dyn io::Read,
+    ) -> Result<StabilityPoolOutputOutcome, DecodeError> {
+        StabilityPoolOutputOutcome::consensus_decode(&mut d, &ModuleDecoderRegistry::default())
+    }
+
+    fn decode_consensus_item(
+        &self,
+        mut r: &mut dyn io::Read,
+    ) -> Result<StabilityPoolConsensusItem, DecodeError> {
+        StabilityPoolConsensusItem::consensus_decode(&mut r, &ModuleDecoderRegistry::default())
+    }
+}
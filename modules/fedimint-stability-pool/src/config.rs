@@ -0,0 +1,95 @@
+use anyhow::bail;
+use fedimint_api::config::{
+    ClientModuleConfig, TypedClientModuleConfig, TypedServerModuleConfig,
+    TypedServerModuleConsensusConfig,
+};
+use fedimint_api::core::ModuleKind;
+use fedimint_api::encoding::Encodable;
+use fedimint_api::PeerId;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::KIND;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StabilityPoolConfig {
+    /// Configuration specific to the guardian that is not encrypted
+    pub local: StabilityPoolConfigLocal,
+    /// Contains all configuration that will be encrypted such as private key
+    /// material
+    pub private: StabilityPoolConfigPrivate,
+    /// Contains all configuration that needs to be the same for every
+    /// federation member
+    pub consensus: StabilityPoolConfigConsensus,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StabilityPoolConfigLocal {
+    /// Where this guardian gets the BTC/USD price from, guardians without a
+    /// feed don't propose prices
+    pub price_feed: Option<PriceFeed>,
+}
+
+/// JSON endpoint returning the BTC/USD price
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceFeed {
+    pub url: Url,
+    /// JSON pointer to the price in USD per BTC in the response, e.g.
+    /// `/bitcoin/usd`
+    pub pointer: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Encodable)]
+pub struct StabilityPoolConfigConsensus {
+    /// Number of guardians that have to propose a price in an epoch for it to
+    /// be used, the median of their proposals becomes the price
+    pub price_threshold: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StabilityPoolConfigPrivate;
+
+/// Clients read the price and accounts through the API, so they need no
+/// config
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable)]
+pub struct StabilityPoolClientConfig;
+
+impl TypedClientModuleConfig for StabilityPoolClientConfig {
+    fn kind(&self) -> fedimint_api::core::ModuleKind {
+        KIND
+    }
+}
+
+impl TypedServerModuleConsensusConfig for StabilityPoolConfigConsensus {
+    fn to_client_config(&self) -> ClientModuleConfig {
+        ClientModuleConfig::new(
+            KIND,
+            serde_json::to_value(&StabilityPoolClientConfig).expect("Serialization can't fail"),
+        )
+    }
+}
+
+impl TypedServerModuleConfig for StabilityPoolConfig {
+    type Local = StabilityPoolConfigLocal;
+    type Private = StabilityPoolConfigPrivate;
+    type Consensus = StabilityPoolConfigConsensus;
+
+    fn from_parts(local: Self::Local, private: Self::Private, consensus: Self::Consensus) -> Self {
+        Self {
+            local,
+            private,
+            consensus,
+        }
+    }
+
+    fn to_parts(self) -> (ModuleKind, Self::Local, Self::Private, Self::Consensus) {
+        (KIND, self.local, self.private, self.consensus)
+    }
+
+    fn validate_config(&self, _identity: &PeerId) -> anyhow::Result<()> {
+        if self.consensus.price_threshold == 0 {
+            bail!("The price threshold has to be at least one");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::{impl_db_prefix_const, OutPoint};
+use secp256k1::XOnlyPublicKey;
+use serde::Serialize;
+use strum_macros::EnumIter;
+
+use crate::pool::{Account, PoolState};
+use crate::StabilityPoolOutputOutcome;
+
+#[repr(u8)]
+#[derive(Clone, EnumIter, Debug)]
+pub enum DbKeyPrefix {
+    Price = 0x60,
+    PoolState = 0x61,
+    Account = 0x62,
+    OutputOutcome = 0x63,
+}
+
+impl std::fmt::Display for DbKeyPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// BTC/USD price in cents per BTC the guardians last agreed on
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct PriceKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PriceKeyPrefix;
+
+impl_db_prefix_const!(
+    key = PriceKey,
+    value = u64,
+    prefix = DbKeyPrefix::Price,
+    key_prefix = PriceKeyPrefix
+);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct PoolStateKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PoolStateKeyPrefix;
+
+impl_db_prefix_const!(
+    key = PoolStateKey,
+    value = PoolState,
+    prefix = DbKeyPrefix::PoolState,
+    key_prefix = PoolStateKeyPrefix
+);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct AccountKey(pub XOnlyPublicKey);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct AccountKeyPrefix;
+
+impl_db_prefix_const!(
+    key = AccountKey,
+    value = Account,
+    prefix = DbKeyPrefix::Account,
+    key_prefix = AccountKeyPrefix
+);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct OutputOutcomeKey(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OutputOutcomeKeyPrefix;
+
+impl_db_prefix_const!(
+    key = OutputOutcomeKey,
+    value = StabilityPoolOutputOutcome,
+    prefix = DbKeyPrefix::OutputOutcome,
+    key_prefix = OutputOutcomeKeyPrefix
+);
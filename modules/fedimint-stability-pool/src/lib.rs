@@ -0,0 +1,670 @@
+//! Stable USD balances without leaving the federation: seekers deposit ecash
+//! into a pool and are owed its USD value at the time, leverage providers
+//! back them with their deposits and take the price risk in return.
+//!
+//! Every epoch the guardians propose the BTC/USD price of their price feeds,
+//! the median becomes the price deposits and withdrawals are settled at. See
+//! [`pool`] for the accounting.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bitcoin_hashes::sha256;
+use common::StabilityPoolDecoder;
+use fedimint_api::cancellable::Cancellable;
+use fedimint_api::config::{
+    ConfigGenParams, DkgPeerMsg, ModuleGenParams, ServerModuleConfig, TypedServerModuleConfig,
+};
+use fedimint_api::config::{ModuleConfigResponse, TypedServerModuleConsensusConfig};
+use fedimint_api::core::{ModuleInstanceId, ModuleKind};
+use fedimint_api::db::{Database, DatabaseTransaction, InvalidDbEntry};
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::module::audit::Audit;
+use fedimint_api::module::interconnect::ModuleInterconect;
+use fedimint_api::module::{
+    api_endpoint, ApiEndpoint, ApiError, ApiVersion, ConsensusProposal, CoreConsensusVersion,
+    InputMeta, IntoModuleError, ModuleConsensusVersion, ModuleError, ModuleGen,
+    TransactionItemAmount,
+};
+use fedimint_api::net::peers::MuxPeerConnections;
+use fedimint_api::server::DynServerModule;
+use fedimint_api::task::TaskGroup;
+use fedimint_api::{
+    plugin_types_trait_impl, push_db_pair_items, Amount, NumPeers, OutPoint, PeerId, ServerModule,
+};
+use futures::StreamExt;
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum::IntoEnumIterator;
+use thiserror::Error;
+use tracing::info;
+
+use crate::config::{
+    PriceFeed, StabilityPoolClientConfig, StabilityPoolConfig, StabilityPoolConfigConsensus,
+    StabilityPoolConfigLocal, StabilityPoolConfigPrivate,
+};
+use crate::db::{
+    AccountKey, AccountKeyPrefix, DbKeyPrefix, OutputOutcomeKey, OutputOutcomeKeyPrefix,
+    PoolStateKey, PoolStateKeyPrefix, PriceKey, PriceKeyPrefix,
+};
+use crate::pool::{Account, PoolError, PoolState, Side};
+use crate::price::{fresh_price, run_price_feed, LatestPrice};
+
+pub mod common;
+pub mod config;
+pub mod db;
+pub mod pool;
+pub mod price;
+
+const KIND: ModuleKind = ModuleKind::from_static_str("stability_pool");
+
+/// Stability pool module
+#[derive(Debug)]
+pub struct StabilityPool {
+    pub cfg: StabilityPoolConfig,
+    latest_price: LatestPrice,
+}
+
+/// BTC/USD price in cents per BTC a guardian proposes for this epoch
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct StabilityPoolConsensusItem {
+    pub price: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct StabilityPoolVerificationCache;
+
+#[derive(Debug)]
+pub struct StabilityPoolGen;
+
+#[async_trait]
+impl ModuleGen for StabilityPoolGen {
+    const KIND: ModuleKind = KIND;
+    type Decoder = StabilityPoolDecoder;
+
+    fn decoder(&self) -> StabilityPoolDecoder {
+        StabilityPoolDecoder
+    }
+
+    fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
+        &[ModuleConsensusVersion(0)]
+    }
+
+    async fn init(
+        &self,
+        cfg: ServerModuleConfig,
+        _db: Database,
+        _env: &BTreeMap<OsString, OsString>,
+        task_group: &mut TaskGroup,
+    ) -> anyhow::Result<DynServerModule> {
+        Ok(StabilityPool::new(cfg.to_typed()?, task_group).await.into())
+    }
+
+    fn trusted_dealer_gen(
+        &self,
+        peers: &[PeerId],
+        params: &ConfigGenParams,
+    ) -> BTreeMap<PeerId, ServerModuleConfig> {
+        let params = params
+            .get::<StabilityPoolGenParams>()
+            .expect("Invalid stability pool params");
+
+        peers
+            .iter()
+            .map(|&peer| {
+                let config = StabilityPoolConfig {
+                    local: StabilityPoolConfigLocal {
+                        price_feed: params.price_feed.clone(),
+                    },
+                    private: StabilityPoolConfigPrivate,
+                    consensus: StabilityPoolConfigConsensus {
+                        price_threshold: peers.threshold() as u32,
+                    },
+                };
+                (peer, config.to_erased())
+            })
+            .collect()
+    }
+
+    async fn distributed_gen(
+        &self,
+        _connections: &MuxPeerConnections<ModuleInstanceId, DkgPeerMsg>,
+        _our_id: &PeerId,
+        _instance_id: ModuleInstanceId,
+        peers: &[PeerId],
+        params: &ConfigGenParams,
+        _task_group: &mut TaskGroup,
+    ) -> anyhow::Result<Cancellable<ServerModuleConfig>> {
+        let params = params.get::<StabilityPoolGenParams>()?;
+
+        let server = StabilityPoolConfig {
+            local: StabilityPoolConfigLocal {
+                price_feed: params.price_feed,
+            },
+            private: StabilityPoolConfigPrivate,
+            consensus: StabilityPoolConfigConsensus {
+                price_threshold: peers.threshold() as u32,
+            },
+        };
+
+        Ok(Ok(server.to_erased()))
+    }
+
+    fn to_config_response(
+        &self,
+        config: serde_json::Value,
+    ) -> anyhow::Result<ModuleConfigResponse> {
+        let config = serde_json::from_value::<StabilityPoolConfigConsensus>(config)?;
+
+        Ok(ModuleConfigResponse {
+            client: config.to_client_config(),
+            consensus_hash: config.consensus_hash()?,
+        })
+    }
+
+    fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
+        config
+            .to_typed::<StabilityPoolConfig>()?
+            .validate_config(identity)
+    }
+
+    fn hash_client_module(&self, config: Value) -> anyhow::Result<sha256::Hash> {
+        serde_json::from_value::<StabilityPoolClientConfig>(config)?.consensus_hash()
+    }
+
+    fn validate_client_config(&self, config: Value) -> anyhow::Result<()> {
+        serde_json::from_value::<StabilityPoolClientConfig>(config)?;
+        Ok(())
+    }
+
+    async fn dump_database(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        prefix_names: Vec<String>,
+    ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
+        let mut stability_pool: BTreeMap<String, Box<dyn erased_serde::Serialize + Send>> =
+            BTreeMap::new();
+        let filtered_prefixes = DbKeyPrefix::iter().filter(|f| {
+            prefix_names.is_empty() || prefix_names.contains(&f.to_string().to_lowercase())
+        });
+        for table in filtered_prefixes {
+            match table {
+                DbKeyPrefix::Price => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PriceKeyPrefix,
+                        PriceKey,
+                        u64,
+                        stability_pool,
+                        "Price"
+                    );
+                }
+                DbKeyPrefix::PoolState => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PoolStateKeyPrefix,
+                        PoolStateKey,
+                        PoolState,
+                        stability_pool,
+                        "Pool State"
+                    );
+                }
+                DbKeyPrefix::Account => {
+                    push_db_pair_items!(
+                        dbtx,
+                        AccountKeyPrefix,
+                        AccountKey,
+                        Account,
+                        stability_pool,
+                        "Accounts"
+                    );
+                }
+                DbKeyPrefix::OutputOutcome => {
+                    push_db_pair_items!(
+                        dbtx,
+                        OutputOutcomeKeyPrefix,
+                        OutputOutcomeKey,
+                        StabilityPoolOutputOutcome,
+                        stability_pool,
+                        "Output Outcomes"
+                    );
+                }
+            }
+        }
+
+        Box::new(stability_pool.into_iter())
+    }
+
+    async fn check_database(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<InvalidDbEntry> {
+        let known_prefixes = DbKeyPrefix::iter().map(|p| p as u8).collect::<Vec<_>>();
+        let mut invalid = dbtx.find_orphaned_entries(&known_prefixes).await;
+        for table in DbKeyPrefix::iter() {
+            invalid.extend(match table {
+                DbKeyPrefix::Price => dbtx.find_undecodable_entries::<PriceKey>().await,
+                DbKeyPrefix::PoolState => dbtx.find_undecodable_entries::<PoolStateKey>().await,
+                DbKeyPrefix::Account => dbtx.find_undecodable_entries::<AccountKey>().await,
+                DbKeyPrefix::OutputOutcome => {
+                    dbtx.find_undecodable_entries::<OutputOutcomeKey>().await
+                }
+            });
+        }
+        invalid
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StabilityPoolGenParams {
+    /// Price feed of the local guardian
+    #[serde(default)]
+    pub price_feed: Option<PriceFeed>,
+}
+
+impl ModuleGenParams for StabilityPoolGenParams {
+    const MODULE_NAME: &'static str = "stability_pool";
+}
+
+/// Withdraws `amount` from the account, the transaction has to be signed by
+/// the account's key
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct StabilityPoolInput {
+    pub account: XOnlyPublicKey,
+    pub amount: Amount,
+}
+
+impl fmt::Display for StabilityPoolInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Stability pool withdrawal of {} from {}",
+            self.amount, self.account
+        )
+    }
+}
+
+/// Deposits `amount` into the account, creating it on the given side if it
+/// doesn't exist yet
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct StabilityPoolOutput {
+    pub account: XOnlyPublicKey,
+    pub side: Side,
+    pub amount: Amount,
+}
+
+impl fmt::Display for StabilityPoolOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Stability pool {:?} deposit of {} to {}",
+            self.side, self.amount, self.account
+        )
+    }
+}
+
+/// The account after the deposit
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct StabilityPoolOutputOutcome {
+    pub account: XOnlyPublicKey,
+}
+
+impl fmt::Display for StabilityPoolOutputOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Stability pool deposit to {}", self.account)
+    }
+}
+
+impl fmt::Display for StabilityPoolConsensusItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BTC/USD price proposal of {} cents", self.price)
+    }
+}
+
+/// Response of the `/pool` endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolInfo {
+    /// Price in cents per BTC, nothing can be deposited or withdrawn before
+    /// the guardians agreed on one
+    pub price: Option<u64>,
+    pub total: Amount,
+    pub seeker_cents: u64,
+    pub seeker_funds: Option<Amount>,
+    pub provider_funds: Option<Amount>,
+}
+
+/// Response of the `/account` endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountInfo {
+    pub account: Account,
+    /// What the account could withdraw at the current price
+    pub funds: Option<Amount>,
+}
+
+#[async_trait]
+impl ServerModule for StabilityPool {
+    type Gen = StabilityPoolGen;
+    type Decoder = StabilityPoolDecoder;
+    type VerificationCache = StabilityPoolVerificationCache;
+
+    fn decoder(&self) -> Self::Decoder {
+        StabilityPoolDecoder
+    }
+
+    fn versions(&self) -> (ModuleConsensusVersion, &[ApiVersion]) {
+        (
+            ModuleConsensusVersion(0),
+            &[ApiVersion { major: 0, minor: 0 }],
+        )
+    }
+
+    async fn await_consensus_proposal(&self, _dbtx: &mut DatabaseTransaction<'_>) {
+        // Prices are only proposed along with epochs triggered by others, e.g.
+        // by the transactions that need them
+        std::future::pending().await
+    }
+
+    async fn consensus_proposal(
+        &self,
+        _dbtx: &mut DatabaseTransaction<'_>,
+    ) -> ConsensusProposal<StabilityPoolConsensusItem> {
+        ConsensusProposal::Contribute(
+            fresh_price(&self.latest_price)
+                .map(|price| StabilityPoolConsensusItem { price })
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    async fn begin_consensus_epoch<'a, 'b>(
+        &'a self,
+        dbtx: &mut DatabaseTransaction<'b>,
+        consensus_items: Vec<(PeerId, StabilityPoolConsensusItem)>,
+    ) {
+        let mut proposals = BTreeMap::new();
+        for (peer, item) in consensus_items {
+            proposals.entry(peer).or_insert(item.price);
+        }
+        if proposals.len() < self.cfg.consensus.price_threshold as usize {
+            return;
+        }
+
+        let mut prices = proposals.into_values().collect::<Vec<_>>();
+        prices.sort_unstable();
+        let price = prices[(prices.len() - 1) / 2];
+        if price == 0 {
+            return;
+        }
+        dbtx.insert_entry(&PriceKey, &price)
+            .await
+            .expect("DB error");
+    }
+
+    fn build_verification_cache<'a>(
+        &'a self,
+        _inputs: impl Iterator<Item = &'a StabilityPoolInput> + Send,
+    ) -> Self::VerificationCache {
+        StabilityPoolVerificationCache
+    }
+
+    async fn validate_input<'a, 'b>(
+        &self,
+        _interconnect: &dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'b>,
+        _verification_cache: &Self::VerificationCache,
+        input: &'a StabilityPoolInput,
+    ) -> Result<InputMeta, ModuleError> {
+        let price = self.price(dbtx).await.into_module_error_other()?;
+        let mut account = dbtx
+            .get_value(&AccountKey(input.account))
+            .await
+            .expect("DB error")
+            .ok_or(StabilityPoolError::UnknownAccount)
+            .into_module_error_other()?;
+        let mut pool = self.pool_state(dbtx).await;
+
+        pool.withdraw(&mut account, input.amount, price)
+            .map_err(StabilityPoolError::Pool)
+            .into_module_error_other()?;
+
+        Ok(InputMeta {
+            amount: TransactionItemAmount {
+                amount: input.amount,
+                fee: Amount::ZERO,
+            },
+            puk_keys: vec![input.account],
+        })
+    }
+
+    async fn apply_input<'a, 'b, 'c>(
+        &'a self,
+        interconnect: &'a dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'c>,
+        input: &'b StabilityPoolInput,
+        cache: &Self::VerificationCache,
+    ) -> Result<InputMeta, ModuleError> {
+        let meta = self
+            .validate_input(interconnect, dbtx, cache, input)
+            .await?;
+
+        let price = self.price(dbtx).await.into_module_error_other()?;
+        let mut account = dbtx
+            .get_value(&AccountKey(input.account))
+            .await
+            .expect("DB error")
+            .expect("Checked by validation");
+        let mut pool = self.pool_state(dbtx).await;
+        pool.withdraw(&mut account, input.amount, price)
+            .expect("Checked by validation");
+
+        dbtx.insert_entry(&AccountKey(input.account), &account)
+            .await
+            .expect("DB error");
+        dbtx.insert_entry(&PoolStateKey, &pool)
+            .await
+            .expect("DB error");
+
+        Ok(meta)
+    }
+
+    async fn validate_output(
+        &self,
+        dbtx: &mut DatabaseTransaction,
+        output: &StabilityPoolOutput,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        let (mut account, mut pool, price) = self
+            .deposit_state(dbtx, output)
+            .await
+            .into_module_error_other()?;
+
+        pool.deposit(&mut account, output.amount, price)
+            .map_err(StabilityPoolError::Pool)
+            .into_module_error_other()?;
+
+        Ok(TransactionItemAmount {
+            amount: output.amount,
+            fee: Amount::ZERO,
+        })
+    }
+
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        _interconnect: &'a dyn ModuleInterconect,
+        dbtx: &mut DatabaseTransaction<'b>,
+        output: &'a StabilityPoolOutput,
+        out_point: OutPoint,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        let amount = self.validate_output(dbtx, output).await?;
+
+        let (mut account, mut pool, price) = self
+            .deposit_state(dbtx, output)
+            .await
+            .expect("Checked by validation");
+        pool.deposit(&mut account, output.amount, price)
+            .expect("Checked by validation");
+
+        dbtx.insert_entry(&AccountKey(output.account), &account)
+            .await
+            .expect("DB error");
+        dbtx.insert_entry(&PoolStateKey, &pool)
+            .await
+            .expect("DB error");
+        dbtx.insert_new_entry(
+            &OutputOutcomeKey(out_point),
+            &StabilityPoolOutputOutcome {
+                account: output.account,
+            },
+        )
+        .await
+        .expect("DB error");
+
+        info!(
+            account = %output.account,
+            side = ?output.side,
+            amount = %output.amount,
+            "Stability pool deposit"
+        );
+        Ok(amount)
+    }
+
+    async fn end_consensus_epoch<'a, 'b>(
+        &'a self,
+        _consensus_peers: &HashSet<PeerId>,
+        _dbtx: &mut DatabaseTransaction<'b>,
+    ) -> Vec<PeerId> {
+        vec![]
+    }
+
+    async fn output_status(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        out_point: OutPoint,
+    ) -> Option<StabilityPoolOutputOutcome> {
+        dbtx.get_value(&OutputOutcomeKey(out_point))
+            .await
+            .expect("DB error")
+    }
+
+    async fn audit(&self, dbtx: &mut DatabaseTransaction<'_>, audit: &mut Audit) {
+        audit
+            .add_items(dbtx, &PoolStateKeyPrefix, |_, pool| {
+                -(pool.total.msats as i64)
+            })
+            .await;
+    }
+
+    fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
+        vec![
+            api_endpoint! {
+                "/price",
+                async |_module: &StabilityPool, dbtx, _params: ()| -> Option<u64> {
+                    Ok(dbtx.get_value(&PriceKey).await.expect("DB error"))
+                }
+            },
+            api_endpoint! {
+                "/pool",
+                async |module: &StabilityPool, dbtx, _params: ()| -> PoolInfo {
+                    let price = dbtx.get_value(&PriceKey).await.expect("DB error");
+                    let pool = module.pool_state(dbtx).await;
+                    Ok(PoolInfo {
+                        price,
+                        total: pool.total,
+                        seeker_cents: pool.seeker_cents,
+                        seeker_funds: price.map(|price| pool.seeker_funds(price)),
+                        provider_funds: price.map(|price| pool.provider_funds(price)),
+                    })
+                }
+            },
+            api_endpoint! {
+                "/account",
+                async |module: &StabilityPool, dbtx, account: XOnlyPublicKey| -> AccountInfo {
+                    let account = dbtx
+                        .get_value(&AccountKey(account))
+                        .await
+                        .expect("DB error")
+                        .ok_or_else(|| ApiError::not_found(String::from("Account not found")))?;
+                    let price = dbtx.get_value(&PriceKey).await.expect("DB error");
+                    let pool = module.pool_state(dbtx).await;
+                    Ok(AccountInfo {
+                        account,
+                        funds: price.map(|price| pool.account_funds(&account, price)),
+                    })
+                }
+            },
+        ]
+    }
+}
+
+impl StabilityPool {
+    /// Create new module instance, fetching prices in the background if a
+    /// price feed is configured
+    pub async fn new(cfg: StabilityPoolConfig, task_group: &mut TaskGroup) -> StabilityPool {
+        let latest_price: LatestPrice = Arc::new(Mutex::new(None));
+        if let Some(feed) = cfg.local.price_feed.clone() {
+            let latest = latest_price.clone();
+            task_group
+                .spawn("stability pool price feed", |handle| async move {
+                    run_price_feed(feed, latest, &handle).await;
+                })
+                .await;
+        }
+        StabilityPool { cfg, latest_price }
+    }
+
+    async fn price(&self, dbtx: &mut DatabaseTransaction<'_>) -> Result<u64, StabilityPoolError> {
+        dbtx.get_value(&PriceKey)
+            .await
+            .expect("DB error")
+            .ok_or(StabilityPoolError::NoPrice)
+    }
+
+    async fn pool_state(&self, dbtx: &mut DatabaseTransaction<'_>) -> PoolState {
+        dbtx.get_value(&PoolStateKey)
+            .await
+            .expect("DB error")
+            .unwrap_or_default()
+    }
+
+    /// Account, pool and price a deposit is made at
+    async fn deposit_state(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        output: &StabilityPoolOutput,
+    ) -> Result<(Account, PoolState, u64), StabilityPoolError> {
+        let price = self.price(dbtx).await?;
+        let account = dbtx
+            .get_value(&AccountKey(output.account))
+            .await
+            .expect("DB error")
+            .unwrap_or_else(|| Account::new(output.side));
+        if account.side() != output.side {
+            return Err(StabilityPoolError::WrongSide(account.side()));
+        }
+        Ok((account, self.pool_state(dbtx).await, price))
+    }
+}
+
+// Must be unique.
+// TODO: we need to provide guidence for allocating these
+pub const MODULE_KEY_STABILITY_POOL: u16 = 130;
+plugin_types_trait_impl!(
+    MODULE_KEY_STABILITY_POOL,
+    StabilityPoolInput,
+    StabilityPoolOutput,
+    StabilityPoolOutputOutcome,
+    StabilityPoolConsensusItem,
+    StabilityPoolVerificationCache
+);
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
+pub enum StabilityPoolError {
+    #[error("The guardians did not agree on a BTC/USD price yet")]
+    NoPrice,
+    #[error("The account does not exist")]
+    UnknownAccount,
+    #[error("The account is on the {0:?} side")]
+    WrongSide(Side),
+    #[error("{0}")]
+    Pool(PoolError),
+}
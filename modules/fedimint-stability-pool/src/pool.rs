@@ -0,0 +1,296 @@
+//! Accounting of the pool, independent of the price oracle and the database.
+//!
+//! Seekers are owed a fixed amount of USD cents, providers own shares of
+//! whatever remains of the pool after paying the seekers at the current price.
+//! A price change thus moves funds between seekers and providers without
+//! touching any account. Should the price fall so far that the pool can't pay
+//! the seekers anymore, they split the whole pool according to their cents.
+
+use std::cmp::min;
+
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::Amount;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const MSATS_PER_BTC: u128 = 100_000_000_000;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
+pub enum Side {
+    /// Wants a stable USD value
+    Seeker,
+    /// Takes the price risk of the seekers in return for leveraged exposure to
+    /// BTC
+    Provider,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub enum Account {
+    Seeker { cents: u64 },
+    Provider { shares: u64 },
+}
+
+impl Account {
+    pub fn new(side: Side) -> Account {
+        match side {
+            Side::Seeker => Account::Seeker { cents: 0 },
+            Side::Provider => Account::Provider { shares: 0 },
+        }
+    }
+
+    pub fn side(&self) -> Side {
+        match self {
+            Account::Seeker { .. } => Side::Seeker,
+            Account::Provider { .. } => Side::Provider,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable,
+)]
+pub struct PoolState {
+    /// Funds held by the pool
+    pub total: Amount,
+    /// Sum of the cents owed to seekers
+    pub seeker_cents: u64,
+    /// Sum of the shares of providers
+    pub provider_shares: u64,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
+pub enum PoolError {
+    #[error("Amount is too small to be worth a cent")]
+    DustDeposit,
+    #[error("The account only holds {0}")]
+    InsufficientFunds(Amount),
+    #[error("Providers don't have enough funds to back more seekers")]
+    InsufficientCollateral,
+    #[error("Providers lost all their funds, no new providers are accepted")]
+    ProvidersWipedOut,
+}
+
+impl PoolState {
+    /// Part of the pool owed to seekers at `price` (cents per BTC)
+    pub fn seeker_funds(&self, price: u64) -> Amount {
+        min(cents_to_msats(self.seeker_cents, price), self.total)
+    }
+
+    /// Part of the pool left to providers at `price` (cents per BTC)
+    pub fn provider_funds(&self, price: u64) -> Amount {
+        self.total - self.seeker_funds(price)
+    }
+
+    /// Funds of `account` at `price` (cents per BTC)
+    pub fn account_funds(&self, account: &Account, price: u64) -> Amount {
+        match *account {
+            Account::Seeker { cents } => {
+                proportion(self.seeker_funds(price), cents, self.seeker_cents)
+            }
+            Account::Provider { shares } => {
+                proportion(self.provider_funds(price), shares, self.provider_shares)
+            }
+        }
+    }
+
+    pub fn deposit(
+        &mut self,
+        account: &mut Account,
+        amount: Amount,
+        price: u64,
+    ) -> Result<(), PoolError> {
+        match account {
+            Account::Seeker { cents } => {
+                let deposit_cents = msats_to_cents(amount, price);
+                if deposit_cents == 0 {
+                    return Err(PoolError::DustDeposit);
+                }
+                self.seeker_cents += deposit_cents;
+                self.total += amount;
+                *cents += deposit_cents;
+            }
+            Account::Provider { shares } => {
+                let provider_funds = self.provider_funds(price);
+                let new_shares = if self.provider_shares == 0 {
+                    amount.msats
+                } else if provider_funds == Amount::ZERO {
+                    return Err(PoolError::ProvidersWipedOut);
+                } else {
+                    (amount.msats as u128 * self.provider_shares as u128
+                        / provider_funds.msats as u128) as u64
+                };
+                if new_shares == 0 {
+                    return Err(PoolError::DustDeposit);
+                }
+                self.provider_shares += new_shares;
+                self.total += amount;
+                *shares += new_shares;
+            }
+        }
+
+        self.check_collateral(price)
+    }
+
+    pub fn withdraw(
+        &mut self,
+        account: &mut Account,
+        amount: Amount,
+        price: u64,
+    ) -> Result<(), PoolError> {
+        let funds = self.account_funds(account, price);
+        if amount > funds {
+            return Err(PoolError::InsufficientFunds(funds));
+        }
+        if amount == Amount::ZERO {
+            return Ok(());
+        }
+
+        match account {
+            Account::Seeker { cents } => {
+                let withdrawn_cents = min(*cents, proportion_up(*cents, amount, funds));
+                self.seeker_cents -= withdrawn_cents;
+                *cents -= withdrawn_cents;
+            }
+            Account::Provider { shares } => {
+                let withdrawn_shares = min(*shares, proportion_up(*shares, amount, funds));
+                self.provider_shares -= withdrawn_shares;
+                *shares -= withdrawn_shares;
+            }
+        }
+        self.total -= amount;
+
+        if account.side() == Side::Provider {
+            self.check_collateral(price)?;
+        }
+        Ok(())
+    }
+
+    /// Every seeker has to be backed by at least the same amount of provider
+    /// funds
+    fn check_collateral(&self, price: u64) -> Result<(), PoolError> {
+        if cents_to_msats(self.seeker_cents, price) > self.provider_funds(price) {
+            return Err(PoolError::InsufficientCollateral);
+        }
+        Ok(())
+    }
+}
+
+/// Value of `amount` in cents at `price` (cents per BTC), rounded down
+pub fn msats_to_cents(amount: Amount, price: u64) -> u64 {
+    (amount.msats as u128 * price as u128 / MSATS_PER_BTC) as u64
+}
+
+/// Amount worth `cents` at `price` (cents per BTC), rounded down
+pub fn cents_to_msats(cents: u64, price: u64) -> Amount {
+    Amount::from_msats((cents as u128 * MSATS_PER_BTC / price as u128) as u64)
+}
+
+/// `part / total` of `amount`, rounded down
+fn proportion(amount: Amount, part: u64, total: u64) -> Amount {
+    if total == 0 {
+        return Amount::ZERO;
+    }
+    Amount::from_msats((amount.msats as u128 * part as u128 / total as u128) as u64)
+}
+
+/// Units of `units` worth `amount` if all of them are worth `funds`, rounded
+/// up so withdrawals never take more than they paid for
+fn proportion_up(units: u64, amount: Amount, funds: Amount) -> u64 {
+    let numerator = units as u128 * amount.msats as u128;
+    let funds = funds.msats as u128;
+    ((numerator + funds - 1) / funds) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_api::Amount;
+
+    use super::{Account, PoolError, PoolState, Side};
+
+    /// $20,000 per BTC
+    const PRICE: u64 = 2_000_000;
+
+    #[test]
+    fn seekers_keep_their_usd_value() {
+        let mut pool = PoolState::default();
+        let mut provider = Account::new(Side::Provider);
+        let mut seeker = Account::new(Side::Seeker);
+
+        assert_eq!(
+            { pool }.deposit(&mut { seeker }, Amount::from_sats(100_000), PRICE),
+            Err(PoolError::InsufficientCollateral)
+        );
+        pool.deposit(&mut provider, Amount::from_sats(200_000), PRICE)
+            .unwrap();
+        pool.deposit(&mut seeker, Amount::from_sats(100_000), PRICE)
+            .unwrap();
+        assert_eq!(seeker, Account::Seeker { cents: 2_000 });
+
+        // The price halving wipes out half of the provider's funds
+        assert_eq!(
+            pool.account_funds(&seeker, PRICE / 2),
+            Amount::from_sats(200_000)
+        );
+        assert_eq!(
+            pool.account_funds(&provider, PRICE / 2),
+            Amount::from_sats(100_000)
+        );
+
+        // The price doubling doubles the provider's BTC exposure
+        assert_eq!(
+            pool.account_funds(&seeker, PRICE * 2),
+            Amount::from_sats(50_000)
+        );
+        assert_eq!(
+            pool.account_funds(&provider, PRICE * 2),
+            Amount::from_sats(250_000)
+        );
+
+        // Seekers get everything if the providers can't pay them anymore
+        assert_eq!(
+            pool.account_funds(&seeker, PRICE / 4),
+            Amount::from_sats(300_000)
+        );
+        assert_eq!(pool.account_funds(&provider, PRICE / 4), Amount::ZERO);
+    }
+
+    #[test]
+    fn withdrawals_keep_seekers_backed() {
+        let mut pool = PoolState::default();
+        let mut provider = Account::new(Side::Provider);
+        let mut seeker = Account::new(Side::Seeker);
+        pool.deposit(&mut provider, Amount::from_sats(200_000), PRICE)
+            .unwrap();
+        pool.deposit(&mut seeker, Amount::from_sats(100_000), PRICE)
+            .unwrap();
+
+        assert_eq!(
+            { pool }.withdraw(&mut { provider }, Amount::from_sats(150_000), PRICE),
+            Err(PoolError::InsufficientCollateral)
+        );
+        assert_eq!(
+            { pool }.withdraw(&mut { seeker }, Amount::from_sats(100_001), PRICE),
+            Err(PoolError::InsufficientFunds(Amount::from_sats(100_000)))
+        );
+
+        pool.withdraw(&mut seeker, Amount::from_sats(100_000), PRICE)
+            .unwrap();
+        assert_eq!(seeker, Account::Seeker { cents: 0 });
+        pool.withdraw(&mut provider, Amount::from_sats(200_000), PRICE)
+            .unwrap();
+        assert_eq!(pool, PoolState::default());
+    }
+}
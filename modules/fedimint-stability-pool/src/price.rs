@@ -0,0 +1,59 @@
+//! Fetches the BTC/USD price this guardian proposes to the federation
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use anyhow::format_err;
+use fedimint_api::task::{sleep, TaskHandle};
+use tracing::warn;
+
+use crate::config::PriceFeed;
+
+/// How often the price feed is queried
+pub const PRICE_FEED_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Prices older than this are not proposed anymore
+pub const MAX_PRICE_AGE: Duration = Duration::from_secs(600);
+
+/// Latest price in cents per BTC and when it was fetched
+pub type LatestPrice = Arc<Mutex<Option<(u64, SystemTime)>>>;
+
+pub async fn run_price_feed(feed: PriceFeed, latest: LatestPrice, handle: &TaskHandle) {
+    let client = reqwest::Client::new();
+    while !handle.is_shutting_down() {
+        match fetch_price(&client, &feed).await {
+            Ok(price) => {
+                *latest.lock().expect("lock poisoned") = Some((price, fedimint_api::time::now()));
+            }
+            Err(e) => warn!(url = %feed.url, "Fetching the BTC/USD price failed: {e}"),
+        }
+        sleep(PRICE_FEED_INTERVAL).await;
+    }
+}
+
+/// Returns the latest price unless it is too old to be proposed
+pub fn fresh_price(latest: &LatestPrice) -> Option<u64> {
+    let (price, fetched_at) = (*latest.lock().expect("lock poisoned"))?;
+    let age = fedimint_api::time::now()
+        .duration_since(fetched_at)
+        .unwrap_or_default();
+    (age <= MAX_PRICE_AGE).then_some(price)
+}
+
+async fn fetch_price(client: &reqwest::Client, feed: &PriceFeed) -> anyhow::Result<u64> {
+    let response = client
+        .get(feed.url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?;
+    let usd = response
+        .pointer(&feed.pointer)
+        .and_then(|price| price.as_f64())
+        .ok_or_else(|| format_err!("No price at {} in {response}", feed.pointer))?;
+    if !usd.is_finite() || usd < 0.01 {
+        return Err(format_err!("Invalid price {usd}"));
+    }
+    Ok((usd * 100.0).round() as u64)
+}
@@ -0,0 +1,134 @@
+//! Policies for choosing which of the federation's UTXOs fund a peg-out
+//! transaction, configured in consensus since all guardians have to build the
+//! same transaction.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::{bail, format_err};
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::Feerate;
+use serde::{Deserialize, Serialize};
+
+use crate::db::UTXOKey;
+use crate::SpendableUTXO;
+
+#[derive(
+    Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable,
+)]
+pub enum CoinSelection {
+    /// Spends the largest UTXOs first to keep the number of inputs and thereby
+    /// the fees low
+    #[default]
+    MinimizeInputs,
+    /// Like [`CoinSelection::MinimizeInputs`], but while the fee rate is at
+    /// most `max_fee_rate` additionally spends the smallest UTXOs, up to
+    /// `max_inputs` inputs in total, so they don't become uneconomical to
+    /// spend once fees rise
+    Consolidate {
+        max_fee_rate: Feerate,
+        max_inputs: u32,
+    },
+    /// Like [`CoinSelection::MinimizeInputs`], but only spends deposits of at
+    /// least `min_amount` we received less than `min_age` blocks ago if the
+    /// other UTXOs don't suffice, so large deposits aren't linked to the
+    /// peg-outs following them
+    AvoidRecentDeposits {
+        #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+        min_amount: bitcoin::Amount,
+        min_age: u32,
+    },
+}
+
+/// Consensus state coin selection depends on besides the UTXOs themselves
+pub(crate) struct CoinSelectionContext {
+    /// Consensus block height the transaction is created at
+    pub block_height: u32,
+    /// Consensus block heights at which we received our UTXOs, missing for
+    /// UTXOs received before we recorded them
+    pub utxo_heights: BTreeMap<bitcoin::OutPoint, u32>,
+    /// Whether additional UTXOs may be spent to consolidate them, disabled
+    /// when quoting fees that the user has to pay
+    pub allow_consolidation: bool,
+}
+
+impl CoinSelection {
+    /// Splits `utxos` into the ones to spend first and the ones to only spend
+    /// if the former don't suffice, each sorted by ascending amount so the
+    /// next one to spend can be popped off the end
+    pub(crate) fn prioritize(
+        &self,
+        mut utxos: Vec<(UTXOKey, SpendableUTXO)>,
+        context: &CoinSelectionContext,
+    ) -> (Vec<(UTXOKey, SpendableUTXO)>, Vec<(UTXOKey, SpendableUTXO)>) {
+        utxos.sort_by_key(|(_, utxo)| utxo.amount);
+        match self {
+            CoinSelection::MinimizeInputs | CoinSelection::Consolidate { .. } => (utxos, vec![]),
+            CoinSelection::AvoidRecentDeposits {
+                min_amount,
+                min_age,
+            } => utxos.into_iter().partition(|(key, utxo)| {
+                let received_height = context.utxo_heights.get(&key.0);
+                let is_recent = received_height
+                    .map(|height| height.saturating_add(*min_age) > context.block_height)
+                    .unwrap_or(false);
+                utxo.amount < *min_amount || !is_recent
+            }),
+        }
+    }
+
+    /// Number of inputs to fill the transaction up to with small UTXOs when
+    /// paying `fee_rate`, `None` if we shouldn't spend more than needed
+    pub(crate) fn consolidation_inputs(
+        &self,
+        fee_rate: Feerate,
+        context: &CoinSelectionContext,
+    ) -> Option<usize> {
+        match self {
+            CoinSelection::Consolidate {
+                max_fee_rate,
+                max_inputs,
+            } if context.allow_consolidation && fee_rate <= *max_fee_rate => {
+                Some(*max_inputs as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for CoinSelection {
+    type Err = anyhow::Error;
+
+    /// Parses `minimize-inputs`, `consolidate:<max sats/kvB>:<max inputs>` or
+    /// `avoid-recent-deposits:<min amount in sats>:<min age in blocks>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let policy = parts.next().unwrap_or_default();
+        let mut next_param = |name: &str| {
+            parts
+                .next()
+                .ok_or_else(|| format_err!("Coin selection {policy} is missing {name}"))
+        };
+        let coin_selection = match policy {
+            "minimize-inputs" => CoinSelection::MinimizeInputs,
+            "consolidate" => CoinSelection::Consolidate {
+                max_fee_rate: Feerate {
+                    sats_per_kvb: next_param("the max fee rate")?.parse()?,
+                },
+                max_inputs: next_param("the max number of inputs")?.parse()?,
+            },
+            "avoid-recent-deposits" => CoinSelection::AvoidRecentDeposits {
+                min_amount: bitcoin::Amount::from_sat(next_param("the min amount")?.parse()?),
+                min_age: next_param("the min age")?.parse()?,
+            },
+            other => bail!(
+                "Unknown coin selection {other}, expected minimize-inputs, consolidate or \
+                 avoid-recent-deposits"
+            ),
+        };
+        if parts.next().is_some() {
+            bail!("Too many parameters for coin selection {policy}");
+        }
+        Ok(coin_selection)
+    }
+}
@@ -1,20 +1,26 @@
 use std::collections::BTreeMap;
+use std::str::FromStr;
 
 use anyhow::bail;
 use anyhow::format_err;
+use bitcoin::hashes::{sha256, Hash as BitcoinHash};
 use bitcoin::Network;
+use fedimint_api::bitcoin_rpc::BitcoindRpcBackend;
 use fedimint_api::config::ClientModuleConfig;
 use fedimint_api::config::TypedServerModuleConfig;
 use fedimint_api::config::{TypedClientModuleConfig, TypedServerModuleConsensusConfig};
 use fedimint_api::core::ModuleKind;
-use fedimint_api::encoding::Encodable;
+use fedimint_api::encoding::{Decodable, Encodable};
 use fedimint_api::{Feerate, PeerId};
 use miniscript::descriptor::Wsh;
 use secp256k1::SecretKey;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use url::Url;
 
+use crate::coin_selection::CoinSelection;
+use crate::hardware_signer::HardwareSignerConfig;
 use crate::keys::CompressedPublicKey;
-use crate::PegInDescriptor;
+use crate::{PegInDescriptor, WalletGenParams};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WalletConfig {
@@ -27,13 +33,51 @@ pub struct WalletConfig {
     pub consensus: WalletConfigConsensus,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct WalletConfigLocal;
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct WalletConfigLocal {
+    /// Prioritized bitcoind RPC endpoints, if set they take precedence over the
+    /// backend chosen by environment variables and we fail over to the next
+    /// one whenever a call fails
+    pub bitcoind_rpcs: Vec<Url>,
+    /// Bitcoin backend used if no prioritized bitcoind endpoints are set, takes
+    /// precedence over the backend chosen by environment variables
+    pub bitcoin_backend: Option<BitcoindRpcBackend>,
+    /// External signer holding our peg-in key, see [`crate::hardware_signer`]
+    pub hardware_signer: Option<HardwareSignerConfig>,
+}
+
+impl<'de> Deserialize<'de> for WalletConfigLocal {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Configs written before the local config had any fields contain `null`
+        #[derive(Deserialize)]
+        struct Fields {
+            #[serde(default)]
+            bitcoind_rpcs: Vec<Url>,
+            #[serde(default)]
+            bitcoin_backend: Option<BitcoindRpcBackend>,
+            #[serde(default)]
+            hardware_signer: Option<HardwareSignerConfig>,
+        }
+
+        Ok(Option::<Fields>::deserialize(deserializer)?
+            .map(|fields| WalletConfigLocal {
+                bitcoind_rpcs: fields.bitcoind_rpcs,
+                bitcoin_backend: fields.bitcoin_backend,
+                hardware_signer: fields.hardware_signer,
+            })
+            .unwrap_or_default())
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WalletConfigPrivate {
-    /// Secret key for signing bitcoin multisig transactions
-    pub peg_in_key: SecretKey,
+    /// Secret key for signing bitcoin multisig transactions, `None` if it is
+    /// only held by the hardware signer of the local config
+    #[serde(default)]
+    pub peg_in_key: Option<SecretKey>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Encodable)]
@@ -47,11 +91,26 @@ pub struct WalletConfigConsensus {
     /// How many bitcoin blocks to wait before considering a transaction
     /// confirmed
     pub finality_delay: u32,
+    /// Additional confirmation requirements for larger peg-ins, only hashed
+    /// if there are any, see [`WalletConfigConsensus::config_hash`]
+    #[serde(default)]
+    #[encodable_ignore]
+    pub peg_in_finality_delays: Vec<PegInFinalityDelay>,
+    /// Where all funds are swept to in an emergency, see
+    /// [`crate::emergency_sweep`]. Only hashed if set.
+    #[serde(default)]
+    #[encodable_ignore]
+    pub recovery_descriptor: Option<PegInDescriptor>,
     /// If we cannot determine the feerate from our bitcoin node, default to
     /// this
     pub default_fee: Feerate,
     /// Fees for bitcoin transactions
     pub fee_consensus: FeeConsensus,
+    /// How UTXOs are chosen to fund peg-outs, only hashed if it isn't the
+    /// default
+    #[serde(default)]
+    #[encodable_ignore]
+    pub coin_selection: CoinSelection,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable)]
@@ -62,9 +121,169 @@ pub struct WalletClientConfig {
     pub network: Network,
     /// Confirmations required for a peg in to be accepted by federation
     pub finality_delay: u32,
+    /// Additional confirmation requirements for larger peg-ins, only hashed
+    /// if there are any
+    #[serde(default)]
+    #[encodable_ignore]
+    pub peg_in_finality_delays: Vec<PegInFinalityDelay>,
     pub fee_consensus: FeeConsensus,
 }
 
+impl WalletClientConfig {
+    /// Hash of the config the guardians agree on. Fields added after the
+    /// first federations were created are only hashed if set, so their
+    /// configs keep the hash they had.
+    pub fn config_hash(&self) -> anyhow::Result<sha256::Hash> {
+        let mut engine = sha256::HashEngine::default();
+        self.consensus_encode(&mut engine)?;
+        self.fee_consensus.encode_reserve_spread(&mut engine)?;
+        if !self.peg_in_finality_delays.is_empty() {
+            self.peg_in_finality_delays.consensus_encode(&mut engine)?;
+        }
+        Ok(sha256::Hash::from_engine(engine))
+    }
+}
+
+/// Peg-ins of at least `min_amount` need `finality_delay` confirmations
+/// instead of the federation's default
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct PegInFinalityDelay {
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub min_amount: bitcoin::Amount,
+    pub finality_delay: u32,
+}
+
+impl FromStr for PegInFinalityDelay {
+    type Err = anyhow::Error;
+
+    /// Parses `<min amount in sats>:<finality delay>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min_amount, finality_delay) = s
+            .split_once(':')
+            .ok_or_else(|| format_err!("Expected <min amount in sats>:<finality delay>"))?;
+        Ok(PegInFinalityDelay {
+            min_amount: bitcoin::Amount::from_sat(min_amount.parse()?),
+            finality_delay: finality_delay.parse()?,
+        })
+    }
+}
+
+/// Spending path for cold keys of the guardians that only opens up after a
+/// timelock, so funds aren't lost if the hot threshold of the federation is
+/// destroyed
+///
+/// Like the hot keys the cold keys are tweaked per UTXO, so recovering a UTXO
+/// requires its tweak from a guardian's database backup.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TimelockedRecovery {
+    pub cold_keys: Vec<CompressedPublicKey>,
+    /// Number of cold keys needed to spend via the recovery path
+    pub threshold: usize,
+    pub timelock: RecoveryTimelock,
+}
+
+impl TimelockedRecovery {
+    pub fn sanity_check(&self) -> anyhow::Result<()> {
+        if self.threshold == 0 || self.threshold > self.cold_keys.len() {
+            bail!(
+                "Recovery threshold {} has to be between 1 and the number of cold keys ({})",
+                self.threshold,
+                self.cold_keys.len()
+            );
+        }
+        // Limit of the multi fragment
+        if self.cold_keys.len() > 20 {
+            bail!("At most 20 cold keys are supported");
+        }
+        match self.timelock {
+            RecoveryTimelock::Older(0) | RecoveryTimelock::After(0) => {
+                bail!("Recovery timelock must not be zero")
+            }
+            RecoveryTimelock::After(height) if height >= LOCK_TIME_THRESHOLD => {
+                bail!("Recovery timelock has to be a block height below {LOCK_TIME_THRESHOLD}")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Values of `nLockTime` from this on are interpreted as timestamps
+const LOCK_TIME_THRESHOLD: u32 = 500_000_000;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RecoveryTimelock {
+    /// Blocks a UTXO has to stay unspent (CSV), i.e. the recovery path opens
+    /// after the federation has been inactive for that long
+    Older(u16),
+    /// Block height from which on all UTXOs can be recovered (CLTV)
+    After(u32),
+}
+
+impl FromStr for RecoveryTimelock {
+    type Err = anyhow::Error;
+
+    /// Parses `older:<blocks>` or `after:<block height>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("older", blocks)) => Ok(RecoveryTimelock::Older(blocks.parse()?)),
+            Some(("after", height)) => Ok(RecoveryTimelock::After(height.parse()?)),
+            _ => bail!("Expected older:<blocks> or after:<block height>"),
+        }
+    }
+}
+
+impl std::fmt::Display for RecoveryTimelock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecoveryTimelock::Older(blocks) => write!(f, "older({blocks})"),
+            RecoveryTimelock::After(height) => write!(f, "after({height})"),
+        }
+    }
+}
+
+/// Descriptor of the federation's wallet: a `threshold` of `pubkeys` can spend,
+/// or a threshold of the cold keys of `recovery` once its timelock expired
+pub fn peg_in_descriptor(
+    pubkeys: &BTreeMap<PeerId, CompressedPublicKey>,
+    threshold: usize,
+    recovery: Option<&TimelockedRecovery>,
+) -> anyhow::Result<PegInDescriptor> {
+    let Some(recovery) = recovery else {
+        return Ok(PegInDescriptor::Wsh(Wsh::new_sortedmulti(
+            threshold,
+            pubkeys.values().copied().collect(),
+        )?));
+    };
+    recovery.sanity_check()?;
+
+    fn join_keys<'a>(keys: impl Iterator<Item = &'a CompressedPublicKey>) -> String {
+        keys.map(ToString::to_string).collect::<Vec<_>>().join(",")
+    }
+    let descriptor = PegInDescriptor::from_str(&format!(
+        "wsh(or_d(multi({threshold},{}),and_v(v:multi({},{}),{})))",
+        join_keys(pubkeys.values()),
+        recovery.threshold,
+        join_keys(recovery.cold_keys.iter()),
+        recovery.timelock,
+    ))?;
+    descriptor.sanity_check()?;
+    Ok(descriptor)
+}
+
+/// The strictest requirement applying to `amount`, never less than
+/// `finality_delay`
+fn peg_in_finality_delay(
+    finality_delay: u32,
+    delays: &[PegInFinalityDelay],
+    amount: bitcoin::Amount,
+) -> u32 {
+    delays
+        .iter()
+        .filter(|delay| delay.min_amount <= amount)
+        .map(|delay| delay.finality_delay)
+        .fold(finality_delay, u32::max)
+}
+
 impl TypedClientModuleConfig for WalletClientConfig {
     fn kind(&self) -> ModuleKind {
         crate::KIND
@@ -75,6 +294,55 @@ impl TypedClientModuleConfig for WalletClientConfig {
 pub struct FeeConsensus {
     pub peg_in_abs: fedimint_api::Amount,
     pub peg_out_abs: fedimint_api::Amount,
+    /// Parts per million of each peg-in's amount charged on top of
+    /// `peg_in_abs` to fund the on-chain fee reserve. The spread is hashed by
+    /// the configs containing the fees only if there is one, see
+    /// [`FeeConsensus::encode_reserve_spread`].
+    #[serde(default)]
+    #[encodable_ignore]
+    pub peg_in_reserve_ppm: u64,
+    /// Parts per million of each peg-out's amount charged on top of
+    /// `peg_out_abs` to fund the on-chain fee reserve
+    #[serde(default)]
+    #[encodable_ignore]
+    pub peg_out_reserve_ppm: u64,
+}
+
+impl FeeConsensus {
+    /// Encodes the reserve spread into the config hash `engine` unless the
+    /// fees have none, so configs created before it existed keep their hash
+    fn encode_reserve_spread(&self, engine: &mut sha256::HashEngine) -> std::io::Result<()> {
+        if self.peg_in_reserve_ppm != 0 || self.peg_out_reserve_ppm != 0 {
+            self.peg_in_reserve_ppm.consensus_encode(engine)?;
+            self.peg_out_reserve_ppm.consensus_encode(engine)?;
+        }
+        Ok(())
+    }
+
+    /// Part of the fee of a peg-in of `amount` that goes into the fee reserve
+    pub fn peg_in_reserve_spread(&self, amount: bitcoin::Amount) -> bitcoin::Amount {
+        reserve_spread(amount, self.peg_in_reserve_ppm)
+    }
+
+    /// Part of the fee of a peg-out of `amount` that goes into the fee reserve
+    pub fn peg_out_reserve_spread(&self, amount: bitcoin::Amount) -> bitcoin::Amount {
+        reserve_spread(amount, self.peg_out_reserve_ppm)
+    }
+
+    /// Total federation fee for a peg-in of `amount`
+    pub fn peg_in_fee(&self, amount: bitcoin::Amount) -> fedimint_api::Amount {
+        self.peg_in_abs + fedimint_api::Amount::from(self.peg_in_reserve_spread(amount))
+    }
+
+    /// Total federation fee for a peg-out of `amount`, not including the
+    /// on-chain fees of the peg-out transaction
+    pub fn peg_out_fee(&self, amount: bitcoin::Amount) -> fedimint_api::Amount {
+        self.peg_out_abs + fedimint_api::Amount::from(self.peg_out_reserve_spread(amount))
+    }
+}
+
+fn reserve_spread(amount: bitcoin::Amount, ppm: u64) -> bitcoin::Amount {
+    bitcoin::Amount::from_sat((amount.to_sat() as u128 * ppm as u128 / 1_000_000) as u64)
 }
 
 impl Default for FeeConsensus {
@@ -82,10 +350,37 @@ impl Default for FeeConsensus {
         Self {
             peg_in_abs: fedimint_api::Amount::ZERO,
             peg_out_abs: fedimint_api::Amount::ZERO,
+            peg_in_reserve_ppm: 0,
+            peg_out_reserve_ppm: 0,
         }
     }
 }
 
+impl WalletConfigConsensus {
+    /// Hash of the config the guardians agree on, see
+    /// [`WalletClientConfig::config_hash`]
+    pub fn config_hash(&self) -> anyhow::Result<sha256::Hash> {
+        let mut engine = sha256::HashEngine::default();
+        self.consensus_encode(&mut engine)?;
+        self.fee_consensus.encode_reserve_spread(&mut engine)?;
+        if !self.peg_in_finality_delays.is_empty() {
+            self.peg_in_finality_delays.consensus_encode(&mut engine)?;
+        }
+        if let Some(recovery_descriptor) = &self.recovery_descriptor {
+            recovery_descriptor.consensus_encode(&mut engine)?;
+        }
+        if self.coin_selection != CoinSelection::default() {
+            self.coin_selection.consensus_encode(&mut engine)?;
+        }
+        Ok(sha256::Hash::from_engine(engine))
+    }
+
+    /// Confirmations required for a peg-in of `amount` to be accepted
+    pub fn peg_in_finality_delay(&self, amount: bitcoin::Amount) -> u32 {
+        peg_in_finality_delay(self.finality_delay, &self.peg_in_finality_delays, amount)
+    }
+}
+
 impl TypedServerModuleConsensusConfig for WalletConfigConsensus {
     fn to_client_config(&self) -> ClientModuleConfig {
         ClientModuleConfig::new(
@@ -95,6 +390,7 @@ impl TypedServerModuleConsensusConfig for WalletConfigConsensus {
                 network: self.network,
                 fee_consensus: self.fee_consensus.clone(),
                 finality_delay: self.finality_delay,
+                peg_in_finality_delays: self.peg_in_finality_delays.clone(),
             })
             .expect("Serialization can't fail"),
         )
@@ -119,18 +415,34 @@ impl TypedServerModuleConfig for WalletConfig {
     }
 
     fn validate_config(&self, identity: &PeerId) -> anyhow::Result<()> {
-        let pubkey = secp256k1::PublicKey::from_secret_key_global(&self.private.peg_in_key);
+        let pubkey = self.peg_in_public_key().ok_or_else(|| {
+            format_err!("Bitcoin wallet needs a private key or a hardware signer holding it")
+        })?;
 
         if self
             .consensus
             .peer_peg_in_keys
             .get(identity)
             .ok_or_else(|| format_err!("Secret key doesn't match any public key"))?
-            != &CompressedPublicKey::new(pubkey)
+            != &pubkey
         {
             bail!(" Bitcoin wallet private key doesn't match multisig pubkey");
         }
 
+        if let (Some(secret_key), Some(signer)) =
+            (&self.private.peg_in_key, &self.local.hardware_signer)
+        {
+            let secret_pubkey =
+                CompressedPublicKey::new(secp256k1::PublicKey::from_secret_key_global(secret_key));
+            if secret_pubkey != signer.public_key {
+                bail!("Hardware signer key doesn't match the bitcoin wallet private key");
+            }
+        }
+
+        if let Some(recovery_descriptor) = &self.consensus.recovery_descriptor {
+            recovery_descriptor.sanity_check()?;
+        }
+
         Ok(())
     }
 }
@@ -140,26 +452,46 @@ impl WalletConfig {
         pubkeys: BTreeMap<PeerId, CompressedPublicKey>,
         sk: SecretKey,
         threshold: usize,
-        network: Network,
-        finality_delay: u32,
+        params: &WalletGenParams,
     ) -> Self {
-        let peg_in_descriptor = PegInDescriptor::Wsh(
-            Wsh::new_sortedmulti(threshold, pubkeys.values().copied().collect()).unwrap(),
-        );
+        let peg_in_descriptor =
+            peg_in_descriptor(&pubkeys, threshold, params.timelocked_recovery.as_ref())
+                .expect("Invalid peg-in descriptor parameters");
 
         Self {
-            local: WalletConfigLocal,
-            private: WalletConfigPrivate { peg_in_key: sk },
+            local: WalletConfigLocal {
+                bitcoind_rpcs: params.bitcoind_rpcs.clone(),
+                bitcoin_backend: params.bitcoin_backend.clone(),
+                hardware_signer: None,
+            },
+            private: WalletConfigPrivate {
+                peg_in_key: Some(sk),
+            },
             consensus: WalletConfigConsensus {
-                network,
+                network: params.network,
                 peg_in_descriptor,
                 peer_peg_in_keys: pubkeys,
-                finality_delay,
+                finality_delay: params.finality_delay,
+                peg_in_finality_delays: params.peg_in_finality_delays.clone(),
+                recovery_descriptor: params.recovery_descriptor.clone(),
                 default_fee: Feerate { sats_per_kvb: 1000 },
-                fee_consensus: Default::default(),
+                fee_consensus: params.fee_consensus.clone(),
+                coin_selection: params.coin_selection.clone(),
             },
         }
     }
+
+    /// Our key of the peg-in multisig, derived from the private key or else
+    /// the one of the hardware signer
+    pub fn peg_in_public_key(&self) -> Option<CompressedPublicKey> {
+        match (&self.private.peg_in_key, &self.local.hardware_signer) {
+            (Some(secret_key), _) => Some(CompressedPublicKey::new(
+                secp256k1::PublicKey::from_secret_key_global(secret_key),
+            )),
+            (None, Some(signer)) => Some(signer.public_key),
+            (None, None) => None,
+        }
+    }
 }
 
 impl WalletClientConfig {
@@ -172,7 +504,13 @@ impl WalletClientConfig {
             peg_in_descriptor,
             network,
             finality_delay,
+            peg_in_finality_delays: vec![],
             fee_consensus: Default::default(),
         }
     }
+
+    /// Confirmations required for a peg-in of `amount` to be accepted
+    pub fn peg_in_finality_delay(&self, amount: bitcoin::Amount) -> u32 {
+        peg_in_finality_delay(self.finality_delay, &self.peg_in_finality_delays, amount)
+    }
 }
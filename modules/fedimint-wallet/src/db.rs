@@ -1,12 +1,20 @@
-use bitcoin::{BlockHash, Txid};
+use anyhow::format_err;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::{BlockHash, Transaction, Txid};
+use fedimint_api::db::{migrate_values, DatabaseTransaction};
 use fedimint_api::encoding::{Decodable, Encodable};
-use fedimint_api::impl_db_prefix_const;
+use fedimint_api::PeerId;
+use fedimint_api::{impl_db_prefix_const, Feerate};
+use futures::future::BoxFuture;
 use secp256k1::ecdsa::Signature;
 use serde::Serialize;
 use strum_macros::EnumIter;
 
+use crate::emergency_sweep::EmergencySweepStep;
 use crate::{
-    PendingTransaction, RoundConsensus, SpendableUTXO, UnsignedTransaction, WalletOutputOutcome,
+    proprietary_tweak_key, AcceleratedPegIn, PegInAcceleration, PegOut, PegOutFees,
+    PegOutSignatureItem, PendingTransaction, RoundConsensus, SpendableUTXO, UnsignedTransaction,
+    WalletOutputOutcome,
 };
 
 #[repr(u8)]
@@ -15,10 +23,20 @@ pub enum DbKeyPrefix {
     BlockHash = 0x30,
     Utxo = 0x31,
     RoundConsensus = 0x32,
+    QueuedPegOut = 0x33,
     UnsignedTransaction = 0x34,
     PendingTransaction = 0x35,
     PegOutTxSigCi = 0x36,
     PegOutBitcoinOutPoint = 0x37,
+    PegInAccelerationRequest = 0x38,
+    AcceleratedPegIn = 0x39,
+    BlockHeight = 0x3a,
+    EmergencySweepApproval = 0x3b,
+    PendingEmergencySweepApproval = 0x3c,
+    EmergencySweepTx = 0x3d,
+    FeeReserve = 0x3e,
+    UtxoHeight = 0x3f,
+    RoundConsensusPeers = 0x40,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -40,9 +58,38 @@ impl_db_prefix_const!(
     key_prefix = BlockHashKeyPrefix
 );
 
+/// Height of the blocks in [`BlockHashKey`], only recorded for blocks synced
+/// since peg-in confirmation requirements depend on the amount
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct BlockHeightKey(pub BlockHash);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct BlockHeightKeyPrefix;
+
+impl_db_prefix_const!(
+    key = BlockHeightKey,
+    value = u32,
+    prefix = DbKeyPrefix::BlockHeight,
+    key_prefix = BlockHeightKeyPrefix
+);
+
 #[derive(Clone, Debug, Encodable, Decodable, Serialize)]
 pub struct UTXOKey(pub bitcoin::OutPoint);
 
+/// Consensus block height at which a UTXO in [`UTXOKey`] became spendable
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct UtxoHeightKey(pub bitcoin::OutPoint);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct UtxoHeightPrefixKey;
+
+impl_db_prefix_const!(
+    key = UtxoHeightKey,
+    value = u32,
+    prefix = DbKeyPrefix::UtxoHeight,
+    key_prefix = UtxoHeightPrefixKey
+);
+
 #[derive(Clone, Debug, Encodable, Decodable)]
 pub struct UTXOPrefixKey;
 
@@ -62,6 +109,31 @@ impl_db_prefix_const!(
     prefix = DbKeyPrefix::RoundConsensus,
 );
 
+/// Peers that proposed a round consensus item in the current epoch
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct RoundConsensusPeersKey;
+
+impl_db_prefix_const!(
+    key = RoundConsensusPeersKey,
+    value = Vec<PeerId>,
+    prefix = DbKeyPrefix::RoundConsensusPeers,
+);
+
+/// Peg-out accepted in the current epoch, all of them are batched into a
+/// single transaction at the end of the epoch
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct QueuedPegOutKey(pub fedimint_api::OutPoint);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct QueuedPegOutPrefixKey;
+
+impl_db_prefix_const!(
+    key = QueuedPegOutKey,
+    value = PegOut,
+    prefix = DbKeyPrefix::QueuedPegOut,
+    key_prefix = QueuedPegOutPrefixKey
+);
+
 #[derive(Clone, Debug, Encodable, Decodable, Serialize)]
 pub struct UnsignedTransactionKey(pub Txid);
 
@@ -113,3 +185,188 @@ impl_db_prefix_const!(
     prefix = DbKeyPrefix::PegOutBitcoinOutPoint,
     key_prefix = PegOutBitcoinTransactionPrefix
 );
+
+/// Deposits users asked us to accelerate, proposed until the federation agrees
+/// on it or the deposit gets claimed
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct PegInAccelerationRequestKey(pub bitcoin::OutPoint);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct PegInAccelerationRequestPrefixKey;
+
+impl_db_prefix_const!(
+    key = PegInAccelerationRequestKey,
+    value = PegInAcceleration,
+    prefix = DbKeyPrefix::PegInAccelerationRequest,
+    key_prefix = PegInAccelerationRequestPrefixKey
+);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct AcceleratedPegInKey(pub bitcoin::OutPoint);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct AcceleratedPegInPrefixKey;
+
+impl_db_prefix_const!(
+    key = AcceleratedPegInKey,
+    value = AcceleratedPegIn,
+    prefix = DbKeyPrefix::AcceleratedPegIn,
+    key_prefix = AcceleratedPegInPrefixKey
+);
+
+/// Emergency sweep approvals accepted in consensus
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct EmergencySweepApprovalKey(pub EmergencySweepStep, pub PeerId);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct EmergencySweepApprovalPrefixKey;
+
+impl_db_prefix_const!(
+    key = EmergencySweepApprovalKey,
+    value = Signature,
+    prefix = DbKeyPrefix::EmergencySweepApproval,
+    key_prefix = EmergencySweepApprovalPrefixKey
+);
+
+/// Emergency sweep approvals submitted to us that we propose until they are
+/// accepted
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct PendingEmergencySweepApprovalKey(pub EmergencySweepStep, pub PeerId);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct PendingEmergencySweepApprovalPrefixKey;
+
+impl_db_prefix_const!(
+    key = PendingEmergencySweepApprovalKey,
+    value = Signature,
+    prefix = DbKeyPrefix::PendingEmergencySweepApproval,
+    key_prefix = PendingEmergencySweepApprovalPrefixKey
+);
+
+/// Transactions sweeping our funds to the recovery descriptor and the amount
+/// they swept
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct EmergencySweepTxKey(pub Txid);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct EmergencySweepTxPrefixKey;
+
+impl_db_prefix_const!(
+    key = EmergencySweepTxKey,
+    value = bitcoin::Amount,
+    prefix = DbKeyPrefix::EmergencySweepTx,
+    key_prefix = EmergencySweepTxPrefixKey
+);
+
+/// Funds set aside for on-chain fees the federation pays itself
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct FeeReserveKey;
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct FeeReserveKeyPrefix;
+
+impl_db_prefix_const!(
+    key = FeeReserveKey,
+    value = bitcoin::Amount,
+    prefix = DbKeyPrefix::FeeReserve,
+    key_prefix = FeeReserveKeyPrefix
+);
+
+/// Encoding of [`UnsignedTransaction`] in database version 0
+#[derive(Debug, Encodable, Decodable)]
+pub struct UnsignedTransactionV0 {
+    pub psbt: PartiallySignedTransaction,
+    pub signatures: Vec<(PeerId, PegOutSignatureItem)>,
+    pub change: bitcoin::Amount,
+    pub fees: PegOutFees,
+}
+
+/// Encoding of [`PendingTransaction`] in database version 0
+#[derive(Debug, Encodable, Decodable)]
+pub struct PendingTransactionV0 {
+    pub tx: Transaction,
+    pub tweak: [u8; 32],
+    pub change: bitcoin::Amount,
+}
+
+/// Adds the data needed for fee bumping to stored peg-out transactions.
+///
+/// The UTXOs spent by unsigned transactions are recovered from their PSBT.
+/// Those spent by pending transactions aren't stored anywhere anymore, so these
+/// can't be fee bumped and are only rebroadcast until they confirm. Their fee
+/// rate and finalization height are unknown and set to the current consensus.
+pub fn migrate_to_v1<'r, 'tx>(
+    dbtx: &'r mut DatabaseTransaction<'tx>,
+) -> BoxFuture<'r, anyhow::Result<()>> {
+    Box::pin(async move {
+        migrate_values::<UnsignedTransactionPrefixKey, UnsignedTransactionV0>(dbtx, |old| {
+            let selected_utxos = old
+                .psbt
+                .unsigned_tx
+                .input
+                .iter()
+                .zip(&old.psbt.inputs)
+                .map(|(tx_input, psbt_input)| {
+                    let outpoint = tx_input.previous_output;
+                    let tweak = psbt_input
+                        .proprietary
+                        .get(&proprietary_tweak_key())
+                        .and_then(|tweak| tweak.clone().try_into().ok())
+                        .ok_or_else(|| format_err!("Missing tweak of peg-out input {outpoint}"))?;
+                    let amount = psbt_input
+                        .witness_utxo
+                        .as_ref()
+                        .map(|utxo| bitcoin::Amount::from_sat(utxo.value))
+                        .ok_or_else(|| format_err!("Missing UTXO of peg-out input {outpoint}"))?;
+                    Ok((UTXOKey(outpoint), SpendableUTXO { tweak, amount }))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            Ok(UnsignedTransaction {
+                psbt: old.psbt,
+                signatures: old.signatures,
+                change: old.change,
+                fees: old.fees,
+                selected_utxos,
+                replaces: None,
+            })
+        })
+        .await?;
+
+        let round_consensus = dbtx.get_value(&RoundConsensusKey).await?;
+        let finalized_height = round_consensus.as_ref().map_or(0, |rc| rc.block_height);
+        let fee_rate = round_consensus.map_or(Feerate { sats_per_kvb: 0 }, |rc| rc.fee_rate);
+        migrate_values::<PendingTransactionPrefixKey, PendingTransactionV0>(dbtx, |old| {
+            Ok(PendingTransaction {
+                fees: PegOutFees {
+                    fee_rate,
+                    total_weight: old.tx.weight() as u64,
+                },
+                tx: old.tx,
+                tweak: old.tweak,
+                change: old.change,
+                selected_utxos: vec![],
+                finalized_height,
+                replaces: None,
+            })
+        })
+        .await
+    })
+}
+
+/// Encoding of [`WalletOutputOutcome`] in database version 1
+#[derive(Debug, Encodable, Decodable)]
+pub struct WalletOutputOutcomeV1(pub Txid);
+
+/// Turns the peg-out transaction ids into outpoints, peg-outs used to get a
+/// transaction of their own paying them in its first output
+pub fn migrate_to_v2<'r, 'tx>(
+    dbtx: &'r mut DatabaseTransaction<'tx>,
+) -> BoxFuture<'r, anyhow::Result<()>> {
+    Box::pin(async move {
+        migrate_values::<PegOutBitcoinTransactionPrefix, WalletOutputOutcomeV1>(dbtx, |old| {
+            Ok(WalletOutputOutcome(bitcoin::OutPoint::new(old.0, 0)))
+        })
+        .await
+    })
+}
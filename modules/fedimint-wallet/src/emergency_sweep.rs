@@ -0,0 +1,148 @@
+//! Threshold-approved sweep of all federation funds to a pre-agreed recovery
+//! descriptor, e.g. when guardians have to abandon compromised infrastructure.
+//!
+//! Every guardian approves a sweep in two steps by signing with its peg-in
+//! key: first a threshold has to arm it, only afterwards the confirmations of
+//! a threshold make the federation sign the sweep transaction. Approvals are
+//! created offline with `fedimintd sign-emergency-sweep` and can be submitted
+//! to any guardian, so no single operator can trigger a sweep.
+
+use std::collections::BTreeMap;
+use std::hash::Hasher;
+use std::str::FromStr;
+
+use anyhow::{bail, format_err};
+use bitcoin::hashes::{sha256, Hash as BitcoinHash, HashEngine};
+use bitcoin::Txid;
+use fedimint_api::encoding::{Decodable, Encodable};
+use fedimint_api::PeerId;
+use secp256k1::{Message, Secp256k1};
+use serde::{Deserialize, Serialize};
+
+use crate::config::WalletConfig;
+use crate::keys::CompressedPublicKey;
+use crate::PegInDescriptor;
+
+const EMERGENCY_SWEEP_TAG: &[u8] = b"fedimint-emergency-sweep";
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
+pub enum EmergencySweepStep {
+    /// Announces the intention to sweep, has no effect on its own
+    Arm,
+    /// Only counted once a threshold armed the sweep
+    Confirm,
+}
+
+impl FromStr for EmergencySweepStep {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "arm" => Ok(EmergencySweepStep::Arm),
+            "confirm" => Ok(EmergencySweepStep::Confirm),
+            other => bail!("Unknown emergency sweep step {other}, expected arm or confirm"),
+        }
+    }
+}
+
+/// A guardian's signed approval of one step of the emergency sweep
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable)]
+pub struct EmergencySweepApproval {
+    pub peer: PeerId,
+    pub step: EmergencySweepStep,
+    pub signature: secp256k1::ecdsa::Signature,
+}
+
+impl EmergencySweepApproval {
+    /// Signs `step` of the sweep to the recovery descriptor of `cfg` as `peer`
+    pub fn new(cfg: &WalletConfig, peer: PeerId, step: EmergencySweepStep) -> anyhow::Result<Self> {
+        let recovery_descriptor = cfg
+            .consensus
+            .recovery_descriptor
+            .as_ref()
+            .ok_or_else(|| format_err!("The federation has no recovery descriptor"))?;
+        let peg_in_key = cfg
+            .private
+            .peg_in_key
+            .as_ref()
+            .ok_or_else(|| format_err!("The peg-in key is only held by the hardware signer"))?;
+        let message = approval_message(peer, step, recovery_descriptor);
+        let signature = Secp256k1::signing_only().sign_ecdsa(&message, peg_in_key);
+        Ok(EmergencySweepApproval {
+            peer,
+            step,
+            signature,
+        })
+    }
+
+    /// Checks that the approval was signed by the peg-in key of its peer
+    pub fn verify(
+        &self,
+        recovery_descriptor: &PegInDescriptor,
+        peer_peg_in_keys: &BTreeMap<PeerId, CompressedPublicKey>,
+    ) -> bool {
+        let Some(key) = peer_peg_in_keys.get(&self.peer) else {
+            return false;
+        };
+        let message = approval_message(self.peer, self.step, recovery_descriptor);
+        Secp256k1::verification_only()
+            .verify_ecdsa(&message, &self.signature, &key.key)
+            .is_ok()
+    }
+}
+
+impl std::hash::Hash for EmergencySweepApproval {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.peer.hash(state);
+        self.step.hash(state);
+        self.signature.serialize_der().hash(state);
+    }
+}
+
+impl PartialEq for EmergencySweepApproval {
+    fn eq(&self, other: &EmergencySweepApproval) -> bool {
+        self.peer == other.peer && self.step == other.step && self.signature == other.signature
+    }
+}
+
+impl Eq for EmergencySweepApproval {}
+
+/// State of the emergency sweep as returned by the `/emergency_sweep` endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmergencySweepStatus {
+    pub recovery_descriptor: Option<PegInDescriptor>,
+    pub armed_by: Vec<PeerId>,
+    pub confirmed_by: Vec<PeerId>,
+    /// Transactions that moved our funds to the recovery descriptor
+    pub sweep_txids: Vec<Txid>,
+}
+
+fn approval_message(
+    peer: PeerId,
+    step: EmergencySweepStep,
+    recovery_descriptor: &PegInDescriptor,
+) -> Message {
+    let mut engine = sha256::Hash::engine();
+    engine.input(EMERGENCY_SWEEP_TAG);
+    peer.consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    step.consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    recovery_descriptor
+        .consensus_encode(&mut engine)
+        .expect("Hashing never fails");
+    Message::from_slice(&sha256::Hash::from_engine(engine)).expect("Hash has the right length")
+}
@@ -0,0 +1,172 @@
+//! Signing of a guardian's peg-out signature shares by an external signer, so
+//! the guardian's peg-in key doesn't have to be stored on the server.
+//!
+//! The signer is a program invoked like HWI's `signtx` command: the PSBT of
+//! the peg-out is appended base64 encoded as last argument and the program
+//! prints a JSON object with the signed PSBT as `psbt` or a failure as `error`.
+//! Each input carries the tweak of the UTXO it spends as proprietary field, the
+//! signer has to sign with the peg-in key tweaked by it just like
+//! [`crate::tweakable::Tweakable`] tweaks the public key. The returned
+//! signatures are verified against our public key before they are proposed.
+//!
+//! Signing runs in a background task instead of while the epoch is processed,
+//! so a slow signer only delays the peg-outs, not consensus.
+
+use std::time::Duration;
+
+use anyhow::bail;
+#[cfg(feature = "native")]
+use anyhow::format_err;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use fedimint_api::db::Database;
+use fedimint_api::task::{timeout, TaskHandle};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::db::{PegOutTxSignatureCI, UnsignedTransactionPrefixKey};
+use crate::keys::CompressedPublicKey;
+use crate::Wallet;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+/// How often unsigned peg-outs are checked for missing signature shares if
+/// no new ones are queued in the meantime
+const SIGNING_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HardwareSignerConfig {
+    /// Program and arguments to invoke for signing, e.g. `["hwi",
+    /// "--fingerprint", "d34db33f", "signtx"]`
+    pub command: Vec<String>,
+    /// Our key of the peg-in multisig, held by the signer
+    pub public_key: CompressedPublicKey,
+    /// Seconds to wait for the signer before trying again
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_TIMEOUT_SECS
+}
+
+/// Output of the signer, following HWI's conventions
+#[cfg(feature = "native")]
+#[derive(Debug, Deserialize)]
+struct SignerOutput {
+    psbt: Option<String>,
+    error: Option<String>,
+}
+
+impl HardwareSignerConfig {
+    /// Has the signer add our signatures to `psbt` and returns the result
+    /// without verifying it
+    #[cfg(feature = "native")]
+    pub async fn sign(
+        &self,
+        psbt: &PartiallySignedTransaction,
+    ) -> anyhow::Result<PartiallySignedTransaction> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| format_err!("Hardware signer command is empty"))?;
+
+        let output = tokio::process::Command::new(program)
+            .args(args)
+            .arg(psbt.to_string())
+            .kill_on_drop(true)
+            .output();
+        let timeout = std::time::Duration::from_secs(self.timeout_secs);
+        let output = fedimint_api::task::timeout(timeout, output)
+            .await
+            .map_err(|_| format_err!("Hardware signer timed out"))??;
+        if !output.status.success() {
+            bail!(
+                "Hardware signer exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let output: SignerOutput = serde_json::from_slice(&output.stdout)
+            .map_err(|e| format_err!("Invalid hardware signer output: {e}"))?;
+        match output {
+            SignerOutput {
+                error: Some(error), ..
+            } => bail!("Hardware signer failed: {error}"),
+            SignerOutput {
+                psbt: Some(psbt), ..
+            } => psbt
+                .parse()
+                .map_err(|e| format_err!("Hardware signer returned an invalid PSBT: {e}")),
+            _ => bail!("Hardware signer returned neither a PSBT nor an error"),
+        }
+    }
+
+    #[cfg(not(feature = "native"))]
+    pub async fn sign(
+        &self,
+        _psbt: &PartiallySignedTransaction,
+    ) -> anyhow::Result<PartiallySignedTransaction> {
+        bail!("Native cargo feature not enabled, can't invoke the hardware signer")
+    }
+}
+
+/// Has the signer sign our share of every unsigned peg-out we haven't signed
+/// yet and submits it like `/admin/submit_peg_out_signature` does, failed
+/// attempts are retried
+pub(crate) async fn run_hardware_signer(wallet: Wallet, db: Database, handle: &TaskHandle) {
+    let signer = wallet
+        .cfg
+        .local
+        .hardware_signer
+        .as_ref()
+        .expect("only started with a hardware signer");
+    while !handle.is_shutting_down() {
+        // Register before reading so no new peg-out in between is missed
+        let unsigned_written = db.wait_key_prefix_write(&UnsignedTransactionPrefixKey);
+        let mut dbtx = db.begin_transaction().await;
+        let unsigned_txs = dbtx
+            .find_by_prefix(&UnsignedTransactionPrefixKey)
+            .await
+            .map(|res| res.expect("DB error"))
+            .collect::<Vec<_>>()
+            .await;
+        let mut unsigned_psbts = vec![];
+        for (key, unsigned) in unsigned_txs {
+            let is_signed = dbtx
+                .get_value(&PegOutTxSignatureCI(key.0))
+                .await
+                .expect("DB error")
+                .is_some();
+            if !is_signed {
+                unsigned_psbts.push(unsigned.psbt);
+            }
+        }
+        drop(dbtx);
+
+        for psbt in unsigned_psbts {
+            let txid = psbt.unsigned_tx.txid();
+            info!(%txid, "Signing peg out with the hardware signer");
+            let signed = match signer.sign(&psbt).await {
+                Ok(signed) => signed,
+                Err(e) => {
+                    warn!(%txid, "Could not sign peg out: {e:#}");
+                    continue;
+                }
+            };
+
+            let mut dbtx = db.begin_transaction().await;
+            // The peg-out may have been finalized or replaced in the meantime
+            match wallet
+                .submit_external_peg_out_signature(&mut dbtx, signed)
+                .await
+            {
+                Ok(_) => dbtx.commit_tx().await.expect("DB Error"),
+                Err(e) => warn!(%txid, "Could not submit hardware signature: {e:#}"),
+            }
+        }
+
+        let _ = timeout(SIGNING_INTERVAL, unsigned_written).await;
+    }
+}
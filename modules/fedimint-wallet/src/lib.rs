@@ -1,12 +1,14 @@
+use std::cmp::max;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::{Infallible, TryInto};
 use std::ffi::{OsStr, OsString};
 use std::hash::Hasher;
+use std::iter::once;
 use std::ops::Sub;
-#[cfg(not(target_family = "wasm"))]
+use std::str::FromStr;
 use std::time::Duration;
 
-use anyhow::bail;
+use anyhow::{bail, format_err};
 use async_trait::async_trait;
 use bitcoin::hashes::hex::ToHex;
 use bitcoin::hashes::{sha256, Hash as BitcoinHash, HashEngine, Hmac, HmacEngine};
@@ -22,8 +24,9 @@ use bitcoin::{PackedLockTime, Sequence};
 use config::WalletConfigConsensus;
 use db::DbKeyPrefix;
 use fedimint_api::bitcoin_rpc::{
-    select_bitcoin_backend_from_envs, BitcoinRpcBackendType, FM_BITCOIND_RPC_ENV,
-    FM_ELECTRUM_RPC_ENV,
+    fm_fee_sources_env_value_to_urls, select_bitcoin_backend_from_envs, BitcoinRpcBackendType,
+    BitcoindRpcBackend, FM_BITCOIND_FILTERS_RPC_ENV, FM_BITCOIND_RPC_ENV, FM_ELECTRUM_RPC_ENV,
+    FM_ESPLORA_RPC_ENV, FM_FEE_SOURCES_ENV,
 };
 use fedimint_api::cancellable::{Cancellable, Cancelled};
 use fedimint_api::config::{
@@ -31,7 +34,9 @@ use fedimint_api::config::{
 };
 use fedimint_api::config::{ModuleConfigResponse, TypedServerModuleConsensusConfig};
 use fedimint_api::core::{ModuleInstanceId, ModuleKind};
-use fedimint_api::db::{Database, DatabaseTransaction};
+use fedimint_api::db::{
+    Database, DatabaseMigration, DatabaseTransaction, DatabaseVersion, InvalidDbEntry, MigrationMap,
+};
 use fedimint_api::encoding::{Decodable, Encodable, UnzipConsensus};
 use fedimint_api::module::audit::Audit;
 use fedimint_api::module::interconnect::ModuleInterconect;
@@ -39,12 +44,13 @@ use fedimint_api::module::{
     api_endpoint, ApiVersion, ConsensusProposal, CoreConsensusVersion, InputMeta, IntoModuleError,
     ModuleConsensusVersion, ModuleGen, TransactionItemAmount,
 };
-use fedimint_api::module::{ApiEndpoint, ModuleError};
+use fedimint_api::module::{ApiEndpoint, ApiError, ModuleError};
 use fedimint_api::net::peers::MuxPeerConnections;
+use fedimint_api::net::{fm_socks5_proxy_env_value, FM_SOCKS5_PROXY_ENV};
 use fedimint_api::server::DynServerModule;
 #[cfg(not(target_family = "wasm"))]
 use fedimint_api::task::sleep;
-use fedimint_api::task::{TaskGroup, TaskHandle};
+use fedimint_api::task::{timeout, TaskGroup, TaskHandle};
 use fedimint_api::{
     plugin_types_trait_impl, push_db_key_items, push_db_pair_items, Feerate, NumPeers, OutPoint,
     PeerId, ServerModule,
@@ -52,6 +58,7 @@ use fedimint_api::{
 use fedimint_bitcoind::DynBitcoindRpc;
 use futures::{stream, StreamExt};
 use impl_tools::autoimpl;
+use miniscript::policy::Liftable;
 use miniscript::psbt::PsbtExt;
 use miniscript::{Descriptor, TranslatePk};
 use rand::rngs::OsRng;
@@ -61,30 +68,62 @@ use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use thiserror::Error;
 use tracing::{debug, error, info, instrument, trace, warn};
+use url::Url;
 
+use crate::coin_selection::{CoinSelection, CoinSelectionContext};
 use crate::common::WalletDecoder;
-use crate::config::{WalletClientConfig, WalletConfig};
+use crate::config::{
+    FeeConsensus, PegInFinalityDelay, TimelockedRecovery, WalletClientConfig, WalletConfig,
+};
 use crate::db::{
-    BlockHashKey, BlockHashKeyPrefix, PegOutBitcoinTransaction, PegOutBitcoinTransactionPrefix,
-    PegOutTxSignatureCI, PegOutTxSignatureCIPrefix, PendingTransactionKey,
-    PendingTransactionPrefixKey, RoundConsensusKey, UTXOKey, UTXOPrefixKey, UnsignedTransactionKey,
-    UnsignedTransactionPrefixKey,
+    migrate_to_v1, migrate_to_v2, AcceleratedPegInKey, AcceleratedPegInPrefixKey, BlockHashKey,
+    BlockHashKeyPrefix, BlockHeightKey, BlockHeightKeyPrefix, EmergencySweepApprovalKey,
+    EmergencySweepApprovalPrefixKey, EmergencySweepTxKey, EmergencySweepTxPrefixKey, FeeReserveKey,
+    FeeReserveKeyPrefix, PegInAccelerationRequestKey, PegInAccelerationRequestPrefixKey,
+    PegOutBitcoinTransaction, PegOutBitcoinTransactionPrefix, PegOutTxSignatureCI,
+    PegOutTxSignatureCIPrefix, PendingEmergencySweepApprovalKey,
+    PendingEmergencySweepApprovalPrefixKey, PendingTransactionKey, PendingTransactionPrefixKey,
+    QueuedPegOutKey, QueuedPegOutPrefixKey, RoundConsensusKey, RoundConsensusPeersKey, UTXOKey,
+    UTXOPrefixKey, UnsignedTransactionKey, UnsignedTransactionPrefixKey, UtxoHeightKey,
+    UtxoHeightPrefixKey,
 };
+use crate::emergency_sweep::{EmergencySweepApproval, EmergencySweepStatus, EmergencySweepStep};
+use crate::hardware_signer::run_hardware_signer;
 use crate::keys::CompressedPublicKey;
 use crate::tweakable::Tweakable;
 use crate::txoproof::{PegInProof, PegInProofError};
+use crate::utxo_monitor::{run_utxo_monitor, UtxoAlert, UtxoAlerts};
 
+pub mod coin_selection;
 pub mod common;
 pub mod config;
 pub mod db;
+pub mod emergency_sweep;
+pub mod hardware_signer;
 pub mod keys;
 pub mod tweakable;
 pub mod txoproof;
+pub mod utxo_monitor;
 
 const KIND: ModuleKind = ModuleKind::from_static_str("wallet");
 
 pub const CONFIRMATION_TARGET: u16 = 10;
 
+/// How long we wait for an additional fee source before ignoring its estimate
+pub const FEE_SOURCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of blocks a peg-out may stay unconfirmed before the federation
+/// replaces it with one paying the consensus fee rate
+pub const PEG_OUT_RBF_DELAY: u32 = 6;
+
+/// Name of the env value used for enabling the acceleration of low-fee deposits
+/// via CPFP, it's the maximum fee in sats we agree to pay per deposit
+pub const FM_PEG_IN_CPFP_MAX_FEE_ENV: &str = "FM_PEG_IN_CPFP_MAX_FEE";
+
+/// Minimum fee rate increase of a replacement transaction, BIP 125 requires it
+/// to pay at least the incremental relay fee (1 sat/vB by default) on top
+const RBF_MIN_FEE_RATE_INCREASE: u64 = 1000;
+
 pub type PartialSig = Vec<u8>;
 
 pub type PegInDescriptor = Descriptor<CompressedPublicKey>;
@@ -95,6 +134,8 @@ pub type PegInDescriptor = Descriptor<CompressedPublicKey>;
 pub enum WalletConsensusItem {
     RoundConsensus(RoundConsensusItem),
     PegOutSignature(PegOutSignatureItem),
+    AcceleratePegIn(PegInAcceleration),
+    EmergencySweepApproval(EmergencySweepApproval),
 }
 
 impl std::fmt::Display for WalletConsensusItem {
@@ -106,6 +147,20 @@ impl std::fmt::Display for WalletConsensusItem {
             WalletConsensusItem::PegOutSignature(sig) => {
                 write!(f, "Wallet PegOut signature for Bitcoin TxId {}", sig.txid)
             }
+            WalletConsensusItem::AcceleratePegIn(acceleration) => {
+                write!(
+                    f,
+                    "Wallet PegIn acceleration for Bitcoin TxId {}",
+                    acceleration.tx.txid()
+                )
+            }
+            WalletConsensusItem::EmergencySweepApproval(approval) => {
+                write!(
+                    f,
+                    "Wallet emergency sweep approval ({:?}) of peer {}",
+                    approval.step, approval.peer
+                )
+            }
         }
     }
 }
@@ -131,11 +186,76 @@ pub struct RoundConsensus {
     pub randomness_beacon: [u8; 32],
 }
 
-#[derive(Debug)]
+/// Request to accelerate an unconfirmed deposit to the federation by sweeping
+/// it into a child transaction that pays for both (CPFP)
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct PegInAcceleration {
+    /// The unconfirmed deposit transaction
+    pub tx: Transaction,
+    /// Index of the output paying the federation
+    pub output_idx: u32,
+    /// Key the federation descriptor was tweaked with to derive the deposit
+    /// address
+    pub tweak_contract_key: secp256k1::XOnlyPublicKey,
+}
+
+impl PegInAcceleration {
+    pub fn outpoint(&self) -> bitcoin::OutPoint {
+        bitcoin::OutPoint {
+            txid: self.tx.txid(),
+            vout: self.output_idx,
+        }
+    }
+}
+
+/// A deposit that was swept into a CPFP transaction, so it won't become a
+/// `SpendableUTXO` once claimed
+#[derive(Clone, Debug, Serialize, Deserialize, Encodable, Decodable)]
+pub struct AcceleratedPegIn {
+    pub child_txid: Txid,
+    /// Outputs spent by the deposit transaction, a block spending any of them
+    /// in another transaction means the deposit was double-spent
+    pub parent_inputs: Vec<bitcoin::OutPoint>,
+    pub claimed: bool,
+}
+
+/// Peg-out transaction that is still collecting signatures, exported for
+/// inspection and signing with standard bitcoin tooling
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnsignedPegOutExport {
+    pub txid: Txid,
+    /// Base64 encoded PSBT without any signatures, its inputs and change output
+    /// carry their tweaks in a proprietary field
+    pub psbt: String,
+    /// The federation UTXOs spent by the transaction, in input order
+    pub inputs: Vec<PegOutInputProvenance>,
+    /// Txid of the stuck transaction this one replaces, if it is a fee bump
+    pub replaces: Option<Txid>,
+}
+
+/// Where an input of a peg-out transaction came from
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PegOutInputProvenance {
+    pub outpoint: bitcoin::OutPoint,
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub amount: bitcoin::Amount,
+    /// Hex encoded tweak of the federation descriptor, either the peg-in
+    /// contract key or the randomness beacon of a change output
+    pub tweak: String,
+}
+
+#[derive(Debug, Clone)]
 pub struct Wallet {
     cfg: WalletConfig,
     secp: Secp256k1<All>,
     btc_rpc: DynBitcoindRpc,
+    /// Additional sources queried for fee estimates besides `btc_rpc`
+    fee_sources: Vec<DynBitcoindRpc>,
+    /// Maximum fee we agree to pay for accelerating a deposit, `None` if we
+    /// don't accelerate deposits
+    peg_in_cpfp_max_fee: Option<bitcoin::Amount>,
+    /// Discrepancies between our UTXOs and the chain found by the UTXO monitor
+    utxo_alerts: UtxoAlerts,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Encodable, Decodable)]
@@ -151,6 +271,14 @@ pub struct PendingTransaction {
     pub tx: Transaction,
     pub tweak: [u8; 32],
     pub change: bitcoin::Amount,
+    pub fees: PegOutFees,
+    /// The UTXOs spent by `tx`, needed to build a fee-bumped replacement
+    pub selected_utxos: Vec<(UTXOKey, SpendableUTXO)>,
+    /// Consensus block height at which the transaction was finalized
+    pub finalized_height: u32,
+    /// Txid of the stuck transaction this one replaces, if it is a fee bump.
+    /// Both are kept until one of them confirms.
+    pub replaces: Option<Txid>,
 }
 
 impl Serialize for PendingTransaction {
@@ -177,6 +305,9 @@ pub struct UnsignedTransaction {
     pub signatures: Vec<(PeerId, PegOutSignatureItem)>,
     pub change: bitcoin::Amount,
     pub fees: PegOutFees,
+    pub selected_utxos: Vec<(UTXOKey, SpendableUTXO)>,
+    /// Txid of the stuck transaction this one replaces, if it is a fee bump
+    pub replaces: Option<Txid>,
 }
 
 impl Serialize for UnsignedTransaction {
@@ -197,7 +328,7 @@ impl Serialize for UnsignedTransaction {
 
 struct StatelessWallet<'a> {
     descriptor: &'a Descriptor<CompressedPublicKey>,
-    secret_key: &'a secp256k1::SecretKey,
+    coin_selection: &'a CoinSelection,
     secp: &'a secp256k1::Secp256k1<secp256k1::All>,
 }
 
@@ -213,6 +344,18 @@ impl PegOutFees {
     }
 }
 
+/// Bitcoin backend of a guardian as returned by the `/bitcoin_rpc_status`
+/// endpoint
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct BitcoinRpcStatus {
+    pub backend_type: BitcoinRpcBackendType,
+    /// Prioritized bitcoind endpoints from the local config, without
+    /// credentials
+    pub configured_backends: Vec<String>,
+    /// Endpoint calls currently go to if we fail over between several
+    pub active_backend: Option<String>,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
 pub struct PegOut {
     pub recipient: bitcoin::Address,
@@ -221,14 +364,14 @@ pub struct PegOut {
     pub fees: PegOutFees,
 }
 
-/// Contains the Bitcoin transaction id of the transaction created by the
-/// withdraw request
+/// Contains the Bitcoin transaction output paying the withdraw request.
+/// Peg-outs of the same epoch are batched, so they share a transaction id.
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
-pub struct WalletOutputOutcome(pub bitcoin::Txid);
+pub struct WalletOutputOutcome(pub bitcoin::OutPoint);
 
 impl std::fmt::Display for WalletOutputOutcome {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Wallet PegOut Bitcoin TxId {}", self.0)
+        write!(f, "Wallet PegOut Bitcoin OutPoint {}", self.0)
     }
 }
 
@@ -248,6 +391,17 @@ impl ModuleGen for WalletGen {
         &[ModuleConsensusVersion(0)]
     }
 
+    fn database_version(&self) -> DatabaseVersion {
+        DatabaseVersion(2)
+    }
+
+    fn get_database_migrations(&self) -> MigrationMap {
+        MigrationMap::from([
+            (DatabaseVersion(0), migrate_to_v1 as DatabaseMigration),
+            (DatabaseVersion(1), migrate_to_v2 as DatabaseMigration),
+        ])
+    }
+
     async fn init(
         &self,
         cfg: ServerModuleConfig,
@@ -286,8 +440,7 @@ impl ModuleGen for WalletGen {
                         .collect(),
                     *sk,
                     peers.threshold(),
-                    params.network,
-                    params.finality_delay,
+                    &params,
                 );
                 (*id, cfg)
             })
@@ -343,13 +496,7 @@ impl ModuleGen for WalletGen {
             }
         }
 
-        let wallet_cfg = WalletConfig::new(
-            peer_peg_in_keys,
-            sk,
-            peers.threshold(),
-            params.network,
-            params.finality_delay,
-        );
+        let wallet_cfg = WalletConfig::new(peer_peg_in_keys, sk, peers.threshold(), &params);
 
         Ok(Ok(wallet_cfg.to_erased()))
     }
@@ -362,7 +509,7 @@ impl ModuleGen for WalletGen {
 
         Ok(ModuleConfigResponse {
             client: config.to_client_config(),
-            consensus_hash: config.consensus_hash()?,
+            consensus_hash: config.config_hash()?,
         })
     }
 
@@ -371,7 +518,12 @@ impl ModuleGen for WalletGen {
     }
 
     fn hash_client_module(&self, config: serde_json::Value) -> anyhow::Result<sha256::Hash> {
-        serde_json::from_value::<WalletClientConfig>(config)?.consensus_hash()
+        serde_json::from_value::<WalletClientConfig>(config)?.config_hash()
+    }
+
+    fn validate_client_config(&self, config: serde_json::Value) -> anyhow::Result<()> {
+        serde_json::from_value::<WalletClientConfig>(config)?;
+        Ok(())
     }
 
     async fn dump_database(
@@ -418,6 +570,16 @@ impl ModuleGen for WalletGen {
                         "Pending Transactions"
                     );
                 }
+                DbKeyPrefix::QueuedPegOut => {
+                    push_db_pair_items!(
+                        dbtx,
+                        QueuedPegOutPrefixKey,
+                        QueuedPegOutKey,
+                        PegOut,
+                        wallet,
+                        "Queued Peg Outs"
+                    );
+                }
                 DbKeyPrefix::RoundConsensus => {
                     let round_consensus = dbtx.get_value(&RoundConsensusKey).await.unwrap();
                     if let Some(round_consensus) = round_consensus {
@@ -444,17 +606,190 @@ impl ModuleGen for WalletGen {
                         "UTXOs"
                     );
                 }
+                DbKeyPrefix::PegInAccelerationRequest => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PegInAccelerationRequestPrefixKey,
+                        PegInAccelerationRequestKey,
+                        PegInAcceleration,
+                        wallet,
+                        "Peg In Acceleration Requests"
+                    );
+                }
+                DbKeyPrefix::AcceleratedPegIn => {
+                    push_db_pair_items!(
+                        dbtx,
+                        AcceleratedPegInPrefixKey,
+                        AcceleratedPegInKey,
+                        AcceleratedPegIn,
+                        wallet,
+                        "Accelerated Peg Ins"
+                    );
+                }
+                DbKeyPrefix::BlockHeight => {
+                    push_db_pair_items!(
+                        dbtx,
+                        BlockHeightKeyPrefix,
+                        BlockHeightKey,
+                        u32,
+                        wallet,
+                        "Block Heights"
+                    );
+                }
+                DbKeyPrefix::EmergencySweepApproval => {
+                    push_db_pair_items!(
+                        dbtx,
+                        EmergencySweepApprovalPrefixKey,
+                        EmergencySweepApprovalKey,
+                        secp256k1::ecdsa::Signature,
+                        wallet,
+                        "Emergency Sweep Approvals"
+                    );
+                }
+                DbKeyPrefix::PendingEmergencySweepApproval => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PendingEmergencySweepApprovalPrefixKey,
+                        PendingEmergencySweepApprovalKey,
+                        secp256k1::ecdsa::Signature,
+                        wallet,
+                        "Pending Emergency Sweep Approvals"
+                    );
+                }
+                DbKeyPrefix::UtxoHeight => {
+                    push_db_pair_items!(
+                        dbtx,
+                        UtxoHeightPrefixKey,
+                        UtxoHeightKey,
+                        u32,
+                        wallet,
+                        "UTXO Heights"
+                    );
+                }
+                DbKeyPrefix::FeeReserve => {
+                    if let Some(reserve) = dbtx.get_value(&FeeReserveKey).await.unwrap() {
+                        wallet.insert("Fee Reserve".to_string(), Box::new(reserve));
+                    }
+                }
+                DbKeyPrefix::RoundConsensusPeers => {
+                    if let Some(peers) = dbtx.get_value(&RoundConsensusPeersKey).await.unwrap() {
+                        wallet.insert("Round Consensus Peers".to_string(), Box::new(peers));
+                    }
+                }
+                DbKeyPrefix::EmergencySweepTx => {
+                    push_db_pair_items!(
+                        dbtx,
+                        EmergencySweepTxPrefixKey,
+                        EmergencySweepTxKey,
+                        bitcoin::Amount,
+                        wallet,
+                        "Emergency Sweep Transactions"
+                    );
+                }
             }
         }
 
         Box::new(wallet.into_iter())
     }
+
+    async fn check_database(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<InvalidDbEntry> {
+        let known_prefixes = DbKeyPrefix::iter().map(|p| p as u8).collect::<Vec<_>>();
+        let mut invalid = dbtx.find_orphaned_entries(&known_prefixes).await;
+        for table in DbKeyPrefix::iter() {
+            invalid.extend(match table {
+                DbKeyPrefix::BlockHash => dbtx.find_undecodable_entries::<BlockHashKey>().await,
+                DbKeyPrefix::Utxo => dbtx.find_undecodable_entries::<UTXOKey>().await,
+                DbKeyPrefix::RoundConsensus => {
+                    dbtx.find_undecodable_entries::<RoundConsensusKey>().await
+                }
+                DbKeyPrefix::QueuedPegOut => {
+                    dbtx.find_undecodable_entries::<QueuedPegOutKey>().await
+                }
+                DbKeyPrefix::UnsignedTransaction => {
+                    dbtx.find_undecodable_entries::<UnsignedTransactionKey>()
+                        .await
+                }
+                DbKeyPrefix::PendingTransaction => {
+                    dbtx.find_undecodable_entries::<PendingTransactionKey>()
+                        .await
+                }
+                DbKeyPrefix::PegOutTxSigCi => {
+                    dbtx.find_undecodable_entries::<PegOutTxSignatureCI>().await
+                }
+                DbKeyPrefix::PegInAccelerationRequest => {
+                    dbtx.find_undecodable_entries::<PegInAccelerationRequestKey>()
+                        .await
+                }
+                DbKeyPrefix::AcceleratedPegIn => {
+                    dbtx.find_undecodable_entries::<AcceleratedPegInKey>().await
+                }
+                DbKeyPrefix::BlockHeight => dbtx.find_undecodable_entries::<BlockHeightKey>().await,
+                DbKeyPrefix::EmergencySweepApproval => {
+                    dbtx.find_undecodable_entries::<EmergencySweepApprovalKey>()
+                        .await
+                }
+                DbKeyPrefix::PendingEmergencySweepApproval => {
+                    dbtx.find_undecodable_entries::<PendingEmergencySweepApprovalKey>()
+                        .await
+                }
+                DbKeyPrefix::EmergencySweepTx => {
+                    dbtx.find_undecodable_entries::<EmergencySweepTxKey>().await
+                }
+                DbKeyPrefix::FeeReserve => dbtx.find_undecodable_entries::<FeeReserveKey>().await,
+                DbKeyPrefix::UtxoHeight => dbtx.find_undecodable_entries::<UtxoHeightKey>().await,
+                DbKeyPrefix::RoundConsensusPeers => {
+                    dbtx.find_undecodable_entries::<RoundConsensusPeersKey>()
+                        .await
+                }
+                DbKeyPrefix::PegOutBitcoinOutPoint => {
+                    dbtx.find_undecodable_entries::<PegOutBitcoinTransaction>()
+                        .await
+                }
+            });
+        }
+        invalid
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalletGenParams {
     pub network: bitcoin::network::constants::Network,
     pub finality_delay: u32,
+    /// Stricter confirmation requirements for larger peg-ins
+    #[serde(default)]
+    pub peg_in_finality_delays: Vec<PegInFinalityDelay>,
+    /// Descriptor all funds can be swept to in an emergency
+    #[serde(default)]
+    pub recovery_descriptor: Option<PegInDescriptor>,
+    #[serde(default)]
+    pub fee_consensus: FeeConsensus,
+    /// Prioritized bitcoind RPC endpoints of the local guardian
+    #[serde(default)]
+    pub bitcoind_rpcs: Vec<Url>,
+    /// Bitcoin backend of the local guardian if it doesn't use bitcoind
+    #[serde(default)]
+    pub bitcoin_backend: Option<BitcoindRpcBackend>,
+    /// Timelocked spending path for the guardians' cold keys
+    #[serde(default)]
+    pub timelocked_recovery: Option<TimelockedRecovery>,
+    #[serde(default)]
+    pub coin_selection: CoinSelection,
+}
+
+impl WalletGenParams {
+    pub fn new(network: bitcoin::network::constants::Network, finality_delay: u32) -> Self {
+        Self {
+            network,
+            finality_delay,
+            peg_in_finality_delays: vec![],
+            recovery_descriptor: None,
+            fee_consensus: FeeConsensus::default(),
+            bitcoind_rpcs: vec![],
+            bitcoin_backend: None,
+            timelocked_recovery: None,
+            coin_selection: CoinSelection::default(),
+        }
+    }
 }
 
 impl ModuleGenParams for WalletGenParams {
@@ -535,12 +870,7 @@ impl ServerModule for Wallet {
             last_consensus_height
         };
 
-        let fee_rate = self
-            .btc_rpc
-            .get_fee_rate(CONFIRMATION_TARGET)
-            .await
-            .expect("bitcoind rpc failed")
-            .unwrap_or(self.cfg.consensus.default_fee);
+        let fee_rate = self.estimate_fee_rate().await;
 
         let round_ci = WalletConsensusItem::RoundConsensus(RoundConsensusItem {
             block_height: proposed_height,
@@ -548,7 +878,7 @@ impl ServerModule for Wallet {
             randomness: OsRng.gen(),
         });
 
-        let items = dbtx
+        let mut items = dbtx
             .find_by_prefix(&PegOutTxSignatureCIPrefix)
             .await
             .map(|res| {
@@ -561,10 +891,35 @@ impl ServerModule for Wallet {
             .chain(stream::once(async { round_ci }))
             .collect::<Vec<WalletConsensusItem>>()
             .await;
+        let has_peg_outs = 1 < items.len();
 
-        // We force new epochs only if height changed, or we have peg-outs (more than
-        // just round_ci item)
-        if last_consensus_height < proposed_height || 1 < items.len() {
+        items.extend(
+            dbtx.find_by_prefix(&PegInAccelerationRequestPrefixKey)
+                .await
+                .map(|res| WalletConsensusItem::AcceleratePegIn(res.expect("DB error").1))
+                .collect::<Vec<_>>()
+                .await,
+        );
+        items.extend(
+            dbtx.find_by_prefix(&PendingEmergencySweepApprovalPrefixKey)
+                .await
+                .map(|res| {
+                    let (PendingEmergencySweepApprovalKey(step, peer), signature) =
+                        res.expect("DB error");
+                    WalletConsensusItem::EmergencySweepApproval(EmergencySweepApproval {
+                        peer,
+                        step,
+                        signature,
+                    })
+                })
+                .collect::<Vec<_>>()
+                .await,
+        );
+
+        // We force new epochs only if height changed, or we have peg-outs. Acceleration
+        // requests and sweep approvals don't, so ones other guardians don't agree with
+        // don't cause empty epochs forever.
+        if last_consensus_height < proposed_height || has_peg_outs {
             ConsensusProposal::Trigger(items)
         } else {
             ConsensusProposal::Contribute(items)
@@ -584,6 +939,8 @@ impl ServerModule for Wallet {
         let UnzipWalletConsensusItem {
             peg_out_signature: peg_out_signatures,
             round_consensus,
+            accelerate_peg_in: peg_in_accelerations,
+            emergency_sweep_approval: emergency_sweep_approvals,
         } = consensus_items.into_iter().unzip_wallet_consensus_item();
 
         // Save signatures to the database
@@ -612,6 +969,14 @@ impl ServerModule for Wallet {
             .collect();
         let randomness_beacon = self.process_randomness_contributions(randomness_contributions);
 
+        let round_consensus_peers = round_consensus
+            .iter()
+            .map(|(peer, _)| *peer)
+            .collect::<Vec<_>>();
+        dbtx.insert_entry(&RoundConsensusPeersKey, &round_consensus_peers)
+            .await
+            .expect("DB Error");
+
         let round_consensus = RoundConsensus {
             block_height,
             fee_rate,
@@ -621,6 +986,12 @@ impl ServerModule for Wallet {
         dbtx.insert_entry(&RoundConsensusKey, &round_consensus)
             .await
             .expect("DB Error");
+
+        self.bump_stuck_peg_outs(dbtx, &round_consensus).await;
+        self.accelerate_peg_ins(dbtx, peg_in_accelerations, &round_consensus)
+            .await;
+        self.save_emergency_sweep_approvals(dbtx, emergency_sweep_approvals)
+            .await;
     }
 
     fn build_verification_cache<'a>(
@@ -646,19 +1017,19 @@ impl ServerModule for Wallet {
             .verify(&self.secp, &self.cfg.consensus.peg_in_descriptor)
             .into_module_error_other()?;
 
-        if dbtx
-            .get_value(&UTXOKey(input.outpoint()))
-            .await
-            .expect("DB error")
-            .is_some()
-        {
+        if self.is_peg_in_claimed(dbtx, input.outpoint()).await {
             return Err(WalletError::PegInAlreadyClaimed).into_module_error_other();
         }
 
+        self.check_peg_in_finality(dbtx, input)
+            .await
+            .into_module_error_other()?;
+
+        let amount = bitcoin::Amount::from_sat(input.tx_output().value);
         Ok(InputMeta {
             amount: TransactionItemAmount {
-                amount: fedimint_api::Amount::from_sats(input.tx_output().value),
-                fee: self.cfg.consensus.fee_consensus.peg_in_abs,
+                amount: amount.into(),
+                fee: self.cfg.consensus.fee_consensus.peg_in_fee(amount),
             },
             puk_keys: vec![*input.tweak_contract_key()],
         })
@@ -676,15 +1047,58 @@ impl ServerModule for Wallet {
             .await?;
         debug!(outpoint = %input.outpoint(), amount = %meta.amount.amount, "Claiming peg-in");
 
-        dbtx.insert_new_entry(
-            &UTXOKey(input.outpoint()),
-            &SpendableUTXO {
-                tweak: input.tweak_contract_key().serialize(),
-                amount: bitcoin::Amount::from_sat(input.tx_output().value),
-            },
-        )
-        .await
-        .expect("DB Error");
+        let spread = self
+            .cfg
+            .consensus
+            .fee_consensus
+            .peg_in_reserve_spread(bitcoin::Amount::from_sat(input.tx_output().value));
+        self.add_to_fee_reserve(dbtx, spread).await;
+
+        dbtx.remove_entry(&PegInAccelerationRequestKey(input.outpoint()))
+            .await
+            .expect("DB Error");
+
+        let accelerated_key = AcceleratedPegInKey(input.outpoint());
+        match dbtx.get_value(&accelerated_key).await.expect("DB error") {
+            // The deposit was already swept into our wallet by the CPFP transaction
+            Some(accelerated) => {
+                dbtx.insert_entry(
+                    &accelerated_key,
+                    &AcceleratedPegIn {
+                        claimed: true,
+                        ..accelerated
+                    },
+                )
+                .await
+                .expect("DB Error");
+            }
+            None => {
+                dbtx.insert_new_entry(
+                    &UTXOKey(input.outpoint()),
+                    &SpendableUTXO {
+                        tweak: input.tweak_contract_key().serialize(),
+                        amount: bitcoin::Amount::from_sat(input.tx_output().value),
+                    },
+                )
+                .await
+                .expect("DB Error");
+                // The deposit may have confirmed long before it is claimed, which matters
+                // for the age of the UTXO under a CSV recovery path
+                let proof_height = dbtx
+                    .get_value(&BlockHeightKey(input.proof_block()))
+                    .await
+                    .expect("DB error");
+                let height = match proof_height {
+                    Some(height) => Some(height),
+                    None => self.consensus_height(dbtx).await,
+                };
+                if let Some(height) = height {
+                    dbtx.insert_entry(&UtxoHeightKey(input.outpoint()), &height)
+                        .await
+                        .expect("DB Error");
+                }
+            }
+        }
 
         Ok(meta)
     }
@@ -694,6 +1108,9 @@ impl ServerModule for Wallet {
         dbtx: &mut DatabaseTransaction,
         output: &WalletOutput,
     ) -> Result<TransactionItemAmount, ModuleError> {
+        if self.is_emergency_sweep_confirmed(dbtx).await {
+            return Err(WalletError::EmergencySweepInProgress).into_module_error_other();
+        }
         if !output
             .recipient
             .is_valid_for_network(self.cfg.consensus.network)
@@ -712,17 +1129,39 @@ impl ServerModule for Wallet {
             ))
             .into_module_error_other();
         }
-        if self.create_peg_out_tx(dbtx, output).await.is_none() {
+        // The peg-out will be batched with the ones already accepted in this epoch, so
+        // our UTXOs need to be able to fund all of them together
+        let peg_outs = self
+            .queued_peg_outs(dbtx)
+            .await
+            .into_iter()
+            .map(|(_, peg_out)| peg_out)
+            .chain(once(output.0.clone()))
+            .collect::<Vec<_>>();
+        let Some(tx) = self.create_peg_out_tx(dbtx, &peg_outs).await else {
             return Err(WalletError::NotEnoughSpendableUTXO).into_module_error_other();
+        };
+        // The funds set aside for on-chain fees must stay in our wallet
+        let spent = tx
+            .selected_utxos
+            .iter()
+            .map(|(_, utxo)| utxo.amount)
+            .sum::<bitcoin::Amount>()
+            - tx.change;
+        let remaining = self.get_wallet_value(dbtx).await - spent;
+        let reserve = self.fee_reserve(dbtx).await;
+        if remaining < reserve {
+            return Err(WalletError::PegOutExceedsFeeReserve(reserve)).into_module_error_other();
         }
         Ok(TransactionItemAmount {
             amount: (output.amount + output.fees.amount()).into(),
-            fee: self.cfg.consensus.fee_consensus.peg_out_abs,
+            fee: self.cfg.consensus.fee_consensus.peg_out_fee(output.amount),
         })
     }
 
     async fn apply_output<'a, 'b>(
         &'a self,
+        _interconnect: &'a dyn ModuleInterconect,
         dbtx: &mut DatabaseTransaction<'b>,
         output: &'a WalletOutput,
         out_point: fedimint_api::OutPoint,
@@ -733,62 +1172,15 @@ impl ServerModule for Wallet {
             "Queuing peg-out",
         );
 
-        let mut tx = self
-            .create_peg_out_tx(dbtx, output)
-            .await
-            .expect("Should have been validated");
-        self.offline_wallet().sign_psbt(&mut tx.psbt);
-        let txid = tx.psbt.unsigned_tx.txid();
-        info!(
-            %txid,
-            "Signing peg out",
-        );
-
-        let sigs = tx
-            .psbt
-            .inputs
-            .iter_mut()
-            .map(|input| {
-                assert_eq!(
-                    input.partial_sigs.len(),
-                    1,
-                    "There was already more than one (our) or no signatures in input"
-                );
-
-                // TODO: don't put sig into PSBT in the first place
-                // We actually take out our own signature so everyone finalizes the tx in the
-                // same epoch.
-                let sig = std::mem::take(&mut input.partial_sigs)
-                    .into_values()
-                    .next()
-                    .expect("asserted previously");
-
-                // We drop SIGHASH_ALL, because we always use that and it is only present in the
-                // PSBT for compatibility with other tools.
-                secp256k1::ecdsa::Signature::from_der(&sig.to_vec()[..sig.to_vec().len() - 1])
-                    .expect("we serialized it ourselves that way")
-            })
-            .collect::<Vec<_>>();
-
-        // Delete used UTXOs
-        for input in tx.psbt.unsigned_tx.input.iter() {
-            dbtx.remove_entry(&UTXOKey(input.previous_output))
-                .await
-                .expect("DB Error");
-        }
-
-        dbtx.insert_new_entry(&UnsignedTransactionKey(txid), &tx)
-            .await
-            .expect("DB Error");
-        dbtx.insert_new_entry(&PegOutTxSignatureCI(txid), &sigs)
+        dbtx.insert_new_entry(&QueuedPegOutKey(out_point), &output.0)
             .await
             .expect("DB Error");
-        dbtx.insert_new_entry(
-            &PegOutBitcoinTransaction(out_point),
-            &WalletOutputOutcome(txid),
-        )
-        .await
-        .expect("DB Error");
+        let spread = self
+            .cfg
+            .consensus
+            .fee_consensus
+            .peg_out_reserve_spread(output.amount);
+        self.add_to_fee_reserve(dbtx, spread).await;
         Ok(amount)
     }
 
@@ -797,6 +1189,10 @@ impl ServerModule for Wallet {
         consensus_peers: &HashSet<PeerId>,
         dbtx: &mut DatabaseTransaction<'b>,
     ) -> Vec<PeerId> {
+        self.batch_queued_peg_outs(dbtx).await;
+        self.refresh_aging_utxos(dbtx).await;
+        self.sweep_to_recovery_descriptor(dbtx).await;
+
         // Sign and finalize any unsigned transactions that have signatures
         let unsigned_txs = dbtx
             .find_by_prefix(&UnsignedTransactionPrefixKey)
@@ -812,34 +1208,40 @@ impl ServerModule for Wallet {
             .into_iter()
             .filter(|(_, unsigned)| !unsigned.signatures.is_empty());
 
+        let finalized_height = self.consensus_height(dbtx).await.unwrap_or(0);
+        let round_consensus_peers = dbtx
+            .get_value(&RoundConsensusPeersKey)
+            .await
+            .expect("DB error")
+            .unwrap_or_default();
         let mut drop_peers = Vec::<PeerId>::new();
-        for (key, unsigned) in unsigned_txs {
-            let UnsignedTransaction {
-                mut psbt,
-                signatures,
-                change,
-                ..
-            } = unsigned;
+        for (key, mut unsigned) in unsigned_txs {
+            let signatures = std::mem::take(&mut unsigned.signatures);
 
             let signers: HashSet<PeerId> = signatures
                 .iter()
-                .filter_map(
-                    |(peer, sig)| match self.sign_peg_out_psbt(&mut psbt, peer, sig) {
+                .filter_map(|(peer, sig)| {
+                    match self.sign_peg_out_psbt(&mut unsigned.psbt, peer, sig) {
                         Ok(_) => Some(*peer),
                         Err(error) => {
                             warn!("Error with {} partial sig {:?}", peer, error);
                             None
                         }
-                    },
-                )
+                    }
+                })
                 .collect();
 
+            // Guardians with a hardware signer contribute their signature asynchronously,
+            // so only peers that didn't take part in the round consensus either are dropped
             for peer in consensus_peers.sub(&signers) {
+                if round_consensus_peers.contains(&peer) {
+                    continue;
+                }
                 error!("Dropping {:?} for not contributing sigs to PSBT", peer);
                 drop_peers.push(peer);
             }
 
-            match self.finalize_peg_out_psbt(&mut psbt, change) {
+            match self.finalize_peg_out_psbt(unsigned, finalized_height) {
                 Ok(pending_tx) => {
                     // We were able to finalize the transaction, so we will delete the PSBT and
                     // instead keep the extracted tx for periodic transmission
@@ -875,14 +1277,42 @@ impl ServerModule for Wallet {
         audit
             .add_items(dbtx, &UTXOPrefixKey, |_, v| v.amount.to_sat() as i64 * 1000)
             .await;
+        // A CPFP transaction is only valid if the deposit it spends confirms, which the
+        // user has to prove to claim it, so its change isn't accounted for before
+        let unclaimed_cpfp = self.unclaimed_cpfp_txids(dbtx).await;
+        audit
+            .add_items(dbtx, &UnsignedTransactionPrefixKey, |k, v| {
+                if unclaimed_cpfp.contains(&k.0) {
+                    0
+                } else {
+                    v.change.to_sat() as i64 * 1000
+                }
+            })
+            .await;
+        // A stuck transaction and its replacement spend the same UTXOs, so only the
+        // change of the latest one is accounted for
+        let replaced = self.replaced_txids(dbtx).await;
+        audit
+            .add_items(dbtx, &PendingTransactionPrefixKey, |k, v| {
+                if replaced.contains(&k.0) || unclaimed_cpfp.contains(&k.0) {
+                    0
+                } else {
+                    v.change.to_sat() as i64 * 1000
+                }
+            })
+            .await;
+        // Swept funds are held by the guardians under the recovery descriptor, the
+        // sweep fee is accounted for as part of them
         audit
-            .add_items(dbtx, &UnsignedTransactionPrefixKey, |_, v| {
-                v.change.to_sat() as i64 * 1000
+            .add_items(dbtx, &EmergencySweepTxPrefixKey, |_, v| {
+                v.to_sat() as i64 * 1000
             })
             .await;
+        // The reserve is funded by fees and set aside to pay on-chain fees, so it isn't
+        // available to back notes
         audit
-            .add_items(dbtx, &PendingTransactionPrefixKey, |_, v| {
-                v.change.to_sat() as i64 * 1000
+            .add_items(dbtx, &FeeReserveKeyPrefix, |_, v| {
+                -(v.to_sat() as i64 * 1000)
             })
             .await;
     }
@@ -896,46 +1326,182 @@ impl ServerModule for Wallet {
                 }
             },
             api_endpoint! {
-                "/peg_out_fees",
-                async |module: &Wallet, dbtx, params: (Address, u64)| -> Option<PegOutFees> {
-                    let (address, sats) = params;
-                    let consensus = module.current_round_consensus(dbtx).await.unwrap();
-                    let tx = module.offline_wallet().create_tx(
-                        bitcoin::Amount::from_sat(sats),
-                        address.script_pubkey(),
-                        module.available_utxos(dbtx).await,
-                        consensus.fee_rate,
-                        &consensus.randomness_beacon
-                    );
-
-                    Ok(tx.map(|tx| tx.fees))
+                "/admin/unsigned_peg_outs",
+                async |module: &Wallet, dbtx, _params: ()| -> Vec<UnsignedPegOutExport> {
+                    Ok(module.export_unsigned_peg_outs(dbtx).await)
                 }
             },
-        ]
-    }
-}
-
-impl Wallet {
-    #[cfg(feature = "native")]
+            api_endpoint! {
+                "/admin/submit_peg_out_signature",
+                async |module: &Wallet, dbtx, psbt: String| -> Txid {
+                    let psbt = PartiallySignedTransaction::from_str(&psbt)
+                        .map_err(|e| ApiError::bad_request(format!("Invalid PSBT: {e}")))?;
+                    module
+                        .submit_external_peg_out_signature(dbtx, psbt)
+                        .await
+                        .map_err(|e| ApiError::bad_request(e.to_string()))
+                }
+            },
+            api_endpoint! {
+                "/accelerate_peg_in",
+                async |module: &Wallet, dbtx, acceleration: PegInAcceleration| -> () {
+                    module
+                        .request_peg_in_acceleration(dbtx, acceleration)
+                        .await
+                        .map_err(|e| ApiError::bad_request(e.to_string()))
+                }
+            },
+            api_endpoint! {
+                "/emergency_sweep",
+                async |module: &Wallet, dbtx, _params: ()| -> EmergencySweepStatus {
+                    Ok(module.emergency_sweep_status(dbtx).await)
+                }
+            },
+            api_endpoint! {
+                "/submit_emergency_sweep_approval",
+                async |module: &Wallet, dbtx, approval: EmergencySweepApproval| -> () {
+                    module
+                        .submit_emergency_sweep_approval(dbtx, approval)
+                        .await
+                        .map_err(|e| ApiError::bad_request(e.to_string()))
+                }
+            },
+            api_endpoint! {
+                "/bitcoin_rpc_status",
+                async |module: &Wallet, _dbtx, _params: ()| -> BitcoinRpcStatus {
+                    Ok(BitcoinRpcStatus {
+                        backend_type: module.btc_rpc.backend_type(),
+                        configured_backends: module
+                            .cfg
+                            .local
+                            .bitcoind_rpcs
+                            .iter()
+                            .map(|url| {
+                                let mut url = url.clone();
+                                let _ = url.set_username("");
+                                let _ = url.set_password(None);
+                                url.to_string()
+                            })
+                            .collect(),
+                        active_backend: module.btc_rpc.active_backend(),
+                    })
+                }
+            },
+            api_endpoint! {
+                "/utxo_alerts",
+                async |module: &Wallet, _dbtx, _params: ()| -> Vec<(bitcoin::OutPoint, UtxoAlert)> {
+                    Ok(module
+                        .utxo_alerts
+                        .lock()
+                        .expect("lock poisoned")
+                        .clone()
+                        .into_iter()
+                        .collect())
+                }
+            },
+            api_endpoint! {
+                "/peg_out_fees",
+                async |module: &Wallet, dbtx, params: (Address, u64)| -> Option<PegOutFees> {
+                    let (address, sats) = params;
+                    let consensus = module.current_round_consensus(dbtx).await.unwrap();
+                    // Users don't pay for consolidating our UTXOs
+                    let context = module.coin_selection_context(dbtx, false).await;
+                    let tx = module.offline_wallet().create_tx(
+                        vec![TxOut {
+                            value: sats,
+                            script_pubkey: address.script_pubkey(),
+                        }],
+                        module.available_utxos(dbtx).await,
+                        consensus.fee_rate,
+                        &consensus.randomness_beacon,
+                        &context,
+                    );
+
+                    Ok(tx.map(|tx| tx.fees))
+                }
+            },
+        ]
+    }
+}
+
+impl Wallet {
+    #[cfg(feature = "native")]
     pub async fn new(
         cfg: WalletConfig,
         db: Database,
         env: &BTreeMap<OsString, OsString>,
         task_group: &mut TaskGroup,
     ) -> anyhow::Result<Wallet> {
-        let bitcoin_backend = select_bitcoin_backend_from_envs(
-            env.get(OsStr::new(FM_BITCOIND_RPC_ENV))
-                .map(OsString::as_os_str),
-            env.get(OsStr::new(FM_ELECTRUM_RPC_ENV))
+        let btc_rpc = Self::make_bitcoin_rpc(&cfg, env, task_group)?;
+
+        let proxy = fm_socks5_proxy_env_value(
+            env.get(OsStr::new(FM_SOCKS5_PROXY_ENV))
                 .map(OsString::as_os_str),
         )?;
+        let fee_sources = fm_fee_sources_env_value_to_urls(
+            env.get(OsStr::new(FM_FEE_SOURCES_ENV))
+                .map(OsString::as_os_str),
+        )?
+        .iter()
+        .map(|url| Ok(fedimint_bitcoind::esplora::EsploraClient::new(url, proxy)?.into()))
+        .collect::<anyhow::Result<Vec<DynBitcoindRpc>>>()?;
+
+        let peg_in_cpfp_max_fee = env
+            .get(OsStr::new(FM_PEG_IN_CPFP_MAX_FEE_ENV))
+            .map(|sats| -> anyhow::Result<_> {
+                let sats = sats
+                    .to_str()
+                    .ok_or_else(|| format_err!("{FM_PEG_IN_CPFP_MAX_FEE_ENV} not ascii text"))?;
+                Ok(bitcoin::Amount::from_sat(sats.parse()?))
+            })
+            .transpose()?;
+
+        Ok(Self::new_with_bitcoind(cfg, db, btc_rpc, task_group)
+            .await?
+            .with_fee_sources(fee_sources)
+            .with_peg_in_cpfp_max_fee(peg_in_cpfp_max_fee))
+    }
 
-        let btc_rpc = fedimint_bitcoind::bitcoincore_rpc::make_bitcoin_rpc_backend(
-            &bitcoin_backend,
-            task_group.make_handle(),
+    /// Creates the Bitcoin backend the wallet uses, the prioritized bitcoind
+    /// endpoints of the local config, else its backend or else the one
+    /// selected by `env`
+    #[cfg(feature = "native")]
+    pub fn make_bitcoin_rpc(
+        cfg: &WalletConfig,
+        env: &BTreeMap<OsString, OsString>,
+        task_group: &mut TaskGroup,
+    ) -> anyhow::Result<DynBitcoindRpc> {
+        let proxy = fm_socks5_proxy_env_value(
+            env.get(OsStr::new(FM_SOCKS5_PROXY_ENV))
+                .map(OsString::as_os_str),
         )?;
+        if cfg.local.bitcoind_rpcs.is_empty() {
+            let bitcoin_backend = match &cfg.local.bitcoin_backend {
+                Some(bitcoin_backend) => bitcoin_backend.clone(),
+                None => select_bitcoin_backend_from_envs(
+                    env.get(OsStr::new(FM_BITCOIND_RPC_ENV))
+                        .map(OsString::as_os_str),
+                    env.get(OsStr::new(FM_ELECTRUM_RPC_ENV))
+                        .map(OsString::as_os_str),
+                    env.get(OsStr::new(FM_ESPLORA_RPC_ENV))
+                        .map(OsString::as_os_str),
+                    env.get(OsStr::new(FM_BITCOIND_FILTERS_RPC_ENV))
+                        .map(OsString::as_os_str),
+                )?,
+            };
 
-        Ok(Self::new_with_bitcoind(cfg, db, btc_rpc, task_group).await?)
+            fedimint_bitcoind::bitcoincore_rpc::make_bitcoin_rpc_backend(
+                &bitcoin_backend,
+                proxy,
+                task_group.make_handle(),
+            )
+        } else {
+            fedimint_bitcoind::bitcoincore_rpc::make_bitcoind_failover_rpc(
+                &cfg.local.bitcoind_rpcs,
+                proxy,
+                task_group.make_handle(),
+            )
+        }
     }
 
     #[cfg(not(feature = "native"))]
@@ -962,6 +1528,24 @@ impl Wallet {
             })
             .await;
 
+        let utxo_alerts = UtxoAlerts::default();
+        let monitor_bitcoind_rpc = bitcoind.clone();
+        let monitor_db = db.clone();
+        let monitor_descriptor = cfg.consensus.peg_in_descriptor.clone();
+        let monitor_alerts = utxo_alerts.clone();
+        task_group
+            .spawn("utxo monitor", |handle| async move {
+                run_utxo_monitor(
+                    monitor_db,
+                    monitor_bitcoind_rpc,
+                    monitor_descriptor,
+                    monitor_alerts,
+                    &handle,
+                )
+                .await;
+            })
+            .await;
+
         let bitcoind_rpc = bitcoind;
 
         let bitcoind_net = bitcoind_rpc
@@ -979,11 +1563,66 @@ impl Wallet {
             cfg,
             secp: Default::default(),
             btc_rpc: bitcoind_rpc,
+            fee_sources: vec![],
+            peg_in_cpfp_max_fee: None,
+            utxo_alerts,
         };
 
+        if wallet.cfg.local.hardware_signer.is_some() {
+            let signer_wallet = wallet.clone();
+            task_group
+                .spawn("hardware signer", |handle| async move {
+                    run_hardware_signer(signer_wallet, db, &handle).await;
+                })
+                .await;
+        }
+
         Ok(wallet)
     }
 
+    /// Accelerate deposits on request if it costs at most `max_fee` each
+    pub fn with_peg_in_cpfp_max_fee(mut self, max_fee: Option<bitcoin::Amount>) -> Self {
+        self.peg_in_cpfp_max_fee = max_fee;
+        self
+    }
+
+    /// Query `fee_sources` for fee estimates in addition to our bitcoin backend
+    pub fn with_fee_sources(mut self, fee_sources: Vec<DynBitcoindRpc>) -> Self {
+        self.fee_sources = fee_sources;
+        self
+    }
+
+    /// Our fee rate proposal is the median of the estimates of our bitcoin
+    /// backend and all additional fee sources that answered in time
+    async fn estimate_fee_rate(&self) -> Feerate {
+        let mut estimates = self
+            .btc_rpc
+            .get_fee_rate(CONFIRMATION_TARGET)
+            .await
+            .expect("bitcoind rpc failed")
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        for (idx, fee_source) in self.fee_sources.iter().enumerate() {
+            match timeout(
+                FEE_SOURCE_TIMEOUT,
+                fee_source.get_fee_rate(CONFIRMATION_TARGET),
+            )
+            .await
+            {
+                Ok(Ok(Some(fee_rate))) => estimates.push(fee_rate),
+                Ok(Ok(None)) => debug!(idx, "Fee source returned no estimate"),
+                Ok(Err(e)) => warn!(idx, "Fee source failed: {e}"),
+                Err(_) => warn!(idx, "Fee source timed out"),
+            }
+        }
+
+        if estimates.is_empty() {
+            return self.cfg.consensus.default_fee;
+        }
+        self.process_fee_proposals(estimates).await
+    }
+
     pub fn process_randomness_contributions(&self, randomness: Vec<[u8; 32]>) -> [u8; 32] {
         fn xor(mut lhs: [u8; 32], rhs: [u8; 32]) -> [u8; 32] {
             lhs.iter_mut().zip(rhs).for_each(|(lhs, rhs)| *lhs ^= rhs);
@@ -1010,7 +1649,17 @@ impl Wallet {
 
         for (peer, sig) in signatures.into_iter() {
             match cache.get_mut(&sig.txid) {
-                Some(unsigned) => unsigned.signatures.push((peer, sig)),
+                Some(unsigned) => {
+                    // Signatures are proposed until the transaction is finalized, which can
+                    // take several epochs if some are contributed asynchronously
+                    if !unsigned
+                        .signatures
+                        .iter()
+                        .any(|(signer, _)| *signer == peer)
+                    {
+                        unsigned.signatures.push((peer, sig));
+                    }
+                }
                 None => warn!(
                     "{} sent peg-out signature for unknown PSBT {}",
                     peer, sig.txid
@@ -1093,9 +1742,18 @@ impl Wallet {
 
     fn finalize_peg_out_psbt(
         &self,
-        psbt: &mut PartiallySignedTransaction,
-        change: Amount,
+        unsigned: UnsignedTransaction,
+        finalized_height: u32,
     ) -> Result<PendingTransaction, ProcessPegOutSigError> {
+        let UnsignedTransaction {
+            mut psbt,
+            change,
+            fees,
+            selected_utxos,
+            replaces,
+            ..
+        } = unsigned;
+
         // We need to save the change output's tweak key to be able to access the funds
         // later on. The tweak is extracted here because the psbt is moved next
         // and not available anymore when the tweak is actually needed in the
@@ -1113,12 +1771,16 @@ impl Wallet {
             return Err(ProcessPegOutSigError::ErrorFinalizingPsbt(error));
         }
 
-        let tx = psbt.clone().extract_tx();
+        let tx = psbt.extract_tx();
 
         Ok(PendingTransaction {
             tx,
             tweak: change_tweak,
             change,
+            fees,
+            selected_utxos,
+            finalized_height,
+            replaces,
         })
     }
 
@@ -1233,24 +1895,50 @@ impl Wallet {
                 })
                 .collect::<HashMap<_, _>>()
                 .await;
+            // Maps the inputs of accelerated deposits to the deposit outpoint
+            let accelerated_inputs = dbtx
+                .find_by_prefix(&AcceleratedPegInPrefixKey)
+                .await
+                .map(|res| res.expect("DB error"))
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .filter(|(_, accelerated)| !accelerated.claimed)
+                .flat_map(|(key, accelerated)| {
+                    accelerated
+                        .parent_inputs
+                        .into_iter()
+                        .map(move |input| (input, key.0))
+                })
+                .collect::<HashMap<_, _>>();
 
             match self.btc_rpc.backend_type() {
-                BitcoinRpcBackendType::Bitcoind => {
-                    if !pending_transactions.is_empty() {
+                BitcoinRpcBackendType::Bitcoind | BitcoinRpcBackendType::Esplora => {
+                    if !pending_transactions.is_empty() || !accelerated_inputs.is_empty() {
                         let block = self
                             .btc_rpc
                             .get_block(&block_hash)
                             .await
                             .expect("bitcoin rpc failed");
                         for transaction in block.txdata {
-                            if let Some(pending_tx) = pending_transactions.get(&transaction.txid())
-                            {
-                                self.recognize_change_utxo(dbtx, pending_tx).await;
+                            let txid = transaction.txid();
+                            if let Some(pending_tx) = pending_transactions.get(&txid) {
+                                self.recognize_change_utxo(dbtx, pending_tx, height).await;
+                            }
+                            for input in &transaction.input {
+                                match accelerated_inputs.get(&input.previous_output) {
+                                    Some(deposit) if deposit.txid != txid => {
+                                        self.drop_double_spent_peg_in(dbtx, *deposit).await;
+                                    }
+                                    _ => {}
+                                }
                             }
                         }
                     }
                 }
-                BitcoinRpcBackendType::Electrum => {
+                // Avoids downloading blocks that can't contain our transactions, double-spent
+                // accelerated deposits aren't detected but also not accounted for in the audit
+                BitcoinRpcBackendType::Electrum | BitcoinRpcBackendType::CompactFilters => {
                     for transaction in &pending_transactions {
                         if self
                             .btc_rpc
@@ -1258,18 +1946,20 @@ impl Wallet {
                             .await
                             .expect("bitcoin rpc backend failed")
                         {
-                            self.recognize_change_utxo(dbtx, transaction.1).await;
+                            self.recognize_change_utxo(dbtx, transaction.1, height)
+                                .await;
                         }
                     }
                 }
             }
 
-            dbtx.insert_new_entry(
-                &BlockHashKey(BlockHash::from_inner(block_hash.into_inner())),
-                &(),
-            )
-            .await
-            .expect("DB Error");
+            let block_hash = BlockHash::from_inner(block_hash.into_inner());
+            dbtx.insert_new_entry(&BlockHashKey(block_hash), &())
+                .await
+                .expect("DB Error");
+            dbtx.insert_new_entry(&BlockHeightKey(block_hash), &height)
+                .await
+                .expect("DB Error");
         }
     }
 
@@ -1279,6 +1969,7 @@ impl Wallet {
         &self,
         dbtx: &mut DatabaseTransaction<'a>,
         pending_tx: &PendingTransaction,
+        height: u32,
     ) {
         let script_pk = self
             .cfg
@@ -1300,97 +1991,1111 @@ impl Wallet {
                 )
                 .await
                 .expect("DB Error");
-
-                dbtx.remove_entry(&PendingTransactionKey(pending_tx.tx.txid()))
-                    .await
-                    .expect("DB error");
+                dbtx.insert_entry(
+                    &UtxoHeightKey(bitcoin::OutPoint {
+                        txid: pending_tx.tx.txid(),
+                        vout: idx as u32,
+                    }),
+                    &height,
+                )
+                .await
+                .expect("DB Error");
             }
         }
+
+        self.remove_rbf_transactions(dbtx, pending_tx.tx.txid())
+            .await;
     }
 
-    async fn block_is_known(
+    /// Drops the CPFP transactions of an accelerated deposit that was
+    /// double-spent, they can never confirm. The fee they were paying is
+    /// returned to the reserve.
+    async fn drop_double_spent_peg_in(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
-        block_hash: BlockHash,
-    ) -> bool {
-        dbtx.get_value(&BlockHashKey(block_hash))
+        deposit: bitcoin::OutPoint,
+    ) {
+        let accelerated = match dbtx
+            .get_value(&AcceleratedPegInKey(deposit))
             .await
             .expect("DB error")
-            .is_some()
+        {
+            Some(accelerated) => accelerated,
+            // Several transactions in the block may conflict with the deposit
+            None => return,
+        };
+        warn!(%deposit, child_txid = %accelerated.child_txid, "Accelerated peg-in was double-spent");
+
+        // The CPFP transaction and its fee bumps all sweep the deposit, the one with
+        // the lowest change paid the most from the reserve
+        let mut sweeps = dbtx
+            .find_by_prefix(&PendingTransactionPrefixKey)
+            .await
+            .map(|res| {
+                let tx = res.expect("DB error").1;
+                (tx.selected_utxos, tx.change)
+            })
+            .collect::<Vec<_>>()
+            .await;
+        sweeps.extend(
+            dbtx.find_by_prefix(&UnsignedTransactionPrefixKey)
+                .await
+                .map(|res| {
+                    let tx = res.expect("DB error").1;
+                    (tx.selected_utxos, tx.change)
+                })
+                .collect::<Vec<_>>()
+                .await,
+        );
+        let paid_fee = sweeps
+            .into_iter()
+            .filter_map(|(selected_utxos, change)| {
+                let (_, utxo) = selected_utxos
+                    .into_iter()
+                    .find(|(utxo_key, _)| utxo_key.0 == deposit)?;
+                utxo.amount.checked_sub(change)
+            })
+            .max()
+            .unwrap_or(bitcoin::Amount::ZERO);
+
+        self.remove_rbf_transactions(dbtx, accelerated.child_txid)
+            .await;
+        self.add_to_fee_reserve(dbtx, paid_fee).await;
+        dbtx.remove_entry(&AcceleratedPegInKey(deposit))
+            .await
+            .expect("DB Error");
     }
 
-    async fn create_peg_out_tx(
+    /// Removes a confirmed peg-out transaction together with all transactions
+    /// it replaced or that were meant to replace it, since they conflict with
+    /// it and can never confirm. Outcomes of peg-outs paid by those point to
+    /// the confirmed transaction again.
+    async fn remove_rbf_transactions(&self, dbtx: &mut DatabaseTransaction<'_>, confirmed: Txid) {
+        let mut pending_txs = dbtx
+            .find_by_prefix(&PendingTransactionPrefixKey)
+            .await
+            .map(|res| {
+                let (key, val) = res.expect("DB error");
+                (key.0, val.replaces)
+            })
+            .collect::<BTreeMap<_, _>>()
+            .await;
+        let mut unsigned_txs = dbtx
+            .find_by_prefix(&UnsignedTransactionPrefixKey)
+            .await
+            .map(|res| {
+                let (key, val) = res.expect("DB error");
+                (key.0, val.replaces)
+            })
+            .collect::<BTreeMap<_, _>>()
+            .await;
+
+        let mut to_remove = vec![confirmed];
+        while let Some(txid) = to_remove.pop() {
+            if txid != confirmed {
+                self.move_peg_out_outcomes(dbtx, txid, confirmed).await;
+            }
+            if let Some(replaces) = pending_txs.remove(&txid) {
+                dbtx.remove_entry(&PendingTransactionKey(txid))
+                    .await
+                    .expect("DB error");
+                to_remove.extend(replaces);
+            }
+            if let Some(replaces) = unsigned_txs.remove(&txid) {
+                dbtx.remove_entry(&UnsignedTransactionKey(txid))
+                    .await
+                    .expect("DB error");
+                dbtx.remove_entry(&PegOutTxSignatureCI(txid))
+                    .await
+                    .expect("DB error");
+                to_remove.extend(replaces);
+            }
+
+            to_remove.extend(
+                pending_txs
+                    .iter()
+                    .chain(unsigned_txs.iter())
+                    .filter(|(_, replaces)| **replaces == Some(txid))
+                    .map(|(replacement, _)| *replacement),
+            );
+        }
+    }
+
+    /// Txids of CPFP transactions, including their fee bumps, whose deposit
+    /// wasn't claimed yet
+    async fn unclaimed_cpfp_txids(&self, dbtx: &mut DatabaseTransaction<'_>) -> HashSet<Txid> {
+        let mut unclaimed = dbtx
+            .find_by_prefix(&AcceleratedPegInPrefixKey)
+            .await
+            .filter_map(|res| async move {
+                let accelerated = res.expect("DB error").1;
+                (!accelerated.claimed).then_some(accelerated.child_txid)
+            })
+            .collect::<HashSet<_>>()
+            .await;
+        if unclaimed.is_empty() {
+            return unclaimed;
+        }
+
+        let mut replacements = dbtx
+            .find_by_prefix(&PendingTransactionPrefixKey)
+            .await
+            .filter_map(|res| async move {
+                let (key, val) = res.expect("DB error");
+                val.replaces.map(|replaces| (key.0, replaces))
+            })
+            .collect::<Vec<_>>()
+            .await;
+        replacements.extend(
+            dbtx.find_by_prefix(&UnsignedTransactionPrefixKey)
+                .await
+                .filter_map(|res| async move {
+                    let (key, val) = res.expect("DB error");
+                    val.replaces.map(|replaces| (key.0, replaces))
+                })
+                .collect::<Vec<_>>()
+                .await,
+        );
+        while let Some(idx) = replacements
+            .iter()
+            .position(|(_, replaces)| unclaimed.contains(replaces))
+        {
+            unclaimed.insert(replacements.swap_remove(idx).0);
+        }
+        unclaimed
+    }
+
+    /// Txids of peg-out transactions that were already replaced by a fee bump
+    async fn replaced_txids(&self, dbtx: &mut DatabaseTransaction<'_>) -> HashSet<Txid> {
+        let mut replaced = dbtx
+            .find_by_prefix(&PendingTransactionPrefixKey)
+            .await
+            .filter_map(|res| async move { res.expect("DB error").1.replaces })
+            .collect::<HashSet<_>>()
+            .await;
+        replaced.extend(
+            dbtx.find_by_prefix(&UnsignedTransactionPrefixKey)
+                .await
+                .filter_map(|res| async move { res.expect("DB error").1.replaces })
+                .collect::<Vec<_>>()
+                .await,
+        );
+        replaced
+    }
+
+    /// Replaces peg-out transactions that didn't confirm within
+    /// [`PEG_OUT_RBF_DELAY`] blocks and pay less than the consensus fee rate.
+    ///
+    /// Since the decision only depends on consensus state every guardian builds
+    /// the same replacement, which then gets signed like a regular peg-out. The
+    /// additional fee is paid from the change. The replacement keeps the order
+    /// of the outputs, so the outcomes of the bumped peg-outs move to the same
+    /// output of the replacement.
+    async fn bump_stuck_peg_outs(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
-        peg_out: &PegOut,
-    ) -> Option<UnsignedTransaction> {
-        let change_tweak = self
-            .current_round_consensus(dbtx)
+        round_consensus: &RoundConsensus,
+    ) {
+        let replaced = self.replaced_txids(dbtx).await;
+        let stuck_txs = dbtx
+            .find_by_prefix(&PendingTransactionPrefixKey)
             .await
-            .unwrap()
-            .randomness_beacon;
-        self.offline_wallet().create_tx(
-            peg_out.amount,
-            peg_out.recipient.script_pubkey(),
-            self.available_utxos(dbtx).await,
-            peg_out.fees.fee_rate,
-            &change_tweak,
-        )
+            .map(|res| res.expect("DB error").1)
+            .filter(|pending| {
+                // Transactions finalized before the UTXOs they spend were recorded can't be
+                // replaced
+                let is_stuck = !pending.selected_utxos.is_empty()
+                    && !replaced.contains(&pending.tx.txid())
+                    && pending.finalized_height + PEG_OUT_RBF_DELAY <= round_consensus.block_height
+                    && pending.fees.fee_rate < round_consensus.fee_rate;
+                async move { is_stuck }
+            })
+            .collect::<Vec<_>>()
+            .await;
+
+        for pending in stuck_txs {
+            let fee_rate = max(
+                round_consensus.fee_rate,
+                Feerate {
+                    sats_per_kvb: pending.fees.fee_rate.sats_per_kvb + RBF_MIN_FEE_RATE_INCREASE,
+                },
+            );
+            let txid = pending.tx.txid();
+            match self.offline_wallet().create_rbf_tx(&pending, fee_rate) {
+                Some(replacement) => {
+                    self.pay_from_fee_reserve(dbtx, pending.change - replacement.change)
+                        .await;
+                    let replacement_txid = self.queue_peg_out_tx(dbtx, replacement).await;
+                    self.move_peg_out_outcomes(dbtx, txid, replacement_txid)
+                        .await;
+                    info!(
+                        %txid,
+                        %replacement_txid,
+                        fee_rate = fee_rate.sats_per_kvb,
+                        "Bumping fee of stuck peg-out",
+                    );
+                }
+                None => warn!(
+                    %txid,
+                    "Change of stuck peg-out is too small to bump its fee",
+                ),
+            }
+        }
     }
 
-    async fn available_utxos(
+    /// Points the outcomes of the peg-outs paid by transaction `from` to the
+    /// same outputs of transaction `to`
+    async fn move_peg_out_outcomes(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
-    ) -> Vec<(UTXOKey, SpendableUTXO)> {
-        dbtx.find_by_prefix(&UTXOPrefixKey)
+        from: Txid,
+        to: Txid,
+    ) {
+        let moved = dbtx
+            .find_by_prefix(&PegOutBitcoinTransactionPrefix)
             .await
-            .map(|res| {
-                let (key, val) = res.expect("FB error");
-                (key, val)
+            .map(|res| res.expect("DB error"))
+            .filter(|(_, outcome)| {
+                let is_paid_by_from = outcome.0.txid == from;
+                async move { is_paid_by_from }
             })
-            .collect::<Vec<(UTXOKey, SpendableUTXO)>>()
+            .collect::<Vec<_>>()
+            .await;
+
+        for (key, outcome) in moved {
+            dbtx.insert_entry(
+                &key,
+                &WalletOutputOutcome(bitcoin::OutPoint {
+                    txid: to,
+                    vout: outcome.0.vout,
+                }),
+            )
+            .await
+            .expect("DB Error");
+        }
+    }
+
+    async fn fee_reserve(&self, dbtx: &mut DatabaseTransaction<'_>) -> bitcoin::Amount {
+        dbtx.get_value(&FeeReserveKey)
             .await
+            .expect("DB error")
+            .unwrap_or(bitcoin::Amount::ZERO)
     }
 
-    pub async fn get_wallet_value(&self, dbtx: &mut DatabaseTransaction<'_>) -> bitcoin::Amount {
-        let sat_sum = self
-            .available_utxos(dbtx)
+    async fn add_to_fee_reserve(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        amount: bitcoin::Amount,
+    ) {
+        if amount == bitcoin::Amount::ZERO {
+            return;
+        }
+        let reserve = self.fee_reserve(dbtx).await + amount;
+        dbtx.insert_entry(&FeeReserveKey, &reserve)
             .await
-            .into_iter()
-            .map(|(_, utxo)| utxo.amount.to_sat())
-            .sum();
-        bitcoin::Amount::from_sat(sat_sum)
+            .expect("DB Error");
     }
 
-    fn offline_wallet(&self) -> StatelessWallet {
-        StatelessWallet {
-            descriptor: &self.cfg.consensus.peg_in_descriptor,
-            secret_key: &self.cfg.private.peg_in_key,
-            secp: &self.secp,
+    /// Takes an on-chain `fee` the federation pays out of the reserve. Fees are
+    /// still paid once it is exhausted, then it just stays empty.
+    async fn pay_from_fee_reserve(&self, dbtx: &mut DatabaseTransaction<'_>, fee: bitcoin::Amount) {
+        let reserve = self.fee_reserve(dbtx).await;
+        if reserve < fee {
+            warn!(%reserve, %fee, "Fee reserve is exhausted");
         }
+        let reserve = reserve.checked_sub(fee).unwrap_or(bitcoin::Amount::ZERO);
+        dbtx.insert_entry(&FeeReserveKey, &reserve)
+            .await
+            .expect("DB Error");
     }
-}
 
-impl<'a> StatelessWallet<'a> {
-    /// Attempts to create a tx ready to be signed from available UTXOs.
-    /// Returns `None` if there are not enough `SpendableUTXO`
-    fn create_tx(
+    async fn is_peg_in_claimed(
         &self,
-        peg_out_amount: bitcoin::Amount,
-        destination: Script,
-        mut utxos: Vec<(UTXOKey, SpendableUTXO)>,
-        fee_rate: Feerate,
-        change_tweak: &[u8],
-    ) -> Option<UnsignedTransaction> {
-        // When building a transaction we need to take care of two things:
-        //  * We need enough input amount to fund all outputs
+        dbtx: &mut DatabaseTransaction<'_>,
+        outpoint: bitcoin::OutPoint,
+    ) -> bool {
+        let is_spendable = dbtx
+            .get_value(&UTXOKey(outpoint))
+            .await
+            .expect("DB error")
+            .is_some();
+        let is_accelerated_and_claimed = dbtx
+            .get_value(&AcceleratedPegInKey(outpoint))
+            .await
+            .expect("DB error")
+            .map_or(false, |accelerated| accelerated.claimed);
+        is_spendable || is_accelerated_and_claimed
+    }
+
+    /// Checks a user's request to accelerate their unconfirmed deposit against
+    /// our policy and remembers it, so we propose it to the other guardians
+    /// until the federation agrees on it
+    async fn request_peg_in_acceleration(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        acceleration: PegInAcceleration,
+    ) -> Result<(), WalletError> {
+        let max_fee = self
+            .peg_in_cpfp_max_fee
+            .ok_or(WalletError::PegInAccelerationDisabled)?;
+
+        let outpoint = acceleration.outpoint();
+        let script_pubkey = self
+            .cfg
+            .consensus
+            .peg_in_descriptor
+            .tweak(&acceleration.tweak_contract_key, &self.secp)
+            .script_pubkey();
+        match acceleration.tx.output.get(outpoint.vout as usize) {
+            Some(output) if output.script_pubkey == script_pubkey => {}
+            _ => return Err(WalletError::PegInAccelerationWrongOutput),
+        }
+
+        if self.is_peg_in_claimed(dbtx, outpoint).await {
+            return Err(WalletError::PegInAlreadyClaimed);
+        }
+        if dbtx
+            .get_value(&AcceleratedPegInKey(outpoint))
+            .await
+            .expect("DB error")
+            .is_some()
+        {
+            return Err(WalletError::PegInAlreadyAccelerated);
+        }
+
+        let round_consensus = self
+            .current_round_consensus(dbtx)
+            .await
+            .ok_or(WalletError::NoRoundConsensus)?;
+        let child = self
+            .offline_wallet()
+            .create_cpfp_tx(
+                &acceleration,
+                round_consensus.fee_rate,
+                &round_consensus.randomness_beacon,
+            )
+            .ok_or(WalletError::PegInAccelerationTooSmall)?;
+        let fee = child.fees.amount();
+        if max_fee < fee {
+            return Err(WalletError::PegInAccelerationTooExpensive(fee, max_fee));
+        }
+        // The user is credited the full deposit, so the federation pays the fee
+        let reserve = self.fee_reserve(dbtx).await;
+        if reserve < fee {
+            return Err(WalletError::PegInAccelerationExceedsFeeReserve(reserve));
+        }
+
+        info!(%outpoint, %fee, "Proposing peg-in acceleration");
+        dbtx.insert_entry(&PegInAccelerationRequestKey(outpoint), &acceleration)
+            .await
+            .expect("DB Error");
+        Ok(())
+    }
+
+    /// Sweeps every deposit a threshold of guardians asked to accelerate into a
+    /// child transaction paying the consensus fee rate for itself and its
+    /// parent. Since the decision only depends on consensus state every
+    /// guardian builds the same transaction.
+    async fn accelerate_peg_ins(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        proposals: Vec<(PeerId, PegInAcceleration)>,
+        round_consensus: &RoundConsensus,
+    ) {
+        let mut votes = HashMap::<PegInAcceleration, HashSet<PeerId>>::new();
+        for (peer, acceleration) in proposals {
+            votes.entry(acceleration).or_default().insert(peer);
+        }
+
+        let threshold = self.cfg.consensus.peer_peg_in_keys.threshold();
+        // Sort for a deterministic order of the DB writes
+        let mut accepted = votes
+            .into_iter()
+            .filter(|(_, peers)| threshold <= peers.len())
+            .map(|(acceleration, _)| acceleration)
+            .collect::<Vec<_>>();
+        accepted.sort_by_key(PegInAcceleration::outpoint);
+
+        for acceleration in accepted {
+            let outpoint = acceleration.outpoint();
+            dbtx.remove_entry(&PegInAccelerationRequestKey(outpoint))
+                .await
+                .expect("DB Error");
+
+            let is_accelerated = dbtx
+                .get_value(&AcceleratedPegInKey(outpoint))
+                .await
+                .expect("DB error")
+                .is_some();
+            if is_accelerated || self.is_peg_in_claimed(dbtx, outpoint).await {
+                continue;
+            }
+
+            match self.offline_wallet().create_cpfp_tx(
+                &acceleration,
+                round_consensus.fee_rate,
+                &round_consensus.randomness_beacon,
+            ) {
+                Some(child) => {
+                    // The user is credited the full deposit, so the fee has to be covered by
+                    // the reserve
+                    let fee = child.fees.amount();
+                    if self.fee_reserve(dbtx).await < fee {
+                        warn!(%outpoint, %fee, "Fee reserve can't pay for the peg-in acceleration");
+                        continue;
+                    }
+                    self.pay_from_fee_reserve(dbtx, fee).await;
+                    let child_txid = self.queue_peg_out_tx(dbtx, child).await;
+                    info!(%outpoint, %child_txid, "Accelerating peg-in");
+                    dbtx.insert_new_entry(
+                        &AcceleratedPegInKey(outpoint),
+                        &AcceleratedPegIn {
+                            child_txid,
+                            parent_inputs: acceleration
+                                .tx
+                                .input
+                                .iter()
+                                .map(|input| input.previous_output)
+                                .collect(),
+                            claimed: false,
+                        },
+                    )
+                    .await
+                    .expect("DB Error");
+                }
+                None => warn!(%outpoint, "Peg-in is too small to pay for its acceleration"),
+            }
+        }
+    }
+
+    /// Peers whose approval of `step` of the emergency sweep was accepted in
+    /// consensus
+    async fn emergency_sweep_approvers(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        step: EmergencySweepStep,
+    ) -> Vec<PeerId> {
+        dbtx.find_by_prefix(&EmergencySweepApprovalPrefixKey)
+            .await
+            .map(|res| res.expect("DB error").0)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .filter(|key| key.0 == step)
+            .map(|key| key.1)
+            .collect()
+    }
+
+    async fn is_emergency_sweep_armed(&self, dbtx: &mut DatabaseTransaction<'_>) -> bool {
+        let threshold = self.cfg.consensus.peer_peg_in_keys.threshold();
+        threshold
+            <= self
+                .emergency_sweep_approvers(dbtx, EmergencySweepStep::Arm)
+                .await
+                .len()
+    }
+
+    async fn is_emergency_sweep_confirmed(&self, dbtx: &mut DatabaseTransaction<'_>) -> bool {
+        let threshold = self.cfg.consensus.peer_peg_in_keys.threshold();
+        threshold
+            <= self
+                .emergency_sweep_approvers(dbtx, EmergencySweepStep::Confirm)
+                .await
+                .len()
+    }
+
+    async fn emergency_sweep_status(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> EmergencySweepStatus {
+        EmergencySweepStatus {
+            recovery_descriptor: self.cfg.consensus.recovery_descriptor.clone(),
+            armed_by: self
+                .emergency_sweep_approvers(dbtx, EmergencySweepStep::Arm)
+                .await,
+            confirmed_by: self
+                .emergency_sweep_approvers(dbtx, EmergencySweepStep::Confirm)
+                .await,
+            sweep_txids: dbtx
+                .find_by_prefix(&EmergencySweepTxPrefixKey)
+                .await
+                .map(|res| res.expect("DB error").0 .0)
+                .collect()
+                .await,
+        }
+    }
+
+    /// Checks a guardian's signed approval of the emergency sweep and
+    /// remembers it, so we propose it to the other guardians until the
+    /// federation accepts it
+    async fn submit_emergency_sweep_approval(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        approval: EmergencySweepApproval,
+    ) -> Result<(), WalletError> {
+        let recovery_descriptor = self
+            .cfg
+            .consensus
+            .recovery_descriptor
+            .as_ref()
+            .ok_or(WalletError::EmergencySweepNotConfigured)?;
+        if !approval.verify(recovery_descriptor, &self.cfg.consensus.peer_peg_in_keys) {
+            return Err(WalletError::InvalidEmergencySweepApproval);
+        }
+        if dbtx
+            .get_value(&EmergencySweepApprovalKey(approval.step, approval.peer))
+            .await
+            .expect("DB error")
+            .is_some()
+        {
+            return Err(WalletError::EmergencySweepAlreadyApproved);
+        }
+        if approval.step == EmergencySweepStep::Confirm
+            && !self.is_emergency_sweep_armed(dbtx).await
+        {
+            return Err(WalletError::EmergencySweepNotArmed);
+        }
+
+        warn!(peer = %approval.peer, step = ?approval.step, "Proposing emergency sweep approval");
+        dbtx.insert_entry(
+            &PendingEmergencySweepApprovalKey(approval.step, approval.peer),
+            &approval.signature,
+        )
+        .await
+        .expect("DB Error");
+        Ok(())
+    }
+
+    /// Accepts valid approvals, confirmations only count if the sweep was
+    /// armed in an earlier epoch
+    async fn save_emergency_sweep_approvals(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        approvals: Vec<(PeerId, EmergencySweepApproval)>,
+    ) {
+        let Some(recovery_descriptor) = &self.cfg.consensus.recovery_descriptor else {
+            return;
+        };
+        let was_armed = self.is_emergency_sweep_armed(dbtx).await;
+
+        for (proposer, approval) in approvals {
+            if !approval.verify(recovery_descriptor, &self.cfg.consensus.peer_peg_in_keys) {
+                warn!(%proposer, peer = %approval.peer, "Invalid emergency sweep approval");
+                continue;
+            }
+            if approval.step == EmergencySweepStep::Confirm && !was_armed {
+                continue;
+            }
+
+            dbtx.remove_entry(&PendingEmergencySweepApprovalKey(
+                approval.step,
+                approval.peer,
+            ))
+            .await
+            .expect("DB Error");
+            let key = EmergencySweepApprovalKey(approval.step, approval.peer);
+            if dbtx.get_value(&key).await.expect("DB error").is_none() {
+                warn!(peer = %approval.peer, step = ?approval.step, "Emergency sweep approved");
+                dbtx.insert_new_entry(&key, &approval.signature)
+                    .await
+                    .expect("DB Error");
+            }
+        }
+    }
+
+    /// Once a threshold confirmed the emergency sweep, moves all our UTXOs to
+    /// the recovery descriptor. Runs every epoch, so change of peg-outs that
+    /// were pending and later peg-ins are swept as well.
+    /// Blocks a UTXO has to stay unspent until the cold keys of an `older`
+    /// recovery path can spend it
+    fn recovery_csv_timelock(&self) -> Option<u32> {
+        self.cfg
+            .consensus
+            .peg_in_descriptor
+            .lift()
+            .ok()?
+            .relative_timelocks()
+            .into_iter()
+            .min()
+    }
+
+    /// With an `older` recovery path the cold keys could spend UTXOs that
+    /// stayed unspent for the timelock while the federation is still running,
+    /// so UTXOs are moved to a fresh address once half of it passed. The fee
+    /// is paid from the fee reserve.
+    async fn refresh_aging_utxos(&self, dbtx: &mut DatabaseTransaction<'_>) {
+        let Some(timelock) = self.recovery_csv_timelock() else {
+            return;
+        };
+        if self.is_emergency_sweep_confirmed(dbtx).await {
+            return;
+        }
+        let Some(consensus_height) = self.consensus_height(dbtx).await else {
+            return;
+        };
+
+        let mut aging_utxos = vec![];
+        for (utxo_key, utxo) in self.available_utxos(dbtx).await {
+            // UTXOs from before heights were recorded are of unknown age
+            let height = dbtx
+                .get_value(&UtxoHeightKey(utxo_key.0))
+                .await
+                .expect("DB error")
+                .unwrap_or(0);
+            if consensus_height.saturating_sub(height) >= timelock / 2 {
+                aging_utxos.push((utxo_key, utxo));
+            }
+        }
+        if aging_utxos.is_empty() {
+            return;
+        }
+
+        let round_consensus = self
+            .current_round_consensus(dbtx)
+            .await
+            .expect("Set in begin_consensus_epoch");
+        let tweak = round_consensus.randomness_beacon;
+        let wallet = self.offline_wallet();
+        match wallet.create_sweep_tx(
+            aging_utxos,
+            wallet.derive_script(&tweak),
+            round_consensus.fee_rate,
+            &tweak,
+        ) {
+            Some(mut tx) => {
+                // The output is our change, the fee comes out of the reserve
+                tx.change = bitcoin::Amount::from_sat(tx.psbt.unsigned_tx.output[0].value);
+                let fee = tx.fees.amount();
+                self.pay_from_fee_reserve(dbtx, fee).await;
+                let inputs = tx.selected_utxos.len();
+                let txid = self.queue_peg_out_tx(dbtx, tx).await;
+                info!(%txid, inputs, %fee, "Refreshing UTXOs before the recovery timelock expires");
+            }
+            None => warn!("Aging UTXOs are too small to pay for refreshing them"),
+        }
+    }
+
+    async fn sweep_to_recovery_descriptor(&self, dbtx: &mut DatabaseTransaction<'_>) {
+        if !self.is_emergency_sweep_confirmed(dbtx).await {
+            return;
+        }
+        let utxos = self.available_utxos(dbtx).await;
+        if utxos.is_empty() {
+            return;
+        }
+
+        let recovery_script = self
+            .cfg
+            .consensus
+            .recovery_descriptor
+            .as_ref()
+            .expect("Sweeps can only be confirmed with a recovery descriptor")
+            .script_pubkey();
+        let round_consensus = self
+            .current_round_consensus(dbtx)
+            .await
+            .expect("Set in begin_consensus_epoch");
+        match self.offline_wallet().create_sweep_tx(
+            utxos,
+            recovery_script,
+            round_consensus.fee_rate,
+            &round_consensus.randomness_beacon,
+        ) {
+            Some(tx) => {
+                let swept = tx
+                    .selected_utxos
+                    .iter()
+                    .map(|(_, utxo)| utxo.amount)
+                    .sum::<bitcoin::Amount>();
+                let txid = self.queue_peg_out_tx(dbtx, tx).await;
+                warn!(%txid, %swept, "Sweeping funds to the recovery descriptor");
+                dbtx.insert_new_entry(&EmergencySweepTxKey(txid), &swept)
+                    .await
+                    .expect("DB Error");
+            }
+            None => debug!("Our UTXOs are too small to pay for sweeping them"),
+        }
+    }
+
+    /// Blocks become known once they are `finality_delay` deep, larger peg-ins
+    /// additionally have to wait for their amount's finality delay. Proofs
+    /// against blocks synced before heights were recorded only need the
+    /// default finality delay.
+    async fn check_peg_in_finality(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        input: &WalletInput,
+    ) -> Result<(), WalletError> {
+        let amount = bitcoin::Amount::from_sat(input.tx_output().value);
+        let required = self.cfg.consensus.peg_in_finality_delay(amount);
+        let extra_delay = required - self.cfg.consensus.finality_delay;
+        if extra_delay == 0 {
+            return Ok(());
+        }
+
+        let Some(block_height) = dbtx
+            .get_value(&BlockHeightKey(input.proof_block()))
+            .await
+            .expect("DB error")
+        else {
+            return Ok(());
+        };
+        let consensus_height = self.consensus_height(dbtx).await.unwrap_or(0);
+        if consensus_height < block_height + extra_delay {
+            return Err(WalletError::PegInNotFinal(amount, required));
+        }
+        Ok(())
+    }
+
+    async fn block_is_known(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        block_hash: BlockHash,
+    ) -> bool {
+        dbtx.get_value(&BlockHashKey(block_hash))
+            .await
+            .expect("DB error")
+            .is_some()
+    }
+
+    /// Creates a single transaction paying all `peg_outs` in order. It pays the
+    /// lowest fee rate any of them requested, which all were checked to be at
+    /// least the consensus fee rate. Returns `None` if `peg_outs` is empty.
+    async fn create_peg_out_tx(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        peg_outs: &[PegOut],
+    ) -> Option<UnsignedTransaction> {
+        let fee_rate = peg_outs.iter().map(|peg_out| peg_out.fees.fee_rate).min()?;
+        let change_tweak = self
+            .current_round_consensus(dbtx)
+            .await
+            .unwrap()
+            .randomness_beacon;
+        self.offline_wallet().create_tx(
+            peg_outs
+                .iter()
+                .map(|peg_out| TxOut {
+                    value: peg_out.amount.to_sat(),
+                    script_pubkey: peg_out.recipient.script_pubkey(),
+                })
+                .collect(),
+            self.available_utxos(dbtx).await,
+            fee_rate,
+            &change_tweak,
+            &self.coin_selection_context(dbtx, true).await,
+        )
+    }
+
+    async fn coin_selection_context(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        allow_consolidation: bool,
+    ) -> CoinSelectionContext {
+        CoinSelectionContext {
+            block_height: self.consensus_height(dbtx).await.unwrap_or_default(),
+            utxo_heights: dbtx
+                .find_by_prefix(&UtxoHeightPrefixKey)
+                .await
+                .map(|res| {
+                    let (key, height) = res.expect("DB error");
+                    (key.0, height)
+                })
+                .collect()
+                .await,
+            allow_consolidation,
+        }
+    }
+
+    async fn queued_peg_outs(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> Vec<(QueuedPegOutKey, PegOut)> {
+        dbtx.find_by_prefix(&QueuedPegOutPrefixKey)
+            .await
+            .map(|res| res.expect("DB error"))
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    /// Batches all peg-outs accepted in this epoch into a single transaction
+    /// with one change output. The outcome of each peg-out is the transaction
+    /// output paying it.
+    async fn batch_queued_peg_outs(&self, dbtx: &mut DatabaseTransaction<'_>) {
+        let (keys, peg_outs): (Vec<_>, Vec<_>) =
+            self.queued_peg_outs(dbtx).await.into_iter().unzip();
+        if peg_outs.is_empty() {
+            return;
+        }
+
+        let tx = self
+            .create_peg_out_tx(dbtx, &peg_outs)
+            .await
+            .expect("Peg-outs were validated together");
+        // Users paid for the inputs needed by their own peg-out, the federation pays
+        // for the ones spent to consolidate its UTXOs
+        let paid_fees = peg_outs
+            .iter()
+            .map(|peg_out| peg_out.fees.amount())
+            .sum::<bitcoin::Amount>();
+        if let Some(consolidation_fee) = tx.fees.amount().checked_sub(paid_fees) {
+            self.pay_from_fee_reserve(dbtx, consolidation_fee).await;
+        }
+        let txid = self.queue_peg_out_tx(dbtx, tx).await;
+        info!(%txid, peg_outs = peg_outs.len(), "Batched peg-outs");
+
+        for (vout, key) in keys.into_iter().enumerate() {
+            dbtx.remove_entry(&key).await.expect("DB Error");
+            dbtx.insert_new_entry(
+                &PegOutBitcoinTransaction(key.0),
+                &WalletOutputOutcome(bitcoin::OutPoint {
+                    txid,
+                    vout: vout as u32,
+                }),
+            )
+            .await
+            .expect("DB Error");
+        }
+    }
+
+    async fn export_unsigned_peg_outs(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> Vec<UnsignedPegOutExport> {
+        dbtx.find_by_prefix(&UnsignedTransactionPrefixKey)
+            .await
+            .map(|res| {
+                let (key, unsigned) = res.expect("DB error");
+                UnsignedPegOutExport {
+                    txid: key.0,
+                    psbt: unsigned.psbt.to_string(),
+                    inputs: unsigned
+                        .selected_utxos
+                        .iter()
+                        .map(|(utxo_key, utxo)| PegOutInputProvenance {
+                            outpoint: utxo_key.0,
+                            amount: utxo.amount,
+                            tweak: utxo.tweak.to_hex(),
+                        })
+                        .collect(),
+                    replaces: unsigned.replaces,
+                }
+            })
+            .collect()
+            .await
+    }
+
+    /// Replaces the signature share we propose for an unsigned peg-out with
+    /// the one an external signer added to the exported PSBT.
+    ///
+    /// The signatures are verified against our own key, so this can't be used
+    /// to make us propose anything we couldn't have signed ourselves.
+    async fn submit_external_peg_out_signature(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        psbt: PartiallySignedTransaction,
+    ) -> anyhow::Result<Txid> {
+        let txid = psbt.unsigned_tx.txid();
+        let unsigned = dbtx
+            .get_value(&UnsignedTransactionKey(txid))
+            .await
+            .expect("DB error")
+            .ok_or_else(|| format_err!("No unsigned peg-out with txid {txid}"))?;
+        let signature = self.our_peg_out_signature(&unsigned.psbt, &psbt)?;
+
+        info!(%txid, "Proposing externally created peg-out signature");
+        dbtx.insert_entry(&PegOutTxSignatureCI(txid), &signature.signature)
+            .await
+            .expect("DB Error");
+        Ok(txid)
+    }
+
+    /// Extracts our signature share from a copy of `unsigned` signed by an
+    /// external signer and verifies it against our key
+    fn our_peg_out_signature(
+        &self,
+        unsigned: &PartiallySignedTransaction,
+        signed: &PartiallySignedTransaction,
+    ) -> anyhow::Result<PegOutSignatureItem> {
+        let txid = unsigned.unsigned_tx.txid();
+        if signed.unsigned_tx.txid() != txid {
+            bail!("Signed PSBT is for a different transaction");
+        }
+
+        let our_key = self
+            .cfg
+            .peg_in_public_key()
+            .expect("validated config has a peg-in key");
+        let our_peer = *self
+            .cfg
+            .consensus
+            .peer_peg_in_keys
+            .iter()
+            .find(|(_, key)| **key == our_key)
+            .expect("our key is part of the federation")
+            .0;
+
+        let signature = unsigned
+            .inputs
+            .iter()
+            .zip(signed.inputs.iter())
+            .map(|(our_input, signed_input)| {
+                let tweak = our_input
+                    .proprietary
+                    .get(&proprietary_tweak_key())
+                    .expect("we saved it with a tweak");
+                let tweaked_key: bitcoin::PublicKey = our_key.tweak(tweak, &self.secp).into();
+                let sig = signed_input
+                    .partial_sigs
+                    .get(&tweaked_key)
+                    .ok_or_else(|| format_err!("Missing signature for key {tweaked_key}"))?;
+                if sig.hash_ty != EcdsaSighashType::All {
+                    bail!("Signatures have to use SIGHASH_ALL");
+                }
+                Ok(sig.sig)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let signature = PegOutSignatureItem { txid, signature };
+        self.sign_peg_out_psbt(&mut unsigned.clone(), &our_peer, &signature)?;
+        Ok(signature)
+    }
+
+    /// Creates our signature share of a peg-out with our peg-in key, `None` if
+    /// the hardware signer holds it and signs in the background
+    fn sign_peg_out_share(
+        &self,
+        psbt: &PartiallySignedTransaction,
+    ) -> Option<Vec<secp256k1::ecdsa::Signature>> {
+        let secret_key = self.cfg.private.peg_in_key.as_ref()?;
+
+        let mut psbt = psbt.clone();
+        self.offline_wallet().sign_psbt(&mut psbt, secret_key);
+        let sigs = psbt
+            .inputs
+            .iter_mut()
+            .map(|input| {
+                assert_eq!(
+                    input.partial_sigs.len(),
+                    1,
+                    "There was already more than one (our) or no signatures in input"
+                );
+
+                // TODO: don't put sig into PSBT in the first place
+                // We actually take out our own signature so everyone finalizes the tx in the
+                // same epoch.
+                let sig = std::mem::take(&mut input.partial_sigs)
+                    .into_values()
+                    .next()
+                    .expect("asserted previously");
+
+                // We drop SIGHASH_ALL, because we always use that and it is only present in the
+                // PSBT for compatibility with other tools.
+                secp256k1::ecdsa::Signature::from_der(&sig.to_vec()[..sig.to_vec().len() - 1])
+                    .expect("we serialized it ourselves that way")
+            })
+            .collect::<Vec<_>>();
+        Some(sigs)
+    }
+
+    /// Signs a peg-out transaction and queues our signature to be shared with
+    /// the other guardians, returns its txid
+    ///
+    /// Without a peg-in key the transaction is queued without our signature,
+    /// which the hardware signer or `/admin/submit_peg_out_signature` adds
+    /// later.
+    async fn queue_peg_out_tx(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        tx: UnsignedTransaction,
+    ) -> Txid {
+        let txid = tx.psbt.unsigned_tx.txid();
+        info!(
+            %txid,
+            "Signing peg out",
+        );
+        let sigs = self.sign_peg_out_share(&tx.psbt);
+
+        // Delete used UTXOs
+        for input in tx.psbt.unsigned_tx.input.iter() {
+            dbtx.remove_entry(&UTXOKey(input.previous_output))
+                .await
+                .expect("DB Error");
+            dbtx.remove_entry(&UtxoHeightKey(input.previous_output))
+                .await
+                .expect("DB Error");
+        }
+
+        dbtx.insert_new_entry(&UnsignedTransactionKey(txid), &tx)
+            .await
+            .expect("DB Error");
+        if let Some(sigs) = sigs {
+            dbtx.insert_new_entry(&PegOutTxSignatureCI(txid), &sigs)
+                .await
+                .expect("DB Error");
+        }
+        txid
+    }
+
+    async fn available_utxos(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> Vec<(UTXOKey, SpendableUTXO)> {
+        dbtx.find_by_prefix(&UTXOPrefixKey)
+            .await
+            .map(|res| {
+                let (key, val) = res.expect("FB error");
+                (key, val)
+            })
+            .collect::<Vec<(UTXOKey, SpendableUTXO)>>()
+            .await
+    }
+
+    pub async fn get_wallet_value(&self, dbtx: &mut DatabaseTransaction<'_>) -> bitcoin::Amount {
+        let sat_sum = self
+            .available_utxos(dbtx)
+            .await
+            .into_iter()
+            .map(|(_, utxo)| utxo.amount.to_sat())
+            .sum();
+        bitcoin::Amount::from_sat(sat_sum)
+    }
+
+    fn offline_wallet(&self) -> StatelessWallet {
+        StatelessWallet {
+            descriptor: &self.cfg.consensus.peg_in_descriptor,
+            coin_selection: &self.cfg.consensus.coin_selection,
+            secp: &self.secp,
+        }
+    }
+}
+
+impl<'a> StatelessWallet<'a> {
+    /// Attempts to create a tx paying all `peg_outs` ready to be signed from
+    /// available UTXOs. The peg-out outputs keep their order and are followed
+    /// by a single change output. Returns `None` if there are not enough
+    /// `SpendableUTXO`
+    fn create_tx(
+        &self,
+        peg_outs: Vec<TxOut>,
+        utxos: Vec<(UTXOKey, SpendableUTXO)>,
+        fee_rate: Feerate,
+        change_tweak: &[u8],
+        coin_selection_context: &CoinSelectionContext,
+    ) -> Option<UnsignedTransaction> {
+        // When building a transaction we need to take care of two things:
+        //  * We need enough input amount to fund all outputs
         //  * We need to keep an eye on the tx weight so we can factor the fees into out
         //    calculation
         // We then go on to calculate the base size of the transaction `total_weight`
         // and the maximum weight per added input which we will add every time
         // we select an input.
         let change_script = self.derive_script(change_tweak);
-        let out_weight = (destination.len() * 4 + 1 + 32
+        let peg_out_amount = bitcoin::Amount::from_sat(peg_outs.iter().map(|out| out.value).sum());
+        let out_weight = (peg_outs
+            .iter()
+            .map(|out| out.script_pubkey.len() * 4 + 1 + 32)
+            .sum::<usize>()
             // Add change script weight, it's very likely to be needed if not we just overpay in fees
             + 1 // script len varint, 1 byte for all addresses we accept
             + change_script.len() * 4 // script len
@@ -1400,23 +3105,20 @@ impl<'a> StatelessWallet<'a> {
             12 + // up to 2**16-1 outputs
             out_weight + // weight of all outputs
             16; // lock time
-        let max_input_weight = (self
-            .descriptor
-            .max_satisfaction_weight()
-            .expect("is satisfyable") +
-            128 + // TxOutHash
-            16 + // TxOutIndex
-            16) as u64; // sequence
+        let max_input_weight = self.max_input_weight();
 
         // Finally we initialize our accumulator for selected input amounts
         let mut total_selected_value = bitcoin::Amount::from_sat(0);
         let mut selected_utxos: Vec<(UTXOKey, SpendableUTXO)> = vec![];
         let mut fees = fee_rate.calculate_fee(total_weight);
 
-        // When selecting UTXOs we select from largest to smallest amounts
-        utxos.sort_by_key(|(_, utxo)| utxo.amount);
+        // When selecting UTXOs we select from largest to smallest amounts, starting
+        // with the ones the coin selection policy prefers
+        let (mut utxos, mut fallback_utxos) = self
+            .coin_selection
+            .prioritize(utxos, coin_selection_context);
         while total_selected_value < peg_out_amount + change_script.dust_value() + fees {
-            match utxos.pop() {
+            match utxos.pop().or_else(|| fallback_utxos.pop()) {
                 Some((utxo_key, utxo)) => {
                     total_selected_value += utxo.amount;
                     total_weight += max_input_weight;
@@ -1427,26 +3129,39 @@ impl<'a> StatelessWallet<'a> {
             }
         }
 
+        // Consolidate the smallest remaining UTXOs that are still worth more than the
+        // fee for spending them
+        if let Some(max_inputs) = self
+            .coin_selection
+            .consolidation_inputs(fee_rate, coin_selection_context)
+        {
+            let input_fee = fee_rate.calculate_fee(max_input_weight);
+            let additional_inputs = max_inputs.saturating_sub(selected_utxos.len());
+            for (utxo_key, utxo) in utxos
+                .into_iter()
+                .filter(|(_, utxo)| utxo.amount > input_fee)
+                .take(additional_inputs)
+            {
+                total_selected_value += utxo.amount;
+                total_weight += max_input_weight;
+                fees = fee_rate.calculate_fee(total_weight);
+                selected_utxos.push((utxo_key, utxo));
+            }
+        }
+
         // We always pay ourselves change back to ensure that we don't lose anything due
         // to dust
         let change = total_selected_value - fees - peg_out_amount;
-        let output: Vec<TxOut> = vec![
-            TxOut {
-                value: peg_out_amount.to_sat(),
-                script_pubkey: destination,
-            },
-            TxOut {
+        let output: Vec<TxOut> = peg_outs
+            .into_iter()
+            .chain(once(TxOut {
                 value: change.to_sat(),
                 script_pubkey: change_script,
-            },
-        ];
-        let mut change_out = bitcoin::util::psbt::Output::default();
-        change_out
-            .proprietary
-            .insert(proprietary_tweak_key(), change_tweak.to_vec());
-
+            }))
+            .collect();
         info!(
             inputs = selected_utxos.len(),
+            peg_outs = output.len() - 1,
             input_sats = total_selected_value.to_sat(),
             peg_out_sats = peg_out_amount.to_sat(),
             fees_sats = fees.to_sat(),
@@ -1463,7 +3178,8 @@ impl<'a> StatelessWallet<'a> {
                 .map(|(utxo_key, _utxo)| TxIn {
                     previous_output: utxo_key.0,
                     script_sig: Default::default(),
-                    sequence: Sequence::MAX,
+                    // Signal replaceability so stuck peg-outs can be fee-bumped
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
                     witness: bitcoin::Witness::new(),
                 })
                 .collect(),
@@ -1471,16 +3187,243 @@ impl<'a> StatelessWallet<'a> {
         };
         info!(txid = %transaction.txid(), "Creating peg-out tx");
 
+        Some(UnsignedTransaction {
+            psbt: self.create_psbt(transaction, &selected_utxos, change_tweak),
+            signatures: vec![],
+            change,
+            fees: PegOutFees {
+                fee_rate,
+                total_weight,
+            },
+            selected_utxos,
+            replaces: None,
+        })
+    }
+
+    /// Creates a child transaction sweeping an unconfirmed deposit back into
+    /// our wallet, paying `fee_rate` for both itself and the deposit
+    /// transaction. The fee is deducted from the deposit, returns `None` if
+    /// it can't cover it.
+    fn create_cpfp_tx(
+        &self,
+        acceleration: &PegInAcceleration,
+        fee_rate: Feerate,
+        change_tweak: &[u8],
+    ) -> Option<UnsignedTransaction> {
+        let deposit = acceleration
+            .tx
+            .output
+            .get(acceleration.output_idx as usize)?;
+        let deposit_utxo = (
+            UTXOKey(acceleration.outpoint()),
+            SpendableUTXO {
+                tweak: acceleration.tweak_contract_key.serialize(),
+                amount: bitcoin::Amount::from_sat(deposit.value),
+            },
+        );
+
+        let change_script = self.derive_script(change_tweak);
+        let total_weight = 16 + // version
+            12 + // input count
+            12 + // output count
+            (1 + change_script.len() * 4 + 32) as u64 + // change output
+            16 + // lock time
+            self.max_input_weight() +
+            acceleration.tx.weight() as u64; // the parent's weight we pay for as well
+        let fees = fee_rate.calculate_fee(total_weight);
+        if deposit_utxo.1.amount < fees + change_script.dust_value() {
+            return None;
+        }
+        let change = deposit_utxo.1.amount - fees;
+
+        let transaction = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: deposit_utxo.0 .0,
+                script_sig: Default::default(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: change.to_sat(),
+                script_pubkey: change_script,
+            }],
+        };
+        info!(
+            txid = %transaction.txid(),
+            parent = %acceleration.tx.txid(),
+            fees_sats = fees.to_sat(),
+            fee_rate = fee_rate.sats_per_kvb,
+            "Creating CPFP tx",
+        );
+
+        let selected_utxos = vec![deposit_utxo];
+        Some(UnsignedTransaction {
+            psbt: self.create_psbt(transaction, &selected_utxos, change_tweak),
+            signatures: vec![],
+            change,
+            fees: PegOutFees {
+                fee_rate,
+                total_weight,
+            },
+            selected_utxos,
+            replaces: None,
+        })
+    }
+
+    /// Creates a transaction spending all `utxos` to `recovery_script`, the
+    /// fee is paid from the swept amount. Returns `None` if it can't cover it.
+    /// Also used to move aging UTXOs to a fresh address of our own.
+    fn create_sweep_tx(
+        &self,
+        utxos: Vec<(UTXOKey, SpendableUTXO)>,
+        recovery_script: Script,
+        fee_rate: Feerate,
+        tweak: &[u8],
+    ) -> Option<UnsignedTransaction> {
+        let total_weight = 16 + // version
+            12 + // up to 2**16-1 inputs
+            12 + // output count
+            (1 + recovery_script.len() * 4 + 32) as u64 + // recovery output
+            16 + // lock time
+            self.max_input_weight() * utxos.len() as u64;
+        let fees = fee_rate.calculate_fee(total_weight);
+        let input_value = utxos
+            .iter()
+            .map(|(_, utxo)| utxo.amount)
+            .sum::<bitcoin::Amount>();
+        if input_value < fees + recovery_script.dust_value() {
+            return None;
+        }
+
+        let transaction = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: utxos
+                .iter()
+                .map(|(utxo_key, _utxo)| TxIn {
+                    previous_output: utxo_key.0,
+                    script_sig: Default::default(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: bitcoin::Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: (input_value - fees).to_sat(),
+                script_pubkey: recovery_script,
+            }],
+        };
+        info!(
+            txid = %transaction.txid(),
+            inputs = utxos.len(),
+            input_sats = input_value.to_sat(),
+            fees_sats = fees.to_sat(),
+            fee_rate = fee_rate.sats_per_kvb,
+            "Creating sweep tx",
+        );
+
+        Some(UnsignedTransaction {
+            // The tweak marks the recovery output like a change output, which finalization
+            // expects, but it only becomes one of our UTXOs when refreshing
+            psbt: self.create_psbt(transaction, &utxos, tweak),
+            signatures: vec![],
+            change: bitcoin::Amount::ZERO,
+            fees: PegOutFees {
+                fee_rate,
+                total_weight,
+            },
+            selected_utxos: utxos,
+            replaces: None,
+        })
+    }
+
+    fn max_input_weight(&self) -> u64 {
+        (self
+            .descriptor
+            .max_satisfaction_weight()
+            .expect("is satisfyable") +
+            128 + // TxOutHash
+            16 + // TxOutIndex
+            16) as u64 // sequence
+    }
+
+    /// Creates a replacement for `pending` that spends the same UTXOs to the
+    /// same outputs but pays `fee_rate`, the additional fee is deducted from
+    /// the change. Returns `None` if the change can't cover it.
+    fn create_rbf_tx(
+        &self,
+        pending: &PendingTransaction,
+        fee_rate: Feerate,
+    ) -> Option<UnsignedTransaction> {
+        let total_weight = pending.fees.total_weight;
+        let fees = fee_rate.calculate_fee(total_weight);
+        let change_script = self.derive_script(&pending.tweak);
+        let input_value = pending
+            .selected_utxos
+            .iter()
+            .map(|(_, utxo)| utxo.amount)
+            .sum::<bitcoin::Amount>();
+        let output_value = pending
+            .tx
+            .output
+            .iter()
+            .filter(|output| output.script_pubkey != change_script)
+            .map(|output| bitcoin::Amount::from_sat(output.value))
+            .sum::<bitcoin::Amount>();
+
+        let change = input_value.checked_sub(output_value + fees)?;
+        if change < change_script.dust_value() {
+            return None;
+        }
+
+        let mut transaction = pending.tx.clone();
+        for input in transaction.input.iter_mut() {
+            input.script_sig = Default::default();
+            input.witness = bitcoin::Witness::new();
+        }
+        transaction
+            .output
+            .iter_mut()
+            .find(|output| output.script_pubkey == change_script)?
+            .value = change.to_sat();
+
+        Some(UnsignedTransaction {
+            psbt: self.create_psbt(transaction, &pending.selected_utxos, &pending.tweak),
+            signatures: vec![],
+            change,
+            fees: PegOutFees {
+                fee_rate,
+                total_weight,
+            },
+            selected_utxos: pending.selected_utxos.clone(),
+            replaces: Some(pending.tx.txid()),
+        })
+    }
+
+    fn create_psbt(
+        &self,
+        transaction: Transaction,
+        selected_utxos: &[(UTXOKey, SpendableUTXO)],
+        change_tweak: &[u8],
+    ) -> PartiallySignedTransaction {
+        let mut outputs = vec![bitcoin::util::psbt::Output::default(); transaction.output.len()];
+        outputs
+            .last_mut()
+            .expect("has change output")
+            .proprietary
+            .insert(proprietary_tweak_key(), change_tweak.to_vec());
+
         // FIXME: use custom data structure that guarantees more invariants and only
         // convert to PSBT for finalization
-        let psbt = PartiallySignedTransaction {
+        PartiallySignedTransaction {
             unsigned_tx: transaction,
             version: 0,
             xpub: Default::default(),
             proprietary: Default::default(),
             unknown: Default::default(),
             inputs: selected_utxos
-                .into_iter()
+                .iter()
                 .map(|(_utxo_key, utxo)| {
                     let script_pubkey = self
                         .descriptor
@@ -1521,21 +3464,11 @@ impl<'a> StatelessWallet<'a> {
                     }
                 })
                 .collect(),
-            outputs: vec![Default::default(), change_out],
-        };
-
-        Some(UnsignedTransaction {
-            psbt,
-            signatures: vec![],
-            change,
-            fees: PegOutFees {
-                fee_rate,
-                total_weight,
-            },
-        })
+            outputs,
+        }
     }
 
-    fn sign_psbt(&self, psbt: &mut PartiallySignedTransaction) {
+    fn sign_psbt(&self, psbt: &mut PartiallySignedTransaction, secret_key: &secp256k1::SecretKey) {
         let mut tx_hasher = SighashCache::new(&psbt.unsigned_tx);
 
         for (idx, (psbt_input, _tx_input)) in psbt
@@ -1549,7 +3482,7 @@ impl<'a> StatelessWallet<'a> {
                     .proprietary
                     .get(&proprietary_tweak_key())
                     .expect("Malformed PSBT: expected tweak");
-                let pub_key = secp256k1::PublicKey::from_secret_key(self.secp, self.secret_key);
+                let pub_key = secp256k1::PublicKey::from_secret_key(self.secp, secret_key);
 
                 let tweak = {
                     let mut hasher = HmacEngine::<sha256::Hash>::new(&pub_key.serialize()[..]);
@@ -1557,7 +3490,7 @@ impl<'a> StatelessWallet<'a> {
                     Hmac::from_engine(hasher).into_inner()
                 };
 
-                self.secret_key
+                secret_key
                     .add_tweak(&Scalar::from_be_bytes(tweak).expect("can't fail"))
                     .expect("Tweaking priv key failed") // TODO: why could this
                                                         // happen?
@@ -1650,12 +3583,16 @@ fn proprietary_tweak_key() -> ProprietaryKey {
 }
 
 #[instrument(level = "debug", skip_all)]
+/// Broadcasts pending transactions every 10 seconds and right after new ones
+/// were added
 pub async fn run_broadcast_pending_tx(db: Database, rpc: DynBitcoindRpc, tg_handle: &TaskHandle) {
     while !tg_handle.is_shutting_down() {
+        // Register before reading so no new transaction in between is missed
+        let pending_written = db.wait_key_prefix_write(&PendingTransactionPrefixKey);
         broadcast_pending_tx(db.begin_transaction().await, &rpc).await;
         // FIXME: remove after modularization finishes
         #[cfg(not(target_family = "wasm"))]
-        fedimint_api::task::sleep(Duration::from_secs(10)).await;
+        let _ = timeout(Duration::from_secs(10), pending_written).await;
     }
 }
 
@@ -1670,7 +3607,16 @@ pub async fn broadcast_pending_tx(mut dbtx: DatabaseTransaction<'_>, rpc: &DynBi
         .collect::<Vec<(_, _)>>()
         .await;
 
+    // Replaced transactions conflict with their fee bumps and would be rejected
+    let replaced = pending_tx
+        .iter()
+        .filter_map(|(_, pending)| pending.replaces)
+        .collect::<HashSet<_>>();
+
     for (_, PendingTransaction { tx, .. }) in pending_tx {
+        if replaced.contains(&tx.txid()) {
+            continue;
+        }
         debug!(
             tx = %tx.txid(),
             weight = tx.weight(),
@@ -1721,10 +3667,38 @@ pub enum WalletError {
     PegInProofError(#[from] PegInProofError),
     #[error("The peg-in was already claimed")]
     PegInAlreadyClaimed,
+    #[error("A peg-in of {0} requires {1} confirmations")]
+    PegInNotFinal(bitcoin::Amount, u32),
     #[error("Peg-out fee rate {0:?} is set below consensus {1:?}")]
     PegOutFeeRate(Feerate, Feerate),
     #[error("Not enough SpendableUTXO")]
     NotEnoughSpendableUTXO,
+    #[error("The peg-out would spend the fee reserve of {0}")]
+    PegOutExceedsFeeReserve(bitcoin::Amount),
+    #[error("This guardian doesn't accelerate peg-ins")]
+    PegInAccelerationDisabled,
+    #[error("The peg-in was already accelerated")]
+    PegInAlreadyAccelerated,
+    #[error("The output doesn't pay to the federation")]
+    PegInAccelerationWrongOutput,
+    #[error("The peg-in is too small to pay for its acceleration")]
+    PegInAccelerationTooSmall,
+    #[error("Accelerating the peg-in costs {0}, more than the limit of {1}")]
+    PegInAccelerationTooExpensive(bitcoin::Amount, bitcoin::Amount),
+    #[error("Accelerating the peg-in would spend more than the fee reserve of {0}")]
+    PegInAccelerationExceedsFeeReserve(bitcoin::Amount),
+    #[error("No round consensus yet")]
+    NoRoundConsensus,
+    #[error("The federation has no recovery descriptor")]
+    EmergencySweepNotConfigured,
+    #[error("Invalid emergency sweep approval signature")]
+    InvalidEmergencySweepApproval,
+    #[error("The emergency sweep has to be armed by a threshold of guardians first")]
+    EmergencySweepNotArmed,
+    #[error("The emergency sweep was approved already")]
+    EmergencySweepAlreadyApproved,
+    #[error("Funds are being swept to the recovery descriptor")]
+    EmergencySweepInProgress,
 }
 
 #[derive(Debug, Error)]
@@ -0,0 +1,153 @@
+//! Watches that every UTXO the federation believes to own still exists on-chain
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use fedimint_api::db::Database;
+use fedimint_api::task::{sleep, TaskHandle};
+use fedimint_bitcoind::DynBitcoindRpc;
+#[cfg(feature = "native")]
+use fedimint_metrics::{opts, IntGaugeVec, REGISTRY};
+use futures::StreamExt;
+#[cfg(feature = "native")]
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::db::{UTXOKey, UTXOPrefixKey};
+use crate::tweakable::Tweakable;
+use crate::PegInDescriptor;
+
+/// How often all UTXOs are checked against the bitcoin backend
+pub const UTXO_MONITOR_INTERVAL: Duration = Duration::from_secs(60);
+
+#[cfg(feature = "native")]
+static UTXO_ALERTS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let gauge = IntGaugeVec::new(
+        opts!(
+            "wallet_utxo_alerts",
+            "Number of federation UTXOs that don't match the chain"
+        ),
+        &["kind"],
+    )
+    .unwrap();
+    REGISTRY.register(Box::new(gauge.clone())).unwrap();
+    gauge
+});
+
+/// Discrepancy between a UTXO in our database and the chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UtxoAlert {
+    /// The output was spent without a peg-out of ours or doesn't exist anymore,
+    /// e.g. due to a reorg
+    Missing,
+    /// The output pays to a different script than the one we derived
+    WrongScript,
+    /// The output has a different amount than we recorded
+    WrongAmount {
+        #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+        expected: bitcoin::Amount,
+        #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+        actual: bitcoin::Amount,
+    },
+}
+
+#[cfg(feature = "native")]
+const ALERT_KINDS: [&str; 3] = ["missing", "wrong_script", "wrong_amount"];
+
+impl UtxoAlert {
+    #[cfg(feature = "native")]
+    fn kind(&self) -> &'static str {
+        match self {
+            UtxoAlert::Missing => "missing",
+            UtxoAlert::WrongScript => "wrong_script",
+            UtxoAlert::WrongAmount { .. } => "wrong_amount",
+        }
+    }
+}
+
+/// Alerts found by the last check, shared between the monitor and the API
+pub type UtxoAlerts = Arc<Mutex<BTreeMap<bitcoin::OutPoint, UtxoAlert>>>;
+
+/// Periodically checks all UTXOs in `db` and publishes discrepancies to
+/// `alerts` and the metrics. If a check fails the alerts of the previous one
+/// are kept.
+pub async fn run_utxo_monitor(
+    db: Database,
+    rpc: DynBitcoindRpc,
+    descriptor: PegInDescriptor,
+    alerts: UtxoAlerts,
+    handle: &TaskHandle,
+) {
+    let secp = Secp256k1::verification_only();
+    while !handle.is_shutting_down() {
+        match check_utxos(&db, &rpc, &descriptor, &secp).await {
+            Ok(new_alerts) => {
+                let mut alerts = alerts.lock().expect("lock poisoned");
+                for (outpoint, alert) in &new_alerts {
+                    if alerts.get(outpoint) != Some(alert) {
+                        error!(%outpoint, ?alert, "Federation UTXO doesn't match the chain");
+                    }
+                }
+                #[cfg(feature = "native")]
+                publish_metrics(&new_alerts);
+                *alerts = new_alerts;
+            }
+            Err(e) => warn!("Checking the federation UTXOs failed: {e}"),
+        }
+        sleep(UTXO_MONITOR_INTERVAL).await;
+    }
+}
+
+async fn check_utxos<C: Verification>(
+    db: &Database,
+    rpc: &DynBitcoindRpc,
+    descriptor: &PegInDescriptor,
+    secp: &Secp256k1<C>,
+) -> anyhow::Result<BTreeMap<bitcoin::OutPoint, UtxoAlert>> {
+    let utxos = db
+        .begin_transaction()
+        .await
+        .find_by_prefix(&UTXOPrefixKey)
+        .await
+        .map(|res| res.expect("DB error"))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut alerts = BTreeMap::new();
+    for (UTXOKey(outpoint), utxo) in utxos {
+        let script_pubkey = descriptor.tweak(&utxo.tweak, secp).script_pubkey();
+        let alert = match rpc.get_unspent_output(&outpoint, &script_pubkey).await? {
+            None => UtxoAlert::Missing,
+            Some(output) if output.script_pubkey != script_pubkey => UtxoAlert::WrongScript,
+            Some(output) if output.value != utxo.amount.to_sat() => UtxoAlert::WrongAmount {
+                expected: utxo.amount,
+                actual: bitcoin::Amount::from_sat(output.value),
+            },
+            Some(_) => continue,
+        };
+
+        // A peg-out might have spent the UTXO since we read it
+        let is_still_ours = db
+            .begin_transaction()
+            .await
+            .get_value(&UTXOKey(outpoint))
+            .await
+            .expect("DB error")
+            .is_some();
+        if is_still_ours {
+            alerts.insert(outpoint, alert);
+        }
+    }
+    Ok(alerts)
+}
+
+#[cfg(feature = "native")]
+fn publish_metrics(alerts: &BTreeMap<bitcoin::OutPoint, UtxoAlert>) {
+    for kind in ALERT_KINDS {
+        let count = alerts.values().filter(|alert| alert.kind() == kind).count();
+        UTXO_ALERTS.with_label_values(&[kind]).set(count as i64);
+    }
+}
@@ -53,6 +53,7 @@ do
   CERTS="$CERTS,$(cat $FM_CFG_DIR/server-$ID/tls-cert)"
 done
 CERTS=${CERTS:1}
+$FM_BIN_DIR/distributedgen validate --out-dir $FM_CFG_DIR/server-0 --certs $CERTS
 echo "Running DKG with certs: $CERTS"
 
 for ((ID=0; ID<FM_FED_SIZE; ID++));
@@ -28,14 +28,30 @@ start_gateway
 CONNECT_STRING=$(cat $FM_CFG_DIR/client-connect.json)
 rm $FM_CFG_DIR/client.json
 $FM_MINT_CLIENT join-federation "$CONNECT_STRING"
+INVITE_CODE=$($FM_MINT_CLIENT connect-info | jq -e -r '.invite_code')
+rm $FM_CFG_DIR/client.json
+$FM_MINT_CLIENT join-federation "$INVITE_CODE"
 
 # reissue
 NOTES=$($FM_MINT_CLIENT spend '42000msat' | jq -e -r '.note')
 [[ $($FM_MINT_CLIENT info | jq -e -r '.total_amount') = "9958000" ]]
-$FM_MINT_CLIENT validate $NOTES
-$FM_MINT_CLIENT reissue $NOTES
+[[ $($FM_MINT_CLIENT validate $NOTES | jq -e -r '.all_valid') = "true" ]]
+[[ $($FM_MINT_CLIENT validate "${NOTES}x" | jq -e -r '.all_valid') = "false" ]]
+COMBINED_NOTES=$($FM_MINT_CLIENT combine-notes $NOTES $NOTES | jq -e -r '.notes')
+[[ $($FM_MINT_CLIENT validate $COMBINED_NOTES | jq -e -r '.total_amount') = "42000" ]]
+$FM_MINT_CLIENT reissue $COMBINED_NOTES
 $FM_MINT_CLIENT fetch
 
+# machine-readable output and exit codes
+TOTAL_AMOUNT=$($FM_MINT_CLIENT info | jq -e -r '.total_amount')
+[[ $($FM_MINT_CLIENT --output json info | jq -e -r '.result.total_amount') = "$TOTAL_AMOUNT" ]]
+# the shell runs several commands with the same loaded client
+[[ $(printf 'info\nfetch\ninfo\n' | $FM_MINT_CLIENT --output json shell | grep -c '"success":true') = "3" ]]
+JOIN_EXIT_CODE=0
+JOIN_OUTPUT=$($FM_MINT_CLIENT --output json join-federation "$INVITE_CODE") || JOIN_EXIT_CODE=$?
+[[ $JOIN_EXIT_CODE = 2 ]]
+[[ $(echo "$JOIN_OUTPUT" | jq -e -r '.error.kind') = "InvalidValue" ]]
+
 # peg out
 PEG_OUT_ADDR="$($FM_BTC_CLIENT getnewaddress)"
 $FM_MINT_CLIENT peg-out $PEG_OUT_ADDR 500